@@ -0,0 +1,100 @@
+//! Times the phases on generated programs which stress them in different
+//! ways: long and deeply nested expressions, many small functions and large
+//! array literals.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use rtsc::phases::codegen::Environment;
+use rtsc::typecheck::{self, TypeChecker};
+use rtsc::{Arm32Generator, Ast, StaticTypeChecker};
+
+fn long_expression() -> String {
+    let terms = vec!["x * 2"; 300].join(" + ");
+    format!("function main() {{ var x = 1; return {}; }}", terms)
+}
+
+// nested as deep as the parser allows
+fn deep_expression() -> String {
+    let depth = rtsc::parser::MAX_DEPTH - 4;
+    format!(
+        "function main() {{ return {}1{}; }}",
+        "(1 + ".repeat(depth),
+        ")".repeat(depth)
+    )
+}
+
+fn many_functions() -> String {
+    let mut source = String::new();
+    for i in 0..200 {
+        source.push_str(&format!(
+            "function f{}(x: number, y: number) {{ if (x == y) {{ return x; }} else {{ return x + y * {}; }} }}\n",
+            i, i
+        ));
+    }
+    source.push_str("function main() { return f0(1, 2) + f199(3, 4); }");
+    source
+}
+
+fn big_array() -> String {
+    let elements: Vec<String> = (0..2000).map(|i| i.to_string()).collect();
+    format!(
+        "function main() {{ var xs = [{}]; return xs[1999]; }}",
+        elements.join(", ")
+    )
+}
+
+fn inputs() -> Vec<(&'static str, String)> {
+    vec![
+        ("long expression", long_expression()),
+        ("deep expression", deep_expression()),
+        ("many functions", many_functions()),
+        ("big array", big_array()),
+    ]
+}
+
+fn parse(source: &str) -> Ast {
+    rtsc::parse_program(source).unwrap()
+}
+
+fn typecheck(ast: &Ast) {
+    StaticTypeChecker::new(Default::default(), typecheck::builtins(), None)
+        .check(ast)
+        .unwrap();
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    // parsing takes up to seconds, the default hundred samples would take long
+    group.sample_size(10);
+    for (name, source) in inputs() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &source, |b, source| {
+            b.iter(|| parse(black_box(source)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_typecheck(c: &mut Criterion) {
+    let mut group = c.benchmark_group("typecheck");
+    for (name, source) in inputs() {
+        let ast = parse(&source);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &ast, |b, ast| {
+            b.iter(|| typecheck(black_box(ast)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_codegen(c: &mut Criterion) {
+    let mut group = c.benchmark_group("codegen");
+    for (name, source) in inputs() {
+        let mut ast = parse(&source);
+        typecheck::lower_typed_operations(&mut ast, typecheck::builtins()).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(name), &ast, |b, ast| {
+            b.iter(|| Arm32Generator::new(ast.clone()).generate(&mut Environment::default()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_typecheck, bench_codegen);
+criterion_main!(benches);
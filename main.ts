@@ -67,6 +67,12 @@ function main() {
     assert(true);
     assert(!false);
 
+    var n = 0 - 12;
+    assert(n / 4 == 0 - 3);
+    assert(n / (0 - 4) == 3);
+    assert(7 / 2 == 3);
+    assert(n * (0 - 2) == 24);
+
     var a = [10, 20, 30];
     assert(a[0] == 10);
     assert(a[1] == 20);
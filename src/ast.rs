@@ -1,11 +1,19 @@
+use std::fmt::Write;
+
 use crate::types::Type;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Ast {
     Null,
     Undefined,
     Number(i32),
+    Float(f32),
     Bool(bool),
+    String(String),
     ArrayLiteral(Vec<Ast>),
     ArrayLookup(Box<Ast>, Box<Ast>),
     ArrayLength(Box<Ast>),
@@ -19,10 +27,370 @@ pub enum Ast {
     Division(Box<Ast>, Box<Ast>),
     Call(String, Vec<Ast>),
     Return(Box<Ast>),
+    Delete(Box<Ast>),
     Block(Vec<Ast>),
     If(Box<Ast>, Box<Ast>, Box<Ast>),
     Function(String, Type, Box<Ast>),
     Var(String, Box<Ast>),
+    /// A name bound to the value of a constant expression, see
+    /// `phases::const_eval`.
+    Const(String, Box<Ast>),
     Assignment(String, Box<Ast>),
     While(Box<Ast>, Box<Ast>),
+    Import(Vec<String>, String),
+    Export(Box<Ast>),
+    /// A function defined outside of the program, called with the declared
+    /// signature.
+    Extern(String, Type),
+    /// A condition checked at compile time, failing the build with the
+    /// message when it is false.
+    StaticAssert(Box<Ast>, String),
+    /// The lines of consecutive `///` comments, without the slashes,
+    /// documenting the declaration that follows.
+    Doc(String),
+    /// Functions called by their name inside of the namespace and by the
+    /// name qualified with the namespace, as in `Foo.bar()`, outside of it,
+    /// where only the exported ones are visible. See `phases::namespaces`.
+    Namespace(String, Vec<Ast>),
+}
+
+impl Ast {
+    /// One line description of the node, without its children.
+    pub fn label(&self) -> String {
+        match self {
+            Ast::Number(value) => format!("Number {}", value),
+            Ast::Float(value) => format!("Float {:?}", value),
+            Ast::Bool(value) => format!("Bool {}", value),
+            Ast::String(text) => format!("String {:?}", text),
+            Ast::Identifier(name) => format!("Identifier {}", name),
+            Ast::Call(name, _) => format!("Call {}", name),
+            Ast::Function(name, signature, _) => format!("Function {}: {}", name, signature),
+            Ast::Extern(name, signature) => format!("Extern {}: {}", name, signature),
+            Ast::Var(name, _) => format!("Var {}", name),
+            Ast::Const(name, _) => format!("Const {}", name),
+            Ast::StaticAssert(_, message) => format!("StaticAssert {:?}", message),
+            Ast::Doc(text) => format!("Doc {:?}", text),
+            Ast::Namespace(name, _) => format!("Namespace {}", name),
+            Ast::Assignment(name, _) => format!("Assignment {}", name),
+            Ast::Import(names, module) => {
+                format!("Import {{{}}} from {:?}", names.join(", "), module)
+            }
+            other => String::from(other.kind()),
+        }
+    }
+
+    /// The name of the variant.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Ast::Null => "Null",
+            Ast::Undefined => "Undefined",
+            Ast::Number(_) => "Number",
+            Ast::Float(_) => "Float",
+            Ast::Bool(_) => "Bool",
+            Ast::String(_) => "String",
+            Ast::ArrayLiteral(_) => "ArrayLiteral",
+            Ast::ArrayLookup(_, _) => "ArrayLookup",
+            Ast::ArrayLength(_) => "ArrayLength",
+            Ast::Identifier(_) => "Identifier",
+            Ast::Not(_) => "Not",
+            Ast::Equal(_, _) => "Equal",
+            Ast::NotEqual(_, _) => "NotEqual",
+            Ast::Addition(_, _) => "Addition",
+            Ast::Subtraction(_, _) => "Subtraction",
+            Ast::Multiplication(_, _) => "Multiplication",
+            Ast::Division(_, _) => "Division",
+            Ast::Call(_, _) => "Call",
+            Ast::Return(_) => "Return",
+            Ast::Delete(_) => "Delete",
+            Ast::Block(_) => "Block",
+            Ast::If(_, _, _) => "If",
+            Ast::Function(_, _, _) => "Function",
+            Ast::Var(_, _) => "Var",
+            Ast::Const(_, _) => "Const",
+            Ast::Assignment(_, _) => "Assignment",
+            Ast::While(_, _) => "While",
+            Ast::Import(_, _) => "Import",
+            Ast::Export(_) => "Export",
+            Ast::Extern(_, _) => "Extern",
+            Ast::StaticAssert(_, _) => "StaticAssert",
+            Ast::Doc(_) => "Doc",
+            Ast::Namespace(_, _) => "Namespace",
+        }
+    }
+
+    pub fn children(&self) -> Vec<&Ast> {
+        match self {
+            Ast::Null
+            | Ast::Undefined
+            | Ast::Number(_)
+            | Ast::Float(_)
+            | Ast::Bool(_)
+            | Ast::String(_)
+            | Ast::Identifier(_)
+            | Ast::Import(_, _)
+            | Ast::Extern(_, _)
+            | Ast::Doc(_) => Vec::new(),
+            Ast::ArrayLiteral(elements)
+            | Ast::Call(_, elements)
+            | Ast::Block(elements)
+            | Ast::Namespace(_, elements) => elements.iter().collect(),
+            Ast::ArrayLength(expr)
+            | Ast::Not(expr)
+            | Ast::Return(expr)
+            | Ast::Delete(expr)
+            | Ast::Function(_, _, expr)
+            | Ast::Var(_, expr)
+            | Ast::Const(_, expr)
+            | Ast::Assignment(_, expr)
+            | Ast::Export(expr)
+            | Ast::StaticAssert(expr, _) => vec![expr],
+            Ast::ArrayLookup(lhs, rhs)
+            | Ast::Equal(lhs, rhs)
+            | Ast::NotEqual(lhs, rhs)
+            | Ast::Addition(lhs, rhs)
+            | Ast::Subtraction(lhs, rhs)
+            | Ast::Multiplication(lhs, rhs)
+            | Ast::Division(lhs, rhs)
+            | Ast::While(lhs, rhs) => vec![lhs, rhs],
+            Ast::If(condition, consequence, alternative) => {
+                vec![condition, consequence, alternative]
+            }
+        }
+    }
+
+    /// The statements of a program, which is a block unless it is a single
+    /// statement.
+    pub fn top_level_statements(&self) -> &[Ast] {
+        match self {
+            Ast::Block(statements) => statements,
+            statement => std::slice::from_ref(statement),
+        }
+    }
+
+    /// The top level statements of a program with the exported functions
+    /// taken out of their `Export`.
+    pub fn declarations(&self) -> impl Iterator<Item = &Ast> {
+        self.top_level_statements()
+            .iter()
+            .map(|statement| match statement {
+                Ast::Export(function) => function.as_ref(),
+                statement => statement,
+            })
+    }
+
+    /// The number of nodes in the tree rooted at the node.
+    pub fn size(&self) -> usize {
+        let mut size = 0;
+        let mut nodes = vec![self];
+        while let Some(node) = nodes.pop() {
+            size += 1;
+            nodes.extend(node.children());
+        }
+        size
+    }
+
+    /// The same children as `children`, for passes rewriting the tree.
+    pub fn children_mut(&mut self) -> Vec<&mut Ast> {
+        match self {
+            Ast::Null
+            | Ast::Undefined
+            | Ast::Number(_)
+            | Ast::Float(_)
+            | Ast::Bool(_)
+            | Ast::String(_)
+            | Ast::Identifier(_)
+            | Ast::Import(_, _)
+            | Ast::Extern(_, _)
+            | Ast::Doc(_) => Vec::new(),
+            Ast::ArrayLiteral(elements)
+            | Ast::Call(_, elements)
+            | Ast::Block(elements)
+            | Ast::Namespace(_, elements) => elements.iter_mut().collect(),
+            Ast::ArrayLength(expr)
+            | Ast::Not(expr)
+            | Ast::Return(expr)
+            | Ast::Delete(expr)
+            | Ast::Function(_, _, expr)
+            | Ast::Var(_, expr)
+            | Ast::Const(_, expr)
+            | Ast::Assignment(_, expr)
+            | Ast::Export(expr)
+            | Ast::StaticAssert(expr, _) => vec![expr],
+            Ast::ArrayLookup(lhs, rhs)
+            | Ast::Equal(lhs, rhs)
+            | Ast::NotEqual(lhs, rhs)
+            | Ast::Addition(lhs, rhs)
+            | Ast::Subtraction(lhs, rhs)
+            | Ast::Multiplication(lhs, rhs)
+            | Ast::Division(lhs, rhs)
+            | Ast::While(lhs, rhs) => vec![lhs, rhs],
+            Ast::If(condition, consequence, alternative) => {
+                vec![condition, consequence, alternative]
+            }
+        }
+    }
+
+    /// Calls `rewrite` on every node of the tree once its children are
+    /// rewritten, which may replace the node, with the address the node had
+    /// in the tree before, which the typechecker records the operations by.
+    /// Without recursion, as a chain of operators is as deep as it is long:
+    /// the nodes are taken out of the tree on the way down and put back on
+    /// the way up.
+    pub fn rewrite_bottom_up(&mut self, mut rewrite: impl FnMut(&mut Ast, *const Ast)) {
+        enum Step {
+            Enter(Ast, *const Ast),
+            Exit(Ast, *const Ast),
+        }
+        let address = self as *const Ast;
+        let mut steps = vec![Step::Enter(std::mem::replace(self, Ast::Null), address)];
+        let mut rewritten = Vec::new();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Enter(mut node, address) => {
+                    let children: Vec<Step> = node
+                        .children_mut()
+                        .into_iter()
+                        .map(|child| {
+                            let address = child as *const Ast;
+                            Step::Enter(std::mem::replace(child, Ast::Null), address)
+                        })
+                        .collect();
+                    steps.push(Step::Exit(node, address));
+                    steps.extend(children.into_iter().rev());
+                }
+                Step::Exit(mut node, address) => {
+                    let children = rewritten.split_off(rewritten.len() - node.children().len());
+                    for (child, rewritten) in node.children_mut().into_iter().zip(children) {
+                        *child = rewritten;
+                    }
+                    rewrite(&mut node, address);
+                    rewritten.push(node);
+                }
+            }
+        }
+        *self = rewritten.pop().expect("the root is rewritten");
+    }
+}
+
+/// Draws the tree with ASCII characters, one node per line.
+pub fn to_tree(ast: &Ast) -> String {
+    fn write_node(output: &mut String, ast: &Ast, prefix: &str) {
+        let children = ast.children();
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let (branch, indent) = if last {
+                ("`-- ", "    ")
+            } else {
+                ("|-- ", "|   ")
+            };
+            writeln!(output, "{}{}{}", prefix, branch, child.label()).unwrap();
+            write_node(output, child, &format!("{}{}", prefix, indent));
+        }
+    }
+    let mut output = ast.label() + "\n";
+    write_node(&mut output, ast, "");
+    output
+}
+
+/// Renders the tree as a Graphviz `digraph`, children ordered left to right.
+pub fn to_dot(ast: &Ast) -> String {
+    fn write_node(output: &mut String, ast: &Ast, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        writeln!(output, "    n{} [label={:?}];", id, ast.label()).unwrap();
+        for child in ast.children() {
+            let child_id = write_node(output, child, next_id);
+            writeln!(output, "    n{} -> n{};", id, child_id).unwrap();
+        }
+        id
+    }
+    let mut output = String::from("digraph ast {\n    node [shape=box, fontname=monospace];\n");
+    write_node(&mut output, ast, &mut 0);
+    output.push_str("}\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn tree() {
+        let ast =
+            parser::parse_program("function f(x) { if (!x) { return [x, 1]; } else {} }").unwrap();
+        assert_eq!(
+            to_tree(&ast),
+            "Block
+`-- Function f: (number) => number
+    `-- Block
+        `-- If
+            |-- Not
+            |   `-- Identifier x
+            |-- Block
+            |   `-- Return
+            |       `-- ArrayLiteral
+            |           |-- Identifier x
+            |           `-- Number 1
+            `-- Block
+"
+        );
+    }
+
+    #[test]
+    fn declarations() {
+        let ast = parser::parse_program("export function f() {} var x = 1;").unwrap();
+        let labels: Vec<String> = ast.declarations().map(Ast::label).collect();
+        assert_eq!(labels, ["Function f: () => number", "Var x"]);
+        let statement = Ast::Return(Box::new(Ast::Number(0)));
+        assert_eq!(statement.top_level_statements().len(), 1);
+    }
+
+    #[test]
+    fn dot() {
+        let ast = parser::parse_program("import {a, b} from \"lib\"; var x = a;").unwrap();
+        assert_eq!(
+            to_dot(&ast),
+            r#"digraph ast {
+    node [shape=box, fontname=monospace];
+    n0 [label="Block"];
+    n1 [label="Import {a, b} from \"lib\""];
+    n0 -> n1;
+    n2 [label="Var x"];
+    n3 [label="Identifier a"];
+    n2 -> n3;
+    n0 -> n2;
+}
+"#
+        );
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use super::*;
+    use crate::parser;
+    use crate::parser::combinators::Parser;
+
+    #[test]
+    fn json_round_trip() {
+        let source = "function f(x: number, xs: array<boolean>): void {
+                if (xs[x] != undefined) { return null; } else { putchar(x + 48); }
+            }";
+        let (_, ast) = parser::make_full_parser(&parser::ParseOptions::default())
+            .parse(source)
+            .unwrap();
+        let json = serde_json::to_string(&ast).unwrap();
+        let deserialized: Ast = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, ast);
+    }
+
+    #[test]
+    fn json_shape() {
+        let ast = Ast::Var(String::from("x"), Box::new(Ast::Number(1)));
+        assert_eq!(
+            serde_json::to_string(&ast).unwrap(),
+            r#"{"Var":["x",{"Number":1}]}"#
+        );
+    }
 }
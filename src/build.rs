@@ -0,0 +1,74 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use rtsc::compiler::Target;
+use rtsc::phases::codegen::Entry;
+
+/// Command assembling and linking the generated code for the target, `None`
+/// when the target has no native toolchain. A `_start` entry replaces the C
+/// runtime's startup files, bare metal programs bring their own runtime.
+pub fn default_linker(target: Target, entry: Entry) -> Option<&'static str> {
+    match (target, entry) {
+        (Target::Arm32Linux, Entry::Start) => Some("arm-linux-gnueabihf-gcc -static -nostartfiles"),
+        (Target::Arm32Linux, _) => Some("arm-linux-gnueabihf-gcc -static"),
+        (Target::Arm32NoneEabi, _) => Some("arm-none-eabi-gcc -nostdlib"),
+        (Target::Wasm32, _) => None,
+    }
+}
+
+/// Executable path used when `-o` is not given, the input without its
+/// extension or `a.out` when reading from the standard input.
+pub fn executable_path(input: &str) -> PathBuf {
+    if input == "-" {
+        PathBuf::from("a.out")
+    } else {
+        Path::new(input).with_extension("")
+    }
+}
+
+/// Writes the assembly to a temporary file and runs `linker` on it to
+/// produce the executable at `output`.
+pub fn link(assembly: &str, linker: &str, output: &Path) -> Result<(), String> {
+    let mut words = linker.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| String::from("the linker command is empty"))?;
+
+    let assembly_path = env::temp_dir().join(format!("rtsc-{}.s", process::id()));
+    fs::write(&assembly_path, assembly)
+        .map_err(|error| format!("cannot write `{}`: {}", assembly_path.display(), error))?;
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(&assembly_path)
+        .arg("-o")
+        .arg(output)
+        .status();
+    let _ = fs::remove_file(&assembly_path);
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("`{}` failed with {}", linker, status)),
+        Err(error) => Err(format!("cannot run `{}`: {}", program, error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn executable_paths() {
+        assert_eq!(executable_path("src/main.ts"), PathBuf::from("src/main"));
+        assert_eq!(executable_path("-"), PathBuf::from("a.out"));
+    }
+
+    #[test]
+    fn missing_linker() {
+        assert_eq!(
+            link("", "rtsc-no-such-linker -static", Path::new("main")).unwrap_err(),
+            "cannot run `rtsc-no-such-linker`: No such file or directory (os error 2)"
+        );
+    }
+}
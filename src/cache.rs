@@ -0,0 +1,150 @@
+//! Results of earlier compilations kept in the directory given with
+//! `--incremental`, so that only the modules which changed since are parsed
+//! and typechecked again. Entries are named after a hash of what they were
+//! computed from, so they never go stale, they only stop being used.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::ast::Ast;
+use crate::parser::{self, ParseError, ParseOptions};
+use crate::span::Span;
+use crate::types::Type;
+
+#[derive(Debug)]
+pub struct Cache {
+    directory: PathBuf,
+}
+
+impl Cache {
+    /// Keeps the cache in the directory, creating it when missing.
+    pub fn open(directory: impl Into<PathBuf>) -> io::Result<Cache> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(Cache { directory })
+    }
+
+    /// The tree of the source and the spans of its statements, parsed only
+    /// when they are not in the cache. Trees are stored as JSON, without the
+    /// `serde` feature every source is parsed. The options are part of the
+    /// key, as the implicit type ends up in the tree and the nesting limit
+    /// decides whether it parses.
+    pub fn parse(
+        &self,
+        source: &str,
+        options: &ParseOptions,
+    ) -> Result<(Ast, Vec<Span>), ParseError> {
+        let implicit = options.implicit_type.to_string();
+        let max_depth = options.max_depth.to_string();
+        let path = self.entry(&[source, &implicit, &max_depth], "json");
+        if let Some(parsed) = read_ast(&path) {
+            return Ok(parsed);
+        }
+        let parsed = parser::parse_program_with_spans(source, options)?;
+        write_ast(&path, &parsed);
+        Ok(parsed)
+    }
+
+    /// Whether the source typechecked before with the same functions in
+    /// scope.
+    pub fn is_checked(&self, source: &str, functions: &LinkedHashMap<String, Type>) -> bool {
+        self.checked_entry(source, functions).exists()
+    }
+
+    /// Records that the source typechecks with the functions in scope.
+    pub fn set_checked(&self, source: &str, functions: &LinkedHashMap<String, Type>) {
+        // a cache which cannot be written only makes the next build slower
+        let _ = fs::write(self.checked_entry(source, functions), "");
+    }
+
+    fn checked_entry(&self, source: &str, functions: &LinkedHashMap<String, Type>) -> PathBuf {
+        let signatures: String = functions
+            .iter()
+            .map(|(name, signature)| format!("{}: {}\n", name, signature))
+            .collect();
+        self.entry(&[source, &signatures], "checked")
+    }
+
+    // Entries of other versions of the compiler get other names.
+    fn entry(&self, parts: &[&str], extension: &str) -> PathBuf {
+        let mut bytes = Vec::from(env!("CARGO_PKG_VERSION").as_bytes());
+        for part in parts {
+            // 0xff never appears in UTF-8, so the parts cannot run together
+            bytes.push(0xff);
+            bytes.extend_from_slice(part.as_bytes());
+        }
+        self.directory
+            .join(format!("{:016x}.{}", hash(&bytes), extension))
+    }
+}
+
+/// 64 bit FNV-1a, which unlike the hasher of the standard library is the
+/// same in every build of the compiler.
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// A damaged entry is parsed again and overwritten.
+#[cfg(feature = "serde")]
+fn read_ast(path: &Path) -> Option<(Ast, Vec<Span>)> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+#[cfg(feature = "serde")]
+fn write_ast(path: &Path, parsed: &(Ast, Vec<Span>)) {
+    // written aside and renamed, so that no compilation reads half of it
+    let partial = path.with_extension("partial");
+    if fs::write(&partial, serde_json::to_string(parsed).unwrap()).is_ok() {
+        let _ = fs::rename(partial, path);
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn read_ast(_path: &Path) -> Option<(Ast, Vec<Span>)> {
+    None
+}
+
+#[cfg(not(feature = "serde"))]
+fn write_ast(_path: &Path, _parsed: &(Ast, Vec<Span>)) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[test]
+    fn hashing() {
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn entries() {
+        let directory = env::temp_dir().join(format!("rtsc-cache-test-{}", process::id()));
+        let cache = Cache::open(&directory).unwrap();
+        let source = "function f(x) { return x; }";
+        let mut functions = LinkedHashMap::new();
+        assert_eq!(
+            cache.parse(source, &ParseOptions::default()),
+            parser::parse_program_with_spans(source, &ParseOptions::default())
+        );
+        // cached trees are the ones parsed before
+        assert_eq!(
+            cache.parse(source, &ParseOptions::default()),
+            parser::parse_program_with_spans(source, &ParseOptions::default())
+        );
+        assert!(cache.parse("function", &ParseOptions::default()).is_err());
+
+        assert!(!cache.is_checked(source, &functions));
+        cache.set_checked(source, &functions);
+        assert!(cache.is_checked(source, &functions));
+        functions.insert(String::from("g"), Type::Number);
+        assert!(!cache.is_checked(source, &functions));
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,1438 @@
+use rtsc::compiler::Target;
+use rtsc::diagnostic::{self, WarningLevel, WarningOptions, WARNINGS};
+use rtsc::doc::DocFormat;
+use rtsc::parser;
+use rtsc::phases::codegen::{
+    Arithmetic, AsmHeader, BoundsCheck, CodegenOptions, Console, Entry, Platform,
+};
+use rtsc::phases::passes::{self, Pass, Passes};
+use rtsc::phases::{coverage, profile};
+use rtsc::types::Type;
+
+// The deepest `--max-depth`, its stack for the parser already taking 320 MiB
+const MAX_DEPTH_LIMIT: usize = 10_000;
+
+pub const USAGE: &str = "Usage: rtsc [OPTIONS] <INPUT>
+       rtsc lsp
+       rtsc doc [--format <markdown|html>] [-o <FILE>] <INPUT>
+       rtsc metrics [--max-complexity <N>] [-o <FILE>] <INPUT>
+       rtsc cov report [-o <FILE>] <INPUT> [<COUNTS>]
+       rtsc prof report [-o <FILE>] <INPUT> [<PROFILE>]
+       rtsc --print-passes [-O<LEVEL>] [--disable-pass <NAME>]...
+       rtsc --demangle [<INPUT>]
+       rtsc --explain <CODE>
+
+Compiles a subset of TypeScript to ARM32 assembly or WebAssembly. Pass `-` as the INPUT
+to read the source from the standard input. The `lsp` command starts a
+language server communicating over the standard input and output. The `doc`
+command renders the signatures of the functions of the INPUT module and
+their `///` comments to Markdown (default) or HTML. The `metrics` command
+reports the statement count, nesting depth and cyclomatic complexity of each
+function, failing when one is more complex than `--max-complexity`. The
+`cov report` command lists the source of the INPUT with how often each line
+ran, from the COUNTS written by a program built with `--instrument-coverage`
+(rtsc.cov by default). The `prof report` command lists the calls and clock
+ticks of each function, the slowest first, from the PROFILE written by a
+program built with `--instrument-profile` (rtsc.prof by default).
+`--print-passes` lists the optional passes which run at the `-O` level.
+`--demangle` copies the INPUT (the standard input by default), such as the
+assembly, replacing the symbols of nested functions with readable names.
+`--explain` describes the error or warning with the code, e.g. E0002.
+
+Options:
+    -o, --output <FILE>    Write the output to FILE instead of stdout
+        --emit <KIND>      What to output: asm (default), llvm-ir, tokens, ast, ast-json,
+                           ast-tree (drawn with ASCII), ast-dot (Graphviz DOT) or
+                           cfg (control-flow graphs of the functions in Graphviz DOT),
+                           call-graph (Graphviz DOT with the stack depth of each
+                           function on ARM32), stack-usage (the frame size and
+                           worst case stack depth of each ARM32 function, with
+                           the calls reaching it), symbols (JSON of the functions
+                           with the stack offsets of their variables and of the
+                           globals of the ARM32 code), ir (the ARM32 instructions
+                           of the functions in basic blocks), ir-opt (the same
+                           after the optimization passes) or obj (an ELF object file
+                           encoded without an assembler, written next to the input
+                           unless `-o` is given)
+        --target <TARGET>  Target to generate code for: arm-linux-gnueabihf (default),
+                           arm-none-eabi (bare metal ARM32 without the C library)
+                           or wasm32 (WebAssembly text format)
+        --target-spec <FILE>
+                           Adjust the ARM32 target to the architecture, the float
+                           calling convention and the division instructions given
+                           in FILE, a target specification in TOML
+        --putchar <CONSOLE>
+                           Where `putchar` writes: syscall (the `write` system call
+                           instead of the C library), or on arm-none-eabi
+                           semihosting (default) or mmio=ADDRESS (a transmit register)
+        --unsigned         Treat numbers as unsigned in division
+        --bounds-check <MODE>
+                           What out of bounds array lookups do: zero (default),
+                           trap (print an error and abort) or off (no check)
+        --division-check <on|off>
+                           Print an error and abort on division by zero in ARM32
+                           code, on by default with `-g`
+        --null-check       Print an error and abort when indexing or taking the length
+                           of an undefined array in ARM32 code
+        --gc               Free unreachable arrays with a mark-sweep garbage collector,
+                           only for ARM32 assembly
+        --source-comments  Precede the ARM32 code of every statement with a comment
+                           quoting its source line
+        --entry <KIND>     Wrap the program's `main` for ARM32: none (default), main (a C
+                           `main` passing its result to `exit`) or start (a `_start`
+                           making the exit system call, for linking without the C
+                           runtime)
+        --asm-header <KIND>
+                           Directives starting the ARM32 assembly: none (default, the
+                           assembler's defaults have to allow `sdiv`) or unified (the
+                           syntax, architecture and ABI spelled out, for both GNU as
+                           and clang without flags)
+        --thumb            Emit Thumb-2 instead of ARM code, smaller and the only kind
+                           Cortex-M cores run
+        --instrument-coverage
+                           Count the runs of every basic block of the ARM32 code and
+                           write the counts to rtsc.cov when `main` returns, needs
+                           `--entry`
+        --instrument-profile
+                           Count the calls of every function of the ARM32 code and
+                           the time spent in them, in microseconds of CPU time on
+                           Linux and in cycles on Cortex-M, and write them to
+                           rtsc.prof when `main` returns, needs `--entry`
+    -g, --debug-info       Map the ARM32 code to the source lines with `.file` and `.loc`
+                           directives, letting debuggers step through the source
+        --source-map <FILE>
+                           Write a JSON map from the lines of the ARM32 assembly to
+                           the source spans of the statements they come from to FILE
+    -O<LEVEL>              Run the optional passes of the level: 0 (none), 1 (default)
+                           or 2
+        --disable-pass <NAME>
+                           Do not run the pass, see `--print-passes` for their names
+        --check            Only parse and typecheck the input, exit with 1 on errors
+        --deny-warnings    Make every warning an error, failing the compilation
+        --implicit-type <TYPE>
+                           The type of parameters and return types without a type
+                           annotation, number by default
+        --max-depth <N>    How deep expressions and statements can nest, 100 by
+                           default
+        --strict           Make parameters and return types without a type annotation
+                           errors instead of warnings, also `--deny W0003`
+        --allow <CODE>     Do not report the warning with the code, e.g. W0002
+        --warn <CODE>      Report the warning with the code without failing
+        --deny <CODE>      Make the warning with the code an error
+        --error-format <FORMAT>
+                           How errors and warnings are printed: human (default) or
+                           json (an object with the file, span, severity, code and
+                           message per line)
+        --incremental <DIR>
+                           Keep the parsed and typechecked modules in DIR and only
+                           process the ones which changed in later compilations
+        --watch            Compile again whenever the input or a module it imports
+                           changes, until interrupted
+        --time-passes      Print the time taken by every phase of the compilation and
+                           the size of its output to stderr, also -Ztime-passes
+        --build            Assemble and link the output into an executable, named
+                           after the input unless `-o` is given
+        --linker <COMMAND> Command used by `--build` to assemble and link, by default
+                           arm-linux-gnueabihf-gcc -static
+    -h, --help             Print this help message
+    -V, --version          Print the version";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Asm,
+    LlvmIr,
+    Tokens,
+    Ast,
+    AstJson,
+    Cfg,
+    AstTree,
+    AstDot,
+    CallGraph,
+    StackUsage,
+    Symbols,
+    Ir,
+    IrOpt,
+    Obj,
+}
+
+impl Emit {
+    fn from_flag_value(value: &str) -> Option<Emit> {
+        match value {
+            "asm" => Some(Emit::Asm),
+            "llvm-ir" => Some(Emit::LlvmIr),
+            "tokens" => Some(Emit::Tokens),
+            "ast" => Some(Emit::Ast),
+            "ast-json" => Some(Emit::AstJson),
+            "cfg" => Some(Emit::Cfg),
+            "ast-tree" => Some(Emit::AstTree),
+            "ast-dot" => Some(Emit::AstDot),
+            "call-graph" => Some(Emit::CallGraph),
+            "stack-usage" => Some(Emit::StackUsage),
+            "symbols" => Some(Emit::Symbols),
+            "ir" => Some(Emit::Ir),
+            "ir-opt" => Some(Emit::IrOpt),
+            "obj" => Some(Emit::Obj),
+            _ => None,
+        }
+    }
+}
+
+// The target and the codegen options are defined by the library, so their
+// flag values are parsed here.
+fn target_from_flag_value(value: &str) -> Option<Target> {
+    match value {
+        "arm-linux-gnueabihf" => Some(Target::Arm32Linux),
+        "arm-none-eabi" => Some(Target::Arm32NoneEabi),
+        "wasm32" => Some(Target::Wasm32),
+        _ => None,
+    }
+}
+
+fn entry_from_flag_value(value: &str) -> Option<Entry> {
+    match value {
+        "none" => Some(Entry::None),
+        "main" => Some(Entry::Main),
+        "start" => Some(Entry::Start),
+        _ => None,
+    }
+}
+
+fn console_from_flag_value(value: &str) -> Option<Console> {
+    match value.split_once('=') {
+        None if value == "semihosting" => Some(Console::Semihosting),
+        None if value == "syscall" => Some(Console::Syscall),
+        Some(("mmio", address)) => match address.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => address.parse().ok(),
+        }
+        .map(Console::Mmio),
+        _ => None,
+    }
+}
+
+fn asm_header_from_flag_value(value: &str) -> Option<AsmHeader> {
+    match value {
+        "none" => Some(AsmHeader::None),
+        "unified" => Some(AsmHeader::Unified),
+        _ => None,
+    }
+}
+
+fn bounds_check_from_flag_value(value: &str) -> Option<BoundsCheck> {
+    match value {
+        "zero" => Some(BoundsCheck::Zero),
+        "trap" => Some(BoundsCheck::Trap),
+        "off" => Some(BoundsCheck::Off),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Human,
+    /// A JSON object per line.
+    Json,
+}
+
+impl ErrorFormat {
+    fn from_flag_value(value: &str) -> Option<ErrorFormat> {
+        match value {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    pub input: String,
+    pub output: Option<String>,
+    /// Where `--source-map` writes the source map of the assembly.
+    pub source_map: Option<String>,
+    pub implicit_type: Type,
+    /// How deep expressions and statements can nest.
+    pub max_depth: usize,
+    pub emit: Emit,
+    pub target: Target,
+    /// The file `--target-spec` reads the `codegen.target` from.
+    pub target_spec: Option<String>,
+    pub codegen: CodegenOptions,
+    pub check_only: bool,
+    pub build: bool,
+    pub linker: Option<String>,
+    pub warnings: WarningOptions,
+    pub error_format: ErrorFormat,
+    pub time_passes: bool,
+    pub watch: bool,
+    pub incremental: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocOptions {
+    pub input: String,
+    pub output: Option<String>,
+    pub format: DocFormat,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricsOptions {
+    pub input: String,
+    pub output: Option<String>,
+    pub max_complexity: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageOptions {
+    pub input: String,
+    /// The file written by the instrumented program.
+    pub counts: String,
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileOptions {
+    pub input: String,
+    /// The file written by the instrumented program.
+    pub profile: String,
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Compile(Box<Options>),
+    Help,
+    Version,
+    Lsp,
+    Doc(DocOptions),
+    Metrics(MetricsOptions),
+    CoverageReport(CoverageOptions),
+    ProfileReport(ProfileOptions),
+    PrintPasses(Passes),
+    /// The file to demangle, the standard input without one.
+    Demangle(Option<String>),
+    Explain(String),
+}
+
+// Splits `--flag=value` into the flag and the value, so that both
+// `--flag value` and `--flag=value` forms are accepted.
+fn split_flag(arg: &str) -> (&str, Option<&str>) {
+    match arg.find('=') {
+        Some(i) if arg.starts_with("--") => (&arg[..i], Some(&arg[i + 1..])),
+        _ => (arg, None),
+    }
+}
+
+fn parse_doc_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let mut input = None;
+    let mut output = None;
+    let mut format = DocFormat::Markdown;
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        let mut value = || {
+            inline_value
+                .map(String::from)
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for `{}`", flag))
+        };
+        match flag {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-o" | "--output" => output = Some(value()?),
+            "--format" => {
+                let value = value()?;
+                format = match value.as_str() {
+                    "markdown" => DocFormat::Markdown,
+                    "html" => DocFormat::Html,
+                    _ => return Err(format!("unknown doc format `{}`", value)),
+                };
+            }
+            _ if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ => {
+                if let Some(previous) = input.replace(arg.clone()) {
+                    return Err(format!(
+                        "expected a single input file, got `{}` and `{}`",
+                        previous, arg
+                    ));
+                }
+            }
+        }
+    }
+    Ok(Command::Doc(DocOptions {
+        input: input.ok_or_else(|| String::from("no input file given"))?,
+        output,
+        format,
+    }))
+}
+
+fn parse_metrics_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let mut input = None;
+    let mut output = None;
+    let mut max_complexity = None;
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        let mut value = || {
+            inline_value
+                .map(String::from)
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for `{}`", flag))
+        };
+        match flag {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-o" | "--output" => output = Some(value()?),
+            "--max-complexity" => {
+                let value = value()?;
+                max_complexity = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid complexity `{}`", value))?,
+                );
+            }
+            _ if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ => {
+                if let Some(previous) = input.replace(arg.clone()) {
+                    return Err(format!(
+                        "expected a single input file, got `{}` and `{}`",
+                        previous, arg
+                    ));
+                }
+            }
+        }
+    }
+    Ok(Command::Metrics(MetricsOptions {
+        input: input.ok_or_else(|| String::from("no input file given"))?,
+        output,
+        max_complexity,
+    }))
+}
+
+// Parses the arguments of `rtsc cov` and `rtsc prof` following the name of
+// the `tool`, `report [-o FILE] INPUT [DATA]`, giving `None` for `--help`.
+fn parse_report_args(
+    tool: &str,
+    default_data: &str,
+    args: impl IntoIterator<Item = String>,
+) -> Result<Option<(String, String, Option<String>)>, String> {
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        Some("report") => {}
+        Some(other) => return Err(format!("unknown {} command `{}`", tool, other)),
+        None => return Err(format!("missing {} command, e.g. `report`", tool)),
+    }
+    let mut files = Vec::new();
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        match flag {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => {
+                output = Some(
+                    inline_value
+                        .map(String::from)
+                        .or_else(|| args.next())
+                        .ok_or_else(|| format!("missing value for `{}`", flag))?,
+                )
+            }
+            _ if flag.starts_with('-') => return Err(format!("unknown option `{}`", flag)),
+            _ => files.push(arg),
+        }
+    }
+    let mut files = files.into_iter();
+    let input = files
+        .next()
+        .ok_or_else(|| String::from("no input file given"))?;
+    let data = files.next().unwrap_or_else(|| String::from(default_data));
+    if let Some(extra) = files.next() {
+        return Err(format!("unexpected argument `{}`", extra));
+    }
+    Ok(Some((input, data, output)))
+}
+
+fn parse_coverage_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    Ok(
+        match parse_report_args("coverage", coverage::FILE_NAME, args)? {
+            Some((input, counts, output)) => Command::CoverageReport(CoverageOptions {
+                input,
+                counts,
+                output,
+            }),
+            None => Command::Help,
+        },
+    )
+}
+
+fn parse_profile_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    Ok(
+        match parse_report_args("profile", profile::FILE_NAME, args)? {
+            Some((input, profile, output)) => Command::ProfileReport(ProfileOptions {
+                input,
+                profile,
+                output,
+            }),
+            None => Command::Help,
+        },
+    )
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(String::as_str) == Some("lsp") {
+        args.next();
+        return match args.next() {
+            Some(extra) => Err(format!("unexpected argument `{}`", extra)),
+            None => Ok(Command::Lsp),
+        };
+    }
+    if args.peek().map(String::as_str) == Some("doc") {
+        args.next();
+        return parse_doc_args(args);
+    }
+    if args.peek().map(String::as_str) == Some("metrics") {
+        args.next();
+        return parse_metrics_args(args);
+    }
+    if args.peek().map(String::as_str) == Some("cov") {
+        args.next();
+        return parse_coverage_args(args);
+    }
+    if args.peek().map(String::as_str) == Some("prof") {
+        args.next();
+        return parse_profile_args(args);
+    }
+    let mut input = None;
+    let mut output = None;
+    let mut emit = Emit::Asm;
+    let mut target = Target::Arm32Linux;
+    let mut target_spec = None;
+    let mut codegen = CodegenOptions::default();
+    let mut check_only = false;
+    let mut build = false;
+    let mut linker = None;
+    let mut console = None;
+    let mut division_check = None;
+    let mut warnings = WarningOptions::default();
+    let mut error_format = ErrorFormat::Human;
+    let mut time_passes = false;
+    let mut watch = false;
+    let mut incremental = None;
+    let mut source_map = None;
+    let mut level = passes::DEFAULT_LEVEL;
+    let mut disabled = Vec::new();
+    let mut print_passes = false;
+    let mut demangle = false;
+    let mut implicit_type = Type::Number;
+    let mut max_depth = parser::MAX_DEPTH;
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = split_flag(&arg);
+        let mut value = || {
+            inline_value
+                .map(String::from)
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for `{}`", flag))
+        };
+        match flag {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--explain" => return Ok(Command::Explain(value()?)),
+            "--print-passes" => print_passes = true,
+            "--demangle" => demangle = true,
+            "-o" | "--output" => output = Some(value()?),
+            "--emit" => {
+                let value = value()?;
+                emit = Emit::from_flag_value(&value)
+                    .ok_or_else(|| format!("unknown emit kind `{}`", value))?;
+            }
+            "--target" => {
+                let value = value()?;
+                target = target_from_flag_value(&value)
+                    .ok_or_else(|| format!("unknown target `{}`", value))?;
+            }
+            "--target-spec" => target_spec = Some(value()?),
+            "--unsigned" => codegen.arithmetic = Arithmetic::Unsigned,
+            "--bounds-check" => {
+                let value = value()?;
+                codegen.bounds_check = bounds_check_from_flag_value(&value)
+                    .ok_or_else(|| format!("unknown bounds check mode `{}`", value))?;
+            }
+            "--division-check" => {
+                let value = value()?;
+                division_check = Some(match value.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("unknown division check `{}`", value)),
+                });
+            }
+            "--null-check" => codegen.null_check = true,
+            "--gc" => codegen.gc = true,
+            "--source-comments" => codegen.source_comments = true,
+            "--entry" => {
+                let value = value()?;
+                codegen.entry = entry_from_flag_value(&value)
+                    .ok_or_else(|| format!("unknown entry kind `{}`", value))?;
+            }
+            "-g" | "--debug-info" => codegen.debug_info = true,
+            "--source-map" => source_map = Some(value()?),
+            "--thumb" => codegen.thumb = true,
+            "--instrument-coverage" => codegen.coverage = true,
+            "--instrument-profile" => codegen.profile = true,
+            "--asm-header" => {
+                let value = value()?;
+                codegen.header = asm_header_from_flag_value(&value)
+                    .ok_or_else(|| format!("unknown assembly header `{}`", value))?;
+            }
+            "--putchar" => {
+                let value = value()?;
+                console = Some(
+                    console_from_flag_value(&value)
+                        .ok_or_else(|| format!("unknown putchar console `{}`", value))?,
+                );
+            }
+            "--check" => check_only = true,
+            "--build" => build = true,
+            "--linker" => linker = Some(value()?),
+            "--error-format" => {
+                let value = value()?;
+                error_format = ErrorFormat::from_flag_value(&value)
+                    .ok_or_else(|| format!("unknown error format `{}`", value))?;
+            }
+            "--deny-warnings" => warnings.deny_warnings = true,
+            "--implicit-type" => {
+                let value = value()?;
+                implicit_type =
+                    parser::parse_type(&value).map_err(|_| format!("unknown type `{}`", value))?;
+            }
+            "--max-depth" => {
+                let value = value()?;
+                max_depth = value
+                    .parse()
+                    .ok()
+                    .filter(|depth| (1..=MAX_DEPTH_LIMIT).contains(depth))
+                    .ok_or_else(|| {
+                        format!(
+                            "invalid depth `{}`, expected a number from 1 to {}",
+                            value, MAX_DEPTH_LIMIT
+                        )
+                    })?;
+            }
+            "--strict" => warnings.levels.push((
+                String::from(diagnostic::MISSING_ANNOTATION),
+                WarningLevel::Deny,
+            )),
+            "--time-passes" | "-Ztime-passes" => time_passes = true,
+            "--watch" => watch = true,
+            "--incremental" => incremental = Some(value()?),
+            "--disable-pass" => {
+                let name = value()?;
+                disabled.push(
+                    Pass::from_name(&name).ok_or_else(|| format!("unknown pass `{}`", name))?,
+                );
+            }
+            _ if flag.starts_with("-O") => {
+                level = flag[2..]
+                    .parse()
+                    .ok()
+                    .filter(|level| *level <= passes::MAX_LEVEL)
+                    .ok_or_else(|| format!("unknown optimization level `{}`", &flag[2..]))?;
+            }
+            "--allow" | "--warn" | "--deny" => {
+                let code = value()?;
+                if !WARNINGS.iter().any(|(known, _)| *known == code) {
+                    return Err(format!("unknown warning `{}`", code));
+                }
+                let level = match flag {
+                    "--allow" => WarningLevel::Allow,
+                    "--warn" => WarningLevel::Warn,
+                    _ => WarningLevel::Deny,
+                };
+                warnings.levels.push((code, level));
+            }
+            _ if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option `{}`", flag));
+            }
+            _ => {
+                if let Some(previous) = input.replace(arg.clone()) {
+                    return Err(format!(
+                        "expected a single input file, got `{}` and `{}`",
+                        previous, arg
+                    ));
+                }
+            }
+        }
+    }
+
+    codegen.passes = disabled
+        .into_iter()
+        .fold(Passes::at_level(level), Passes::without);
+    if print_passes {
+        return Ok(Command::PrintPasses(codegen.passes));
+    }
+    if demangle {
+        return Ok(Command::Demangle(input));
+    }
+    let input = input.ok_or_else(|| String::from("no input file given"))?;
+    // debug builds catch the division by zero unless told otherwise
+    codegen.division_check = division_check.unwrap_or(codegen.debug_info);
+    if watch && input == "-" {
+        return Err(String::from("`--watch` needs an input file"));
+    }
+    if build && emit != Emit::Asm {
+        return Err(String::from("`--build` can only be used with `--emit=asm`"));
+    }
+    // the map refers to the lines of the assembly written out
+    if source_map.is_some() && (emit != Emit::Asm || build) {
+        return Err(String::from(
+            "`--source-map` can only be used with `--emit=asm` without `--build`",
+        ));
+    }
+    codegen.source_map = source_map.is_some();
+    let arm32_only = [
+        (codegen.gc, "--gc"),
+        (codegen.source_comments, "--source-comments"),
+        (codegen.debug_info, "--debug-info"),
+        (codegen.source_map, "--source-map"),
+        (codegen.entry != Entry::None, "--entry"),
+        (console.is_some(), "--putchar"),
+        (division_check == Some(true), "--division-check"),
+        (codegen.null_check, "--null-check"),
+        (codegen.header != AsmHeader::None, "--asm-header"),
+        (codegen.thumb, "--thumb"),
+        (codegen.coverage, "--instrument-coverage"),
+        (codegen.profile, "--instrument-profile"),
+        (target_spec.is_some(), "--target-spec"),
+    ];
+    if emit == Emit::Obj && target == Target::Wasm32 {
+        return Err(String::from(
+            "`--emit=obj` is only supported when generating ARM32 code",
+        ));
+    }
+    for (kind, flag) in [
+        (Emit::StackUsage, "--emit=stack-usage"),
+        (Emit::Symbols, "--emit=symbols"),
+        (Emit::Ir, "--emit=ir"),
+        (Emit::IrOpt, "--emit=ir-opt"),
+    ] {
+        if emit == kind && target == Target::Wasm32 {
+            return Err(format!(
+                "`{}` is only supported when generating ARM32 code",
+                flag
+            ));
+        }
+    }
+    if emit == Emit::Obj && codegen.debug_info {
+        return Err(String::from(
+            "`--debug-info` is not supported with `--emit=obj`",
+        ));
+    }
+    if emit == Emit::Obj && codegen.thumb {
+        return Err(String::from("`--thumb` is not supported with `--emit=obj`"));
+    }
+    for (enabled, flag) in arm32_only {
+        if enabled && (emit == Emit::LlvmIr || target == Target::Wasm32) {
+            return Err(format!(
+                "`{}` is only supported when generating ARM32 assembly",
+                flag
+            ));
+        }
+    }
+    // the counts are written once the wrapper gets back from `main`
+    let instrumentation = [
+        (codegen.coverage, "--instrument-coverage"),
+        (codegen.profile, "--instrument-profile"),
+    ];
+    for (enabled, flag) in instrumentation {
+        if enabled && codegen.entry == Entry::None && matches!(emit, Emit::Asm | Emit::Obj) {
+            return Err(format!(
+                "`{}` needs `--entry=main` or `--entry=start`",
+                flag
+            ));
+        }
+    }
+    if target == Target::Arm32NoneEabi {
+        // there is neither `malloc` and `free` for the collector nor `exit`
+        // for the C `main` wrapper
+        if codegen.gc {
+            return Err(String::from("`--gc` is not supported on arm-none-eabi"));
+        }
+        if codegen.entry == Entry::Main {
+            return Err(String::from(
+                "`--entry=main` is not supported on arm-none-eabi",
+            ));
+        }
+        if console == Some(Console::Syscall) {
+            return Err(String::from(
+                "`--putchar=syscall` is not supported on arm-none-eabi",
+            ));
+        }
+        for (enabled, flag) in instrumentation {
+            if enabled && matches!(console, Some(Console::Mmio(_))) {
+                return Err(format!("`{}` needs semihosting on arm-none-eabi", flag));
+            }
+        }
+        // the cycle counter is only found on Cortex-M cores
+        if codegen.profile && !codegen.thumb {
+            return Err(String::from(
+                "`--instrument-profile` needs `--thumb` on arm-none-eabi",
+            ));
+        }
+        codegen.platform = Platform::BareMetal;
+        codegen.console = console.unwrap_or(Console::Semihosting);
+    } else if let Some(console) = console {
+        if console != Console::Syscall {
+            return Err(String::from(
+                "only `--putchar=syscall` is supported without `--target arm-none-eabi`",
+            ));
+        }
+        codegen.console = console;
+    }
+    Ok(Command::Compile(Box::new(Options {
+        input,
+        output,
+        source_map,
+        implicit_type,
+        max_depth,
+        emit,
+        target,
+        target_spec,
+        codegen,
+        check_only,
+        build,
+        linker,
+        warnings,
+        error_format,
+        time_passes,
+        watch,
+        incremental,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtsc::phases::target::TargetSpec;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| String::from(*arg)))
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(
+            parse(&["main.ts"]),
+            Ok(Command::Compile(Box::new(Options {
+                input: String::from("main.ts"),
+                output: None,
+                source_map: None,
+                implicit_type: Type::Number,
+                max_depth: parser::MAX_DEPTH,
+                emit: Emit::Asm,
+                target: Target::Arm32Linux,
+                target_spec: None,
+                codegen: CodegenOptions::default(),
+                check_only: false,
+                build: false,
+                linker: None,
+                warnings: WarningOptions::default(),
+                error_format: ErrorFormat::Human,
+                time_passes: false,
+                watch: false,
+                incremental: None,
+            })))
+        );
+    }
+
+    #[test]
+    fn all_options() {
+        assert_eq!(
+            parse(&[
+                "--emit",
+                "ast",
+                "-o",
+                "main.txt",
+                "main.ts",
+                "--target=arm-linux-gnueabihf",
+                "--target-spec=armv7.toml",
+                "--check",
+                "--unsigned",
+                "--bounds-check=trap",
+                "--gc",
+                "--source-comments",
+                "-g",
+                "--entry=start",
+                "--null-check",
+                "--asm-header=unified",
+                "--thumb",
+                "--instrument-coverage",
+                "--instrument-profile",
+                "-O2",
+                "--disable-pass=const-fold",
+                "--implicit-type",
+                "array<float>",
+                "--max-depth=500"
+            ]),
+            Ok(Command::Compile(Box::new(Options {
+                input: String::from("main.ts"),
+                output: Some(String::from("main.txt")),
+                source_map: None,
+                implicit_type: Type::Array {
+                    element_type: Box::new(Type::Float)
+                },
+                max_depth: 500,
+                emit: Emit::Ast,
+                target: Target::Arm32Linux,
+                target_spec: Some(String::from("armv7.toml")),
+                codegen: CodegenOptions {
+                    arithmetic: Arithmetic::Unsigned,
+                    bounds_check: BoundsCheck::Trap,
+                    gc: true,
+                    source_comments: true,
+                    debug_info: true,
+                    entry: Entry::Start,
+                    division_check: true,
+                    null_check: true,
+                    platform: Platform::Linux,
+                    console: Console::Libc,
+                    header: AsmHeader::Unified,
+                    thumb: true,
+                    coverage: true,
+                    profile: true,
+                    source_map: false,
+                    passes: Passes::at_level(2).without(Pass::ConstFold),
+                    target: TargetSpec::default(),
+                },
+                check_only: true,
+                build: false,
+                linker: None,
+                warnings: WarningOptions::default(),
+                error_format: ErrorFormat::Human,
+                time_passes: false,
+                watch: false,
+                incremental: None,
+            })))
+        );
+    }
+
+    #[test]
+    fn cfg() {
+        match parse(&["--emit=cfg", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(options.emit, Emit::Cfg),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse(&["--emit=call-graph", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(options.emit, Emit::CallGraph),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse(&["--emit=stack-usage", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(options.emit, Emit::StackUsage),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse(&["--emit=symbols", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(options.emit, Emit::Symbols),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--emit=stack-usage", "--target=wasm32", "main.ts"]),
+            Err(String::from(
+                "`--emit=stack-usage` is only supported when generating ARM32 code"
+            ))
+        );
+        match parse(&["--emit=ir-opt", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(options.emit, Emit::IrOpt),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse(&["--emit=obj", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(options.emit, Emit::Obj),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn thumb() {
+        match parse(&["--target=arm-none-eabi", "--thumb", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert!(options.codegen.thumb),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--thumb", "--emit=obj", "main.ts"]),
+            Err(String::from("`--thumb` is not supported with `--emit=obj`"))
+        );
+        assert_eq!(
+            parse(&["--thumb", "--target=wasm32", "main.ts"]),
+            Err(String::from(
+                "`--thumb` is only supported when generating ARM32 assembly"
+            ))
+        );
+    }
+
+    #[test]
+    fn target_spec() {
+        match parse(&["--target-spec", "armv6.toml", "main.ts"]) {
+            Ok(Command::Compile(options)) => {
+                assert_eq!(options.target_spec, Some(String::from("armv6.toml")))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--target-spec=armv6.toml", "--emit=llvm-ir", "main.ts"]),
+            Err(String::from(
+                "`--target-spec` is only supported when generating ARM32 assembly"
+            ))
+        );
+    }
+
+    #[test]
+    fn wasm_target() {
+        match parse(&["--target", "wasm32", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(options.target, Target::Wasm32),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn division_check() {
+        let division_check = |args: &[&str]| match parse(args) {
+            Ok(Command::Compile(options)) => options.codegen.division_check,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert!(!division_check(&["main.ts"]));
+        assert!(division_check(&["-g", "main.ts"]));
+        assert!(!division_check(&["-g", "--division-check=off", "main.ts"]));
+        assert!(division_check(&["--division-check", "on", "main.ts"]));
+        assert_eq!(
+            parse(&["--division-check=yes", "main.ts"]),
+            Err(String::from("unknown division check `yes`"))
+        );
+    }
+
+    #[test]
+    fn bare_metal_target() {
+        match parse(&["--target", "arm-none-eabi", "main.ts"]) {
+            Ok(Command::Compile(options)) => {
+                assert_eq!(options.target, Target::Arm32NoneEabi);
+                assert_eq!(options.codegen.platform, Platform::BareMetal);
+                assert_eq!(options.codegen.console, Console::Semihosting);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse(&[
+            "--target=arm-none-eabi",
+            "--putchar=mmio=0x4000c000",
+            "main.ts",
+        ]) {
+            Ok(Command::Compile(options)) => {
+                assert_eq!(options.codegen.console, Console::Mmio(0x4000_c000))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--putchar=semihosting", "main.ts"]),
+            Err(String::from(
+                "only `--putchar=syscall` is supported without `--target arm-none-eabi`"
+            ))
+        );
+        match parse(&["--putchar=syscall", "--entry=start", "main.ts"]) {
+            Ok(Command::Compile(options)) => {
+                assert_eq!(options.codegen.platform, Platform::Linux);
+                assert_eq!(options.codegen.console, Console::Syscall);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--putchar=syscall", "--target=wasm32", "main.ts"]),
+            Err(String::from(
+                "`--putchar` is only supported when generating ARM32 assembly"
+            ))
+        );
+        assert_eq!(
+            parse(&["--target=arm-none-eabi", "--putchar=mmio", "main.ts"]),
+            Err(String::from("unknown putchar console `mmio`"))
+        );
+        assert_eq!(
+            parse(&["--target=arm-none-eabi", "--gc", "main.ts"]),
+            Err(String::from("`--gc` is not supported on arm-none-eabi"))
+        );
+    }
+
+    #[test]
+    fn demangle() {
+        assert_eq!(
+            parse(&["--demangle", "main.s"]),
+            Ok(Command::Demangle(Some(String::from("main.s"))))
+        );
+        assert_eq!(parse(&["--demangle"]), Ok(Command::Demangle(None)));
+    }
+
+    #[test]
+    fn explain() {
+        assert_eq!(
+            parse(&["--explain", "E0002"]),
+            Ok(Command::Explain(String::from("E0002")))
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        match parse(&["--deny-warnings", "--allow", "W0002", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(
+                options.warnings,
+                WarningOptions {
+                    deny_warnings: true,
+                    levels: vec![(String::from("W0002"), WarningLevel::Allow)],
+                }
+            ),
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse(&["--strict", "--warn=W0003", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(
+                options.warnings.levels,
+                [
+                    (String::from("W0003"), WarningLevel::Deny),
+                    (String::from("W0003"), WarningLevel::Warn)
+                ]
+            ),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--implicit-type=int", "main.ts"]),
+            Err(String::from("unknown type `int`"))
+        );
+        match parse(&["--error-format=json", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(options.error_format, ErrorFormat::Json),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--deny=W9999", "main.ts"]),
+            Err(String::from("unknown warning `W9999`"))
+        );
+    }
+
+    #[test]
+    fn time_passes() {
+        for flag in ["--time-passes", "-Ztime-passes"] {
+            match parse(&[flag, "main.ts"]) {
+                Ok(Command::Compile(options)) => assert!(options.time_passes),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn watch() {
+        match parse(&["--watch", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert!(options.watch),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--watch", "-"]),
+            Err(String::from("`--watch` needs an input file"))
+        );
+    }
+
+    #[test]
+    fn incremental() {
+        match parse(&["--incremental=target/rtsc", "main.ts"]) {
+            Ok(Command::Compile(options)) => {
+                assert_eq!(options.incremental, Some(String::from("target/rtsc")))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn llvm_ir() {
+        match parse(&["--emit=llvm-ir", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert_eq!(options.emit, Emit::LlvmIr),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn build() {
+        match parse(&[
+            "--build",
+            "--linker=clang --target=armv7-linux-gnueabihf",
+            "main.ts",
+        ]) {
+            Ok(Command::Compile(options)) => {
+                assert!(options.build);
+                assert_eq!(
+                    options.linker.as_deref(),
+                    Some("clang --target=armv7-linux-gnueabihf")
+                );
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--build", "--emit=tokens", "main.ts"]),
+            Err(String::from("`--build` can only be used with `--emit=asm`"))
+        );
+    }
+
+    #[test]
+    fn source_map() {
+        match parse(&["main.ts", "--source-map", "main.map"]) {
+            Ok(Command::Compile(options)) => {
+                assert_eq!(options.source_map.as_deref(), Some("main.map"));
+                assert!(options.codegen.source_map);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        for args in [
+            ["--source-map=main.map", "--emit=obj", "main.ts"],
+            ["--source-map=main.map", "--build", "main.ts"],
+        ] {
+            assert_eq!(
+                parse(&args),
+                Err(String::from(
+                    "`--source-map` can only be used with `--emit=asm` without `--build`"
+                ))
+            );
+        }
+        assert_eq!(
+            parse(&["--source-map=main.map", "--target=wasm32", "main.ts"]),
+            Err(String::from(
+                "`--source-map` is only supported when generating ARM32 assembly"
+            ))
+        );
+    }
+
+    #[test]
+    fn passes() {
+        match parse(&["-O0", "main.ts"]) {
+            Ok(Command::Compile(options)) => {
+                assert_eq!(options.codegen.passes, Passes::at_level(0))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--print-passes", "-O2", "--disable-pass", "const-fold"]),
+            Ok(Command::PrintPasses(
+                Passes::at_level(2).without(Pass::ConstFold)
+            ))
+        );
+        assert_eq!(
+            parse(&["--print-passes"]),
+            Ok(Command::PrintPasses(Passes::default()))
+        );
+        assert_eq!(
+            parse(&["-O3", "main.ts"]),
+            Err(String::from("unknown optimization level `3`"))
+        );
+        assert_eq!(
+            parse(&["--disable-pass=inline", "main.ts"]),
+            Err(String::from("unknown pass `inline`"))
+        );
+    }
+
+    #[test]
+    fn gc_targets() {
+        assert_eq!(
+            parse(&["--gc", "--target=wasm32", "main.ts"]),
+            Err(String::from(
+                "`--gc` is only supported when generating ARM32 assembly"
+            ))
+        );
+        assert!(parse(&["--gc", "--emit=ast", "main.ts"]).is_ok());
+        assert_eq!(
+            parse(&["--source-comments", "--emit=llvm-ir", "main.ts"]),
+            Err(String::from(
+                "`--source-comments` is only supported when generating ARM32 assembly"
+            ))
+        );
+        assert_eq!(
+            parse(&["--entry", "main", "--emit=llvm-ir", "main.ts"]),
+            Err(String::from(
+                "`--entry` is only supported when generating ARM32 assembly"
+            ))
+        );
+        assert_eq!(
+            parse(&["-g", "--target=wasm32", "main.ts"]),
+            Err(String::from(
+                "`--debug-info` is only supported when generating ARM32 assembly"
+            ))
+        );
+    }
+
+    #[test]
+    fn stdin_input() {
+        match parse(&["--check", "-"]) {
+            Ok(Command::Compile(options)) => assert_eq!(options.input, "-"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert_eq!(parse(&["main.ts", "--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["-V"]), Ok(Command::Version));
+    }
+
+    #[test]
+    fn lsp_command() {
+        assert_eq!(parse(&["lsp"]), Ok(Command::Lsp));
+        assert_eq!(
+            parse(&["lsp", "main.ts"]),
+            Err(String::from("unexpected argument `main.ts`"))
+        );
+    }
+
+    #[test]
+    fn doc_command() {
+        assert_eq!(
+            parse(&["doc", "lib.ts"]),
+            Ok(Command::Doc(DocOptions {
+                input: String::from("lib.ts"),
+                output: None,
+                format: DocFormat::Markdown,
+            }))
+        );
+        assert_eq!(
+            parse(&["doc", "--format=html", "lib.ts", "-o", "lib.html"]),
+            Ok(Command::Doc(DocOptions {
+                input: String::from("lib.ts"),
+                output: Some(String::from("lib.html")),
+                format: DocFormat::Html,
+            }))
+        );
+        assert_eq!(
+            parse(&["doc", "--format", "pdf", "lib.ts"]),
+            Err(String::from("unknown doc format `pdf`"))
+        );
+        assert_eq!(parse(&["doc"]), Err(String::from("no input file given")));
+    }
+
+    #[test]
+    fn coverage() {
+        match parse(&["--instrument-coverage", "--entry=start", "main.ts"]) {
+            Ok(Command::Compile(options)) => assert!(options.codegen.coverage),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse(&["--instrument-coverage", "main.ts"]),
+            Err(String::from(
+                "`--instrument-coverage` needs `--entry=main` or `--entry=start`"
+            ))
+        );
+        assert_eq!(
+            parse(&[
+                "--target=arm-none-eabi",
+                "--putchar=mmio=0x4000c000",
+                "--entry=start",
+                "--instrument-coverage",
+                "main.ts"
+            ]),
+            Err(String::from(
+                "`--instrument-coverage` needs semihosting on arm-none-eabi"
+            ))
+        );
+        assert_eq!(
+            parse(&["cov", "report", "main.ts"]),
+            Ok(Command::CoverageReport(CoverageOptions {
+                input: String::from("main.ts"),
+                counts: String::from("rtsc.cov"),
+                output: None,
+            }))
+        );
+        assert_eq!(
+            parse(&["cov", "report", "-o", "main.txt", "main.ts", "run.cov"]),
+            Ok(Command::CoverageReport(CoverageOptions {
+                input: String::from("main.ts"),
+                counts: String::from("run.cov"),
+                output: Some(String::from("main.txt")),
+            }))
+        );
+        assert_eq!(
+            parse(&["cov", "merge"]),
+            Err(String::from("unknown coverage command `merge`"))
+        );
+    }
+
+    #[test]
+    fn profile() {
+        assert_eq!(
+            parse(&["--instrument-profile", "--entry=none", "main.ts"]),
+            Err(String::from(
+                "`--instrument-profile` needs `--entry=main` or `--entry=start`"
+            ))
+        );
+        assert_eq!(
+            parse(&[
+                "--target=arm-none-eabi",
+                "--entry=start",
+                "--instrument-profile",
+                "main.ts"
+            ]),
+            Err(String::from(
+                "`--instrument-profile` needs `--thumb` on arm-none-eabi"
+            ))
+        );
+        assert_eq!(
+            parse(&["--instrument-profile", "--target=wasm32", "main.ts"]),
+            Err(String::from(
+                "`--instrument-profile` is only supported when generating ARM32 assembly"
+            ))
+        );
+        assert_eq!(
+            parse(&["prof", "report", "main.ts", "run.prof"]),
+            Ok(Command::ProfileReport(ProfileOptions {
+                input: String::from("main.ts"),
+                profile: String::from("run.prof"),
+                output: None,
+            }))
+        );
+        assert_eq!(
+            parse(&["prof"]),
+            Err(String::from("missing profile command, e.g. `report`"))
+        );
+    }
+
+    #[test]
+    fn metrics_command() {
+        assert_eq!(
+            parse(&["metrics", "--max-complexity=10", "main.ts"]),
+            Ok(Command::Metrics(MetricsOptions {
+                input: String::from("main.ts"),
+                output: None,
+                max_complexity: Some(10),
+            }))
+        );
+        assert_eq!(
+            parse(&["metrics", "--max-complexity", "ten", "main.ts"]),
+            Err(String::from("invalid complexity `ten`"))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(&[]), Err(String::from("no input file given")));
+        assert_eq!(
+            parse(&["--max-depth=0", "main.ts"]),
+            Err(String::from(
+                "invalid depth `0`, expected a number from 1 to 10000"
+            ))
+        );
+        assert_eq!(
+            parse(&["a.ts", "b.ts"]),
+            Err(String::from(
+                "expected a single input file, got `a.ts` and `b.ts`"
+            ))
+        );
+        assert_eq!(
+            parse(&["a.ts", "-o"]),
+            Err(String::from("missing value for `-o`"))
+        );
+        assert_eq!(
+            parse(&["a.ts", "--emit=bin"]),
+            Err(String::from("unknown emit kind `bin`"))
+        );
+        assert_eq!(
+            parse(&["a.ts", "--fast"]),
+            Err(String::from("unknown option `--fast`"))
+        );
+    }
+}
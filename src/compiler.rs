@@ -0,0 +1,1018 @@
+//! The whole compilation behind one entry point, used by the command line
+//! driver and by tools embedding the compiler alike:
+//!
+//! ```
+//! use rtsc::compiler::{Compiler, EmitKind, Output, Target};
+//!
+//! let compiled = Compiler::new()
+//!     .with_target(Target::Wasm32)
+//!     .with_emit(EmitKind::Assembly)
+//!     .compile_str("function main() { return 0; }")
+//!     .unwrap();
+//! assert!(matches!(compiled.output, Output::Text(_)));
+//! ```
+//!
+//! The output only depends on the source and the options, compiling a
+//! program twice gives the same bytes. The phases keep what the code is
+//! generated from in ordered collections rather than hash maps.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::ast::Ast;
+use crate::cache::Cache;
+use crate::diagnostic::{self, Diagnostic, Diagnostics, WarningOptions};
+use crate::lexer::Lexer;
+use crate::lines::{self, SourceLine};
+use crate::mangle;
+use crate::modules::{self, Module};
+use crate::parser::ParseOptions;
+use crate::phases::callgraph::CallGraph;
+use crate::phases::codegen::{self, Arm32Generator, CodegenOptions, Entry, Environment};
+use crate::phases::const_eval;
+use crate::phases::coverage;
+use crate::phases::elf;
+use crate::phases::instruction::{self, Instruction};
+use crate::phases::ir;
+use crate::phases::lint;
+use crate::phases::llvm::LlvmGenerator;
+use crate::phases::namespaces;
+use crate::phases::nested;
+use crate::phases::passes::Pass;
+use crate::phases::typecheck;
+use crate::phases::unroll;
+use crate::phases::wasm::WasmGenerator;
+use crate::sourcemap;
+use crate::symbols;
+use crate::timings::Timings;
+use crate::types::Type;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Arm32Linux,
+    Arm32NoneEabi,
+    Wasm32,
+}
+
+/// What the compilation produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitKind {
+    /// ARM32 assembly, or the WebAssembly text format for `Target::Wasm32`.
+    Assembly,
+    LlvmIr,
+    /// An ELF object file of the ARM32 code.
+    Object,
+    /// The calls between the functions in Graphviz DOT, with the stack
+    /// depth of each function on ARM32.
+    CallGraph,
+    /// The frame size and the worst case stack depth of each function of
+    /// the ARM32 code, see `CallGraph::stack_usage`.
+    StackUsage,
+    /// The functions, their frames and the globals of the ARM32 code as
+    /// JSON, see `symbols`.
+    Symbols,
+    /// The ARM32 instructions of the functions in basic blocks, before the
+    /// passes rewriting the instructions run, see `phases::ir`.
+    Ir,
+    /// Likewise after the passes.
+    IrOptimized,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    /// Nothing, the program was only checked.
+    None,
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub struct CompilationOutput {
+    pub output: Output,
+    /// The warnings about the program, none of them an error.
+    pub warnings: Vec<Diagnostic>,
+    /// How long the phases took, for `--time-passes`.
+    pub timings: Timings,
+    /// The source map of the ARM32 assembly with
+    /// `CodegenOptions::source_map`, see `sourcemap`.
+    pub source_map: Option<String>,
+}
+
+/// Where the basic blocks counted by `CodegenOptions::coverage` are in the
+/// source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageMap {
+    /// Paths and sources of the modules, which the lines refer to by index.
+    pub files: Vec<(String, String)>,
+    /// The line of every block, in the order of the counters.
+    pub lines: Vec<SourceLine>,
+}
+
+#[derive(Debug)]
+pub struct Compiler {
+    target: Target,
+    emit: EmitKind,
+    codegen: CodegenOptions,
+    prelude: LinkedHashMap<String, Type>,
+    warnings: WarningOptions,
+    cache: Option<Cache>,
+    count_tokens: bool,
+    parse_options: ParseOptions,
+}
+
+impl Default for Compiler {
+    fn default() -> Compiler {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    /// Compiles to ARM32 assembly for Linux, with the builtins in scope.
+    pub fn new() -> Compiler {
+        Compiler {
+            target: Target::Arm32Linux,
+            emit: EmitKind::Assembly,
+            codegen: CodegenOptions::default(),
+            prelude: typecheck::builtins(),
+            warnings: WarningOptions::default(),
+            cache: None,
+            count_tokens: false,
+            parse_options: ParseOptions::default(),
+        }
+    }
+
+    pub fn with_target(mut self, target: Target) -> Compiler {
+        self.target = target;
+        self
+    }
+
+    pub fn with_emit(mut self, emit: EmitKind) -> Compiler {
+        self.emit = emit;
+        self
+    }
+
+    pub fn with_codegen_options(mut self, codegen: CodegenOptions) -> Compiler {
+        self.codegen = codegen;
+        self
+    }
+
+    /// The functions every module can call without declaring them, by
+    /// default `typecheck::builtins()`.
+    pub fn with_prelude(mut self, prelude: LinkedHashMap<String, Type>) -> Compiler {
+        self.prelude = prelude;
+        self
+    }
+
+    pub fn with_warnings(mut self, warnings: WarningOptions) -> Compiler {
+        self.warnings = warnings;
+        self
+    }
+
+    /// Takes the modules which did not change from the cache.
+    pub fn with_cache(mut self, cache: Cache) -> Compiler {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The type of the parameters and return types without a type
+    /// annotation, `number` by default.
+    pub fn with_implicit_type(mut self, implicit_type: Type) -> Compiler {
+        self.parse_options.implicit_type = implicit_type;
+        self
+    }
+
+    /// How deep expressions and statements can nest,
+    /// `parser::MAX_DEPTH` by default.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Compiler {
+        self.parse_options.max_depth = max_depth;
+        self
+    }
+
+    /// Also times lexing and counts the tokens, which the parser does not
+    /// need.
+    pub fn with_token_count(mut self, count_tokens: bool) -> Compiler {
+        self.count_tokens = count_tokens;
+        self
+    }
+
+    /// Compiles the source as if it was in a file named `<input>` in the
+    /// working directory, which is where its imports are looked for.
+    pub fn compile_str(&self, source: &str) -> Result<CompilationOutput, Vec<Diagnostic>> {
+        self.compile_source(Path::new("<input>"), String::from(source))
+    }
+
+    pub fn compile_file(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<CompilationOutput, Vec<Diagnostic>> {
+        let path = path.as_ref();
+        self.compile_source(path, read(path)?)
+    }
+
+    /// Compiles the source of the module at the path, reading the modules
+    /// it imports from the file system.
+    pub fn compile_source(
+        &self,
+        path: &Path,
+        source: String,
+    ) -> Result<CompilationOutput, Vec<Diagnostic>> {
+        self.run(path, source, true)
+    }
+
+    /// Checks the module at the path and gives the program linked from it
+    /// and its imports, with the typed operations lowered, as the generators
+    /// get it. This is what `phases::interpreter` runs.
+    pub fn link_file(&self, path: impl AsRef<Path>) -> Result<Ast, Vec<Diagnostic>> {
+        let path = path.as_ref();
+        let mut timings = Timings::default();
+        let (modules, warnings) = self.load(path, read(path)?, &mut timings)?;
+        self.link(modules, &mut timings)
+            .map_err(|error| warnings.into_iter().chain([error]).collect())
+    }
+
+    /// Finds the basic blocks of the program linked from the module at the
+    /// path, as instrumented by `CodegenOptions::coverage`.
+    pub fn coverage_map(&self, path: impl AsRef<Path>) -> Result<CoverageMap, Vec<Diagnostic>> {
+        let path = path.as_ref();
+        let mut timings = Timings::default();
+        let (modules, warnings) = self.load(path, read(path)?, &mut timings)?;
+        let files = modules
+            .iter()
+            .map(|module| (module.path.display().to_string(), module.source.clone()))
+            .collect();
+        let (program, source_lines) = self
+            .link_with_lines(modules, true, &mut timings)
+            .map_err(|error| warnings.into_iter().chain([error]).collect::<Vec<_>>())?;
+        let statements: BTreeMap<*const Ast, usize> = lines::statements(&program)
+            .into_iter()
+            .enumerate()
+            .map(|(i, statement)| (statement as *const Ast, i))
+            .collect();
+        let lines = coverage::blocks(&program)
+            .into_iter()
+            .map(|block| source_lines[statements[&(block as *const Ast)]].clone())
+            .collect();
+        Ok(CoverageMap { files, lines })
+    }
+
+    /// Only parses, typechecks and lints the source, with `Output::None`.
+    pub fn check_source(
+        &self,
+        path: &Path,
+        source: String,
+    ) -> Result<CompilationOutput, Vec<Diagnostic>> {
+        self.run(path, source, false)
+    }
+
+    fn run(
+        &self,
+        path: &Path,
+        source: String,
+        generate: bool,
+    ) -> Result<CompilationOutput, Vec<Diagnostic>> {
+        let mut timings = Timings::default();
+        let (modules, warnings) = self.load(path, source, &mut timings)?;
+        let mut source_map = None;
+        let output = if generate {
+            self.generate(modules, &mut timings, &mut source_map)
+                .map_err(|error| warnings.iter().cloned().chain([error]).collect::<Vec<_>>())?
+        } else {
+            Output::None
+        };
+        Ok(CompilationOutput {
+            output,
+            warnings,
+            timings,
+            source_map,
+        })
+    }
+
+    fn load(
+        &self,
+        path: &Path,
+        source: String,
+        timings: &mut Timings,
+    ) -> Result<(Vec<Module>, Vec<Diagnostic>), Vec<Diagnostic>> {
+        let modules = timings
+            .time("parse", || {
+                modules::load_cached_modules(
+                    path,
+                    source,
+                    |path| fs::read_to_string(path),
+                    &self.parse_options,
+                    self.cache.as_ref(),
+                )
+            })
+            .map_err(|error| vec![error])?;
+        timings.size(
+            modules.iter().map(|module| module.ast.size()).sum(),
+            "nodes",
+        );
+        if self.count_tokens {
+            // the parser works on the characters, the tokens are only counted
+            let tokens = timings.time("lex", || {
+                modules
+                    .iter()
+                    .filter_map(|module| Lexer::new().tokenize(&module.source).ok())
+                    .map(|tokens| tokens.len())
+                    .sum()
+            });
+            timings.size(tokens, "tokens");
+        }
+        timings
+            .time("typecheck", || {
+                modules::check_cached_modules(&modules, &self.prelude, self.cache.as_ref())
+            })
+            .map_err(|error| vec![error])?;
+        let warnings = timings.time("lint", || self.lint(&modules));
+        if warnings
+            .iter()
+            .any(|warning| warning.level == diagnostic::Level::Error)
+        {
+            return Err(warnings);
+        }
+        Ok((modules, warnings))
+    }
+
+    // The warnings about the modules, with the levels they were given.
+    fn lint(&self, modules: &[Module]) -> Vec<Diagnostic> {
+        let mut diagnostics = Diagnostics::new(self.warnings.clone());
+        let mut warnings = Vec::new();
+        for module in modules {
+            for warning in lint::lint(&module.source, &module.ast, &module.spans) {
+                diagnostics.report(warning);
+            }
+            let path = module.path.display().to_string();
+            warnings.extend(
+                diagnostics
+                    .take()
+                    .into_iter()
+                    .map(|warning| warning.in_file(path.clone())),
+            );
+        }
+        warnings
+    }
+
+    // Leaves the source map of the assembly in `source_map` when asked for.
+    fn generate(
+        &self,
+        modules: Vec<Module>,
+        timings: &mut Timings,
+        source_map: &mut Option<String>,
+    ) -> Result<Output, Diagnostic> {
+        let arm32 = self.target != Target::Wasm32;
+        match self.emit {
+            EmitKind::Assembly if arm32 => {
+                let files: Vec<String> = modules
+                    .iter()
+                    .map(|module| module.path.display().to_string())
+                    .collect();
+                let with_lines = self.codegen.source_comments
+                    || self.codegen.debug_info
+                    || self.codegen.source_map;
+                let (program, source_lines) = self.link_with_lines(modules, with_lines, timings)?;
+                check_arm32_arguments(&program)?;
+                self.check_entry(&program)?;
+                let (code, sources) =
+                    self.generate_code(program, files.clone(), source_lines.clone(), timings);
+                if self.codegen.source_map {
+                    *source_map = Some(sourcemap::to_json(&files, &source_lines, &code, &sources));
+                }
+                Ok(Output::Text(instruction::render(&code)))
+            }
+            EmitKind::Assembly => {
+                let program = self.link(modules, timings)?;
+                check_arm32_builtins(&program)?;
+                self.check_entry(&program)?;
+                Ok(Output::Text(timings.time("codegen", || {
+                    WasmGenerator::new(program)
+                        .with_options(self.codegen)
+                        .generate()
+                })))
+            }
+            EmitKind::LlvmIr => {
+                let program = self.link(modules, timings)?;
+                check_arm32_builtins(&program)?;
+                Ok(Output::Text(timings.time("codegen", || {
+                    LlvmGenerator::new(program)
+                        .with_options(self.codegen)
+                        .generate()
+                })))
+            }
+            EmitKind::Object => {
+                let program = self.link(modules, timings)?;
+                check_arm32_arguments(&program)?;
+                self.check_entry(&program)?;
+                let (code, _) = self.generate_code(program, Vec::new(), Vec::new(), timings);
+                let object = timings
+                    .time("encode", || elf::object(&code))
+                    .map_err(|error| Diagnostic::error(diagnostic::UNENCODABLE, error, None))?;
+                timings.size(object.len(), "bytes");
+                Ok(Output::Binary(object))
+            }
+            EmitKind::CallGraph => {
+                let program = self.link(modules, timings)?;
+                let graph = CallGraph::new(&program);
+                // the frame sizes are only known for the ARM32 code
+                let frame_sizes = if arm32 {
+                    check_arm32_arguments(&program)?;
+                    self.frame_sizes(program)
+                } else {
+                    BTreeMap::new()
+                };
+                Ok(Output::Text(graph.to_dot(&frame_sizes)))
+            }
+            EmitKind::StackUsage => {
+                let program = self.link(modules, timings)?;
+                check_arm32_arguments(&program)?;
+                let graph = CallGraph::new(&program);
+                Ok(Output::Text(graph.stack_usage(&self.frame_sizes(program))))
+            }
+            EmitKind::Ir | EmitKind::IrOptimized => {
+                let program = self.link(modules, timings)?;
+                check_arm32_arguments(&program)?;
+                let mut codegen = self.codegen;
+                if self.emit == EmitKind::Ir {
+                    for pass in Pass::ALL {
+                        codegen.passes = codegen.passes.without(pass);
+                    }
+                }
+                let code = Arm32Generator::new(program)
+                    .with_options(codegen)
+                    .generate(&mut Environment::default());
+                Ok(Output::Text(ir::to_text(&code)))
+            }
+            EmitKind::Symbols => {
+                let program = self.link(modules, timings)?;
+                check_arm32_arguments(&program)?;
+                let generator = Arm32Generator::new(program).with_options(self.codegen);
+                let code = generator.generate(&mut Environment::default());
+                Ok(Output::Text(symbols::to_json(
+                    generator.program(),
+                    &generator.frame_layouts(),
+                    &codegen::frame_sizes(&code),
+                )))
+            }
+        }
+    }
+
+    // The frame sizes of the functions of the ARM32 code, by their names in
+    // the source.
+    fn frame_sizes(&self, program: Ast) -> BTreeMap<String, u32> {
+        let mut sizes = codegen::frame_sizes(
+            &Arm32Generator::new(program)
+                .with_options(self.codegen)
+                .generate(&mut Environment::default()),
+        );
+        if let Some(size) = sizes.remove(codegen::MAIN_FUNCTION) {
+            sizes.insert(String::from("main"), size);
+        }
+        sizes
+    }
+
+    // Joins the modules into one program whose constants are folded, whose
+    // string comparisons and float arithmetic are calls, which needs the
+    // types of the whole program, whose nested functions are top level ones
+    // and whose small loops are unrolled with `Pass::UnrollLoops`.
+    fn link(&self, modules: Vec<Module>, timings: &mut Timings) -> Result<Ast, Diagnostic> {
+        self.link_with_lines(modules, false, timings)
+            .map(|(program, _)| program)
+    }
+
+    // Also gives the lines of the statements of the linked program when
+    // asked for, which flattening the namespaces, moving the nested
+    // functions and unrolling the loops reorders.
+    fn link_with_lines(
+        &self,
+        modules: Vec<Module>,
+        with_lines: bool,
+        timings: &mut Timings,
+    ) -> Result<(Ast, Vec<SourceLine>), Diagnostic> {
+        timings.time("link", || {
+            let mut modules = modules;
+            let paths: Vec<&Path> = modules.iter().map(|module| module.path.as_path()).collect();
+            let names = mangle::module_names(&paths);
+            let mut source_lines = Vec::new();
+            for (i, (module, name)) in modules.iter_mut().zip(names).enumerate() {
+                let lines = if with_lines {
+                    lines::statement_lines(i, &module.source, &module.spans)
+                } else {
+                    Vec::new()
+                };
+                nested::mangle(&mut module.ast, &name);
+                let origins = namespaces::flatten(&mut module.ast, &name);
+                if with_lines {
+                    source_lines.extend(origins.into_iter().map(|i| lines[i].clone()));
+                }
+            }
+            let mut program = modules::link_modules(modules)?;
+            const_eval::fold(&mut program, self.codegen.passes.contains(Pass::ConstFold));
+            typecheck::lower_typed_operations(&mut program, self.prelude.clone())
+                .map_err(|error| Diagnostic::error(error.code, error.message, None))?;
+            let mut origins = nested::hoist(&mut program);
+            if self.codegen.passes.contains(Pass::UnrollLoops) {
+                let unrolled = unroll::unroll(&mut program);
+                origins = unrolled.into_iter().map(|i| origins[i]).collect();
+            }
+            let source_lines = if with_lines {
+                origins
+                    .into_iter()
+                    .map(|i| source_lines[i].clone())
+                    .collect()
+            } else {
+                source_lines
+            };
+            Ok((program, source_lines))
+        })
+    }
+
+    // The wrappers call the program's `main` without arguments, the arguments
+    // of the process reach the builtins through them.
+    fn check_entry(&self, program: &Ast) -> Result<(), Diagnostic> {
+        let error = |message| Diagnostic::error(diagnostic::ENTRY, message, None);
+        if self.codegen.entry == Entry::None {
+            let graph = CallGraph::new(program);
+            for builtin in [codegen::ARG_COUNT, codegen::ARG_NUMBER] {
+                if graph.calls.values().any(|calls| calls.contains(builtin)) {
+                    return Err(error(format!(
+                        "`{}` needs `--entry=main` or `--entry=start`",
+                        builtin
+                    )));
+                }
+            }
+            return Ok(());
+        }
+        let main = program.declarations().find_map(|function| match function {
+            Ast::Function(name, function_type, _) if name == "main" => Some(function_type),
+            _ => None,
+        });
+        match main {
+            Some(Type::Function {
+                parameter_types, ..
+            }) if parameter_types.is_empty() => Ok(()),
+            Some(_) => Err(error(String::from(
+                "`--entry` needs the `main` function to take no parameters",
+            ))),
+            None => Err(error(String::from("`--entry` needs a `main` function"))),
+        }
+    }
+
+    fn generate_code(
+        &self,
+        ast: Ast,
+        files: Vec<String>,
+        source_lines: Vec<SourceLine>,
+        timings: &mut Timings,
+    ) -> (Vec<Instruction>, Vec<Option<usize>>) {
+        let mut env = Environment::default();
+        let generator = Arm32Generator::new(ast)
+            .with_options(self.codegen)
+            .with_source(files, source_lines);
+        let code = timings.time("codegen", || generator.generate(&mut env));
+        let executable = code.iter().filter(|item| item.is_executable()).count();
+        timings.size(executable, "instructions");
+        (code, generator.instruction_sources())
+    }
+}
+
+// The lines of the statements of the modules, the linked program keeps them
+// in order.
+fn read(path: &Path) -> Result<String, Vec<Diagnostic>> {
+    fs::read_to_string(path).map_err(|error| {
+        vec![Diagnostic::error(
+            diagnostic::MISSING_MODULE,
+            format!("cannot read `{}`: {}", path.display(), error),
+            None,
+        )]
+    })
+}
+
+// The array builtins come with the ARM32 runtime, which lays arrays out with
+// their capacity, as do the one making strings of numbers and the ones
+// reading the arguments the ARM32 entry wrappers save.
+fn check_arm32_builtins(program: &Ast) -> Result<(), Diagnostic> {
+    let graph = CallGraph::new(program);
+    let builtins = [
+        codegen::PUSH,
+        codegen::POP,
+        codegen::CONCAT,
+        codegen::SLICE,
+        codegen::NUMBER_TO_STRING,
+        codegen::ARG_COUNT,
+        codegen::ARG_NUMBER,
+    ];
+    for builtin in builtins {
+        if graph.calls.values().any(|calls| calls.contains(builtin)) {
+            return Err(Diagnostic::error(
+                diagnostic::UNSUPPORTED_BUILTIN,
+                format!("`{}` is only available on ARM32", builtin),
+                None,
+            ));
+        }
+    }
+    Ok(())
+}
+
+// The ARM32 code passes the arguments in `r0`-`r3` and has no convention
+// for the ones beyond.
+fn check_arm32_arguments(program: &Ast) -> Result<(), Diagnostic> {
+    let error = |message| Diagnostic::error(diagnostic::TOO_MANY_ARGUMENTS, message, None);
+    let readable = |name: &String| mangle::demangle(name).unwrap_or_else(|| name.clone());
+    let mut nodes = vec![program];
+    while let Some(node) = nodes.pop() {
+        match node {
+            Ast::Function(
+                name,
+                Type::Function {
+                    parameter_types, ..
+                },
+                _,
+            ) if parameter_types.len() > 4 => {
+                return Err(error(format!(
+                    "`{}` takes {} parameters, but ARM32 functions take at most four",
+                    readable(name),
+                    parameter_types.len()
+                )))
+            }
+            Ast::Call(name, arguments) if arguments.len() > 4 => {
+                return Err(error(format!(
+                    "the call of `{}` passes {} arguments, but ARM32 calls pass at most four",
+                    readable(name),
+                    arguments.len()
+                )))
+            }
+            _ => nodes.extend(node.children().into_iter().rev()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::{Level, WarningLevel};
+    use crate::phases::passes::Passes;
+
+    fn text(compiled: CompilationOutput) -> String {
+        match compiled.output {
+            Output::Text(text) => text,
+            output => panic!("{:?} is not text", output),
+        }
+    }
+
+    #[test]
+    fn outputs() {
+        let source = "function main() { putchar(65); return 0; }";
+        let assembly = text(Compiler::new().compile_str(source).unwrap());
+        assert!(assembly.contains("main:"));
+        let wasm = text(
+            Compiler::new()
+                .with_target(Target::Wasm32)
+                .compile_str(source)
+                .unwrap(),
+        );
+        assert!(wasm.starts_with("(module"));
+        let ir = text(
+            Compiler::new()
+                .with_emit(EmitKind::LlvmIr)
+                .compile_str(source)
+                .unwrap(),
+        );
+        assert!(ir.contains("define i32 @main()"));
+        match Compiler::new()
+            .with_emit(EmitKind::Object)
+            .compile_str(source)
+            .unwrap()
+            .output
+        {
+            Output::Binary(object) => assert!(object.starts_with(b"\x7fELF")),
+            output => panic!("{:?} is not an object", output),
+        }
+        let checked = Compiler::new()
+            .check_source(Path::new("main.ts"), String::from(source))
+            .unwrap();
+        assert_eq!(checked.output, Output::None);
+    }
+
+    #[test]
+    fn stack_usage() {
+        let source = "function main() { putchar(65); return 0; }";
+        let usage = text(
+            Compiler::new()
+                .with_emit(EmitKind::StackUsage)
+                .with_codegen_options(CodegenOptions {
+                    entry: Entry::Main,
+                    ..CodegenOptions::default()
+                })
+                .compile_str(source)
+                .unwrap(),
+        );
+        // the wrapper renames `main`, the report keeps the source name
+        assert!(usage.ends_with("main         24         24  main -> putchar\n"));
+    }
+
+    #[test]
+    fn source_map() {
+        let source = "function main() {\n    putchar(65);\n    return 0;\n}";
+        let compiled = Compiler::new()
+            .with_codegen_options(CodegenOptions {
+                source_map: true,
+                ..CodegenOptions::default()
+            })
+            .compile_str(source)
+            .unwrap();
+        let map = compiled.source_map.unwrap();
+        assert!(map.contains("\"line\": 2, \"column\": 5}"));
+        assert!(map.contains("\"line\": 3, \"column\": 5}"));
+        assert_eq!(
+            Compiler::new().compile_str(source).unwrap().source_map,
+            None
+        );
+    }
+
+    #[test]
+    fn nested_functions() {
+        let source = "function main(): number {
+    function twice(x: number): number { return 2 * x; }
+    return twice(2);
+}";
+        let assembly = text(Compiler::new().compile_str(source).unwrap());
+        assert!(assembly.contains("\n.local _T.7_input_4main5twice\n_T.7_input_4main5twice:\n"));
+        assert!(assembly.contains("bl _T.7_input_4main5twice\n"));
+        let ir = text(
+            Compiler::new()
+                .with_emit(EmitKind::LlvmIr)
+                .compile_str(source)
+                .unwrap(),
+        );
+        assert!(ir.contains("define internal i32 @_T.7_input_4main5twice(i32 %x)"));
+        // the statements of the moved function keep their lines
+        let compiled = Compiler::new()
+            .with_codegen_options(CodegenOptions {
+                source_map: true,
+                ..CodegenOptions::default()
+            })
+            .compile_str(source)
+            .unwrap();
+        assert!(compiled
+            .source_map
+            .unwrap()
+            .contains("\"line\": 2, \"column\": 41}"));
+    }
+
+    #[test]
+    fn namespaces() {
+        let source = "namespace math {
+    function square(x: number): number { return x * x; }
+    export function cube(x: number): number { return x * square(x); }
+}
+function main(): number { return math.cube(2); }";
+        let assembly = text(Compiler::new().compile_str(source).unwrap());
+        assert!(assembly.contains("\n.local _T.7_input_4math4cube\n_T.7_input_4math4cube:\n"));
+        assert!(assembly.contains("bl _T.7_input_4math6square\n"));
+        assert!(assembly.contains("bl _T.7_input_4math4cube\n"));
+        let source = "namespace math { function square(x: number): number { return x * x; } }
+function main(): number { return math.square(2); }";
+        assert_eq!(
+            Compiler::new().compile_str(source).unwrap_err()[0].code,
+            diagnostic::UNDEFINED_FUNCTION
+        );
+        let source = "namespace math { export function one(): number { return 1; } }
+namespace math { export function one(): number { return 2; } }
+function main(): number { return math.one(); }";
+        let errors = Compiler::new().compile_str(source).unwrap_err();
+        assert_eq!(errors[0].code, diagnostic::DUPLICATE_DEFINITION);
+        assert_eq!(
+            errors[0].message,
+            "Function `_input_::math::one` is defined twice in `<input>`"
+        );
+    }
+
+    #[test]
+    fn unrolled_loops() {
+        let source = "function main() {
+    var i = 0;
+    while (i != 3) {
+        putchar(65 + i);
+        i = i + 1;
+    }
+    return i;
+}";
+        let compiled = Compiler::new()
+            .with_codegen_options(CodegenOptions {
+                passes: Passes::at_level(2),
+                source_map: true,
+                ..CodegenOptions::default()
+            })
+            .compile_str(source)
+            .unwrap();
+        // every copy of the body keeps its lines
+        let map = compiled.source_map.clone().unwrap();
+        assert_eq!(map.matches("\"line\": 4, \"column\": 9}").count(), 3);
+        let calls = |compiled: CompilationOutput| text(compiled).matches("bl putchar\n").count();
+        assert_eq!(calls(compiled), 3);
+        assert_eq!(calls(Compiler::new().compile_str(source).unwrap()), 1);
+    }
+
+    #[test]
+    fn prelude() {
+        let source = "function main() { return answer(); }";
+        assert_eq!(
+            Compiler::new().compile_str(source).unwrap_err()[0].code,
+            diagnostic::UNDEFINED_FUNCTION
+        );
+        let mut prelude = LinkedHashMap::new();
+        prelude.insert(
+            String::from("answer"),
+            Type::Function {
+                parameter_types: LinkedHashMap::new(),
+                return_type: Box::new(Type::Number),
+            },
+        );
+        assert!(Compiler::new()
+            .with_prelude(prelude)
+            .compile_str(source)
+            .is_ok());
+    }
+
+    #[test]
+    fn implicit_type() {
+        let source = "function id(s): string { return s; }";
+        let errors = Compiler::new().compile_str(source).unwrap_err();
+        assert_eq!(errors[0].code, diagnostic::TYPE_MISMATCH);
+        let compiled = Compiler::new()
+            .with_implicit_type(Type::String)
+            .compile_str(source)
+            .unwrap();
+        assert_eq!(
+            compiled.warnings[0].message,
+            "parameter `s` has no type annotation, `string` is assumed"
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        let source = "function main(): number { return 0; putchar(1); }";
+        let compiled = Compiler::new().compile_str(source).unwrap();
+        assert_eq!(compiled.warnings.len(), 1);
+        assert_eq!(compiled.warnings[0].code, diagnostic::UNREACHABLE_CODE);
+        assert_eq!(compiled.warnings[0].file.as_deref(), Some("<input>"));
+
+        let denied = Compiler::new()
+            .with_warnings(WarningOptions {
+                deny_warnings: true,
+                levels: vec![(
+                    String::from(diagnostic::UNUSED_ASSIGNMENT),
+                    WarningLevel::Allow,
+                )],
+            })
+            .compile_str(source)
+            .unwrap_err();
+        assert_eq!(denied.len(), 1);
+        assert_eq!(denied[0].level, Level::Error);
+
+        let errors = Compiler::new()
+            .compile_str("function main() { return true + 1; }")
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, diagnostic::TYPE_MISMATCH);
+    }
+
+    #[test]
+    fn arm32_builtins() {
+        let source = "function main(): number { var s = numberToString(42); return s[0]; }";
+        assert!(Compiler::new().compile_str(source).is_ok());
+        for compiler in [
+            Compiler::new().with_target(Target::Wasm32),
+            Compiler::new().with_emit(EmitKind::LlvmIr),
+        ] {
+            let errors = compiler.compile_str(source).unwrap_err();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code, diagnostic::UNSUPPORTED_BUILTIN);
+            assert_eq!(
+                errors[0].message,
+                "`numberToString` is only available on ARM32"
+            );
+        }
+        let source = "function main(): number { return argCount(); }";
+        let entry = CodegenOptions {
+            entry: Entry::Main,
+            ..CodegenOptions::default()
+        };
+        assert!(Compiler::new()
+            .with_codegen_options(entry)
+            .compile_str(source)
+            .is_ok());
+        for compiler in [
+            Compiler::new().with_target(Target::Wasm32),
+            Compiler::new().with_emit(EmitKind::LlvmIr),
+        ] {
+            let errors = compiler
+                .with_codegen_options(entry)
+                .compile_str(source)
+                .unwrap_err();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code, diagnostic::UNSUPPORTED_BUILTIN);
+            assert_eq!(errors[0].message, "`argCount` is only available on ARM32");
+        }
+    }
+
+    #[test]
+    fn globals_named_like_runtime_symbols() {
+        let source = "var malloc = 1; var __rtsc_gc_top = 0; \
+                      function main(): number { var a = [1]; return malloc + __rtsc_gc_top + a[0]; }";
+        let gc = CodegenOptions {
+            gc: true,
+            ..CodegenOptions::default()
+        };
+        let assembly = text(
+            Compiler::new()
+                .with_codegen_options(gc)
+                .compile_str(source)
+                .unwrap(),
+        );
+        assert!(!assembly.contains("\nmalloc:"));
+        assert!(assembly.contains("\n_T.6malloc:"));
+        assert_eq!(assembly.matches("\n__rtsc_gc_top:").count(), 1);
+        assert!(assembly.contains("\n_T.13__rtsc_gc_top:"));
+        let module = text(
+            Compiler::new()
+                .with_emit(EmitKind::LlvmIr)
+                .compile_str(source)
+                .unwrap(),
+        );
+        assert!(module.contains("@_T.6malloc = internal global i32 1\n"));
+    }
+
+    #[test]
+    fn library_function_names() {
+        let source = "function printf(size: number): number { return size; }
+                      function main(): number { return printf(1); }";
+        let assembly = text(Compiler::new().compile_str(source).unwrap());
+        assert!(assembly.contains("\n.local _T.7_input_6printf\n_T.7_input_6printf:"));
+        assert!(assembly.contains("bl _T.7_input_6printf\n"));
+        for source in [
+            "function malloc(size: number): number { return size; }",
+            "export function putchar(c: number): number { return c; }",
+            "export function __rtsc_push(): number { return 0; }",
+            "function main(): number { function free(): number { return 0; } return free(); }",
+        ] {
+            let errors = Compiler::new().compile_str(source).unwrap_err();
+            assert_eq!(errors[0].code, diagnostic::RESERVED_NAME, "{}", source);
+        }
+    }
+
+    #[test]
+    fn globals_named_like_mangled_functions() {
+        let source = "var count = 1;
+                      export function _T5count(): number { return count; }
+                      function main(): number { return _T5count(); }";
+        let assembly = text(Compiler::new().compile_str(source).unwrap());
+        assert_eq!(assembly.matches("\n_T5count:").count(), 1);
+        assert_eq!(assembly.matches("\n_T.5count:").count(), 1);
+    }
+
+    #[test]
+    fn argument_counts() {
+        let errors = Compiler::new()
+            .compile_str("function main(): number { return putchar(1, 2); }")
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, diagnostic::ARGUMENT_COUNT);
+        let five = "function f(a: number, b: number, c: number, d: number, e: number): number { \
+                    return a + e; } \
+                    function main(): number { return f(1, 2, 3, 4, 5); }";
+        for emit in [EmitKind::Assembly, EmitKind::Object, EmitKind::Symbols] {
+            let errors = Compiler::new()
+                .with_emit(emit)
+                .compile_str(five)
+                .unwrap_err();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].code, diagnostic::TOO_MANY_ARGUMENTS);
+            assert_eq!(
+                errors[0].message,
+                "`_input_::f` takes 5 parameters, but ARM32 functions take at most four"
+            );
+        }
+        let errors = Compiler::new()
+            .compile_str(
+                "extern function f(a: number, b: number, c: number, d: number, e: number): number; \
+                 function main(): number { return f(1, 2, 3, 4, 5); }",
+            )
+            .unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "the call of `f` passes 5 arguments, but ARM32 calls pass at most four"
+        );
+        assert!(Compiler::new()
+            .with_target(Target::Wasm32)
+            .compile_str(five)
+            .is_ok());
+        assert!(Compiler::new()
+            .with_emit(EmitKind::LlvmIr)
+            .compile_str(five)
+            .is_ok());
+    }
+}
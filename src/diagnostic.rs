@@ -0,0 +1,400 @@
+//! Problems found in the program, shared by all the phases.
+//!
+//! Every diagnostic has a stable code, explained at length by `rtsc --explain
+//! CODE`. Warnings can be allowed or turned into errors from the command
+//! line. Errors stop the compilation, warnings are only reported.
+use std::fmt;
+
+use crate::span::{Location, Span};
+
+/// Source which is not part of the grammar.
+pub const SYNTAX_ERROR: &str = "E0001";
+/// A value of another type than the one expected.
+pub const TYPE_MISMATCH: &str = "E0002";
+pub const UNDEFINED_VARIABLE: &str = "E0003";
+pub const UNDEFINED_FUNCTION: &str = "E0004";
+/// A function or builtin called with the wrong number of arguments.
+pub const ARGUMENT_COUNT: &str = "E0005";
+/// Something else than an array where one is needed.
+pub const NOT_AN_ARRAY: &str = "E0006";
+/// An empty array literal, whose element type cannot be told.
+pub const EMPTY_ARRAY: &str = "E0007";
+/// A global variable initialized with something else than a constant.
+pub const GLOBAL_INITIALIZER: &str = "E0008";
+pub const RETURN_OUTSIDE_FUNCTION: &str = "E0009";
+/// An import, export, extern or namespace declaration inside of a function,
+/// or anything else than a function inside of a namespace.
+pub const NOT_AT_TOP_LEVEL: &str = "E0010";
+/// `push` called on something else than a variable holding an array.
+pub const PUSH_TARGET: &str = "E0011";
+pub const IMPORT_CYCLE: &str = "E0012";
+/// An imported module which cannot be read.
+pub const MISSING_MODULE: &str = "E0013";
+/// An import of a function the module does not export.
+pub const MISSING_EXPORT: &str = "E0014";
+/// A function or global defined by two modules.
+pub const DUPLICATE_DEFINITION: &str = "E0015";
+/// A program not fitting the `--entry` wrapper, or using what needs one.
+pub const ENTRY: &str = "E0016";
+/// A builtin which the target does not provide.
+pub const UNSUPPORTED_BUILTIN: &str = "E0017";
+/// ARM32 code which the object file writer cannot encode.
+pub const UNENCODABLE: &str = "E0018";
+/// A `static_assert` whose condition is false.
+pub const STATIC_ASSERTION: &str = "E0019";
+/// A `static_assert` condition or `const` value which cannot be evaluated
+/// at compile time.
+pub const NOT_CONSTANT: &str = "E0020";
+pub const ASSIGNMENT_TO_CONSTANT: &str = "E0021";
+/// A function taking, or a call passing, more arguments than the ARM32
+/// code passes in registers.
+pub const TOO_MANY_ARGUMENTS: &str = "E0022";
+/// A function named like a symbol of the C library or of the runtime which
+/// the generated code calls.
+pub const RESERVED_NAME: &str = "E0023";
+
+/// A statement which no path through the function reaches.
+pub const UNREACHABLE_CODE: &str = "W0001";
+/// A value assigned to a local variable which is never read.
+pub const UNUSED_ASSIGNMENT: &str = "W0002";
+/// A parameter or return type without annotation, taken to be the implicit
+/// type, `number` by default. Denied by `--strict`.
+pub const MISSING_ANNOTATION: &str = "W0003";
+
+/// The codes of all the warnings with a short description of each.
+pub const WARNINGS: [(&str, &str); 3] = [
+    (UNREACHABLE_CODE, "unreachable code"),
+    (UNUSED_ASSIGNMENT, "assigned value is never read"),
+    (MISSING_ANNOTATION, "missing type annotation"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Warning => write!(f, "warning"),
+            Level::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub code: &'static str,
+    pub message: String,
+    /// The code the diagnostic is about, when it can be told.
+    pub span: Option<Span>,
+    /// The file the span points into, when it is not clear from the
+    /// context.
+    pub file: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: String, span: Option<Span>) -> Diagnostic {
+        Diagnostic {
+            level: Level::Error,
+            code,
+            message,
+            span,
+            file: None,
+        }
+    }
+
+    pub fn warning(code: &'static str, message: String, span: Option<Span>) -> Diagnostic {
+        Diagnostic {
+            level: Level::Warning,
+            code,
+            message,
+            span,
+            file: None,
+        }
+    }
+
+    pub fn in_file(mut self, file: String) -> Diagnostic {
+        self.file = Some(file);
+        self
+    }
+
+    /// The diagnostic followed by the line its span starts on, with carets
+    /// under the part of the line the span covers:
+    ///
+    /// ```text
+    /// warning[W0001]: unreachable statement
+    ///  --> main.ts:5:5
+    ///   |
+    /// 5 |     putchar(y);
+    ///   |     ^^^^^^^^^^^
+    /// ```
+    ///
+    /// `color` highlights it with ANSI escape codes.
+    pub fn render_snippet(&self, path: &str, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("\x1b[{}m{}\x1b[0m", style, text)
+            } else {
+                String::from(text)
+            }
+        };
+        let level_style = match self.level {
+            Level::Warning => "1;33",
+            Level::Error => "1;31",
+        };
+        // without a snippet the file goes in front of the message
+        let message = match self.span {
+            Some(_) => format!(": {}", self.message),
+            None => format!(": {}: {}", path, self.message),
+        };
+        let mut output = format!(
+            "{}{}",
+            paint(level_style, &format!("{}[{}]", self.level, self.code)),
+            paint("1", &message)
+        );
+        let span = match self.span {
+            Some(span) => span,
+            None => return output,
+        };
+        let location = Location::from_offset(source, span.start);
+        let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[span.start..]
+            .find('\n')
+            .map_or(source.len(), |i| span.start + i);
+        let line = &source[line_start..line_end];
+        let number = location.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let carets = source[span.start..span.end.clamp(span.start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        output.push_str(&format!(
+            "\n{}{} {}:{}\n{}\n{}{}\n{}{}",
+            gutter,
+            paint("1;34", "-->"),
+            path,
+            location,
+            paint("1;34", &format!("{} |", gutter)),
+            paint("1;34", &format!("{} | ", number)),
+            line,
+            paint("1;34", &format!("{} | ", gutter)),
+            // tabs are kept so that the carets line up with the source
+            paint(
+                level_style,
+                &format!(
+                    "{}{}",
+                    line[..span.start - line_start]
+                        .chars()
+                        .map(|c| if c == '\t' { '\t' } else { ' ' })
+                        .collect::<String>(),
+                    "^".repeat(carets)
+                )
+            ),
+        ));
+        output
+    }
+}
+
+impl Diagnostic {
+    /// The diagnostic as a JSON object on a single line, for tools reading
+    /// the output of the compiler, e.g. `{"file": "main.ts", "span":
+    /// {"start": 34, "end": 45, "line": 3, "column": 5}, "severity":
+    /// "warning", "code": "W0001", "message": "unreachable statement"}`.
+    /// The file and the span are `null` when not known, the line and the
+    /// column are only given along with the source.
+    pub fn to_json(&self, path: Option<&str>, source: Option<&str>) -> String {
+        let file = path.map_or(String::from("null"), json_string);
+        let span = match (self.span, source) {
+            (Some(span), Some(source)) => {
+                let location = Location::from_offset(source, span.start);
+                format!(
+                    "{{\"start\": {}, \"end\": {}, \"line\": {}, \"column\": {}}}",
+                    span.start, span.end, location.line, location.column
+                )
+            }
+            (Some(span), None) => {
+                format!("{{\"start\": {}, \"end\": {}}}", span.start, span.end)
+            }
+            (None, _) => String::from("null"),
+        };
+        format!(
+            "{{\"file\": {}, \"span\": {}, \"severity\": \"{}\", \"code\": \"{}\", \"message\": {}}}",
+            file,
+            span,
+            self.level,
+            self.code,
+            json_string(&self.message)
+        )
+    }
+}
+
+/// The text as a JSON string literal.
+pub fn json_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            c if (c as u32) < 0x20 => literal.push_str(&format!("\\u{:04x}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: ", self.level, self.code)?;
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// What a warning is turned into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// The command line choices about the warnings, by default all of them are
+/// reported without failing the compilation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarningOptions {
+    /// Makes every warning not given a level of its own an error.
+    pub deny_warnings: bool,
+    /// Levels of single warnings, a later entry for a code wins.
+    pub levels: Vec<(String, WarningLevel)>,
+}
+
+impl WarningOptions {
+    pub fn level(&self, code: &str) -> WarningLevel {
+        let default = if self.deny_warnings {
+            WarningLevel::Deny
+        } else {
+            WarningLevel::Warn
+        };
+        self.levels
+            .iter()
+            .rev()
+            .find(|(other, _)| other == code)
+            .map_or(default, |(_, level)| *level)
+    }
+}
+
+/// Gathers the diagnostics of the phases, applying the levels the warnings
+/// were given.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    options: WarningOptions,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new(options: WarningOptions) -> Diagnostics {
+        Diagnostics {
+            options,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn report(&mut self, mut diagnostic: Diagnostic) {
+        if diagnostic.level == Level::Warning {
+            match self.options.level(diagnostic.code) {
+                WarningLevel::Allow => return,
+                WarningLevel::Warn => {}
+                WarningLevel::Deny => diagnostic.level = Level::Error,
+            }
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn errors(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.level == Level::Error)
+            .count()
+    }
+
+    /// Hands out the diagnostics reported so far, leaving none behind.
+    pub fn take(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        let warning = |code| Diagnostic::warning(code, String::from("m"), None);
+        let mut diagnostics = Diagnostics::new(WarningOptions {
+            deny_warnings: true,
+            levels: vec![
+                (String::from(UNUSED_ASSIGNMENT), WarningLevel::Deny),
+                (String::from(UNUSED_ASSIGNMENT), WarningLevel::Allow),
+            ],
+        });
+        diagnostics.report(warning(UNREACHABLE_CODE));
+        diagnostics.report(warning(UNUSED_ASSIGNMENT));
+        assert_eq!(diagnostics.errors(), 1);
+        let reported = diagnostics.take();
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].to_string(), "error[W0001]: m");
+    }
+
+    #[test]
+    fn json() {
+        let warning = Diagnostic::warning(
+            UNREACHABLE_CODE,
+            String::from("`x` is \"unreachable\""),
+            Some(Span::new(4, 5)),
+        );
+        assert_eq!(
+            warning.to_json(Some("main.ts"), Some("f();\ng();")),
+            r#"{"file": "main.ts", "span": {"start": 4, "end": 5, "line": 1, "column": 5}, "severity": "warning", "code": "W0001", "message": "`x` is \"unreachable\""}"#
+        );
+        let error = Diagnostic::error(SYNTAX_ERROR, String::from("a\nb"), None);
+        assert_eq!(
+            error.to_json(None, None),
+            r#"{"file": null, "span": null, "severity": "error", "code": "E0001", "message": "a\nb"}"#
+        );
+    }
+
+    #[test]
+    fn snippets() {
+        let source = "function f(x) {\n    return x;\n    putchar(x);\n}";
+        let warning = Diagnostic::warning(
+            UNREACHABLE_CODE,
+            String::from("unreachable statement"),
+            Some(Span::new(34, 45)),
+        );
+        assert_eq!(
+            warning.render_snippet("f.ts", source, false),
+            "warning[W0001]: unreachable statement
+ --> f.ts:3:5
+  |
+3 |     putchar(x);
+  |     ^^^^^^^^^^^"
+        );
+        assert_eq!(
+            warning.render_snippet("f.ts", source, true).lines().next(),
+            Some("\x1b[1;33mwarning[W0001]\x1b[0m\x1b[1m: unreachable statement\x1b[0m")
+        );
+        let error = Diagnostic::error(SYNTAX_ERROR, String::from("m"), None);
+        assert_eq!(
+            error.render_snippet("f.ts", source, false),
+            "error[E0001]: f.ts: m"
+        );
+    }
+}
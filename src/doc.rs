@@ -0,0 +1,175 @@
+//! Documentation of a module, rendered from the signatures of the functions
+//! it declares at the top level and the `///` comments preceding them.
+use std::fmt::Write;
+
+use crate::ast::Ast;
+use crate::format;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    Markdown,
+    /// A standalone page.
+    Html,
+}
+
+/// A function of the module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub name: String,
+    /// The declaration without the body, see `format::signature`.
+    pub signature: String,
+    /// The lines of its doc comment, empty when it has none.
+    pub doc: String,
+}
+
+/// The functions, exported ones and external ones declared at the top level
+/// of the module, in source order. A doc comment documents the declaration
+/// right after it, it is dropped when anything else follows.
+pub fn items(program: &Ast) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut doc = None;
+    for statement in program.top_level_statements() {
+        let name = match statement {
+            Ast::Doc(text) => {
+                doc = Some(text.clone());
+                continue;
+            }
+            Ast::Function(name, _, _) | Ast::Extern(name, _) => name,
+            Ast::Export(function) => match function.as_ref() {
+                Ast::Function(name, _, _) => name,
+                _ => continue,
+            },
+            _ => {
+                doc = None;
+                continue;
+            }
+        };
+        items.push(Item {
+            name: name.clone(),
+            signature: format::signature(statement),
+            doc: doc.take().unwrap_or_default(),
+        });
+    }
+    items
+}
+
+/// Renders the documentation of the module under the title, usually the
+/// name of its file.
+pub fn render(program: &Ast, title: &str, format: DocFormat) -> String {
+    let items = items(program);
+    match format {
+        DocFormat::Markdown => render_markdown(&items, title),
+        DocFormat::Html => render_html(&items, title),
+    }
+}
+
+fn render_markdown(items: &[Item], title: &str) -> String {
+    let mut output = format!("# {}\n", title);
+    for item in items {
+        write!(
+            output,
+            "\n## `{}`\n\n```ts\n{}\n```\n",
+            item.name, item.signature
+        )
+        .unwrap();
+        if !item.doc.is_empty() {
+            write!(output, "\n{}\n", item.doc).unwrap();
+        }
+    }
+    output
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// The paragraphs of the doc comments are separated by empty lines, as in
+// Markdown.
+fn render_html(items: &[Item], title: &str) -> String {
+    let title = escape(title);
+    let mut output = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, title
+    );
+    for item in items {
+        write!(
+            output,
+            "<h2 id=\"{}\"><code>{}</code></h2>\n<pre><code>{}</code></pre>\n",
+            item.name,
+            item.name,
+            escape(&item.signature)
+        )
+        .unwrap();
+        for paragraph in item.doc.split("\n\n").filter(|text| !text.is_empty()) {
+            writeln!(output, "<p>{}</p>", escape(paragraph)).unwrap();
+        }
+    }
+    output.push_str("</body>\n</html>\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    const SOURCE: &str = "/// Adds two numbers.
+        ///
+        /// Wraps around on overflow.
+        export function add(x: number, y: number): number { return x + y; }
+        /// Not documenting anything.
+        var zero = 0;
+        function helper(xs: array<number>): number { return xs[0]; }
+        /// Writes `a < b` to the console.
+        extern function print(a: number, b: number): void;";
+
+    #[test]
+    fn documented_items() {
+        let items = items(&parser::parse_program(SOURCE).unwrap());
+        assert_eq!(
+            items,
+            vec![
+                Item {
+                    name: String::from("add"),
+                    signature: String::from("export function add(x: number, y: number): number"),
+                    doc: String::from("Adds two numbers.\n\nWraps around on overflow."),
+                },
+                Item {
+                    name: String::from("helper"),
+                    signature: String::from("function helper(xs: array<number>): number"),
+                    doc: String::new(),
+                },
+                Item {
+                    name: String::from("print"),
+                    signature: String::from("extern function print(a: number, b: number): void"),
+                    doc: String::from("Writes `a < b` to the console."),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown() {
+        let program = parser::parse_program(SOURCE).unwrap();
+        assert!(render(&program, "math", DocFormat::Markdown).starts_with(
+            "# math\n\n## `add`\n\n```ts\nexport function add(x: number, y: number): number\n```\n\n\
+             Adds two numbers.\n\nWraps around on overflow.\n\n## `helper`\n\n```ts\n"
+        ));
+    }
+
+    #[test]
+    fn html() {
+        let program = parser::parse_program(SOURCE).unwrap();
+        let html = render(&program, "math", DocFormat::Html);
+        assert!(html.contains(
+            "<h2 id=\"add\"><code>add</code></h2>\n\
+             <pre><code>export function add(x: number, y: number): number</code></pre>\n\
+             <p>Adds two numbers.</p>\n<p>Wraps around on overflow.</p>\n"
+        ));
+        assert!(html.contains("<p>Writes `a &lt; b` to the console.</p>\n"));
+        assert!(html.ends_with("</body>\n</html>\n"));
+    }
+}
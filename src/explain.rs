@@ -0,0 +1,375 @@
+//! The longer descriptions of the diagnostics printed by `rtsc --explain
+//! CODE`, each with an example of a program it is reported for.
+
+/// The description of the diagnostic with the code, `None` for unknown
+/// codes.
+pub fn explanation(code: &str) -> Option<&'static str> {
+    let text = match code {
+        "E0001" => {
+            "E0001: syntax error
+
+The source does not follow the grammar of the language, so it cannot be
+parsed. The error points at where the parser gave up, which is the start of
+the function or the top level statement containing the mistake, e.g. a
+missing semicolon:
+
+    function main() {
+        var x = 1
+        return x;
+    }
+
+Statements end with `;`, blocks are delimited with `{` and `}` and every
+`if` needs an `else`, which may be an empty block.
+
+Expressions and statements nested more than 100 levels deep, e.g. in
+parentheses or blocks, are reported as nesting too deep. Moving the inner
+part into a variable or a function of its own keeps the nesting shallower,
+`--max-depth=N` raises the limit."
+        }
+        "E0002" => {
+            "E0002: type mismatch
+
+A value has another type than the one expected where it is used, e.g. a
+function declared to return a number returning a boolean:
+
+    function isZero(x: number) {
+        return x == 0;
+    }
+
+Parameters and return values are numbers unless annotated otherwise, so the
+function above needs `: boolean` after its parameter list. The message names
+the expected type first and the actual one second."
+        }
+        "E0003" => {
+            "E0003: undefined variable
+
+A variable is used but no declaration of it is visible at that point:
+
+    function f(c: boolean) {
+        if (c) { var y = 1; } else {}
+        return y;
+    }
+
+Variables declared inside a block, like the body of an `if` or a `while`,
+are only visible until the end of the block. Declare them before it
+instead."
+        }
+        "E0004" => {
+            "E0004: undefined function
+
+A function is called but it is neither defined in the program, imported
+from another module, declared with `extern` nor one of the builtins:
+
+    function main() {
+        return square(2);
+    }
+
+Functions of the C library have to be declared before being called, e.g.
+`extern function abs(x: number): number;`."
+        }
+        "E0005" => {
+            "E0005: wrong number of arguments
+
+A function or one of the array builtins is called with more or fewer
+arguments than it takes:
+
+    function f(xs: array<number>) {
+        return concat(xs);
+    }
+
+`push(xs, x)` and `concat(a, b)` take two arguments, `pop(xs)` one and
+`slice(xs, start, end)` three. Functions take one argument per parameter."
+        }
+        "E0006" => {
+            "E0006: not an array
+
+Something which is not an array is indexed, measured with `length`,
+deleted or passed to an array builtin:
+
+    function f(x) {
+        return x[0];
+    }
+
+Parameters are numbers unless annotated otherwise, arrays are written as
+`array<number>`. Strings can be indexed and measured, but not deleted."
+        }
+        "E0007" => {
+            "E0007: empty array literal
+
+The type of the elements of an array is taken from its literal, which is
+not possible when there are none:
+
+    function f() {
+        var xs = [];
+    }
+
+Start with an array holding an element of the intended type, or use
+`slice` to make an empty copy of another array."
+        }
+        "E0008" => {
+            "E0008: global initialized with a non-constant
+
+Variables declared outside of functions are globals, whose initial value
+is placed in the data section when the program is compiled. It therefore
+has to be a number, a boolean, `null` or `undefined`:
+
+    var limit = 2 * 8;
+
+Compute the value by hand, or assign it at the beginning of `main`."
+        }
+        "E0009" => {
+            "E0009: return outside of a function
+
+`return` is only meaningful inside of a function body:
+
+    var x = 1;
+    return x;
+
+Move the code into a function, the program starts at `main`."
+        }
+        "E0010" => {
+            "E0010: declaration not at the top level
+
+Imports, exports and `extern` declarations apply to the whole module, so
+they cannot be placed inside of a function:
+
+    function f(x) {
+        extern function abs(x: number): number;
+        return abs(x);
+    }
+
+Move the declaration outside of the function, before its first use.
+Namespaces are declared at the top level too, and only hold functions."
+        }
+        "E0011" => {
+            "E0011: `push` needs a variable
+
+`push` stores the array it grew back into the variable holding it, which
+is not possible when the array is any other expression:
+
+    function f() {
+        push([1, 2], 3);
+    }
+
+Assign the array to a variable first and pass the variable."
+        }
+        "E0012" => {
+            "E0012: import cycle
+
+Modules are compiled after all the modules they import, so two modules
+cannot import from each other, directly or through other modules:
+
+    // a.ts
+    import { g } from \"./b\";
+    // b.ts
+    import { f } from \"./a\";
+
+Move the functions both modules need into a third one."
+        }
+        "E0013" => {
+            "E0013: module cannot be read
+
+A module named in an import does not exist or cannot be read:
+
+    import { add } from \"./lib\";
+
+Paths are relative to the importing file and the `.ts` extension is
+optional, so the import above reads `lib.ts` next to it."
+        }
+        "E0014" => {
+            "E0014: function not exported
+
+A module imports a function which the other module either does not define
+or does not mark with `export`:
+
+    // lib.ts
+    function add(x: number, y: number) { return x + y; }
+    // main.ts
+    import { add } from \"./lib\";
+
+Write `export function add(...)` to make the function importable."
+        }
+        "E0015" => {
+            "E0015: duplicate definition
+
+All the modules of a program are compiled into a single output, where
+the names of functions and globals must be unique:
+
+    // lib.ts
+    export function f() { return 1; }
+    // main.ts
+    import { f } from \"./lib\";
+    function f() { return 2; }
+
+Rename one of the definitions."
+        }
+        "E0016" => {
+            "E0016: program does not fit the entry
+
+With `--entry=main` or `--entry=start` the compiler wraps the program's
+`main`, which therefore has to exist and take no parameters:
+
+    function main(x) {
+        return x;
+    }
+
+Without a wrapper the arguments of the process are not available, so
+`argCount` and `argNumber` need one of the two options."
+        }
+        "E0017" => {
+            "E0017: builtin not available on the target
+
+The array builtins `push`, `pop`, `concat` and `slice`, as well as array
+literals spreading other arrays, come with the ARM32 runtime, which keeps
+the capacity of every array before it. So do `numberToString`, which
+allocates the string it gives, and `argCount` and `argNumber`, which read
+the arguments the ARM32 entry wrappers save:
+
+    function f(xs: array<number>) {
+        return concat(xs, xs);
+    }
+
+The program above compiles for ARM32 but not with `--target wasm32` or
+`--emit=llvm-ir`."
+        }
+        "E0018" => {
+            "E0018: code cannot be encoded
+
+`--emit=obj` encodes the ARM32 code without an assembler, which only knows
+the instructions and operands the code generator usually produces. A
+function with a frame too large for the offsets of loads and stores, or a
+branch too far for its instruction, is reported instead of being encoded
+wrongly.
+
+Emitting assembly with `--emit=asm` and assembling it with `as` or `clang`
+works for such programs."
+        }
+        "E0019" => {
+            "E0019: static assertion failed
+
+The condition of a `static_assert` is evaluated when compiling, and the
+compilation fails with its message when it is false:
+
+    static_assert(length(\"abc\") == 4, \"three characters\");
+
+Either the condition or the assumption it states is wrong."
+        }
+        "E0020" => {
+            "E0020: value is not a constant
+
+The condition of a `static_assert` and the value of a `const` have to be
+evaluated when compiling, so they may only combine literals and other
+constants with arithmetic, comparisons, `!` and the `length` of strings:
+
+    function f(x) {
+        const y = x + 1;
+        return y;
+    }
+
+Variables and calls only have values when the program runs, declare the
+name with `var` instead."
+        }
+        "E0021" => {
+            "E0021: assignment to a constant
+
+A name declared with `const` keeps its value, which is substituted for it
+wherever it is used:
+
+    const limit = 10;
+    function f() {
+        limit = 20;
+        return limit;
+    }
+
+Declare it with `var` if it has to change."
+        }
+        "E0022" => {
+            "E0022: too many arguments for ARM32
+
+The ARM32 code passes the arguments of a call in `r0`-`r3`, so functions
+take at most four parameters and calls pass at most four arguments:
+
+    function mix(a, b, c, d, e) {
+        return a + b + c + d + e;
+    }
+
+Pass the values in an array instead, or split the function. WebAssembly
+and LLVM IR have no such limit."
+        }
+        "E0023" => {
+            "E0023: reserved function name
+
+The generated code calls functions of the C library such as `malloc`,
+`free` and `putchar`, and routines of its own whose names start with
+`__rtsc_`. A function of the program with one of those names would take
+their place:
+
+    export function malloc(size) {
+        return 0;
+    }
+
+Rename the function. An `extern` declaration of a C library function is
+fine."
+        }
+        "W0001" => {
+            "W0001: unreachable code
+
+A statement follows one which always returns, so it can never run:
+
+    function f(x) {
+        return x;
+        putchar(x);
+    }
+
+Only the first unreachable statement of a block is reported. Often the
+`return` was meant to be inside of an `if`."
+        }
+        "W0002" => {
+            "W0002: assigned value is never read
+
+A value is stored in a local variable, but every path from there either
+assigns the variable again or leaves the function without reading it:
+
+    function f(x) {
+        var y = 0;
+        y = x * 2;
+        return y;
+    }
+
+Here the `0` is never read. Either the assignment is not needed or the
+code meant to read the variable before overwriting it."
+        }
+        "W0003" => {
+            "W0003: missing type annotation
+
+A parameter or the return type of a function has no annotation:
+
+    function half(x) {
+        return x / 2;
+    }
+
+The compiler takes it to be a `number`, which hides mistakes such as
+passing a string. Annotate it, `function half(x: number): number`.
+`--strict` makes the warning an error."
+        }
+        _ => return None,
+    };
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::WARNINGS;
+
+    #[test]
+    fn every_code_is_explained() {
+        let errors = (1..=23).map(|i| format!("E{:04}", i));
+        let warnings = WARNINGS.iter().map(|(code, _)| String::from(*code));
+        for code in errors.chain(warnings) {
+            let text = explanation(&code).unwrap();
+            assert!(text.starts_with(&format!("{}: ", code)));
+        }
+        assert_eq!(explanation("E9999"), None);
+    }
+}
@@ -0,0 +1,758 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Write};
+
+use crate::ast::Ast;
+use crate::lexer::{Lexer, Trivia};
+use crate::lines;
+use crate::parser::{self, ParseError, ParseErrorKind, ParseOptions};
+use crate::span::{Location, Span};
+use crate::types::Type;
+
+const INDENT: &str = "    ";
+
+// Binding strength of the expression grammar levels, from the loosest
+// (comparison) to the tightest (atom). Used to decide where parentheses are
+// needed so that the printed source parses back into the same tree.
+const COMPARISON: u8 = 1;
+const SUM: u8 = 2;
+const PRODUCT: u8 = 3;
+const UNARY: u8 = 4;
+const ATOM: u8 = 5;
+
+/// Formats the whole program, i.e. the top level block returned by
+/// `parser::make_full_parser`, whose statements are not surrounded by braces.
+pub fn format_program(program: &Ast) -> String {
+    write_program(program, &mut Comments::default())
+}
+
+/// Formats the program parsed from `source` like `format_program`, keeping
+/// the ordinary comments the parser skips. A comment trailing a statement on
+/// its line stays there, any other one goes on its own line before the
+/// statement or closing brace following it.
+pub fn format_source(source: &str) -> Result<String, ParseError> {
+    let (program, spans) = parser::parse_program_with_spans(source, &ParseOptions::default())?;
+    let tokens = Lexer::new()
+        .tokenize_with_trivia(source)
+        .map_err(|error| ParseError {
+            kind: ParseErrorKind::UnexpectedInput,
+            offset: error.span.start,
+            location: Location::from_offset(source, error.span.start),
+        })?;
+    let spans = lines::statements(&program)
+        .into_iter()
+        .map(|statement| statement as *const Ast)
+        .zip(spans)
+        .collect();
+    let mut comments = Comments {
+        source,
+        pending: tokens.comments().cloned().collect(),
+        spans,
+    };
+    Ok(write_program(&program, &mut comments))
+}
+
+fn write_program(program: &Ast, comments: &mut Comments<'_>) -> String {
+    let mut output = String::new();
+    match program {
+        Ast::Block(statements) => write_statements(&mut output, statements, 0, comments),
+        statement => write_statements(&mut output, std::slice::from_ref(statement), 0, comments),
+    }
+    .unwrap();
+    comments.write_before(&mut output, usize::MAX, 0).unwrap();
+    output
+}
+
+/// The comments of the source being formatted, still to be written, and the
+/// spans of its statements to place them by. A tree formatted on its own has
+/// neither.
+#[derive(Debug, Default)]
+struct Comments<'a> {
+    source: &'a str,
+    pending: VecDeque<Trivia>,
+    spans: HashMap<*const Ast, Span>,
+}
+
+impl Comments<'_> {
+    fn span(&self, statement: &Ast) -> Option<Span> {
+        self.spans.get(&(statement as *const Ast)).copied()
+    }
+
+    fn any_before(&self, offset: usize) -> bool {
+        self.pending
+            .front()
+            .is_some_and(|comment| comment.span.start < offset)
+    }
+
+    // Writes the comments starting before `offset`, each on its own line.
+    fn write_before(&mut self, f: &mut impl Write, offset: usize, indent: usize) -> fmt::Result {
+        while self.any_before(offset) {
+            let comment = self.pending.pop_front().unwrap();
+            write_indent(f, indent)?;
+            f.write_str(comment.text.trim_end())?;
+            f.write_str("\n")?;
+        }
+        Ok(())
+    }
+
+    // Writes the comment right after `end` on the same line, if there is one.
+    fn write_trailing(&mut self, f: &mut impl Write, end: usize) -> fmt::Result {
+        let trailing = self.pending.front().is_some_and(|comment| {
+            comment.span.start >= end
+                && self.source[end..comment.span.start]
+                    .chars()
+                    .all(|c| c == ' ' || c == '\t')
+        });
+        if trailing {
+            let comment = self.pending.pop_front().unwrap();
+            write!(f, " {}", comment.text.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// The declaration of a function, or of an exported or external one,
+/// without its body, e.g. `function f(x: number): number`.
+pub fn signature(declaration: &Ast) -> String {
+    let mut output = String::new();
+    match declaration {
+        Ast::Function(name, function_type, _) => write_signature(&mut output, name, function_type),
+        Ast::Export(function) => {
+            output.push_str("export ");
+            output.push_str(&signature(function));
+            Ok(())
+        }
+        Ast::Extern(name, function_type) => {
+            output.push_str("extern ");
+            write_signature(&mut output, name, function_type)
+        }
+        other => unreachable!("{:?} is not a function declaration", other),
+    }
+    .unwrap();
+    output
+}
+
+impl fmt::Display for Ast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if is_statement(self) {
+            write_statement(f, self, 0, &mut Comments::default())
+        } else {
+            write_expression(f, self, COMPARISON)
+        }
+    }
+}
+
+fn is_statement(ast: &Ast) -> bool {
+    matches!(
+        ast,
+        Ast::Return(_)
+            | Ast::Delete(_)
+            | Ast::Block(_)
+            | Ast::If(_, _, _)
+            | Ast::Function(_, _, _)
+            | Ast::Var(_, _)
+            | Ast::Const(_, _)
+            | Ast::Assignment(_, _)
+            | Ast::While(_, _)
+            | Ast::Import(_, _)
+            | Ast::Export(_)
+            | Ast::Extern(_, _)
+            | Ast::StaticAssert(_, _)
+            | Ast::Doc(_)
+            | Ast::Namespace(_, _)
+    )
+}
+
+fn write_indent(f: &mut impl Write, indent: usize) -> fmt::Result {
+    for _ in 0..indent {
+        f.write_str(INDENT)?;
+    }
+    Ok(())
+}
+
+fn write_type(f: &mut impl Write, type_: &Type) -> fmt::Result {
+    match type_ {
+        Type::Boolean => f.write_str("boolean"),
+        Type::Number => f.write_str("number"),
+        Type::Float => f.write_str("float"),
+        Type::String => f.write_str("string"),
+        Type::Void => f.write_str("void"),
+        Type::Undefined => f.write_str("undefined"),
+        Type::Array { element_type } => {
+            f.write_str("array<")?;
+            write_type(f, element_type)?;
+            f.write_str(">")
+        }
+        Type::Function { .. } => unreachable!("function types cannot be written in source"),
+    }
+}
+
+// Writes each statement on its own line, along with the comments before and
+// after it.
+fn write_statements(
+    f: &mut impl Write,
+    statements: &[Ast],
+    indent: usize,
+    comments: &mut Comments<'_>,
+) -> fmt::Result {
+    for statement in statements {
+        let span = comments.span(statement);
+        if let Some(span) = span {
+            comments.write_before(f, span.start, indent)?;
+        }
+        write_indent(f, indent)?;
+        write_statement(f, statement, indent, comments)?;
+        if let Some(span) = span {
+            comments.write_trailing(f, span.end)?;
+        }
+        f.write_str("\n")?;
+    }
+    Ok(())
+}
+
+// Writes the statement starting at the current position, the `indent` is the
+// level of the line the statement begins on and is used for nested lines.
+fn write_statement(
+    f: &mut impl Write,
+    ast: &Ast,
+    indent: usize,
+    comments: &mut Comments<'_>,
+) -> fmt::Result {
+    match ast {
+        Ast::Return(expr) => {
+            f.write_str("return ")?;
+            write_expression(f, expr, COMPARISON)?;
+            f.write_str(";")
+        }
+        Ast::Delete(expr) => {
+            f.write_str("delete ")?;
+            write_expression(f, expr, COMPARISON)?;
+            f.write_str(";")
+        }
+        Ast::Var(name, expr) => {
+            write!(f, "var {} = ", name)?;
+            write_expression(f, expr, COMPARISON)?;
+            f.write_str(";")
+        }
+        Ast::Const(name, expr) => {
+            write!(f, "const {} = ", name)?;
+            write_expression(f, expr, COMPARISON)?;
+            f.write_str(";")
+        }
+        Ast::Assignment(name, expr) => {
+            write!(f, "{} = ", name)?;
+            write_expression(f, expr, COMPARISON)?;
+            f.write_str(";")
+        }
+        Ast::Block(statements) => write_block(f, ast, statements, indent, comments),
+        Ast::If(condition, consequence, alternative) => {
+            f.write_str("if (")?;
+            write_expression(f, condition, COMPARISON)?;
+            f.write_str(") ")?;
+            write_statement(f, consequence, indent, comments)?;
+            f.write_str(" else ")?;
+            write_statement(f, alternative, indent, comments)
+        }
+        Ast::While(condition, body) => {
+            f.write_str("while (")?;
+            write_expression(f, condition, COMPARISON)?;
+            f.write_str(") ")?;
+            write_statement(f, body, indent, comments)
+        }
+        Ast::Function(name, function_type, body) => {
+            write_signature(f, name, function_type)?;
+            f.write_str(" ")?;
+            write_statement(f, body, indent, comments)
+        }
+        Ast::Extern(name, function_type) => {
+            f.write_str("extern ")?;
+            write_signature(f, name, function_type)?;
+            f.write_str(";")
+        }
+        Ast::StaticAssert(condition, message) => {
+            f.write_str("static_assert(")?;
+            write_expression(f, condition, COMPARISON)?;
+            write!(f, ", \"{}\");", message)
+        }
+        Ast::Import(names, path) => {
+            write!(f, "import {{ {} }} from \"{}\";", names.join(", "), path)
+        }
+        Ast::Doc(text) => {
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    f.write_str("\n")?;
+                    write_indent(f, indent)?;
+                }
+                match line {
+                    "" => f.write_str("///")?,
+                    line => write!(f, "/// {}", line)?,
+                }
+            }
+            Ok(())
+        }
+        Ast::Export(function) => {
+            f.write_str("export ")?;
+            write_statement(f, function, indent, comments)
+        }
+        Ast::Namespace(name, statements) => {
+            write!(f, "namespace {} ", name)?;
+            write_block(f, ast, statements, indent, comments)
+        }
+        expr => {
+            write_expression(f, expr, COMPARISON)?;
+            f.write_str(";")
+        }
+    }
+}
+
+// Writes the statements in braces, with the comments up to the end of the
+// statement holding them.
+fn write_block(
+    f: &mut impl Write,
+    ast: &Ast,
+    statements: &[Ast],
+    indent: usize,
+    comments: &mut Comments<'_>,
+) -> fmt::Result {
+    let end = comments.span(ast).map_or(0, |span| span.end);
+    if statements.is_empty() && !comments.any_before(end) {
+        return f.write_str("{}");
+    }
+    f.write_str("{\n")?;
+    write_statements(f, statements, indent + 1, comments)?;
+    comments.write_before(f, end, indent + 1)?;
+    write_indent(f, indent)?;
+    f.write_str("}")
+}
+
+fn write_signature(f: &mut impl Write, name: &str, function_type: &Type) -> fmt::Result {
+    let (parameter_types, return_type) = match function_type {
+        Type::Function {
+            parameter_types,
+            return_type,
+        } => (parameter_types, return_type),
+        _ => unreachable!(),
+    };
+    write!(f, "function {}(", name)?;
+    for (i, (parameter, type_)) in parameter_types.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}: ", parameter)?;
+        write_type(f, type_)?;
+    }
+    f.write_str("): ")?;
+    write_type(f, return_type)
+}
+
+fn write_arguments(f: &mut impl Write, args: &[Ast]) -> fmt::Result {
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write_expression(f, arg, COMPARISON)?;
+    }
+    Ok(())
+}
+
+fn write_binary(
+    f: &mut impl Write,
+    lhs: &Ast,
+    operator: &str,
+    rhs: &Ast,
+    precedence: u8,
+) -> fmt::Result {
+    // All binary operators are left associative, so the right hand side
+    // has to bind tighter than the operator itself.
+    write_expression(f, lhs, precedence)?;
+    write!(f, " {} ", operator)?;
+    write_expression(f, rhs, precedence + 1)
+}
+
+// Writes the expression, wrapping it in parentheses when it binds looser
+// than the `context` precedence level it appears in.
+fn write_expression(f: &mut impl Write, ast: &Ast, context: u8) -> fmt::Result {
+    let precedence = match ast {
+        Ast::Equal(_, _) | Ast::NotEqual(_, _) => COMPARISON,
+        Ast::Addition(_, _) | Ast::Subtraction(_, _) => SUM,
+        Ast::Multiplication(_, _) | Ast::Division(_, _) => PRODUCT,
+        Ast::Not(_) => UNARY,
+        _ => ATOM,
+    };
+    if precedence < context {
+        f.write_str("(")?;
+        write_expression(f, ast, COMPARISON)?;
+        return f.write_str(")");
+    }
+
+    match ast {
+        Ast::Null => f.write_str("null"),
+        Ast::Undefined => f.write_str("undefined"),
+        Ast::Number(value) => write!(f, "{}", value),
+        // a whole float still needs its point to be read back as one
+        Ast::Float(value) if value.fract() == 0.0 => write!(f, "{}.0", value),
+        Ast::Float(value) => write!(f, "{}", value),
+        Ast::Bool(value) => write!(f, "{}", value),
+        Ast::String(text) => write!(f, "\"{}\"", text),
+        Ast::Identifier(name) => f.write_str(name),
+        Ast::ArrayLiteral(elements) => {
+            f.write_str("[")?;
+            write_arguments(f, elements)?;
+            f.write_str("]")
+        }
+        Ast::ArrayLookup(array, index) => {
+            write_expression(f, array, ATOM)?;
+            f.write_str("[")?;
+            write_expression(f, index, COMPARISON)?;
+            f.write_str("]")
+        }
+        Ast::ArrayLength(array) => {
+            f.write_str("length(")?;
+            write_expression(f, array, COMPARISON)?;
+            f.write_str(")")
+        }
+        Ast::Call(name, args) => {
+            write!(f, "{}(", name)?;
+            write_arguments(f, args)?;
+            f.write_str(")")
+        }
+        Ast::Not(expr) => {
+            // The grammar only allows an atom after the negation.
+            f.write_str("!")?;
+            write_expression(f, expr, ATOM)
+        }
+        Ast::Equal(lhs, rhs) => write_binary(f, lhs, "==", rhs, COMPARISON),
+        Ast::NotEqual(lhs, rhs) => write_binary(f, lhs, "!=", rhs, COMPARISON),
+        Ast::Addition(lhs, rhs) => write_binary(f, lhs, "+", rhs, SUM),
+        Ast::Subtraction(lhs, rhs) => write_binary(f, lhs, "-", rhs, SUM),
+        Ast::Multiplication(lhs, rhs) => write_binary(f, lhs, "*", rhs, PRODUCT),
+        Ast::Division(lhs, rhs) => write_binary(f, lhs, "/", rhs, PRODUCT),
+        Ast::Return(_)
+        | Ast::Delete(_)
+        | Ast::Block(_)
+        | Ast::If(_, _, _)
+        | Ast::Function(_, _, _)
+        | Ast::Var(_, _)
+        | Ast::Const(_, _)
+        | Ast::Assignment(_, _)
+        | Ast::While(_, _)
+        | Ast::Import(_, _)
+        | Ast::Export(_)
+        | Ast::Extern(_, _)
+        | Ast::StaticAssert(_, _)
+        | Ast::Doc(_)
+        | Ast::Namespace(_, _) => unreachable!("statement used as an expression"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::parser::combinators::Parser;
+
+    fn parse(source: &str) -> Ast {
+        let options = parser::ParseOptions::default();
+        let (rest, ast) = parser::make_full_parser(&options).parse(source).unwrap();
+        assert_eq!(rest, "");
+        ast
+    }
+
+    #[test]
+    fn expression_parentheses() {
+        let ast = Ast::Multiplication(
+            Box::new(Ast::Addition(
+                Box::new(Ast::Number(1)),
+                Box::new(Ast::Number(2)),
+            )),
+            Box::new(Ast::Subtraction(
+                Box::new(Ast::Number(3)),
+                Box::new(Ast::Subtraction(
+                    Box::new(Ast::Number(4)),
+                    Box::new(Ast::Number(5)),
+                )),
+            )),
+        );
+        assert_eq!(ast.to_string(), "(1 + 2) * (3 - (4 - 5))");
+    }
+
+    #[test]
+    fn not_of_comparison() {
+        let ast = Ast::Not(Box::new(Ast::Equal(
+            Box::new(Ast::Identifier(String::from("x"))),
+            Box::new(Ast::Null),
+        )));
+        assert_eq!(ast.to_string(), "!(x == null)");
+    }
+
+    #[test]
+    fn program_indentation() {
+        let source = "function f(x) { if (x == 1) { return 1; } else return x; }";
+        let expected = "function f(x: number): number {
+    if (x == 1) {
+        return 1;
+    } else return x;
+}
+";
+        assert_eq!(format_program(&parse(source)), expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = r#"function main(n: number, xs: array<number>): void {
+            var i = 0;
+            var scale = 2.0 * 0.25;
+            while (i != length(xs)) {
+                putchar(xs[i] * (n - 1) / 2 + 48);
+                i = i + 1;
+            }
+            if (!true) {} else { return undefined; }
+        }
+        import { f, g } from "./lib";
+        ////////
+        ///Gives one.
+        ///
+        ///    indented
+        export function h() { return 1; }
+        extern function abs(x: number): number;
+        extern function sqrtf(x: float): float;
+        namespace geometry {
+            export function area(w: number, h: number) { return w * h; }
+        }
+        namespace empty {}
+        var a = geometry.area(2, 3);"#;
+        let ast = parse(source);
+        let formatted = format_program(&ast);
+        assert_eq!(parse(&formatted), ast);
+        assert_eq!(format_program(&parse(&formatted)), formatted);
+    }
+
+    #[test]
+    fn comments_kept() {
+        let source = "// header
+
+function f(x) { // why
+    var y = x; // trailing
+    // before return
+    if (y == 1) {
+        // nothing to do
+    } else {}
+    return y;
+    // after return
+}
+f(1);//last
+";
+        let expected = "// header
+function f(x: number): number {
+    // why
+    var y = x; // trailing
+    // before return
+    if (y == 1) {
+        // nothing to do
+    } else {}
+    return y;
+    // after return
+}
+f(1); //last
+";
+        assert_eq!(format_source(source).unwrap(), expected);
+        assert_eq!(format_source(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn doc_comments() {
+        let source = "function f() {\n    /// Inner.\n    ///\n    ///  two\n    return 1;\n}\n";
+        assert_eq!(
+            format_program(&parse(source)),
+            source.replace("function f() {", "function f(): number {")
+        );
+    }
+
+    mod generated {
+        use super::*;
+        use linked_hash_map::LinkedHashMap;
+        use proptest::prelude::*;
+
+        const KEYWORDS: [&str; 26] = [
+            "boolean",
+            "number",
+            "float",
+            "string",
+            "void",
+            "array",
+            "function",
+            "if",
+            "true",
+            "false",
+            "undefined",
+            "null",
+            "length",
+            "else",
+            "return",
+            "delete",
+            "while",
+            "var",
+            "import",
+            "export",
+            "extern",
+            "namespace",
+            "from",
+            "push",
+            "pop",
+            "slice",
+        ];
+
+        // Names which may start with a keyword, the way `iffy` or `nullable`
+        // do, but are not one.
+        fn name() -> impl Strategy<Value = String> {
+            let prefix = prop_oneof![
+                Just(String::new()),
+                proptest::sample::select(&KEYWORDS[..]).prop_map(String::from),
+            ];
+            (prefix, "[a-z_][a-z0-9_]{0,4}")
+                .prop_map(|(prefix, rest)| prefix + &rest)
+                .prop_filter("keyword", |name| !KEYWORDS.contains(&name.as_str()))
+        }
+
+        fn value_type() -> impl Strategy<Value = Type> {
+            let scalar = prop_oneof![
+                Just(Type::Boolean),
+                Just(Type::Number),
+                Just(Type::Float),
+                Just(Type::String),
+            ];
+            scalar.prop_recursive(2, 2, 1, |element| {
+                element.prop_map(|element| Type::Array {
+                    element_type: Box::new(element),
+                })
+            })
+        }
+
+        fn function_type() -> impl Strategy<Value = Type> {
+            let return_type = prop_oneof![Just(Type::Void), value_type()];
+            (
+                proptest::collection::vec((name(), value_type()), 0..4),
+                return_type,
+            )
+                .prop_map(|(parameters, return_type)| Type::Function {
+                    parameter_types: parameters.into_iter().collect::<LinkedHashMap<_, _>>(),
+                    return_type: Box::new(return_type),
+                })
+        }
+
+        fn expression() -> impl Strategy<Value = Ast> {
+            let leaf = prop_oneof![
+                Just(Ast::Null),
+                Just(Ast::Undefined),
+                (0..=i32::MAX).prop_map(Ast::Number),
+                // eighths are exact, so they print back as the same float
+                (0u32..100_000).prop_map(|n| Ast::Float(n as f32 / 8.0)),
+                any::<bool>().prop_map(Ast::Bool),
+                "[a-z ]{0,8}".prop_map(Ast::String),
+                name().prop_map(Ast::Identifier),
+            ];
+            leaf.prop_recursive(4, 24, 3, |inner| {
+                let binary = |operator: fn(Box<Ast>, Box<Ast>) -> Ast| {
+                    (inner.clone(), inner.clone())
+                        .prop_map(move |(lhs, rhs)| operator(Box::new(lhs), Box::new(rhs)))
+                };
+                prop_oneof![
+                    binary(Ast::Equal),
+                    binary(Ast::NotEqual),
+                    binary(Ast::Addition),
+                    binary(Ast::Subtraction),
+                    binary(Ast::Multiplication),
+                    binary(Ast::Division),
+                    // only variables can be indexed
+                    (name(), inner.clone()).prop_map(|(array, index)| Ast::ArrayLookup(
+                        Box::new(Ast::Identifier(array)),
+                        Box::new(index)
+                    )),
+                    inner.clone().prop_map(|expr| Ast::Not(Box::new(expr))),
+                    inner
+                        .clone()
+                        .prop_map(|expr| Ast::ArrayLength(Box::new(expr))),
+                    proptest::collection::vec(inner.clone(), 0..3).prop_map(Ast::ArrayLiteral),
+                    (name(), proptest::collection::vec(inner, 0..3))
+                        .prop_map(|(name, args)| Ast::Call(name, args)),
+                ]
+            })
+        }
+
+        fn statement() -> impl Strategy<Value = Ast> {
+            let simple = prop_oneof![
+                expression().prop_map(|expr| Ast::Return(Box::new(expr))),
+                expression().prop_map(|expr| Ast::Delete(Box::new(expr))),
+                (name(), expression()).prop_map(|(name, expr)| Ast::Var(name, Box::new(expr))),
+                (name(), expression()).prop_map(|(name, expr)| Ast::Const(name, Box::new(expr))),
+                (name(), expression())
+                    .prop_map(|(name, expr)| Ast::Assignment(name, Box::new(expr))),
+                (name(), proptest::collection::vec(expression(), 0..3))
+                    .prop_map(|(name, args)| Ast::Call(name, args)),
+            ];
+            // every nested block costs the parser a lot of stack
+            simple.prop_recursive(2, 8, 3, |inner| {
+                prop_oneof![
+                    proptest::collection::vec(inner.clone(), 0..3).prop_map(Ast::Block),
+                    (expression(), inner.clone(), inner.clone()).prop_map(
+                        |(condition, consequence, alternative)| Ast::If(
+                            Box::new(condition),
+                            Box::new(consequence),
+                            Box::new(alternative)
+                        )
+                    ),
+                    (expression(), inner).prop_map(|(condition, body)| Ast::While(
+                        Box::new(condition),
+                        Box::new(body)
+                    )),
+                ]
+            })
+        }
+
+        fn function() -> impl Strategy<Value = Ast> {
+            (
+                name(),
+                function_type(),
+                proptest::collection::vec(statement(), 0..3),
+            )
+                .prop_map(|(name, function_type, body)| {
+                    Ast::Function(name, function_type, Box::new(Ast::Block(body)))
+                })
+        }
+
+        fn declaration() -> impl Strategy<Value = Ast> {
+            prop_oneof![
+                function(),
+                function().prop_map(|function| Ast::Export(Box::new(function))),
+                (name(), function_type())
+                    .prop_map(|(name, function_type)| Ast::Extern(name, function_type)),
+                (name(), expression()).prop_map(|(name, expr)| Ast::Var(name, Box::new(expr))),
+                (proptest::collection::vec(name(), 1..3), "[a-z./]{1,8}")
+                    .prop_map(|(names, path)| Ast::Import(names, path)),
+                (expression(), "[a-z ]{0,8}").prop_map(|(condition, message)| {
+                    Ast::StaticAssert(Box::new(condition), message)
+                }),
+                (name(), proptest::collection::vec(function(), 0..3))
+                    .prop_map(|(name, functions)| Ast::Namespace(name, functions)),
+            ]
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(64))]
+
+            #[test]
+            fn expressions_round_trip(expr in expression()) {
+                let source = expr.to_string();
+                let parsed = parser::parse_expression(&source);
+                prop_assert_eq!(parsed.ok(), Some(expr), "{}", source);
+            }
+
+            #[test]
+            fn programs_round_trip(declarations in proptest::collection::vec(declaration(), 1..4)) {
+                let program = Ast::Block(declarations);
+                let source = format_program(&program);
+                let parsed = parser::parse_program(&source);
+                prop_assert_eq!(parsed.ok(), Some(program), "{}", source);
+            }
+        }
+    }
+}
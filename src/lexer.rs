@@ -0,0 +1,411 @@
+use std::fmt;
+
+use regex::Regex;
+
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Function,
+    If,
+    Else,
+    Return,
+    Delete,
+    While,
+    Var,
+    Const,
+    True,
+    False,
+    Undefined,
+    Null,
+    Length,
+    Import,
+    Export,
+    Extern,
+    StaticAssert,
+    Namespace,
+    From,
+    BooleanKeyword,
+    NumberKeyword,
+    FloatKeyword,
+    StringKeyword,
+    VoidKeyword,
+    ArrayKeyword,
+    Identifier,
+    Number,
+    Float,
+    String,
+    Assign,
+    Comma,
+    Colon,
+    Semicolon,
+    Less,
+    Greater,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Not,
+    Equal,
+    NotEqual,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Ellipsis,
+    Dot,
+    /// A whole `///` line.
+    DocComment,
+}
+
+impl TokenKind {
+    fn from_word(word: &str) -> TokenKind {
+        match word {
+            "function" => TokenKind::Function,
+            "if" => TokenKind::If,
+            "else" => TokenKind::Else,
+            "return" => TokenKind::Return,
+            "delete" => TokenKind::Delete,
+            "while" => TokenKind::While,
+            "var" => TokenKind::Var,
+            "const" => TokenKind::Const,
+            "true" => TokenKind::True,
+            "false" => TokenKind::False,
+            "undefined" => TokenKind::Undefined,
+            "null" => TokenKind::Null,
+            "length" => TokenKind::Length,
+            "import" => TokenKind::Import,
+            "export" => TokenKind::Export,
+            "extern" => TokenKind::Extern,
+            "static_assert" => TokenKind::StaticAssert,
+            "namespace" => TokenKind::Namespace,
+            "from" => TokenKind::From,
+            "boolean" => TokenKind::BooleanKeyword,
+            "number" => TokenKind::NumberKeyword,
+            "float" => TokenKind::FloatKeyword,
+            "string" => TokenKind::StringKeyword,
+            "void" => TokenKind::VoidKeyword,
+            "array" => TokenKind::ArrayKeyword,
+            _ => TokenKind::Identifier,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub span: Span,
+}
+
+/// What a piece of trivia is made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+}
+
+/// Whitespace or an ordinary comment, which the parser skips.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+/// A token together with the trivia in front of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriviaToken {
+    pub leading: Vec<Trivia>,
+    pub token: Token,
+}
+
+/// The source split into tokens without losing any of its characters. The
+/// trivia after the last token is kept in `trailing`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokensWithTrivia {
+    pub tokens: Vec<TriviaToken>,
+    pub trailing: Vec<Trivia>,
+}
+
+impl TokensWithTrivia {
+    /// All trivia in source order, including the trailing one.
+    pub fn trivia(&self) -> impl Iterator<Item = &Trivia> {
+        self.tokens
+            .iter()
+            .flat_map(|token| token.leading.iter())
+            .chain(self.trailing.iter())
+    }
+
+    /// The comments in source order.
+    pub fn comments(&self) -> impl Iterator<Item = &Trivia> {
+        self.trivia()
+            .filter(|trivia| trivia.kind == TriviaKind::Comment)
+    }
+
+    /// Puts the source back together, character for character.
+    pub fn to_source(&self) -> String {
+        let mut source = String::new();
+        for token in &self.tokens {
+            for trivia in &token.leading {
+                source.push_str(&trivia.text);
+            }
+            source.push_str(&token.token.text);
+        }
+        for trivia in &self.trailing {
+            source.push_str(&trivia.text);
+        }
+        source
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexError {
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unexpected character")
+    }
+}
+
+/// Splits the source into tokens, skipping whitespace and comments. The
+/// `///` comments documenting declarations are tokens, while four or more
+/// slashes start an ordinary comment again.
+///
+/// Words are matched as a whole before being classified, so an identifier
+/// such as `iffy` is never split into the `if` keyword and `fy`.
+///
+/// [`Lexer::tokenize_with_trivia`] keeps the skipped whitespace and comments
+/// attached to the tokens that follow them, for tools that must not lose
+/// them.
+#[derive(Debug)]
+pub struct Lexer {
+    trivia: Vec<(Regex, TriviaKind)>,
+    rules: Vec<(Regex, Option<TokenKind>)>,
+}
+
+impl Default for Lexer {
+    fn default() -> Self {
+        Lexer::new()
+    }
+}
+
+impl Lexer {
+    pub fn new() -> Lexer {
+        // Rules are tried in order, so longer operators must come before
+        // their prefixes. A rule without a kind is a word to be classified.
+        let rules = vec![
+            (r"^[0-9]+\.[0-9]+", Some(TokenKind::Float)),
+            (r"^[0-9]+", Some(TokenKind::Number)),
+            (r"^[a-zA-Z_][a-zA-Z0-9_]*", None),
+            (r#"^"[^"\n]*""#, Some(TokenKind::String)),
+            (r"^==", Some(TokenKind::Equal)),
+            (r"^!=", Some(TokenKind::NotEqual)),
+            (r"^=", Some(TokenKind::Assign)),
+            (r"^!", Some(TokenKind::Not)),
+            (r"^,", Some(TokenKind::Comma)),
+            (r"^:", Some(TokenKind::Colon)),
+            (r"^;", Some(TokenKind::Semicolon)),
+            (r"^<", Some(TokenKind::Less)),
+            (r"^>", Some(TokenKind::Greater)),
+            (r"^\(", Some(TokenKind::LeftParen)),
+            (r"^\)", Some(TokenKind::RightParen)),
+            (r"^\{", Some(TokenKind::LeftBrace)),
+            (r"^\}", Some(TokenKind::RightBrace)),
+            (r"^\[", Some(TokenKind::LeftBracket)),
+            (r"^\]", Some(TokenKind::RightBracket)),
+            (r"^///.*", Some(TokenKind::DocComment)),
+            (r"^\+", Some(TokenKind::Plus)),
+            (r"^\-", Some(TokenKind::Minus)),
+            (r"^\*", Some(TokenKind::Star)),
+            (r"^/", Some(TokenKind::Slash)),
+            (r"^\.\.\.", Some(TokenKind::Ellipsis)),
+            (r"^\.", Some(TokenKind::Dot)),
+        ];
+        Lexer {
+            trivia: vec![
+                (Regex::new(r"^[ \n\r\t]+").unwrap(), TriviaKind::Whitespace),
+                (
+                    Regex::new(r"^(//([^/\n].*)?(?m:$)|////.*)").unwrap(),
+                    TriviaKind::Comment,
+                ),
+            ],
+            rules: rules
+                .into_iter()
+                .map(|(pattern, kind)| (Regex::new(pattern).unwrap(), kind))
+                .collect(),
+        }
+    }
+
+    pub fn tokenize(&self, source: &str) -> Result<Vec<Token>, LexError> {
+        let tokens = self.tokenize_with_trivia(source)?;
+        Ok(tokens.tokens.into_iter().map(|token| token.token).collect())
+    }
+
+    pub fn tokenize_with_trivia(&self, source: &str) -> Result<TokensWithTrivia, LexError> {
+        let mut tokens = Vec::new();
+        let mut offset = 0;
+        loop {
+            let leading = self.skip_trivia(source, &mut offset);
+            if offset == source.len() {
+                return Ok(TokensWithTrivia {
+                    tokens,
+                    trailing: leading,
+                });
+            }
+
+            let rest = &source[offset..];
+            let (text, kind) = self
+                .rules
+                .iter()
+                .find_map(|(regex, kind)| {
+                    regex.find(rest).map(|matched| {
+                        let text = matched.as_str();
+                        (text, kind.unwrap_or_else(|| TokenKind::from_word(text)))
+                    })
+                })
+                .ok_or_else(|| {
+                    let length = rest.chars().next().unwrap().len_utf8();
+                    LexError {
+                        span: Span::new(offset, offset + length),
+                    }
+                })?;
+            tokens.push(TriviaToken {
+                leading,
+                token: Token {
+                    kind,
+                    text: text.to_owned(),
+                    span: Span::new(offset, offset + text.len()),
+                },
+            });
+            offset += text.len();
+        }
+    }
+
+    fn skip_trivia(&self, source: &str, offset: &mut usize) -> Vec<Trivia> {
+        let mut skipped = Vec::new();
+        while let Some((matched, kind)) = self
+            .trivia
+            .iter()
+            .find_map(|(regex, kind)| regex.find(&source[*offset..]).map(|m| (m, *kind)))
+        {
+            skipped.push(Trivia {
+                kind,
+                text: matched.as_str().to_owned(),
+                span: Span::new(*offset, *offset + matched.end()),
+            });
+            *offset += matched.end();
+        }
+        skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        Lexer::new()
+            .tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.kind)
+            .collect()
+    }
+
+    #[test]
+    fn statement_tokens() {
+        assert_eq!(
+            kinds("var x = a[1] != 2; // comment\n"),
+            vec![
+                TokenKind::Var,
+                TokenKind::Identifier,
+                TokenKind::Assign,
+                TokenKind::Identifier,
+                TokenKind::LeftBracket,
+                TokenKind::Number,
+                TokenKind::RightBracket,
+                TokenKind::NotEqual,
+                TokenKind::Number,
+                TokenKind::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn float_tokens() {
+        assert_eq!(
+            kinds("var x: float = 1.5;"),
+            vec![
+                TokenKind::Var,
+                TokenKind::Identifier,
+                TokenKind::Colon,
+                TokenKind::FloatKeyword,
+                TokenKind::Assign,
+                TokenKind::Float,
+                TokenKind::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn doc_comment_tokens() {
+        assert_eq!(
+            kinds("//\n//// banner\n/// Doc.\nf(); // x /// y"),
+            vec![
+                TokenKind::DocComment,
+                TokenKind::Identifier,
+                TokenKind::LeftParen,
+                TokenKind::RightParen,
+                TokenKind::Semicolon,
+            ]
+        );
+    }
+
+    #[test]
+    fn trivia_is_kept() {
+        let source = "  // lead\nf( x ); // tail\n/// Doc.\n";
+        let tokens = Lexer::new().tokenize_with_trivia(source).unwrap();
+        assert_eq!(tokens.to_source(), source);
+        assert_eq!(
+            tokens
+                .comments()
+                .map(|comment| comment.text.as_str())
+                .collect::<Vec<_>>(),
+            vec!["// lead", "// tail"]
+        );
+        assert_eq!(tokens.tokens[0].leading[1].span, Span::new(2, 9));
+        assert_eq!(tokens.tokens[5].token.kind, TokenKind::DocComment);
+        assert_eq!(tokens.trailing.len(), 1);
+    }
+
+    #[test]
+    fn keyword_prefixed_identifier() {
+        assert_eq!(
+            kinds("iffy returned"),
+            vec![TokenKind::Identifier, TokenKind::Identifier]
+        );
+    }
+
+    #[test]
+    fn token_spans() {
+        let tokens = Lexer::new().tokenize("  f(x)").unwrap();
+        assert_eq!(tokens[0].span, Span::new(2, 3));
+        assert_eq!(tokens[2].text, "x");
+        assert_eq!(tokens[3].span, Span::new(5, 6));
+    }
+
+    #[test]
+    fn unexpected_character() {
+        assert_eq!(
+            Lexer::new().tokenize("x = #;"),
+            Err(LexError {
+                span: Span::new(4, 5)
+            })
+        );
+    }
+}
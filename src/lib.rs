@@ -1,3 +1,14 @@
+//! The compiler as a library: every phase from the lexer to the code
+//! generators, for tools which embed the compiler rather than run it.
+//!
+//! ```
+//! use rtsc::typecheck::TypeChecker;
+//!
+//! let ast = rtsc::parse_program("function main() { return 0; }").unwrap();
+//! rtsc::StaticTypeChecker::new(Default::default(), rtsc::typecheck::builtins(), None)
+//!     .check(&ast)
+//!     .unwrap();
+//! ```
 #![deny(
     warnings,
     missing_debug_implementations,
@@ -9,6 +20,38 @@
 #![forbid(unsafe_code)]
 
 pub mod ast;
+pub mod cache;
+pub mod compiler;
+pub mod diagnostic;
+pub mod doc;
+pub mod explain;
+pub mod format;
+pub mod lexer;
+pub mod lines;
+#[cfg(feature = "lsp")]
+pub mod lsp;
+pub mod mangle;
+pub mod metrics;
+pub mod modules;
 pub mod parser;
 pub mod phases;
+pub mod sourcemap;
+pub mod span;
+pub mod symbols;
+pub mod timings;
 pub mod types;
+
+pub use ast::Ast;
+pub use cache::Cache;
+pub use compiler::{CompilationOutput, Compiler};
+pub use diagnostic::{Diagnostic, Diagnostics};
+pub use lexer::{Lexer, Token};
+pub use lines::SourceLine;
+pub use modules::Module;
+pub use parser::{parse_program, ParseError};
+pub use phases::codegen::{Arm32Generator, CodeGenerator, CodegenOptions, Environment};
+pub use phases::llvm::LlvmGenerator;
+pub use phases::typecheck::{self, StaticTypeChecker, TypeError};
+pub use phases::wasm::WasmGenerator;
+pub use span::{Location, Span};
+pub use types::Type;
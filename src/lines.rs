@@ -0,0 +1,133 @@
+//! Source lines of the statements, which the AST does not keep. The parser
+//! records the spans of the statements alongside the tree, in the order of
+//! `statements`. The variables a single `var` statement declares all get
+//! its line.
+use crate::ast::Ast;
+use crate::span::{Location, Span};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// Index of the source file among the ones compiled together.
+    pub file: usize,
+    /// Where the statement starts.
+    pub location: Location,
+    /// The whole line the statement starts on, without the indentation.
+    pub text: String,
+    /// The source of the statement, up to its semicolon or closing brace.
+    /// The variables declared together span their whole `var` statement.
+    pub span: Span,
+}
+
+/// Every statement of the program in source order, nested statements
+/// following the one containing them. The top level block of the program is
+/// not a statement itself.
+pub fn statements(program: &Ast) -> Vec<&Ast> {
+    fn visit<'a>(ast: &'a Ast, statements: &mut Vec<&'a Ast>) {
+        statements.push(ast);
+        match ast {
+            Ast::Block(block) | Ast::Namespace(_, block) => {
+                block.iter().for_each(|x| visit(x, statements))
+            }
+            Ast::If(_, consequence, alternative) => {
+                visit(consequence, statements);
+                visit(alternative, statements);
+            }
+            Ast::While(_, body) | Ast::Function(_, _, body) | Ast::Export(body) => {
+                visit(body, statements)
+            }
+            _ => {}
+        }
+    }
+    let mut statements = Vec::new();
+    match program {
+        Ast::Block(block) => block.iter().for_each(|x| visit(x, &mut statements)),
+        statement => visit(statement, &mut statements),
+    }
+    statements
+}
+
+/// The lines of the statements spanning `spans` in `source`, as given by
+/// `parser::parse_program_with_spans`.
+pub fn statement_lines(file: usize, source: &str, spans: &[Span]) -> Vec<SourceLine> {
+    spans
+        .iter()
+        .map(|&span| {
+            let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = source[span.start..]
+                .find('\n')
+                .map_or(source.len(), |i| span.start + i);
+            SourceLine {
+                file,
+                location: Location::from_offset(source, span.start),
+                text: source[line_start..line_end].trim().to_string(),
+                span,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, ParseOptions};
+
+    #[test]
+    fn lines_of_statements() {
+        let source = "import {g} from \"lib\";
+// counts down
+function f(n: number): number {
+    while (n != (1)) n = n - 1;
+    if (n == 1) {
+        g(n);
+    } else return 2;
+    return n;
+}";
+        let (program, spans) =
+            parser::parse_program_with_spans(source, &ParseOptions::default()).unwrap();
+        let lines: Vec<(usize, String)> = statement_lines(0, source, &spans)
+            .into_iter()
+            .map(|line| (line.location.line, line.text))
+            .collect();
+        let expected = vec![
+            (1, "import {g} from \"lib\";"),
+            (3, "function f(n: number): number {"),
+            (3, "function f(n: number): number {"),
+            (4, "while (n != (1)) n = n - 1;"),
+            (4, "while (n != (1)) n = n - 1;"),
+            (5, "if (n == 1) {"),
+            (5, "if (n == 1) {"),
+            (6, "g(n);"),
+            (7, "} else return 2;"),
+            (8, "return n;"),
+        ];
+        assert_eq!(
+            lines,
+            expected
+                .into_iter()
+                .map(|(line, text)| (line, String::from(text)))
+                .collect::<Vec<(usize, String)>>()
+        );
+        assert_eq!(statements(&program).len(), lines.len());
+        assert_eq!(
+            statement_lines(0, source, &spans)[3].location,
+            Location { line: 4, column: 5 }
+        );
+        let span = statement_lines(0, source, &spans)[5].span;
+        assert_eq!(
+            &source[span.start..span.end],
+            "if (n == 1) {\n        g(n);\n    } else return 2;"
+        );
+    }
+
+    #[test]
+    fn lines_of_declarations() {
+        let source = "var [a, b] = f();\nvar c = [a, b], d = f(c, 1);\nf();";
+        let (_, spans) =
+            parser::parse_program_with_spans(source, &ParseOptions::default()).unwrap();
+        let lines: Vec<usize> = statement_lines(0, source, &spans)
+            .into_iter()
+            .map(|line| line.location.line)
+            .collect();
+        assert_eq!(lines, vec![1, 1, 1, 2, 2, 3]);
+    }
+}
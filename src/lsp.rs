@@ -0,0 +1,593 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use linked_hash_map::LinkedHashMap;
+use serde_json::{json, Value};
+
+use crate::ast::Ast;
+use crate::diagnostic::{self, Diagnostic};
+use crate::lexer::{Lexer, Token, TokenKind};
+use crate::modules;
+use crate::parser;
+use crate::span::{Location, Span};
+use crate::types::Type;
+
+/// Language server speaking the Language Server Protocol over stdio.
+///
+/// Documents are synchronized in full on every change. The server publishes
+/// parse and type errors, shows function signatures on hover and jumps to
+/// the declaration of functions, parameters and variables in scope.
+#[derive(Debug)]
+pub struct Server {
+    builtins: LinkedHashMap<String, Type>,
+    documents: HashMap<String, String>,
+    lexer: Lexer,
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+    PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    format!("file://{}", path.display())
+}
+
+// Positions count the characters of a line in UTF-16 code units, the
+// encoding the protocol uses by default.
+fn position(source: &str, offset: usize) -> Value {
+    let location = Location::from_offset(source, offset);
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let character = source[line_start..offset].encode_utf16().count();
+    json!({ "line": location.line - 1, "character": character })
+}
+
+fn range(source: &str, span: Span) -> Value {
+    json!({ "start": position(source, span.start), "end": position(source, span.end) })
+}
+
+fn offset(source: &str, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let line_start = if line == 0 {
+        0
+    } else {
+        source.match_indices('\n').nth(line - 1)?.0 + 1
+    };
+    let line_text = source[line_start..].split('\n').next().unwrap_or("");
+    let mut units = 0;
+    let column = line_text
+        .char_indices()
+        .find(|(_, c)| {
+            units += c.len_utf16();
+            units > character
+        })
+        .map(|(i, _)| i)
+        .unwrap_or_else(|| line_text.len());
+    Some(line_start + column)
+}
+
+/// What a declaration names. Functions and variables have names of their
+/// own, like in the typechecker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Function,
+    Variable,
+}
+
+/// A block of the document with the names declared directly in it, which
+/// for the body of a function include its parameters.
+#[derive(Debug)]
+struct Scope {
+    parent: Option<usize>,
+    /// The tokens of the braces, the whole document for the top level.
+    start: usize,
+    end: usize,
+    /// The tokens of the names with what they name.
+    declarations: Vec<(usize, Kind)>,
+}
+
+/// The scopes of the document, the top level one first and the rest in
+/// the order they open.
+fn scopes(tokens: &[Token]) -> Vec<Scope> {
+    let mut scopes = vec![Scope {
+        parent: None,
+        start: 0,
+        end: tokens.len(),
+        declarations: Vec::new(),
+    }];
+    let mut open = vec![0];
+    let mut parameters = Vec::new();
+    let mut in_parameters = false;
+    for (i, token) in tokens.iter().enumerate() {
+        let previous = i.checked_sub(1).map(|i| tokens[i].kind);
+        let current = open[open.len() - 1];
+        match token.kind {
+            TokenKind::Identifier if previous == Some(TokenKind::Function) => {
+                scopes[current].declarations.push((i, Kind::Function));
+                parameters.clear();
+                in_parameters = true;
+            }
+            TokenKind::Identifier
+                if matches!(previous, Some(TokenKind::Var | TokenKind::Const)) =>
+            {
+                scopes[current].declarations.push((i, Kind::Variable))
+            }
+            TokenKind::Identifier
+                if in_parameters
+                    && matches!(previous, Some(TokenKind::LeftParen | TokenKind::Comma)) =>
+            {
+                parameters.push((i, Kind::Variable))
+            }
+            TokenKind::RightParen => in_parameters = false,
+            // the parameters of an extern function have no body
+            TokenKind::Semicolon => parameters.clear(),
+            TokenKind::LeftBrace => {
+                scopes.push(Scope {
+                    parent: Some(current),
+                    start: i,
+                    end: tokens.len(),
+                    declarations: std::mem::take(&mut parameters),
+                });
+                open.push(scopes.len() - 1);
+            }
+            TokenKind::RightBrace if open.len() > 1 => {
+                let closed = open.pop().unwrap();
+                scopes[closed].end = i;
+            }
+            _ => {}
+        }
+    }
+    scopes
+}
+
+/// The token declaring the name at the index, looked up like the
+/// typechecker does: a function when it is called and a variable
+/// otherwise, declared before it in the innermost scope around it which has
+/// one. `None` for the names declared in other modules.
+fn resolve(tokens: &[Token], scopes: &[Scope], index: usize) -> Option<usize> {
+    let declared = |scope: &Scope| scope.declarations.iter().any(|(i, _)| *i == index);
+    if scopes.iter().any(declared) {
+        return Some(index);
+    }
+    let name = &tokens[index].text;
+    let kind = match tokens.get(index + 1).map(|token| token.kind) {
+        Some(TokenKind::LeftParen) => Kind::Function,
+        _ => Kind::Variable,
+    };
+    let mut scope = scopes
+        .iter()
+        .rposition(|scope| scope.start <= index && index <= scope.end);
+    while let Some(current) = scope {
+        let declaration = scopes[current]
+            .declarations
+            .iter()
+            .rev()
+            .find(|(i, declared)| *i < index && *declared == kind && tokens[*i].text == *name);
+        if let Some((i, _)) = declaration {
+            return Some(*i);
+        }
+        scope = scopes[current].parent;
+    }
+    None
+}
+
+impl Server {
+    pub fn new(builtins: LinkedHashMap<String, Type>) -> Server {
+        Server {
+            builtins,
+            documents: HashMap::new(),
+            lexer: Lexer::new(),
+        }
+    }
+
+    fn read(&self, path: &Path) -> io::Result<String> {
+        match self.documents.get(&path_to_uri(path)) {
+            Some(text) => Ok(text.clone()),
+            None => fs::read_to_string(path),
+        }
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let text = &self.documents[uri];
+        let (span, error) = match parser::parse_program(text) {
+            Err(error) => (
+                Span::new(error.offset, text.len()),
+                Some(Diagnostic::error(
+                    diagnostic::SYNTAX_ERROR,
+                    error.to_string(),
+                    None,
+                )),
+            ),
+            Ok(_) => {
+                let checked =
+                    modules::load_modules(&uri_to_path(uri), text.clone(), |path| self.read(path))
+                        .and_then(|modules| modules::check_modules(&modules, &self.builtins));
+                let path = uri_to_path(uri).display().to_string();
+                match checked {
+                    Ok(()) => (Span::new(0, 0), None),
+                    // errors without a span in the document, such as the
+                    // ones in the modules it imports, are reported at its
+                    // beginning
+                    Err(error) => match error.span {
+                        Some(span) if error.file.as_ref() == Some(&path) => (span, Some(error)),
+                        _ => (Span::new(0, 0), Some(error)),
+                    },
+                }
+            }
+        };
+        let diagnostics = match error {
+            None => json!([]),
+            Some(error) => json!([{
+                "range": range(text, span),
+                "severity": 1,
+                "code": error.code,
+                "source": "rtsc",
+                "message": error.message,
+            }]),
+        };
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })
+    }
+
+    fn token_at(&self, uri: &str, position: &Value) -> Option<(Vec<Token>, usize)> {
+        let text = self.documents.get(uri)?;
+        let offset = offset(text, position)?;
+        let tokens = self.lexer.tokenize(text).ok()?;
+        let index = tokens.iter().position(|token| {
+            token.kind == TokenKind::Identifier
+                && token.span.start <= offset
+                && offset <= token.span.end
+        })?;
+        Some((tokens, index))
+    }
+
+    fn hover(&self, uri: &str, position: &Value) -> Value {
+        let (tokens, index) = match self.token_at(uri, position) {
+            Some(found) => found,
+            None => return Value::Null,
+        };
+        let name = &tokens[index].text;
+        let text = self.documents[uri].clone();
+        let mut signatures = self.builtins.clone();
+        if let Ok(modules) = modules::load_modules(&uri_to_path(uri), text, |p| self.read(p)) {
+            for module in &modules {
+                for function in module.ast.declarations() {
+                    if let Ast::Function(name, function_type, _) = function {
+                        signatures.insert(name.clone(), function_type.clone());
+                    }
+                }
+            }
+        }
+        match signatures.get(name) {
+            Some(signature) => json!({
+                "contents": { "kind": "plaintext", "value": format!("function {}: {}", name, signature) },
+                "range": range(&self.documents[uri], tokens[index].span),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn definition(&self, uri: &str, position: &Value) -> Value {
+        let (tokens, index) = match self.token_at(uri, position) {
+            Some(found) => found,
+            None => return Value::Null,
+        };
+        let name = &tokens[index].text;
+        if let Some(declaration) = resolve(&tokens, &scopes(&tokens), index) {
+            let span = tokens[declaration].span;
+            return json!({ "uri": uri, "range": range(&self.documents[uri], span) });
+        }
+
+        // The name may come from an imported module.
+        let path = uri_to_path(uri);
+        let imported = match parser::parse_program(&self.documents[uri]) {
+            Ok(Ast::Block(statements)) => {
+                statements
+                    .into_iter()
+                    .find_map(|statement| match statement {
+                        Ast::Import(names, specifier) if names.contains(name) => {
+                            Some(modules::resolve_import(&path, &specifier))
+                        }
+                        _ => None,
+                    })
+            }
+            _ => None,
+        };
+        let imported = match imported {
+            Some(imported) => imported,
+            None => return Value::Null,
+        };
+        let text = match self.read(&imported) {
+            Ok(text) => text,
+            Err(_) => return Value::Null,
+        };
+        let tokens = match self.lexer.tokenize(&text) {
+            Ok(tokens) => tokens,
+            Err(_) => return Value::Null,
+        };
+        // the exported functions are declared at the top level
+        match scopes(&tokens)[0]
+            .declarations
+            .iter()
+            .find(|(i, kind)| *kind == Kind::Function && tokens[*i].text == *name)
+        {
+            Some((declaration, _)) => json!({
+                "uri": path_to_uri(&imported),
+                "range": range(&text, tokens[*declaration].span),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    /// Handles a single message, returning the messages to send back.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_owned();
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                },
+                "serverInfo": { "name": "rtsc", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.documents.insert(uri.clone(), text.to_owned());
+                return vec![self.diagnostics(&uri)];
+            }
+            "textDocument/didChange" => {
+                if let Some(text) = params["contentChanges"][0]["text"].as_str() {
+                    self.documents.insert(uri.clone(), text.to_owned());
+                }
+                return vec![self.diagnostics(&uri)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "method": "textDocument/publishDiagnostics",
+                    "params": { "uri": uri, "diagnostics": [] },
+                })];
+            }
+            "textDocument/hover" => self.hover(&uri, &params["position"]),
+            "textDocument/definition" => self.definition(&uri, &params["position"]),
+            "shutdown" => Value::Null,
+            _ => {
+                // Notifications we do not care about have no id and no answer.
+                if message.get("id").is_none() {
+                    return vec![];
+                }
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "error": { "code": -32601, "message": format!("Unknown method {}", method) },
+                })];
+            }
+        };
+        vec![json!({ "jsonrpc": "2.0", "id": message["id"], "result": result })]
+    }
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// Serves requests until the client sends `exit` or closes the input.
+pub fn run(
+    builtins: LinkedHashMap<String, Type>,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    let mut server = Server::new(builtins);
+    while let Some(message) = read_message(&mut input)? {
+        if message["method"] == "exit" {
+            break;
+        }
+        for response in server.handle(&message) {
+            write_message(&mut output, &response)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///project/main.ts";
+
+    fn open(server: &mut Server, text: &str) -> Value {
+        let mut messages = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "text": text } },
+        }));
+        messages.remove(0)
+    }
+
+    fn request(server: &mut Server, method: &str, line: u64, character: u64) -> Value {
+        let mut messages = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+            },
+        }));
+        messages.remove(0)["result"].take()
+    }
+
+    #[test]
+    fn parse_error_diagnostic() {
+        let mut server = Server::new(LinkedHashMap::new());
+        let diagnostics = open(&mut server, "var x = 1;\nx ++;");
+        let diagnostic = &diagnostics["params"]["diagnostics"][0];
+        assert_eq!(diagnostic["message"], "Unexpected input at 2:1");
+        assert_eq!(diagnostic["code"], "E0001");
+        assert_eq!(
+            diagnostic["range"]["start"],
+            json!({"line": 1, "character": 0})
+        );
+    }
+
+    #[test]
+    fn type_error_diagnostic() {
+        let mut server = Server::new(LinkedHashMap::new());
+        let diagnostics = open(&mut server, "function f(): number {\n    return true;\n}");
+        let diagnostic = &diagnostics["params"]["diagnostics"][0];
+        assert_eq!(diagnostic["code"], "E0002");
+        assert_eq!(
+            diagnostic["range"],
+            json!({"start": {"line": 1, "character": 4}, "end": {"line": 1, "character": 16}})
+        );
+    }
+
+    #[test]
+    fn valid_document_has_no_diagnostics() {
+        let mut server = Server::new(LinkedHashMap::new());
+        let diagnostics = open(&mut server, "function f() { return 1; }");
+        assert_eq!(diagnostics["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn hover_shows_signature() {
+        let mut server = Server::new(LinkedHashMap::new());
+        open(
+            &mut server,
+            "function f(x: number): boolean { return x == 1; }\nfunction g() { return f(1); }",
+        );
+        let hover = request(&mut server, "textDocument/hover", 1, 22);
+        assert_eq!(
+            hover["contents"]["value"],
+            "function f: (number) => boolean"
+        );
+    }
+
+    #[test]
+    fn definition_of_parameter_and_function() {
+        let mut server = Server::new(LinkedHashMap::new());
+        open(
+            &mut server,
+            "function f(a: number, b: number) {\n    var c = b;\n    return f(c, a);\n}",
+        );
+        let definition = request(&mut server, "textDocument/definition", 1, 12);
+        assert_eq!(
+            definition["range"]["start"],
+            json!({"line": 0, "character": 22})
+        );
+        let definition = request(&mut server, "textDocument/definition", 2, 11);
+        assert_eq!(
+            definition["range"]["start"],
+            json!({"line": 0, "character": 9})
+        );
+        let definition = request(&mut server, "textDocument/definition", 2, 13);
+        assert_eq!(
+            definition["range"]["start"],
+            json!({"line": 1, "character": 8})
+        );
+    }
+
+    #[test]
+    fn definition_in_scope() {
+        let mut server = Server::new(LinkedHashMap::new());
+        open(
+            &mut server,
+            "var x = 1;\n\
+             function f(x: number) { return x; }\n\
+             function g() { if (x == 1) { var y = 1; } else {} var y = 2; return y; }\n\
+             function y() { return x + y(); }",
+        );
+        let mut start = |line, character| {
+            request(&mut server, "textDocument/definition", line, character)["range"]["start"]
+                .take()
+        };
+        // the parameter shadows the global
+        assert_eq!(start(1, 31), json!({"line": 1, "character": 11}));
+        // the global outside of the block of the `if`, the variable after it
+        assert_eq!(start(2, 19), json!({"line": 0, "character": 4}));
+        assert_eq!(start(2, 68), json!({"line": 2, "character": 54}));
+        // functions and variables have names of their own
+        assert_eq!(start(3, 22), json!({"line": 0, "character": 4}));
+        assert_eq!(start(3, 26), json!({"line": 3, "character": 9}));
+    }
+
+    #[test]
+    fn utf16_positions() {
+        let mut server = Server::new(LinkedHashMap::new());
+        open(
+            &mut server,
+            "function f(x: number): number { var s = \"😀\"; return x; }",
+        );
+        // the emoji takes two code units
+        let definition = request(&mut server, "textDocument/definition", 0, 53);
+        assert_eq!(
+            definition["range"],
+            json!({"start": {"line": 0, "character": 11}, "end": {"line": 0, "character": 12}})
+        );
+        let source = "\"😀\" + y";
+        assert_eq!(
+            position(source, source.len()),
+            json!({"line": 0, "character": 8})
+        );
+        assert_eq!(
+            offset(source, &json!({"line": 0, "character": 7})),
+            Some(source.len() - 1)
+        );
+    }
+
+    #[test]
+    fn message_framing() {
+        let initialize = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#;
+        let exit = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let input = format!(
+            "Content-Length: {}\r\n\r\n{}Content-Length: {}\r\n\r\n{}",
+            initialize.len(),
+            initialize,
+            exit.len(),
+            exit
+        );
+        let mut output = Vec::new();
+        run(LinkedHashMap::new(), input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Content-Length: "));
+        assert!(output.contains(r#""hoverProvider":true"#));
+    }
+}
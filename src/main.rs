@@ -1,53 +1,573 @@
-mod ast;
-mod parser;
-mod phases;
-mod types;
+//! The command line driver around `rtsc::Compiler`. Everything but parsing
+//! the arguments and building executables lives in the `rtsc` library.
+mod build;
+mod cli;
 
+use std::cell::RefCell;
 use std::env;
+use std::fmt;
 use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-use linked_hash_map::LinkedHashMap;
-
-use ast::Ast;
-use parser::combinators::Parser;
-use phases::codegen::{Arm32Generator, CodeGenerator, Environment};
-use phases::typecheck::{StaticTypeChecker, TypeChecker};
-use types::Type;
-
-fn parse(source: &str) -> Ast {
-    let parser = parser::make_full_parser();
-    parser.parse(&source).unwrap().1
-}
-
-fn typecheck(ast: &Ast) {
-    let mut functions = LinkedHashMap::new();
-    // I kind of use `putchar` function in test so I add it to the available funcions at start
-    let mut putchar_parameters = LinkedHashMap::new();
-    putchar_parameters.insert(String::from("x1"), Type::Number);
-    functions.insert(
-        String::from("putchar"),
-        Type::Function {
-            parameter_types: putchar_parameters,
-            return_type: Box::new(Type::Void),
+use rtsc::compiler::{EmitKind, Output};
+use rtsc::diagnostic::Level;
+#[cfg(feature = "lsp")]
+use rtsc::lsp;
+use rtsc::parser::ParseOptions;
+use rtsc::phases::cfg;
+use rtsc::phases::target::{Arch, TargetSpec};
+#[cfg(feature = "lsp")]
+use rtsc::phases::typecheck;
+use rtsc::phases::{coverage, profile};
+use rtsc::span::Location;
+use rtsc::timings::Timings;
+use rtsc::{ast, diagnostic, doc, explain, mangle, metrics, modules, parser};
+use rtsc::{Ast, Cache, CompilationOutput, Compiler, Diagnostic, Lexer};
+
+use cli::{
+    Command, CoverageOptions, DocOptions, Emit, ErrorFormat, MetricsOptions, Options,
+    ProfileOptions,
+};
+
+// Why the compilation stopped: a diagnostic about the program, printed with
+// its code and the source of the file it points into when known, or a
+// problem with running the compiler itself.
+enum Failure {
+    Program(Diagnostic, Option<String>),
+    Other(String),
+}
+
+impl From<Diagnostic> for Failure {
+    fn from(diagnostic: Diagnostic) -> Failure {
+        Failure::Program(diagnostic, None)
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure::Other(message)
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Program(
+                diagnostic @ Diagnostic {
+                    file: Some(file), ..
+                },
+                Some(source),
+            ) => write!(f, "{}", diagnostic.render_snippet(file, source, color())),
+            Failure::Program(diagnostic, _) => write!(f, "{}", diagnostic),
+            Failure::Other(message) => write!(f, "error: {}", message),
+        }
+    }
+}
+
+// Diagnostics are colored unless they are redirected or the `NO_COLOR`
+// environment variable is set.
+fn color() -> bool {
+    io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+impl Failure {
+    fn to_json(&self) -> String {
+        match self {
+            Failure::Program(diagnostic, source) => {
+                diagnostic.to_json(diagnostic.file.as_deref(), source.as_deref())
+            }
+            Failure::Other(message) => format!(
+                "{{\"file\": null, \"span\": null, \"severity\": \"error\", \"code\": null, \"message\": {}}}",
+                diagnostic::json_string(message)
+            ),
+        }
+    }
+}
+
+fn fail(failure: impl Into<Failure>) -> ! {
+    eprintln!("{}", failure.into());
+    process::exit(1);
+}
+
+fn input_path(options: &Options) -> &Path {
+    if options.input == "-" {
+        Path::new("<stdin>")
+    } else {
+        Path::new(&options.input)
+    }
+}
+
+fn parse(options: &Options, source: &str) -> Result<Ast, Failure> {
+    let parse_options = ParseOptions {
+        implicit_type: options.implicit_type.clone(),
+        max_depth: options.max_depth,
+    };
+    parse_file(input_path(options), source, &parse_options)
+}
+
+fn parse_file(path: &Path, source: &str, options: &ParseOptions) -> Result<Ast, Failure> {
+    parser::parse_program_with_options(source, options).map_err(|error| {
+        let diagnostic = Diagnostic::error(
+            diagnostic::SYNTAX_ERROR,
+            error.to_string(),
+            Some(error.span()),
+        )
+        .in_file(path.display().to_string());
+        Failure::Program(diagnostic, Some(String::from(source)))
+    })
+}
+
+fn dump_tokens(source: &str) -> Result<String, Diagnostic> {
+    let tokens = Lexer::new().tokenize(source).map_err(|error| {
+        let location = Location::from_offset(source, error.span.start);
+        Diagnostic::error(
+            diagnostic::SYNTAX_ERROR,
+            format!("{} at {}", error, location),
+            None,
+        )
+    })?;
+    let mut output = String::new();
+    for token in tokens {
+        output.push_str(&format!(
+            "{}-{} {:?} {:?}\n",
+            Location::from_offset(source, token.span.start),
+            Location::from_offset(source, token.span.end),
+            token.kind,
+            token.text
+        ));
+    }
+    Ok(output)
+}
+
+#[cfg(feature = "serde")]
+fn ast_to_json(ast: &Ast) -> Result<String, String> {
+    Ok(serde_json::to_string_pretty(ast).unwrap() + "\n")
+}
+
+#[cfg(not(feature = "serde"))]
+fn ast_to_json(_ast: &Ast) -> Result<String, String> {
+    Err(String::from(
+        "`--emit=ast-json` requires rtsc to be built with the `serde` feature",
+    ))
+}
+
+fn read_source(path: &str) -> io::Result<String> {
+    if path == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        Ok(source)
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+fn compiler(options: &Options) -> Result<Compiler, Failure> {
+    let mut codegen = options.codegen;
+    if let Some(path) = &options.target_spec {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("cannot read `{}`: {}", path, error))?;
+        codegen.target =
+            TargetSpec::parse(&text).map_err(|error| format!("{}: {}", path, error))?;
+        if codegen.thumb && codegen.target.arch == Arch::Armv6 {
+            return Err(Failure::from(String::from(
+                "`--thumb` needs Thumb-2, which ARMv6 does not have",
+            )));
+        }
+    }
+    let mut compiler = Compiler::new()
+        .with_target(options.target)
+        .with_codegen_options(codegen)
+        .with_warnings(options.warnings.clone())
+        .with_implicit_type(options.implicit_type.clone())
+        .with_max_depth(options.max_depth)
+        .with_token_count(options.time_passes);
+    if let Some(directory) = &options.incremental {
+        let cache = Cache::open(directory)
+            .map_err(|error| format!("cannot use `{}` as the cache: {}", directory, error))?;
+        compiler = compiler.with_cache(cache);
+    }
+    Ok(compiler)
+}
+
+// The source of the file the diagnostic points into, for showing the line
+// it is about.
+fn diagnostic_source(options: &Options, source: &str, diagnostic: &Diagnostic) -> Option<String> {
+    let file = diagnostic.file.as_ref()?;
+    if Path::new(file) == modules::normalize(input_path(options)) {
+        Some(String::from(source))
+    } else {
+        fs::read_to_string(file).ok()
+    }
+}
+
+fn print_diagnostic(options: &Options, source: &str, diagnostic: &Diagnostic) {
+    let source = diagnostic_source(options, source, diagnostic);
+    let rendered = match (options.error_format, &diagnostic.file, source) {
+        (ErrorFormat::Human, Some(file), Some(source)) => {
+            diagnostic.render_snippet(file, &source, color())
+        }
+        (ErrorFormat::Human, _, _) => diagnostic.to_string(),
+        (ErrorFormat::Json, file, source) => diagnostic.to_json(file.as_deref(), source.as_deref()),
+    };
+    eprintln!("{}", rendered);
+}
+
+// Prints the warnings, failing with the error which stopped the compilation
+// or, when only warnings made errors did, with how many there were.
+fn compile(
+    compiler: &Compiler,
+    options: &Options,
+    source: &str,
+    check_only: bool,
+) -> Result<CompilationOutput, Failure> {
+    let path = input_path(options);
+    let result = if check_only {
+        compiler.check_source(path, String::from(source))
+    } else {
+        compiler.compile_source(path, String::from(source))
+    };
+    let mut diagnostics = match result {
+        Ok(compiled) => {
+            for warning in &compiled.warnings {
+                print_diagnostic(options, source, warning);
+            }
+            return Ok(compiled);
+        }
+        Err(diagnostics) => diagnostics,
+    };
+    let is_warning = |diagnostic: &Diagnostic| {
+        diagnostic::WARNINGS
+            .iter()
+            .any(|(code, _)| *code == diagnostic.code)
+    };
+    let error = match diagnostics.last() {
+        Some(last) if !is_warning(last) => diagnostics.pop(),
+        _ => None,
+    };
+    for warning in &diagnostics {
+        print_diagnostic(options, source, warning);
+    }
+    Err(match error {
+        Some(error) => {
+            let source = diagnostic_source(options, source, &error);
+            Failure::Program(error, source)
+        }
+        None => match diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.level == Level::Error)
+            .count()
+        {
+            1 => Failure::Other(String::from("aborting due to a denied warning")),
+            errors => Failure::Other(format!("aborting due to {} denied warnings", errors)),
         },
+    })
+}
+
+fn write_output(options: &Options, output: String) -> Result<(), Failure> {
+    match &options.output {
+        Some(path) => fs::write(path, output)
+            .map_err(|error| format!("cannot write `{}`: {}", path, error))?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+fn run(options: &Options) -> Result<(), Failure> {
+    let source = read_source(&options.input)
+        .map_err(|error| format!("cannot read `{}`: {}", options.input, error))?;
+    if options.check_only {
+        let checked = compile(&compiler(options)?, options, &source, true)?;
+        report_timings(options, &checked.timings);
+        return Ok(());
+    }
+
+    if options.build {
+        let linker = options
+            .linker
+            .as_deref()
+            .or_else(|| build::default_linker(options.target, options.codegen.entry))
+            .ok_or_else(|| String::from("`--build` needs a `--linker` for this target"))?;
+        let compiler = compiler(options)?.with_emit(EmitKind::Assembly);
+        let mut compiled = compile(&compiler, options, &source, false)?;
+        let assembly = match compiled.output {
+            Output::Text(assembly) => assembly,
+            _ => unreachable!("assembly is text"),
+        };
+        let executable = match &options.output {
+            Some(path) => PathBuf::from(path),
+            None => build::executable_path(&options.input),
+        };
+        compiled
+            .timings
+            .time("assemble", || build::link(&assembly, linker, &executable))?;
+        report_timings(options, &compiled.timings);
+        return Ok(());
+    }
+
+    let emit = match options.emit {
+        Emit::Asm => EmitKind::Assembly,
+        Emit::LlvmIr => EmitKind::LlvmIr,
+        Emit::Obj => EmitKind::Object,
+        Emit::CallGraph => EmitKind::CallGraph,
+        Emit::StackUsage => EmitKind::StackUsage,
+        Emit::Symbols => EmitKind::Symbols,
+        Emit::Ir => EmitKind::Ir,
+        Emit::IrOpt => EmitKind::IrOptimized,
+        Emit::Tokens => return write_output(options, dump_tokens(&source)?),
+        Emit::Ast => return write_output(options, format!("{:#?}\n", parse(options, &source)?)),
+        Emit::AstJson => return write_output(options, ast_to_json(&parse(options, &source)?)?),
+        Emit::AstTree => return write_output(options, ast::to_tree(&parse(options, &source)?)),
+        Emit::AstDot => return write_output(options, ast::to_dot(&parse(options, &source)?)),
+        Emit::Cfg => {
+            return write_output(
+                options,
+                cfg::to_dot(&cfg::functions(&parse(options, &source)?)),
+            )
+        }
+    };
+    let compiled = compile(&compiler(options)?.with_emit(emit), options, &source, false)?;
+    match compiled.output {
+        Output::Binary(object) => {
+            let path = match &options.output {
+                Some(path) => PathBuf::from(path),
+                None => build::executable_path(&options.input).with_extension("o"),
+            };
+            fs::write(&path, object)
+                .map_err(|error| format!("cannot write `{}`: {}", path.display(), error))?;
+        }
+        Output::Text(text) => write_output(options, text)?,
+        Output::None => {}
+    }
+    if let (Some(path), Some(map)) = (&options.source_map, compiled.source_map) {
+        fs::write(path, map).map_err(|error| format!("cannot write `{}`: {}", path, error))?;
+    }
+    report_timings(options, &compiled.timings);
+    Ok(())
+}
+
+fn report_timings(options: &Options, timings: &Timings) {
+    if options.time_passes {
+        eprint!("{}", timings.report());
+    }
+}
+
+fn report(failure: Failure, options: &Options) {
+    match options.error_format {
+        ErrorFormat::Human => eprintln!("{}", failure),
+        ErrorFormat::Json => eprintln!("{}", failure.to_json()),
+    }
+}
+
+// The input and the modules it imports, as far as they can be parsed.
+fn watched_files(options: &Options) -> Vec<PathBuf> {
+    let files = RefCell::new(vec![modules::normalize(input_path(options))]);
+    if let Ok(source) = fs::read_to_string(&options.input) {
+        let _ = modules::load_modules(input_path(options), source, |path| {
+            files.borrow_mut().push(path.to_path_buf());
+            fs::read_to_string(path)
+        });
+    }
+    files.into_inner()
+}
+
+fn modification_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| {
+            fs::metadata(file)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+        .collect()
+}
+
+// Compiles the input again whenever it or one of the modules it imports
+// changes, until interrupted. The files are polled, which works the same on
+// every platform.
+fn watch(options: &Options) -> ! {
+    loop {
+        if let Err(failure) = run(options) {
+            report(failure, options);
+        }
+        let files = watched_files(options);
+        let compiled = modification_times(&files);
+        eprintln!("watching {} file(s) for changes", files.len());
+        while modification_times(&files) == compiled {
+            thread::sleep(Duration::from_millis(250));
+        }
+        eprintln!();
+    }
+}
+
+// The module is only parsed, its documentation does not depend on whether
+// it typechecks.
+fn document(options: &DocOptions) -> Result<(), Failure> {
+    let source = read_source(&options.input)
+        .map_err(|error| format!("cannot read `{}`: {}", options.input, error))?;
+    let (path, title) = if options.input == "-" {
+        (Path::new("<stdin>"), "stdin")
+    } else {
+        let path = Path::new(&options.input);
+        let title = path.file_stem().and_then(|stem| stem.to_str());
+        (path, title.unwrap_or(&options.input))
+    };
+    let output = doc::render(
+        &parse_file(path, &source, &ParseOptions::default())?,
+        title,
+        options.format,
     );
-    StaticTypeChecker::new(LinkedHashMap::new(), functions, None).check(&ast);
+    match &options.output {
+        Some(path) => fs::write(path, output)
+            .map_err(|error| format!("cannot write `{}`: {}", path, error))?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+// Like the documentation, the metrics only need the module to parse.
+fn measure(options: &MetricsOptions) -> Result<(), Failure> {
+    let source = read_source(&options.input)
+        .map_err(|error| format!("cannot read `{}`: {}", options.input, error))?;
+    let path = if options.input == "-" {
+        Path::new("<stdin>")
+    } else {
+        Path::new(&options.input)
+    };
+    let functions = metrics::functions(&parse_file(path, &source, &ParseOptions::default())?);
+    let output = metrics::render(&functions);
+    match &options.output {
+        Some(path) => fs::write(path, output)
+            .map_err(|error| format!("cannot write `{}`: {}", path, error))?,
+        None => print!("{}", output),
+    }
+    let limit = options.max_complexity.unwrap_or(usize::MAX);
+    match functions
+        .iter()
+        .find(|function| function.complexity > limit)
+    {
+        Some(function) => Err(Failure::Other(format!(
+            "function `{}` has a cyclomatic complexity of {}, more than {}",
+            function.name, function.complexity, limit
+        ))),
+        None => Ok(()),
+    }
 }
 
-fn generate_code(ast: Ast) -> String {
-    let mut output_asm = String::new();
-    let mut env = Environment::default();
-    Arm32Generator::new(ast).emit(&mut output_asm, &mut env);
-    output_asm
+// The program is checked again to find its blocks, the warnings were seen
+// when it was compiled.
+// The error stopping the compilation of the input of a report.
+fn first_error(diagnostics: Vec<Diagnostic>) -> Failure {
+    let error = diagnostics
+        .into_iter()
+        .rev()
+        .find(|diagnostic| diagnostic.level == Level::Error);
+    error.map_or_else(
+        || Failure::Other(String::from("aborting due to a denied warning")),
+        Failure::from,
+    )
 }
 
-fn compile(source: &str) -> String {
-    let ast = parse(source);
-    typecheck(&ast);
-    generate_code(ast)
+fn report_coverage(options: &CoverageOptions) -> Result<(), Failure> {
+    let map = Compiler::new()
+        .coverage_map(&options.input)
+        .map_err(first_error)?;
+    let bytes = fs::read(&options.counts)
+        .map_err(|error| format!("cannot read `{}`: {}", options.counts, error))?;
+    let counts = coverage::read_counts(&bytes)
+        .map_err(|error| format!("cannot read `{}`: {}", options.counts, error))?;
+    if counts.len() != map.lines.len() {
+        return Err(Failure::Other(format!(
+            "`{}` has {} counters but `{}` has {} blocks, was it built from another version?",
+            options.counts,
+            counts.len(),
+            options.input,
+            map.lines.len()
+        )));
+    }
+    let output = coverage::report(&map.files, &map.lines, &counts);
+    match &options.output {
+        Some(path) => fs::write(path, output)
+            .map_err(|error| format!("cannot write `{}`: {}", path, error))?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+fn report_profile(options: &ProfileOptions) -> Result<(), Failure> {
+    let program = Compiler::new()
+        .link_file(&options.input)
+        .map_err(first_error)?;
+    let functions = profile::functions(&program);
+    let bytes = fs::read(&options.profile)
+        .map_err(|error| format!("cannot read `{}`: {}", options.profile, error))?;
+    let profiles = profile::read_table(&bytes)
+        .map_err(|error| format!("cannot read `{}`: {}", options.profile, error))?;
+    if profiles.len() != functions.len() {
+        return Err(Failure::Other(format!(
+            "`{}` has {} entries but `{}` has {} functions, was it built from another version?",
+            options.profile,
+            profiles.len(),
+            options.input,
+            functions.len()
+        )));
+    }
+    let output = profile::report(&functions, &profiles);
+    match &options.output {
+        Some(path) => fs::write(path, output)
+            .map_err(|error| format!("cannot write `{}`: {}", path, error))?,
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "lsp")]
+fn serve() -> Result<(), String> {
+    let stdin = io::stdin();
+    lsp::run(typecheck::builtins(), stdin.lock(), io::stdout()).map_err(|error| error.to_string())
+}
+
+#[cfg(not(feature = "lsp"))]
+fn serve() -> Result<(), String> {
+    Err(String::from(
+        "`rtsc lsp` requires rtsc to be built with the `lsp` feature",
+    ))
 }
 
 fn main() {
-    let source = fs::read_to_string(env::args().nth(1).unwrap()).unwrap();
-    print!("{}", compile(&source));
+    let command = cli::parse_args(env::args().skip(1))
+        .unwrap_or_else(|error| fail(format!("{}\n\n{}", error, cli::USAGE)));
+    match command {
+        Command::Help => println!("{}", cli::USAGE),
+        Command::Version => println!("rtsc {}", env!("CARGO_PKG_VERSION")),
+        Command::Lsp => serve().unwrap_or_else(|error| fail(error)),
+        Command::Doc(options) => document(&options).unwrap_or_else(|failure| fail(failure)),
+        Command::Metrics(options) => measure(&options).unwrap_or_else(|failure| fail(failure)),
+        Command::CoverageReport(options) => {
+            report_coverage(&options).unwrap_or_else(|failure| fail(failure))
+        }
+        Command::ProfileReport(options) => {
+            report_profile(&options).unwrap_or_else(|failure| fail(failure))
+        }
+        Command::PrintPasses(passes) => print!("{}", passes.render()),
+        Command::Demangle(input) => {
+            let input = input.unwrap_or_else(|| String::from("-"));
+            match read_source(&input) {
+                Ok(text) => print!("{}", mangle::demangle_text(&text)),
+                Err(error) => fail(format!("cannot read `{}`: {}", input, error)),
+            }
+        }
+        Command::Explain(code) => match explain::explanation(&code) {
+            Some(text) => println!("{}", text),
+            None => fail(format!("no diagnostic has the code `{}`", code)),
+        },
+        Command::Compile(options) if options.watch => watch(&options),
+        Command::Compile(options) => run(&options).unwrap_or_else(|failure| {
+            report(failure, &options);
+            process::exit(1);
+        }),
+    }
 }
@@ -0,0 +1,204 @@
+//! The names of the symbols of functions other than the top level ones.
+//!
+//! A mangled name is `_T.` followed by the module, the functions enclosing
+//! the function and its own name, each preceded by its length, and for the
+//! second and later function of the same name in the same function by `_`
+//! and its number. The function `helper` declared in `main` of `main.ts` is
+//! `_T.4main4main6helper`, which `demangle` turns back into
+//! `main::main::helper`. Identifiers cannot contain the `.`, so no name of
+//! the program takes the symbol of a mangled one, while assemblers accept
+//! it in symbols.
+//!
+//! Global variables are mangled too, their name being the only component,
+//! so that they never take the symbol of a function, of the C library or of
+//! the runtime: `var malloc = 1;` is `_T.6malloc`.
+use std::collections::BTreeSet;
+use std::path::{Component, Path};
+
+const PREFIX: &str = "_T.";
+
+/// The symbol of the function named by the last of the components, which
+/// are the module and the enclosing functions before it. Numbers above 1
+/// tell apart functions of the same name in the same function.
+pub fn mangle(components: &[&str], number: usize) -> String {
+    let mut symbol = String::from(PREFIX);
+    for component in components {
+        symbol.push_str(&component.len().to_string());
+        symbol.push_str(component);
+    }
+    if number > 1 {
+        symbol.push_str(&format!("_{}", number));
+    }
+    symbol
+}
+
+/// The symbol of the global variable, which no function has since theirs
+/// take at least two components.
+pub fn global(name: &str) -> String {
+    mangle(&[name], 1)
+}
+
+/// The readable name of the mangled symbol, `None` for other symbols.
+pub fn demangle(symbol: &str) -> Option<String> {
+    let (demangled, length) = demangle_prefix(symbol)?;
+    if length == symbol.len() {
+        Some(demangled)
+    } else {
+        None
+    }
+}
+
+// Demangles the longest mangled name at the start of the text, giving the
+// readable name and the length of the mangled one.
+fn demangle_prefix(text: &str) -> Option<(String, usize)> {
+    let bytes = text.as_bytes();
+    let mut at = text.strip_prefix(PREFIX).map(|_| PREFIX.len())?;
+    let mut components = Vec::new();
+    loop {
+        let digits = bytes[at..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits == 0 || bytes[at] == b'0' {
+            break;
+        }
+        let length: usize = text[at..at + digits].parse().ok()?;
+        let start = at + digits;
+        let component = text.get(start..start + length)?;
+        if !component
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'_')
+        {
+            return None;
+        }
+        components.push(component);
+        at = start + length;
+    }
+    if components.is_empty() {
+        return None;
+    }
+    let mut demangled = components.join("::");
+    let digits = bytes[at..]
+        .iter()
+        .skip(1)
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    if bytes.get(at) == Some(&b'_') && digits > 0 {
+        demangled.push_str(&format!("#{}", &text[at + 1..at + 1 + digits]));
+        at += 1 + digits;
+    }
+    Some((demangled, at))
+}
+
+/// Replaces the mangled symbols in the text, such as assembly, with their
+/// readable names.
+pub fn demangle_text(text: &str) -> String {
+    let is_symbol = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(PREFIX) {
+        let preceded = rest[..start].chars().next_back().is_some_and(is_symbol);
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let word = rest.find(|c| !is_symbol(c)).unwrap_or(rest.len());
+        match demangle(&rest[..word]) {
+            Some(demangled) if !preceded => {
+                output.push_str(&demangled);
+                rest = &rest[word..];
+            }
+            _ => {
+                output.push_str(PREFIX);
+                rest = &rest[PREFIX.len()..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// The names of the modules at the paths in mangled symbols: the path
+/// relative to the directory of the last one, the entry module, without the
+/// extension and with `_` joining the directories and replacing the
+/// characters symbols cannot have. A name taken by an earlier module gets a
+/// number.
+pub fn module_names(paths: &[&Path]) -> Vec<String> {
+    let directory = paths
+        .last()
+        .and_then(|entry| entry.parent())
+        .unwrap_or_else(|| Path::new(""));
+    let mut taken = BTreeSet::new();
+    paths
+        .iter()
+        .map(|path| {
+            let relative = path.strip_prefix(directory).unwrap_or(path);
+            let relative = relative.with_extension("");
+            let name: Vec<String> = relative
+                .components()
+                .map(|component| match component {
+                    Component::Normal(name) => name
+                        .to_string_lossy()
+                        .chars()
+                        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                        .collect(),
+                    _ => String::new(),
+                })
+                .filter(|name| !name.is_empty())
+                .collect();
+            let name = name.join("_");
+            let mut unique = name.clone();
+            let mut number = 1;
+            while !taken.insert(unique.clone()) {
+                number += 1;
+                unique = format!("{}_{}", name, number);
+            }
+            unique
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let symbol = mangle(&["main", "main", "helper"], 1);
+        assert_eq!(symbol, "_T.4main4main6helper");
+        assert_eq!(demangle(&symbol).as_deref(), Some("main::main::helper"));
+        let symbol = mangle(&["lib_util", "f", "g_2"], 3);
+        assert_eq!(symbol, "_T.8lib_util1f3g_2_3");
+        assert_eq!(demangle(&symbol).as_deref(), Some("lib_util::f::g_2#3"));
+        for symbol in [
+            "main",
+            "_T",
+            "_T.",
+            "_Tx",
+            "_T5count",
+            "_T.9main",
+            "_T.4main_",
+            "_T.04main",
+        ] {
+            assert_eq!(demangle(symbol), None, "{}", symbol);
+        }
+    }
+
+    #[test]
+    fn assembly() {
+        assert_eq!(
+            demangle_text(".local _T.4main4main6helper\n    bl _T.4main4main6helper_2\n"),
+            ".local main::main::helper\n    bl main::main::helper#2\n"
+        );
+        assert_eq!(demangle_text("x_T.1a _T _T.1 _T1a"), "x_T.1a _T _T.1 _T1a");
+    }
+
+    #[test]
+    fn modules() {
+        let paths = [
+            Path::new("src/lib/util.ts"),
+            Path::new("src/lib-util.ts"),
+            Path::new("src/main.ts"),
+        ];
+        assert_eq!(module_names(&paths), ["lib_util", "lib_util_2", "main"]);
+        assert_eq!(module_names(&[Path::new("<input>")]), ["_input_"]);
+    }
+}
@@ -0,0 +1,157 @@
+//! Size and complexity of the functions of a module, reported by
+//! `rtsc metrics`.
+//!
+//! The cyclomatic complexity is the number of independent paths through a
+//! function: one plus the conditional branches reachable in its control-flow
+//! graph, as every `if` and `while` adds a path.
+use std::fmt::Write;
+
+use crate::ast::Ast;
+use crate::phases::cfg::{Cfg, Terminator};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionMetrics {
+    pub name: String,
+    /// Statements of the body, nested ones included. Blocks and doc comments
+    /// are not counted.
+    pub statements: usize,
+    /// How many `if` and `while` statements the most nested statement is in.
+    pub depth: usize,
+    pub complexity: usize,
+}
+
+/// The metrics of the functions of the program, exported ones included, in
+/// source order.
+pub fn functions(program: &Ast) -> Vec<FunctionMetrics> {
+    program
+        .declarations()
+        .filter_map(|declaration| match declaration {
+            Ast::Function(name, _, body) => Some(FunctionMetrics {
+                name: name.clone(),
+                statements: statements(body),
+                depth: depth(body),
+                complexity: complexity(&Cfg::new(body)),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn complexity(graph: &Cfg<'_>) -> usize {
+    let branches = graph
+        .blocks
+        .iter()
+        .zip(graph.reachable())
+        .filter(|(block, reachable)| {
+            *reachable && matches!(block.terminator, Terminator::Branch(_, _, _))
+        })
+        .count();
+    branches + 1
+}
+
+fn statements(ast: &Ast) -> usize {
+    match ast {
+        Ast::Block(statements) => statements.iter().map(self::statements).sum(),
+        Ast::If(_, consequence, alternative) => {
+            1 + statements(consequence) + statements(alternative)
+        }
+        Ast::While(_, body) => 1 + statements(body),
+        Ast::Doc(_) => 0,
+        _ => 1,
+    }
+}
+
+fn depth(ast: &Ast) -> usize {
+    match ast {
+        Ast::Block(statements) => statements.iter().map(depth).max().unwrap_or(0),
+        Ast::If(_, consequence, alternative) => 1 + depth(consequence).max(depth(alternative)),
+        Ast::While(_, body) => 1 + depth(body),
+        _ => 0,
+    }
+}
+
+/// Renders the metrics as a table with a row per function.
+pub fn render(functions: &[FunctionMetrics]) -> String {
+    let width = functions
+        .iter()
+        .map(|function| function.name.len())
+        .chain(Some("function".len()))
+        .max()
+        .unwrap();
+    let mut output = format!(
+        "{:<width$}  statements  depth  complexity\n",
+        "function",
+        width = width
+    );
+    for function in functions {
+        writeln!(
+            output,
+            "{:<width$}  {:>10}  {:>5}  {:>10}",
+            function.name,
+            function.statements,
+            function.depth,
+            function.complexity,
+            width = width
+        )
+        .unwrap();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    const SOURCE: &str = "function straight(): number { var x = 1; return x; }
+        /// Counts the digits.
+        export function digits(n: number): number {
+            var count = 1;
+            while (n != 0) {
+                if (n == 1) { return count; } else {}
+                n = n / 10;
+                count = count + 1;
+            }
+            return count;
+        }
+        extern function abs(x: number): number;
+        function dead(): number { return 1; if (true) { return 2; } else { return 3; } }";
+
+    #[test]
+    fn function_metrics() {
+        let program = parser::parse_program(SOURCE).unwrap();
+        let metrics: Vec<_> = functions(&program)
+            .into_iter()
+            .map(|function| {
+                (
+                    function.name,
+                    function.statements,
+                    function.depth,
+                    function.complexity,
+                )
+            })
+            .collect();
+        assert_eq!(
+            metrics,
+            vec![
+                (String::from("straight"), 2, 0, 1),
+                (String::from("digits"), 7, 2, 3),
+                // the branch after the return is never taken
+                (String::from("dead"), 4, 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn table() {
+        let program = parser::parse_program(SOURCE).unwrap();
+        assert_eq!(
+            render(&functions(&program)),
+            "function  statements  depth  complexity
+straight           2      0           1
+digits             7      2           3
+dead               4      1           1
+"
+        );
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use linked_hash_map::LinkedHashMap;
+
+use crate::ast::Ast;
+use crate::cache::Cache;
+use crate::diagnostic::{self, Diagnostic};
+use crate::lines;
+use crate::mangle;
+use crate::parser::{self, ParseOptions};
+use crate::phases::typecheck::{StaticTypeChecker, TypeChecker};
+use crate::span::Span;
+use crate::types::Type;
+
+/// Single parsed source file together with the files it imports from.
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub path: PathBuf,
+    pub source: String,
+    pub ast: Ast,
+    /// The spans of the statements of `ast`, in the order of
+    /// `lines::statements`.
+    pub spans: Vec<Span>,
+    pub imports: Vec<(Vec<String>, PathBuf)>,
+}
+
+impl Module {
+    /// Signatures of the functions the module marks with `export`.
+    pub fn exports(&self) -> LinkedHashMap<String, Type> {
+        let mut exports = LinkedHashMap::new();
+        for statement in self.ast.top_level_statements() {
+            if let Ast::Export(function) = statement {
+                if let Ast::Function(name, function_type, _) = function.as_ref() {
+                    exports.insert(name.clone(), function_type.clone());
+                }
+            }
+        }
+        exports
+    }
+}
+
+/// Removes `.` and `..` components without touching the file system, so that
+/// the same file imported through different relative paths is loaded once.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Resolves the `from "..."` part of an import relative to the importing
+/// module, the `.ts` extension is optional.
+pub fn resolve_import(importer: &Path, specifier: &str) -> PathBuf {
+    let directory = importer.parent().unwrap_or_else(|| Path::new(""));
+    let mut path = directory.join(specifier);
+    if path.extension().is_none() {
+        path.set_extension("ts");
+    }
+    normalize(&path)
+}
+
+/// Loads the entry module and, transitively, every module it imports.
+/// Modules are ordered so that each one comes after all of its imports.
+pub fn load_modules<F>(entry: &Path, source: String, read: F) -> Result<Vec<Module>, Diagnostic>
+where
+    F: Fn(&Path) -> io::Result<String>,
+{
+    load_cached_modules(entry, source, read, &ParseOptions::default(), None)
+}
+
+/// Like `load_modules`, parsing as the options say and taking the trees of
+/// unchanged modules from the cache.
+pub fn load_cached_modules<F>(
+    entry: &Path,
+    source: String,
+    read: F,
+    options: &ParseOptions,
+    cache: Option<&Cache>,
+) -> Result<Vec<Module>, Diagnostic>
+where
+    F: Fn(&Path) -> io::Result<String>,
+{
+    let mut modules = Vec::new();
+    let mut visiting = Vec::new();
+    load_module(
+        &normalize(entry),
+        Some(source),
+        &read,
+        options,
+        cache,
+        &mut modules,
+        &mut visiting,
+    )?;
+    Ok(modules)
+}
+
+fn load_module<F>(
+    path: &Path,
+    source: Option<String>,
+    read: &F,
+    options: &ParseOptions,
+    cache: Option<&Cache>,
+    modules: &mut Vec<Module>,
+    visiting: &mut Vec<PathBuf>,
+) -> Result<(), Diagnostic>
+where
+    F: Fn(&Path) -> io::Result<String>,
+{
+    if modules.iter().any(|module| module.path == path) {
+        return Ok(());
+    }
+    if visiting.iter().any(|visited| visited == path) {
+        let cycle: Vec<String> = visiting
+            .iter()
+            .chain(std::iter::once(&path.to_path_buf()))
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(Diagnostic::error(
+            diagnostic::IMPORT_CYCLE,
+            format!("Import cycle {}", cycle.join(" -> ")),
+            None,
+        ));
+    }
+
+    let source = match source {
+        Some(source) => source,
+        None => read(path).map_err(|error| {
+            Diagnostic::error(
+                diagnostic::MISSING_MODULE,
+                format!("Cannot read module `{}`: {}", path.display(), error),
+                None,
+            )
+        })?,
+    };
+    let (ast, spans) = match cache {
+        Some(cache) => cache.parse(&source, options),
+        None => parser::parse_program_with_spans(&source, options),
+    }
+    .map_err(|error| {
+        Diagnostic::error(
+            diagnostic::SYNTAX_ERROR,
+            error.to_string(),
+            Some(error.span()),
+        )
+        .in_file(path.display().to_string())
+    })?;
+    let imports: Vec<(Vec<String>, PathBuf)> = ast
+        .top_level_statements()
+        .iter()
+        .filter_map(|statement| match statement {
+            Ast::Import(names, specifier) => Some((names.clone(), resolve_import(path, specifier))),
+            _ => None,
+        })
+        .collect();
+
+    visiting.push(path.to_path_buf());
+    for (_, import) in &imports {
+        load_module(import, None, read, options, cache, modules, visiting)?;
+    }
+    visiting.pop();
+
+    modules.push(Module {
+        path: path.to_path_buf(),
+        source,
+        ast,
+        spans,
+        imports,
+    });
+    Ok(())
+}
+
+/// Typechecks every module with the given builtin functions and the
+/// signatures it imports from other modules in scope.
+pub fn check_modules(
+    modules: &[Module],
+    builtins: &LinkedHashMap<String, Type>,
+) -> Result<(), Diagnostic> {
+    check_cached_modules(modules, builtins, None)
+}
+
+/// Like `check_modules`, skipping the modules which typechecked before with
+/// the same signatures imported.
+pub fn check_cached_modules(
+    modules: &[Module],
+    builtins: &LinkedHashMap<String, Type>,
+    cache: Option<&Cache>,
+) -> Result<(), Diagnostic> {
+    for module in modules {
+        let mut functions = builtins.clone();
+        for (names, import) in &module.imports {
+            let imported = modules
+                .iter()
+                .find(|module| module.path == *import)
+                .expect("imported modules are loaded first");
+            let exports = imported.exports();
+            for name in names {
+                let signature = exports.get(name).ok_or_else(|| {
+                    Diagnostic::error(
+                        diagnostic::MISSING_EXPORT,
+                        format!(
+                            "Module `{}` has no exported function `{}`",
+                            imported.path.display(),
+                            name
+                        ),
+                        None,
+                    )
+                    .in_file(module.path.display().to_string())
+                })?;
+                // a function of the module would shadow the imported one, its
+                // mangled symbol not colliding with it
+                let defined = module.ast.declarations().any(|declaration| {
+                    matches!(declaration, Ast::Function(defined, _, _) if defined == name)
+                });
+                if defined {
+                    return Err(Diagnostic::error(
+                        diagnostic::DUPLICATE_DEFINITION,
+                        format!(
+                            "Function `{}` is defined in both `{}` and `{}`",
+                            name,
+                            imported.path.display(),
+                            module.path.display()
+                        ),
+                        None,
+                    ));
+                }
+                functions.insert(name.clone(), signature.clone());
+            }
+        }
+        if cache.is_some_and(|cache| cache.is_checked(&module.source, &functions)) {
+            continue;
+        }
+        let statement_spans = lines::statements(&module.ast)
+            .into_iter()
+            .map(|statement| statement as *const Ast)
+            .zip(module.spans.iter().copied())
+            .collect();
+        StaticTypeChecker::new(LinkedHashMap::new(), functions.clone(), None)
+            .with_statement_spans(statement_spans)
+            .check(&module.ast)
+            .map_err(|error| {
+                Diagnostic::error(error.code, error.message, error.span)
+                    .in_file(module.path.display().to_string())
+            })?;
+        if let Some(cache) = cache {
+            cache.set_checked(&module.source, &functions);
+        }
+    }
+    Ok(())
+}
+
+/// Joins the modules into a single program so that all of their functions
+/// and globals end up in one assembly output, where their names must be
+/// unique.
+pub fn link_modules(modules: Vec<Module>) -> Result<Ast, Diagnostic> {
+    let mut definitions: BTreeMap<String, PathBuf> = BTreeMap::new();
+    let mut statements = Vec::new();
+    for module in modules {
+        for function in module.ast.declarations() {
+            let (kind, name) = match function {
+                Ast::Function(name, _, _) => ("Function", name),
+                Ast::Var(name, _) => ("Global", name),
+                _ => continue,
+            };
+            if let Some(other) = definitions.insert(name.clone(), module.path.clone()) {
+                // the functions of namespaces have mangled names by now
+                let name = mangle::demangle(name).unwrap_or_else(|| name.clone());
+                let message = if other == module.path {
+                    format!(
+                        "{} `{}` is defined twice in `{}`",
+                        kind,
+                        name,
+                        module.path.display()
+                    )
+                } else {
+                    format!(
+                        "{} `{}` is defined in both `{}` and `{}`",
+                        kind,
+                        name,
+                        other.display(),
+                        module.path.display()
+                    )
+                };
+                return Err(Diagnostic::error(
+                    diagnostic::DUPLICATE_DEFINITION,
+                    message,
+                    None,
+                ));
+            }
+        }
+        match module.ast {
+            Ast::Block(module_statements) => statements.extend(module_statements),
+            statement => statements.push(statement),
+        }
+    }
+    Ok(Ast::Block(statements))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn reader(files: &[(&str, &str)]) -> impl Fn(&Path) -> io::Result<String> {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), String::from(*source)))
+            .collect();
+        move |path| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+        }
+    }
+
+    #[test]
+    fn resolving_imports() {
+        assert_eq!(
+            resolve_import(Path::new("src/main.ts"), "./lib"),
+            PathBuf::from("src/lib.ts")
+        );
+        assert_eq!(
+            resolve_import(Path::new("src/a/main.ts"), "../lib.ts"),
+            PathBuf::from("src/lib.ts")
+        );
+        assert_eq!(
+            resolve_import(Path::new("main.ts"), "./lib"),
+            PathBuf::from("lib.ts")
+        );
+    }
+
+    #[test]
+    fn modules_are_ordered_by_dependencies() {
+        let read = reader(&[
+            (
+                "a.ts",
+                "import { b } from \"./b\"; export function a() { return b(); }",
+            ),
+            ("b.ts", "export function b() { return 1; }"),
+        ]);
+        let main = "import { a } from \"./a\"; import { b } from \"./b\";".to_owned();
+        let modules = load_modules(Path::new("main.ts"), main, read).unwrap();
+        let paths: Vec<&Path> = modules.iter().map(|m| m.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            vec![Path::new("b.ts"), Path::new("a.ts"), Path::new("main.ts")]
+        );
+        assert!(check_modules(&modules, &LinkedHashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn import_cycle() {
+        let read = reader(&[("a.ts", "import { f } from \"./main\";")]);
+        let main = "import { g } from \"./a\";".to_owned();
+        assert_eq!(
+            load_modules(Path::new("main.ts"), main, read)
+                .unwrap_err()
+                .to_string(),
+            "error[E0012]: Import cycle main.ts -> a.ts -> main.ts"
+        );
+    }
+
+    #[test]
+    fn importing_private_function() {
+        let read = reader(&[("lib.ts", "function f() { return 1; }")]);
+        let main = "import { f } from \"./lib\";".to_owned();
+        let modules = load_modules(Path::new("main.ts"), main, read).unwrap();
+        assert_eq!(
+            check_modules(&modules, &LinkedHashMap::new())
+                .unwrap_err()
+                .to_string(),
+            "error[E0014]: main.ts: Module `lib.ts` has no exported function `f`"
+        );
+    }
+
+    #[test]
+    fn linking_duplicate_functions() {
+        let read = reader(&[("lib.ts", "export function f() { return 1; }")]);
+        let main = "import { f } from \"./lib\"; function f() { return 2; }".to_owned();
+        let modules = load_modules(Path::new("main.ts"), main, read).unwrap();
+        assert_eq!(
+            link_modules(modules).unwrap_err().to_string(),
+            "error[E0015]: Function `f` is defined in both `lib.ts` and `main.ts`"
+        );
+    }
+
+    #[test]
+    fn linking_duplicate_globals() {
+        let read = reader(&[(
+            "lib.ts",
+            "var count = 0; export function f() { return count; }",
+        )]);
+        let main = "import { f } from \"./lib\"; var count = 1;".to_owned();
+        let modules = load_modules(Path::new("main.ts"), main, read).unwrap();
+        assert_eq!(
+            link_modules(modules).unwrap_err().to_string(),
+            "error[E0015]: Global `count` is defined in both `lib.ts` and `main.ts`"
+        );
+    }
+
+    #[test]
+    fn linking_duplicates_within_a_module() {
+        let main = "function f() { return 1; } function f() { return 2; }".to_owned();
+        let modules = load_modules(Path::new("main.ts"), main, reader(&[])).unwrap();
+        assert_eq!(
+            link_modules(modules).unwrap_err().to_string(),
+            "error[E0015]: Function `f` is defined twice in `main.ts`"
+        );
+    }
+}
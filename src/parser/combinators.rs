@@ -1,3 +1,7 @@
+use std::cell::{Cell, OnceCell};
+use std::fmt;
+use std::rc::Rc;
+
 use regex::Regex;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -109,6 +113,24 @@ where
     }
 }
 
+/// Runs the parsers one after the other, keeping the value of the first, as
+/// binding it to `and` with a `constant` would. Unlike the `constant` it does
+/// not clone the value, which for a tree takes as much of the stack as the
+/// tree is deep.
+pub fn followed_by<'a, P1, P2, R1, R2>(parser1: P1, parser2: P2) -> impl Parser<'a, R1>
+where
+    P1: Parser<'a, R1>,
+    P2: Parser<'a, R2>,
+{
+    move |input: &'a str| {
+        let (next_input, result1) = parser1.parse(input)?;
+        match parser2.parse(next_input) {
+            Ok((next_input, _)) => Ok((next_input, result1)),
+            Err(_) => Err(next_input),
+        }
+    }
+}
+
 pub fn zero_or_more<'a, T>(parser: impl Parser<'a, T>) -> impl Parser<'a, Vec<T>> {
     move |input| {
         let mut items = Vec::new();
@@ -142,6 +164,115 @@ where
     }
 }
 
+/// A rule of the grammar which refers to itself. It is built once, its
+/// definition reaching it through `handle`, instead of being built again at
+/// every level of nesting, which keeps the frames of the stack small.
+pub struct Recursive<'a, T> {
+    parser: Rc<OnceCell<Box<dyn Parser<'a, T> + 'a>>>,
+}
+
+impl<T> fmt::Debug for Recursive<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recursive")
+            .field("defined", &self.parser.get().is_some())
+            .finish()
+    }
+}
+
+impl<'a, T: 'a> Default for Recursive<'a, T> {
+    fn default() -> Self {
+        Recursive::new()
+    }
+}
+
+impl<'a, T: 'a> Recursive<'a, T> {
+    pub fn new() -> Recursive<'a, T> {
+        Recursive {
+            parser: Rc::new(OnceCell::new()),
+        }
+    }
+
+    /// Runs the rule, once it is defined. The handle does not keep the rule
+    /// alive, so that the rule holding handles on itself is freed.
+    pub fn handle(&self) -> impl Parser<'a, T> + Clone + 'a {
+        let parser = Rc::downgrade(&self.parser);
+        move |input: &'a str| match parser.upgrade() {
+            Some(parser) => parser
+                .get()
+                .expect("recursive parser run before it is defined")
+                .parse(input),
+            None => Err(input),
+        }
+    }
+
+    /// Defines the rule, returning the parser which keeps it alive.
+    pub fn define(self, parser: impl Parser<'a, T> + 'a) -> impl Parser<'a, T> + Clone + 'a {
+        if self.parser.set(Box::new(parser)).is_err() {
+            unreachable!("a recursive parser is defined once");
+        }
+        let parser = self.parser;
+        move |input: &'a str| parser.get().unwrap().parse(input)
+    }
+}
+
+/// Spans as the lengths of the input left where they start and end, which
+/// unlike offsets need no knowledge of the whole source.
+pub type Spans = Vec<(usize, usize)>;
+
+/// Puts the span of what the parser consumed ahead of the spans it returns,
+/// those of the parts of it.
+pub fn spanned<'a, T>(parser: impl Parser<'a, (T, Spans)>) -> impl Parser<'a, (T, Spans)> {
+    move |input: &'a str| {
+        let (next_input, (value, parts)) = parser.parse(input)?;
+        let mut spans = Vec::with_capacity(parts.len() + 1);
+        spans.push((input.len(), next_input.len()));
+        spans.extend(parts);
+        Ok((next_input, (value, spans)))
+    }
+}
+
+thread_local! {
+    // how many `nested` parsers are running, at most how many may and the
+    // shortest rest of the input one was stopped at
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    static MAX_DEPTH: Cell<usize> = const { Cell::new(usize::MAX) };
+    static TOO_DEEP: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Counts the parser towards the nesting depth, failing without running it
+/// when it would nest deeper than `with_max_depth` allows. Each level of
+/// nesting takes a few frames of the stack.
+pub fn nested<'a, T>(parser: impl Parser<'a, T> + Clone) -> impl Parser<'a, T> + Clone {
+    move |input: &'a str| {
+        let depth = DEPTH.with(Cell::get);
+        if depth >= MAX_DEPTH.with(Cell::get) {
+            TOO_DEEP.with(|too_deep| {
+                too_deep.set(Some(
+                    too_deep
+                        .get()
+                        .map_or(input.len(), |rest| rest.min(input.len())),
+                ))
+            });
+            return Err(input);
+        }
+        DEPTH.with(|cell| cell.set(depth + 1));
+        let result = parser.parse(input);
+        DEPTH.with(|cell| cell.set(depth));
+        result
+    }
+}
+
+/// Runs the parsing with at most `max_depth` levels of `nested` parsers,
+/// returning the shortest rest of the input where the limit stopped one.
+pub fn with_max_depth<T>(max_depth: usize, parse: impl FnOnce() -> T) -> (T, Option<usize>) {
+    let previous = MAX_DEPTH.with(|cell| cell.replace(max_depth));
+    let outer = TOO_DEEP.with(|cell| cell.replace(None));
+    let result = parse();
+    MAX_DEPTH.with(|cell| cell.set(previous));
+    let too_deep = TOO_DEEP.with(|cell| cell.replace(outer));
+    (result, too_deep)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -153,6 +284,29 @@ mod tests {
         assert_eq!(match_regex(input, &regex), Ok(("45", String::from("123"))));
     }
 
+    #[test]
+    fn nesting_depth() {
+        fn parens<'a>() -> impl Parser<'a, usize> {
+            or_(
+                and(
+                    regex(Regex::new(r"^\(").unwrap()),
+                    bind(nested(|input| parens().parse(input)), |depth| {
+                        and(regex(Regex::new(r"^\)").unwrap()), constant(depth + 1))
+                    }),
+                ),
+                constant(0),
+            )
+        }
+        assert_eq!(
+            with_max_depth(2, || parens().parse("(())")),
+            (Ok(("", 2)), None)
+        );
+        assert_eq!(
+            with_max_depth(2, || parens().parse("((()))")),
+            (Ok(("((()))", 0)), Some(3))
+        );
+    }
+
     #[test]
     fn constant_parser() {
         let input = "12345";
@@ -222,7 +376,7 @@ mod tests {
                 "END",
                 vec!["1", "1", "1", "1"]
                     .into_iter()
-                    .map(|s| String::from(s))
+                    .map(String::from)
                     .collect()
             ))
         );
@@ -1,18 +1,28 @@
+use std::sync::OnceLock;
+
 use regex::Regex;
 
 use crate::ast::Ast;
 use crate::parser::combinators as cmb;
 use crate::parser::combinators::{OrValue, Parser};
+use crate::phases::codegen::{CONCAT, SLICE};
 use crate::types::Type;
 
 pub type Comment = String;
 pub type Whitespace = String;
 
+// The parsers of the tokens are built again by the ones binding to what
+// precedes them, so the patterns are only compiled once.
 macro_rules! token_parser {
     ($name:ident, $pattern:expr) => {
         #[allow(clippy::trivial_regex)]
         pub fn $name<'a>() -> impl Parser<'a, String> {
-            make_token_parser(Regex::new($pattern).unwrap())
+            static PATTERN: OnceLock<Regex> = OnceLock::new();
+            make_token_parser(
+                PATTERN
+                    .get_or_init(|| Regex::new($pattern).unwrap())
+                    .clone(),
+            )
         }
     };
 }
@@ -24,21 +34,34 @@ pub fn make_token_parser<'a>(pattern: Regex) -> impl Parser<'a, String> {
     })
 }
 
-token_parser! {make_bool_keyword_parser, "^boolean"}
-token_parser! {make_number_keyword_parser, "^number"}
-token_parser! {make_void_keyword_parser, "^void"}
-token_parser! {make_array_keyword_parser, "^array"}
-token_parser! {make_function_parser, "^function"}
-token_parser! {make_if_parser, "^if"}
-token_parser! {make_true_parser, "^true"}
-token_parser! {make_false_parser, "^false"}
-token_parser! {make_undefined_parser, "^undefined"}
-token_parser! {make_null_parser, "^null"}
-token_parser! {make_length_parser, "^length"}
-token_parser! {make_else_parser, "^else"}
-token_parser! {make_return_parser, "^return"}
-token_parser! {make_while_parser, "^while"}
-token_parser! {make_var_parser, "^var"}
+// Keywords end at a word boundary, so that names like `iffy` or `nullable`
+// are not read as the keyword followed by the rest of the name.
+token_parser! {make_bool_keyword_parser, r"^boolean\b"}
+token_parser! {make_number_keyword_parser, r"^number\b"}
+token_parser! {make_float_keyword_parser, r"^float\b"}
+token_parser! {make_string_keyword_parser, r"^string\b"}
+token_parser! {make_void_keyword_parser, r"^void\b"}
+token_parser! {make_array_keyword_parser, r"^array\b"}
+token_parser! {make_function_parser, r"^function\b"}
+token_parser! {make_if_parser, r"^if\b"}
+token_parser! {make_true_parser, r"^true\b"}
+token_parser! {make_false_parser, r"^false\b"}
+token_parser! {make_undefined_parser, r"^undefined\b"}
+token_parser! {make_null_parser, r"^null\b"}
+token_parser! {make_length_parser, r"^length\b"}
+token_parser! {make_else_parser, r"^else\b"}
+token_parser! {make_return_parser, r"^return\b"}
+token_parser! {make_delete_parser, r"^delete\b"}
+token_parser! {make_while_parser, r"^while\b"}
+token_parser! {make_var_parser, r"^var\b"}
+token_parser! {make_const_parser, r"^const\b"}
+token_parser! {make_import_parser, r"^import\b"}
+token_parser! {make_export_parser, r"^export\b"}
+token_parser! {make_extern_parser, r"^extern\b"}
+token_parser! {make_static_assert_parser, r"^static_assert\b"}
+token_parser! {make_namespace_parser, r"^namespace\b"}
+token_parser! {make_doc_comment_parser, r"^///.*"}
+token_parser! {make_from_parser, r"^from\b"}
 token_parser! {make_assign_parser, "^="}
 token_parser! {make_comma_parser, "^,"}
 token_parser! {make_colon_parser, "^:"}
@@ -58,17 +81,31 @@ token_parser! {make_plus_parser, r"^\+"}
 token_parser! {make_minus_parser, r"^\-"}
 token_parser! {make_star_parser, r"^\*"}
 token_parser! {make_slash_parser, r"^/"}
+token_parser! {make_ellipsis_parser, r"^\.\.\."}
 token_parser! {make_id_string_parser, r"^[a-zA-Z_][a-zA-Z0-9_]*"}
-
-pub fn make_expression_parser<'a>() -> impl Parser<'a, Ast> {
-    |input| make_comparison_parser().parse(input)
+// qualified_name <- ID (DOT ID)?, a single token to keep calls shallow
+token_parser! {make_qualified_name_parser, r"^[a-zA-Z_][a-zA-Z0-9_]*(\.[a-zA-Z_][a-zA-Z0-9_]*)?"}
+token_parser! {make_string_parser, r#"^"[^"\n]*""#}
+
+// The parser is built once, the expressions nested in others are parsed
+// through a handle on it, each counting as a level of nesting.
+pub fn make_expression_parser<'a>() -> impl Parser<'a, Ast> + Clone {
+    let rule = cmb::Recursive::new();
+    let parser = make_comparison_parser(cmb::nested(rule.handle()));
+    cmb::nested(rule.define(parser))
 }
 
+// Every token builds it again after matching, so the patterns are only
+// compiled once too.
 pub fn make_ignored_parser<'a>() -> impl Parser<'a, Vec<OrValue<Whitespace, Comment>>> {
-    let whitespace_regex = Regex::new(r"^[ \n\r\t]+").unwrap();
-    let whitespace_parser = cmb::regex(whitespace_regex);
-    let comment_regex = Regex::new(r"^//.*").unwrap();
-    let comment_parser = cmb::regex(comment_regex);
+    static WHITESPACE: OnceLock<Regex> = OnceLock::new();
+    static COMMENT: OnceLock<Regex> = OnceLock::new();
+    let whitespace_regex = WHITESPACE.get_or_init(|| Regex::new(r"^[ \n\r\t]+").unwrap());
+    let whitespace_parser = cmb::regex(whitespace_regex.clone());
+    // `///` starts a doc comment, which is part of the grammar
+    let comment_regex =
+        COMMENT.get_or_init(|| Regex::new(r"^(//([^/\n].*)?(?m:$)|////.*)").unwrap());
+    let comment_parser = cmb::regex(comment_regex.clone());
     cmb::zero_or_more(cmb::or(whitespace_parser, comment_parser))
 }
 
@@ -91,10 +128,12 @@ pub fn make_array_type_parser<'a>() -> impl Parser<'a, Type> {
     }
 }
 
-// type <- VOID | BOOLEAN | NUMBER | array_type
+// type <- VOID | BOOLEAN | NUMBER | FLOAT | STRING | array_type
 pub fn make_type_parser<'a>() -> impl Parser<'a, Type> {
     let parser = cmb::or_(make_void_keyword_parser(), make_bool_keyword_parser());
     let parser = cmb::or_(parser, make_number_keyword_parser());
+    let parser = cmb::or_(parser, make_float_keyword_parser());
+    let parser = cmb::or_(parser, make_string_keyword_parser());
     let parser = cmb::or(parser, make_array_type_parser());
     cmb::bind(parser, move |or| match or {
         OrValue::Lhs(value) => {
@@ -102,6 +141,8 @@ pub fn make_type_parser<'a>() -> impl Parser<'a, Type> {
                 "void" => Type::Void,
                 "boolean" => Type::Boolean,
                 "number" => Type::Number,
+                "float" => Type::Float,
+                "string" => Type::String,
                 _ => unreachable!(),
             };
             cmb::constant(type_)
@@ -110,14 +151,31 @@ pub fn make_type_parser<'a>() -> impl Parser<'a, Type> {
     })
 }
 
+token_parser! {make_digits_parser, "^[0-9]+"}
+token_parser! {make_float_digits_parser, r"^[0-9]+\.[0-9]+"}
+
+// Numbers which do not fit in an `i32` are not numbers, parsing fails at
+// their first digit.
 pub fn make_number_parser<'a>() -> impl Parser<'a, Ast> {
-    cmb::map(
-        make_token_parser(Regex::new("^[0-9]+").unwrap()),
-        |text: String| match text.parse::<i32>() {
-            Ok(value) => Ast::Number(value),
+    let digits_parser = make_digits_parser();
+    move |input| {
+        let (rest, text) = digits_parser.parse(input)?;
+        match text.parse::<i32>() {
+            Ok(value) => Ok((rest, Ast::Number(value))),
+            Err(_) => Err(input),
+        }
+    }
+}
+
+// Floats are written with digits on both sides of the point, `1.` and `.5`
+// are not floats.
+pub fn make_float_parser<'a>() -> impl Parser<'a, Ast> {
+    cmb::map(make_float_digits_parser(), |text: String| {
+        match text.parse::<f32>() {
+            Ok(value) => Ast::Float(value),
             Err(_) => unreachable!(),
-        },
-    )
+        }
+    })
 }
 
 pub fn make_bool_parser<'a>() -> impl Parser<'a, Ast> {
@@ -141,193 +199,248 @@ pub fn make_null_parser_<'a>() -> impl Parser<'a, Ast> {
     cmb::and(make_null_parser(), cmb::constant(Ast::Null))
 }
 
+// The characters between the quotes are taken as they are, there are no
+// escape sequences.
+pub fn make_string_literal_parser<'a>() -> impl Parser<'a, Ast> {
+    cmb::map(make_string_parser(), |text: String| {
+        Ast::String(String::from(&text[1..text.len() - 1]))
+    })
+}
+
 pub fn make_identifier_parser<'a>() -> impl Parser<'a, Ast> {
     cmb::map(make_id_string_parser(), Ast::Identifier)
 }
 
-// array_literal <- LEFT_BRACKET args RIGHT_BRACKET
-pub fn make_array_literal_parser<'a>() -> impl Parser<'a, Ast> {
-    cmb::bind(
-        cmb::and(make_left_bracket_parser(), make_args_parser()),
-        |elements| {
-            cmb::and(
+// element <- ELLIPSIS? expression
+pub fn make_element_parser<'a>(
+    expression: impl Parser<'a, Ast> + Clone,
+) -> impl Parser<'a, (bool, Ast)> {
+    cmb::bind(cmb::maybe(make_ellipsis_parser()), move |spread| {
+        cmb::map(expression.clone(), move |element| {
+            (spread.is_some(), element)
+        })
+    })
+}
+
+// elements <- (element (COMMA element)*)?
+pub fn make_elements_parser<'a>(
+    expression: impl Parser<'a, Ast> + Clone,
+) -> impl Parser<'a, Vec<(bool, Ast)>> {
+    let first_parser = cmb::maybe(make_element_parser(expression.clone()));
+    let rest_parser = cmb::zero_or_more(cmb::and(
+        make_comma_parser(),
+        make_element_parser(expression),
+    ));
+    move |input: &'a str| {
+        let (next_input, first) = first_parser.parse(input)?;
+        let (next_input, mut elements) = rest_parser.parse(next_input)?;
+        if let Some(first) = first {
+            elements.insert(0, first);
+        }
+        Ok((next_input, elements))
+    }
+}
+
+// Joins the arrays spread into the literal and the runs of elements between
+// them with `concat`, a literal made of a single spread array copies it with
+// `slice`.
+fn spread_array(elements: Vec<(bool, Ast)>) -> Ast {
+    if elements.iter().all(|(spread, _)| !spread) {
+        return Ast::ArrayLiteral(elements.into_iter().map(|(_, x)| x).collect());
+    }
+    let mut parts: Vec<Ast> = Vec::new();
+    for (spread, element) in elements {
+        match parts.last_mut() {
+            Some(Ast::ArrayLiteral(run)) if !spread => run.push(element),
+            _ if spread => parts.push(element),
+            _ => parts.push(Ast::ArrayLiteral(vec![element])),
+        }
+    }
+    let mut parts = parts.into_iter();
+    let first = parts.next().unwrap();
+    match parts.next() {
+        None => Ast::Call(
+            String::from(SLICE),
+            vec![first, Ast::Number(0), Ast::Number(i32::MAX)],
+        ),
+        Some(second) => parts.fold(
+            Ast::Call(String::from(CONCAT), vec![first, second]),
+            |array, part| Ast::Call(String::from(CONCAT), vec![array, part]),
+        ),
+    }
+}
+
+// array_literal <- LEFT_BRACKET elements RIGHT_BRACKET
+pub fn make_array_literal_parser<'a>(
+    expression: impl Parser<'a, Ast> + Clone,
+) -> impl Parser<'a, Ast> {
+    cmb::map(
+        cmb::and(
+            make_left_bracket_parser(),
+            cmb::followed_by(
+                make_elements_parser(expression),
                 make_right_bracket_parser(),
-                cmb::constant(Ast::ArrayLiteral(elements)),
-            )
-        },
+            ),
+        ),
+        spread_array,
     )
 }
 
 // array_lookup <- ID LEFT_BRACKET expression RIGHT_BRACKET
-pub fn make_array_lookup_parser<'a>() -> impl Parser<'a, Ast> {
+pub fn make_array_lookup_parser<'a>(
+    expression: impl Parser<'a, Ast> + Clone,
+) -> impl Parser<'a, Ast> {
     cmb::bind(make_identifier_parser(), move |id| {
-        cmb::bind(
-            cmb::and(make_left_bracket_parser(), make_expression_parser()),
-            move |index| {
-                cmb::and(
-                    make_right_bracket_parser(),
-                    cmb::constant(Ast::ArrayLookup(Box::new(id.clone()), Box::new(index))),
-                )
-            },
+        cmb::map(
+            cmb::and(
+                make_left_bracket_parser(),
+                cmb::followed_by(expression.clone(), make_right_bracket_parser()),
+            ),
+            move |index| Ast::ArrayLookup(Box::new(id.clone()), Box::new(index)),
         )
     })
 }
 
 // array_length <- length LEFT_PAREN expression RIGH_PAREN
-pub fn make_array_length_parser<'a>() -> impl Parser<'a, Ast> {
+pub fn make_array_length_parser<'a>(expression: impl Parser<'a, Ast>) -> impl Parser<'a, Ast> {
     let parser = cmb::and(make_length_parser(), make_left_paren_parser());
-    let parser = cmb::and(parser, make_expression_parser());
-    cmb::bind(parser, |array| {
-        cmb::and(
-            make_right_paren_parser(),
-            cmb::constant(Ast::ArrayLength(Box::new(array))),
-        )
-    })
+    let parser = cmb::and(
+        parser,
+        cmb::followed_by(expression, make_right_paren_parser()),
+    );
+    cmb::map(parser, |array| Ast::ArrayLength(Box::new(array)))
 }
 
-pub fn make_args_parser<'a>() -> impl Parser<'a, Vec<Ast>> {
-    cmb::bind(cmb::maybe(make_expression_parser()), |arg| {
-        cmb::bind(
-            cmb::zero_or_more(cmb::and(make_comma_parser(), make_expression_parser())),
-            move |ref mut args| {
-                cmb::constant({
-                    if let Some(ref first_arg) = arg {
-                        let mut all_args = Vec::with_capacity(1 + args.len());
-                        all_args.push(first_arg.clone());
-                        all_args.append(args);
-                        all_args
-                    } else {
-                        Vec::new()
-                    }
-                })
-            },
-        )
-    })
+pub fn make_args_parser<'a>(expression: impl Parser<'a, Ast> + Clone) -> impl Parser<'a, Vec<Ast>> {
+    let first_parser = cmb::maybe(expression.clone());
+    let rest_parser = cmb::zero_or_more(cmb::and(make_comma_parser(), expression));
+    move |input: &'a str| {
+        let (next_input, first) = first_parser.parse(input)?;
+        match first {
+            Some(first) => {
+                let (next_input, mut args) = rest_parser.parse(next_input)?;
+                args.insert(0, first);
+                Ok((next_input, args))
+            }
+            None => Ok((next_input, Vec::new())),
+        }
+    }
 }
 
-pub fn make_call_parser<'a>() -> impl Parser<'a, Ast> {
-    cmb::bind(make_id_string_parser(), move |name| {
-        cmb::bind(
-            cmb::and(make_left_paren_parser(), make_args_parser()),
-            move |args| {
-                cmb::and(
+// call <- qualified_name LEFT_PAREN args RIGHT_PAREN
+pub fn make_call_parser<'a>(expression: impl Parser<'a, Ast> + Clone) -> impl Parser<'a, Ast> {
+    cmb::bind(make_qualified_name_parser(), move |name| {
+        cmb::map(
+            cmb::and(
+                make_left_paren_parser(),
+                cmb::followed_by(
+                    make_args_parser(expression.clone()),
                     make_right_paren_parser(),
-                    cmb::constant(Ast::Call(name.clone(), args)),
-                )
-            },
+                ),
+            ),
+            move |args| Ast::Call(name.clone(), args),
         )
     })
 }
 
-// scalar <- null | undefined | bool | ID, NUMBER
+// scalar <- null | undefined | bool | ID | FLOAT | NUMBER | STRING
 pub fn make_scalar_parser<'a>() -> impl Parser<'a, Ast> {
-    |input| {
-        let parser = cmb::or_(make_null_parser_(), make_undefined_parser_());
-        let parser = cmb::or_(parser, make_bool_parser());
-        let parser = cmb::or_(parser, make_identifier_parser());
-        let parser = cmb::or_(parser, make_number_parser());
-        parser.parse(input)
-    }
+    let parser = cmb::or_(make_null_parser_(), make_undefined_parser_());
+    let parser = cmb::or_(parser, make_bool_parser());
+    let parser = cmb::or_(parser, make_identifier_parser());
+    let parser = cmb::or_(parser, make_float_parser());
+    let parser = cmb::or_(parser, make_number_parser());
+    cmb::or_(parser, make_string_literal_parser())
 }
 
 // atom <- array_length | array_literal | array_lookup | call | scalar | LEFT_PAREN expression RIGHT_PAREN
-pub fn make_atom_parser<'a>() -> impl Parser<'a, Ast> {
-    let parser = cmb::or_(make_array_length_parser(), make_array_literal_parser());
-    let parser = cmb::or_(parser, make_array_lookup_parser());
-    let parser = cmb::or_(parser, make_call_parser());
+pub fn make_atom_parser<'a>(expression: impl Parser<'a, Ast> + Clone) -> impl Parser<'a, Ast> {
+    let parser = cmb::or_(
+        make_array_length_parser(expression.clone()),
+        make_array_literal_parser(expression.clone()),
+    );
+    let parser = cmb::or_(parser, make_array_lookup_parser(expression.clone()));
+    let parser = cmb::or_(parser, make_call_parser(expression.clone()));
     let parser = cmb::or_(parser, make_scalar_parser());
     let expr_in_parens_parser = cmb::and(
         make_left_paren_parser(),
-        cmb::bind(make_expression_parser(), |e| {
-            cmb::and(make_right_paren_parser(), cmb::constant(e))
-        }),
+        cmb::followed_by(expression, make_right_paren_parser()),
     );
     cmb::or_(parser, expr_in_parens_parser)
 }
 
 // unary <- NOT? atom
-pub fn make_unary_parser<'a>() -> impl Parser<'a, Ast> {
-    cmb::bind(cmb::maybe(make_not_parser()), move |not| {
-        cmb::map(make_atom_parser(), move |term| {
-            if not.is_some() {
-                Ast::Not(Box::new(term))
-            } else {
-                term
+pub fn make_unary_parser<'a>(expression: impl Parser<'a, Ast> + Clone) -> impl Parser<'a, Ast> {
+    let not_parser = cmb::maybe(make_not_parser());
+    let atom_parser = make_atom_parser(expression);
+    move |input: &'a str| {
+        let (next_input, not) = not_parser.parse(input)?;
+        let (next_input, term) = atom_parser.parse(next_input)?;
+        if not.is_some() {
+            Ok((next_input, Ast::Not(Box::new(term))))
+        } else {
+            Ok((next_input, term))
+        }
+    }
+}
+
+// operand (operator operand)*, the operations applied from the left
+fn make_operations_parser<'a>(
+    operand: impl Parser<'a, Ast>,
+    operator: impl Parser<'a, OrValue<String, String>>,
+    apply: fn(OrValue<String, String>, Ast, Ast) -> Ast,
+) -> impl Parser<'a, Ast> {
+    move |input: &'a str| {
+        let (mut input, mut acc) = operand.parse(input)?;
+        while let Ok((next_input, operator)) = operator.parse(input) {
+            match operand.parse(next_input) {
+                Ok((next_input, term)) => {
+                    acc = apply(operator, acc, term);
+                    input = next_input;
+                }
+                Err(_) => break,
             }
-        })
-    })
+        }
+        Ok((input, acc))
+    }
 }
 
 // product <- unary ((STAR / SLASH) unary)*
-pub fn make_product_parser<'a>() -> impl Parser<'a, Ast> {
-    cmb::bind(make_unary_parser(), move |first| {
-        cmb::map(
-            cmb::zero_or_more(cmb::bind(
-                cmb::or(make_star_parser(), make_slash_parser()),
-                move |operator| {
-                    cmb::bind(make_unary_parser(), move |term| {
-                        cmb::constant((operator.clone(), term))
-                    })
-                },
-            )),
-            move |operator_terms: Vec<(OrValue<String, String>, Ast)>| {
-                operator_terms
-                    .into_iter()
-                    .fold(first.clone(), move |acc, (operator, term)| match operator {
-                        OrValue::Lhs(_star) => Ast::Multiplication(Box::new(acc), Box::new(term)),
-                        OrValue::Rhs(_slash) => Ast::Division(Box::new(acc), Box::new(term)),
-                    })
-            },
-        )
-    })
+pub fn make_product_parser<'a>(expression: impl Parser<'a, Ast> + Clone) -> impl Parser<'a, Ast> {
+    make_operations_parser(
+        make_unary_parser(expression),
+        cmb::or(make_star_parser(), make_slash_parser()),
+        |operator, acc, term| match operator {
+            OrValue::Lhs(_star) => Ast::Multiplication(Box::new(acc), Box::new(term)),
+            OrValue::Rhs(_slash) => Ast::Division(Box::new(acc), Box::new(term)),
+        },
+    )
 }
 
 // sum <- product ((PLUS / MINUS) product)*
-pub fn make_sum_parser<'a>() -> impl Parser<'a, Ast> {
-    cmb::bind(make_product_parser(), move |first| {
-        cmb::map(
-            cmb::zero_or_more(cmb::bind(
-                cmb::or(make_plus_parser(), make_minus_parser()),
-                move |operator| {
-                    cmb::bind(make_product_parser(), move |term| {
-                        cmb::constant((operator.clone(), term))
-                    })
-                },
-            )),
-            move |operator_terms: Vec<(OrValue<String, String>, Ast)>| {
-                operator_terms
-                    .into_iter()
-                    .fold(first.clone(), move |acc, (operator, term)| match operator {
-                        OrValue::Lhs(_plus) => Ast::Addition(Box::new(acc), Box::new(term)),
-                        OrValue::Rhs(_minus) => Ast::Subtraction(Box::new(acc), Box::new(term)),
-                    })
-            },
-        )
-    })
+pub fn make_sum_parser<'a>(expression: impl Parser<'a, Ast> + Clone) -> impl Parser<'a, Ast> {
+    make_operations_parser(
+        make_product_parser(expression),
+        cmb::or(make_plus_parser(), make_minus_parser()),
+        |operator, acc, term| match operator {
+            OrValue::Lhs(_plus) => Ast::Addition(Box::new(acc), Box::new(term)),
+            OrValue::Rhs(_minus) => Ast::Subtraction(Box::new(acc), Box::new(term)),
+        },
+    )
 }
 
 // comparison <- sum ((EQUAL / NOT_EQUAL) sum)*
-pub fn make_comparison_parser<'a>() -> impl Parser<'a, Ast> {
-    cmb::bind(make_sum_parser(), move |first| {
-        cmb::map(
-            cmb::zero_or_more(cmb::bind(
-                cmb::or(make_equal_parser(), make_not_equal_parser()),
-                move |operator| {
-                    cmb::bind(make_sum_parser(), move |term| {
-                        cmb::constant((operator.clone(), term))
-                    })
-                },
-            )),
-            move |operator_terms: Vec<(OrValue<String, String>, Ast)>| {
-                operator_terms
-                    .into_iter()
-                    .fold(first.clone(), move |acc, (operator, term)| match operator {
-                        OrValue::Lhs(_equal) => Ast::Equal(Box::new(acc), Box::new(term)),
-                        OrValue::Rhs(_not_equal) => Ast::NotEqual(Box::new(acc), Box::new(term)),
-                    })
-            },
-        )
-    })
+pub fn make_comparison_parser<'a>(
+    expression: impl Parser<'a, Ast> + Clone,
+) -> impl Parser<'a, Ast> {
+    make_operations_parser(
+        make_sum_parser(expression),
+        cmb::or(make_equal_parser(), make_not_equal_parser()),
+        |operator, acc, term| match operator {
+            OrValue::Lhs(_equal) => Ast::Equal(Box::new(acc), Box::new(term)),
+            OrValue::Rhs(_not_equal) => Ast::NotEqual(Box::new(acc), Box::new(term)),
+        },
+    )
 }
 
 #[cfg(test)]
@@ -365,6 +478,35 @@ mod tests {
         assert_eq!(parsed, Ast::Number(123));
     }
 
+    #[test]
+    fn number_out_of_range() {
+        let parser = make_number_parser();
+        assert_eq!(parser.parse("2147483647"), Ok(("", Ast::Number(i32::MAX))));
+        assert_eq!(parser.parse("2147483648;"), Err("2147483648;"));
+    }
+
+    #[test]
+    fn keyword_prefixed_names() {
+        let parser = make_scalar_parser();
+        assert_eq!(
+            parser.parse("nullable"),
+            Ok(("", Ast::Identifier(String::from("nullable"))))
+        );
+        assert_eq!(
+            parser.parse("true_"),
+            Ok(("", Ast::Identifier(String::from("true_"))))
+        );
+        assert_eq!(parser.parse("null;"), Ok((";", Ast::Null)));
+    }
+
+    #[test]
+    fn float_parser() {
+        let parser = make_scalar_parser();
+        assert_eq!(parser.parse("1.5 //xx"), Ok(("", Ast::Float(1.5))));
+        assert_eq!(parser.parse("12"), Ok(("", Ast::Number(12))));
+        assert_eq!(make_type_parser().parse("float"), Ok(("", Type::Float)));
+    }
+
     #[test]
     fn bool_parser() {
         let true_input = "true  //xx";
@@ -403,7 +545,7 @@ mod tests {
     #[test]
     fn array_literal_parser() {
         let input = "[1,2]  //xx";
-        let parser = make_array_literal_parser();
+        let parser = make_array_literal_parser(make_expression_parser());
         let next_input = parser.parse(input).unwrap().0;
         let parsed = parser.parse(input).unwrap().1;
         assert_eq!(next_input, "");
@@ -415,7 +557,7 @@ mod tests {
     #[test]
     fn array_lookup_parser() {
         let input = "x[1]  //xx";
-        let parser = make_array_lookup_parser();
+        let parser = make_array_lookup_parser(make_expression_parser());
         let next_input = parser.parse(input).unwrap().0;
         let parsed = parser.parse(input).unwrap().1;
         assert_eq!(next_input, "");
@@ -431,7 +573,7 @@ mod tests {
     #[test]
     fn array_length_parser() {
         let input = "length(x)  //xx";
-        let parser = make_array_length_parser();
+        let parser = make_array_length_parser(make_expression_parser());
         let next_input = parser.parse(input).unwrap().0;
         let parsed = parser.parse(input).unwrap().1;
         assert_eq!(next_input, "");
@@ -444,7 +586,7 @@ mod tests {
     #[test]
     fn args_parser() {
         let input = "arg1, arg2, arg3  //xx";
-        let parser = make_args_parser();
+        let parser = make_args_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -460,7 +602,7 @@ mod tests {
     #[test]
     fn call_parser_with_args() {
         let input = "f(arg1, arg2, arg3)  //xx";
-        let parser = make_call_parser();
+        let parser = make_call_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         let (name, args) = match parsed {
             Ast::Call(name, args) => (name, args),
@@ -492,10 +634,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn spread_parser() {
+        let parser = make_array_literal_parser(make_expression_parser());
+        let (next_input, parsed) = parser.parse("[0, ...xs, 8, 9, ...f()] //xx").unwrap();
+        assert_eq!(next_input, "");
+        let concat = |lhs, rhs| Ast::Call(String::from("concat"), vec![lhs, rhs]);
+        assert_eq!(
+            parsed,
+            concat(
+                concat(
+                    concat(
+                        Ast::ArrayLiteral(vec![Ast::Number(0)]),
+                        Ast::Identifier(String::from("xs"))
+                    ),
+                    Ast::ArrayLiteral(vec![Ast::Number(8), Ast::Number(9)])
+                ),
+                Ast::Call(String::from("f"), vec![])
+            )
+        );
+        let (_, parsed) = parser.parse("[...xs]").unwrap();
+        assert_eq!(
+            parsed,
+            Ast::Call(
+                String::from("slice"),
+                vec![
+                    Ast::Identifier(String::from("xs")),
+                    Ast::Number(0),
+                    Ast::Number(i32::MAX)
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn string_parser() {
+        let parser = make_atom_parser(make_expression_parser());
+        let (next_input, parsed) = parser.parse("\"a // b\" //xx").unwrap();
+        assert_eq!(next_input, "");
+        assert_eq!(parsed, Ast::String(String::from("a // b")));
+        let (_, parsed) = make_type_parser().parse("array<string>").unwrap();
+        assert_eq!(
+            parsed,
+            Type::Array {
+                element_type: Box::new(Type::String)
+            }
+        );
+    }
+
     #[test]
     fn call_parser_without_args() {
         let input = "f()  //xx";
-        let parser = make_call_parser();
+        let parser = make_call_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         let (name, args) = match parsed {
             Ast::Call(name, args) => (name, args),
@@ -509,7 +699,7 @@ mod tests {
     #[test]
     fn atom_parser_for_call() {
         let input = "f()  //xx";
-        let parser = make_atom_parser();
+        let parser = make_atom_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         let (name, args) = match parsed {
             Ast::Call(name, args) => (name, args),
@@ -523,7 +713,7 @@ mod tests {
     #[test]
     fn atom_parser_for_id() {
         let input = "identifier //xx";
-        let parser = make_atom_parser();
+        let parser = make_atom_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(parsed, Ast::Identifier(String::from("identifier")));
@@ -532,7 +722,7 @@ mod tests {
     #[test]
     fn atom_parser_for_number() {
         let input = "123 //xx";
-        let parser = make_atom_parser();
+        let parser = make_atom_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(parsed, Ast::Number(123));
@@ -541,7 +731,7 @@ mod tests {
     #[test]
     fn atom_parser_for_array_literal() {
         let input = "[1,2] //xx";
-        let parser = make_atom_parser();
+        let parser = make_atom_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -553,7 +743,7 @@ mod tests {
     #[test]
     fn atom_parser_for_array_lookup() {
         let input = "x[1] //xx";
-        let parser = make_atom_parser();
+        let parser = make_atom_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -568,7 +758,7 @@ mod tests {
     #[test]
     fn unary_parser() {
         let input = "!id //xx";
-        let parser = make_unary_parser();
+        let parser = make_unary_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -580,7 +770,7 @@ mod tests {
     #[test]
     fn product_parser() {
         let input = "1 * 2 / 3 //xx";
-        let parser = make_product_parser();
+        let parser = make_product_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -598,7 +788,7 @@ mod tests {
     #[test]
     fn sum_parser() {
         let input = "1 + 2 - 3 //xx";
-        let parser = make_sum_parser();
+        let parser = make_sum_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -616,7 +806,7 @@ mod tests {
     #[test]
     fn comparison_parser() {
         let input = "1 == 2 != 3 //xx";
-        let parser = make_comparison_parser();
+        let parser = make_comparison_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -634,7 +824,7 @@ mod tests {
     #[test]
     fn expression_parser() {
         let input = "f(1 * 2) //xx";
-        let parser = make_comparison_parser();
+        let parser = make_comparison_parser(make_expression_parser());
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -3,22 +3,238 @@ pub mod combinators;
 mod expression;
 mod statement;
 
+use std::fmt;
+use std::panic;
+use std::thread;
+
 use super::ast::Ast;
+use crate::format;
+use crate::lexer::Lexer;
 use crate::parser::combinators as cmb;
 use crate::parser::expression as exp;
 use crate::parser::statement as stmt;
+use crate::span::{Location, Span};
+use crate::types::Type;
 use combinators::Parser;
 
-pub fn make_full_parser<'a>() -> impl Parser<'a, Ast> {
+/// How the source is parsed, handed to the parsers of the statements which
+/// depend on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// The type of the parameters and return types written without a type
+    /// annotation.
+    pub implicit_type: Type,
+    /// How deep expressions and statements can nest, `MAX_DEPTH` by default.
+    pub max_depth: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            implicit_type: Type::Number,
+            max_depth: MAX_DEPTH,
+        }
+    }
+}
+
+pub fn make_full_parser<'a>(options: &'a ParseOptions) -> impl Parser<'a, Ast> {
+    cmb::map(make_spanned_parser(options), |(program, _)| program)
+}
+
+// The program and the spans of its statements, see `cmb::Spans`.
+fn make_spanned_parser<'a>(options: &'a ParseOptions) -> impl Parser<'a, (Ast, cmb::Spans)> {
     cmb::map(
         cmb::and(
             exp::make_ignored_parser(),
-            cmb::zero_or_more(stmt::make_statement_parser()),
+            stmt::make_top_level_statements_parser(options),
         ),
-        Ast::Block,
+        |(statements, spans)| (Ast::Block(statements), spans),
+    )
+}
+
+/// How deep expressions and statements can nest by default. A statement
+/// nested in the block of an `if` or a `while` is a single level.
+pub const MAX_DEPTH: usize = 100;
+
+/// The stack the parser is given for each level of nesting, twice what a
+/// level of nested `if` blocks takes in a debug build, and for the rest.
+const STACK_PER_LEVEL: usize = 32 << 10;
+const BASE_STACK: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedInput,
+    /// Expressions or statements nested deeper than the limit.
+    TooDeep,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub offset: usize,
+    pub location: Location,
+}
+
+impl ParseError {
+    /// Where the parser stopped, as an empty span.
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.offset)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::UnexpectedInput => write!(f, "Unexpected input at {}", self.location),
+            ParseErrorKind::TooDeep => write!(f, "Nesting too deep at {}", self.location),
+        }
+    }
+}
+
+/// Parses the whole source, failing unless all of the input is consumed.
+pub fn parse_program(source: &str) -> Result<Ast, ParseError> {
+    parse_program_with_options(source, &ParseOptions::default())
+}
+
+/// Like `parse_program`, parsing as the options say.
+pub fn parse_program_with_options(source: &str, options: &ParseOptions) -> Result<Ast, ParseError> {
+    with_stack_for(options.max_depth, || {
+        parse_to_completion_with_max_depth(make_full_parser(options), source, options.max_depth)
+    })
+}
+
+/// Like `parse_program_with_options`, along with the spans of the statements
+/// in the order of `lines::statements`. A statement spans from its first
+/// token to its last one, the variables declared together span their whole
+/// statement.
+pub fn parse_program_with_spans(
+    source: &str,
+    options: &ParseOptions,
+) -> Result<(Ast, Vec<Span>), ParseError> {
+    let (program, spans) = with_stack_for(options.max_depth, || {
+        parse_to_completion_with_max_depth(make_spanned_parser(options), source, options.max_depth)
+    })?;
+    // the parsers of the tokens skip the whitespace and comments following
+    // them, which the statements end before
+    let ends: Vec<usize> = match Lexer::new().tokenize(source) {
+        Ok(tokens) => tokens.iter().map(|token| token.span.end).collect(),
+        Err(_) => Vec::new(),
+    };
+    let spans = spans
+        .into_iter()
+        .map(|(start, end)| {
+            let (start, end) = (source.len() - start, source.len() - end);
+            let end = match ends.partition_point(|&token_end| token_end <= end) {
+                0 => source[..end].trim_end().len(),
+                i => ends[i - 1],
+            };
+            Span::new(start, end.max(start))
+        })
+        .collect();
+    Ok((program, spans))
+}
+
+/// Parses a single expression such as `f(x) + 1`, with nothing but
+/// whitespace and comments around it.
+pub fn parse_expression(source: &str) -> Result<Ast, ParseError> {
+    with_stack_for(MAX_DEPTH, || {
+        parse_to_completion(
+            cmb::and(exp::make_ignored_parser(), exp::make_expression_parser()),
+            source,
+        )
+    })
+}
+
+/// Parses a single statement such as `x = 1;` or a whole function, with
+/// nothing but whitespace and comments around it. A declaration of several
+/// variables is a block of them.
+pub fn parse_statement(source: &str) -> Result<Ast, ParseError> {
+    let options = ParseOptions::default();
+    with_stack_for(options.max_depth, || {
+        parse_to_completion(
+            cmb::and(
+                exp::make_ignored_parser(),
+                cmb::map(stmt::make_statement_parser(&options), |(statement, _)| {
+                    statement
+                }),
+            ),
+            source,
+        )
+    })
+}
+
+/// Parses a single type such as `array<number>`, with nothing but
+/// whitespace and comments around it.
+pub fn parse_type(source: &str) -> Result<Type, ParseError> {
+    parse_to_completion(
+        cmb::and(exp::make_ignored_parser(), exp::make_type_parser()),
+        source,
     )
 }
 
+/// Entry point for fuzzing the front end with arbitrary bytes: parses them
+/// when they are UTF-8 and formats what they parse into. Whatever the input,
+/// this must not panic.
+pub fn fuzz_parse(data: &[u8]) {
+    if let Ok(source) = std::str::from_utf8(data) {
+        if let Ok(program) = parse_program(source) {
+            format::format_program(&program);
+        }
+    }
+}
+
+/// Runs the parsing on a thread with enough stack for `max_depth` levels of
+/// nesting, whatever the stack of the calling thread.
+fn with_stack_for<T: Send>(max_depth: usize, parse: impl FnOnce() -> T + Send) -> T {
+    let stack_size = max_depth
+        .saturating_mul(STACK_PER_LEVEL)
+        .saturating_add(BASE_STACK);
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .name(String::from("parser"))
+            .stack_size(stack_size)
+            .spawn_scoped(scope, parse)
+            .expect("cannot make a stack for the nesting depth")
+            .join()
+            .unwrap_or_else(|payload| panic::resume_unwind(payload))
+    })
+}
+
+/// Runs the parser on the source, failing at where it stopped unless all of
+/// the input is consumed.
+pub fn parse_to_completion<'a, T>(
+    parser: impl Parser<'a, T>,
+    source: &'a str,
+) -> Result<T, ParseError> {
+    parse_to_completion_with_max_depth(parser, source, MAX_DEPTH)
+}
+
+/// Like `parse_to_completion`, with expressions and statements nesting at
+/// most `max_depth` levels deep. The parser runs on the calling thread, whose
+/// stack must fit that many levels.
+pub fn parse_to_completion_with_max_depth<'a, T>(
+    parser: impl Parser<'a, T>,
+    source: &'a str,
+    max_depth: usize,
+) -> Result<T, ParseError> {
+    let (result, too_deep) = cmb::with_max_depth(max_depth, || parser.parse(source));
+    let error = |kind, rest: usize| {
+        let offset = source.len() - rest;
+        ParseError {
+            kind,
+            offset,
+            location: Location::from_offset(source, offset),
+        }
+    };
+    match (result, too_deep) {
+        (Ok(("", parsed)), _) => Ok(parsed),
+        (_, Some(rest)) => Err(error(ParseErrorKind::TooDeep, rest)),
+        (Ok((rest, _)), None) | (Err(rest), None) => {
+            Err(error(ParseErrorKind::UnexpectedInput, rest.len()))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -35,7 +251,8 @@ mod tests {
                 return result;
             } //xx
             ";
-        let parser = make_full_parser();
+        let options = ParseOptions::default();
+        let parser = make_full_parser(&options);
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -78,4 +295,154 @@ mod tests {
             )])
         );
     }
+
+    #[test]
+    fn statement_spans() {
+        let source =
+            "// f\nfunction f() {\n    print(\"//\"); // print\n}\n\nwhile (x) { x = 0; } ";
+        let (program, spans) = parse_program_with_spans(source, &ParseOptions::default()).unwrap();
+        let spanned: Vec<&str> = spans
+            .iter()
+            .map(|span| &source[span.start..span.end])
+            .collect();
+        assert_eq!(
+            spanned,
+            vec![
+                "function f() {\n    print(\"//\"); // print\n}",
+                "{\n    print(\"//\"); // print\n}",
+                "print(\"//\");",
+                "while (x) { x = 0; }",
+                "{ x = 0; }",
+                "x = 0;",
+            ]
+        );
+        assert_eq!(program, parse_program(source).unwrap());
+    }
+
+    #[test]
+    fn program_with_trailing_garbage() {
+        let error = parse_program("var x = 1;\nx ++ 1;").unwrap_err();
+        assert_eq!(error.offset, 11);
+        assert_eq!(error.to_string(), "Unexpected input at 2:1");
+    }
+
+    #[test]
+    fn snippets() {
+        assert_eq!(
+            parse_expression(" x + 1 // x"),
+            Ok(Ast::Addition(
+                Box::new(Ast::Identifier(String::from("x"))),
+                Box::new(Ast::Number(1))
+            ))
+        );
+        assert_eq!(
+            parse_expression("x + 1;").unwrap_err().span(),
+            Span::new(5, 5)
+        );
+        assert_eq!(
+            parse_statement("return 0;"),
+            Ok(Ast::Return(Box::new(Ast::Number(0))))
+        );
+        assert_eq!(parse_statement("x = 1; y = 2;").unwrap_err().offset, 7);
+        assert!(parse_statement("").is_err());
+    }
+
+    #[test]
+    fn nesting_depth() {
+        let parens = |depth| format!("x = {}1{};", "(".repeat(depth), ")".repeat(depth));
+        // the statement and the expression right of `=` are two levels
+        assert!(parse_program(&parens(MAX_DEPTH - 2)).is_ok());
+        let error = parse_program(&parens(MAX_DEPTH - 1)).unwrap_err();
+        assert_eq!(error.kind, ParseErrorKind::TooDeep);
+        assert_eq!(error.offset, 4 + MAX_DEPTH - 1);
+        assert_eq!(error.to_string(), "Nesting too deep at 1:104");
+
+        // the block of an `if` is not a level of its own, the statements in
+        // it are
+        let ifs = |depth| {
+            format!(
+                "{}x = 1;{}",
+                "if (x) {".repeat(depth),
+                "} else {}".repeat(depth)
+            )
+        };
+        let parse = |depth, max_depth| {
+            let options = ParseOptions {
+                max_depth,
+                ..ParseOptions::default()
+            };
+            parse_program_with_options(&ifs(depth), &options)
+        };
+        assert!(parse(8, 10).is_ok());
+        assert_eq!(parse(9, 10).unwrap_err().kind, ParseErrorKind::TooDeep);
+        // a larger limit gets a larger stack
+        assert!(parse(1000, 1002).is_ok());
+        // the limit only stops the parser, it does not turn other errors into
+        // nesting errors
+        assert_eq!(
+            parse_program("x = (1;").unwrap_err().kind,
+            ParseErrorKind::UnexpectedInput
+        );
+    }
+
+    #[test]
+    fn to_completion() {
+        let parser = || cmb::regex(regex::Regex::new("^[a-z]+").unwrap());
+        assert_eq!(
+            parse_to_completion(parser(), "abc"),
+            Ok(String::from("abc"))
+        );
+        assert_eq!(parse_to_completion(parser(), "ab1").unwrap_err().offset, 2);
+        assert_eq!(parse_to_completion(parser(), "1").unwrap_err().offset, 0);
+    }
+
+    #[test]
+    fn fuzz_parse_does_not_panic() {
+        use proptest::test_runner::{Config, TestRunner};
+
+        const TOKENS: [&str; 32] = [
+            "function",
+            "var",
+            "if",
+            "else",
+            "while",
+            "return",
+            "import",
+            "export",
+            "extern",
+            "x",
+            "f",
+            "number",
+            "array",
+            "<",
+            ">",
+            "0",
+            "2147483648",
+            "1.5",
+            "\"s\"",
+            "\"",
+            "(",
+            ")",
+            "{",
+            "}",
+            "[",
+            "]",
+            ",",
+            ";",
+            ":",
+            "=",
+            "+",
+            "...",
+        ];
+
+        fuzz_parse(&[0xff, 0xfe]);
+        fuzz_parse(b"var x = 99999999999;");
+        let tokens = proptest::collection::vec(proptest::sample::select(&TOKENS[..]), 0..40);
+        TestRunner::new(Config::with_cases(256))
+            .run(&tokens, |tokens| {
+                fuzz_parse(tokens.join(" ").as_bytes());
+                Ok(())
+            })
+            .unwrap();
+    }
 }
@@ -1,20 +1,81 @@
 use crate::parser::combinators as cmb;
-use crate::parser::combinators::Parser;
+use crate::parser::combinators::{Parser, Spans};
 use crate::parser::expression as exp;
+use crate::parser::ParseOptions;
 
 use crate::ast::Ast;
 use crate::types::Type;
 
-pub fn make_statement_parser<'a>() -> impl Parser<'a, Ast> {
-    |input: &'a str| {
-        let parser = cmb::or_(make_return_parser(), make_if_parser());
-        let parser = cmb::or_(parser, make_while_parser());
-        let parser = cmb::or_(parser, make_var_parser());
-        let parser = cmb::or_(parser, make_assignment_parser());
-        let parser = cmb::or_(parser, make_block_parser());
-        let parser = cmb::or_(parser, make_function_parser());
-        let parser = cmb::or_(parser, make_expression_parser());
-        parser.parse(input)
+// The parser is built once, the statements nested in others are parsed
+// through a handle on it, each counting as a level of nesting. The statement
+// comes with its span and those of the statements in it, in the order of
+// `lines::statements`.
+pub fn make_statement_parser<'a>(
+    options: &'a ParseOptions,
+) -> impl Parser<'a, (Ast, Spans)> + Clone {
+    let rule = cmb::Recursive::new();
+    let statement = cmb::nested(rule.handle());
+    let parser = cmb::or_(
+        simple(make_return_parser()),
+        make_if_parser(statement.clone()),
+    );
+    let parser = cmb::or_(parser, make_while_parser(statement.clone()));
+    let parser = cmb::or_(parser, simple(make_delete_parser()));
+    let parser = cmb::or_(
+        parser,
+        cmb::map(
+            make_declaration_parser(),
+            |(mut declarations, mut spans)| {
+                if declarations.len() == 1 {
+                    (declarations.pop().unwrap(), spans)
+                } else {
+                    // the block spans the statement like its variables
+                    spans.push(spans[0]);
+                    (Ast::Block(declarations), spans)
+                }
+            },
+        ),
+    );
+    let parser = cmb::or_(parser, simple(make_assignment_parser()));
+    let parser = cmb::or_(parser, make_block_parser(statement.clone()));
+    let parser = cmb::or_(parser, make_function_parser(options, statement.clone()));
+    let parser = cmb::or_(parser, simple(make_import_parser()));
+    let parser = cmb::or_(parser, make_export_parser(options, statement));
+    let parser = cmb::or_(parser, simple(make_extern_parser(options)));
+    let parser = cmb::or_(parser, simple(make_static_assert_parser()));
+    let parser = cmb::or_(parser, simple(make_const_parser()));
+    let parser = cmb::or_(parser, simple(make_doc_parser()));
+    let parser = cmb::or_(parser, simple(make_expression_parser()));
+    cmb::nested(rule.define(parser))
+}
+
+// A statement with no statements in it, spanning only itself.
+fn simple<'a>(parser: impl Parser<'a, Ast>) -> impl Parser<'a, (Ast, Spans)> {
+    cmb::spanned(cmb::map(parser, |statement| (statement, Vec::new())))
+}
+
+// Joins the statements of a list, each given with its spans.
+fn concat(statements: Vec<(Vec<Ast>, Spans)>) -> (Vec<Ast>, Spans) {
+    let (statements, spans): (Vec<Vec<Ast>>, Vec<Spans>) = statements.into_iter().unzip();
+    (
+        statements.into_iter().flatten().collect(),
+        spans.into_iter().flatten().collect(),
+    )
+}
+
+// The body of an `if` or a `while`. A block is parsed right away instead of
+// as a statement of its own, so that it does not count as another level of
+// nesting.
+fn make_body_parser<'a>(
+    statement: impl Parser<'a, (Ast, Spans)> + Clone,
+) -> impl Parser<'a, (Ast, Spans)> {
+    let block = make_block_parser(statement.clone());
+    move |input: &'a str| {
+        if input.starts_with('{') {
+            block.parse(input)
+        } else {
+            statement.parse(input)
+        }
     }
 }
 
@@ -22,112 +83,224 @@ pub fn make_statement_parser<'a>() -> impl Parser<'a, Ast> {
 pub fn make_return_parser<'a>() -> impl Parser<'a, Ast> {
     cmb::and(
         exp::make_return_parser(),
-        cmb::bind(exp::make_expression_parser(), |expr| {
-            cmb::and(
-                exp::make_semicolon_parser(),
-                cmb::constant(Ast::Return(Box::new(expr))),
-            )
-        }),
+        cmb::map(
+            cmb::followed_by(exp::make_expression_parser(), exp::make_semicolon_parser()),
+            |expr| Ast::Return(Box::new(expr)),
+        ),
+    )
+}
+
+// delete_statement <- DELETE expression SEMICOLON
+pub fn make_delete_parser<'a>() -> impl Parser<'a, Ast> {
+    cmb::and(
+        exp::make_delete_parser(),
+        cmb::map(
+            cmb::followed_by(exp::make_expression_parser(), exp::make_semicolon_parser()),
+            |expr| Ast::Delete(Box::new(expr)),
+        ),
     )
 }
 
 // expression_statement <- expression SEMICOLON
 pub fn make_expression_parser<'a>() -> impl Parser<'a, Ast> {
-    cmb::bind(exp::make_expression_parser(), |expr| {
-        cmb::and(exp::make_semicolon_parser(), cmb::constant(expr))
-    })
+    cmb::followed_by(exp::make_expression_parser(), exp::make_semicolon_parser())
 }
 
 // if_statement <- IF LEFT_PAREN expression RIGHT_PAREN statement ELSE statement
-pub fn make_if_parser<'a>() -> impl Parser<'a, Ast> {
+pub fn make_if_parser<'a>(
+    statement: impl Parser<'a, (Ast, Spans)> + Clone,
+) -> impl Parser<'a, (Ast, Spans)> {
+    let condition_parser = make_condition_parser(exp::make_if_parser());
+    let consequence_parser = make_body_parser(statement.clone());
+    let alternative_parser = cmb::and(exp::make_else_parser(), make_body_parser(statement));
+    cmb::spanned(move |input: &'a str| {
+        let (next_input, conditional) = condition_parser.parse(input)?;
+        let (next_input, (consequence, mut spans)) =
+            consequence_parser.parse(next_input).map_err(|_| input)?;
+        let (next_input, (alternative, alternative_spans)) =
+            alternative_parser.parse(next_input).map_err(|_| input)?;
+        spans.extend(alternative_spans);
+        let statement = Ast::If(
+            Box::new(conditional),
+            Box::new(consequence),
+            Box::new(alternative),
+        );
+        Ok((next_input, (statement, spans)))
+    })
+}
+
+// condition <- keyword LEFT_PAREN expression RIGHT_PAREN
+fn make_condition_parser<'a>(keyword: impl Parser<'a, String>) -> impl Parser<'a, Ast> {
     cmb::and(
-        exp::make_if_parser(),
-        cmb::and(
-            exp::make_left_paren_parser(),
-            cmb::bind(exp::make_expression_parser(), move |conditional| {
-                cmb::and(
-                    exp::make_right_paren_parser(),
-                    cmb::bind(make_statement_parser(), move |consequence| {
-                        let conditional = conditional.clone();
-                        cmb::and(
-                            exp::make_else_parser(),
-                            cmb::bind(make_statement_parser(), move |alternative| {
-                                cmb::constant(Ast::If(
-                                    Box::new(conditional.clone()),
-                                    Box::new(consequence.clone()),
-                                    Box::new(alternative),
-                                ))
-                            }),
-                        )
-                    }),
-                )
-            }),
+        cmb::and(keyword, exp::make_left_paren_parser()),
+        cmb::followed_by(
+            exp::make_expression_parser(),
+            exp::make_right_paren_parser(),
         ),
     )
 }
 
 // while_statement <- WHILE LEFT_PAREN expression RIGHT_PAREN statement
-pub fn make_while_parser<'a>() -> impl Parser<'a, Ast> {
-    cmb::and(
-        cmb::and(exp::make_while_parser(), exp::make_left_paren_parser()),
-        cmb::bind(exp::make_expression_parser(), move |conditional| {
-            cmb::and(
-                exp::make_right_paren_parser(),
-                cmb::bind(make_statement_parser(), move |stmt| {
-                    cmb::constant(Ast::While(Box::new(conditional.clone()), Box::new(stmt)))
-                }),
-            )
-        }),
-    )
+pub fn make_while_parser<'a>(
+    statement: impl Parser<'a, (Ast, Spans)> + Clone,
+) -> impl Parser<'a, (Ast, Spans)> {
+    let condition_parser = make_condition_parser(exp::make_while_parser());
+    let body_parser = make_body_parser(statement);
+    cmb::spanned(move |input: &'a str| {
+        let (next_input, conditional) = condition_parser.parse(input)?;
+        let (next_input, (body, spans)) = body_parser.parse(next_input).map_err(|_| input)?;
+        Ok((
+            next_input,
+            (Ast::While(Box::new(conditional), Box::new(body)), spans),
+        ))
+    })
 }
 
-// var_statement <- VAR ID ASSIGN expression SEMICOLON
-pub fn make_var_parser<'a>() -> impl Parser<'a, Ast> {
+// declarator <- ID ASSIGN expression
+pub fn make_declarator_parser<'a>() -> impl Parser<'a, Ast> {
+    cmb::bind(exp::make_id_string_parser(), move |identifier| {
+        cmb::and(
+            exp::make_assign_parser(),
+            cmb::map(exp::make_expression_parser(), move |expr| {
+                Ast::Var(identifier.clone(), Box::new(expr))
+            }),
+        )
+    })
+}
+
+// var_statement <- VAR declarator (COMMA declarator)* SEMICOLON
+pub fn make_var_parser<'a>() -> impl Parser<'a, Vec<Ast>> {
+    let first_parser = cmb::and(exp::make_var_parser(), make_declarator_parser());
+    let rest_parser = cmb::followed_by(
+        cmb::zero_or_more(cmb::and(exp::make_comma_parser(), make_declarator_parser())),
+        exp::make_semicolon_parser(),
+    );
+    move |input: &'a str| {
+        let (next_input, first) = first_parser.parse(input)?;
+        let (next_input, mut declarations) = rest_parser.parse(next_input).map_err(|_| input)?;
+        declarations.insert(0, first);
+        Ok((next_input, declarations))
+    }
+}
+
+// destructuring_statement <- VAR LEFT_BRACKET ID (COMMA ID)* RIGHT_BRACKET ASSIGN expression SEMICOLON
+pub fn make_destructuring_parser<'a>() -> impl Parser<'a, Vec<Ast>> {
     cmb::and(
-        exp::make_var_parser(),
-        cmb::bind(exp::make_id_string_parser(), move |identifier| {
-            cmb::and(
-                exp::make_assign_parser(),
-                cmb::bind(exp::make_expression_parser(), move |expr| {
+        cmb::and(exp::make_var_parser(), exp::make_left_bracket_parser()),
+        cmb::bind(exp::make_id_string_parser(), move |first| {
+            cmb::bind(
+                cmb::zero_or_more(cmb::and(
+                    exp::make_comma_parser(),
+                    exp::make_id_string_parser(),
+                )),
+                move |rest| {
+                    let mut names = vec![first.clone()];
+                    names.extend(rest);
                     cmb::and(
-                        exp::make_semicolon_parser(),
-                        cmb::constant(Ast::Var(identifier.clone(), Box::new(expr))),
+                        cmb::and(exp::make_right_bracket_parser(), exp::make_assign_parser()),
+                        cmb::map(
+                            cmb::followed_by(
+                                exp::make_expression_parser(),
+                                exp::make_semicolon_parser(),
+                            ),
+                            move |array| destructure(&names, array),
+                        ),
                     )
-                }),
+                },
             )
         }),
     )
 }
 
+// The array is evaluated once, into a variable whose name programs cannot
+// spell, and every name is declared with the element at its position.
+fn destructure(names: &[String], array: Ast) -> Vec<Ast> {
+    let array_name = format!("{}.array", names.join("."));
+    let mut declarations = vec![Ast::Var(array_name.clone(), Box::new(array))];
+    declarations.extend(names.iter().enumerate().map(|(i, name)| {
+        let element = Ast::ArrayLookup(
+            Box::new(Ast::Identifier(array_name.clone())),
+            Box::new(Ast::Number(i as i32)),
+        );
+        Ast::Var(name.clone(), Box::new(element))
+    }));
+    declarations
+}
+
+// declaration <- var_statement | destructuring_statement
+// Each of the variables spans the whole statement.
+pub fn make_declaration_parser<'a>() -> impl Parser<'a, (Vec<Ast>, Spans)> {
+    let parser = cmb::or_(make_var_parser(), make_destructuring_parser());
+    move |input: &'a str| {
+        let (next_input, declarations) = parser.parse(input)?;
+        let spans = vec![(input.len(), next_input.len()); declarations.len()];
+        Ok((next_input, (declarations, spans)))
+    }
+}
+
+// statements <- (declaration | statement)*
+// The variables of a declaration go straight into the enclosing list, as a
+// block of their own would end their scope right away.
+pub fn make_statements_parser<'a>(
+    statement: impl Parser<'a, (Ast, Spans)>,
+) -> impl Parser<'a, (Vec<Ast>, Spans)> {
+    cmb::map(
+        cmb::zero_or_more(cmb::or_(
+            make_declaration_parser(),
+            cmb::map(statement, |(statement, spans)| (vec![statement], spans)),
+        )),
+        concat,
+    )
+}
+
+// top_level_statements <- (namespace_statement | declaration | statement)*
+// Namespaces are only allowed at the top level.
+pub fn make_top_level_statements_parser<'a>(
+    options: &'a ParseOptions,
+) -> impl Parser<'a, (Vec<Ast>, Spans)> {
+    let statement = make_statement_parser(options);
+    cmb::map(
+        cmb::zero_or_more(cmb::or_(
+            cmb::map(
+                make_namespace_parser(statement.clone()),
+                |(namespace, spans)| (vec![namespace], spans),
+            ),
+            cmb::or_(
+                make_declaration_parser(),
+                cmb::map(statement, |(statement, spans)| (vec![statement], spans)),
+            ),
+        )),
+        concat,
+    )
+}
+
 // assignment_statement <- ID ASSIGN expression SEMICOLON
 pub fn make_assignment_parser<'a>() -> impl Parser<'a, Ast> {
     cmb::bind(exp::make_id_string_parser(), move |identifier| {
         cmb::and(
             exp::make_assign_parser(),
-            cmb::bind(exp::make_expression_parser(), move |expr| {
-                cmb::and(
-                    exp::make_semicolon_parser(),
-                    cmb::constant(Ast::Assignment(identifier.clone(), Box::new(expr))),
-                )
-            }),
+            cmb::map(
+                cmb::followed_by(exp::make_expression_parser(), exp::make_semicolon_parser()),
+                move |expr| Ast::Assignment(identifier.clone(), Box::new(expr)),
+            ),
         )
     })
 }
 
 // block_statement <- LEFT_BRACE statement* RIGHT_BRACE
-pub fn make_block_parser<'a>() -> impl Parser<'a, Ast> {
-    cmb::and(
+pub fn make_block_parser<'a>(
+    statement: impl Parser<'a, (Ast, Spans)>,
+) -> impl Parser<'a, (Ast, Spans)> {
+    cmb::spanned(cmb::and(
         exp::make_left_brace_parser(),
-        cmb::bind(
-            cmb::zero_or_more(make_statement_parser()),
-            move |statements| {
-                cmb::and(
-                    exp::make_right_brace_parser(),
-                    cmb::constant(Ast::Block(statements)),
-                )
-            },
+        cmb::map(
+            cmb::followed_by(
+                make_statements_parser(statement),
+                exp::make_right_brace_parser(),
+            ),
+            |(statements, spans)| (Ast::Block(statements), spans),
         ),
-    )
+    ))
 }
 
 pub fn make_optional_type_annotation_parser<'a>() -> impl Parser<'a, Option<Type>> {
@@ -142,7 +315,9 @@ pub fn make_parameter_parser<'a>() -> impl Parser<'a, (String, Option<Type>)> {
     })
 }
 
-pub fn make_parameters_parser<'a>() -> impl Parser<'a, Vec<(String, Type)>> {
+pub fn make_parameters_parser<'a>(
+    options: &'a ParseOptions,
+) -> impl Parser<'a, Vec<(String, Type)>> {
     cmb::bind(
         cmb::maybe(make_parameter_parser()),
         move |opt_first_param| {
@@ -150,9 +325,15 @@ pub fn make_parameters_parser<'a>() -> impl Parser<'a, Vec<(String, Type)>> {
                 cmb::zero_or_more(cmb::and(exp::make_comma_parser(), make_parameter_parser())),
                 move |rest| {
                     if let Some((fst_name, fst_type)) = opt_first_param.clone() {
-                        let mut output_params = vec![(fst_name, fst_type.unwrap_or(Type::Number))];
+                        let mut output_params = vec![(
+                            fst_name,
+                            fst_type.unwrap_or_else(|| options.implicit_type.clone()),
+                        )];
                         for param in rest {
-                            output_params.push((param.0, param.1.unwrap_or(Type::Number)));
+                            output_params.push((
+                                param.0,
+                                param.1.unwrap_or_else(|| options.implicit_type.clone()),
+                            ));
                         }
                         cmb::constant(output_params)
                     } else {
@@ -165,33 +346,125 @@ pub fn make_parameters_parser<'a>() -> impl Parser<'a, Vec<(String, Type)>> {
 }
 
 // function_statement <- FUNCTION ID LEFT_PAREN paramters RIGHT_PAREN block_statement
-pub fn make_function_parser<'a>() -> impl Parser<'a, Ast> {
+pub fn make_function_parser<'a>(
+    options: &'a ParseOptions,
+    statement: impl Parser<'a, (Ast, Spans)>,
+) -> impl Parser<'a, (Ast, Spans)> {
+    let name_parser = cmb::and(exp::make_function_parser(), exp::make_id_string_parser());
+    let parameters_parser = cmb::and(
+        exp::make_left_paren_parser(),
+        cmb::bind(make_parameters_parser(options), |parameters| {
+            cmb::and(exp::make_right_paren_parser(), cmb::constant(parameters))
+        }),
+    );
+    let return_type_parser = make_optional_type_annotation_parser();
+    let block_parser = make_block_parser(statement);
+    cmb::spanned(move |input: &'a str| {
+        let (next_input, function_id) = name_parser.parse(input)?;
+        let (next_input, parameters) = parameters_parser.parse(next_input).map_err(|_| input)?;
+        let (next_input, ret_type_annot) =
+            return_type_parser.parse(next_input).map_err(|_| input)?;
+        let (next_input, (block, spans)) = block_parser.parse(next_input).map_err(|_| input)?;
+        let type_ = Type::Function {
+            parameter_types: parameters.into_iter().collect(),
+            return_type: Box::new(ret_type_annot.unwrap_or_else(|| options.implicit_type.clone())),
+        };
+        Ok((
+            next_input,
+            (Ast::Function(function_id, type_, Box::new(block)), spans),
+        ))
+    })
+}
+
+// import_statement <- IMPORT LEFT_BRACE ID (COMMA ID)* RIGHT_BRACE FROM STRING SEMICOLON
+pub fn make_import_parser<'a>() -> impl Parser<'a, Ast> {
     cmb::and(
-        exp::make_function_parser(),
+        cmb::and(exp::make_import_parser(), exp::make_left_brace_parser()),
+        cmb::bind(exp::make_id_string_parser(), move |first| {
+            cmb::bind(
+                cmb::zero_or_more(cmb::and(
+                    exp::make_comma_parser(),
+                    exp::make_id_string_parser(),
+                )),
+                move |rest| {
+                    let mut names = vec![first.clone()];
+                    names.extend(rest);
+                    cmb::and(
+                        cmb::and(exp::make_right_brace_parser(), exp::make_from_parser()),
+                        cmb::bind(exp::make_string_parser(), move |path| {
+                            let path = path[1..path.len() - 1].to_owned();
+                            cmb::and(
+                                exp::make_semicolon_parser(),
+                                cmb::constant(Ast::Import(names.clone(), path)),
+                            )
+                        }),
+                    )
+                },
+            )
+        }),
+    )
+}
+
+// export_statement <- EXPORT function_statement
+pub fn make_export_parser<'a>(
+    options: &'a ParseOptions,
+    statement: impl Parser<'a, (Ast, Spans)>,
+) -> impl Parser<'a, (Ast, Spans)> {
+    cmb::spanned(cmb::and(
+        exp::make_export_parser(),
+        cmb::map(
+            make_function_parser(options, statement),
+            |(function, spans)| (Ast::Export(Box::new(function)), spans),
+        ),
+    ))
+}
+
+// namespace_statement <- NAMESPACE ID LEFT_BRACE statements RIGHT_BRACE
+pub fn make_namespace_parser<'a>(
+    statement: impl Parser<'a, (Ast, Spans)> + Clone,
+) -> impl Parser<'a, (Ast, Spans)> {
+    cmb::spanned(cmb::and(
+        exp::make_namespace_parser(),
+        cmb::bind(exp::make_id_string_parser(), move |name| {
+            cmb::and(
+                exp::make_left_brace_parser(),
+                cmb::map(
+                    cmb::followed_by(
+                        make_statements_parser(statement.clone()),
+                        exp::make_right_brace_parser(),
+                    ),
+                    move |(statements, spans)| (Ast::Namespace(name.clone(), statements), spans),
+                ),
+            )
+        }),
+    ))
+}
+
+// extern_statement <- EXTERN FUNCTION ID LEFT_PAREN paramters RIGHT_PAREN type_annotation? SEMICOLON
+pub fn make_extern_parser<'a>(options: &'a ParseOptions) -> impl Parser<'a, Ast> {
+    cmb::and(
+        cmb::and(exp::make_extern_parser(), exp::make_function_parser()),
         cmb::bind(exp::make_id_string_parser(), move |function_id| {
             cmb::and(
                 exp::make_left_paren_parser(),
-                cmb::bind(make_parameters_parser(), move |parameters| {
+                cmb::bind(make_parameters_parser(options), move |parameters| {
                     let function_id = function_id.clone();
-                    let parameters = parameters;
                     cmb::and(
                         exp::make_right_paren_parser(),
                         cmb::bind(
                             make_optional_type_annotation_parser(),
                             move |ret_type_annot| {
-                                let parameters = parameters.clone();
-                                let function_id = function_id.clone();
-                                cmb::bind(make_block_parser(), move |block| {
-                                    let type_ = Type::Function {
-                                        parameter_types: parameters.iter().cloned().collect(),
-                                        return_type: Box::new(
-                                            ret_type_annot.clone().unwrap_or(Type::Number),
-                                        ),
-                                    };
-                                    let f =
-                                        Ast::Function(function_id.clone(), type_, Box::new(block));
-                                    cmb::constant(f)
-                                })
+                                let type_ = Type::Function {
+                                    parameter_types: parameters.iter().cloned().collect(),
+                                    return_type: Box::new(
+                                        ret_type_annot
+                                            .unwrap_or_else(|| options.implicit_type.clone()),
+                                    ),
+                                };
+                                cmb::and(
+                                    exp::make_semicolon_parser(),
+                                    cmb::constant(Ast::Extern(function_id.clone(), type_)),
+                                )
                             },
                         ),
                     )
@@ -201,9 +474,68 @@ pub fn make_function_parser<'a>() -> impl Parser<'a, Ast> {
     )
 }
 
+// const_statement <- CONST ID ASSIGN expression SEMICOLON
+pub fn make_const_parser<'a>() -> impl Parser<'a, Ast> {
+    cmb::and(
+        exp::make_const_parser(),
+        cmb::bind(exp::make_id_string_parser(), move |name| {
+            cmb::and(
+                exp::make_assign_parser(),
+                cmb::map(
+                    cmb::followed_by(exp::make_expression_parser(), exp::make_semicolon_parser()),
+                    move |value| Ast::Const(name.clone(), Box::new(value)),
+                ),
+            )
+        }),
+    )
+}
+
+// static_assert_statement <- STATIC_ASSERT LEFT_PAREN expression COMMA STRING RIGHT_PAREN SEMICOLON
+pub fn make_static_assert_parser<'a>() -> impl Parser<'a, Ast> {
+    cmb::and(
+        cmb::and(
+            exp::make_static_assert_parser(),
+            exp::make_left_paren_parser(),
+        ),
+        cmb::bind(exp::make_expression_parser(), move |condition| {
+            cmb::and(
+                exp::make_comma_parser(),
+                cmb::bind(exp::make_string_parser(), move |message| {
+                    let message = message[1..message.len() - 1].to_owned();
+                    cmb::and(
+                        cmb::and(exp::make_right_paren_parser(), exp::make_semicolon_parser()),
+                        cmb::constant(Ast::StaticAssert(Box::new(condition.clone()), message)),
+                    )
+                }),
+            )
+        }),
+    )
+}
+
+// doc_comment <- DOC_COMMENT+
+pub fn make_doc_parser<'a>() -> impl Parser<'a, Ast> {
+    cmb::bind(exp::make_doc_comment_parser(), |first| {
+        cmb::map(
+            cmb::zero_or_more(exp::make_doc_comment_parser()),
+            move |mut lines| {
+                lines.insert(0, first.clone());
+                let lines: Vec<&str> = lines
+                    .iter()
+                    .map(|line| {
+                        let line = line[3..].trim_end();
+                        line.strip_prefix(' ').unwrap_or(line)
+                    })
+                    .collect();
+                Ast::Doc(lines.join("\n"))
+            },
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use linked_hash_map::LinkedHashMap;
 
     #[test]
     fn return_parser() {
@@ -214,6 +546,18 @@ mod tests {
         assert_eq!(parsed, Ast::Return(Box::new(Ast::Number(1))));
     }
 
+    #[test]
+    fn delete_parser() {
+        let input = "delete xs; //xx";
+        let parser = make_delete_parser();
+        let (next_input, parsed) = parser.parse(input).unwrap();
+        assert_eq!(next_input, "");
+        assert_eq!(
+            parsed,
+            Ast::Delete(Box::new(Ast::Identifier(String::from("xs"))))
+        );
+    }
+
     #[test]
     fn expression_parser() {
         let input = "1; //xx";
@@ -226,8 +570,9 @@ mod tests {
     #[test]
     fn if_parser() {
         let input = "if (1) 2; else 3; //xx";
-        let parser = make_if_parser();
-        let (next_input, parsed) = parser.parse(input).unwrap();
+        let options = ParseOptions::default();
+        let parser = make_if_parser(make_statement_parser(&options));
+        let (next_input, (parsed, _)) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
             parsed,
@@ -242,8 +587,9 @@ mod tests {
     #[test]
     fn while_parser() {
         let input = "while (1) { 2; } //xx";
-        let parser = make_while_parser();
-        let (next_input, parsed) = parser.parse(input).unwrap();
+        let options = ParseOptions::default();
+        let parser = make_while_parser(make_statement_parser(&options));
+        let (next_input, (parsed, _)) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
             parsed,
@@ -262,7 +608,55 @@ mod tests {
         assert_eq!(next_input, "");
         assert_eq!(
             parsed,
-            Ast::Var(String::from("x"), Box::new(Ast::Number(1)))
+            vec![Ast::Var(String::from("x"), Box::new(Ast::Number(1)))]
+        );
+        let (_, parsed) = parser.parse("var x = f(1, 2), y = [x], z = 3;").unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                Ast::Var(
+                    String::from("x"),
+                    Box::new(Ast::Call(
+                        String::from("f"),
+                        vec![Ast::Number(1), Ast::Number(2)]
+                    ))
+                ),
+                Ast::Var(
+                    String::from("y"),
+                    Box::new(Ast::ArrayLiteral(vec![Ast::Identifier(String::from("x"))]))
+                ),
+                Ast::Var(String::from("z"), Box::new(Ast::Number(3))),
+            ]
+        );
+    }
+
+    #[test]
+    fn destructuring_parser() {
+        let input = "{ var [q, r] = divide(7, 2); return q; }";
+        let options = ParseOptions::default();
+        let parser = make_block_parser(make_statement_parser(&options));
+        let (next_input, (parsed, _)) = parser.parse(input).unwrap();
+        assert_eq!(next_input, "");
+        let element = |i| {
+            Box::new(Ast::ArrayLookup(
+                Box::new(Ast::Identifier(String::from("q.r.array"))),
+                Box::new(Ast::Number(i)),
+            ))
+        };
+        assert_eq!(
+            parsed,
+            Ast::Block(vec![
+                Ast::Var(
+                    String::from("q.r.array"),
+                    Box::new(Ast::Call(
+                        String::from("divide"),
+                        vec![Ast::Number(7), Ast::Number(2)]
+                    ))
+                ),
+                Ast::Var(String::from("q"), element(0)),
+                Ast::Var(String::from("r"), element(1)),
+                Ast::Return(Box::new(Ast::Identifier(String::from("q")))),
+            ])
         );
     }
 
@@ -281,8 +675,9 @@ mod tests {
     #[test]
     fn block_parser() {
         let input = "{1;2;} //xx";
-        let parser = make_block_parser();
-        let (next_input, parsed) = parser.parse(input).unwrap();
+        let options = ParseOptions::default();
+        let parser = make_block_parser(make_statement_parser(&options));
+        let (next_input, (parsed, _)) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(parsed, Ast::Block(vec![Ast::Number(1), Ast::Number(2),]));
     }
@@ -290,7 +685,8 @@ mod tests {
     #[test]
     fn parameters_parser() {
         let input = "x, y, z //xx";
-        let parser = make_parameters_parser();
+        let options = ParseOptions::default();
+        let parser = make_parameters_parser(&options);
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -303,10 +699,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parameters_with_implicit_type_parser() {
+        let input = "x, y: number //xx";
+        let options = ParseOptions {
+            implicit_type: Type::Boolean,
+            ..ParseOptions::default()
+        };
+        let parser = make_parameters_parser(&options);
+        let (next_input, parsed) = parser.parse(input).unwrap();
+        assert_eq!(next_input, "");
+        assert_eq!(
+            parsed,
+            vec![
+                (String::from("x"), Type::Boolean),
+                (String::from("y"), Type::Number)
+            ]
+        );
+    }
+
     #[test]
     fn parameters_with_types_parser() {
         let input = "x: number, y: boolean, z: void , w: number//xx";
-        let parser = make_parameters_parser();
+        let options = ParseOptions::default();
+        let parser = make_parameters_parser(&options);
         let (next_input, parsed) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
@@ -323,8 +739,9 @@ mod tests {
     #[test]
     fn function_parser() {
         let input = "function f(x:number, y:boolean, z: number, w: boolean) { 1; } //xx";
-        let parser = make_function_parser();
-        let (next_input, parsed) = parser.parse(input).unwrap();
+        let options = ParseOptions::default();
+        let parser = make_function_parser(&options, make_statement_parser(&options));
+        let (next_input, (parsed, _)) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
             parsed,
@@ -347,11 +764,117 @@ mod tests {
         );
     }
 
+    #[test]
+    fn import_parser() {
+        let input = "import { f, g } from \"./lib\"; //xx";
+        let parser = make_import_parser();
+        let (next_input, parsed) = parser.parse(input).unwrap();
+        assert_eq!(next_input, "");
+        assert_eq!(
+            parsed,
+            Ast::Import(
+                vec![String::from("f"), String::from("g")],
+                String::from("./lib")
+            )
+        );
+    }
+
+    #[test]
+    fn extern_parser() {
+        let input = "extern function abs(x: number): number; //xx";
+        let options = ParseOptions::default();
+        let parser = make_extern_parser(&options);
+        let (next_input, parsed) = parser.parse(input).unwrap();
+        assert_eq!(next_input, "");
+        assert_eq!(
+            parsed,
+            Ast::Extern(
+                String::from("abs"),
+                Type::Function {
+                    parameter_types: ([(String::from("x"), Type::Number)])
+                        .iter()
+                        .cloned()
+                        .collect(),
+                    return_type: Box::new(Type::Number)
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn const_parser() {
+        let input = "const size = 4; //xx";
+        let parser = make_const_parser();
+        let (next_input, parsed) = parser.parse(input).unwrap();
+        assert_eq!(next_input, "");
+        assert_eq!(
+            parsed,
+            Ast::Const(String::from("size"), Box::new(Ast::Number(4)))
+        );
+        assert!(make_statement_parser(&ParseOptions::default())
+            .parse("constant = 4;")
+            .is_ok());
+    }
+
+    #[test]
+    fn doc_parser() {
+        let input = "/// Adds one.\n//// not documentation\n///\n///  indented  \nfunction";
+        let (next_input, parsed) = make_doc_parser().parse(input).unwrap();
+        assert_eq!(next_input, "function");
+        assert_eq!(parsed, Ast::Doc(String::from("Adds one.\n\n indented")));
+        let (next_input, _) = make_assignment_parser()
+            .parse("x = 1; // plain\n//\n/// Doc.")
+            .unwrap();
+        assert_eq!(next_input, "/// Doc.");
+    }
+
+    #[test]
+    fn static_assert_parser() {
+        let input = "static_assert(1 + 1 == 2, \"arithmetic\"); //xx";
+        let parser = make_static_assert_parser();
+        let (next_input, parsed) = parser.parse(input).unwrap();
+        assert_eq!(next_input, "");
+        assert_eq!(
+            parsed,
+            Ast::StaticAssert(
+                Box::new(Ast::Equal(
+                    Box::new(Ast::Addition(
+                        Box::new(Ast::Number(1)),
+                        Box::new(Ast::Number(1))
+                    )),
+                    Box::new(Ast::Number(2))
+                )),
+                String::from("arithmetic")
+            )
+        );
+    }
+
+    #[test]
+    fn export_parser() {
+        let input = "export function f() { 1; } //xx";
+        let options = ParseOptions::default();
+        let parser = make_export_parser(&options, make_statement_parser(&options));
+        let (next_input, (parsed, _)) = parser.parse(input).unwrap();
+        assert_eq!(next_input, "");
+        assert_eq!(
+            parsed,
+            Ast::Export(Box::new(Ast::Function(
+                String::from("f"),
+                Type::Function {
+                    parameter_types: LinkedHashMap::new(),
+                    return_type: Box::new(Type::Number)
+                },
+                Box::new(Ast::Block(vec![Ast::Number(1)]))
+            )))
+        );
+    }
+
     #[test]
     fn function_parser_without_args() {
         let input = "function f() { 1; } //xx";
-        let parser = make_function_parser();
-        let (next_input, parsed) = parser.parse(input).unwrap();
+        let options = ParseOptions::default();
+        let parser = make_function_parser(&options, make_statement_parser(&options));
+        let (next_input, (parsed, _)) = parser.parse(input).unwrap();
         assert_eq!(next_input, "");
         assert_eq!(
             parsed,
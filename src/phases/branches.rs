@@ -0,0 +1,350 @@
+//! Branch simplification on the ARM32 code.
+//!
+//! Every `if` gets a jump over its `else` branch and two labels, even when
+//! the branch is empty, the condition is a constant or the `then` branch
+//! returns. The pass cleans up after the code generator until nothing
+//! changes:
+//!
+//! - a branch on a constant compared with a constant is either always
+//!   taken or never,
+//! - a branch to a jump goes to the jump's target instead,
+//! - code after an unconditional jump or a return up to the next label
+//!   never runs,
+//! - a branch to the instruction right after it does nothing,
+//! - of several labels in a row the first one stands for the others, and
+//!   local labels nothing refers to are dropped.
+//!
+//! Only the local `.L` labels are merged or dropped, the others may be
+//! referred to from outside of the code.
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::phases::instruction::{Condition, Instruction, Operand, Register};
+
+pub fn simplify(code: Vec<Instruction>) -> Vec<Instruction> {
+    simplify_mapped(code).0
+}
+
+/// The simplified code along with the index of the instruction each
+/// instruction comes from.
+pub fn simplify_mapped(code: Vec<Instruction>) -> (Vec<Instruction>, Vec<usize>) {
+    let mut code: Vec<(Instruction, usize)> = code.into_iter().zip(0..).collect();
+    while fold_constant_branches(&mut code)
+        | thread_jumps(&mut code)
+        | remove_dead_code(&mut code)
+        | remove_jumps_to_next(&mut code)
+        | merge_labels(&mut code)
+    {}
+    code.into_iter().unzip()
+}
+
+fn is_local(label: &str) -> bool {
+    label.starts_with(".L")
+}
+
+// Instructions which neither run nor take space between two others.
+fn is_transparent(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Comment(_) | Instruction::File(_, _) | Instruction::Loc(_, _, _)
+    )
+}
+
+// Whether the instruction never continues with the next one.
+fn ends_flow(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::B(Condition::Always, _) | Instruction::Bx(_) => true,
+        Instruction::Pop(registers) => registers.contains(&Register::Pc),
+        _ => false,
+    }
+}
+
+// The index of the first instruction from `start` on which is not
+// transparent.
+fn next_opaque(code: &[(Instruction, usize)], start: usize) -> Option<usize> {
+    (start..code.len()).find(|&i| !is_transparent(&code[i].0))
+}
+
+fn fold_constant_branches(code: &mut Vec<(Instruction, usize)>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    while i < code.len() {
+        let value = match &code[i].0 {
+            Instruction::Mov(Condition::Always, register, Operand::Immediate(value))
+            | Instruction::LoadConstant(register, value) => Some((*register, *value)),
+            _ => None,
+        };
+        let compare = value.and_then(|(register, value)| {
+            let cmp = next_opaque(code, i + 1)?;
+            match &code[cmp].0 {
+                Instruction::Cmp(compared, Operand::Immediate(other)) if *compared == register => {
+                    Some((cmp, value, *other))
+                }
+                _ => None,
+            }
+        });
+        let branch = compare.and_then(|(cmp, value, other)| {
+            let branch = next_opaque(code, cmp + 1)?;
+            match &code[branch].0 {
+                Instruction::B(condition, _) if *condition != Condition::Always => {
+                    Some((branch, holds(*condition, value, other)))
+                }
+                _ => None,
+            }
+        });
+        match branch {
+            Some((branch, true)) => {
+                if let Instruction::B(condition, _) = &mut code[branch].0 {
+                    *condition = Condition::Always;
+                }
+                changed = true;
+            }
+            Some((branch, false)) => {
+                code.remove(branch);
+                changed = true;
+            }
+            None => {}
+        }
+        i += 1;
+    }
+    changed
+}
+
+// Whether the condition holds after comparing the value with the other.
+fn holds(condition: Condition, value: i32, other: i32) -> bool {
+    match condition {
+        Condition::Always => true,
+        Condition::Eq => value == other,
+        Condition::Ne => value != other,
+        Condition::Lt => value < other,
+        Condition::Gt => value > other,
+        Condition::Hs => value as u32 >= other as u32,
+        Condition::Lo => (value as u32) < other as u32,
+    }
+}
+
+fn thread_jumps(code: &mut [(Instruction, usize)]) -> bool {
+    // the labels directly followed by a jump, with its target
+    let mut jumps = BTreeMap::new();
+    let mut labels = Vec::new();
+    for (instruction, _) in code.iter() {
+        match instruction {
+            Instruction::Label(label) => labels.push(label.clone()),
+            instruction if is_transparent(instruction) => {}
+            Instruction::B(Condition::Always, target) => {
+                for label in labels.drain(..) {
+                    jumps.insert(label, target.clone());
+                }
+            }
+            _ => labels.clear(),
+        }
+    }
+    let mut changed = false;
+    for (instruction, _) in code.iter_mut() {
+        if let Instruction::B(_, target) = instruction {
+            let mut seen = BTreeSet::new();
+            let mut destination = target.clone();
+            while let Some(next) = jumps.get(&destination) {
+                if !seen.insert(destination.clone()) {
+                    // a loop jumping to itself stays as it is
+                    destination = target.clone();
+                    break;
+                }
+                destination = next.clone();
+            }
+            if destination != *target {
+                *target = destination;
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+fn remove_dead_code(code: &mut Vec<(Instruction, usize)>) -> bool {
+    let length = code.len();
+    let mut dead = false;
+    code.retain(|(instruction, _)| {
+        if !instruction.is_executable() {
+            if !is_transparent(instruction) {
+                dead = false;
+            }
+            return true;
+        }
+        if dead {
+            return false;
+        }
+        dead = ends_flow(instruction);
+        true
+    });
+    code.len() != length
+}
+
+fn remove_jumps_to_next(code: &mut Vec<(Instruction, usize)>) -> bool {
+    let mut changed = false;
+    let mut i = 0;
+    while i < code.len() {
+        if let Instruction::B(_, target) = &code[i].0 {
+            let mut next = i + 1;
+            let mut reaches = false;
+            while next < code.len() {
+                match &code[next].0 {
+                    Instruction::Label(label) if label == target => {
+                        reaches = true;
+                        break;
+                    }
+                    Instruction::Label(_) => {}
+                    instruction if is_transparent(instruction) => {}
+                    _ => break,
+                }
+                next += 1;
+            }
+            if reaches {
+                code.remove(i);
+                changed = true;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    changed
+}
+
+fn merge_labels(code: &mut Vec<(Instruction, usize)>) -> bool {
+    // directives are written as is, the labels they name have to stay
+    let pinned: Vec<String> = code
+        .iter()
+        .filter_map(|(instruction, _)| match instruction {
+            Instruction::Directive(directive) => Some(directive.clone()),
+            _ => None,
+        })
+        .collect();
+    let is_pinned = |label: &str| pinned.iter().any(|directive| directive.contains(label));
+    let mut renamed = BTreeMap::new();
+    let mut first: Option<String> = None;
+    for (instruction, _) in code.iter() {
+        match instruction {
+            Instruction::Label(label) => match &first {
+                Some(first) if is_local(first) && is_local(label) && !is_pinned(label) => {
+                    renamed.insert(label.clone(), first.clone());
+                }
+                _ => first = Some(label.clone()),
+            },
+            instruction if is_transparent(instruction) => {}
+            _ => first = None,
+        }
+    }
+    let mut referenced = BTreeSet::new();
+    for (instruction, _) in code.iter_mut() {
+        if let Instruction::B(_, target)
+        | Instruction::Bl(target)
+        | Instruction::LoadAddress(_, target) = instruction
+        {
+            if let Some(first) = renamed.get(target) {
+                *target = first.clone();
+            }
+            referenced.insert(target.clone());
+        }
+    }
+    let length = code.len();
+    code.retain(|(instruction, _)| match instruction {
+        Instruction::Label(label) => {
+            !renamed.contains_key(label)
+                && (!is_local(label) || referenced.contains(label) || is_pinned(label))
+        }
+        _ => true,
+    });
+    code.len() != length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phases::instruction;
+
+    fn label(name: &str) -> Instruction {
+        Instruction::Label(String::from(name))
+    }
+
+    fn b(condition: Condition, target: &str) -> Instruction {
+        Instruction::B(condition, String::from(target))
+    }
+
+    #[test]
+    fn empty_else() {
+        let code = vec![
+            Instruction::Cmp(Register::R0, Operand::Immediate(0)),
+            b(Condition::Eq, ".L0"),
+            Instruction::LoadConstant(Register::R0, 2),
+            b(Condition::Always, ".L1"),
+            label(".L0"),
+            label(".L1"),
+            Instruction::Bx(Register::Lr),
+        ];
+        let (simplified, origins) = simplify_mapped(code);
+        assert_eq!(
+            instruction::render(&simplified),
+            "    cmp r0, #0
+    beq .L0
+    ldr r0, =2
+.L0:
+    bx lr
+"
+        );
+        assert_eq!(origins, [0, 1, 2, 4, 6]);
+    }
+
+    #[test]
+    fn constant_conditions() {
+        let code = |value| {
+            vec![
+                Instruction::Mov(Condition::Always, Register::R0, Operand::Immediate(value)),
+                Instruction::Cmp(Register::R0, Operand::Immediate(0)),
+                b(Condition::Eq, ".L0"),
+                Instruction::Bl(String::from("f")),
+                b(Condition::Always, ".L1"),
+                label(".L0"),
+                label(".L1"),
+                Instruction::Bx(Register::Lr),
+            ]
+        };
+        assert_eq!(
+            instruction::render(&simplify(code(0))),
+            "    mov r0, #0\n    cmp r0, #0\n    bx lr\n"
+        );
+        assert_eq!(
+            instruction::render(&simplify(code(1))),
+            "    mov r0, #1\n    cmp r0, #0\n    bl f\n    bx lr\n"
+        );
+    }
+
+    #[test]
+    fn jumps_to_jumps() {
+        let code = vec![
+            label("f"),
+            b(Condition::Ne, ".L0"),
+            Instruction::Bl(String::from("g")),
+            label(".L0"),
+            b(Condition::Always, ".L1"),
+            label(".L2"),
+            b(Condition::Always, ".L2"),
+            label(".L1"),
+            Instruction::Directive(String::from(".word .L3")),
+            label(".L3"),
+            Instruction::Bx(Register::Lr),
+        ];
+        assert_eq!(
+            instruction::render(&simplify(code)),
+            "f:
+    bne .L1
+    bl g
+    b .L1
+.L2:
+    b .L2
+.L1:
+.word .L3
+.L3:
+    bx lr
+"
+        );
+    }
+}
@@ -0,0 +1,359 @@
+//! Which functions call which, recursion cycles among them and the worst
+//! case stack depth of the program.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::ast::Ast;
+use crate::mangle;
+use crate::phases::codegen::{FLOAT_OPERATIONS, INTRINSICS};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallGraph {
+    /// Functions defined in the program, in the order of their definitions.
+    pub functions: Vec<String>,
+    /// Functions called by each defined function, including the ones defined
+    /// elsewhere such as `putchar`.
+    pub calls: BTreeMap<String, BTreeSet<String>>,
+}
+
+// The name of the function as written in the source, with the module and
+// the functions or the namespace around it, for the mangled ones.
+fn readable(function: &str) -> String {
+    mangle::demangle(function).unwrap_or_else(|| String::from(function))
+}
+
+// The float operations and the intrinsics are not calls, they are expanded
+// in place.
+fn collect_calls(ast: &Ast, calls: &mut BTreeSet<String>) {
+    match ast {
+        Ast::Call(name, _)
+            if !FLOAT_OPERATIONS.contains(&name.as_str())
+                && !INTRINSICS.contains(&name.as_str()) =>
+        {
+            calls.insert(name.clone());
+        }
+        _ => {}
+    }
+    for child in ast.children() {
+        collect_calls(child, calls);
+    }
+}
+
+impl CallGraph {
+    pub fn new(program: &Ast) -> CallGraph {
+        let mut graph = CallGraph {
+            functions: Vec::new(),
+            calls: BTreeMap::new(),
+        };
+        graph.add_functions(program);
+        graph
+    }
+
+    fn add_functions(&mut self, ast: &Ast) {
+        match ast {
+            Ast::Block(statements) => statements.iter().for_each(|x| self.add_functions(x)),
+            Ast::Export(function) => self.add_functions(function),
+            Ast::Function(name, _, body) => {
+                let mut calls = BTreeSet::new();
+                collect_calls(body, &mut calls);
+                self.functions.push(name.clone());
+                self.calls.insert(name.clone(), calls);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn callees(&self, function: &str) -> impl Iterator<Item = &str> {
+        self.calls
+            .get(function)
+            .into_iter()
+            .flat_map(|calls| calls.iter().map(String::as_str))
+    }
+
+    /// Groups of functions calling each other recursively, a function
+    /// calling itself being a group of one. Found with Tarjan's strongly
+    /// connected components algorithm.
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        struct Search<'a> {
+            graph: &'a CallGraph,
+            index: BTreeMap<&'a str, usize>,
+            low_link: BTreeMap<&'a str, usize>,
+            stack: Vec<&'a str>,
+            cycles: Vec<Vec<String>>,
+        }
+
+        impl<'a> Search<'a> {
+            fn visit(&mut self, function: &'a str) {
+                let index = self.index.len();
+                self.index.insert(function, index);
+                self.low_link.insert(function, index);
+                self.stack.push(function);
+                for callee in self.graph.callees(function) {
+                    if !self.index.contains_key(callee) {
+                        self.visit(callee);
+                        let low_link = self.low_link[function].min(self.low_link[callee]);
+                        self.low_link.insert(function, low_link);
+                    } else if self.stack.contains(&callee) {
+                        let low_link = self.low_link[function].min(self.index[callee]);
+                        self.low_link.insert(function, low_link);
+                    }
+                }
+                if self.low_link[function] == index {
+                    let start = self.stack.iter().position(|x| *x == function).unwrap();
+                    let component: Vec<String> =
+                        self.stack.drain(start..).map(String::from).collect();
+                    let calls_itself = self.graph.callees(function).any(|x| x == function);
+                    if component.len() > 1 || calls_itself {
+                        self.cycles.push(component);
+                    }
+                }
+            }
+        }
+
+        let mut search = Search {
+            graph: self,
+            index: BTreeMap::new(),
+            low_link: BTreeMap::new(),
+            stack: Vec::new(),
+            cycles: Vec::new(),
+        };
+        for function in &self.functions {
+            if !search.index.contains_key(function.as_str()) {
+                search.visit(function);
+            }
+        }
+        search.cycles
+    }
+
+    /// Bytes of stack used at most by a call to `function`, given the frame
+    /// size of every defined function. Functions defined elsewhere are taken
+    /// as using no stack. `None` when the function can recurse.
+    pub fn max_stack_depth(
+        &self,
+        function: &str,
+        frame_sizes: &BTreeMap<String, u32>,
+    ) -> Option<u32> {
+        self.deepest_chain(function, frame_sizes).0
+    }
+
+    /// The calls from `function` taking the most stack with the bytes they
+    /// take, as `max_stack_depth`. When the function can recurse, the chain
+    /// leads to the first recursive function found instead.
+    pub fn deepest_chain<'a>(
+        &'a self,
+        function: &'a str,
+        frame_sizes: &BTreeMap<String, u32>,
+    ) -> (Option<u32>, Vec<&'a str>) {
+        let recursive: BTreeSet<String> = self.cycles().into_iter().flatten().collect();
+        self.depth(function, frame_sizes, &recursive)
+    }
+
+    fn depth<'a>(
+        &'a self,
+        function: &'a str,
+        frame_sizes: &BTreeMap<String, u32>,
+        recursive: &BTreeSet<String>,
+    ) -> (Option<u32>, Vec<&'a str>) {
+        if recursive.contains(function) {
+            return (None, vec![function]);
+        }
+        let mut deepest = (Some(0), Vec::new());
+        for callee in self.callees(function) {
+            let (depth, chain) = self.depth(callee, frame_sizes, recursive);
+            match depth {
+                None => {
+                    deepest = (None, chain);
+                    break;
+                }
+                Some(depth) if deepest.1.is_empty() || Some(depth) > deepest.0 => {
+                    deepest = (Some(depth), chain)
+                }
+                Some(_) => {}
+            }
+        }
+        let (depth, mut chain) = deepest;
+        chain.insert(0, function);
+        let frame_size = frame_sizes.get(function).copied().unwrap_or(0);
+        (depth.map(|depth| frame_size + depth), chain)
+    }
+
+    /// Lists the frame size of every defined function with the most stack a
+    /// call to it takes and the calls taking it, `unbounded` when it can
+    /// recurse.
+    pub fn stack_usage(&self, frame_sizes: &BTreeMap<String, u32>) -> String {
+        let width = self
+            .functions
+            .iter()
+            .map(|function| readable(function).len())
+            .chain(Some("function".len()))
+            .max()
+            .unwrap();
+        let mut output = format!(
+            "{:<width$}  frame      stack  deepest calls\n",
+            "function",
+            width = width
+        );
+        for function in &self.functions {
+            let (depth, chain) = self.deepest_chain(function, frame_sizes);
+            let (depth, note) = match depth {
+                Some(depth) => (depth.to_string(), ""),
+                None => (String::from("unbounded"), " (recursive)"),
+            };
+            writeln!(
+                output,
+                "{:<width$}  {:>5}  {:>9}  {}{}",
+                readable(function),
+                frame_sizes.get(function).copied().unwrap_or(0),
+                depth,
+                chain
+                    .iter()
+                    .map(|function| readable(function))
+                    .collect::<Vec<_>>()
+                    .join(" -> "),
+                note,
+                width = width
+            )
+            .unwrap();
+        }
+        output
+    }
+
+    /// Renders the graph in the Graphviz DOT format. The calls forming
+    /// recursion cycles are red and the functions defined elsewhere dashed.
+    /// With frame sizes, every defined function is labeled with its worst
+    /// case stack depth.
+    pub fn to_dot(&self, frame_sizes: &BTreeMap<String, u32>) -> String {
+        let cycles = self.cycles();
+        let in_cycle = |caller: &str, callee: &str| {
+            cycles
+                .iter()
+                .any(|cycle| cycle.iter().any(|x| x == caller) && cycle.iter().any(|x| x == callee))
+        };
+        let mut output = String::from("digraph calls {\n    node [fontname=monospace];\n");
+        for cycle in &cycles {
+            let cycle: Vec<String> = cycle.iter().map(|function| readable(function)).collect();
+            writeln!(output, "    // recursion: {}", cycle.join(", ")).unwrap();
+        }
+        let mut external = BTreeSet::new();
+        for function in &self.functions {
+            let label = if frame_sizes.is_empty() {
+                readable(function)
+            } else {
+                match self.max_stack_depth(function, frame_sizes) {
+                    Some(depth) => format!("{}\\nstack: {} bytes", readable(function), depth),
+                    None => format!("{}\\nstack: unbounded", readable(function)),
+                }
+            };
+            writeln!(output, "    \"{}\" [label=\"{}\"];", function, label).unwrap();
+            external.extend(
+                self.callees(function)
+                    .filter(|x| !self.calls.contains_key(*x)),
+            );
+        }
+        for function in external {
+            writeln!(output, "    \"{}\" [style=dashed];", function).unwrap();
+        }
+        for function in &self.functions {
+            for callee in self.callees(function) {
+                let style = if in_cycle(function, callee) {
+                    " [color=red]"
+                } else {
+                    ""
+                };
+                writeln!(output, "    \"{}\" -> \"{}\"{};", function, callee, style).unwrap();
+            }
+        }
+        output.push_str("}\n");
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn call_graph(source: &str) -> CallGraph {
+        CallGraph::new(&parser::parse_program(source).unwrap())
+    }
+
+    #[test]
+    fn recursion_cycles() {
+        let graph = call_graph(
+            "function main() { even(4); return factorial(3); }
+            function even(n) { if (n == 0) { return 1; } else { return odd(n - 1); } }
+            function odd(n) { if (n == 0) { return 0; } else { return even(n - 1); } }
+            function factorial(n) { if (n == 0) { return 1; } else { return n * factorial(n - 1); } }",
+        );
+        assert_eq!(
+            graph.callees("main").collect::<Vec<&str>>(),
+            vec!["even", "factorial"]
+        );
+        assert_eq!(
+            graph.cycles(),
+            vec![
+                vec![String::from("even"), String::from("odd")],
+                vec![String::from("factorial")]
+            ]
+        );
+        assert_eq!(graph.max_stack_depth("main", &BTreeMap::new()), None);
+    }
+
+    #[test]
+    fn stack_depth() {
+        let graph = call_graph(
+            "function main() { f(); g(); }
+            function f() { g(); }
+            function g() { putchar(1); }",
+        );
+        assert!(graph.cycles().is_empty());
+        let frame_sizes: BTreeMap<String, u32> = vec![("main", 24), ("f", 32), ("g", 40)]
+            .into_iter()
+            .map(|(name, size)| (String::from(name), size))
+            .collect();
+        assert_eq!(graph.max_stack_depth("main", &frame_sizes), Some(96));
+        assert_eq!(graph.max_stack_depth("g", &frame_sizes), Some(40));
+        assert_eq!(
+            graph.deepest_chain("main", &frame_sizes),
+            (Some(96), vec!["main", "f", "g", "putchar"])
+        );
+    }
+
+    #[test]
+    fn stack_usage_table() {
+        let graph = call_graph(
+            "function main() { f(); return factorial(3); }
+            function f() { putchar(1); }
+            function factorial(n) { if (n == 0) { return 1; } else { return n * factorial(n - 1); } }",
+        );
+        let frame_sizes: BTreeMap<String, u32> = vec![("main", 24), ("f", 32), ("factorial", 40)]
+            .into_iter()
+            .map(|(name, size)| (String::from(name), size))
+            .collect();
+        assert_eq!(
+            graph.stack_usage(&frame_sizes),
+            "function   frame      stack  deepest calls
+main          24  unbounded  main -> factorial (recursive)
+f             32         32  f -> putchar
+factorial     40  unbounded  factorial (recursive)
+"
+        );
+    }
+
+    #[test]
+    fn dot() {
+        let graph = call_graph("function f() { f(); putchar(1); }");
+        assert_eq!(
+            graph.to_dot(&BTreeMap::new()),
+            "digraph calls {
+    node [fontname=monospace];
+    // recursion: f
+    \"f\" [label=\"f\"];
+    \"putchar\" [style=dashed];
+    \"f\" -> \"f\" [color=red];
+    \"f\" -> \"putchar\";
+}
+"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Control-flow graph of a function body.
+//!
+//! Straight-line statements are grouped into basic blocks, which end with a
+//! terminator saying where the control goes next. `if` and `while` become
+//! conditional branches and every `return` ends its block; the statements
+//! following it land in a fresh block without predecessors, so unreachable
+//! code is still part of the graph.
+use std::fmt::Write;
+
+use crate::ast::Ast;
+
+pub type BlockId = usize;
+
+/// The block where the function starts.
+pub const ENTRY: BlockId = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator<'a> {
+    Jump(BlockId),
+    /// Goes to the first block when the condition holds, else to the second.
+    Branch(&'a Ast, BlockId, BlockId),
+    Return(&'a Ast),
+    /// The end of the function body reached without a `return`.
+    Exit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock<'a> {
+    pub statements: Vec<&'a Ast>,
+    pub terminator: Terminator<'a>,
+}
+
+impl<'a> BasicBlock<'a> {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self.terminator {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch(_, consequence, alternative) => vec![consequence, alternative],
+            Terminator::Return(_) | Terminator::Exit => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg<'a> {
+    pub blocks: Vec<BasicBlock<'a>>,
+}
+
+impl<'a> Cfg<'a> {
+    /// Builds the graph of a function body, or of a whole function.
+    pub fn new(body: &'a Ast) -> Cfg<'a> {
+        let body = match body {
+            Ast::Function(_, _, body) => body,
+            body => body,
+        };
+        let mut cfg = Cfg { blocks: Vec::new() };
+        let entry = cfg.add_block();
+        cfg.add_statement(body, entry);
+        cfg
+    }
+
+    fn add_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock {
+            statements: Vec::new(),
+            terminator: Terminator::Exit,
+        });
+        self.blocks.len() - 1
+    }
+
+    // Adds the statement at the end of `current`, returns the block where the
+    // control continues after it.
+    fn add_statement(&mut self, ast: &'a Ast, current: BlockId) -> BlockId {
+        match ast {
+            Ast::Block(statements) => statements.iter().fold(current, |block, statement| {
+                self.add_statement(statement, block)
+            }),
+            Ast::If(condition, consequence, alternative) => {
+                let consequence_entry = self.add_block();
+                let alternative_entry = self.add_block();
+                self.blocks[current].terminator =
+                    Terminator::Branch(condition, consequence_entry, alternative_entry);
+                let consequence_exit = self.add_statement(consequence, consequence_entry);
+                let alternative_exit = self.add_statement(alternative, alternative_entry);
+                let join = self.add_block();
+                self.blocks[consequence_exit].terminator = Terminator::Jump(join);
+                self.blocks[alternative_exit].terminator = Terminator::Jump(join);
+                join
+            }
+            Ast::While(condition, body) => {
+                let header = self.add_block();
+                let body_entry = self.add_block();
+                let exit = self.add_block();
+                self.blocks[current].terminator = Terminator::Jump(header);
+                self.blocks[header].terminator = Terminator::Branch(condition, body_entry, exit);
+                let body_exit = self.add_statement(body, body_entry);
+                self.blocks[body_exit].terminator = Terminator::Jump(header);
+                exit
+            }
+            Ast::Return(expr) => {
+                self.blocks[current].terminator = Terminator::Return(expr);
+                self.add_block()
+            }
+            statement => {
+                self.blocks[current].statements.push(statement);
+                current
+            }
+        }
+    }
+
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        self.blocks[block].successors()
+    }
+
+    pub fn predecessors(&self, block: BlockId) -> Vec<BlockId> {
+        (0..self.blocks.len())
+            .filter(|&id| self.successors(id).contains(&block))
+            .collect()
+    }
+
+    /// Whether each block can be reached from the entry.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![ENTRY];
+        while let Some(block) = stack.pop() {
+            if !reachable[block] {
+                reachable[block] = true;
+                stack.extend(self.successors(block));
+            }
+        }
+        reachable
+    }
+}
+
+/// The graphs of all the functions in the program, including exported ones
+/// and the ones in namespaces.
+pub fn functions(program: &Ast) -> Vec<(&str, Cfg<'_>)> {
+    match program {
+        Ast::Block(statements) | Ast::Namespace(_, statements) => {
+            statements.iter().flat_map(functions).collect()
+        }
+        Ast::Export(function) => functions(function),
+        Ast::Function(name, _, _) => vec![(name.as_str(), Cfg::new(program))],
+        _ => Vec::new(),
+    }
+}
+
+// Statements are left aligned by ending every line with `\\l`.
+fn dot_label(lines: &[String]) -> String {
+    let mut label = String::new();
+    for line in lines {
+        for c in line.chars() {
+            match c {
+                '"' | '\\' => {
+                    label.push('\\');
+                    label.push(c);
+                }
+                '\n' => label.push_str("\\l"),
+                c => label.push(c),
+            }
+        }
+        label.push_str("\\l");
+    }
+    label
+}
+
+/// Renders the graphs as a Graphviz `digraph` with a cluster per function.
+/// Unreachable blocks are drawn dashed.
+pub fn to_dot(functions: &[(&str, Cfg<'_>)]) -> String {
+    let mut output = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+    for (name, cfg) in functions {
+        writeln!(output, "    subgraph \"cluster_{}\" {{", name).unwrap();
+        writeln!(output, "        label=\"{}\";", name).unwrap();
+        let reachable = cfg.reachable();
+        for (id, block) in cfg.blocks.iter().enumerate() {
+            let mut lines = vec![format!("{}:", id)];
+            lines.extend(block.statements.iter().map(|statement| match statement {
+                Ast::Var(_, _)
+                | Ast::Assignment(_, _)
+                | Ast::Delete(_)
+                | Ast::Const(_, _)
+                | Ast::StaticAssert(_, _)
+                | Ast::Doc(_) => statement.to_string(),
+                expression => format!("{};", expression),
+            }));
+            match block.terminator {
+                Terminator::Branch(condition, _, _) => lines.push(format!("if ({})", condition)),
+                Terminator::Return(expr) => lines.push(format!("return {};", expr)),
+                Terminator::Jump(_) | Terminator::Exit => {}
+            }
+            let style = if reachable[id] { "" } else { ", style=dashed" };
+            writeln!(
+                output,
+                "        \"{}.{}\" [label=\"{}\"{}];",
+                name,
+                id,
+                dot_label(&lines),
+                style
+            )
+            .unwrap();
+        }
+        for (id, block) in cfg.blocks.iter().enumerate() {
+            match block.terminator {
+                Terminator::Jump(target) => writeln!(
+                    output,
+                    "        \"{0}.{1}\" -> \"{0}.{2}\";",
+                    name, id, target
+                )
+                .unwrap(),
+                Terminator::Branch(_, consequence, alternative) => {
+                    writeln!(
+                        output,
+                        "        \"{0}.{1}\" -> \"{0}.{2}\" [label=true];",
+                        name, id, consequence
+                    )
+                    .unwrap();
+                    writeln!(
+                        output,
+                        "        \"{0}.{1}\" -> \"{0}.{2}\" [label=false];",
+                        name, id, alternative
+                    )
+                    .unwrap();
+                }
+                Terminator::Return(_) | Terminator::Exit => {}
+            }
+        }
+        output.push_str("    }\n");
+    }
+    output.push_str("}\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn branches_and_loops() {
+        let program = parser::parse_program(
+            "function f(x) {
+                var y = 0;
+                while (x != 0) { x = x - 1; }
+                if (x == y) { return 1; } else { y = 2; }
+                return y;
+            }",
+        )
+        .unwrap();
+        let graphs = functions(&program);
+        assert_eq!(graphs.len(), 1);
+        let (name, cfg) = &graphs[0];
+        assert_eq!(*name, "f");
+        // entry, loop header, body, exit, then, else, after return, join, after return
+        assert_eq!(cfg.blocks.len(), 9);
+        assert_eq!(cfg.blocks[ENTRY].statements.len(), 1);
+        assert_eq!(cfg.successors(ENTRY), vec![1]);
+        assert_eq!(cfg.successors(1), vec![2, 3]);
+        assert_eq!(cfg.successors(2), vec![1]);
+        assert_eq!(cfg.predecessors(1), vec![ENTRY, 2]);
+        assert_eq!(cfg.successors(3), vec![4, 5]);
+        assert!(matches!(cfg.blocks[4].terminator, Terminator::Return(_)));
+        assert!(matches!(cfg.blocks[7].terminator, Terminator::Return(_)));
+        assert_eq!(cfg.blocks[8].terminator, Terminator::Exit);
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let program = parser::parse_program("function f() { return 1; putchar(1); }").unwrap();
+        let (_, cfg) = &functions(&program)[0];
+        let body = match &program {
+            Ast::Block(statements) => match &statements[0] {
+                Ast::Function(_, _, body) => body,
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        assert_eq!(&Cfg::new(body), cfg);
+        assert_eq!(cfg.reachable(), vec![true, false]);
+        assert_eq!(cfg.blocks[1].statements.len(), 1);
+    }
+
+    #[test]
+    fn dot() {
+        let program =
+            parser::parse_program("function f(x) { while (x != 0) { x = x - 1; } return x; }")
+                .unwrap();
+        assert_eq!(
+            to_dot(&functions(&program)),
+            r#"digraph cfg {
+    node [shape=box, fontname=monospace];
+    subgraph "cluster_f" {
+        label="f";
+        "f.0" [label="0:\l"];
+        "f.1" [label="1:\lif (x != 0)\l"];
+        "f.2" [label="2:\lx = x - 1;\l"];
+        "f.3" [label="3:\lreturn x;\l"];
+        "f.4" [label="4:\l", style=dashed];
+        "f.0" -> "f.1";
+        "f.1" -> "f.2" [label=true];
+        "f.1" -> "f.3" [label=false];
+        "f.2" -> "f.1";
+    }
+}
+"#
+        );
+    }
+}
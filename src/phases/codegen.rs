@@ -1,20 +1,38 @@
-use std::collections::BTreeMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
 
 use crate::ast::Ast;
+use crate::lines::{self, SourceLine};
+use crate::mangle;
+use crate::phases::branches;
+use crate::phases::copy_propagation;
+use crate::phases::coverage;
+use crate::phases::division;
+use crate::phases::gc;
+use crate::phases::instruction::{
+    self, Address, Condition, FloatRegister, Instruction, Operand, Register,
+};
+use crate::phases::passes::{Pass, Passes};
+use crate::phases::profile;
+use crate::phases::runtime;
+use crate::phases::schedule;
+use crate::phases::target::{Arch, FloatAbi, TargetSpec};
+use crate::phases::thumb;
 use crate::types::Type;
 
-static LABEL: AtomicUsize = AtomicUsize::new(0);
-
-fn make_label() -> String {
-    let value = LABEL.fetch_add(1, Ordering::SeqCst);
-    format!(".L{}", value)
-}
-
 #[derive(Debug, Clone, Default)]
 pub struct Environment {
     pub locals: BTreeMap<String, isize>,
     pub next_local_offset: isize,
+    /// Variables declared at the top level, living in the data section.
+    pub globals: BTreeSet<String>,
+    /// Whether the function returns its float in `s0` as well, see
+    /// `Arm32Generator::float_signature`.
+    pub float_result: bool,
+    /// The entry of the function in the table of `--instrument-profile`.
+    pub profile_entry: Option<usize>,
+    scopes: Vec<(BTreeMap<String, isize>, isize)>,
 }
 
 impl Environment {
@@ -22,217 +40,2033 @@ impl Environment {
         Environment {
             locals,
             next_local_offset,
+            globals: BTreeSet::new(),
+            float_result: false,
+            profile_entry: None,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Starts a block, the locals declared inside are forgotten and their
+    /// stack slots reused once the matching `leave_scope` is called.
+    pub fn enter_scope(&mut self) {
+        self.scopes
+            .push((self.locals.clone(), self.next_local_offset));
+    }
+
+    pub fn leave_scope(&mut self) {
+        let (locals, next_local_offset) = self.scopes.pop().expect("unbalanced scopes");
+        self.locals = locals;
+        self.next_local_offset = next_local_offset;
+    }
+}
+
+/// How the backends interpret numbers in division. Numbers are `i32`, so
+/// signed arithmetic is the default, the unsigned one is kept for programs
+/// relying on the old behaviour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arithmetic {
+    #[default]
+    Signed,
+    Unsigned,
+}
+
+/// What happens when an array is indexed outside of its bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoundsCheck {
+    /// The lookup gives zero.
+    #[default]
+    Zero,
+    /// The program prints an error and aborts.
+    Trap,
+    /// Nothing is checked, the lookup reads whatever is in memory.
+    Off,
+}
+
+/// Where the execution of the ARM32 program starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Entry {
+    /// The program's own `main` is the C `main`, called by the C runtime.
+    #[default]
+    None,
+    /// A C `main` calling the program's `main` and passing what it returns
+    /// to `exit`.
+    Main,
+    /// A bare `_start` calling the program's `main` and exiting with the
+    /// `exit` system call, for executables linked without the C runtime.
+    Start,
+}
+
+/// Label of the program's `main` function when a wrapper takes its place.
+pub const MAIN_FUNCTION: &str = "__rtsc_main";
+
+/// Builtins giving the number of command line arguments and the value of
+/// one read as a number, only available with an entry wrapper.
+pub const ARG_COUNT: &str = "argCount";
+pub const ARG_NUMBER: &str = "argNumber";
+/// Builtin giving the decimal digits of a number as character codes.
+pub const NUMBER_TO_STRING: &str = "numberToString";
+/// Builtins growing and shrinking arrays, `push` stores the array it
+/// appended to back into the variable passed to it.
+pub const PUSH: &str = "push";
+pub const POP: &str = "pop";
+pub const CONCAT: &str = "concat";
+pub const SLICE: &str = "slice";
+/// Arithmetic and comparison of floats, which the typechecker turns into
+/// calls of these as the generators do not know the types of the operands.
+pub const FLOAT_ADD: &str = "__rtsc_fadd";
+pub const FLOAT_SUBTRACT: &str = "__rtsc_fsub";
+pub const FLOAT_MULTIPLY: &str = "__rtsc_fmul";
+pub const FLOAT_DIVIDE: &str = "__rtsc_fdiv";
+pub const FLOAT_EQUAL: &str = "__rtsc_feq";
+pub const FLOAT_OPERATIONS: [&str; 5] = [
+    FLOAT_ADD,
+    FLOAT_SUBTRACT,
+    FLOAT_MULTIPLY,
+    FLOAT_DIVIDE,
+    FLOAT_EQUAL,
+];
+
+/// Builtins giving the smaller and the larger of two numbers and the
+/// magnitude of one. The typechecker turns calls of them into calls of the
+/// intrinsics below, unless the program defines functions of the same names.
+pub const MIN: &str = "min";
+pub const MAX: &str = "max";
+pub const ABS: &str = "abs";
+/// Comparisons of numbers which the generators expand in place without
+/// branches, signed whatever the `Arithmetic`.
+pub const MIN_INTRINSIC: &str = "__rtsc_min";
+pub const MAX_INTRINSIC: &str = "__rtsc_max";
+pub const ABS_INTRINSIC: &str = "__rtsc_abs";
+/// Builtins counting the leading zeros and the set bits of a number and
+/// rotating its bits right, turned into intrinsics like `min`.
+pub const CLZ: &str = "clz";
+pub const POPCOUNT: &str = "popcount";
+pub const ROTATE: &str = "rotate";
+/// Bit manipulations expanded in place into the instruction of the same
+/// name. The rotation is by the amount modulo 32. ARM32 has no instruction
+/// counting bits, so `popcount` calls `runtime::POPCOUNT_ROUTINE` instead.
+pub const CLZ_INTRINSIC: &str = "__rtsc_clz";
+pub const ROTATE_INTRINSIC: &str = "__rtsc_rotate";
+pub const INTRINSICS: [&str; 5] = [
+    MIN_INTRINSIC,
+    MAX_INTRINSIC,
+    ABS_INTRINSIC,
+    CLZ_INTRINSIC,
+    ROTATE_INTRINSIC,
+];
+
+/// What the ARM32 program runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    /// Linux with the C library.
+    #[default]
+    Linux,
+    /// No operating system nor C library, the arrays come from the bump
+    /// allocator of `phases::runtime` and are never freed.
+    BareMetal,
+}
+
+/// Where `putchar` writes the characters in the ARM32 output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Console {
+    /// The C library's `putchar`.
+    #[default]
+    Libc,
+    /// The debugger's console through ARM semihosting.
+    Semihosting,
+    /// A transmit register mapped at the address, written a word at a time.
+    Mmio(u32),
+    /// The standard output through the Linux `write` system call, which
+    /// together with the `_start` entry leaves out the C library.
+    Syscall,
+}
+
+/// The directives the ARM32 assembly starts with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AsmHeader {
+    /// None, the assembler's defaults apply, which have to allow `sdiv` and
+    /// `udiv`.
+    #[default]
+    None,
+    /// Unified syntax with the architecture and its division extension and
+    /// on Linux the floating point unit and calling convention spelled out,
+    /// which GNU as and clang's integrated assembler accept without flags.
+    Unified,
+}
+
+/// Settings shared by all the code generators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CodegenOptions {
+    pub arithmetic: Arithmetic,
+    pub bounds_check: BoundsCheck,
+    /// Allocates arrays through the mark-sweep collector of `phases::gc`,
+    /// only supported by the ARM32 generator.
+    pub gc: bool,
+    /// Precedes the ARM32 code of every statement with a comment quoting its
+    /// source line.
+    pub source_comments: bool,
+    /// Emits `.file` and `.loc` directives so that the assembler produces a
+    /// DWARF line table, only supported by the ARM32 generator.
+    pub debug_info: bool,
+    /// Only supported by the ARM32 generator.
+    pub entry: Entry,
+    /// Prints an error and aborts when dividing by zero, which `sdiv` and
+    /// `udiv` silently turn into zero. Only supported by the ARM32 generator.
+    pub division_check: bool,
+    /// Prints an error and aborts when looking into an array which is
+    /// `undefined`, instead of reading address zero. Only supported by the
+    /// ARM32 generator.
+    pub null_check: bool,
+    /// Only supported by the ARM32 generator.
+    pub platform: Platform,
+    /// Only supported by the ARM32 generator.
+    pub console: Console,
+    /// Only supported by the ARM32 generator.
+    pub header: AsmHeader,
+    /// Emits Thumb-2 code, see `phases::thumb`. Only supported by the ARM32
+    /// generator.
+    pub thumb: bool,
+    /// Counts how often every basic block runs, see `phases::coverage`. Only
+    /// supported by the ARM32 generator.
+    pub coverage: bool,
+    /// Counts the calls and the time spent in every function, see
+    /// `phases::profile`. Only supported by the ARM32 generator.
+    pub profile: bool,
+    /// Notes the statement every instruction comes from, see
+    /// `Arm32Generator::instruction_sources`. Only supported by the ARM32
+    /// generator.
+    pub source_map: bool,
+    /// The optional passes to run, see `phases::passes`.
+    pub passes: Passes,
+    /// The architecture and conventions of the core, see `phases::target`.
+    /// Only supported by the ARM32 generator.
+    pub target: TargetSpec,
+}
+
+/// Variables declared at the top level with their initial values, the
+/// typechecker only lets constants initialize them.
+pub fn global_variables(ast: &Ast) -> Vec<(&str, i32)> {
+    ast.top_level_statements()
+        .iter()
+        .filter_map(|statement| match statement {
+            Ast::Var(name, value) => Some((name.as_str(), constant_value(value))),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Functions other objects may call: the exported ones and `main`, which the
+/// C runtime calls. The rest are local to the object.
+pub fn exported_functions(ast: &Ast) -> BTreeSet<String> {
+    ast.top_level_statements()
+        .iter()
+        .filter_map(|statement| match statement {
+            Ast::Export(function) => match function.as_ref() {
+                Ast::Function(name, _, _) => Some(name.clone()),
+                _ => None,
+            },
+            Ast::Function(name, _, _) if name == "main" => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn constant_value(ast: &Ast) -> i32 {
+    constant(ast).unwrap_or_else(|| {
+        panic!(
+            "Global variables must be initialized with a constant, got {:?}",
+            ast
+        )
+    })
+}
+
+fn constant(ast: &Ast) -> Option<i32> {
+    match ast {
+        Ast::Number(value) => Some(*value),
+        Ast::Bool(value) => Some(*value as i32),
+        Ast::Float(value) => Some(value.to_bits() as i32),
+        Ast::Null | Ast::Undefined => Some(0),
+        _ => None,
+    }
+}
+
+/// Values of an array literal made of constants only, which the generators
+/// place in read-only memory instead of allocating it on every evaluation.
+pub fn constant_elements(elements: &[Ast]) -> Option<Vec<i32>> {
+    elements.iter().map(constant).collect()
+}
+
+/// Codes of the characters of a string, which is laid out like an array of
+/// them.
+pub fn character_codes(text: &str) -> Vec<i32> {
+    text.chars().map(|c| c as i32).collect()
+}
+
+/// Whether the program `delete`s arrays, which rules out arrays living in
+/// read-only memory as any array may end up passed to `free`.
+pub fn deletes_arrays(ast: &Ast) -> bool {
+    match ast {
+        Ast::Delete(_) => true,
+        Ast::Block(statements) => statements.iter().any(deletes_arrays),
+        Ast::If(_, consequence, alternative) => {
+            deletes_arrays(consequence) || deletes_arrays(alternative)
         }
+        Ast::While(_, body) | Ast::Function(_, _, body) | Ast::Export(body) => deletes_arrays(body),
+        _ => false,
     }
 }
 
+/// Signatures of the functions defined and declared at the top level.
+fn function_signatures(ast: &Ast) -> BTreeMap<String, Type> {
+    ast.declarations()
+        .filter_map(|statement| match statement {
+            Ast::Function(name, signature, _) | Ast::Extern(name, signature) => {
+                Some((name.clone(), signature.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Whether the function is called anywhere in the tree.
+fn calls(ast: &Ast, function: &str) -> bool {
+    // a chain of operators is as deep as it is long
+    let mut nodes = vec![ast];
+    while let Some(node) = nodes.pop() {
+        if matches!(node, Ast::Call(name, _) if name == function) {
+            return true;
+        }
+        nodes.extend(node.children());
+    }
+    false
+}
+
+/// Where the parameters and the locals of a generated function live, as
+/// offsets from its `fp`. Locals are listed in the order of their
+/// declarations, the ones of blocks which ended sharing their slots with the
+/// next ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameLayout {
+    pub function: String,
+    /// The label of the function in the assembly.
+    pub label: String,
+    pub parameters: Vec<(String, isize)>,
+    pub locals: Vec<(String, isize)>,
+}
+
 pub trait CodeGenerator {
-    fn emit(&self, buffer: &mut String, env: &mut Environment);
+    /// Writes the generated code to the output, failing when it cannot be
+    /// written.
+    fn emit(&self, output: &mut dyn io::Write, env: &mut Environment) -> io::Result<()>;
 }
 
 #[derive(Debug)]
 pub struct Arm32Generator {
     ast: Ast,
+    options: CodegenOptions,
+    // Labels are numbered per generator, so that the output does not depend
+    // on what else was compiled in the same process.
+    next_label: Cell<usize>,
+    uses_bounds_error: Cell<bool>,
+    uses_division_error: Cell<bool>,
+    uses_null_error: Cell<bool>,
+    uses_number_to_string: Cell<bool>,
+    uses_string_equal: Cell<bool>,
+    uses_popcount: Cell<bool>,
+    uses_new_array: Cell<bool>,
+    uses_array_builtins: Cell<bool>,
+    uses_arguments: Cell<bool>,
+    constant_arrays_allowed: bool,
+    signatures: BTreeMap<String, Type>,
+    exported: BTreeSet<String>,
+    // labels and values of the arrays placed in `.rodata`
+    constant_arrays: RefCell<Vec<(String, Vec<i32>)>>,
+    source_files: Vec<String>,
+    source_lines: Vec<SourceLine>,
+    // lines of the statements, by their address in `ast`
+    statement_lines: RefCell<BTreeMap<*const Ast, usize>>,
+    // coverage counters of the statements starting basic blocks
+    coverage_counters: RefCell<BTreeMap<*const Ast, usize>>,
+    // entries of the functions in the profile table
+    profile_entries: RefCell<BTreeMap<String, usize>>,
+    frame_layouts: RefCell<Vec<FrameLayout>>,
+    // the instructions from `start` to `end` of the code and the line of the
+    // statement emitting them, outer statements first
+    source_ranges: RefCell<Vec<(usize, usize, usize)>>,
+    instruction_sources: RefCell<Vec<Option<usize>>>,
 }
 
 impl CodeGenerator for Arm32Generator {
-    fn emit(&self, buffer: &mut String, env: &mut Environment) {
-        self.emit_ast(&self.ast, buffer, env);
+    fn emit(&self, output: &mut dyn io::Write, env: &mut Environment) -> io::Result<()> {
+        instruction::write(&self.generate(env), output)
+    }
+}
+
+// Most of the expressions keep the intermediate result on the stack, pushing
+// `ip` alongside to keep the stack 8 byte aligned.
+fn push_r0() -> Instruction {
+    Instruction::Push(vec![Register::R0, Register::Ip])
+}
+
+fn pop_r1() -> Instruction {
+    Instruction::Pop(vec![Register::R1, Register::Ip])
+}
+
+fn mov(cond: Condition, rd: Register, value: i32) -> Instruction {
+    Instruction::Mov(cond, rd, Operand::Immediate(value))
+}
+
+// Expressions that do not call any function are evaluated in the argument
+// registers instead of going through the stack, the result ends up in `r0`.
+const EXPRESSION_REGISTERS: [Register; 4] =
+    [Register::R0, Register::R1, Register::R2, Register::R3];
+
+/// Sethi-Ullman numbers of the nodes of a tree by their address, that is
+/// how many registers are needed to evaluate each of them without spilling
+/// anything to the stack. `None` for statements and for expressions that
+/// call functions, as calls clobber the argument registers.
+type RegisterNeeds = BTreeMap<*const Ast, Option<usize>>;
+
+// Worked out from the leaves up with a stack of its own, as a chain of
+// operators is as deep as it is long.
+fn register_needs(ast: &Ast) -> RegisterNeeds {
+    let mut needs = RegisterNeeds::new();
+    let mut nodes = vec![(ast, false)];
+    while let Some((node, operands_done)) = nodes.pop() {
+        if !operands_done {
+            nodes.push((node, true));
+            nodes.extend(node.children().into_iter().map(|child| (child, false)));
+            continue;
+        }
+        let need = |operand: &Ast| needs[&(operand as *const Ast)];
+        let binary = |lhs: &Ast, rhs: &Ast| binary_register_need(need(lhs), need(rhs));
+        let node_need = match node {
+            Ast::Undefined
+            | Ast::Null
+            | Ast::Number(_)
+            | Ast::Float(_)
+            | Ast::Bool(_)
+            | Ast::String(_)
+            | Ast::Identifier(_) => Some(1),
+            Ast::Not(expr) | Ast::ArrayLength(expr) => need(expr),
+            // the bounds check needs one more register for the length
+            Ast::ArrayLookup(array, index) => binary(array, index).map(|need| need.max(3)),
+            Ast::Multiplication(_, _) | Ast::Division(_, _) if shift_operand(node).is_some() => {
+                need(shift_operand(node).unwrap().0)
+            }
+            Ast::Addition(lhs, rhs)
+            | Ast::Subtraction(lhs, rhs)
+            | Ast::Multiplication(lhs, rhs)
+            | Ast::Division(lhs, rhs)
+            | Ast::Equal(lhs, rhs)
+            | Ast::NotEqual(lhs, rhs) => binary(lhs, rhs),
+            Ast::Call(name, args) if FLOAT_OPERATIONS.contains(&name.as_str()) => {
+                binary(&args[0], &args[1])
+            }
+            Ast::Call(name, args) if INTRINSICS.contains(&name.as_str()) => match args.as_slice() {
+                [operand] => need(operand),
+                [lhs, rhs] => binary(lhs, rhs),
+                _ => unreachable!("{} takes one or two arguments", name),
+            },
+            _ => None,
+        };
+        needs.insert(node as *const Ast, node_need);
+    }
+    needs
+}
+
+fn binary_register_need(lhs_need: Option<usize>, rhs_need: Option<usize>) -> Option<usize> {
+    let (lhs_need, rhs_need) = (lhs_need?, rhs_need?);
+    if lhs_need == rhs_need {
+        Some(lhs_need + 1)
+    } else {
+        Some(lhs_need.max(rhs_need))
+    }
+}
+
+// Exponent of a positive power of two constant.
+fn power_of_two(ast: &Ast) -> Option<u32> {
+    match ast {
+        Ast::Number(value) if *value > 0 && value & (value - 1) == 0 => {
+            Some(value.trailing_zeros())
+        }
+        _ => None,
+    }
+}
+
+/// The operand and the shift amount of a multiplication or division by a
+/// power of two, which are lowered to shifts as cores without `sdiv`/`udiv`
+/// are common and multiplying takes longer than shifting.
+fn shift_operand(ast: &Ast) -> Option<(&Ast, u32)> {
+    match ast {
+        Ast::Multiplication(lhs, rhs) => power_of_two(rhs)
+            .map(|shift| (lhs.as_ref(), shift))
+            .or_else(|| power_of_two(lhs).map(|shift| (rhs.as_ref(), shift))),
+        Ast::Division(lhs, rhs) => power_of_two(rhs).map(|shift| (lhs.as_ref(), shift)),
+        _ => None,
+    }
+}
+
+// Multiplies or divides `rd` by `2^shift` in place.
+fn emit_shift(
+    ast: &Ast,
+    arithmetic: Arithmetic,
+    rd: Register,
+    shift: u32,
+    code: &mut Vec<Instruction>,
+) {
+    if shift == 0 {
+        return;
+    }
+    let shift = Operand::Immediate(shift as i32);
+    match (ast, arithmetic) {
+        (Ast::Multiplication(_, _), _) => {
+            code.push(Instruction::Lsl(Condition::Always, rd, rd, shift))
+        }
+        (Ast::Division(_, _), Arithmetic::Unsigned) => {
+            code.push(Instruction::Lsr(Condition::Always, rd, rd, shift))
+        }
+        // shifting a negative number rounds towards minus infinity, so the
+        // magnitude is divided instead to round towards zero like `sdiv`
+        (Ast::Division(_, _), Arithmetic::Signed) => {
+            let zero = Operand::Immediate(0);
+            code.push(Instruction::Cmp(rd, zero.clone()));
+            code.push(Instruction::Rsb(Condition::Lt, rd, rd, zero.clone()));
+            code.push(Instruction::Lsr(Condition::Always, rd, rd, shift));
+            code.push(Instruction::Rsb(Condition::Lt, rd, rd, zero));
+        }
+        _ => unreachable!("{:?} is not a multiplication or a division", ast),
+    }
+}
+
+const BOUNDS_ERROR_ROUTINE: &str = "__rtsc_bounds_error";
+const DIVISION_ERROR_ROUTINE: &str = "__rtsc_division_error";
+const NULL_ERROR_ROUTINE: &str = "__rtsc_null_error";
+// ARM EABI Linux system call numbers.
+pub(crate) const SYS_EXIT: i32 = 1;
+pub(crate) const SYS_WRITE: i32 = 4;
+// Exit status of a process killed by `SIGABRT`, as reported by shells.
+const ABORT_STATUS: i32 = 134;
+
+// Most locals alive at the same time, the slots of a block are reused by
+// the blocks that follow it.
+fn count_locals(ast: &Ast) -> usize {
+    match ast {
+        Ast::Block(statements) => {
+            let mut declared = 0;
+            let mut peak = 0;
+            for statement in statements {
+                if let Ast::Var(_, _) = statement {
+                    declared += 1;
+                    peak = peak.max(declared);
+                } else {
+                    peak = peak.max(declared + count_locals(statement));
+                }
+            }
+            peak
+        }
+        Ast::If(_, consequence, alternative) => {
+            count_locals(consequence).max(count_locals(alternative))
+        }
+        Ast::While(_, body) => count_locals(body),
+        Ast::Var(_, _) => 1,
+        _ => 0,
+    }
+}
+
+/// Bytes the function body needs below the spilled arguments, one word for
+/// every `var` in scope at the same time, rounded up to keep the stack 8
+/// byte aligned.
+fn locals_size(body: &Ast) -> i32 {
+    let size = 4 * count_locals(body) as i32;
+    (size + 7) & !7
+}
+
+/// The most stack each generated function uses at once, in bytes, from the
+/// saved `lr` down to the deepest temporary, found by following the changes
+/// of `sp` through its code.
+pub fn frame_sizes(code: &[Instruction]) -> BTreeMap<String, u32> {
+    let words = |registers: &Vec<Register>| 4 * registers.len() as i32;
+    let mut sizes = BTreeMap::new();
+    let mut function = None;
+    let mut offset = 0;
+    let mut deepest = 0;
+    let mut before_return = 0;
+    for instruction in code {
+        match instruction {
+            Instruction::Global(name) | Instruction::Local(name) => {
+                if let Some(name) = function.replace(name.clone()) {
+                    sizes.insert(name, -deepest as u32);
+                }
+                offset = 0;
+                deepest = 0;
+            }
+            Instruction::Push(registers) => offset -= words(registers),
+            // the code after a `return` continues with the stack it had
+            Instruction::Pop(registers) if registers.contains(&Register::Pc) => {
+                offset = before_return
+            }
+            Instruction::Pop(registers) => offset += words(registers),
+            Instruction::Sub(_, Register::Sp, Register::Sp, Operand::Immediate(size)) => {
+                offset -= size
+            }
+            Instruction::Mov(_, Register::Sp, Operand::Register(Register::Fp)) => {
+                before_return = offset;
+                offset = -8;
+            }
+            _ => {}
+        }
+        deepest = deepest.min(offset);
+    }
+    if let Some(name) = function {
+        sizes.insert(name, -deepest as u32);
+    }
+    sizes
+}
+
+// Stores `r0` into the variable.
+fn emit_store(name: &str, code: &mut Vec<Instruction>, env: &Environment) {
+    match env.locals.get(name) {
+        Some(offset) => code.push(Instruction::Str(
+            Condition::Always,
+            Register::R0,
+            Address::Offset(Register::Fp, Operand::Immediate(*offset as i32)),
+        )),
+        None if env.globals.contains(name) => {
+            code.push(Instruction::LoadAddress(Register::R1, mangle::global(name)));
+            code.push(Instruction::Str(
+                Condition::Always,
+                Register::R0,
+                Address::Register(Register::R1),
+            ));
+        }
+        None => panic!("Assignment to an undefined variable `{}`", name),
+    }
+}
+
+fn emit_not(rd: Register, code: &mut Vec<Instruction>) {
+    code.push(Instruction::Cmp(rd, Operand::Immediate(0)));
+    code.push(mov(Condition::Eq, rd, 1));
+    code.push(mov(Condition::Ne, rd, 0));
+}
+
+fn emit_array_length(rd: Register, code: &mut Vec<Instruction>) {
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        rd,
+        Address::Offset(rd, Operand::Immediate(0)),
+    ));
+}
+
+fn emit_binary_operation(
+    ast: &Ast,
+    arithmetic: Arithmetic,
+    rd: Register,
+    lhs: Register,
+    rhs: Register,
+    code: &mut Vec<Instruction>,
+) {
+    match ast {
+        Ast::Addition(_, _) => code.push(Instruction::Add(
+            Condition::Always,
+            rd,
+            lhs,
+            Operand::Register(rhs),
+        )),
+        Ast::Subtraction(_, _) => code.push(Instruction::Sub(
+            Condition::Always,
+            rd,
+            lhs,
+            Operand::Register(rhs),
+        )),
+        Ast::Multiplication(_, _) => code.push(Instruction::Mul(rd, lhs, rhs)),
+        Ast::Division(_, _) => code.push(match arithmetic {
+            Arithmetic::Signed => Instruction::Sdiv(rd, lhs, rhs),
+            Arithmetic::Unsigned => Instruction::Udiv(rd, lhs, rhs),
+        }),
+        Ast::Equal(_, _) | Ast::NotEqual(_, _) => {
+            let equal = matches!(ast, Ast::Equal(_, _)) as i32;
+            code.push(Instruction::Cmp(lhs, Operand::Register(rhs)));
+            code.push(mov(Condition::Eq, rd, equal));
+            code.push(mov(Condition::Ne, rd, 1 - equal));
+        }
+        _ => unreachable!("{:?} is not a binary operation", ast),
+    }
+}
+
+// Applies the intrinsic to `lhs` and `rhs`, leaving the result in `rd`,
+// which is one of them. The smaller or the larger of the two takes a single
+// conditional move, equal operands are both the result.
+fn emit_binary_intrinsic(
+    name: &str,
+    rd: Register,
+    lhs: Register,
+    rhs: Register,
+    code: &mut Vec<Instruction>,
+) {
+    if name == ROTATE_INTRINSIC {
+        code.push(Instruction::Ror(
+            Condition::Always,
+            rd,
+            lhs,
+            Operand::Register(rhs),
+        ));
+        return;
+    }
+    code.push(Instruction::Cmp(lhs, Operand::Register(rhs)));
+    let (condition, other) = match (name == MIN_INTRINSIC, rd == lhs) {
+        (true, true) => (Condition::Gt, rhs),
+        (true, false) => (Condition::Lt, lhs),
+        (false, true) => (Condition::Lt, rhs),
+        (false, false) => (Condition::Gt, lhs),
+    };
+    code.push(Instruction::Mov(condition, rd, Operand::Register(other)));
+}
+
+// Applies the intrinsic to `rd` in place. The magnitude of the most
+// negative number is the number itself.
+fn emit_unary_intrinsic(name: &str, rd: Register, code: &mut Vec<Instruction>) {
+    if name == CLZ_INTRINSIC {
+        code.push(Instruction::Clz(rd, rd));
+        return;
+    }
+    code.push(Instruction::Cmp(rd, Operand::Immediate(0)));
+    code.push(Instruction::Rsb(
+        Condition::Lt,
+        rd,
+        rd,
+        Operand::Immediate(0),
+    ));
+}
+
+const FLOAT_REGISTERS: [FloatRegister; 4] = [
+    FloatRegister::S0,
+    FloatRegister::S1,
+    FloatRegister::S2,
+    FloatRegister::S3,
+];
+
+// Applies the float operation to the bits of `lhs` and `rhs` in the VFP,
+// leaving the bits of the result in `rd`.
+fn emit_float_operation(
+    name: &str,
+    rd: Register,
+    lhs: Register,
+    rhs: Register,
+    code: &mut Vec<Instruction>,
+) {
+    let (s0, s1) = (FloatRegister::S0, FloatRegister::S1);
+    code.push(Instruction::VmovToFloat(s0, lhs));
+    code.push(Instruction::VmovToFloat(s1, rhs));
+    if name == FLOAT_EQUAL {
+        // comparisons with NaN are unordered, which is not equal
+        code.push(Instruction::Vcmp(s0, s1));
+        code.push(Instruction::Vmrs);
+        code.push(mov(Condition::Eq, rd, 1));
+        code.push(mov(Condition::Ne, rd, 0));
+        return;
+    }
+    code.push(match name {
+        FLOAT_ADD => Instruction::Vadd(s0, s0, s1),
+        FLOAT_SUBTRACT => Instruction::Vsub(s0, s0, s1),
+        FLOAT_MULTIPLY => Instruction::Vmul(s0, s0, s1),
+        FLOAT_DIVIDE => Instruction::Vdiv(s0, s0, s1),
+        _ => unreachable!("{} is not a float operation", name),
+    });
+    code.push(Instruction::VmovFromFloat(rd, s0));
+}
+
+// Moves the arguments from `r0`-`r3`, one register per parameter, to where
+// the hard-float calling convention expects them: the floats to `s0`
+// onwards and the others down to the core registers from `r0`.
+fn emit_float_arguments(floats: &[bool], code: &mut Vec<Instruction>) {
+    let parameters = || floats.iter().enumerate();
+    for (register, (i, _)) in parameters().filter(|(_, float)| **float).enumerate() {
+        code.push(Instruction::VmovToFloat(
+            FLOAT_REGISTERS[register],
+            EXPRESSION_REGISTERS[i],
+        ));
+    }
+    // the arguments only move down, into registers already moved from
+    for (register, (i, _)) in parameters().filter(|(_, float)| !**float).enumerate() {
+        if register != i {
+            code.push(Instruction::Mov(
+                Condition::Always,
+                EXPRESSION_REGISTERS[register],
+                Operand::Register(EXPRESSION_REGISTERS[i]),
+            ));
+        }
+    }
+}
+
+// Undoes `emit_float_arguments` in the called function, so that every
+// argument is spilled from the register of its parameter.
+fn emit_receive_float_arguments(floats: &[bool], code: &mut Vec<Instruction>) {
+    let parameters = || floats.iter().enumerate();
+    let core: Vec<usize> = parameters()
+        .filter(|(_, float)| !**float)
+        .map(|(i, _)| i)
+        .collect();
+    // the last argument moves up first, into a register no argument is in
+    for (register, i) in core.iter().enumerate().rev() {
+        if register != *i {
+            code.push(Instruction::Mov(
+                Condition::Always,
+                EXPRESSION_REGISTERS[*i],
+                Operand::Register(EXPRESSION_REGISTERS[register]),
+            ));
+        }
+    }
+    for (register, (i, _)) in parameters().filter(|(_, float)| **float).enumerate() {
+        code.push(Instruction::VmovFromFloat(
+            EXPRESSION_REGISTERS[i],
+            FLOAT_REGISTERS[register],
+        ));
     }
 }
 
 impl Arm32Generator {
     pub fn new(ast: Ast) -> Arm32Generator {
-        Arm32Generator { ast }
+        Arm32Generator {
+            // `pop` writes the length of the array
+            constant_arrays_allowed: !deletes_arrays(&ast) && !calls(&ast, POP),
+            signatures: function_signatures(&ast),
+            exported: exported_functions(&ast),
+            ast,
+            options: CodegenOptions::default(),
+            next_label: Cell::new(0),
+            uses_bounds_error: Cell::new(false),
+            uses_division_error: Cell::new(false),
+            uses_null_error: Cell::new(false),
+            uses_number_to_string: Cell::new(false),
+            uses_string_equal: Cell::new(false),
+            uses_popcount: Cell::new(false),
+            uses_new_array: Cell::new(false),
+            uses_array_builtins: Cell::new(false),
+            uses_arguments: Cell::new(false),
+            constant_arrays: RefCell::new(Vec::new()),
+            source_files: Vec::new(),
+            source_lines: Vec::new(),
+            statement_lines: RefCell::new(BTreeMap::new()),
+            coverage_counters: RefCell::new(BTreeMap::new()),
+            profile_entries: RefCell::new(BTreeMap::new()),
+            frame_layouts: RefCell::new(Vec::new()),
+            source_ranges: RefCell::new(Vec::new()),
+            instruction_sources: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn with_options(mut self, options: CodegenOptions) -> Arm32Generator {
+        self.options = options;
+        self
+    }
+
+    /// The source files the program was compiled from and the lines of its
+    /// statements found by `lines::statement_lines`, needed by the source
+    /// comments and the debug info.
+    pub fn with_source(mut self, files: Vec<String>, lines: Vec<SourceLine>) -> Arm32Generator {
+        self.source_files = files;
+        self.source_lines = lines;
+        self
+    }
+
+    /// The program the code is generated for.
+    pub fn program(&self) -> &Ast {
+        &self.ast
+    }
+
+    /// The frames of the functions, in the order of their definitions, as
+    /// laid out by the last `generate`.
+    pub fn frame_layouts(&self) -> Vec<FrameLayout> {
+        self.frame_layouts.borrow().clone()
+    }
+
+    /// For every instruction of the last `generate`, the index of the line
+    /// of the statement it comes from among the ones given to `with_source`.
+    /// Only known with `CodegenOptions::source_map`, the runtime and the data
+    /// come from no statement.
+    pub fn instruction_sources(&self) -> Vec<Option<usize>> {
+        self.instruction_sources.borrow().clone()
+    }
+
+    /// Lowers the whole AST into a list of instructions.
+    pub fn generate(&self, env: &mut Environment) -> Vec<Instruction> {
+        self.next_label.set(0);
+        self.frame_layouts.borrow_mut().clear();
+        self.source_ranges.borrow_mut().clear();
+        self.uses_bounds_error.set(false);
+        self.uses_division_error.set(false);
+        self.uses_null_error.set(false);
+        self.uses_number_to_string.set(false);
+        self.uses_string_equal.set(false);
+        self.uses_popcount.set(false);
+        self.uses_new_array.set(false);
+        self.uses_array_builtins.set(false);
+        self.uses_arguments.set(false);
+        self.constant_arrays.borrow_mut().clear();
+        *self.statement_lines.borrow_mut() = lines::statements(&self.ast)
+            .into_iter()
+            .zip(0..self.source_lines.len())
+            .map(|(statement, line)| (statement as *const Ast, line))
+            .collect();
+        *self.coverage_counters.borrow_mut() = if self.options.coverage {
+            coverage::blocks(&self.ast)
+                .into_iter()
+                .enumerate()
+                .map(|(counter, statement)| (statement as *const Ast, counter))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+        *self.profile_entries.borrow_mut() = if self.options.profile {
+            profile::functions(&self.ast)
+                .into_iter()
+                .enumerate()
+                .map(|(entry, name)| (String::from(name), entry))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+        let mut code = Vec::new();
+        self.emit_header(&mut code);
+        if self.options.debug_info {
+            for (i, file) in self.source_files.iter().enumerate() {
+                code.push(Instruction::File(i + 1, file.clone()));
+            }
+        }
+        let globals = global_variables(&self.ast);
+        env.globals
+            .extend(globals.iter().map(|(name, _)| String::from(*name)));
+        match &self.ast {
+            Ast::Block(statements) => {
+                for statement in statements {
+                    self.emit_top_level(statement, &mut code, env);
+                }
+            }
+            statement => self.emit_top_level(statement, &mut code, env),
+        }
+        self.emit_entry(&mut code);
+        Arm32Generator::emit_globals(&globals, &mut code);
+        self.emit_constant_arrays(&mut code);
+        if self.uses_bounds_error.get() {
+            self.emit_error_routine(
+                BOUNDS_ERROR_ROUTINE,
+                "error: array index out of bounds\n",
+                &mut code,
+            );
+        }
+        if self.uses_division_error.get() {
+            self.emit_error_routine(
+                DIVISION_ERROR_ROUTINE,
+                "error: division by zero\n",
+                &mut code,
+            );
+        }
+        if self.uses_null_error.get() {
+            self.emit_error_routine(
+                NULL_ERROR_ROUTINE,
+                "error: array is null or undefined\n",
+                &mut code,
+            );
+        }
+        if self.uses_number_to_string.get() {
+            runtime::emit_number_to_string(&mut code);
+        }
+        if self.uses_array_builtins.get() {
+            runtime::emit_array_builtins(&mut code);
+        }
+        if self.uses_new_array.get() {
+            runtime::emit_new_array(self.alloc_routine(), &mut code);
+        }
+        if self.uses_string_equal.get() {
+            runtime::emit_string_equal(&mut code);
+        }
+        if self.uses_popcount.get() {
+            runtime::emit_popcount(&mut code);
+        }
+        if self.options.gc {
+            gc::emit_runtime(&mut code);
+        }
+        if self.options.coverage {
+            coverage::emit_runtime(
+                self.options.platform,
+                self.options.console,
+                self.coverage_counters.borrow().len(),
+                &mut code,
+            );
+        }
+        if self.options.profile {
+            profile::emit_runtime(
+                self.options.platform,
+                self.options.console,
+                profile::functions(&self.ast).len(),
+                &mut code,
+            );
+        }
+        runtime::emit_runtime(
+            self.options.platform,
+            self.options.console,
+            self.uses_arguments.get(),
+            &mut code,
+        );
+        let mut sources = vec![None; code.len()];
+        for &(start, end, line) in self.source_ranges.borrow().iter() {
+            sources[start..end].fill(Some(line));
+        }
+        if !self.options.target.hardware_division {
+            let (lowered, origins) = division::lower_mapped(code);
+            code = lowered;
+            sources = origins.into_iter().map(|origin| sources[origin]).collect();
+            division::emit_routines(&mut code);
+            sources.resize(code.len(), None);
+        }
+        if self.options.passes.contains(Pass::SimplifyBranches) {
+            let (simplified, origins) = branches::simplify_mapped(code);
+            code = simplified;
+            sources = origins.into_iter().map(|origin| sources[origin]).collect();
+        }
+        if self.options.passes.contains(Pass::CopyPropagation) {
+            let (propagated, origins) = copy_propagation::propagate_mapped(code);
+            code = propagated;
+            sources = origins.into_iter().map(|origin| sources[origin]).collect();
+        }
+        if self.options.passes.contains(Pass::Schedule) {
+            let (scheduled, origins) = schedule::schedule_mapped(code);
+            code = scheduled;
+            sources = origins.into_iter().map(|origin| sources[origin]).collect();
+        }
+        if self.options.thumb {
+            let (thumb, origins) = thumb::thumb_mapped(code);
+            code = thumb;
+            sources = origins.into_iter().map(|origin| sources[origin]).collect();
+        }
+        *self.instruction_sources.borrow_mut() = if self.options.source_map {
+            sources
+        } else {
+            Vec::new()
+        };
+        code
+    }
+
+    fn emit_header(&self, code: &mut Vec<Instruction>) {
+        let mut directives = Vec::new();
+        match (self.options.header, self.options.thumb) {
+            (AsmHeader::None, false) => return,
+            // `it` and the rewritten instructions need the unified syntax
+            (AsmHeader::None, true) => directives.push(".syntax unified"),
+            (AsmHeader::Unified, _) => {
+                directives.push(".syntax unified");
+                let cortex_m = self.options.thumb && self.options.platform == Platform::BareMetal;
+                match self.options.target.arch {
+                    Arch::Armv6 => directives.push(".arch armv6"),
+                    // the Cortex-M cores, whose division is not an extension
+                    Arch::Armv7 if cortex_m => directives.push(".arch armv7-m"),
+                    Arch::Armv7 if self.options.target.hardware_division => {
+                        directives.extend([".arch armv7-a", ".arch_extension idiv"])
+                    }
+                    Arch::Armv7 => directives.push(".arch armv7-a"),
+                }
+                let fpu = match self.options.target.arch {
+                    Arch::Armv6 => ".fpu vfpv2",
+                    // the single precision unit of the Cortex-M4F
+                    Arch::Armv7 if cortex_m => ".fpu fpv4-sp-d16",
+                    Arch::Armv7 => ".fpu vfpv3-d16",
+                };
+                if self.float_abi() == FloatAbi::Hard {
+                    // arm-linux-gnueabihf passes arguments in VFP registers,
+                    // which the linker checks against the C library
+                    directives.extend([fpu, ".eabi_attribute Tag_ABI_VFP_args, 1"]);
+                } else if FLOAT_OPERATIONS.iter().any(|name| calls(&self.ast, name)) {
+                    directives.push(fpu);
+                }
+            }
+        }
+        directives.push(if self.options.thumb { ".thumb" } else { ".arm" });
+        code.extend(
+            directives
+                .into_iter()
+                .map(|directive| Instruction::Directive(String::from(directive))),
+        );
+        code.push(Instruction::Section(String::from(".text")));
+    }
+
+    fn emit_top_level(&self, ast: &Ast, code: &mut Vec<Instruction>, env: &mut Environment) {
+        // globals are emitted into the data section
+        if !matches!(ast, Ast::Var(_, _)) {
+            self.emit_ast(ast, code, env);
+        }
+    }
+
+    // Globals starting at zero go to `.bss`, the rest to `.data`.
+    fn emit_globals(globals: &[(&str, i32)], code: &mut Vec<Instruction>) {
+        let (zeroed, initialized): (Vec<_>, Vec<_>) =
+            globals.iter().copied().partition(|(_, value)| *value == 0);
+        for (section, globals) in [(".data", initialized), (".bss", zeroed)] {
+            if globals.is_empty() {
+                continue;
+            }
+            code.push(Instruction::Section(String::from(section)));
+            code.push(Instruction::Balign(4));
+            for (name, value) in globals {
+                code.push(Instruction::Label(mangle::global(name)));
+                if value == 0 {
+                    code.push(Instruction::Space(4));
+                } else {
+                    code.push(Instruction::Word(value));
+                }
+            }
+            code.push(Instruction::Section(String::from(".text")));
+        }
+    }
+
+    // Label of the read-only array holding the values, identical arrays are
+    // only emitted once.
+    fn constant_array(&self, values: Vec<i32>) -> String {
+        let mut constant_arrays = self.constant_arrays.borrow_mut();
+        if let Some((label, _)) = constant_arrays.iter().find(|(_, other)| *other == values) {
+            return label.clone();
+        }
+        let label = self.make_label();
+        constant_arrays.push((label.clone(), values));
+        label
+    }
+
+    fn emit_constant_arrays(&self, code: &mut Vec<Instruction>) {
+        let constant_arrays = self.constant_arrays.borrow();
+        if constant_arrays.is_empty() {
+            return;
+        }
+        code.push(Instruction::Section(String::from(".rodata")));
+        code.push(Instruction::Balign(4));
+        for (label, values) in constant_arrays.iter() {
+            // full, so that `push` copies the array instead of writing to it
+            code.push(Instruction::Word(values.len() as i32));
+            code.push(Instruction::Label(label.clone()));
+            code.push(Instruction::Word(values.len() as i32));
+            code.extend(values.iter().map(|value| Instruction::Word(*value)));
+        }
+        code.push(Instruction::Section(String::from(".text")));
+    }
+
+    // Loads the element into `rd`, checking the index according to the
+    // bounds check option. Both `array` and `index` are clobbered, `length`
+    // is used as a scratch.
+    fn emit_array_lookup(
+        &self,
+        rd: Register,
+        array: Register,
+        index: Register,
+        length: Register,
+        code: &mut Vec<Instruction>,
+    ) {
+        let in_bounds = match self.options.bounds_check {
+            BoundsCheck::Zero => Condition::Lo,
+            BoundsCheck::Trap | BoundsCheck::Off => Condition::Always,
+        };
+        if self.options.bounds_check != BoundsCheck::Off {
+            code.push(Instruction::Ldr(
+                Condition::Always,
+                length,
+                Address::Register(array),
+            ));
+            code.push(Instruction::Cmp(index, Operand::Register(length)));
+        }
+        match self.options.bounds_check {
+            BoundsCheck::Zero => code.push(mov(Condition::Hs, rd, 0)),
+            BoundsCheck::Trap => {
+                self.uses_bounds_error.set(true);
+                code.push(Instruction::B(
+                    Condition::Hs,
+                    String::from(BOUNDS_ERROR_ROUTINE),
+                ));
+            }
+            BoundsCheck::Off => {}
+        }
+        // skip the length stored in the first word
+        code.push(Instruction::Add(
+            in_bounds,
+            array,
+            array,
+            Operand::Immediate(4),
+        ));
+        // multiply index by four
+        code.push(Instruction::Lsl(
+            in_bounds,
+            index,
+            index,
+            Operand::Immediate(2),
+        ));
+        code.push(Instruction::Ldr(
+            in_bounds,
+            rd,
+            Address::Offset(array, Operand::Register(index)),
+        ));
+    }
+
+    // Traps when the divisor in `rhs` is zero, unless it is a constant.
+    fn emit_division_check(&self, divisor: &Ast, rhs: Register, code: &mut Vec<Instruction>) {
+        if !self.options.division_check || matches!(constant(divisor), Some(value) if value != 0) {
+            return;
+        }
+        self.uses_division_error.set(true);
+        code.push(Instruction::Cmp(rhs, Operand::Immediate(0)));
+        code.push(Instruction::B(
+            Condition::Eq,
+            String::from(DIVISION_ERROR_ROUTINE),
+        ));
+    }
+
+    // Traps when the array pointer in `rd` is zero, unless the array comes
+    // from a literal.
+    fn emit_null_check(&self, array: &Ast, rd: Register, code: &mut Vec<Instruction>) {
+        if !self.options.null_check || matches!(array, Ast::ArrayLiteral(_) | Ast::String(_)) {
+            return;
+        }
+        self.uses_null_error.set(true);
+        code.push(Instruction::Cmp(rd, Operand::Immediate(0)));
+        code.push(Instruction::B(
+            Condition::Eq,
+            String::from(NULL_ERROR_ROUTINE),
+        ));
+    }
+
+    // Prints the error to the standard error and aborts, used by the `trap`
+    // bounds check, the division check and the null check.
+    fn emit_error_routine(&self, routine: &str, message: &str, code: &mut Vec<Instruction>) {
+        let message_label = format!(".L{}_message", routine.trim_start_matches("__rtsc_"));
+        code.push(Instruction::Label(String::from(routine)));
+        match self.options.platform {
+            Platform::Linux => {
+                code.push(mov(Condition::Always, Register::R0, 2));
+                code.push(Instruction::LoadAddress(
+                    Register::R1,
+                    message_label.clone(),
+                ));
+                code.push(mov(Condition::Always, Register::R2, message.len() as i32));
+                if self.options.console == Console::Syscall {
+                    code.push(mov(Condition::Always, Register::R7, SYS_WRITE));
+                    code.push(Instruction::Svc(0));
+                    code.push(mov(Condition::Always, Register::R0, ABORT_STATUS));
+                    code.push(mov(Condition::Always, Register::R7, SYS_EXIT));
+                    code.push(Instruction::Svc(0));
+                } else {
+                    code.push(Instruction::Bl(String::from("write")));
+                    code.push(Instruction::Bl(String::from("abort")));
+                }
+            }
+            Platform::BareMetal => {
+                runtime::emit_print(self.options.console, &message_label, code);
+                code.push(Instruction::B(
+                    Condition::Always,
+                    String::from(runtime::ABORT_ROUTINE),
+                ));
+            }
+        }
+        code.push(Instruction::Section(String::from(".rodata")));
+        code.push(Instruction::Label(message_label));
+        code.push(Instruction::Asciz(String::from(message)));
+        code.push(Instruction::Section(String::from(".text")));
+    }
+
+    fn alloc_routine(&self) -> &'static str {
+        if self.options.gc {
+            gc::ALLOC_ROUTINE
+        } else if self.options.platform == Platform::BareMetal {
+            runtime::ALLOC_ROUTINE
+        } else {
+            "malloc"
+        }
+    }
+
+    // The convention of the target when it does not give one, the one of the
+    // C library on Linux and the one of arm-none-eabi on bare metal.
+    fn float_abi(&self) -> FloatAbi {
+        self.options
+            .target
+            .float_abi
+            .unwrap_or(match self.options.platform {
+                Platform::Linux => FloatAbi::Hard,
+                Platform::BareMetal => FloatAbi::Soft,
+            })
+    }
+
+    // The hard-float calling convention of arm-linux-gnueabihf passes floats
+    // in the VFP registers, so C code can call the functions and be called.
+    // Gives which parameters of the function are floats and whether it
+    // returns one, `None` when nothing has to move. The soft-float
+    // convention, bare metal code's unless the target says otherwise, keeps
+    // floats in the core registers like arm-none-eabi.
+    fn float_signature(&self, name: &str) -> Option<(Vec<bool>, bool)> {
+        if self.float_abi() != FloatAbi::Hard {
+            return None;
+        }
+        match self.signatures.get(name)? {
+            Type::Function {
+                parameter_types,
+                return_type,
+            } => {
+                let floats: Vec<bool> = parameter_types
+                    .values()
+                    .map(|parameter| *parameter == Type::Float)
+                    .collect();
+                let result = **return_type == Type::Float;
+                (result || floats.contains(&true)).then_some((floats, result))
+            }
+            _ => None,
+        }
+    }
+
+    // Calls the function with the arguments in `r0`-`r3`, leaving the result
+    // in `r0`.
+    fn emit_call(&self, name: &str, code: &mut Vec<Instruction>) {
+        let float_signature = self.float_signature(name);
+        if let Some((floats, _)) = &float_signature {
+            emit_float_arguments(floats, code);
+        }
+        code.push(Instruction::Bl(self.function_label(name)));
+        if let Some((_, true)) = float_signature {
+            code.push(Instruction::VmovFromFloat(Register::R0, FloatRegister::S0));
+        }
+    }
+
+    // The program's `main` is renamed when a wrapper is emitted under the
+    // name the linker looks for, `putchar` goes to the runtime when the C
+    // library does not provide it and so do the other builtins.
+    fn function_label(&self, name: &str) -> String {
+        match name {
+            "main" if self.options.entry != Entry::None => String::from(MAIN_FUNCTION),
+            "putchar" if self.options.console != Console::Libc => {
+                String::from(runtime::PUTCHAR_ROUTINE)
+            }
+            NUMBER_TO_STRING => {
+                self.uses_number_to_string.set(true);
+                self.uses_new_array.set(true);
+                String::from(runtime::NUMBER_TO_STRING_ROUTINE)
+            }
+            PUSH | POP | CONCAT | SLICE => {
+                self.uses_array_builtins.set(true);
+                self.uses_new_array.set(true);
+                String::from(match name {
+                    PUSH => runtime::PUSH_ROUTINE,
+                    POP => runtime::POP_ROUTINE,
+                    CONCAT => runtime::CONCAT_ROUTINE,
+                    _ => runtime::SLICE_ROUTINE,
+                })
+            }
+            runtime::STRING_EQUAL_ROUTINE => {
+                self.uses_string_equal.set(true);
+                String::from(name)
+            }
+            runtime::POPCOUNT_ROUTINE => {
+                self.uses_popcount.set(true);
+                String::from(name)
+            }
+            ARG_COUNT | ARG_NUMBER => {
+                self.uses_arguments.set(true);
+                String::from(if name == ARG_COUNT {
+                    runtime::ARG_COUNT_ROUTINE
+                } else {
+                    runtime::ARG_NUMBER_ROUTINE
+                })
+            }
+            name => String::from(name),
+        }
+    }
+
+    fn emit_entry(&self, code: &mut Vec<Instruction>) {
+        match self.options.entry {
+            Entry::None => {}
+            Entry::Main => {
+                code.push(Instruction::Global(String::from("main")));
+                code.push(Instruction::Label(String::from("main")));
+                // `lr` is only pushed to keep the stack 8 byte aligned,
+                // `exit` does not return
+                code.push(Instruction::Push(vec![Register::Fp, Register::Lr]));
+                if self.uses_arguments.get() {
+                    runtime::emit_save_arguments(Register::R0, Register::R1, Register::R2, code);
+                }
+                code.push(Instruction::Bl(String::from(MAIN_FUNCTION)));
+                self.emit_dumps(code);
+                code.push(Instruction::Bl(String::from("exit")));
+            }
+            Entry::Start => {
+                code.push(Instruction::Global(String::from("_start")));
+                code.push(Instruction::Label(String::from("_start")));
+                // the outermost frame, for debuggers walking the stack
+                code.push(mov(Condition::Always, Register::Fp, 0));
+                code.push(mov(Condition::Always, Register::Lr, 0));
+                // the kernel leaves `argc` on the stack, followed by `argv`,
+                // a bare metal program has none
+                if self.uses_arguments.get() && self.options.platform == Platform::Linux {
+                    code.push(Instruction::Ldr(
+                        Condition::Always,
+                        Register::R0,
+                        Address::Register(Register::Sp),
+                    ));
+                    code.push(Instruction::Add(
+                        Condition::Always,
+                        Register::R1,
+                        Register::Sp,
+                        Operand::Immediate(4),
+                    ));
+                    runtime::emit_save_arguments(Register::R0, Register::R1, Register::R2, code);
+                }
+                if self.options.profile {
+                    profile::emit_start_clock(self.options.platform, code);
+                }
+                code.push(Instruction::Bl(String::from(MAIN_FUNCTION)));
+                self.emit_dumps(code);
+                match self.options.platform {
+                    Platform::Linux => {
+                        code.push(mov(Condition::Always, Register::R7, SYS_EXIT));
+                        code.push(Instruction::Svc(0));
+                    }
+                    Platform::BareMetal => runtime::emit_exit(
+                        self.options.console,
+                        runtime::ADP_STOPPED_APPLICATION_EXIT,
+                        code,
+                    ),
+                }
+            }
+        }
+    }
+
+    // Writes the coverage counters and the profile once `main` returned its
+    // status in `r0`.
+    fn emit_dumps(&self, code: &mut Vec<Instruction>) {
+        let dumps = [
+            (self.options.coverage, coverage::DUMP_ROUTINE),
+            (self.options.profile, profile::DUMP_ROUTINE),
+        ];
+        for (enabled, routine) in dumps {
+            if enabled {
+                code.push(push_r0());
+                code.push(Instruction::Bl(String::from(routine)));
+                code.push(Instruction::Pop(vec![Register::R0, Register::Ip]));
+            }
+        }
     }
 
-    fn make_initial_function_environment(params: &[String]) -> Environment {
+    // Counts the run of the basic block starting with the statement.
+    fn emit_coverage_increment(&self, statement: &Ast, code: &mut Vec<Instruction>) {
+        if let Some(&counter) = self
+            .coverage_counters
+            .borrow()
+            .get(&(statement as *const Ast))
+        {
+            coverage::emit_increment(counter, code);
+        }
+    }
+
+    fn make_label(&self) -> String {
+        let label = self.next_label.get();
+        self.next_label.set(label + 1);
+        format!(".L{}", label)
+    }
+
+    fn make_initial_function_environment(&self, params: &[String]) -> Environment {
         let locals = params
             .iter()
             .enumerate()
             .map(|(i, name)| (name.clone(), 4 * i as isize - 16))
             .collect();
-        Environment::new(locals, -20)
+        Environment::new(locals, -20 - self.frame_link_size() as isize)
     }
-    fn emit_prologue(buffer: &mut String) {
-        buffer.push_str("    push {fp, lr}\n");
-        buffer.push_str("    mov fp, sp\n");
-        buffer.push_str("    push {r0, r1, r2, r3}\n");
+
+    // Bytes between the spilled arguments and the locals taken by the link
+    // to the previous frame of the collector's shadow stack and the time of
+    // the call kept by the profiler.
+    fn frame_link_size(&self) -> i32 {
+        let profile_size = if self.options.profile { 8 } else { 0 };
+        self.gc_link_size() + profile_size
     }
-    fn emit_epilogue(buffer: &mut String) {
-        buffer.push_str("    mov sp, fp\n"); // deallocate stack
-        buffer.push_str("    mov r0, #0\n");
-        buffer.push_str("    pop {fp, pc}\n");
+
+    fn gc_link_size(&self) -> i32 {
+        if self.options.gc {
+            gc::LINK_SIZE
+        } else {
+            0
+        }
     }
 
-    fn emit_ast(&self, ast: &Ast, buffer: &mut String, env: &mut Environment) {
-        match ast {
-            Ast::Block(statements) => {
-                buffer.push('\n');
-                for statement in statements {
-                    self.emit_ast(statement, buffer, env);
+    // Offset from `fp` of the time of the call, below the link of the
+    // collector.
+    fn profile_offset(&self) -> i32 {
+        -20 - self.gc_link_size()
+    }
+    // The AAPCS requires `sp` to be 8 byte aligned at every call, so the
+    // frame is built from 8 byte multiples only:
+    //
+    //   fp + 4        saved lr
+    //   fp            saved fp
+    //   fp - 16 ..    spilled r0-r3 holding the arguments
+    //   fp - 20 ..    locals, rounded up to 8 bytes
+    //
+    // With `--gc` the locals move 8 bytes down, `fp - 20` holding the link
+    // to the previous frame of the collector's shadow stack. With
+    // `--instrument-profile` they move 8 more bytes, the first word below
+    // the link holding the time of the call.
+    //
+    // Below that every temporary push pairs its register with `ip` and the
+    // call sequence reserves all four argument words.
+    fn emit_prologue(&self, code: &mut Vec<Instruction>, locals_size: i32) {
+        let frame_size = locals_size + self.frame_link_size();
+        code.push(Instruction::Push(vec![Register::Fp, Register::Lr]));
+        code.push(Instruction::Mov(
+            Condition::Always,
+            Register::Fp,
+            Operand::Register(Register::Sp),
+        ));
+        code.push(Instruction::Push(vec![
+            Register::R0,
+            Register::R1,
+            Register::R2,
+            Register::R3,
+        ]));
+        if frame_size > 0 {
+            code.push(Instruction::Sub(
+                Condition::Always,
+                Register::Sp,
+                Register::Sp,
+                Operand::Immediate(frame_size),
+            ));
+        }
+        if self.options.gc {
+            gc::emit_enter_frame(code);
+        }
+    }
+    fn emit_epilogue(&self, code: &mut Vec<Instruction>, env: &Environment) {
+        if let Some(entry) = env.profile_entry {
+            profile::emit_exit(entry, self.profile_offset(), code);
+        }
+        if self.options.gc {
+            gc::emit_leave_frame(code);
+        }
+        // deallocate stack
+        code.push(Instruction::Mov(
+            Condition::Always,
+            Register::Sp,
+            Operand::Register(Register::Fp),
+        ));
+        code.push(mov(Condition::Always, Register::R0, 0));
+        if env.float_result {
+            code.push(Instruction::VmovToFloat(FloatRegister::S0, Register::R0));
+        }
+        code.push(Instruction::Pop(vec![Register::Fp, Register::Pc]));
+    }
+
+    // Evaluates an expression that does not call any function into `target`,
+    // using only the `free` registers for the intermediate results. Both
+    // operands of an operation are evaluated in registers, the left hand side
+    // ends up in `target` and the right hand side in `free[0]`. The operand
+    // needing more registers goes first so that the other one can be
+    // evaluated with what is left. With a stack of its own, like
+    // `emit_expression`, the operation itself is emitted by the step after
+    // the ones of its operands.
+    fn emit_in_registers(
+        &self,
+        ast: &Ast,
+        target: Register,
+        free: &[Register],
+        needs: &RegisterNeeds,
+        code: &mut Vec<Instruction>,
+        env: &mut Environment,
+    ) {
+        enum Step<'a> {
+            Emit(&'a Ast, Register, Vec<Register>),
+            Operation(&'a Ast, Register, Vec<Register>),
+        }
+        let mut steps = vec![Step::Emit(ast, target, free.to_vec())];
+        while let Some(step) = steps.pop() {
+            let (ast, target, free) = match step {
+                Step::Emit(ast, target, free) => (ast, target, free),
+                Step::Operation(ast, target, free) => {
+                    self.emit_register_operation(ast, target, &free, code);
+                    continue;
+                }
+            };
+            let (lhs, rhs) = match ast {
+                Ast::Undefined | Ast::Null => {
+                    code.push(mov(Condition::Always, target, 0));
+                    continue;
+                }
+                Ast::Number(value) => {
+                    code.push(Instruction::LoadConstant(target, *value));
+                    continue;
                 }
+                Ast::Float(value) => {
+                    code.push(Instruction::LoadConstant(target, value.to_bits() as i32));
+                    continue;
+                }
+                Ast::Bool(value) => {
+                    code.push(mov(Condition::Always, target, *value as i32));
+                    continue;
+                }
+                // strings cannot be deleted, so they are always read-only
+                Ast::String(text) => {
+                    code.push(Instruction::LoadAddress(
+                        target,
+                        self.constant_array(character_codes(text)),
+                    ));
+                    continue;
+                }
+                Ast::Identifier(name) => {
+                    match env.locals.get(name) {
+                        Some(offset) => code.push(Instruction::Ldr(
+                            Condition::Always,
+                            target,
+                            Address::Offset(Register::Fp, Operand::Immediate(*offset as i32)),
+                        )),
+                        None if env.globals.contains(name) => {
+                            code.push(Instruction::LoadAddress(target, mangle::global(name)));
+                            code.push(Instruction::Ldr(
+                                Condition::Always,
+                                target,
+                                Address::Register(target),
+                            ));
+                        }
+                        None => panic!("Tried to use an undefined name {}", name),
+                    }
+                    continue;
+                }
+                Ast::Multiplication(_, _) | Ast::Division(_, _) if shift_operand(ast).is_some() => {
+                    let (operand, _) = shift_operand(ast).unwrap();
+                    steps.push(Step::Operation(ast, target, free.clone()));
+                    steps.push(Step::Emit(operand, target, free));
+                    continue;
+                }
+                Ast::Not(operand) | Ast::ArrayLength(operand) => {
+                    steps.push(Step::Operation(ast, target, free.clone()));
+                    steps.push(Step::Emit(operand, target, free));
+                    continue;
+                }
+                Ast::Call(name, args) if INTRINSICS.contains(&name.as_str()) && args.len() == 1 => {
+                    steps.push(Step::Operation(ast, target, free.clone()));
+                    steps.push(Step::Emit(&args[0], target, free));
+                    continue;
+                }
+                Ast::ArrayLookup(lhs, rhs)
+                | Ast::Addition(lhs, rhs)
+                | Ast::Subtraction(lhs, rhs)
+                | Ast::Multiplication(lhs, rhs)
+                | Ast::Division(lhs, rhs)
+                | Ast::Equal(lhs, rhs)
+                | Ast::NotEqual(lhs, rhs) => (lhs.as_ref(), rhs.as_ref()),
+                Ast::Call(name, args)
+                    if FLOAT_OPERATIONS.contains(&name.as_str())
+                        || INTRINSICS.contains(&name.as_str()) =>
+                {
+                    (&args[0], &args[1])
+                }
+                _ => unreachable!("{:?} does not fit in registers", ast),
+            };
+            let lhs_need = needs[&(lhs as *const Ast)].expect("operand fits in registers");
+            let rhs_need = needs[&(rhs as *const Ast)].expect("operand fits in registers");
+            steps.push(Step::Operation(ast, target, free.clone()));
+            if lhs_need >= rhs_need {
+                steps.push(Step::Emit(rhs, free[0], free[1..].to_vec()));
+                steps.push(Step::Emit(lhs, target, free));
+            } else {
+                let mut temporaries = vec![target];
+                temporaries.extend_from_slice(&free[1..]);
+                steps.push(Step::Emit(lhs, target, free[1..].to_vec()));
+                steps.push(Step::Emit(rhs, free[0], temporaries));
             }
-            Ast::Undefined => {
-                buffer.push('\n');
-                buffer.push_str("    mov r0, #0\n");
+        }
+    }
+
+    // The operation of `emit_in_registers` once its operands are in
+    // `target` and `free[0]`.
+    fn emit_register_operation(
+        &self,
+        ast: &Ast,
+        target: Register,
+        free: &[Register],
+        code: &mut Vec<Instruction>,
+    ) {
+        match ast {
+            Ast::Not(_) => emit_not(target, code),
+            Ast::ArrayLength(array) => {
+                self.emit_null_check(array, target, code);
+                emit_array_length(target, code);
             }
-            Ast::Null => {
-                buffer.push('\n');
-                buffer.push_str("    mov r0, #0\n");
+            Ast::ArrayLookup(array, _) => {
+                self.emit_null_check(array, target, code);
+                self.emit_array_lookup(target, target, free[0], free[1], code);
             }
-            Ast::Number(value) => {
-                buffer.push('\n');
-                buffer.push_str(&format!("    ldr r0, ={}\n", value));
+            Ast::Multiplication(_, _) | Ast::Division(_, _) if shift_operand(ast).is_some() => {
+                let (_, shift) = shift_operand(ast).unwrap();
+                emit_shift(ast, self.options.arithmetic, target, shift, code);
             }
-            Ast::Bool(value) => {
-                buffer.push('\n');
-                if *value {
-                    buffer.push_str("    mov r0, #1\n");
-                } else {
-                    buffer.push_str("    mov r0, #0\n");
-                }
-            }
-            Ast::ArrayLiteral(elements) => {
-                let length = elements.len();
-                let size = 4 * (length + 1); // +1 because we will have length stored as
-                                             // a first word in the memory chunk
-                buffer.push('\n');
-                buffer.push_str(&format!("    ldr r0, ={}\n", size));
-                buffer.push_str("    bl malloc\n");
-                buffer.push_str("    push {r4, ip}\n");
-                buffer.push_str("    mov r4, r0\n");
-                buffer.push_str(&format!("    ldr r0, ={}\n", length));
-                buffer.push_str("    str r0, [r4]\n"); // store length of array on the first byte
-
-                for (i, elem) in elements.iter().enumerate() {
-                    self.emit_ast(elem, buffer, env);
-                    buffer.push_str(&format!("    str r0, [r4, #{}]\n", 4 * (i + 1)));
-                }
-                buffer.push_str("    mov r0, r4\n");
-                buffer.push_str("    pop {r4, ip}\n");
-            }
-            Ast::ArrayLookup(array, index) => {
-                self.emit_ast(array, buffer, env);
-                buffer.push_str("    push {r0, ip}\n");
-                self.emit_ast(index, buffer, env);
-                buffer.push_str("    pop {r1, ip}\n"); // r1 points to first element of array
-                buffer.push_str("    ldr r2, [r1]\n");
-                buffer.push_str("    cmp r0, r2\n");
-                buffer.push_str("    movhs r0, #0\n");
-                buffer.push_str("    addlo r1, r1, #4\n");
-                buffer.push_str("    lsllo r0, r0, #2\n"); // multiply index by four
-                buffer.push_str("    ldrlo r0, [r1, r0]\n");
+            Ast::Addition(_, _)
+            | Ast::Subtraction(_, _)
+            | Ast::Multiplication(_, _)
+            | Ast::Division(_, _)
+            | Ast::Equal(_, _)
+            | Ast::NotEqual(_, _) => {
+                if let Ast::Division(_, divisor) = ast {
+                    self.emit_division_check(divisor, free[0], code);
+                }
+                emit_binary_operation(ast, self.options.arithmetic, target, target, free[0], code);
             }
-            Ast::ArrayLength(array) => {
-                self.emit_ast(array, buffer, env);
-                buffer.push_str("    ldr r0, [r0, #0]\n");
-            }
-            Ast::Not(expr) => {
-                buffer.push('\n');
-                self.emit_ast(expr, buffer, env);
-                buffer.push_str("    cmp r0, #0\n");
-                buffer.push_str("    moveq r0, #1\n");
-                buffer.push_str("    movne r0, #0\n");
-            }
-            Ast::Addition(lhs, rhs) => {
-                buffer.push('\n');
-                self.emit_ast(lhs, buffer, env);
-                buffer.push_str("    push {r0, ip}\n");
-                self.emit_ast(rhs, buffer, env);
-                buffer.push_str("    pop {r1, ip}\n");
-                buffer.push_str("    add r0, r0, r1\n");
-            }
-            Ast::Subtraction(lhs, rhs) => {
-                buffer.push('\n');
-                self.emit_ast(lhs, buffer, env);
-                buffer.push_str("    push {r0, ip}\n");
-                self.emit_ast(rhs, buffer, env);
-                buffer.push_str("    pop {r1, ip}\n");
-                buffer.push_str("    sub r0, r1, r0\n");
-            }
-            Ast::Multiplication(lhs, rhs) => {
-                buffer.push('\n');
-                self.emit_ast(lhs, buffer, env);
-                buffer.push_str("    push {r0, ip}\n");
-                self.emit_ast(rhs, buffer, env);
-                buffer.push_str("    pop {r1, ip}\n");
-                buffer.push_str("    mul r0, r0, r1\n");
-            }
-            Ast::Division(lhs, rhs) => {
-                buffer.push('\n');
-                self.emit_ast(lhs, buffer, env);
-                buffer.push_str("    push {r0, ip}\n");
-                self.emit_ast(rhs, buffer, env);
-                buffer.push_str("    pop {r1, ip}\n");
-                buffer.push_str("    udiv r0, r0, r1\n");
-            }
-            Ast::Equal(lhs, rhs) => {
-                buffer.push('\n');
-                self.emit_ast(lhs, buffer, env);
-                buffer.push_str("    push {r0, ip}\n");
-                self.emit_ast(rhs, buffer, env);
-                buffer.push_str("    pop {r1, ip}\n");
-                buffer.push_str("    cmp r0, r1\n");
-                buffer.push_str("    moveq r0, #1\n");
-                buffer.push_str("    movne r0, #0\n");
-            }
-            Ast::NotEqual(lhs, rhs) => {
-                buffer.push('\n');
-                self.emit_ast(lhs, buffer, env);
-                buffer.push_str("    push {r0, ip}\n");
-                self.emit_ast(rhs, buffer, env);
-                buffer.push_str("    pop {r1, ip}\n");
-                buffer.push_str("    cmp r0, r1\n");
-                buffer.push_str("    moveq r0, #0\n");
-                buffer.push_str("    movne r0, #1\n");
+            Ast::Call(name, _) if FLOAT_OPERATIONS.contains(&name.as_str()) => {
+                emit_float_operation(name, target, target, free[0], code);
             }
-            Ast::Call(name, args) => {
-                buffer.push('\n');
-                match args.len() {
-                    0 => {
-                        buffer.push_str(&format!("    bl {}\n", name));
+            Ast::Call(name, args) if args.len() == 1 => emit_unary_intrinsic(name, target, code),
+            Ast::Call(name, _) => emit_binary_intrinsic(name, target, target, free[0], code),
+            _ => unreachable!("{:?} is not an operation", ast),
+        }
+    }
+
+    // Emits the code of the expression, leaving its value in `r0`. Most of
+    // the operations keep the left hand side on the stack while the right
+    // hand side is evaluated and then work on `r1` and `r0`, the ones fitting
+    // in registers are left to `emit_in_registers`. With a stack of its own,
+    // as a chain of operators is as deep as it is long: the operands are
+    // emitted in order and the steps after each of them emit what goes in
+    // between and the operation itself.
+    fn emit_expression(
+        &self,
+        expression: &Ast,
+        code: &mut Vec<Instruction>,
+        env: &mut Environment,
+    ) {
+        enum Step<'a> {
+            Emit(&'a Ast),
+            PushR0,
+            PopR1,
+            // stores `r0` at the offset from the register
+            Store(Register, i32),
+            Operation(&'a Ast),
+        }
+        let needs = register_needs(expression);
+        let registers = &EXPRESSION_REGISTERS[..self.options.target.expression_registers];
+        let mut steps = vec![Step::Emit(expression)];
+        while let Some(step) = steps.pop() {
+            let ast = match step {
+                Step::Emit(ast) => ast,
+                Step::PushR0 => {
+                    code.push(push_r0());
+                    continue;
+                }
+                Step::PopR1 => {
+                    code.push(pop_r1());
+                    continue;
+                }
+                Step::Store(base, offset) => {
+                    code.push(Instruction::Str(
+                        Condition::Always,
+                        Register::R0,
+                        Address::Offset(base, Operand::Immediate(offset)),
+                    ));
+                    continue;
+                }
+                Step::Operation(ast) => {
+                    self.emit_operation(ast, code, env);
+                    continue;
+                }
+            };
+            if let Some(need) = needs[&(ast as *const Ast)] {
+                if need <= registers.len() {
+                    self.emit_in_registers(ast, registers[0], &registers[1..], &needs, code, env);
+                    continue;
+                }
+            }
+            // leaves the left hand side in `r1` and the right hand side in
+            // `r0`
+            let mut binary_operands = |lhs, rhs| {
+                steps.push(Step::Operation(ast));
+                steps.push(Step::PopR1);
+                steps.push(Step::Emit(rhs));
+                steps.push(Step::PushR0);
+                steps.push(Step::Emit(lhs));
+            };
+            match ast {
+                Ast::Undefined
+                | Ast::Null
+                | Ast::Number(_)
+                | Ast::Float(_)
+                | Ast::Bool(_)
+                | Ast::String(_)
+                | Ast::Identifier(_) => {
+                    unreachable!("single values always fit in registers")
+                }
+                Ast::ArrayLiteral(elements)
+                    if self.constant_arrays_allowed && constant_elements(elements).is_some() =>
+                {
+                    let values = constant_elements(elements).unwrap();
+                    code.push(Instruction::LoadAddress(
+                        Register::R0,
+                        self.constant_array(values),
+                    ));
+                }
+                Ast::ArrayLiteral(elements) => {
+                    // the length is stored in the first word, with no room
+                    // left for more elements
+                    let length = elements.len() as i32;
+                    self.uses_new_array.set(true);
+                    code.push(Instruction::LoadConstant(Register::R0, length));
+                    code.push(Instruction::LoadConstant(Register::R1, length));
+                    code.push(Instruction::Bl(String::from(runtime::NEW_ARRAY_ROUTINE)));
+                    code.push(Instruction::Push(vec![Register::R4, Register::Ip]));
+                    code.push(Instruction::Mov(
+                        Condition::Always,
+                        Register::R4,
+                        Operand::Register(Register::R0),
+                    ));
+                    steps.push(Step::Operation(ast));
+                    for (i, elem) in elements.iter().enumerate().rev() {
+                        steps.push(Step::Store(Register::R4, 4 * (i as i32 + 1)));
+                        steps.push(Step::Emit(elem));
                     }
+                }
+                Ast::ArrayLookup(array, index) => binary_operands(array, index),
+                Ast::Multiplication(_, _) | Ast::Division(_, _) if shift_operand(ast).is_some() => {
+                    let (operand, _) = shift_operand(ast).unwrap();
+                    steps.push(Step::Operation(ast));
+                    steps.push(Step::Emit(operand));
+                }
+                Ast::ArrayLength(operand) | Ast::Not(operand) => {
+                    steps.push(Step::Operation(ast));
+                    steps.push(Step::Emit(operand));
+                }
+                Ast::Addition(lhs, rhs)
+                | Ast::Subtraction(lhs, rhs)
+                | Ast::Multiplication(lhs, rhs)
+                | Ast::Division(lhs, rhs)
+                | Ast::Equal(lhs, rhs)
+                | Ast::NotEqual(lhs, rhs) => binary_operands(lhs, rhs),
+                Ast::Call(name, args) if FLOAT_OPERATIONS.contains(&name.as_str()) => {
+                    binary_operands(&args[0], &args[1])
+                }
+                Ast::Call(name, args) if INTRINSICS.contains(&name.as_str()) && args.len() == 1 => {
+                    steps.push(Step::Operation(ast));
+                    steps.push(Step::Emit(&args[0]));
+                }
+                Ast::Call(name, args) if INTRINSICS.contains(&name.as_str()) => {
+                    binary_operands(&args[0], &args[1])
+                }
+                Ast::Call(name, args) => match args.len() {
+                    0 => self.emit_call(name, code),
                     1 => {
-                        self.emit_ast(&args[0], buffer, env);
-                        buffer.push_str(&format!("    bl {}\n", name));
+                        steps.push(Step::Operation(ast));
+                        steps.push(Step::Emit(&args[0]));
                     }
                     x if x < 5 => {
-                        buffer.push_str("    sub sp, sp, #16\n");
-                        for (i, arg) in args.iter().enumerate() {
-                            self.emit_ast(arg, buffer, env);
-                            buffer.push_str(&format!("    str r0, [sp, #{}]\n", 4 * i));
+                        code.push(Instruction::Sub(
+                            Condition::Always,
+                            Register::Sp,
+                            Register::Sp,
+                            Operand::Immediate(16),
+                        ));
+                        steps.push(Step::Operation(ast));
+                        for (i, arg) in args.iter().enumerate().rev() {
+                            steps.push(Step::Store(Register::Sp, 4 * i as i32));
+                            steps.push(Step::Emit(arg));
                         }
-                        buffer.push_str("    pop {r0, r1, r2, r3}\n");
-                        buffer.push_str(&format!("    bl {}\n", name));
                     }
                     _ => {
                         panic!("More than four arguments are not supported");
                     }
+                },
+                statement => unreachable!("{:?} is not an expression", statement),
+            }
+        }
+    }
+
+    // The operation of `emit_expression` once its operands are evaluated.
+    fn emit_operation(&self, ast: &Ast, code: &mut Vec<Instruction>, env: &Environment) {
+        match ast {
+            Ast::ArrayLiteral(_) => {
+                code.push(Instruction::Mov(
+                    Condition::Always,
+                    Register::R0,
+                    Operand::Register(Register::R4),
+                ));
+                code.push(Instruction::Pop(vec![Register::R4, Register::Ip]));
+            }
+            Ast::ArrayLookup(array, _) => {
+                self.emit_null_check(array, Register::R1, code);
+                self.emit_array_lookup(
+                    Register::R0,
+                    Register::R1,
+                    Register::R0,
+                    Register::R2,
+                    code,
+                );
+            }
+            Ast::ArrayLength(array) => {
+                self.emit_null_check(array, Register::R0, code);
+                emit_array_length(Register::R0, code);
+            }
+            Ast::Not(_) => emit_not(Register::R0, code),
+            Ast::Multiplication(_, _) | Ast::Division(_, _) if shift_operand(ast).is_some() => {
+                let (_, shift) = shift_operand(ast).unwrap();
+                emit_shift(ast, self.options.arithmetic, Register::R0, shift, code);
+            }
+            Ast::Addition(_, _)
+            | Ast::Subtraction(_, _)
+            | Ast::Multiplication(_, _)
+            | Ast::Division(_, _)
+            | Ast::Equal(_, _)
+            | Ast::NotEqual(_, _) => {
+                if let Ast::Division(_, divisor) = ast {
+                    self.emit_division_check(divisor, Register::R0, code);
+                }
+                emit_binary_operation(
+                    ast,
+                    self.options.arithmetic,
+                    Register::R0,
+                    Register::R1,
+                    Register::R0,
+                    code,
+                );
+            }
+            Ast::Call(name, _) if FLOAT_OPERATIONS.contains(&name.as_str()) => {
+                emit_float_operation(name, Register::R0, Register::R1, Register::R0, code);
+            }
+            Ast::Call(name, args) if INTRINSICS.contains(&name.as_str()) && args.len() == 1 => {
+                emit_unary_intrinsic(name, Register::R0, code);
+            }
+            Ast::Call(name, _) if INTRINSICS.contains(&name.as_str()) => {
+                emit_binary_intrinsic(name, Register::R0, Register::R1, Register::R0, code);
+            }
+            Ast::Call(name, args) if args.len() == 1 => self.emit_call(name, code),
+            Ast::Call(name, args) => {
+                code.push(Instruction::Pop(vec![
+                    Register::R0,
+                    Register::R1,
+                    Register::R2,
+                    Register::R3,
+                ]));
+                self.emit_call(name, code);
+                // the array may have moved to make room for the element
+                if let (PUSH, Ast::Identifier(array)) = (name.as_str(), &args[0]) {
+                    emit_store(array, code, env);
                 }
             }
+            _ => unreachable!("{:?} is not an operation", ast),
+        }
+    }
+
+    // Branches and loop bodies get their own scope even when they are not
+    // blocks, e.g. `if (x) var y = 1;`.
+    fn emit_in_scope(&self, ast: &Ast, code: &mut Vec<Instruction>, env: &mut Environment) {
+        env.enter_scope();
+        self.emit_ast(ast, code, env);
+        env.leave_scope();
+    }
+
+    // Ties the code following it to the source line of the statement.
+    fn emit_source_line(&self, statement: &Ast, code: &mut Vec<Instruction>) {
+        let line = match self
+            .statement_lines
+            .borrow()
+            .get(&(statement as *const Ast))
+        {
+            Some(&line) => &self.source_lines[line],
+            None => return,
+        };
+        // blocks have no code of their own, the function's starts with its
+        // prologue
+        let has_code = !matches!(
+            statement,
+            Ast::Block(_)
+                | Ast::Export(_)
+                | Ast::Import(_, _)
+                | Ast::Extern(_, _)
+                | Ast::Const(_, _)
+                | Ast::StaticAssert(_, _)
+                | Ast::Doc(_)
+        );
+        if self.options.debug_info && has_code {
+            code.push(Instruction::Loc(
+                line.file + 1,
+                line.location.line,
+                line.location.column,
+            ));
+        }
+        if self.options.source_comments && has_code && !matches!(statement, Ast::Function(_, _, _))
+        {
+            code.push(Instruction::Comment(format!(
+                "line {}: {}",
+                line.location.line, line.text
+            )));
+        }
+    }
+
+    // Emits the code of the node, noting the instructions of the statements
+    // for the source map.
+    fn emit_ast(&self, ast: &Ast, code: &mut Vec<Instruction>, env: &mut Environment) {
+        let line = self
+            .statement_lines
+            .borrow()
+            .get(&(ast as *const Ast))
+            .copied();
+        match line {
+            Some(line) if self.options.source_map => {
+                let range = self.source_ranges.borrow().len();
+                self.source_ranges
+                    .borrow_mut()
+                    .push((code.len(), code.len(), line));
+                self.emit_node(ast, code, env);
+                self.source_ranges.borrow_mut()[range].1 = code.len();
+            }
+            _ => self.emit_node(ast, code, env),
+        }
+    }
+
+    fn emit_node(&self, ast: &Ast, code: &mut Vec<Instruction>, env: &mut Environment) {
+        self.emit_source_line(ast, code);
+        // a loop counts every test of its condition
+        if !matches!(ast, Ast::While(_, _)) {
+            self.emit_coverage_increment(ast, code);
+        }
+        match ast {
+            Ast::Block(statements) => {
+                env.enter_scope();
+                for statement in statements {
+                    self.emit_ast(statement, code, env);
+                }
+                env.leave_scope();
+            }
+            Ast::Undefined
+            | Ast::Null
+            | Ast::Number(_)
+            | Ast::Float(_)
+            | Ast::Bool(_)
+            | Ast::String(_)
+            | Ast::Identifier(_)
+            | Ast::ArrayLiteral(_)
+            | Ast::ArrayLookup(_, _)
+            | Ast::ArrayLength(_)
+            | Ast::Not(_)
+            | Ast::Addition(_, _)
+            | Ast::Subtraction(_, _)
+            | Ast::Multiplication(_, _)
+            | Ast::Division(_, _)
+            | Ast::Equal(_, _)
+            | Ast::NotEqual(_, _)
+            | Ast::Call(_, _) => self.emit_expression(ast, code, env),
             Ast::Var(name, expr) => {
-                buffer.push('\n');
-                self.emit_ast(expr, buffer, env);
-                buffer.push_str("    push {r0, ip}\n");
-                env.locals.insert(name.clone(), env.next_local_offset - 4);
-                env.next_local_offset -= 8;
+                self.emit_ast(expr, code, env);
+                // the slot was already reserved by the prologue
+                let offset = env.next_local_offset;
+                code.push(Instruction::Str(
+                    Condition::Always,
+                    Register::R0,
+                    Address::Offset(Register::Fp, Operand::Immediate(offset as i32)),
+                ));
+                env.locals.insert(name.clone(), offset);
+                env.next_local_offset -= 4;
+                if let Some(layout) = self.frame_layouts.borrow_mut().last_mut() {
+                    layout.locals.push((name.clone(), offset));
+                }
             }
             Ast::Assignment(name, expr) => {
-                let offset = *env.locals.get(name).unwrap_or_else(|| {
-                    panic!("Assignment to an undefined variable `{}`", name);
-                });
-                self.emit_ast(expr, buffer, env);
-                buffer.push_str(&format!("    str r0, [fp, #{}]\n", offset));
-            }
-            Ast::Identifier(name) => {
-                buffer.push('\n');
-                let offset = env.locals.get(name);
-                if let Some(offset) = offset {
-                    buffer.push_str(&format!("    ldr r0, [fp, #{}]\n", offset));
-                } else {
-                    panic!("Tried to use an undefined name {}", name);
-                }
+                self.emit_ast(expr, code, env);
+                emit_store(name, code, env);
             }
             Ast::Function(name, function_type, body) => {
                 let (parameter_types, _return_type) = match function_type {
@@ -247,13 +2081,20 @@ impl Arm32Generator {
                     panic!("More than four arguments are not supported");
                 }
 
-                buffer.push('\n');
-
-                buffer.push('\n');
-                buffer.push_str(&format!(".global {}\n", name));
-                buffer.push_str(&format!("{}:\n", name));
-                Arm32Generator::emit_prologue(buffer);
-                let mut env = Arm32Generator::make_initial_function_environment(
+                // with a wrapper, the wrapper is the `main` the C runtime calls
+                let label = self.function_label(name);
+                code.push(if self.exported.contains(name) && label == *name {
+                    Instruction::Global(label)
+                } else {
+                    Instruction::Local(label)
+                });
+                code.push(Instruction::Label(self.function_label(name)));
+                let float_signature = self.float_signature(name);
+                if let Some((floats, _)) = &float_signature {
+                    emit_receive_float_arguments(floats, code);
+                }
+                self.emit_prologue(code, locals_size(body));
+                let mut function_env = self.make_initial_function_environment(
                     parameter_types
                         .iter()
                         .map(|(x, _)| x)
@@ -261,46 +2102,1113 @@ impl Arm32Generator {
                         .collect::<Vec<String>>()
                         .as_ref(),
                 );
-                self.emit_ast(body, buffer, &mut env);
-                Arm32Generator::emit_epilogue(buffer);
+                function_env.globals = env.globals.clone();
+                function_env.float_result = matches!(float_signature, Some((_, true)));
+                function_env.profile_entry = self.profile_entries.borrow().get(name).copied();
+                if let Some(entry) = function_env.profile_entry {
+                    profile::emit_enter(entry, self.profile_offset(), code);
+                }
+                self.frame_layouts.borrow_mut().push(FrameLayout {
+                    function: name.clone(),
+                    label: self.function_label(name),
+                    parameters: parameter_types
+                        .keys()
+                        .map(|parameter| (parameter.clone(), function_env.locals[parameter]))
+                        .collect(),
+                    locals: Vec::new(),
+                });
+                self.emit_ast(body, code, &mut function_env);
+                self.emit_epilogue(code, &function_env);
             }
             Ast::Return(expr) => {
-                self.emit_ast(expr, buffer, env);
-                buffer.push_str("    mov sp, fp\n");
-                buffer.push_str("    pop {fp, pc}\n");
+                self.emit_ast(expr, code, env);
+                if let Some(entry) = env.profile_entry {
+                    profile::emit_exit(entry, self.profile_offset(), code);
+                }
+                if self.options.gc {
+                    gc::emit_leave_frame(code);
+                }
+                if env.float_result {
+                    code.push(Instruction::VmovToFloat(FloatRegister::S0, Register::R0));
+                }
+                code.push(Instruction::Mov(
+                    Condition::Always,
+                    Register::Sp,
+                    Operand::Register(Register::Fp),
+                ));
+                code.push(Instruction::Pop(vec![Register::Fp, Register::Pc]));
+            }
+            Ast::Delete(array) => {
+                self.emit_ast(array, code, env);
+                // the collector owns the arrays when it is enabled, the bump
+                // allocator never reuses them
+                if !self.options.gc && self.options.platform == Platform::Linux {
+                    code.push(Instruction::Sub(
+                        Condition::Always,
+                        Register::R0,
+                        Register::R0,
+                        Operand::Immediate(runtime::CAPACITY_SIZE),
+                    ));
+                    code.push(Instruction::Bl(String::from("free")));
+                }
             }
             Ast::If(condition, consequence, alternative) => {
-                let false_label = make_label();
-                let end_if_label = make_label();
-                buffer.push('\n');
-                self.emit_ast(condition, buffer, env);
-                buffer.push_str("    cmp r0, #0\n");
-                buffer.push_str(&format!("    beq {}\n", false_label));
+                let false_label = self.make_label();
+                let end_if_label = self.make_label();
+                self.emit_ast(condition, code, env);
+                code.push(Instruction::Cmp(Register::R0, Operand::Immediate(0)));
+                code.push(Instruction::B(Condition::Eq, false_label.clone()));
 
-                self.emit_ast(consequence, buffer, env);
-                buffer.push_str(&format!("    b {}\n", end_if_label));
+                self.emit_in_scope(consequence, code, env);
+                code.push(Instruction::B(Condition::Always, end_if_label.clone()));
 
-                buffer.push('\n');
-                buffer.push_str(&format!("{}:", false_label));
-                self.emit_ast(alternative, buffer, env);
+                code.push(Instruction::Label(false_label));
+                self.emit_in_scope(alternative, code, env);
 
-                buffer.push('\n');
-                buffer.push_str(&format!("{}:", end_if_label));
+                code.push(Instruction::Label(end_if_label));
             }
+            // called with a plain `bl`, the linker finds the definition
+            Ast::Import(_, _) | Ast::Extern(_, _) | Ast::Doc(_) => {}
+            Ast::Namespace(_, _) => unreachable!("namespaces are flattened when linking"),
+            // checked by the typechecker and folded into their uses by
+            // `const_eval::fold`, nothing is left to run
+            Ast::Const(_, _) | Ast::StaticAssert(_, _) => {}
+            Ast::Export(function) => self.emit_ast(function, code, env),
             Ast::While(condition, block) => {
-                let start_label = make_label();
-                let end_label = make_label();
-                buffer.push('\n');
-                buffer.push_str(&format!("{}:\n", start_label));
-                self.emit_ast(condition, buffer, env);
-                buffer.push_str("    cmp r0, #0\n");
-                buffer.push_str(&format!("    beq {}\n", end_label));
+                let start_label = self.make_label();
+                let end_label = self.make_label();
+                code.push(Instruction::Label(start_label.clone()));
+                self.emit_coverage_increment(ast, code);
+                self.emit_ast(condition, code, env);
+                code.push(Instruction::Cmp(Register::R0, Operand::Immediate(0)));
+                code.push(Instruction::B(Condition::Eq, end_label.clone()));
 
-                self.emit_ast(block, buffer, env);
-                buffer.push_str(&format!("    b {}\n", start_label));
+                self.emit_in_scope(block, code, env);
+                code.push(Instruction::B(Condition::Always, start_label));
 
-                buffer.push_str(&format!("{}:", end_label));
+                code.push(Instruction::Label(end_label));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, ParseOptions};
+    use crate::phases::typecheck;
+    use linked_hash_map::LinkedHashMap;
+
+    fn generate(source: &str) -> Vec<Instruction> {
+        let ast = parser::parse_program(source).unwrap();
+        Arm32Generator::new(ast).generate(&mut Environment::default())
+    }
+
+    #[test]
+    fn function_frame() {
+        let code = generate("function f() { return 42; }");
+        assert_eq!(
+            code,
+            vec![
+                Instruction::Local(String::from("f")),
+                Instruction::Label(String::from("f")),
+                Instruction::Push(vec![Register::Fp, Register::Lr]),
+                Instruction::Mov(
+                    Condition::Always,
+                    Register::Fp,
+                    Operand::Register(Register::Sp)
+                ),
+                Instruction::Push(vec![Register::R0, Register::R1, Register::R2, Register::R3]),
+                Instruction::LoadConstant(Register::R0, 42),
+                Instruction::Mov(
+                    Condition::Always,
+                    Register::Sp,
+                    Operand::Register(Register::Fp)
+                ),
+                // the implicit `return 0;` after it never runs
+                Instruction::Pop(vec![Register::Fp, Register::Pc]),
+            ]
+        );
+    }
+
+    #[test]
+    fn binary_operation() {
+        let code = generate("function f(x) { return x - 1; }");
+        let rendered = instruction::render(&code[5..8]);
+        assert_eq!(
+            rendered,
+            "    ldr r0, [fp, #-16]
+    ldr r1, =1
+    sub r0, r0, r1
+"
+        );
+    }
+
+    #[test]
+    fn operand_needing_more_registers_goes_first() {
+        let code = generate("function f(x, y) { return 1 - x * y; }");
+        let rendered = instruction::render(&code[5..10]);
+        assert_eq!(
+            rendered,
+            "    ldr r1, [fp, #-16]
+    ldr r0, [fp, #-12]
+    mul r1, r1, r0
+    ldr r0, =1
+    sub r0, r0, r1
+"
+        );
+    }
+
+    #[test]
+    fn calls_spill_to_the_stack() {
+        let code = generate("function f(x) { return x + f(x); }");
+        let rendered = instruction::render(&code[5..11]);
+        assert_eq!(
+            rendered,
+            "    ldr r0, [fp, #-16]
+    push {r0, ip}
+    ldr r0, [fp, #-16]
+    bl f
+    pop {r1, ip}
+    add r0, r1, r0
+"
+        );
+    }
+
+    #[test]
+    fn signed_division() {
+        let source = "function f(x) { return x / 3; }";
+        let ast = parser::parse_program(source).unwrap();
+        assert_eq!(
+            generate(source)[7],
+            Instruction::Sdiv(Register::R0, Register::R0, Register::R1)
+        );
+        let code = Arm32Generator::new(ast)
+            .with_options(CodegenOptions {
+                arithmetic: Arithmetic::Unsigned,
+                ..CodegenOptions::default()
+            })
+            .generate(&mut Environment::default());
+        assert_eq!(
+            code[7],
+            Instruction::Udiv(Register::R0, Register::R0, Register::R1)
+        );
+    }
+
+    #[test]
+    fn strength_reduction() {
+        let code = generate("function f(x) { return 8 * x / 4; }");
+        assert_eq!(
+            instruction::render(&code[5..11]),
+            "    ldr r0, [fp, #-16]\n    lsl r0, r0, #3\n    cmp r0, #0\n    rsblt r0, r0, #0\n    lsr r0, r0, #2\n    rsblt r0, r0, #0\n"
+        );
+        assert!(!code
+            .iter()
+            .any(|i| matches!(i, Instruction::Mul(..) | Instruction::Sdiv(..))));
+    }
+
+    #[test]
+    fn extern_functions() {
+        let code = generate(
+            "extern function abs(x: number): number;
+            function f() { return abs(1); }",
+        );
+        assert!(code.contains(&Instruction::Bl(String::from("abs"))));
+        assert!(!code.contains(&Instruction::Label(String::from("abs"))));
+    }
+
+    #[test]
+    fn delete_frees_the_array() {
+        let code = generate("function f(xs: array<number>) { delete xs; }");
+        assert_eq!(
+            instruction::render(&code[5..8]),
+            "    ldr r0, [fp, #-16]\n    sub r0, r0, #4\n    bl free\n"
+        );
+    }
+
+    #[test]
+    fn globals() {
+        let code = generate("var count = 0; var base = 65; function f() { count = base; }");
+        assert_eq!(
+            instruction::render(&code[5..10]),
+            "    ldr r0, =_T.4base
+    ldr r0, [r0]
+    ldr r1, =_T.5count
+    str r0, [r1]
+    mov sp, fp
+"
+        );
+        assert_eq!(
+            instruction::render(&code[12..]),
+            ".section .data
+    .balign 4
+_T.4base:
+    .word 65
+.section .text
+.section .bss
+    .balign 4
+_T.5count:
+    .space 4
+.section .text
+"
+        );
+    }
+
+    #[test]
+    fn constant_arrays() {
+        let code = generate("function f() { var a = [1, 2]; var b = [1, 2]; }");
+        assert_eq!(
+            code[6],
+            Instruction::LoadAddress(Register::R0, String::from(".L0"))
+        );
+        assert_eq!(
+            instruction::render(&code[code.len() - 8..]),
+            ".section .rodata
+    .balign 4
+    .word 2
+.L0:
+    .word 2
+    .word 1
+    .word 2
+.section .text
+"
+        );
+        // any array may be freed once the program uses `delete`
+        let code = generate("function f() { var a = [1, 2]; delete a; }");
+        assert_eq!(
+            code[8],
+            Instruction::Bl(String::from(runtime::NEW_ARRAY_ROUTINE))
+        );
+        assert!(code.contains(&Instruction::Bl(String::from("malloc"))));
+    }
+
+    #[test]
+    fn garbage_collected_frames() {
+        let ast = parser::parse_program("function f() { var xs = [1]; delete xs; }").unwrap();
+        let code = Arm32Generator::new(ast)
+            .with_options(CodegenOptions {
+                gc: true,
+                ..CodegenOptions::default()
+            })
+            .generate(&mut Environment::default());
+        assert_eq!(
+            instruction::render(&code[5..13]),
+            "    sub sp, sp, #16
+    ldr r0, =__rtsc_gc_top
+    ldr r1, [r0]
+    str r1, [fp, #-20]
+    str fp, [r0]
+    ldr r0, =1
+    ldr r1, =1
+    bl __rtsc_new_array
+"
+        );
+        assert!(code.contains(&Instruction::Bl(String::from(gc::ALLOC_ROUTINE))));
+        // the local moves below the link and `delete` leaves it alone
+        assert_eq!(
+            instruction::render(&code[19..21]),
+            "    str r0, [fp, #-28]
+    ldr r1, [fp, #-20]
+"
+        );
+        assert!(code.contains(&Instruction::Label(String::from("__rtsc_gc_collect"))));
+    }
+
+    #[test]
+    fn locals_are_reserved_upfront() {
+        let code = generate("function f() { var i = 0; while (i != 3) { var x = i; i = x + 1; } }");
+        assert_eq!(
+            code[5],
+            Instruction::Sub(
+                Condition::Always,
+                Register::Sp,
+                Register::Sp,
+                Operand::Immediate(8)
+            )
+        );
+        assert!(code.contains(&Instruction::Str(
+            Condition::Always,
+            Register::R0,
+            Address::Offset(Register::Fp, Operand::Immediate(-24))
+        )));
+        assert!(!code.contains(&push_r0()));
+    }
+
+    #[test]
+    fn sibling_blocks_share_slots() {
+        let code = generate(
+            "function f(c) { var x = 1; if (c) { var y = 2; } else { var z = 3; var x = 4; } return x; }",
+        );
+        // x, and then either y or z with the shadowing x
+        assert_eq!(
+            code[5],
+            Instruction::Sub(
+                Condition::Always,
+                Register::Sp,
+                Register::Sp,
+                Operand::Immediate(16)
+            )
+        );
+        let stores: Vec<&Instruction> = code
+            .iter()
+            .filter(|instruction| matches!(instruction, Instruction::Str(_, _, _)))
+            .collect();
+        let slot = |offset| {
+            Instruction::Str(
+                Condition::Always,
+                Register::R0,
+                Address::Offset(Register::Fp, Operand::Immediate(offset)),
+            )
+        };
+        assert_eq!(stores, vec![&slot(-20), &slot(-24), &slot(-24), &slot(-28)]);
+        // the outer `x` is visible again after the block
+        assert_eq!(
+            code[code.len() - 3],
+            Instruction::Ldr(
+                Condition::Always,
+                Register::R0,
+                Address::Offset(Register::Fp, Operand::Immediate(-20))
+            )
+        );
+    }
+
+    #[test]
+    fn labels_are_deterministic() {
+        let source = "function f(x) { if (x) { return 1; } else { return 2; } }";
+        let first = generate(source);
+        assert_eq!(first, generate(source));
+        assert!(first.contains(&Instruction::Label(String::from(".L0"))));
+
+        let generator = Arm32Generator::new(parser::parse_program(source).unwrap());
+        assert_eq!(
+            generator.generate(&mut Environment::default()),
+            generator.generate(&mut Environment::default())
+        );
+    }
+
+    // Follows the changes of `sp` relative to the function entry and returns
+    // the offsets at which the functions are called.
+    fn stack_offsets_at_calls(code: &[Instruction]) -> Vec<i32> {
+        let words = |registers: &Vec<Register>| 4 * registers.len() as i32;
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        let mut before_return = 0;
+        for instruction in code {
+            match instruction {
+                Instruction::Global(_) | Instruction::Local(_) => offset = 0,
+                Instruction::Push(registers) => offset -= words(registers),
+                Instruction::Pop(registers) if registers.contains(&Register::Pc) => {
+                    offset = before_return
+                }
+                Instruction::Pop(registers) => offset += words(registers),
+                Instruction::Sub(_, Register::Sp, Register::Sp, Operand::Immediate(size)) => {
+                    offset -= size
+                }
+                Instruction::Mov(_, Register::Sp, Operand::Register(Register::Fp)) => {
+                    before_return = offset;
+                    offset = -8;
+                }
+                Instruction::Bl(_) => offsets.push(offset),
+                _ => {}
+            }
+        }
+        offsets
+    }
+
+    #[test]
+    fn source_comments() {
+        let source = "function f(n) {\n    while (n != 1) {\n        n = n - 1;\n    }\n}";
+        let (ast, spans) =
+            parser::parse_program_with_spans(source, &ParseOptions::default()).unwrap();
+        let source_lines = lines::statement_lines(0, source, &spans);
+        let code = Arm32Generator::new(ast)
+            .with_options(CodegenOptions {
+                source_comments: true,
+                ..CodegenOptions::default()
+            })
+            .with_source(vec![String::from("main.ts")], source_lines)
+            .generate(&mut Environment::default());
+        let comments: Vec<String> = code
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Comment(text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            comments,
+            vec!["line 2: while (n != 1) {", "line 3: n = n - 1;"]
+        );
+        assert_eq!(code[5], Instruction::Comment(comments[0].clone()));
+    }
+
+    #[test]
+    fn debug_info() {
+        let source = "function f(n) {\n    return n;\n}";
+        let (ast, spans) =
+            parser::parse_program_with_spans(source, &ParseOptions::default()).unwrap();
+        let source_lines = lines::statement_lines(0, source, &spans);
+        let code = Arm32Generator::new(ast)
+            .with_options(CodegenOptions {
+                debug_info: true,
+                ..CodegenOptions::default()
+            })
+            .with_source(vec![String::from("main.ts")], source_lines)
+            .generate(&mut Environment::default());
+        assert_eq!(
+            instruction::render(&code[..4]),
+            "    .file 1 \"main.ts\"\n    .loc 1 1 1\n\n.local f\nf:\n"
+        );
+        assert_eq!(code[7], Instruction::Loc(1, 2, 5));
+        assert!(!code
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::Comment(_))));
+    }
+
+    #[test]
+    fn asm_headers() {
+        let header = |header, platform, thumb| {
+            let code = Arm32Generator::new(parser::parse_program("function f() {}").unwrap())
+                .with_options(CodegenOptions {
+                    header,
+                    platform,
+                    thumb,
+                    ..CodegenOptions::default()
+                })
+                .generate(&mut Environment::default());
+            let end = code
+                .iter()
+                .position(|instruction| matches!(instruction, Instruction::Local(_)))
+                .unwrap();
+            instruction::render(&code[..end])
+        };
+        assert_eq!(
+            header(AsmHeader::Unified, Platform::Linux, false),
+            ".syntax unified
+.arch armv7-a
+.arch_extension idiv
+.fpu vfpv3-d16
+.eabi_attribute Tag_ABI_VFP_args, 1
+.arm
+.section .text
+"
+        );
+        assert_eq!(
+            header(AsmHeader::Unified, Platform::BareMetal, false),
+            ".syntax unified
+.arch armv7-a
+.arch_extension idiv
+.arm
+.section .text
+"
+        );
+        assert_eq!(
+            header(AsmHeader::Unified, Platform::BareMetal, true),
+            ".syntax unified
+.arch armv7-m
+.thumb
+.section .text
+"
+        );
+        assert_eq!(
+            header(AsmHeader::None, Platform::Linux, true),
+            ".syntax unified
+.thumb
+.section .text
+"
+        );
+        assert_eq!(
+            generate("function f() {}")[0],
+            Instruction::Local(String::from("f"))
+        );
+    }
+
+    #[test]
+    fn target_specs() {
+        let source = "function f(a, b, c, d) { return (a + b) / (c - d); }
+            function g(x: float): float { return x; }";
+        let generate = |target| {
+            let mut ast = parser::parse_program(source).unwrap();
+            typecheck::lower_typed_operations(&mut ast, LinkedHashMap::new()).unwrap();
+            Arm32Generator::new(ast)
+                .with_options(CodegenOptions {
+                    header: AsmHeader::Unified,
+                    target,
+                    ..CodegenOptions::default()
+                })
+                .generate(&mut Environment::default())
+        };
+        let armv6 = generate(TargetSpec {
+            arch: Arch::Armv6,
+            float_abi: Some(FloatAbi::Soft),
+            hardware_division: false,
+            expression_registers: 4,
+        });
+        assert!(instruction::render(&armv6).starts_with(".syntax unified\n.arch armv6\n.arm\n"));
+        assert!(!armv6
+            .iter()
+            .any(|x| matches!(x, Instruction::Sdiv(..) | Instruction::VmovToFloat(..))));
+        assert!(armv6.contains(&Instruction::Bl(String::from(
+            division::SIGNED_DIVISION_ROUTINE
+        ))));
+        assert!(armv6.contains(&Instruction::Label(String::from(
+            division::UNSIGNED_DIVISION_ROUTINE
+        ))));
+        let armv7 = generate(TargetSpec::default());
+        assert!(armv7.iter().any(|x| matches!(x, Instruction::Sdiv(..))));
+        // the hard-float convention returns the float in `s0`
+        assert!(armv7.contains(&Instruction::VmovToFloat(FloatRegister::S0, Register::R0)));
+        // two registers leave the operands of the division to the stack
+        let pop_operand = Instruction::Pop(vec![Register::R1, Register::Ip]);
+        assert!(!armv7.contains(&pop_operand));
+        assert!(generate(TargetSpec {
+            expression_registers: 2,
+            ..TargetSpec::default()
+        })
+        .contains(&pop_operand));
+    }
+
+    #[test]
+    fn visibility() {
+        let code = generate("export function f() {} function g() {} function main() {}");
+        let symbols: Vec<&Instruction> = code
+            .iter()
+            .filter(|instruction| {
+                matches!(instruction, Instruction::Global(_) | Instruction::Local(_))
+            })
+            .collect();
+        assert_eq!(
+            symbols,
+            [
+                &Instruction::Global(String::from("f")),
+                &Instruction::Local(String::from("g")),
+                &Instruction::Global(String::from("main")),
+            ]
+        );
+        // the wrapper is the global `main`
+        let code = Arm32Generator::new(parser::parse_program("function main() {}").unwrap())
+            .with_options(CodegenOptions {
+                entry: Entry::Main,
+                ..CodegenOptions::default()
+            })
+            .generate(&mut Environment::default());
+        assert!(code.contains(&Instruction::Local(String::from(MAIN_FUNCTION))));
+        assert!(code.contains(&Instruction::Global(String::from("main"))));
+    }
+
+    #[test]
+    fn entry_wrappers() {
+        let source = "function main() { return main(); }";
+        let generate_with_entry = |entry| {
+            Arm32Generator::new(parser::parse_program(source).unwrap())
+                .with_options(CodegenOptions {
+                    entry,
+                    ..CodegenOptions::default()
+                })
+                .generate(&mut Environment::default())
+        };
+        let code = generate_with_entry(Entry::None);
+        assert_eq!(code[1], Instruction::Label(String::from("main")));
+        assert!(code.contains(&Instruction::Bl(String::from("main"))));
+
+        let code = generate_with_entry(Entry::Main);
+        assert_eq!(code[1], Instruction::Label(String::from(MAIN_FUNCTION)));
+        assert!(code.contains(&Instruction::Bl(String::from(MAIN_FUNCTION))));
+        let wrapper = code
+            .iter()
+            .position(|x| *x == Instruction::Global(String::from("main")))
+            .unwrap();
+        assert_eq!(
+            instruction::render(&code[wrapper..]),
+            "\n.global main\nmain:\n    push {fp, lr}\n    bl __rtsc_main\n    bl exit\n"
+        );
+
+        let code = generate_with_entry(Entry::Start);
+        let wrapper = code
+            .iter()
+            .position(|x| *x == Instruction::Global(String::from("_start")))
+            .unwrap();
+        assert_eq!(
+            instruction::render(&code[wrapper..]),
+            "\n.global _start\n_start:\n    mov fp, #0\n    mov lr, #0\n    bl __rtsc_main\n    mov r7, #1\n    svc #0\n"
+        );
+    }
+
+    #[test]
+    fn argument_builtins() {
+        let source = "function main() { return argNumber(argCount() - 1); }";
+        let code = Arm32Generator::new(parser::parse_program(source).unwrap())
+            .with_options(CodegenOptions {
+                entry: Entry::Start,
+                ..CodegenOptions::default()
+            })
+            .generate(&mut Environment::default());
+        assert!(code.contains(&Instruction::Bl(String::from(runtime::ARG_COUNT_ROUTINE))));
+        assert!(code.contains(&Instruction::Bl(String::from(runtime::ARG_NUMBER_ROUTINE))));
+        let wrapper = code
+            .iter()
+            .position(|x| *x == Instruction::Global(String::from("_start")))
+            .unwrap();
+        assert_eq!(
+            instruction::render(&code[wrapper + 4..wrapper + 11]),
+            "    ldr r0, [sp]\n    add r1, sp, #4\n    ldr r2, =__rtsc_argc\n    str r0, [r2]\n    ldr r2, =__rtsc_argv\n    str r1, [r2]\n    bl __rtsc_main\n"
+        );
+        assert!(code.contains(&Instruction::Label(String::from(
+            runtime::ARG_NUMBER_ROUTINE
+        ))));
+    }
+
+    #[test]
+    fn number_to_string() {
+        let code = Arm32Generator::new(
+            parser::parse_program("function f(x) { return length(numberToString(x)); }").unwrap(),
+        )
+        .with_options(CodegenOptions {
+            gc: true,
+            ..CodegenOptions::default()
+        })
+        .generate(&mut Environment::default());
+        assert!(code.contains(&Instruction::Bl(String::from(
+            runtime::NUMBER_TO_STRING_ROUTINE
+        ))));
+        let routine = code
+            .iter()
+            .position(|x| *x == Instruction::Label(String::from(runtime::NUMBER_TO_STRING_ROUTINE)))
+            .unwrap();
+        // the array comes from the collector like the literals
+        assert!(code[routine..].contains(&Instruction::Bl(String::from(gc::ALLOC_ROUTINE))));
+    }
+
+    #[test]
+    fn array_builtins() {
+        let code = generate("function f(xs: array<number>) { push(xs, 1); return pop(xs); }");
+        // the array `push` returns replaces the one in the variable
+        assert_eq!(
+            instruction::render(&code[11..13]),
+            "    bl __rtsc_push\n    str r0, [fp, #-16]\n"
+        );
+        for routine in [
+            runtime::PUSH_ROUTINE,
+            runtime::POP_ROUTINE,
+            runtime::SLICE_ROUTINE,
+            runtime::NEW_ARRAY_ROUTINE,
+        ] {
+            assert!(code.contains(&Instruction::Label(String::from(routine))));
+        }
+        // `pop` would write the length of a read-only array
+        let code = generate("function f() { var xs = [1]; return pop(xs); }");
+        assert_eq!(
+            code[8],
+            Instruction::Bl(String::from(runtime::NEW_ARRAY_ROUTINE))
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let mut ast = parser::parse_program(
+            "function f(s: string): boolean { delete [1]; return (s == \"ab\") == (length(\"ab\") == 2); }",
+        )
+        .unwrap();
+        typecheck::lower_typed_operations(&mut ast, LinkedHashMap::new()).unwrap();
+        let code = Arm32Generator::new(ast).generate(&mut Environment::default());
+        // the literal is read-only even though the program deletes arrays
+        let literal = code
+            .iter()
+            .position(|x| *x == Instruction::Word(97))
+            .unwrap();
+        assert_eq!(code[literal - 1], Instruction::Word(2));
+        assert_eq!(code[literal + 1], Instruction::Word(98));
+        assert!(code.contains(&Instruction::Bl(String::from(
+            runtime::STRING_EQUAL_ROUTINE
+        ))));
+        assert!(code.contains(&Instruction::Label(String::from(
+            runtime::STRING_EQUAL_ROUTINE
+        ))));
+    }
+
+    #[test]
+    fn floats() {
+        let mut ast = parser::parse_program(
+            "function f(x: float, n: number): float { return x * 0.5; }
+            function g(): float { return f(2.0, 1); }",
+        )
+        .unwrap();
+        typecheck::lower_typed_operations(&mut ast, LinkedHashMap::new()).unwrap();
+        let code = Arm32Generator::new(ast).generate(&mut Environment::default());
+        let (s0, s1) = (FloatRegister::S0, FloatRegister::S1);
+        let move_register = |rd, rm| Instruction::Mov(Condition::Always, rd, Operand::Register(rm));
+        // the float argument comes in `s0` and the number in `r0`
+        assert_eq!(
+            code[2..4],
+            [
+                move_register(Register::R1, Register::R0),
+                Instruction::VmovFromFloat(Register::R0, s0),
+            ]
+        );
+        let multiply = code
+            .iter()
+            .position(|x| *x == Instruction::Vmul(s0, s0, s1))
+            .unwrap();
+        assert_eq!(
+            code[multiply + 1],
+            Instruction::VmovFromFloat(Register::R0, s0)
+        );
+        assert_eq!(
+            code[multiply + 2],
+            Instruction::VmovToFloat(s0, Register::R0)
+        );
+        let call = code
+            .iter()
+            .position(|x| *x == Instruction::Bl(String::from("f")))
+            .unwrap();
+        assert_eq!(
+            code[call - 2..call + 3],
+            [
+                Instruction::VmovToFloat(s0, Register::R0),
+                move_register(Register::R0, Register::R1),
+                Instruction::Bl(String::from("f")),
+                Instruction::VmovFromFloat(Register::R0, s0),
+                Instruction::VmovToFloat(s0, Register::R0),
+            ]
+        );
+    }
+
+    #[test]
+    fn intrinsics() {
+        let mut ast = parser::parse_program(
+            "function f(a: number, b: number) { return max(a, b) - abs(min(a, b)); }",
+        )
+        .unwrap();
+        typecheck::lower_typed_operations(&mut ast, typecheck::builtins()).unwrap();
+        let code = Arm32Generator::new(ast).generate(&mut Environment::default());
+        assert_eq!(
+            instruction::render(&code[5..15]),
+            "    ldr r0, [fp, #-16]\n    ldr r1, [fp, #-12]\n    cmp r0, r1\n    movlt r0, r1\n    \
+             ldr r1, [fp, #-16]\n    ldr r2, [fp, #-12]\n    cmp r1, r2\n    movgt r1, r2\n    \
+             cmp r1, #0\n    rsblt r1, r1, #0\n"
+        );
+        assert!(!code
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::Bl(_))));
+    }
+
+    #[test]
+    fn bit_intrinsics() {
+        let mut ast = parser::parse_program(
+            "function f(x: number, n: number) { return rotate(clz(x), n) + popcount(x); }",
+        )
+        .unwrap();
+        typecheck::lower_typed_operations(&mut ast, typecheck::builtins()).unwrap();
+        let code = Arm32Generator::new(ast).generate(&mut Environment::default());
+        assert_eq!(
+            instruction::render(&code[5..9]),
+            "    ldr r0, [fp, #-16]\n    clz r0, r0\n    ldr r1, [fp, #-12]\n    ror r0, r0, r1\n"
+        );
+        assert!(code.contains(&Instruction::Bl(String::from(runtime::POPCOUNT_ROUTINE))));
+        assert!(code.contains(&Instruction::Label(String::from(runtime::POPCOUNT_ROUTINE))));
+    }
+
+    #[test]
+    fn coverage_counters() {
+        let source = "function main() { while (0 == 1) { putchar(65); } return 0; }";
+        let code = Arm32Generator::new(parser::parse_program(source).unwrap())
+            .with_options(CodegenOptions {
+                entry: Entry::Main,
+                coverage: true,
+                ..CodegenOptions::default()
+            })
+            .generate(&mut Environment::default());
+        let assembly = instruction::render(&code);
+        // the loop counts its condition on every iteration
+        assert!(assembly.contains(
+            ".L0:\n    ldr r1, =__rtsc_coverage_counters\n    ldr r0, [r1]\n    add r0, r0, #1\n"
+        ));
+        assert!(assembly
+            .contains("    bl __rtsc_main\n    push {r0, ip}\n    bl __rtsc_coverage_dump\n"));
+        assert!(assembly.contains("__rtsc_coverage_counters:\n    .space 12\n"));
+    }
+
+    #[test]
+    fn profile_calls() {
+        let source = "function f(): number { return 1; } function main() { f(); }";
+        let code = Arm32Generator::new(parser::parse_program(source).unwrap())
+            .with_options(CodegenOptions {
+                entry: Entry::Main,
+                gc: true,
+                profile: true,
+                ..CodegenOptions::default()
+            })
+            .generate(&mut Environment::default());
+        let assembly = instruction::render(&code);
+        // the time of the call goes below the link of the collector
+        assert!(assembly
+            .contains("    ldr r0, =1\n    bl __rtsc_profile_enter\n    str r0, [fp, #-28]\n"));
+        assert!(assembly.contains(
+            "    ldr r0, =1\n    ldr r1, =0\n    ldr r2, [fp, #-28]\n    bl __rtsc_profile_exit\n"
+        ));
+        assert!(assembly
+            .contains("    bl __rtsc_main\n    push {r0, ip}\n    bl __rtsc_profile_dump\n"));
+        assert!(assembly.contains("__rtsc_profile_table:\n    .space 32\n"));
+    }
+
+    #[test]
+    fn bare_metal() {
+        let source = "function main() { var a = [1, putchar(65)]; delete a; return a[2]; }";
+        let code = Arm32Generator::new(parser::parse_program(source).unwrap())
+            .with_options(CodegenOptions {
+                bounds_check: BoundsCheck::Trap,
+                entry: Entry::Start,
+                platform: Platform::BareMetal,
+                console: Console::Semihosting,
+                ..CodegenOptions::default()
+            })
+            .generate(&mut Environment::default());
+        assert!(code.contains(&Instruction::Bl(String::from(runtime::PUTCHAR_ROUTINE))));
+        assert!(code.contains(&Instruction::Bl(String::from(runtime::ALLOC_ROUTINE))));
+        for libc in ["malloc", "free", "putchar", "write", "abort"] {
+            assert!(!code.contains(&Instruction::Bl(String::from(libc))));
+        }
+        let wrapper = code
+            .iter()
+            .position(|x| *x == Instruction::Global(String::from("_start")))
+            .unwrap();
+        assert_eq!(
+            instruction::render(&code[wrapper + 5..wrapper + 9]),
+            "    mov r0, #24\n    ldr r1, =131110\n    svc #1193046\n    b .\n"
+        );
+        assert!(code.contains(&Instruction::Label(String::from(runtime::ABORT_ROUTINE))));
+    }
+
+    #[test]
+    fn frame_layout() {
+        let generator = Arm32Generator::new(
+            parser::parse_program(
+                "function f(x, y) { var a = 1; if (x == y) { var b = 2; } else {} var c = 3; }",
+            )
+            .unwrap(),
+        );
+        generator.generate(&mut Environment::default());
+        assert_eq!(
+            generator.frame_layouts(),
+            [FrameLayout {
+                function: String::from("f"),
+                label: String::from("f"),
+                parameters: vec![(String::from("x"), -16), (String::from("y"), -12)],
+                locals: vec![
+                    (String::from("a"), -20),
+                    (String::from("b"), -24),
+                    (String::from("c"), -24),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn frame_sizes_of_functions() {
+        let code = generate(
+            "function f(x) { var a = 1; return g(x, a) + 1; }
+            function g(x, y) { return x; }",
+        );
+        let sizes = frame_sizes(&code);
+        // lr, fp, four arguments, one local padded to two words and the
+        // argument area of the call
+        assert_eq!(sizes["f"], 8 + 16 + 8 + 16);
+        assert_eq!(sizes["g"], 8 + 16);
+    }
+
+    #[test]
+    fn calls_keep_the_stack_aligned() {
+        let code = generate(include_str!("../../main.ts"));
+        let offsets = stack_offsets_at_calls(&code);
+        assert!(offsets.len() > 30);
+        for offset in offsets {
+            assert_eq!(offset % 8, 0, "misaligned call at sp {}", offset);
+        }
+    }
+
+    #[test]
+    fn call_sequence() {
+        let code = generate("function f(x, y, z) { var a = 1; return f(a, 2, 3 + f(1, 2, 3)); }");
+        let calls = stack_offsets_at_calls(&code);
+        // 8 bytes of fp and lr, 16 bytes of spilled arguments and 8 bytes of
+        // locals, the inner call also has the 16 bytes reserved for the
+        // arguments of the outer one and the spilled `3` below that
+        assert_eq!(calls, vec![-56, -32]);
+        let start = code
+            .iter()
+            .position(|instruction| {
+                *instruction
+                    == Instruction::Sub(
+                        Condition::Always,
+                        Register::Sp,
+                        Register::Sp,
+                        Operand::Immediate(16),
+                    )
+            })
+            .unwrap();
+        let end = code
+            .iter()
+            .rposition(|instruction| matches!(instruction, Instruction::Bl(_)))
+            .unwrap();
+        let rendered = instruction::render(&code[start..=end]);
+        assert_eq!(
+            rendered,
+            "    sub sp, sp, #16
+    ldr r0, [fp, #-20]
+    str r0, [sp, #0]
+    ldr r0, =2
+    str r0, [sp, #4]
+    ldr r0, =3
+    push {r0, ip}
+    sub sp, sp, #16
+    ldr r0, =1
+    str r0, [sp, #0]
+    ldr r0, =2
+    str r0, [sp, #4]
+    ldr r0, =3
+    str r0, [sp, #8]
+    pop {r0, r1, r2, r3}
+    bl f
+    pop {r1, ip}
+    add r0, r1, r0
+    str r0, [sp, #8]
+    pop {r0, r1, r2, r3}
+    bl f
+"
+        );
+    }
+
+    #[test]
+    fn bounds_checks() {
+        let source = "function f(xs: array<number>, i) { return xs[i]; }";
+        let generate_with = |bounds_check| {
+            let ast = parser::parse_program(source).unwrap();
+            let options = CodegenOptions {
+                bounds_check,
+                ..CodegenOptions::default()
+            };
+            Arm32Generator::new(ast)
+                .with_options(options)
+                .generate(&mut Environment::default())
+        };
+
+        let code = generate_with(BoundsCheck::Trap);
+        assert_eq!(
+            instruction::render(&code[7..12]),
+            "    ldr r2, [r0]
+    cmp r1, r2
+    bhs __rtsc_bounds_error
+    add r0, r0, #4
+    lsl r1, r1, #2
+"
+        );
+        assert!(code.contains(&Instruction::Label(String::from(BOUNDS_ERROR_ROUTINE))));
+
+        let code = generate_with(BoundsCheck::Off);
+        assert_eq!(
+            instruction::render(&code[7..10]),
+            "    add r0, r0, #4
+    lsl r1, r1, #2
+    ldr r0, [r0, r1]
+"
+        );
+        assert!(!code.contains(&Instruction::Label(String::from(BOUNDS_ERROR_ROUTINE))));
+    }
+
+    #[test]
+    fn division_check() {
+        let generate_with = |source| {
+            Arm32Generator::new(parser::parse_program(source).unwrap())
+                .with_options(CodegenOptions {
+                    division_check: true,
+                    ..CodegenOptions::default()
+                })
+                .generate(&mut Environment::default())
+        };
+        let code = generate_with("function f(x, y) { return x / y; }");
+        let division = code
+            .iter()
+            .position(|x| matches!(x, Instruction::Sdiv(..)))
+            .unwrap();
+        assert_eq!(
+            instruction::render(&code[division - 2..division]),
+            "    cmp r1, #0\n    beq __rtsc_division_error\n"
+        );
+        assert!(code.contains(&Instruction::Label(String::from(DIVISION_ERROR_ROUTINE))));
+        assert!(code.contains(&Instruction::Asciz(String::from(
+            "error: division by zero\n"
+        ))));
+
+        // dividing by a constant cannot fail
+        let code = generate_with("function f(x) { return x / 3; }");
+        assert!(!code.contains(&Instruction::Label(String::from(DIVISION_ERROR_ROUTINE))));
+    }
+
+    #[test]
+    fn null_check() {
+        let code = Arm32Generator::new(
+            parser::parse_program("function f(xs: array<number>) { return xs[0] + length([1]); }")
+                .unwrap(),
+        )
+        .with_options(CodegenOptions {
+            null_check: true,
+            ..CodegenOptions::default()
+        })
+        .generate(&mut Environment::default());
+        let checks: Vec<usize> = code
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| **x == Instruction::B(Condition::Eq, String::from(NULL_ERROR_ROUTINE)))
+            .map(|(i, _)| i)
+            .collect();
+        // the literal is known not to be null
+        assert_eq!(checks.len(), 1);
+        assert!(matches!(
+            code[checks[0] - 1],
+            Instruction::Cmp(_, Operand::Immediate(0))
+        ));
+        assert!(code.contains(&Instruction::Label(String::from(NULL_ERROR_ROUTINE))));
+    }
+
+    #[test]
+    fn register_need() {
+        let register_need = |source| {
+            let expression = match parser::parse_program(source).unwrap() {
+                Ast::Block(mut statements) => statements.remove(0),
+                statement => statement,
+            };
+            register_needs(&expression)[&(&expression as *const Ast)]
+        };
+        assert_eq!(register_need("x;"), Some(1));
+        assert_eq!(register_need("(a + b) * (c + d);"), Some(3));
+        assert_eq!(register_need("a + (b + (c + d));"), Some(2));
+        assert_eq!(register_need("xs[0];"), Some(3));
+        assert_eq!(register_need("a + f();"), None);
+    }
+
+    #[test]
+    fn emit() {
+        struct Full;
+        impl io::Write for Full {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::StorageFull, "full"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
             }
         }
+        let source = "function main() { return 0; }";
+        let generator = Arm32Generator::new(parser::parse_program(source).unwrap());
+        let mut output = Vec::new();
+        generator
+            .emit(&mut output, &mut Environment::default())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            instruction::render(&generate(source))
+        );
+        assert!(generator
+            .emit(&mut Full, &mut Environment::default())
+            .is_err());
     }
 }
@@ -0,0 +1,313 @@
+//! Evaluation of expressions at compile time.
+//!
+//! Expressions without side effects over literals and `const` bindings are
+//! constant. They back `static_assert`, the initial values of globals and
+//! `fold`, which replaces them with their values before the code is
+//! generated. The arithmetic is the one of the generated code: numbers wrap
+//! around and dividing by zero gives zero. Divisions of negative numbers are
+//! left to the program, as `sdiv` and `udiv` give them different results.
+use std::collections::BTreeMap;
+
+use crate::ast::Ast;
+use crate::types::Type;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Number(i32),
+    Float(f32),
+    Bool(bool),
+    String(String),
+}
+
+impl Constant {
+    /// The literal with the value.
+    pub fn to_ast(&self) -> Ast {
+        match self {
+            Constant::Number(value) => Ast::Number(*value),
+            Constant::Float(value) => Ast::Float(*value),
+            Constant::Bool(value) => Ast::Bool(*value),
+            Constant::String(text) => Ast::String(text.clone()),
+        }
+    }
+}
+
+/// The values of the `const` bindings in scope.
+pub type Constants = BTreeMap<String, Constant>;
+
+/// The value of the expression, `None` when it is not constant.
+pub fn evaluate(expression: &Ast, constants: &Constants) -> Option<Constant> {
+    // with a stack of its own, as a chain of operators is as deep as it is
+    // long: the operands are evaluated first, in order
+    enum Step<'a> {
+        Enter(&'a Ast),
+        Exit(&'a Ast),
+    }
+    let mut steps = vec![Step::Enter(expression)];
+    let mut values = Vec::new();
+    while let Some(step) = steps.pop() {
+        match step {
+            Step::Enter(ast) => {
+                steps.push(Step::Exit(ast));
+                steps.extend(ast.children().into_iter().rev().map(Step::Enter));
+            }
+            Step::Exit(ast) => {
+                let operands = values.split_off(values.len() - ast.children().len());
+                values.push(operation(ast, operands, constants)?);
+            }
+        }
+    }
+    values.pop()
+}
+
+// The value of the expression, given the values of its operands.
+fn operation(expression: &Ast, operands: Vec<Constant>, constants: &Constants) -> Option<Constant> {
+    let mut operands = operands.into_iter();
+    let mut operand = || operands.next();
+    Some(match expression {
+        Ast::Number(value) => Constant::Number(*value),
+        Ast::Float(value) => Constant::Float(*value),
+        Ast::Bool(value) => Constant::Bool(*value),
+        Ast::String(text) => Constant::String(text.clone()),
+        Ast::Identifier(name) => constants.get(name)?.clone(),
+        Ast::Not(_) => match operand()? {
+            Constant::Bool(value) => Constant::Bool(!value),
+            _ => return None,
+        },
+        Ast::Equal(_, _) => Constant::Bool(equal(operand()?, operand()?)?),
+        Ast::NotEqual(_, _) => Constant::Bool(!equal(operand()?, operand()?)?),
+        Ast::Addition(_, _) => arithmetic(
+            operand()?,
+            operand()?,
+            |lhs, rhs| Some(lhs.wrapping_add(rhs)),
+            |lhs, rhs| lhs + rhs,
+        )?,
+        Ast::Subtraction(_, _) => arithmetic(
+            operand()?,
+            operand()?,
+            |lhs, rhs| Some(lhs.wrapping_sub(rhs)),
+            |lhs, rhs| lhs - rhs,
+        )?,
+        Ast::Multiplication(_, _) => arithmetic(
+            operand()?,
+            operand()?,
+            |lhs, rhs| Some(lhs.wrapping_mul(rhs)),
+            |lhs, rhs| lhs * rhs,
+        )?,
+        Ast::Division(_, _) => arithmetic(operand()?, operand()?, divide, |lhs, rhs| lhs / rhs)?,
+        Ast::ArrayLength(_) => match operand()? {
+            // a string has an element per character, like `character_codes`
+            Constant::String(text) => Constant::Number(text.chars().count() as i32),
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+fn equal(lhs: Constant, rhs: Constant) -> Option<bool> {
+    match (lhs, rhs) {
+        (Constant::Number(lhs), Constant::Number(rhs)) => Some(lhs == rhs),
+        (Constant::Float(lhs), Constant::Float(rhs)) => Some(lhs == rhs),
+        (Constant::Bool(lhs), Constant::Bool(rhs)) => Some(lhs == rhs),
+        (Constant::String(lhs), Constant::String(rhs)) => Some(lhs == rhs),
+        _ => None,
+    }
+}
+
+fn arithmetic(
+    lhs: Constant,
+    rhs: Constant,
+    numbers: fn(i32, i32) -> Option<i32>,
+    floats: fn(f32, f32) -> f32,
+) -> Option<Constant> {
+    match (lhs, rhs) {
+        (Constant::Number(lhs), Constant::Number(rhs)) => {
+            Some(Constant::Number(numbers(lhs, rhs)?))
+        }
+        (Constant::Float(lhs), Constant::Float(rhs)) => Some(Constant::Float(floats(lhs, rhs))),
+        _ => None,
+    }
+}
+
+fn divide(lhs: i32, rhs: i32) -> Option<i32> {
+    match (lhs, rhs) {
+        (_, 0) => Some(0),
+        (lhs, rhs) if lhs >= 0 && rhs > 0 => Some(lhs / rhs),
+        _ => None,
+    }
+}
+
+/// Replaces the uses of `const` bindings and the initial values of the
+/// globals of the typechecked program with their values, and with
+/// `expressions` the other constant expressions too, see
+/// `passes::Pass::ConstFold`. The bindings themselves are left in place,
+/// the generators emit nothing for them.
+pub fn fold(program: &mut Ast, expressions: bool) {
+    let mut constants = Constants::new();
+    match program {
+        Ast::Block(statements) => {
+            for statement in statements {
+                // the globals are laid out with their values
+                let expressions = expressions || matches!(statement, Ast::Var(_, _));
+                fold_statement(statement, &mut constants, expressions);
+            }
+        }
+        statement => fold_statement(statement, &mut constants, expressions),
+    }
+}
+
+fn fold_statement(statement: &mut Ast, constants: &mut Constants, expressions: bool) {
+    match statement {
+        Ast::Const(name, value) => {
+            fold_expression(value, constants, expressions);
+            if let Some(value) = evaluate(value, constants) {
+                constants.insert(name.clone(), value);
+            }
+        }
+        // a variable shadows the binding of the same name
+        Ast::Var(name, value) => {
+            fold_expression(value, constants, expressions);
+            constants.remove(name);
+        }
+        Ast::Block(statements) => {
+            let mut scope = constants.clone();
+            for statement in statements {
+                fold_statement(statement, &mut scope, expressions);
+            }
+        }
+        Ast::Function(_, function_type, body) => {
+            let mut scope = constants.clone();
+            if let Type::Function {
+                parameter_types, ..
+            } = function_type
+            {
+                for parameter in parameter_types.keys() {
+                    scope.remove(parameter);
+                }
+            }
+            fold_statement(body, &mut scope, expressions);
+        }
+        Ast::Export(function) => fold_statement(function, constants, expressions),
+        Ast::If(condition, consequence, alternative) => {
+            fold_expression(condition, constants, expressions);
+            fold_statement(consequence, &mut constants.clone(), expressions);
+            fold_statement(alternative, &mut constants.clone(), expressions);
+        }
+        Ast::While(condition, body) => {
+            fold_expression(condition, constants, expressions);
+            fold_statement(body, &mut constants.clone(), expressions);
+        }
+        Ast::Return(value) | Ast::Delete(value) | Ast::Assignment(_, value) => {
+            fold_expression(value, constants, expressions)
+        }
+        Ast::Import(_, _) | Ast::Extern(_, _) | Ast::StaticAssert(_, _) | Ast::Doc(_) => {}
+        expression => fold_expression(expression, constants, expressions),
+    }
+}
+
+// The operands are folded first, so every node is evaluated over literals,
+// and only over literals, as evaluating an operand which is not one again
+// would take as long as the operand is deep. Without `expressions` only the
+// bindings are replaced.
+fn fold_expression(expression: &mut Ast, constants: &Constants, expressions: bool) {
+    expression.rewrite_bottom_up(|expression, _| {
+        let binding = matches!(expression, Ast::Identifier(_));
+        if !binding && (expression.children().is_empty() || !expressions) {
+            return;
+        }
+        let literals = expression.children().into_iter().all(|operand| {
+            matches!(
+                operand,
+                Ast::Number(_) | Ast::Float(_) | Ast::Bool(_) | Ast::String(_)
+            )
+        });
+        if !literals {
+            return;
+        }
+        if let Some(value) = evaluate(expression, constants) {
+            *expression = value.to_ast();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn evaluate_source(source: &str) -> Option<Constant> {
+        let mut constants = Constants::new();
+        constants.insert(String::from("N"), Constant::Number(3));
+        evaluate(&parser::parse_expression(source).unwrap(), &constants)
+    }
+
+    #[test]
+    fn constant_expressions() {
+        assert_eq!(evaluate_source("1 + 2 * 3"), Some(Constant::Number(7)));
+        assert_eq!(evaluate_source("7 / 0"), Some(Constant::Number(0)));
+        assert_eq!(
+            evaluate_source("2147483647 + 1"),
+            Some(Constant::Number(i32::MIN))
+        );
+        assert_eq!(evaluate_source("1.5 * 2.0"), Some(Constant::Float(3.0)));
+        assert_eq!(evaluate_source("!(1 == 2)"), Some(Constant::Bool(true)));
+        assert_eq!(
+            evaluate_source("\"ab\" != \"ab\""),
+            Some(Constant::Bool(false))
+        );
+        assert_eq!(
+            evaluate_source("length(\"abc\") + 1"),
+            Some(Constant::Number(4))
+        );
+        assert_eq!(
+            evaluate_source("length(\"été\")"),
+            Some(Constant::Number(3))
+        );
+        assert_eq!(evaluate_source("N * N"), Some(Constant::Number(9)));
+    }
+
+    #[test]
+    fn not_constant() {
+        assert_eq!(evaluate_source("x + 1"), None);
+        assert_eq!(evaluate_source("f() == 1"), None);
+        assert_eq!(evaluate_source("xs[0]"), None);
+        assert_eq!(evaluate_source("1 == true"), None);
+        // `udiv` would give another result
+        assert_eq!(evaluate_source("(0 - 7) / 2"), None);
+    }
+
+    #[test]
+    fn folding() {
+        let mut program = parser::parse_program(
+            "const N = 2 * 3;
+            var global = N + 1;
+            function f(N: number) { return N; }
+            function g(x: number) {
+                if (x == N) { var N = x; return N; } else { return N - length(\"ab\"); }
+            }",
+        )
+        .unwrap();
+        let mut bindings = program.clone();
+        fold(&mut program, true);
+        let expected = parser::parse_program(
+            "const N = 6;
+            var global = 7;
+            function f(N: number) { return N; }
+            function g(x: number) {
+                if (x == 6) { var N = x; return N; } else { return 4; }
+            }",
+        )
+        .unwrap();
+        assert_eq!(program, expected);
+        fold(&mut bindings, false);
+        let expected = parser::parse_program(
+            "const N = 2 * 3;
+            var global = 7;
+            function f(N: number) { return N; }
+            function g(x: number) {
+                if (x == 6) { var N = x; return N; } else { return 6 - length(\"ab\"); }
+            }",
+        )
+        .unwrap();
+        assert_eq!(bindings, expected);
+    }
+}
@@ -0,0 +1,267 @@
+//! Copy propagation and redundant load elimination on the ARM32 code.
+//!
+//! The code generator loads a local from its slot, `[fp, #offset]`, every
+//! time an expression reads it, even when a register still holds the value
+//! from the previous load or store. Within a basic block the pass remembers
+//! which register holds which slot and replaces loads of a remembered slot
+//! with a `mov` from the register, or drops them when the value is already
+//! in the right register. Anything the pass does not understand, such as a
+//! call, a label or a store through another base, forgets everything.
+use crate::phases::instruction::{Address, Condition, Instruction, Operand, Register};
+
+pub fn propagate(code: Vec<Instruction>) -> Vec<Instruction> {
+    propagate_mapped(code).0
+}
+
+/// The code without the redundant loads, along with the index of the
+/// instruction each instruction comes from.
+pub fn propagate_mapped(code: Vec<Instruction>) -> (Vec<Instruction>, Vec<usize>) {
+    let mut propagated = Vec::with_capacity(code.len());
+    let mut origins = Vec::with_capacity(code.len());
+    let mut holders = Holders::default();
+    for (origin, instruction) in code.into_iter().enumerate() {
+        let instruction = match instruction {
+            Instruction::Ldr(
+                Condition::Always,
+                target,
+                Address::Offset(Register::Fp, Operand::Immediate(offset)),
+            ) if !is_special(target) => {
+                let holder = holders.holder(offset, target);
+                if holder == Some(target) {
+                    continue;
+                }
+                holders.forget(target);
+                holders.hold(offset, target);
+                match holder {
+                    Some(source) => {
+                        Instruction::Mov(Condition::Always, target, Operand::Register(source))
+                    }
+                    None => Instruction::Ldr(
+                        Condition::Always,
+                        target,
+                        Address::Offset(Register::Fp, Operand::Immediate(offset)),
+                    ),
+                }
+            }
+            Instruction::Str(
+                condition,
+                source,
+                Address::Offset(Register::Fp, Operand::Immediate(offset)),
+            ) => {
+                holders.0.retain(|(slot, _)| *slot != offset);
+                if condition == Condition::Always {
+                    holders.hold(offset, source);
+                }
+                Instruction::Str(
+                    condition,
+                    source,
+                    Address::Offset(Register::Fp, Operand::Immediate(offset)),
+                )
+            }
+            // the copy holds the same slots as the original
+            Instruction::Mov(Condition::Always, target, Operand::Register(source))
+                if !is_special(target) && !is_special(source) =>
+            {
+                if target != source {
+                    holders.forget(target);
+                    let slots: Vec<i32> = holders.slots(source).collect();
+                    for slot in slots {
+                        holders.hold(slot, target);
+                    }
+                }
+                Instruction::Mov(Condition::Always, target, Operand::Register(source))
+            }
+            instruction => {
+                match written(&instruction) {
+                    Some(registers) => {
+                        for register in registers {
+                            holders.forget(register);
+                        }
+                    }
+                    None => holders.0.clear(),
+                }
+                instruction
+            }
+        };
+        propagated.push(instruction);
+        origins.push(origin);
+    }
+    (propagated, origins)
+}
+
+// The offsets of the slots and the registers holding their values, a slot
+// may be in several registers and a register may hold several slots.
+#[derive(Default)]
+struct Holders(Vec<(i32, Register)>);
+
+impl Holders {
+    // A register holding the slot, the preferred one if it does.
+    fn holder(&self, offset: i32, preferred: Register) -> Option<Register> {
+        let mut holders = self
+            .0
+            .iter()
+            .filter(|(slot, _)| *slot == offset)
+            .map(|(_, register)| *register);
+        let first = holders.next()?;
+        Some(if holders.any(|register| register == preferred) {
+            preferred
+        } else {
+            first
+        })
+    }
+
+    fn slots(&self, register: Register) -> impl Iterator<Item = i32> + '_ {
+        self.0
+            .iter()
+            .filter(move |(_, holder)| *holder == register)
+            .map(|(slot, _)| *slot)
+    }
+
+    fn hold(&mut self, offset: i32, register: Register) {
+        if !is_special(register) && !self.0.contains(&(offset, register)) {
+            self.0.push((offset, register));
+        }
+    }
+
+    fn forget(&mut self, register: Register) {
+        if is_special(register) {
+            self.0.clear();
+        } else {
+            self.0.retain(|(_, holder)| *holder != register);
+        }
+    }
+}
+
+// The frame pointer addresses the slots, the others never hold them.
+fn is_special(register: Register) -> bool {
+    matches!(
+        register,
+        Register::Fp | Register::Sp | Register::Lr | Register::Pc
+    )
+}
+
+// The registers the instruction writes, `None` for the instructions after
+// which nothing is known: the ones ending the block, calls and the ones
+// writing memory other than the slots.
+fn written(instruction: &Instruction) -> Option<Vec<Register>> {
+    let registers = match instruction {
+        Instruction::Mov(_, target, _)
+        | Instruction::LoadConstant(target, _)
+        | Instruction::LoadAddress(target, _)
+        | Instruction::Ldr(_, target, _)
+        | Instruction::Ldrb(_, target, _)
+        | Instruction::Add(_, target, _, _)
+        | Instruction::Sub(_, target, _, _)
+        | Instruction::Rsb(_, target, _, _)
+        | Instruction::Mul(target, _, _)
+        | Instruction::Sdiv(target, _, _)
+        | Instruction::Udiv(target, _, _)
+        | Instruction::Lsl(_, target, _, _)
+        | Instruction::Lsr(_, target, _, _)
+        | Instruction::Ror(_, target, _, _)
+        | Instruction::Clz(target, _)
+        | Instruction::VmovFromFloat(target, _) => vec![*target],
+        // a conditional branch falls through into the same block
+        Instruction::B(condition, _) if *condition != Condition::Always => Vec::new(),
+        Instruction::Cmp(_, _)
+        | Instruction::VmovToFloat(_, _)
+        | Instruction::Vadd(_, _, _)
+        | Instruction::Vsub(_, _, _)
+        | Instruction::Vmul(_, _, _)
+        | Instruction::Vdiv(_, _, _)
+        | Instruction::Vcmp(_, _)
+        | Instruction::Vmrs
+        | Instruction::Comment(_)
+        | Instruction::File(_, _)
+        | Instruction::Loc(_, _, _) => Vec::new(),
+        _ => return None,
+    };
+    Some(registers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ldr(register: Register, offset: i32) -> Instruction {
+        Instruction::Ldr(
+            Condition::Always,
+            register,
+            Address::Offset(Register::Fp, Operand::Immediate(offset)),
+        )
+    }
+
+    fn str(register: Register, offset: i32) -> Instruction {
+        Instruction::Str(
+            Condition::Always,
+            register,
+            Address::Offset(Register::Fp, Operand::Immediate(offset)),
+        )
+    }
+
+    fn mov(target: Register, source: Register) -> Instruction {
+        Instruction::Mov(Condition::Always, target, Operand::Register(source))
+    }
+
+    #[test]
+    fn reloads() {
+        let code = vec![
+            ldr(Register::R0, -16),
+            ldr(Register::R1, -16),
+            Instruction::Mul(Register::R0, Register::R0, Register::R1),
+            str(Register::R0, -20),
+            ldr(Register::R0, -20),
+            ldr(Register::R1, -16),
+            Instruction::Add(
+                Condition::Always,
+                Register::R0,
+                Register::R0,
+                Operand::Register(Register::R1),
+            ),
+            ldr(Register::R2, -16),
+        ];
+        let (propagated, origins) = propagate_mapped(code);
+        assert_eq!(
+            propagated,
+            [
+                ldr(Register::R0, -16),
+                mov(Register::R1, Register::R0),
+                Instruction::Mul(Register::R0, Register::R0, Register::R1),
+                str(Register::R0, -20),
+                // `r1` still holds the slot the multiplication overwrote in `r0`
+                Instruction::Add(
+                    Condition::Always,
+                    Register::R0,
+                    Register::R0,
+                    Operand::Register(Register::R1)
+                ),
+                mov(Register::R2, Register::R1),
+            ]
+        );
+        assert_eq!(origins, [0, 1, 2, 3, 6, 7]);
+    }
+
+    #[test]
+    fn blocks() {
+        let code = vec![
+            ldr(Register::R0, -16),
+            Instruction::B(Condition::Eq, String::from(".L0")),
+            ldr(Register::R1, -16),
+            Instruction::Label(String::from(".L0")),
+            ldr(Register::R0, -16),
+            Instruction::Bl(String::from("f")),
+            ldr(Register::R0, -16),
+            Instruction::Str(
+                Condition::Always,
+                Register::R1,
+                Address::Offset(Register::R2, Operand::Immediate(4)),
+            ),
+            ldr(Register::R1, -16),
+        ];
+        let propagated = propagate(code);
+        assert_eq!(propagated[2], mov(Register::R1, Register::R0));
+        assert_eq!(propagated[4], ldr(Register::R0, -16));
+        assert_eq!(propagated[6], ldr(Register::R0, -16));
+        assert_eq!(propagated[8], ldr(Register::R1, -16));
+    }
+}
@@ -0,0 +1,253 @@
+//! Code coverage instrumentation added to the ARM32 output by
+//! `--instrument-coverage`.
+//!
+//! Every basic block of the functions increments a counter of its own in
+//! `.bss` before running. Once `main` returns, the `--entry` wrappers call
+//! `__rtsc_coverage_dump`, which writes the counters to `rtsc.cov` in the
+//! working directory as little endian words, in the order of `blocks`. The
+//! report of `rtsc cov report` finds the same blocks in the source to tell
+//! how often each line ran.
+use std::fmt::Write;
+
+use crate::ast::Ast;
+use crate::lines::SourceLine;
+use crate::phases::codegen::{Console, Platform};
+use crate::phases::instruction::{Address, Condition, Instruction, Operand, Register};
+use crate::phases::runtime;
+
+pub const COUNTERS: &str = "__rtsc_coverage_counters";
+pub const DUMP_ROUTINE: &str = "__rtsc_coverage_dump";
+/// Where the counters are written to, relative to the working directory.
+pub const FILE_NAME: &str = "rtsc.cov";
+/// The first statement of every basic block of the functions, in source
+/// order. A block starts a function body, a branch of an `if`, the body of
+/// a `while` and follows an `if`, a `while` or a `return`. Every `while`
+/// starts one too, as the loop jumps back to its condition. Statements
+/// without code of their own never start one, the block starts at the next
+/// statement, and a branch without statements has no counter.
+pub fn blocks(program: &Ast) -> Vec<&Ast> {
+    // Visits the statement, `leader` telling whether it starts a block.
+    // Returns whether the statement following it does.
+    fn visit<'a>(ast: &'a Ast, leader: bool, blocks: &mut Vec<&'a Ast>) -> bool {
+        match ast {
+            Ast::Block(statements) => statements
+                .iter()
+                .fold(leader, |leader, statement| visit(statement, leader, blocks)),
+            Ast::Doc(_) | Ast::Const(_, _) | Ast::StaticAssert(_, _) => leader,
+            _ => {
+                if leader || matches!(ast, Ast::While(_, _)) {
+                    blocks.push(ast);
+                }
+                match ast {
+                    Ast::If(_, consequence, alternative) => {
+                        visit(consequence, true, blocks);
+                        visit(alternative, true, blocks);
+                        true
+                    }
+                    Ast::While(_, body) => {
+                        visit(body, true, blocks);
+                        true
+                    }
+                    Ast::Return(_) => true,
+                    _ => false,
+                }
+            }
+        }
+    }
+    let mut blocks = Vec::new();
+    for declaration in program.declarations() {
+        if let Ast::Function(_, _, body) = declaration {
+            visit(body, true, &mut blocks);
+        }
+    }
+    blocks
+}
+
+/// Increments the counter of the block, clobbering `r0` to `r2`, which hold
+/// nothing between statements.
+pub fn emit_increment(counter: usize, code: &mut Vec<Instruction>) {
+    let offset = 4 * counter as i32;
+    code.push(Instruction::LoadAddress(
+        Register::R1,
+        String::from(COUNTERS),
+    ));
+    // loads and stores reach 4095 bytes past their base
+    let address = if offset == 0 {
+        Address::Register(Register::R1)
+    } else if offset < 4096 {
+        Address::Offset(Register::R1, Operand::Immediate(offset))
+    } else {
+        code.push(Instruction::LoadConstant(Register::R2, offset));
+        Address::Offset(Register::R1, Operand::Register(Register::R2))
+    };
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R0,
+        address.clone(),
+    ));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R0,
+        Register::R0,
+        Operand::Immediate(1),
+    ));
+    code.push(Instruction::Str(Condition::Always, Register::R0, address));
+}
+
+/// Emits the counters and the routine writing them to `rtsc.cov`.
+pub fn emit_runtime(
+    platform: Platform,
+    console: Console,
+    blocks: usize,
+    code: &mut Vec<Instruction>,
+) {
+    let size = 4 * blocks as u32;
+    runtime::emit_write_file(
+        platform,
+        console,
+        DUMP_ROUTINE,
+        FILE_NAME,
+        COUNTERS,
+        size as i32,
+        code,
+    );
+    code.push(Instruction::Section(String::from(".bss")));
+    code.push(Instruction::Balign(4));
+    code.push(Instruction::Label(String::from(COUNTERS)));
+    code.push(Instruction::Space(size));
+    code.push(Instruction::Section(String::from(".text")));
+}
+
+/// Reads the counters written by the dump routine.
+pub fn read_counts(bytes: &[u8]) -> Result<Vec<u32>, String> {
+    let words = bytes.chunks_exact(4);
+    if !words.remainder().is_empty() {
+        return Err(format!(
+            "{} bytes are not a whole number of counters",
+            bytes.len()
+        ));
+    }
+    Ok(words
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect())
+}
+
+/// Lists the sources with how often each line ran, in the manner of `gcov`:
+/// `-` marks the lines starting no block and `#####` the ones whose blocks
+/// never ran. A line starting several blocks gets the largest count. The
+/// `files` are the paths and the sources the `lines` of the blocks refer to.
+pub fn report(files: &[(String, String)], lines: &[SourceLine], counts: &[u32]) -> String {
+    let mut output = String::new();
+    for (file, (path, source)) in files.iter().enumerate() {
+        writeln!(output, "{}:", path).unwrap();
+        for (i, text) in source.lines().enumerate() {
+            let count = lines
+                .iter()
+                .zip(counts)
+                .filter(|(line, _)| line.file == file && line.location.line == i + 1)
+                .map(|(_, &count)| count)
+                .max();
+            let count = match count {
+                None => String::from("-"),
+                Some(0) => String::from("#####"),
+                Some(count) => count.to_string(),
+            };
+            writeln!(output, "{:>9}:{:>5}:{}", count, i + 1, text).unwrap();
+        }
+    }
+    let run = counts.iter().filter(|&&count| count > 0).count();
+    let percentage = if counts.is_empty() {
+        100.0
+    } else {
+        100.0 * run as f64 / counts.len() as f64
+    };
+    writeln!(
+        output,
+        "{} of {} blocks run ({:.1}%)",
+        run,
+        counts.len(),
+        percentage
+    )
+    .unwrap();
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines;
+    use crate::parser::{self, ParseOptions};
+
+    const SOURCE: &str = "function f(n: number): number {
+    var i = 0;
+    while (i != n) {
+        i = i + 1;
+    }
+    if (i == 0) { return 1; } else {}
+    return i;
+    /// Never reached.
+    const unused = 2;
+    i = 3;
+}";
+
+    #[test]
+    fn basic_blocks() {
+        let program = parser::parse_program(SOURCE).unwrap();
+        let blocks: Vec<String> = blocks(&program)
+            .into_iter()
+            .map(|statement| statement.to_string().lines().next().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            blocks,
+            [
+                "var i = 0;",
+                "while (i != n) {",
+                "i = i + 1;",
+                "if (i == 0) {",
+                "return 1;",
+                "return i;",
+                "i = 3;",
+            ]
+        );
+    }
+
+    #[test]
+    fn line_counts() {
+        let (program, spans) =
+            parser::parse_program_with_spans(SOURCE, &ParseOptions::default()).unwrap();
+        let statements = lines::statements(&program);
+        let source_lines = lines::statement_lines(0, SOURCE, &spans);
+        let block_lines: Vec<SourceLine> = blocks(&program)
+            .into_iter()
+            .map(|block| {
+                let index = statements
+                    .iter()
+                    .position(|statement| std::ptr::eq(*statement, block))
+                    .unwrap();
+                source_lines[index].clone()
+            })
+            .collect();
+        let files = [(String::from("f.ts"), String::from(SOURCE))];
+        let report = report(&files, &block_lines, &[1, 4, 3, 1, 0, 1, 0]);
+        assert!(report.starts_with(
+            "f.ts:
+        -:    1:function f(n: number): number {
+        1:    2:    var i = 0;
+        4:    3:    while (i != n) {
+        3:    4:        i = i + 1;
+        -:    5:    }
+        1:    6:    if (i == 0) { return 1; } else {}
+        1:    7:    return i;
+"
+        ));
+        assert!(report.ends_with(
+            "    #####:   10:    i = 3;\n        -:   11:}\n5 of 7 blocks run (71.4%)\n"
+        ));
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(read_counts(&[1, 0, 0, 0, 2, 1, 0, 0]), Ok(vec![1, 258]));
+        assert!(read_counts(&[1, 0]).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! Division on the cores without the `sdiv` and `udiv` instructions, such
+//! as the ARMv6 ones and the ARMv7-A ones without the division extension.
+//!
+//! Every division instruction of the code is replaced with a call of a
+//! routine, which takes the dividend and the divisor on the stack and
+//! leaves the quotient in place of the dividend:
+//!
+//! ```text
+//! push {lr}
+//! sub sp, sp, #8
+//! str rn, [sp]
+//! str rm, [sp, #4]
+//! bl __rtsc_sdiv
+//! ldr rd, [sp]
+//! add sp, sp, #8
+//! pop {lr}
+//! ```
+//!
+//! The routines keep every register, so the division still only writes its
+//! destination, only the flags are lost, which the generated code never
+//! tests across a division. Like the instructions they give zero when
+//! dividing by zero.
+use crate::phases::instruction::{Address, Condition, Instruction, Operand, Register};
+
+pub const SIGNED_DIVISION_ROUTINE: &str = "__rtsc_sdiv";
+pub const UNSIGNED_DIVISION_ROUTINE: &str = "__rtsc_udiv";
+
+fn label(name: &str) -> Instruction {
+    Instruction::Label(String::from(name))
+}
+
+fn stack_slot(offset: i32) -> Address {
+    match offset {
+        0 => Address::Register(Register::Sp),
+        offset => Address::Offset(Register::Sp, Operand::Immediate(offset)),
+    }
+}
+
+fn load(rd: Register, offset: i32) -> Instruction {
+    Instruction::Ldr(Condition::Always, rd, stack_slot(offset))
+}
+
+fn store(rd: Register, offset: i32) -> Instruction {
+    Instruction::Str(Condition::Always, rd, stack_slot(offset))
+}
+
+fn stack(grow: bool, bytes: i32) -> Instruction {
+    let operand = Operand::Immediate(bytes);
+    if grow {
+        Instruction::Sub(Condition::Always, Register::Sp, Register::Sp, operand)
+    } else {
+        Instruction::Add(Condition::Always, Register::Sp, Register::Sp, operand)
+    }
+}
+
+fn ret() -> Instruction {
+    Instruction::Mov(
+        Condition::Always,
+        Register::Pc,
+        Operand::Register(Register::Lr),
+    )
+}
+
+pub fn lower(code: Vec<Instruction>) -> Vec<Instruction> {
+    lower_mapped(code).0
+}
+
+/// The code calling the routines instead of dividing, along with the index
+/// of the instruction each instruction comes from. The routines are not
+/// included, see `emit_routines`.
+pub fn lower_mapped(code: Vec<Instruction>) -> (Vec<Instruction>, Vec<usize>) {
+    let mut lowered = Vec::with_capacity(code.len());
+    let mut origins = Vec::with_capacity(code.len());
+    for (origin, instruction) in code.into_iter().enumerate() {
+        let (routine, rd, rn, rm) = match instruction {
+            Instruction::Sdiv(rd, rn, rm) => (SIGNED_DIVISION_ROUTINE, rd, rn, rm),
+            Instruction::Udiv(rd, rn, rm) => (UNSIGNED_DIVISION_ROUTINE, rd, rn, rm),
+            instruction => {
+                lowered.push(instruction);
+                origins.push(origin);
+                continue;
+            }
+        };
+        let call = [
+            Instruction::Push(vec![Register::Lr]),
+            stack(true, 8),
+            store(rn, 0),
+            store(rm, 4),
+            Instruction::Bl(String::from(routine)),
+            load(rd, 0),
+            stack(false, 8),
+            Instruction::Pop(vec![Register::Lr]),
+        ];
+        origins.extend(std::iter::repeat_n(origin, call.len()));
+        lowered.extend(call);
+    }
+    (lowered, origins)
+}
+
+/// Emits the routines the lowered code calls, nothing when it divides
+/// nowhere.
+pub fn emit_routines(code: &mut Vec<Instruction>) {
+    let calls = |routine: &str| {
+        code.iter()
+            .any(|instruction| matches!(instruction, Instruction::Bl(name) if name == routine))
+    };
+    let signed = calls(SIGNED_DIVISION_ROUTINE);
+    if !signed && !calls(UNSIGNED_DIVISION_ROUTINE) {
+        return;
+    }
+    code.push(Instruction::Section(String::from(".text")));
+    code.push(Instruction::Balign(4));
+    if signed {
+        emit_signed_division(code);
+    }
+    emit_unsigned_division(code);
+}
+
+// Divides the magnitudes and negates the quotient once for every negative
+// operand.
+fn emit_signed_division(code: &mut Vec<Instruction>) {
+    let negate =
+        |register| Instruction::Rsb(Condition::Lt, register, register, Operand::Immediate(0));
+    code.push(label(SIGNED_DIVISION_ROUTINE));
+    code.push(Instruction::Push(vec![
+        Register::R0,
+        Register::R1,
+        Register::Lr,
+    ]));
+    // the operands are above the saved registers
+    code.push(load(Register::R0, 12));
+    code.push(load(Register::R1, 16));
+    for register in [Register::R0, Register::R1] {
+        code.push(Instruction::Cmp(register, Operand::Immediate(0)));
+        code.push(negate(register));
+    }
+    code.push(stack(true, 8));
+    code.push(store(Register::R0, 0));
+    code.push(store(Register::R1, 4));
+    code.push(Instruction::Bl(String::from(UNSIGNED_DIVISION_ROUTINE)));
+    code.push(load(Register::R0, 0));
+    code.push(stack(false, 8));
+    for offset in [12, 16] {
+        code.push(load(Register::R1, offset));
+        code.push(Instruction::Cmp(Register::R1, Operand::Immediate(0)));
+        code.push(negate(Register::R0));
+    }
+    code.push(store(Register::R0, 12));
+    code.push(Instruction::Pop(vec![
+        Register::R0,
+        Register::R1,
+        Register::Lr,
+    ]));
+    code.push(ret());
+}
+
+// Long division, a bit of the dividend at a time from the top: the
+// remainder takes the bit and the divisor is subtracted whenever it fits,
+// setting the bit of the quotient.
+fn emit_unsigned_division(code: &mut Vec<Instruction>) {
+    let bits = ".Ludiv_bits";
+    let subtract = ".Ludiv_subtract";
+    let next = ".Ludiv_next";
+    let done = ".Ludiv_done";
+    let (dividend, divisor, quotient, remainder) =
+        (Register::R0, Register::R1, Register::R2, Register::R3);
+    let (bit, carry, count) = (Register::R4, Register::R5, Register::Ip);
+    let saved = vec![
+        Register::R0,
+        Register::R1,
+        Register::R2,
+        Register::R3,
+        Register::R4,
+        Register::R5,
+        Register::Ip,
+    ];
+    let shift =
+        |instruction: fn(Condition, Register, Register, Operand) -> Instruction, rd, rn, amount| {
+            instruction(Condition::Always, rd, rn, Operand::Immediate(amount))
+        };
+    code.push(label(UNSIGNED_DIVISION_ROUTINE));
+    code.push(Instruction::Push(saved.clone()));
+    let operands = 4 * saved.len() as i32;
+    code.push(load(dividend, operands));
+    code.push(load(divisor, operands + 4));
+    code.push(Instruction::Mov(
+        Condition::Always,
+        quotient,
+        Operand::Immediate(0),
+    ));
+    code.push(Instruction::Cmp(divisor, Operand::Immediate(0)));
+    code.push(Instruction::B(Condition::Eq, String::from(done)));
+    code.push(Instruction::Mov(
+        Condition::Always,
+        remainder,
+        Operand::Immediate(0),
+    ));
+    code.push(Instruction::Mov(
+        Condition::Always,
+        count,
+        Operand::Immediate(32),
+    ));
+    code.push(label(bits));
+    // a remainder shifted past 32 bits exceeds any divisor
+    code.push(shift(Instruction::Lsr, carry, remainder, 31));
+    code.push(shift(Instruction::Lsl, remainder, remainder, 1));
+    code.push(shift(Instruction::Lsr, bit, dividend, 31));
+    code.push(Instruction::Add(
+        Condition::Always,
+        remainder,
+        remainder,
+        Operand::Register(bit),
+    ));
+    code.push(shift(Instruction::Lsl, dividend, dividend, 1));
+    code.push(shift(Instruction::Lsl, quotient, quotient, 1));
+    code.push(Instruction::Cmp(carry, Operand::Immediate(0)));
+    code.push(Instruction::B(Condition::Ne, String::from(subtract)));
+    code.push(Instruction::Cmp(remainder, Operand::Register(divisor)));
+    code.push(Instruction::B(Condition::Lo, String::from(next)));
+    code.push(label(subtract));
+    code.push(Instruction::Sub(
+        Condition::Always,
+        remainder,
+        remainder,
+        Operand::Register(divisor),
+    ));
+    code.push(Instruction::Add(
+        Condition::Always,
+        quotient,
+        quotient,
+        Operand::Immediate(1),
+    ));
+    code.push(label(next));
+    code.push(Instruction::Sub(
+        Condition::Always,
+        count,
+        count,
+        Operand::Immediate(1),
+    ));
+    code.push(Instruction::Cmp(count, Operand::Immediate(0)));
+    code.push(Instruction::B(Condition::Ne, String::from(bits)));
+    code.push(label(done));
+    code.push(store(quotient, operands));
+    code.push(Instruction::Pop(saved));
+    code.push(ret());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phases::instruction;
+
+    #[test]
+    fn lowered_divisions() {
+        let code = vec![
+            Instruction::Mov(Condition::Always, Register::R0, Operand::Immediate(7)),
+            Instruction::Sdiv(Register::R0, Register::R1, Register::R2),
+            Instruction::Bx(Register::Lr),
+        ];
+        let (mut lowered, origins) = lower_mapped(code);
+        assert_eq!(
+            instruction::render(&lowered),
+            "    mov r0, #7
+    push {lr}
+    sub sp, sp, #8
+    str r1, [sp]
+    str r2, [sp, #4]
+    bl __rtsc_sdiv
+    ldr r0, [sp]
+    add sp, sp, #8
+    pop {lr}
+    bx lr
+"
+        );
+        assert_eq!(origins, [0, 1, 1, 1, 1, 1, 1, 1, 1, 2]);
+        emit_routines(&mut lowered);
+        let routines: Vec<&Instruction> = lowered
+            .iter()
+            .filter(|instruction| matches!(instruction, Instruction::Label(name) if !name.starts_with(".L")))
+            .collect();
+        assert_eq!(
+            routines,
+            [
+                &label(SIGNED_DIVISION_ROUTINE),
+                &label(UNSIGNED_DIVISION_ROUTINE)
+            ]
+        );
+        let mut unsigned = lower(vec![Instruction::Udiv(
+            Register::R1,
+            Register::R1,
+            Register::R2,
+        )]);
+        emit_routines(&mut unsigned);
+        assert!(!unsigned.contains(&label(SIGNED_DIVISION_ROUTINE)));
+        assert!(unsigned.contains(&label(UNSIGNED_DIVISION_ROUTINE)));
+        let mut none = vec![Instruction::Bx(Register::Lr)];
+        emit_routines(&mut none);
+        assert_eq!(none, [Instruction::Bx(Register::Lr)]);
+    }
+}
@@ -0,0 +1,251 @@
+//! Dominators and natural loops of a function, computed over its
+//! control-flow graph.
+//!
+//! A block dominates another one when every path from the entry to the
+//! other block goes through it. An edge to a block dominating its source is
+//! a back edge, and the natural loop of a back edge is its target, the
+//! header, along with the blocks reaching the source without going through
+//! the header. Unreachable blocks are dominated by nothing but themselves and
+//! are never part of a loop.
+use std::collections::BTreeSet;
+
+use crate::phases::cfg::{BlockId, Cfg, ENTRY};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dominators {
+    /// The immediate dominator of each block, `None` for the entry and the
+    /// unreachable blocks.
+    pub idom: Vec<Option<BlockId>>,
+}
+
+impl Dominators {
+    /// Computes the dominators with the iterative algorithm of Cooper, Harvey
+    /// and Kennedy, going over the blocks in reverse postorder.
+    pub fn new(cfg: &Cfg<'_>) -> Dominators {
+        let order = reverse_postorder(cfg);
+        let mut position = vec![usize::MAX; cfg.blocks.len()];
+        for (index, &block) in order.iter().enumerate() {
+            position[block] = index;
+        }
+        let predecessors: Vec<Vec<BlockId>> = (0..cfg.blocks.len())
+            .map(|block| cfg.predecessors(block))
+            .collect();
+        let mut idom = vec![None; cfg.blocks.len()];
+        idom[ENTRY] = Some(ENTRY);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in order.iter().skip(1) {
+                let mut processed = predecessors[block]
+                    .iter()
+                    .copied()
+                    .filter(|&predecessor| idom[predecessor].is_some());
+                let first = match processed.next() {
+                    Some(first) => first,
+                    None => continue,
+                };
+                let new = processed.fold(first, |a, b| intersect(&idom, &position, a, b));
+                if idom[block] != Some(new) {
+                    idom[block] = Some(new);
+                    changed = true;
+                }
+            }
+        }
+        idom[ENTRY] = None;
+        Dominators { idom }
+    }
+
+    /// Whether every path from the entry to `block` goes through `dominator`.
+    /// Every block dominates itself.
+    pub fn dominates(&self, dominator: BlockId, block: BlockId) -> bool {
+        let mut current = Some(block);
+        while let Some(id) = current {
+            if id == dominator {
+                return true;
+            }
+            current = self.idom[id];
+        }
+        false
+    }
+
+    /// The blocks immediately dominated by the block, its children in the
+    /// dominator tree.
+    pub fn children(&self, block: BlockId) -> Vec<BlockId> {
+        (0..self.idom.len())
+            .filter(|&id| self.idom[id] == Some(block))
+            .collect()
+    }
+}
+
+// The reachable blocks, each one before its successors except along back
+// edges.
+fn reverse_postorder(cfg: &Cfg<'_>) -> Vec<BlockId> {
+    let mut visited = vec![false; cfg.blocks.len()];
+    let mut postorder = Vec::new();
+    // the blocks on the path from the entry, with their successors left
+    let mut stack = vec![(ENTRY, cfg.successors(ENTRY))];
+    visited[ENTRY] = true;
+    while let Some((block, successors)) = stack.last_mut() {
+        let block = *block;
+        match successors.first().copied() {
+            Some(successor) => {
+                successors.remove(0);
+                if !visited[successor] {
+                    visited[successor] = true;
+                    stack.push((successor, cfg.successors(successor)));
+                }
+            }
+            None => {
+                postorder.push(block);
+                stack.pop();
+            }
+        }
+    }
+    postorder.reverse();
+    postorder
+}
+
+// The nearest common dominator of two processed blocks.
+fn intersect(
+    idom: &[Option<BlockId>],
+    position: &[usize],
+    mut a: BlockId,
+    mut b: BlockId,
+) -> BlockId {
+    while a != b {
+        while position[a] > position[b] {
+            a = idom[a].unwrap();
+        }
+        while position[b] > position[a] {
+            b = idom[b].unwrap();
+        }
+    }
+    a
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// The block every iteration starts in, which dominates the loop.
+    pub header: BlockId,
+    /// The blocks of the loop, the header included.
+    pub blocks: BTreeSet<BlockId>,
+    /// The blocks jumping back to the header.
+    pub latches: Vec<BlockId>,
+}
+
+impl Loop {
+    /// Whether the other loop is nested in this one.
+    pub fn contains(&self, other: &Loop) -> bool {
+        self.header != other.header && other.blocks.is_subset(&self.blocks)
+    }
+}
+
+/// The natural loops of the function ordered by their headers, so an outer
+/// loop comes before the loops nested in it. The back edges to the same
+/// header make up a single loop.
+pub fn loops(cfg: &Cfg<'_>, dominators: &Dominators) -> Vec<Loop> {
+    let reachable = cfg.reachable();
+    let mut loops: Vec<Loop> = Vec::new();
+    for header in 0..cfg.blocks.len() {
+        let latches: Vec<BlockId> = cfg
+            .predecessors(header)
+            .into_iter()
+            .filter(|&latch| reachable[latch] && dominators.dominates(header, latch))
+            .collect();
+        if latches.is_empty() {
+            continue;
+        }
+        let mut blocks = BTreeSet::from([header]);
+        let mut stack = latches.clone();
+        while let Some(block) = stack.pop() {
+            if reachable[block] && blocks.insert(block) {
+                stack.extend(cfg.predecessors(block));
+            }
+        }
+        loops.push(Loop {
+            header,
+            blocks,
+            latches,
+        });
+    }
+    loops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::phases::cfg;
+
+    #[test]
+    fn dominator_tree() {
+        let program = parser::parse_program(
+            "function f(c: boolean) {
+                if (c) { putchar(1); } else { putchar(2); }
+                return 0;
+                putchar(3);
+            }",
+        )
+        .unwrap();
+        let (_, cfg) = &cfg::functions(&program)[0];
+        let dominators = Dominators::new(cfg);
+        // entry, then, else, join, after return
+        assert_eq!(dominators.idom, [None, Some(0), Some(0), Some(0), None]);
+        assert_eq!(dominators.children(ENTRY), [1, 2, 3]);
+        assert!(dominators.dominates(ENTRY, 3));
+        assert!(!dominators.dominates(1, 3));
+        assert!(dominators.dominates(4, 4));
+        assert!(!dominators.dominates(ENTRY, 4));
+        assert!(loops(cfg, &dominators).is_empty());
+    }
+
+    #[test]
+    fn nested_loops() {
+        let program = parser::parse_program(
+            "function f(n) {
+                while (n != 0) {
+                    var i = n;
+                    while (i != 0) { i = i - 1; }
+                    n = n - 1;
+                }
+                return n;
+            }",
+        )
+        .unwrap();
+        let (_, cfg) = &cfg::functions(&program)[0];
+        let dominators = Dominators::new(cfg);
+        // entry, outer header, outer body, exit, inner header, inner body,
+        // after the inner loop, after return
+        assert_eq!(
+            dominators.idom,
+            [
+                None,
+                Some(0),
+                Some(1),
+                Some(1),
+                Some(2),
+                Some(4),
+                Some(4),
+                None
+            ]
+        );
+        let loops = loops(cfg, &dominators);
+        assert_eq!(
+            loops,
+            [
+                Loop {
+                    header: 1,
+                    blocks: BTreeSet::from([1, 2, 4, 5, 6]),
+                    latches: vec![6],
+                },
+                Loop {
+                    header: 4,
+                    blocks: BTreeSet::from([4, 5]),
+                    latches: vec![5],
+                },
+            ]
+        );
+        assert!(loops[0].contains(&loops[1]));
+        assert!(!loops[1].contains(&loops[0]));
+    }
+}
@@ -0,0 +1,925 @@
+//! Encodes the ARM32 instructions into an ELF relocatable object for
+//! `--emit=obj`, doing the work of the assembler. The constants and
+//! addresses loaded with `ldr rd, =...` go to a literal pool at the end of
+//! their section, where the GNU assembler puts them without `.ltorg`.
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+use crate::phases::instruction::{
+    Address, Condition, FloatRegister, Instruction, Operand, Register,
+};
+
+const EM_ARM: u16 = 40;
+const EF_ARM_EABI_VER5: u32 = 0x0500_0000;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+
+const SHF_WRITE: u32 = 0x1;
+const SHF_ALLOC: u32 = 0x2;
+const SHF_EXECINSTR: u32 = 0x4;
+const SHF_INFO_LINK: u32 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_SECTION: u8 = 3;
+
+const R_ARM_ABS32: u8 = 2;
+const R_ARM_CALL: u8 = 28;
+const R_ARM_JUMP24: u8 = 29;
+
+const ELF_HEADER_SIZE: usize = 52;
+const SECTION_HEADER_SIZE: usize = 40;
+
+/// `mov r0, r0`, padding code to an alignment.
+const NOP: u32 = 0xe1a0_0000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Literal {
+    Constant(i32),
+    Address(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Contents {
+    Code,
+    Data,
+}
+
+// What a relocation refers to, local labels go through the symbol of their
+// section with the offset of the label as the addend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Symbol(String),
+    Section(usize),
+}
+
+#[derive(Debug)]
+struct Section {
+    name: String,
+    kind: u32,
+    flags: u32,
+    data: Vec<u8>,
+    alignment: u32,
+    /// The literals with the offsets of the `ldr` instructions loading them.
+    literals: Vec<(Literal, Vec<usize>)>,
+    /// Offsets of branches with the label and the relocation they need when
+    /// the label is not in the section.
+    branches: Vec<(usize, String, u8)>,
+    relocations: Vec<(usize, Target, u8)>,
+    /// `$a` and `$d` symbols, telling disassemblers and linkers where code
+    /// and data start.
+    mapping: Vec<(usize, Contents)>,
+}
+
+impl Section {
+    fn new(name: &str) -> Result<Section, String> {
+        let (kind, flags) = match name {
+            ".text" => (SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR),
+            ".data" => (SHT_PROGBITS, SHF_ALLOC | SHF_WRITE),
+            ".bss" => (SHT_NOBITS, SHF_ALLOC | SHF_WRITE),
+            ".rodata" => (SHT_PROGBITS, SHF_ALLOC),
+            _ => return Err(format!("unsupported section `{}`", name)),
+        };
+        Ok(Section {
+            name: String::from(name),
+            kind,
+            flags,
+            data: Vec::new(),
+            alignment: 4,
+            literals: Vec::new(),
+            branches: Vec::new(),
+            relocations: Vec::new(),
+            mapping: Vec::new(),
+        })
+    }
+
+    fn is_code(&self) -> bool {
+        self.flags & SHF_EXECINSTR != 0
+    }
+
+    fn contents(&mut self, contents: Contents) {
+        let current = self.mapping.last().map(|(_, contents)| *contents);
+        if self.is_code() && current != Some(contents) {
+            self.mapping.push((self.data.len(), contents));
+        }
+    }
+
+    fn emit(&mut self, word: u32) {
+        self.contents(Contents::Code);
+        self.data.extend_from_slice(&word.to_le_bytes());
+    }
+
+    fn emit_data(&mut self, bytes: &[u8]) {
+        self.contents(Contents::Data);
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn align(&mut self, alignment: u32) {
+        self.alignment = self.alignment.max(alignment);
+        while !self.data.len().is_multiple_of(alignment as usize) {
+            if self.is_code() && self.data.len().is_multiple_of(4) {
+                self.data.extend_from_slice(&NOP.to_le_bytes());
+            } else {
+                self.data.push(0);
+            }
+        }
+    }
+
+    fn patch(&mut self, offset: usize, bits: u32) {
+        let word = u32::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap());
+        self.data[offset..offset + 4].copy_from_slice(&(word | bits).to_le_bytes());
+    }
+}
+
+#[derive(Debug, Default)]
+struct Assembler {
+    sections: Vec<Section>,
+    current: usize,
+    labels: BTreeMap<String, (usize, usize)>,
+    globals: Vec<String>,
+}
+
+/// The instructions as the contents of an ELF relocatable object.
+pub fn object(code: &[Instruction]) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler::default();
+    for instruction in code {
+        assembler.assemble(instruction)?;
+    }
+    assembler.place_literals()?;
+    assembler.resolve_branches()?;
+    Ok(assembler.write())
+}
+
+impl Assembler {
+    fn section(&mut self) -> &mut Section {
+        if self.sections.is_empty() {
+            self.sections.push(Section::new(".text").unwrap());
+        }
+        &mut self.sections[self.current]
+    }
+
+    fn assemble(&mut self, instruction: &Instruction) -> Result<(), String> {
+        match instruction {
+            Instruction::Global(name) => {
+                if !self.globals.contains(name) {
+                    self.globals.push(name.clone());
+                }
+            }
+            Instruction::Label(name) => {
+                self.section();
+                let location = (self.current, self.sections[self.current].data.len());
+                if self.labels.insert(name.clone(), location).is_some() {
+                    return Err(format!("label `{}` is defined twice", name));
+                }
+            }
+            Instruction::Section(name) => {
+                // the code before the first directive is in `.text`
+                self.section();
+                self.current = match self.sections.iter().position(|s| s.name == *name) {
+                    Some(index) => index,
+                    None => {
+                        self.sections.push(Section::new(name)?);
+                        self.sections.len() - 1
+                    }
+                };
+            }
+            Instruction::Asciz(text) => {
+                let mut bytes = Vec::from(text.as_bytes());
+                bytes.push(0);
+                self.section().emit_data(&bytes);
+            }
+            Instruction::Word(value) => self.section().emit_data(&value.to_le_bytes()),
+            Instruction::Space(size) => self.section().emit_data(&vec![0; *size as usize]),
+            Instruction::Balign(alignment) => self.section().align(*alignment),
+            // labels are local symbols unless declared global
+            Instruction::Local(_) => {}
+            // there is no debug info in the objects, and the header only
+            // matters to assemblers
+            Instruction::Comment(_)
+            | Instruction::File(_, _)
+            | Instruction::Loc(_, _, _)
+            | Instruction::Directive(_) => {}
+            Instruction::LoadConstant(rd, value) => match move_immediate(*value, false) {
+                Some(bits) => self.section().emit(bits | number(*rd) << 12),
+                None => self.load_literal(*rd, Literal::Constant(*value)),
+            },
+            Instruction::LoadAddress(rd, label) => {
+                self.load_literal(*rd, Literal::Address(label.clone()))
+            }
+            Instruction::B(cond, label) => {
+                self.branch(condition(*cond) | 0x0a00_0000, label, R_ARM_JUMP24)
+            }
+            Instruction::Bl(name) => self.branch(0xeb00_0000, name, R_ARM_CALL),
+            instruction => {
+                let word = encode(instruction)?;
+                self.section().emit(word);
+            }
+        }
+        Ok(())
+    }
+
+    fn load_literal(&mut self, rd: Register, literal: Literal) {
+        let section = self.section();
+        let offset = section.data.len();
+        // `ldr rd, [pc, #offset]`, the offset is known once the pool is placed
+        section.emit(0xe51f_0000 | number(rd) << 12);
+        match section
+            .literals
+            .iter_mut()
+            .find(|(other, _)| *other == literal)
+        {
+            Some((_, loads)) => loads.push(offset),
+            None => section.literals.push((literal, vec![offset])),
+        }
+    }
+
+    fn branch(&mut self, bits: u32, label: &str, relocation: u8) {
+        let section = self.section();
+        if label == "." {
+            // a branch to itself, `pc` being 8 bytes ahead
+            section.emit(bits | 0xff_fffe);
+            return;
+        }
+        section
+            .branches
+            .push((section.data.len(), String::from(label), relocation));
+        section.emit(bits);
+    }
+
+    fn is_global(&self, label: &str) -> bool {
+        self.globals.iter().any(|global| global == label)
+    }
+
+    // Appends the pool of every section, the loads reach 4095 bytes ahead.
+    fn place_literals(&mut self) -> Result<(), String> {
+        for index in 0..self.sections.len() {
+            let literals = std::mem::take(&mut self.sections[index].literals);
+            if literals.is_empty() {
+                continue;
+            }
+            self.sections[index].align(4);
+            for (literal, loads) in literals {
+                let offset = self.sections[index].data.len();
+                let value = match literal {
+                    Literal::Constant(value) => value as u32,
+                    Literal::Address(label) => {
+                        let (target, addend) = match self.labels.get(&label) {
+                            Some((section, offset)) if !self.is_global(&label) => {
+                                (Target::Section(*section), *offset as u32)
+                            }
+                            _ => (Target::Symbol(label), 0),
+                        };
+                        self.sections[index]
+                            .relocations
+                            .push((offset, target, R_ARM_ABS32));
+                        addend
+                    }
+                };
+                let section = &mut self.sections[index];
+                section.emit_data(&value.to_le_bytes());
+                for load in loads {
+                    // the pool may directly follow the last load, behind `pc`
+                    let distance = offset as i64 - (load as i64 + 8);
+                    if distance > 0xfff {
+                        return Err(format!(
+                            "the literal pool of `{}` is out of reach, the section is too large",
+                            section.name
+                        ));
+                    }
+                    let up = if distance >= 0 { 1 << 23 } else { 0 };
+                    section.patch(load, up | distance.unsigned_abs() as u32);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Branches within a section are resolved here, the rest by the linker.
+    fn resolve_branches(&mut self) -> Result<(), String> {
+        for index in 0..self.sections.len() {
+            let branches = std::mem::take(&mut self.sections[index].branches);
+            for (offset, label, relocation) in branches {
+                let (target, bits) = match self.labels.get(&label) {
+                    Some((section, target)) if *section == index => {
+                        let distance = *target as i64 - (offset as i64 + 8);
+                        if !(-(1 << 25)..1 << 25).contains(&distance) {
+                            return Err(format!("branch to `{}` is out of range", label));
+                        }
+                        self.sections[index].patch(offset, (distance >> 2) as u32 & 0xff_ffff);
+                        continue;
+                    }
+                    Some((section, target)) if !self.is_global(&label) => (
+                        Target::Section(*section),
+                        ((*target as i64 - 8) >> 2) as u32 & 0xff_ffff,
+                    ),
+                    // the addend of -8 makes up for the `pc` being ahead
+                    _ => (Target::Symbol(label), 0xff_fffe),
+                };
+                let section = &mut self.sections[index];
+                section.patch(offset, bits);
+                section.relocations.push((offset, target, relocation));
+            }
+            let section = &mut self.sections[index];
+            section.relocations.sort_by_key(|(offset, _, _)| *offset);
+        }
+        Ok(())
+    }
+
+    fn write(self) -> Vec<u8> {
+        let mut names = StringTable::default();
+        let mut symbol_names = StringTable::default();
+        // section symbols, then the other local symbols, then the global ones
+        let mut symbols = vec![Symbol::default()];
+        for index in 0..self.sections.len() {
+            symbols.push(Symbol {
+                info: STB_LOCAL << 4 | STT_SECTION,
+                section: index as u16 + 1,
+                ..Symbol::default()
+            });
+        }
+        for (index, section) in self.sections.iter().enumerate() {
+            for (offset, contents) in &section.mapping {
+                let name = match contents {
+                    Contents::Code => "$a",
+                    Contents::Data => "$d",
+                };
+                symbols.push(Symbol {
+                    name: symbol_names.add(name),
+                    value: *offset as u32,
+                    info: STB_LOCAL << 4 | STT_NOTYPE,
+                    section: index as u16 + 1,
+                });
+            }
+        }
+        let mut labels: Vec<(&String, &(usize, usize))> = self.labels.iter().collect();
+        labels.sort_by(|(a, a_location), (b, b_location)| (a_location, a).cmp(&(b_location, b)));
+        for (name, (section, offset)) in &labels {
+            // `.L` labels are local to the assembly, like with `as`
+            if !self.is_global(name) && !name.starts_with(".L") {
+                symbols.push(Symbol {
+                    name: symbol_names.add(name),
+                    value: *offset as u32,
+                    info: STB_LOCAL << 4 | STT_NOTYPE,
+                    section: *section as u16 + 1,
+                });
+            }
+        }
+        let first_global = symbols.len();
+        let mut indices = BTreeMap::new();
+        let undefined = self.sections.iter().flat_map(|section| {
+            section
+                .relocations
+                .iter()
+                .filter_map(|(_, target, _)| match target {
+                    Target::Symbol(name) => Some(name),
+                    Target::Section(_) => None,
+                })
+        });
+        for name in self.globals.iter().chain(undefined) {
+            if indices.contains_key(name) {
+                continue;
+            }
+            let (section, value) = match self.labels.get(name) {
+                Some((section, offset)) => (*section as u16 + 1, *offset as u32),
+                None => (0, 0),
+            };
+            indices.insert(name.clone(), symbols.len() as u32);
+            symbols.push(Symbol {
+                name: symbol_names.add(name),
+                value,
+                info: STB_GLOBAL << 4 | STT_NOTYPE,
+                section,
+            });
+        }
+
+        let mut output = vec![0; ELF_HEADER_SIZE];
+        let mut headers = vec![SectionHeader::default()];
+        for section in &self.sections {
+            align(&mut output, section.alignment as usize);
+            headers.push(SectionHeader {
+                name: names.add(&section.name),
+                kind: section.kind,
+                flags: section.flags,
+                offset: output.len() as u32,
+                size: section.data.len() as u32,
+                alignment: section.alignment,
+                ..SectionHeader::default()
+            });
+            if section.kind != SHT_NOBITS {
+                output.extend_from_slice(&section.data);
+            }
+        }
+        let symtab_index = (headers.len() + relocated_sections(&self.sections)) as u32;
+        for (index, section) in self.sections.iter().enumerate() {
+            if section.relocations.is_empty() {
+                continue;
+            }
+            align(&mut output, 4);
+            let offset = output.len() as u32;
+            for (at, target, relocation) in &section.relocations {
+                let symbol = match target {
+                    Target::Symbol(name) => indices[name],
+                    Target::Section(section) => *section as u32 + 1,
+                };
+                put_u32(&mut output, *at as u32);
+                put_u32(&mut output, symbol << 8 | u32::from(*relocation));
+            }
+            headers.push(SectionHeader {
+                name: names.add(&format!(".rel{}", section.name)),
+                kind: SHT_REL,
+                flags: SHF_INFO_LINK,
+                offset,
+                size: output.len() as u32 - offset,
+                link: symtab_index,
+                info: index as u32 + 1,
+                alignment: 4,
+                entry_size: 8,
+            });
+        }
+        align(&mut output, 4);
+        let offset = output.len() as u32;
+        for symbol in &symbols {
+            put_u32(&mut output, symbol.name);
+            put_u32(&mut output, symbol.value);
+            put_u32(&mut output, 0);
+            output.push(symbol.info);
+            output.push(0);
+            put_u16(&mut output, symbol.section);
+        }
+        headers.push(SectionHeader {
+            name: names.add(".symtab"),
+            kind: SHT_SYMTAB,
+            offset,
+            size: output.len() as u32 - offset,
+            link: symtab_index + 1,
+            info: first_global as u32,
+            alignment: 4,
+            entry_size: 16,
+            ..SectionHeader::default()
+        });
+        headers.push(SectionHeader {
+            name: names.add(".strtab"),
+            kind: SHT_STRTAB,
+            offset: output.len() as u32,
+            size: symbol_names.bytes.len() as u32,
+            alignment: 1,
+            ..SectionHeader::default()
+        });
+        output.extend_from_slice(&symbol_names.bytes);
+        // the names of the sections include its own
+        let shstrtab_name = names.add(".shstrtab");
+        headers.push(SectionHeader {
+            name: shstrtab_name,
+            kind: SHT_STRTAB,
+            offset: output.len() as u32,
+            size: names.bytes.len() as u32,
+            alignment: 1,
+            ..SectionHeader::default()
+        });
+        output.extend_from_slice(&names.bytes);
+
+        align(&mut output, 4);
+        let section_headers = output.len() as u32;
+        for header in &headers {
+            header.write(&mut output);
+        }
+        write_elf_header(&mut output, section_headers, headers.len() as u16);
+        output
+    }
+}
+
+fn relocated_sections(sections: &[Section]) -> usize {
+    sections
+        .iter()
+        .filter(|section| !section.relocations.is_empty())
+        .count()
+}
+
+#[derive(Debug, Default)]
+struct Symbol {
+    name: u32,
+    value: u32,
+    info: u8,
+    section: u16,
+}
+
+#[derive(Debug, Default)]
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    alignment: u32,
+    entry_size: u32,
+}
+
+impl SectionHeader {
+    fn write(&self, output: &mut Vec<u8>) {
+        put_u32(output, self.name);
+        put_u32(output, self.kind);
+        put_u32(output, self.flags);
+        put_u32(output, 0);
+        put_u32(output, self.offset);
+        put_u32(output, self.size);
+        put_u32(output, self.link);
+        put_u32(output, self.info);
+        put_u32(output, self.alignment);
+        put_u32(output, self.entry_size);
+    }
+}
+
+#[derive(Debug)]
+struct StringTable {
+    bytes: Vec<u8>,
+}
+
+impl Default for StringTable {
+    fn default() -> StringTable {
+        StringTable { bytes: vec![0] }
+    }
+}
+
+impl StringTable {
+    fn add(&mut self, name: &str) -> u32 {
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend_from_slice(name.as_bytes());
+        self.bytes.push(0);
+        offset
+    }
+}
+
+fn write_elf_header(output: &mut [u8], section_headers: u32, section_count: u16) {
+    let mut header = Vec::with_capacity(ELF_HEADER_SIZE);
+    // 32 bit, little endian, version 1
+    header.extend_from_slice(&[0x7f, b'E', b'L', b'F', 1, 1, 1]);
+    header.resize(16, 0);
+    put_u16(&mut header, 1); // relocatable
+    put_u16(&mut header, EM_ARM);
+    put_u32(&mut header, 1);
+    put_u32(&mut header, 0); // entry
+    put_u32(&mut header, 0); // program headers
+    put_u32(&mut header, section_headers);
+    put_u32(&mut header, EF_ARM_EABI_VER5);
+    put_u16(&mut header, ELF_HEADER_SIZE as u16);
+    put_u16(&mut header, 0);
+    put_u16(&mut header, 0);
+    put_u16(&mut header, SECTION_HEADER_SIZE as u16);
+    put_u16(&mut header, section_count);
+    // `.shstrtab` comes last
+    put_u16(&mut header, section_count - 1);
+    output[..ELF_HEADER_SIZE].copy_from_slice(&header);
+}
+
+fn align(output: &mut Vec<u8>, alignment: usize) {
+    while !output.len().is_multiple_of(alignment) {
+        output.push(0);
+    }
+}
+
+fn put_u16(output: &mut Vec<u8>, value: u16) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(output: &mut Vec<u8>, value: u32) {
+    output.extend_from_slice(&value.to_le_bytes());
+}
+
+fn number(register: Register) -> u32 {
+    match register {
+        Register::R0 => 0,
+        Register::R1 => 1,
+        Register::R2 => 2,
+        Register::R3 => 3,
+        Register::R4 => 4,
+        Register::R5 => 5,
+        Register::R6 => 6,
+        Register::R7 => 7,
+        Register::R8 => 8,
+        Register::Fp => 11,
+        Register::Ip => 12,
+        Register::Sp => 13,
+        Register::Lr => 14,
+        Register::Pc => 15,
+    }
+}
+
+fn condition(condition: Condition) -> u32 {
+    let code = match condition {
+        Condition::Eq => 0x0,
+        Condition::Ne => 0x1,
+        Condition::Hs => 0x2,
+        Condition::Lo => 0x3,
+        Condition::Lt => 0xb,
+        Condition::Gt => 0xc,
+        Condition::Always => 0xe,
+    };
+    code << 28
+}
+
+fn register_list(registers: &[Register]) -> u32 {
+    registers
+        .iter()
+        .fold(0, |mask, register| mask | 1 << number(*register))
+}
+
+/// The 12 bits of an immediate operand, an 8 bit value rotated right by an
+/// even amount, `None` when the value has no such form.
+fn immediate(value: i32) -> Option<u32> {
+    (0..16).find_map(|rotation| {
+        let rotated = (value as u32).rotate_left(2 * rotation);
+        (rotated <= 0xff).then_some(rotation << 8 | rotated)
+    })
+}
+
+// `mov` of the value, or `mvn` of its complement, with `movw` for the
+// 16 bit values when allowed.
+fn move_immediate(value: i32, movw: bool) -> Option<u32> {
+    if let Some(bits) = immediate(value) {
+        Some(0xe3a0_0000 | bits)
+    } else if let Some(bits) = immediate(!value) {
+        Some(0xe3e0_0000 | bits)
+    } else if movw && (0..=0xffff).contains(&value) {
+        let value = value as u32;
+        Some(0xe300_0000 | (value >> 12) << 16 | value & 0xfff)
+    } else {
+        None
+    }
+}
+
+const SUB: u32 = 0x2;
+const RSB: u32 = 0x3;
+const ADD: u32 = 0x4;
+const CMP: u32 = 0xa;
+const CMN: u32 = 0xb;
+
+// Data processing instruction, turning `add` of an immediate into `sub` of
+// its negation and back when only that can be encoded, as assemblers do.
+fn data_processing(
+    cond: Condition,
+    opcode: u32,
+    rd: Register,
+    rn: Register,
+    operand: &Operand,
+) -> Result<u32, String> {
+    let flags = if opcode == CMP || opcode == CMN { 1 } else { 0 };
+    let base = |opcode: u32| condition(cond) | opcode << 21 | flags << 20;
+    let registers = number(rn) << 16 | number(rd) << 12;
+    match operand {
+        Operand::Register(rm) => Ok(base(opcode) | registers | number(*rm)),
+        Operand::Immediate(value) => {
+            if let Some(bits) = immediate(*value) {
+                return Ok(base(opcode) | 1 << 25 | registers | bits);
+            }
+            let negated = match opcode {
+                ADD => Some(SUB),
+                SUB => Some(ADD),
+                CMP => Some(CMN),
+                _ => None,
+            };
+            negated
+                .and_then(|negated| {
+                    immediate(value.wrapping_neg())
+                        .map(|bits| base(negated) | 1 << 25 | registers | bits)
+                })
+                .ok_or_else(|| format!("immediate `#{}` cannot be encoded", value))
+        }
+    }
+}
+
+fn memory(cond: Condition, bits: u32, rd: Register, address: &Address) -> Result<u32, String> {
+    let (rn, offset) = match address {
+        Address::Register(rn) => (*rn, &Operand::Immediate(0)),
+        Address::Offset(rn, offset) => (*rn, offset),
+    };
+    // pre-indexed without writeback
+    let base = condition(cond) | 0x0400_0000 | 1 << 24 | bits | number(rn) << 16 | number(rd) << 12;
+    match offset {
+        Operand::Register(rm) => Ok(base | 1 << 25 | 1 << 23 | number(*rm)),
+        Operand::Immediate(value) if value.unsigned_abs() <= 0xfff => {
+            let up = if *value >= 0 { 1 << 23 } else { 0 };
+            Ok(base | up | value.unsigned_abs())
+        }
+        Operand::Immediate(value) => Err(format!("offset `#{}` cannot be encoded", value)),
+    }
+}
+
+// `lsl` and `lsr` are moves with a shifted operand.
+fn shift(
+    cond: Condition,
+    kind: u32,
+    rd: Register,
+    rm: Register,
+    amount: &Operand,
+) -> Result<u32, String> {
+    let base = condition(cond) | 0x01a0_0000 | number(rd) << 12 | kind << 5 | number(rm);
+    match amount {
+        Operand::Register(rs) => Ok(base | number(*rs) << 8 | 1 << 4),
+        Operand::Immediate(value @ 0..=31) if kind == 0 || *value > 0 => {
+            Ok(base | (*value as u32) << 7)
+        }
+        // a right shift by 32 is encoded as 0
+        Operand::Immediate(32) if kind == 1 => Ok(base),
+        Operand::Immediate(value) => Err(format!("shift `#{}` cannot be encoded", value)),
+    }
+}
+
+fn float_number(register: FloatRegister) -> u32 {
+    match register {
+        FloatRegister::S0 => 0,
+        FloatRegister::S1 => 1,
+        FloatRegister::S2 => 2,
+        FloatRegister::S3 => 3,
+    }
+}
+
+// Single precision registers are split into the top four bits and the lowest
+// one, which goes into a bit of its own.
+fn vfp_d(sd: FloatRegister) -> u32 {
+    let sd = float_number(sd);
+    (sd >> 1) << 12 | (sd & 1) << 22
+}
+
+fn vfp_n(sn: FloatRegister) -> u32 {
+    let sn = float_number(sn);
+    (sn >> 1) << 16 | (sn & 1) << 7
+}
+
+fn vfp_m(sm: FloatRegister) -> u32 {
+    let sm = float_number(sm);
+    sm >> 1 | (sm & 1) << 5
+}
+
+// The instructions which do not refer to labels.
+fn encode(instruction: &Instruction) -> Result<u32, String> {
+    let word = match instruction {
+        Instruction::Push(registers) => match registers.as_slice() {
+            // `str rd, [sp, #-4]!`
+            [register] => 0xe52d_0004 | number(*register) << 12,
+            registers => 0xe92d_0000 | register_list(registers),
+        },
+        Instruction::Pop(registers) => match registers.as_slice() {
+            // `ldr rd, [sp], #4`
+            [register] => 0xe49d_0004 | number(*register) << 12,
+            registers => 0xe8bd_0000 | register_list(registers),
+        },
+        Instruction::Mov(cond, rd, Operand::Register(rm)) => {
+            condition(*cond) | 0x01a0_0000 | number(*rd) << 12 | number(*rm)
+        }
+        Instruction::Mov(cond, rd, Operand::Immediate(value)) => {
+            let bits = move_immediate(*value, true)
+                .ok_or_else(|| format!("immediate `#{}` cannot be encoded", value))?;
+            // the condition replaces the one of `move_immediate`
+            condition(*cond) | bits & 0x0fff_ffff | number(*rd) << 12
+        }
+        Instruction::Ldr(cond, rd, address) => memory(*cond, 1 << 20, *rd, address)?,
+        Instruction::Ldrb(cond, rd, address) => memory(*cond, 1 << 22 | 1 << 20, *rd, address)?,
+        Instruction::Str(cond, rd, address) => memory(*cond, 0, *rd, address)?,
+        Instruction::Add(cond, rd, rn, operand) => data_processing(*cond, ADD, *rd, *rn, operand)?,
+        Instruction::Sub(cond, rd, rn, operand) => data_processing(*cond, SUB, *rd, *rn, operand)?,
+        Instruction::Rsb(cond, rd, rn, operand) => data_processing(*cond, RSB, *rd, *rn, operand)?,
+        Instruction::Cmp(rn, operand) => {
+            data_processing(Condition::Always, CMP, Register::R0, *rn, operand)?
+        }
+        Instruction::Mul(rd, rn, rm) => {
+            0xe000_0090 | number(*rd) << 16 | number(*rm) << 8 | number(*rn)
+        }
+        Instruction::Sdiv(rd, rn, rm) => {
+            0xe710_f010 | number(*rd) << 16 | number(*rm) << 8 | number(*rn)
+        }
+        Instruction::Udiv(rd, rn, rm) => {
+            0xe730_f010 | number(*rd) << 16 | number(*rm) << 8 | number(*rn)
+        }
+        Instruction::Lsl(cond, rd, rm, amount) => shift(*cond, 0, *rd, *rm, amount)?,
+        Instruction::Lsr(cond, rd, rm, amount) => shift(*cond, 1, *rd, *rm, amount)?,
+        Instruction::Ror(cond, rd, rm, amount) => shift(*cond, 3, *rd, *rm, amount)?,
+        Instruction::Clz(rd, rm) => 0xe16f_0f10 | number(*rd) << 12 | number(*rm),
+        Instruction::Svc(number) if *number <= 0xff_ffff => 0xef00_0000 | number,
+        Instruction::VmovToFloat(sn, rt) => 0xee00_0a10 | vfp_n(*sn) | number(*rt) << 12,
+        Instruction::VmovFromFloat(rt, sn) => 0xee10_0a10 | vfp_n(*sn) | number(*rt) << 12,
+        Instruction::Vadd(sd, sn, sm) => 0xee30_0a00 | vfp_d(*sd) | vfp_n(*sn) | vfp_m(*sm),
+        Instruction::Vsub(sd, sn, sm) => 0xee30_0a40 | vfp_d(*sd) | vfp_n(*sn) | vfp_m(*sm),
+        Instruction::Vmul(sd, sn, sm) => 0xee20_0a00 | vfp_d(*sd) | vfp_n(*sn) | vfp_m(*sm),
+        Instruction::Vdiv(sd, sn, sm) => 0xee80_0a00 | vfp_d(*sd) | vfp_n(*sn) | vfp_m(*sm),
+        Instruction::Vcmp(sd, sm) => 0xeeb4_0a40 | vfp_d(*sd) | vfp_m(*sm),
+        Instruction::Vmrs => 0xeef1_fa10,
+        instruction => return Err(format!("`{}` cannot be encoded", instruction)),
+    };
+    Ok(word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn encodings() {
+        use Condition::{Always, Eq};
+        use FloatRegister::{S0, S1, S2, S3};
+        use Register::*;
+        let immediate = Operand::Immediate;
+        // as encoded by llvm-mc
+        let expected = [
+            (Instruction::Push(vec![Fp, Lr]), 0xe92d_4800),
+            (Instruction::Push(vec![R0]), 0xe52d_0004),
+            (Instruction::Pop(vec![R1]), 0xe49d_1004),
+            (Instruction::Pop(vec![Fp, Pc]), 0xe8bd_8800),
+            (
+                Instruction::Mov(Always, R0, Operand::Register(R1)),
+                0xe1a0_0001,
+            ),
+            (Instruction::Mov(Eq, R0, immediate(1)), 0x03a0_0001),
+            (Instruction::Mov(Always, R0, immediate(-1)), 0xe3e0_0000),
+            (Instruction::Mov(Always, R0, immediate(4660)), 0xe301_0234),
+            (
+                Instruction::Ldr(Always, R0, Address::Offset(Fp, immediate(-8))),
+                0xe51b_0008,
+            ),
+            (
+                Instruction::Ldrb(Always, R1, Address::Offset(R0, Operand::Register(R2))),
+                0xe7d0_1002,
+            ),
+            (
+                Instruction::Str(Always, R0, Address::Register(Sp)),
+                0xe58d_0000,
+            ),
+            (Instruction::Add(Always, R0, R1, immediate(-4)), 0xe241_0004),
+            (Instruction::Sub(Always, Sp, Sp, immediate(16)), 0xe24d_d010),
+            (Instruction::Rsb(Always, R0, R0, immediate(0)), 0xe260_0000),
+            (Instruction::Cmp(R0, immediate(-1)), 0xe370_0001),
+            (Instruction::Mul(R0, R1, R2), 0xe000_0291),
+            (Instruction::Sdiv(R0, R1, R2), 0xe710_f211),
+            (Instruction::Udiv(R0, R1, R2), 0xe730_f211),
+            (Instruction::Lsl(Always, R0, R1, immediate(2)), 0xe1a0_0101),
+            (
+                Instruction::Lsr(Always, R0, R1, Operand::Register(R2)),
+                0xe1a0_0231,
+            ),
+            (
+                Instruction::Ror(Always, R0, R1, Operand::Register(R2)),
+                0xe1a0_0271,
+            ),
+            (Instruction::Clz(R0, R1), 0xe16f_0f11),
+            (Instruction::Svc(0), 0xef00_0000),
+            (Instruction::VmovToFloat(S3, R2), 0xee01_2a90),
+            (Instruction::VmovFromFloat(R3, S3), 0xee11_3a90),
+            (Instruction::Vadd(S2, S1, S3), 0xee30_1aa1),
+            (Instruction::Vsub(S0, S0, S1), 0xee30_0a60),
+            (Instruction::Vmul(S0, S0, S1), 0xee20_0a20),
+            (Instruction::Vdiv(S0, S0, S1), 0xee80_0a20),
+            (Instruction::Vcmp(S3, S2), 0xeef4_1a41),
+            (Instruction::Vmrs, 0xeef1_fa10),
+        ];
+        for (instruction, word) in expected {
+            assert_eq!(encode(&instruction), Ok(word), "{}", instruction);
+        }
+        assert_eq!(
+            encode(&Instruction::Rsb(Always, R0, R0, immediate(0x101))),
+            Err(String::from("immediate `#257` cannot be encoded"))
+        );
+    }
+
+    #[test]
+    fn objects() {
+        let code = vec![
+            Instruction::Global(String::from("main")),
+            Instruction::Label(String::from("main")),
+            Instruction::LoadConstant(Register::R0, 0x12345),
+            Instruction::Bl(String::from("putchar")),
+            Instruction::B(Condition::Ne, String::from("main")),
+            Instruction::Section(String::from(".data")),
+            Instruction::Word(7),
+        ];
+        let bytes = object(&code).unwrap();
+        assert_eq!(bytes[..4], [0x7f, b'E', b'L', b'F']);
+        assert_eq!(bytes[18..20], EM_ARM.to_le_bytes());
+        // `.text` follows the header, its pool after the code
+        let text = ELF_HEADER_SIZE;
+        assert_eq!(word(&bytes, text), 0xe59f_0004);
+        assert_eq!(word(&bytes, text + 4), 0xebff_fffe);
+        assert_eq!(word(&bytes, text + 8), 0x1aff_fffc);
+        assert_eq!(word(&bytes, text + 12), 0x12345);
+        assert_eq!(word(&bytes, text + 16), 7);
+        let names = String::from_utf8_lossy(&bytes);
+        for name in ["main", "putchar", ".rel.text", ".symtab"] {
+            assert!(names.contains(&format!("\0{}\0", name)), "{}", name);
+        }
+
+        let code = [Instruction::Section(String::from(".init"))];
+        assert_eq!(
+            object(&code),
+            Err(String::from("unsupported section `.init`"))
+        );
+    }
+}
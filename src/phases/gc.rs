@@ -0,0 +1,393 @@
+//! Mark-sweep garbage collector linked into the ARM32 output by `--gc`.
+//!
+//! Every array is allocated through `__rtsc_alloc`, which puts two words in
+//! front of it: the next object in the list of all allocations and the mark.
+//! The array pointer seen by the program points past them and the capacity
+//! of the array, at the length.
+//!
+//! The roots come from a shadow stack kept by the generated code: each
+//! function stores the previous `__rtsc_gc_top` in its frame and points the
+//! global at its own `fp`, restoring it on return. The collector follows the
+//! chain to the outermost frame and scans every word between its own `sp`
+//! and that frame, which covers the arguments, locals and temporaries of all
+//! the active functions. A word is taken as a reference when it is the
+//! address of a live array, and so are the elements of the arrays reached.
+use crate::phases::instruction::{Address, Condition, Instruction, Operand, Register};
+use crate::phases::runtime;
+
+pub const ALLOC_ROUTINE: &str = "__rtsc_alloc";
+pub const TOP: &str = "__rtsc_gc_top";
+const COLLECT_ROUTINE: &str = "__rtsc_gc_collect";
+const MARK_ROUTINE: &str = "__rtsc_gc_mark";
+const OBJECTS: &str = "__rtsc_gc_objects";
+const ALLOCATED: &str = "__rtsc_gc_allocated";
+
+/// Offset from `fp` of the slot holding the previous shadow stack frame.
+pub const LINK_OFFSET: i32 = -20;
+/// Bytes the frame reserves for the link, padded to keep `sp` aligned.
+pub const LINK_SIZE: i32 = 8;
+/// Bytes allocated between two collections.
+const THRESHOLD: i32 = 64 * 1024;
+const HEADER_SIZE: i32 = 8;
+// Offset of the length of the array from the start of the object.
+const ARRAY_OFFSET: i32 = HEADER_SIZE + runtime::CAPACITY_SIZE;
+
+const WHITE: i32 = 0;
+const GREY: i32 = 1;
+const BLACK: i32 = 2;
+
+fn address(base: Register, offset: i32) -> Address {
+    if offset == 0 {
+        Address::Register(base)
+    } else {
+        Address::Offset(base, Operand::Immediate(offset))
+    }
+}
+
+fn load(cond: Condition, rd: Register, base: Register, offset: i32) -> Instruction {
+    Instruction::Ldr(cond, rd, address(base, offset))
+}
+
+fn store(cond: Condition, rd: Register, base: Register, offset: i32) -> Instruction {
+    Instruction::Str(cond, rd, address(base, offset))
+}
+
+fn mov(cond: Condition, rd: Register, value: i32) -> Instruction {
+    Instruction::Mov(cond, rd, Operand::Immediate(value))
+}
+
+fn mov_register(rd: Register, rm: Register) -> Instruction {
+    Instruction::Mov(Condition::Always, rd, Operand::Register(rm))
+}
+
+fn label(name: &str) -> Instruction {
+    Instruction::Label(String::from(name))
+}
+
+fn branch(cond: Condition, name: &str) -> Instruction {
+    Instruction::B(cond, String::from(name))
+}
+
+fn call(name: &str) -> Instruction {
+    Instruction::Bl(String::from(name))
+}
+
+/// Links the frame into the shadow stack, emitted after the prologue.
+pub fn emit_enter_frame(code: &mut Vec<Instruction>) {
+    code.push(Instruction::LoadAddress(Register::R0, String::from(TOP)));
+    code.push(load(Condition::Always, Register::R1, Register::R0, 0));
+    code.push(store(
+        Condition::Always,
+        Register::R1,
+        Register::Fp,
+        LINK_OFFSET,
+    ));
+    code.push(store(Condition::Always, Register::Fp, Register::R0, 0));
+}
+
+/// Unlinks the frame before returning, keeping the result in `r0`.
+pub fn emit_leave_frame(code: &mut Vec<Instruction>) {
+    code.push(load(
+        Condition::Always,
+        Register::R1,
+        Register::Fp,
+        LINK_OFFSET,
+    ));
+    code.push(Instruction::LoadAddress(Register::R2, String::from(TOP)));
+    code.push(store(Condition::Always, Register::R1, Register::R2, 0));
+}
+
+/// Emits the allocator, the collector and their globals.
+pub fn emit_runtime(code: &mut Vec<Instruction>) {
+    emit_alloc(code);
+    emit_mark(code);
+    emit_collect(code);
+    code.push(Instruction::Section(String::from(".data")));
+    code.push(Instruction::Balign(4));
+    for global in [TOP, OBJECTS, ALLOCATED] {
+        code.push(label(global));
+        code.push(Instruction::Word(0));
+    }
+    code.push(Instruction::Section(String::from(".text")));
+}
+
+// Takes the size of the array in `r0` and returns the pointer to it,
+// collecting first once enough memory was allocated since the last time.
+// The caller's `r4` is saved on the stack, so an array it is filling stays
+// reachable.
+fn emit_alloc(code: &mut Vec<Instruction>) {
+    code.push(label(ALLOC_ROUTINE));
+    code.push(Instruction::Push(vec![
+        Register::R4,
+        Register::R5,
+        Register::Ip,
+        Register::Lr,
+    ]));
+    code.push(mov_register(Register::R4, Register::R0));
+    code.push(Instruction::LoadAddress(
+        Register::R5,
+        String::from(ALLOCATED),
+    ));
+    code.push(load(Condition::Always, Register::R1, Register::R5, 0));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R1,
+        Register::R1,
+        Operand::Register(Register::R4),
+    ));
+    code.push(store(Condition::Always, Register::R1, Register::R5, 0));
+    code.push(Instruction::LoadConstant(Register::R2, THRESHOLD));
+    code.push(Instruction::Cmp(
+        Register::R1,
+        Operand::Register(Register::R2),
+    ));
+    code.push(branch(Condition::Lo, ".Lgc_allocate"));
+    code.push(call(COLLECT_ROUTINE));
+    code.push(mov(Condition::Always, Register::R1, 0));
+    code.push(store(Condition::Always, Register::R1, Register::R5, 0));
+    code.push(label(".Lgc_allocate"));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R0,
+        Register::R4,
+        Operand::Immediate(HEADER_SIZE),
+    ));
+    code.push(call("malloc"));
+    code.push(Instruction::LoadAddress(
+        Register::R1,
+        String::from(OBJECTS),
+    ));
+    code.push(load(Condition::Always, Register::R2, Register::R1, 0));
+    code.push(store(Condition::Always, Register::R2, Register::R0, 0));
+    code.push(mov(Condition::Always, Register::R2, WHITE));
+    code.push(store(Condition::Always, Register::R2, Register::R0, 4));
+    code.push(store(Condition::Always, Register::R0, Register::R1, 0));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R0,
+        Register::R0,
+        Operand::Immediate(HEADER_SIZE),
+    ));
+    code.push(Instruction::Pop(vec![
+        Register::R4,
+        Register::R5,
+        Register::Ip,
+        Register::Pc,
+    ]));
+}
+
+// Marks grey the object `r0` points to, leaving objects already marked
+// alone. Only clobbers `r0` to `r2`.
+fn emit_mark(code: &mut Vec<Instruction>) {
+    code.push(label(MARK_ROUTINE));
+    code.push(Instruction::LoadAddress(
+        Register::R1,
+        String::from(OBJECTS),
+    ));
+    code.push(load(Condition::Always, Register::R1, Register::R1, 0));
+    code.push(label(".Lgc_mark_next"));
+    code.push(Instruction::Cmp(Register::R1, Operand::Immediate(0)));
+    code.push(Instruction::Mov(
+        Condition::Eq,
+        Register::Pc,
+        Operand::Register(Register::Lr),
+    ));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R2,
+        Register::R1,
+        Operand::Immediate(ARRAY_OFFSET),
+    ));
+    code.push(Instruction::Cmp(
+        Register::R2,
+        Operand::Register(Register::R0),
+    ));
+    code.push(load(Condition::Ne, Register::R1, Register::R1, 0));
+    code.push(branch(Condition::Ne, ".Lgc_mark_next"));
+    code.push(load(Condition::Always, Register::R2, Register::R1, 4));
+    code.push(Instruction::Cmp(Register::R2, Operand::Immediate(WHITE)));
+    code.push(mov(Condition::Eq, Register::R2, GREY));
+    code.push(store(Condition::Eq, Register::R2, Register::R1, 4));
+    code.push(mov_register(Register::Pc, Register::Lr));
+}
+
+fn emit_collect(code: &mut Vec<Instruction>) {
+    code.push(label(COLLECT_ROUTINE));
+    code.push(Instruction::Push(vec![
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::R7,
+        Register::R8,
+        Register::Lr,
+    ]));
+    // nothing is reachable from outside of the generated code
+    code.push(Instruction::LoadAddress(Register::R0, String::from(TOP)));
+    code.push(load(Condition::Always, Register::R5, Register::R0, 0));
+    code.push(Instruction::Cmp(Register::R5, Operand::Immediate(0)));
+    code.push(branch(Condition::Eq, ".Lgc_done"));
+    code.push(label(".Lgc_outermost"));
+    code.push(load(
+        Condition::Always,
+        Register::R0,
+        Register::R5,
+        LINK_OFFSET,
+    ));
+    code.push(Instruction::Cmp(Register::R0, Operand::Immediate(0)));
+    code.push(Instruction::Mov(
+        Condition::Ne,
+        Register::R5,
+        Operand::Register(Register::R0),
+    ));
+    code.push(branch(Condition::Ne, ".Lgc_outermost"));
+
+    // every word of the stack is a potential root
+    code.push(mov_register(Register::R4, Register::Sp));
+    code.push(label(".Lgc_roots"));
+    code.push(Instruction::Cmp(
+        Register::R4,
+        Operand::Register(Register::R5),
+    ));
+    code.push(branch(Condition::Hs, ".Lgc_trace"));
+    code.push(load(Condition::Always, Register::R0, Register::R4, 0));
+    code.push(call(MARK_ROUTINE));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R4,
+        Register::R4,
+        Operand::Immediate(4),
+    ));
+    code.push(branch(Condition::Always, ".Lgc_roots"));
+
+    // scans the elements of the grey objects until none is left
+    code.push(label(".Lgc_trace"));
+    code.push(mov(Condition::Always, Register::R6, 0));
+    code.push(Instruction::LoadAddress(
+        Register::R4,
+        String::from(OBJECTS),
+    ));
+    code.push(load(Condition::Always, Register::R4, Register::R4, 0));
+    code.push(label(".Lgc_trace_object"));
+    code.push(Instruction::Cmp(Register::R4, Operand::Immediate(0)));
+    code.push(branch(Condition::Eq, ".Lgc_trace_end"));
+    code.push(load(Condition::Always, Register::R0, Register::R4, 4));
+    code.push(Instruction::Cmp(Register::R0, Operand::Immediate(GREY)));
+    code.push(branch(Condition::Ne, ".Lgc_trace_skip"));
+    code.push(mov(Condition::Always, Register::R0, BLACK));
+    code.push(store(Condition::Always, Register::R0, Register::R4, 4));
+    code.push(mov(Condition::Always, Register::R6, 1));
+    code.push(load(
+        Condition::Always,
+        Register::R7,
+        Register::R4,
+        ARRAY_OFFSET,
+    ));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R8,
+        Register::R4,
+        Operand::Immediate(ARRAY_OFFSET + 4),
+    ));
+    code.push(label(".Lgc_trace_element"));
+    code.push(Instruction::Cmp(Register::R7, Operand::Immediate(0)));
+    code.push(branch(Condition::Eq, ".Lgc_trace_skip"));
+    code.push(load(Condition::Always, Register::R0, Register::R8, 0));
+    code.push(call(MARK_ROUTINE));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R8,
+        Register::R8,
+        Operand::Immediate(4),
+    ));
+    code.push(Instruction::Sub(
+        Condition::Always,
+        Register::R7,
+        Register::R7,
+        Operand::Immediate(1),
+    ));
+    code.push(branch(Condition::Always, ".Lgc_trace_element"));
+    code.push(label(".Lgc_trace_skip"));
+    code.push(load(Condition::Always, Register::R4, Register::R4, 0));
+    code.push(branch(Condition::Always, ".Lgc_trace_object"));
+    code.push(label(".Lgc_trace_end"));
+    code.push(Instruction::Cmp(Register::R6, Operand::Immediate(0)));
+    code.push(branch(Condition::Ne, ".Lgc_trace"));
+
+    // frees the white objects, `r4` holds the link to the current one, the
+    // next pointer being the first word an object is its own link
+    code.push(Instruction::LoadAddress(
+        Register::R4,
+        String::from(OBJECTS),
+    ));
+    code.push(label(".Lgc_sweep"));
+    code.push(load(Condition::Always, Register::R5, Register::R4, 0));
+    code.push(Instruction::Cmp(Register::R5, Operand::Immediate(0)));
+    code.push(branch(Condition::Eq, ".Lgc_done"));
+    code.push(load(Condition::Always, Register::R0, Register::R5, 4));
+    code.push(Instruction::Cmp(Register::R0, Operand::Immediate(WHITE)));
+    code.push(branch(Condition::Eq, ".Lgc_free"));
+    code.push(mov(Condition::Always, Register::R0, WHITE));
+    code.push(store(Condition::Always, Register::R0, Register::R5, 4));
+    code.push(mov_register(Register::R4, Register::R5));
+    code.push(branch(Condition::Always, ".Lgc_sweep"));
+    code.push(label(".Lgc_free"));
+    code.push(load(Condition::Always, Register::R0, Register::R5, 0));
+    code.push(store(Condition::Always, Register::R0, Register::R4, 0));
+    code.push(mov_register(Register::R0, Register::R5));
+    code.push(call("free"));
+    code.push(branch(Condition::Always, ".Lgc_sweep"));
+
+    code.push(label(".Lgc_done"));
+    code.push(Instruction::Pop(vec![
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::R7,
+        Register::R8,
+        Register::Pc,
+    ]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phases::instruction;
+
+    #[test]
+    fn frame_links() {
+        let mut code = Vec::new();
+        emit_enter_frame(&mut code);
+        emit_leave_frame(&mut code);
+        assert_eq!(
+            instruction::render(&code),
+            "    ldr r0, =__rtsc_gc_top
+    ldr r1, [r0]
+    str r1, [fp, #-20]
+    str fp, [r0]
+    ldr r1, [fp, #-20]
+    ldr r2, =__rtsc_gc_top
+    str r1, [r2]
+"
+        );
+    }
+
+    #[test]
+    fn runtime_globals() {
+        let mut code = Vec::new();
+        emit_runtime(&mut code);
+        let rendered = instruction::render(&code);
+        assert!(rendered.starts_with("__rtsc_alloc:\n"));
+        assert!(rendered.ends_with(
+            ".section .data
+    .balign 4
+__rtsc_gc_top:
+    .word 0
+__rtsc_gc_objects:
+    .word 0
+__rtsc_gc_allocated:
+    .word 0
+.section .text
+"
+        ));
+    }
+}
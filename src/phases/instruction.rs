@@ -0,0 +1,370 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Register {
+    R0,
+    R1,
+    R2,
+    R3,
+    R4,
+    R5,
+    R6,
+    R7,
+    R8,
+    Fp,
+    Ip,
+    Sp,
+    Lr,
+    Pc,
+}
+
+/// Single precision registers of the VFP, the hard-float calling convention
+/// passes the float arguments and results in them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatRegister {
+    S0,
+    S1,
+    S2,
+    S3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Always,
+    Eq,
+    Ne,
+    Hs,
+    Lo,
+    Lt,
+    Gt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Immediate(i32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    Register(Register),
+    Offset(Register, Operand),
+}
+
+/// Single line of the generated ARM32 assembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Global(String),
+    /// Starts a function only the object itself calls, like `Global`.
+    Local(String),
+    Label(String),
+    Section(String),
+    /// Zero terminated string data.
+    Asciz(String),
+    Word(i32),
+    Space(u32),
+    Balign(u32),
+    Push(Vec<Register>),
+    Pop(Vec<Register>),
+    Mov(Condition, Register, Operand),
+    /// Loads an arbitrary 32 bit constant through the literal pool.
+    LoadConstant(Register, i32),
+    /// Loads the address of a label through the literal pool.
+    LoadAddress(Register, String),
+    Ldr(Condition, Register, Address),
+    /// Loads a single byte, zero extended.
+    Ldrb(Condition, Register, Address),
+    Str(Condition, Register, Address),
+    Add(Condition, Register, Register, Operand),
+    Sub(Condition, Register, Register, Operand),
+    /// Reverse subtract, `rd = operand - rn`.
+    Rsb(Condition, Register, Register, Operand),
+    Mul(Register, Register, Register),
+    Sdiv(Register, Register, Register),
+    Udiv(Register, Register, Register),
+    Lsl(Condition, Register, Register, Operand),
+    Lsr(Condition, Register, Register, Operand),
+    /// Rotate right.
+    Ror(Condition, Register, Register, Operand),
+    /// Count leading zeros, 32 for zero.
+    Clz(Register, Register),
+    Cmp(Register, Operand),
+    /// Copies a core register into a VFP register, bit for bit.
+    VmovToFloat(FloatRegister, Register),
+    /// Copies a VFP register into a core register, bit for bit.
+    VmovFromFloat(Register, FloatRegister),
+    Vadd(FloatRegister, FloatRegister, FloatRegister),
+    Vsub(FloatRegister, FloatRegister, FloatRegister),
+    Vmul(FloatRegister, FloatRegister, FloatRegister),
+    Vdiv(FloatRegister, FloatRegister, FloatRegister),
+    Vcmp(FloatRegister, FloatRegister),
+    /// Copies the flags set by `vcmp` to the ones conditions test.
+    Vmrs,
+    B(Condition, String),
+    Bl(String),
+    /// Branch which switches between the ARM and Thumb states.
+    Bx(Register),
+    /// Supervisor call, i.e. a system call on Linux.
+    Svc(u32),
+    /// Breakpoint, the semihosting call of Thumb code.
+    Bkpt(u32),
+    /// Makes the next Thumb instruction conditional.
+    It(Condition),
+    Comment(String),
+    /// Number and name of a source file for the debug info.
+    File(usize, String),
+    /// File number, line and column of the following instructions.
+    Loc(usize, usize, usize),
+    /// Any other assembler directive, written as is.
+    Directive(String),
+}
+
+impl Instruction {
+    /// Whether the instruction is assembled into code, as opposed to labels,
+    /// data and directives.
+    pub fn is_executable(&self) -> bool {
+        !matches!(
+            self,
+            Instruction::Global(_)
+                | Instruction::Local(_)
+                | Instruction::Label(_)
+                | Instruction::Section(_)
+                | Instruction::Asciz(_)
+                | Instruction::Word(_)
+                | Instruction::Space(_)
+                | Instruction::Balign(_)
+                | Instruction::Comment(_)
+                | Instruction::File(_, _)
+                | Instruction::Loc(_, _, _)
+                | Instruction::Directive(_)
+        )
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Register::R0 => "r0",
+            Register::R1 => "r1",
+            Register::R2 => "r2",
+            Register::R3 => "r3",
+            Register::R4 => "r4",
+            Register::R5 => "r5",
+            Register::R6 => "r6",
+            Register::R7 => "r7",
+            Register::R8 => "r8",
+            Register::Fp => "fp",
+            Register::Ip => "ip",
+            Register::Sp => "sp",
+            Register::Lr => "lr",
+            Register::Pc => "pc",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let suffix = match self {
+            Condition::Always => "",
+            Condition::Eq => "eq",
+            Condition::Ne => "ne",
+            Condition::Hs => "hs",
+            Condition::Lo => "lo",
+            Condition::Lt => "lt",
+            Condition::Gt => "gt",
+        };
+        write!(f, "{}", suffix)
+    }
+}
+
+impl fmt::Display for FloatRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FloatRegister::S0 => "s0",
+            FloatRegister::S1 => "s1",
+            FloatRegister::S2 => "s2",
+            FloatRegister::S3 => "s3",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(register) => write!(f, "{}", register),
+            Operand::Immediate(value) => write!(f, "#{}", value),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Register(base) => write!(f, "[{}]", base),
+            Address::Offset(base, offset) => write!(f, "[{}, {}]", base, offset),
+        }
+    }
+}
+
+fn write_register_list(f: &mut fmt::Formatter<'_>, registers: &[Register]) -> fmt::Result {
+    let names: Vec<String> = registers.iter().map(Register::to_string).collect();
+    write!(f, "{{{}}}", names.join(", "))
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Global(name) => write!(f, ".global {}", name),
+            Instruction::Local(name) => write!(f, ".local {}", name),
+            Instruction::Label(name) => write!(f, "{}:", name),
+            Instruction::Section(name) => write!(f, ".section {}", name),
+            Instruction::Asciz(text) => write!(f, ".asciz \"{}\"", text.escape_default()),
+            Instruction::Word(value) => write!(f, ".word {}", value),
+            Instruction::Space(size) => write!(f, ".space {}", size),
+            Instruction::Balign(alignment) => write!(f, ".balign {}", alignment),
+            Instruction::Push(registers) => {
+                write!(f, "push ")?;
+                write_register_list(f, registers)
+            }
+            Instruction::Pop(registers) => {
+                write!(f, "pop ")?;
+                write_register_list(f, registers)
+            }
+            Instruction::Mov(cond, rd, operand) => write!(f, "mov{} {}, {}", cond, rd, operand),
+            Instruction::LoadConstant(rd, value) => write!(f, "ldr {}, ={}", rd, value),
+            Instruction::LoadAddress(rd, label) => write!(f, "ldr {}, ={}", rd, label),
+            Instruction::Ldr(cond, rd, address) => write!(f, "ldr{} {}, {}", cond, rd, address),
+            Instruction::Ldrb(cond, rd, address) => {
+                write!(f, "ldrb{} {}, {}", cond, rd, address)
+            }
+            Instruction::Str(cond, rd, address) => write!(f, "str{} {}, {}", cond, rd, address),
+            Instruction::Add(cond, rd, rn, operand) => {
+                write!(f, "add{} {}, {}, {}", cond, rd, rn, operand)
+            }
+            Instruction::Sub(cond, rd, rn, operand) => {
+                write!(f, "sub{} {}, {}, {}", cond, rd, rn, operand)
+            }
+            Instruction::Rsb(cond, rd, rn, operand) => {
+                write!(f, "rsb{} {}, {}, {}", cond, rd, rn, operand)
+            }
+            Instruction::Mul(rd, rn, rm) => write!(f, "mul {}, {}, {}", rd, rn, rm),
+            Instruction::Sdiv(rd, rn, rm) => write!(f, "sdiv {}, {}, {}", rd, rn, rm),
+            Instruction::Udiv(rd, rn, rm) => write!(f, "udiv {}, {}, {}", rd, rn, rm),
+            Instruction::Lsl(cond, rd, rn, operand) => {
+                write!(f, "lsl{} {}, {}, {}", cond, rd, rn, operand)
+            }
+            Instruction::Lsr(cond, rd, rn, operand) => {
+                write!(f, "lsr{} {}, {}, {}", cond, rd, rn, operand)
+            }
+            Instruction::Ror(cond, rd, rn, operand) => {
+                write!(f, "ror{} {}, {}, {}", cond, rd, rn, operand)
+            }
+            Instruction::Clz(rd, rm) => write!(f, "clz {}, {}", rd, rm),
+            Instruction::Cmp(rn, operand) => write!(f, "cmp {}, {}", rn, operand),
+            Instruction::VmovToFloat(sd, rn) => write!(f, "vmov {}, {}", sd, rn),
+            Instruction::VmovFromFloat(rd, sn) => write!(f, "vmov {}, {}", rd, sn),
+            Instruction::Vadd(sd, sn, sm) => write!(f, "vadd.f32 {}, {}, {}", sd, sn, sm),
+            Instruction::Vsub(sd, sn, sm) => write!(f, "vsub.f32 {}, {}, {}", sd, sn, sm),
+            Instruction::Vmul(sd, sn, sm) => write!(f, "vmul.f32 {}, {}, {}", sd, sn, sm),
+            Instruction::Vdiv(sd, sn, sm) => write!(f, "vdiv.f32 {}, {}, {}", sd, sn, sm),
+            Instruction::Vcmp(sd, sm) => write!(f, "vcmp.f32 {}, {}", sd, sm),
+            Instruction::Vmrs => write!(f, "vmrs APSR_nzcv, fpscr"),
+            Instruction::B(cond, label) => write!(f, "b{} {}", cond, label),
+            Instruction::Bl(name) => write!(f, "bl {}", name),
+            Instruction::Bx(rm) => write!(f, "bx {}", rm),
+            Instruction::Svc(number) => write!(f, "svc #{}", number),
+            Instruction::Bkpt(number) => write!(f, "bkpt #{}", number),
+            Instruction::It(cond) => write!(f, "it {}", cond),
+            Instruction::Comment(text) => write!(f, "@ {}", text),
+            Instruction::File(number, name) => write!(f, ".file {} {:?}", number, name),
+            Instruction::Loc(file, line, column) => {
+                write!(f, ".loc {} {} {}", file, line, column)
+            }
+            Instruction::Directive(directive) => write!(f, "{}", directive),
+        }
+    }
+}
+
+/// Renders the instructions as assembly source, one instruction per line.
+pub fn render(instructions: &[Instruction]) -> String {
+    let mut output = Vec::new();
+    write(instructions, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+/// Writes the assembly an instruction at a time, without rendering all of
+/// it first.
+pub fn write(instructions: &[Instruction], output: &mut dyn io::Write) -> io::Result<()> {
+    for instruction in instructions {
+        match instruction {
+            Instruction::Global(_) | Instruction::Local(_) => {
+                writeln!(output, "\n{}", instruction)?
+            }
+            Instruction::Label(_) | Instruction::Section(_) | Instruction::Directive(_) => {
+                writeln!(output, "{}", instruction)?
+            }
+            _ => writeln!(output, "    {}", instruction)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_instructions() {
+        assert_eq!(
+            Instruction::Push(vec![Register::Fp, Register::Lr]).to_string(),
+            "push {fp, lr}"
+        );
+        assert_eq!(
+            Instruction::Mov(Condition::Eq, Register::R0, Operand::Immediate(1)).to_string(),
+            "moveq r0, #1"
+        );
+        assert_eq!(
+            Instruction::Ldr(
+                Condition::Lo,
+                Register::R0,
+                Address::Offset(Register::R1, Operand::Register(Register::R0))
+            )
+            .to_string(),
+            "ldrlo r0, [r1, r0]"
+        );
+        assert_eq!(
+            Instruction::Str(
+                Condition::Always,
+                Register::R0,
+                Address::Register(Register::R4)
+            )
+            .to_string(),
+            "str r0, [r4]"
+        );
+        assert_eq!(
+            Instruction::LoadConstant(Register::R0, -12).to_string(),
+            "ldr r0, =-12"
+        );
+        assert_eq!(
+            Instruction::Vadd(FloatRegister::S0, FloatRegister::S0, FloatRegister::S1).to_string(),
+            "vadd.f32 s0, s0, s1"
+        );
+        assert_eq!(
+            Instruction::Asciz(String::from("out of \"bounds\"\n")).to_string(),
+            ".asciz \"out of \\\"bounds\\\"\\n\""
+        );
+    }
+
+    #[test]
+    fn rendering() {
+        let instructions = vec![
+            Instruction::Global(String::from("main")),
+            Instruction::Label(String::from("main")),
+            Instruction::Bl(String::from("f")),
+        ];
+        assert_eq!(render(&instructions), "\n.global main\nmain:\n    bl f\n");
+    }
+}
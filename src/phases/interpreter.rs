@@ -0,0 +1,534 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::rc::Rc;
+
+use crate::ast::Ast;
+use crate::phases::codegen::{
+    self, Arithmetic, BoundsCheck, CodegenOptions, ABS_INTRINSIC, ARG_COUNT, ARG_NUMBER,
+    CLZ_INTRINSIC, CONCAT, FLOAT_ADD, FLOAT_DIVIDE, FLOAT_EQUAL, FLOAT_MULTIPLY, FLOAT_SUBTRACT,
+    MAX_INTRINSIC, MIN_INTRINSIC, NUMBER_TO_STRING, POP, PUSH, ROTATE_INTRINSIC, SLICE,
+};
+use crate::phases::runtime::{POPCOUNT_ROUTINE, STRING_EQUAL_ROUTINE};
+use crate::types::Type;
+
+/// Calls nested deeper than this stop the program instead of overflowing
+/// the interpreter's own stack, given a thread with a large stack like the
+/// other phases.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// A value of the running program. Numbers, booleans, `null` and
+/// `undefined` are all words like in the compiled code, arrays and strings
+/// are shared between the variables holding them.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(i32),
+    Float(f32),
+    Array(Rc<RefCell<Vec<Value>>>),
+}
+
+impl Value {
+    fn array(elements: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(elements)))
+    }
+
+    fn number(&self) -> i32 {
+        match self {
+            Value::Number(value) => *value,
+            // arrays are never at address zero
+            Value::Array(_) => 1,
+            Value::Float(value) => value.to_bits() as i32,
+        }
+    }
+
+    fn float(&self) -> f32 {
+        match self {
+            Value::Float(value) => *value,
+            other => f32::from_bits(other.number() as u32),
+        }
+    }
+
+    fn elements(&self) -> Result<Rc<RefCell<Vec<Value>>>, String> {
+        match self {
+            Value::Array(elements) => Ok(Rc::clone(elements)),
+            _ => Err(String::from("Looked into an array which is undefined")),
+        }
+    }
+
+    // `==` on words, arrays are equal when they are the same array.
+    fn same(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Array(lhs), Value::Array(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::Array(_), _) | (_, Value::Array(_)) => false,
+            (lhs, rhs) => lhs.number() == rhs.number(),
+        }
+    }
+}
+
+// How a statement finished.
+enum Flow {
+    Next,
+    Return(Value),
+}
+
+/// Runs the linked program, with its typed operations lowered, by walking
+/// its tree. It follows the ARM32 code generated with the same options,
+/// which makes it the reference the compiled programs are tested against,
+/// except that `push` always appends to the array in place. Calling an
+/// `extern` function stops the program.
+#[derive(Debug)]
+pub struct Interpreter {
+    ast: Ast,
+    options: CodegenOptions,
+    arguments: Vec<String>,
+}
+
+// The locals of a call, the innermost block last.
+type Scopes = Vec<HashMap<String, Value>>;
+
+struct Run<'a> {
+    functions: HashMap<&'a str, (Vec<&'a str>, &'a Ast)>,
+    globals: HashMap<String, Value>,
+    options: CodegenOptions,
+    arguments: &'a [String],
+    output: &'a mut dyn io::Write,
+    depth: usize,
+}
+
+impl Interpreter {
+    pub fn new(ast: Ast) -> Interpreter {
+        Interpreter {
+            ast,
+            options: CodegenOptions::default(),
+            arguments: Vec::new(),
+        }
+    }
+
+    /// Only the arithmetic and bounds checking are taken into account.
+    pub fn with_options(mut self, options: CodegenOptions) -> Interpreter {
+        self.options = options;
+        self
+    }
+
+    /// The command line seen by `argCount` and `argNumber`, starting with
+    /// the program name.
+    pub fn with_arguments(mut self, arguments: Vec<String>) -> Interpreter {
+        self.arguments = arguments;
+        self
+    }
+
+    /// Initializes the globals and calls `main`, writing what the program
+    /// prints to `output`. Gives what `main` returns, or why the program
+    /// stopped.
+    pub fn run(&self, output: &mut dyn io::Write) -> Result<i32, String> {
+        let mut functions = HashMap::new();
+        for declaration in self.ast.declarations() {
+            if let Ast::Function(
+                name,
+                Type::Function {
+                    parameter_types, ..
+                },
+                body,
+            ) = declaration
+            {
+                let parameters = parameter_types.keys().map(String::as_str).collect();
+                functions.insert(name.as_str(), (parameters, body.as_ref()));
+            }
+        }
+        let globals = codegen::global_variables(&self.ast)
+            .into_iter()
+            .map(|(name, value)| (String::from(name), Value::Number(value)))
+            .collect();
+        let mut run = Run {
+            functions,
+            globals,
+            options: self.options,
+            arguments: &self.arguments,
+            output,
+            depth: 0,
+        };
+        let status = run.call("main", Vec::new())?.number();
+        run.output.flush().map_err(|error| error.to_string())?;
+        Ok(status)
+    }
+}
+
+impl<'a> Run<'a> {
+    fn call(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, String> {
+        let (parameters, body) = match self.functions.get(name) {
+            Some((parameters, body)) => (parameters.clone(), *body),
+            None => return self.call_builtin(name, arguments),
+        };
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(format!("Calls nested deeper than {}", MAX_CALL_DEPTH));
+        }
+        self.depth += 1;
+        let mut locals = vec![parameters
+            .into_iter()
+            .map(String::from)
+            .zip(arguments)
+            .collect()];
+        let flow = self.execute(body, &mut locals);
+        self.depth -= 1;
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Next => Ok(Value::Number(0)),
+        }
+    }
+
+    fn call_builtin(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, String> {
+        let number = |i: usize| arguments[i].number();
+        let float = |i: usize| arguments[i].float();
+        Ok(match name {
+            "putchar" => {
+                self.output
+                    .write_all(&[number(0) as u8])
+                    .map_err(|error| error.to_string())?;
+                Value::Number(0)
+            }
+            ARG_COUNT => Value::Number(self.arguments.len() as i32),
+            ARG_NUMBER => Value::Number(
+                usize::try_from(number(0))
+                    .ok()
+                    .and_then(|i| self.arguments.get(i))
+                    .map_or(0, |argument| parse_number(argument)),
+            ),
+            NUMBER_TO_STRING => string(&number(0).to_string()),
+            PUSH => {
+                arguments[0]
+                    .elements()?
+                    .borrow_mut()
+                    .push(arguments[1].clone());
+                Value::Number(0)
+            }
+            POP => arguments[0]
+                .elements()?
+                .borrow_mut()
+                .pop()
+                .unwrap_or(Value::Number(0)),
+            CONCAT => {
+                let mut elements = arguments[0].elements()?.borrow().clone();
+                elements.extend(arguments[1].elements()?.borrow().iter().cloned());
+                Value::array(elements)
+            }
+            SLICE => {
+                let elements = arguments[0].elements()?;
+                let elements = elements.borrow();
+                let clamp = |index: i32| (index.max(0) as usize).min(elements.len());
+                let (start, end) = (clamp(number(1)), clamp(number(2)));
+                Value::array(elements[start..end.max(start)].to_vec())
+            }
+            STRING_EQUAL_ROUTINE => {
+                let lhs = arguments[0].elements()?;
+                let rhs = arguments[1].elements()?;
+                let lhs = lhs.borrow();
+                let rhs = rhs.borrow();
+                let equal = lhs.len() == rhs.len()
+                    && lhs.iter().zip(rhs.iter()).all(|(lhs, rhs)| lhs.same(rhs));
+                Value::Number(equal as i32)
+            }
+            FLOAT_ADD => Value::Float(float(0) + float(1)),
+            FLOAT_SUBTRACT => Value::Float(float(0) - float(1)),
+            FLOAT_MULTIPLY => Value::Float(float(0) * float(1)),
+            FLOAT_DIVIDE => Value::Float(float(0) / float(1)),
+            FLOAT_EQUAL => Value::Number((float(0) == float(1)) as i32),
+            MIN_INTRINSIC => Value::Number(number(0).min(number(1))),
+            MAX_INTRINSIC => Value::Number(number(0).max(number(1))),
+            ABS_INTRINSIC => Value::Number(number(0).wrapping_abs()),
+            CLZ_INTRINSIC => Value::Number(number(0).leading_zeros() as i32),
+            POPCOUNT_ROUTINE => Value::Number(number(0).count_ones() as i32),
+            ROTATE_INTRINSIC => Value::Number(number(0).rotate_right(number(1) as u32)),
+            name => return Err(format!("Cannot call `{}` when interpreting", name)),
+        })
+    }
+
+    fn execute(&mut self, statement: &Ast, locals: &mut Scopes) -> Result<Flow, String> {
+        match statement {
+            Ast::Block(statements) => {
+                locals.push(HashMap::new());
+                let flow = statements
+                    .iter()
+                    .map(|statement| self.execute(statement, locals))
+                    .find(|flow| !matches!(flow, Ok(Flow::Next)));
+                locals.pop();
+                if let Some(flow) = flow {
+                    return flow;
+                }
+            }
+            Ast::Return(value) => return Ok(Flow::Return(self.evaluate(value, locals)?)),
+            Ast::If(condition, consequence, alternative) => {
+                return if self.evaluate(condition, locals)?.number() != 0 {
+                    self.execute_in_scope(consequence, locals)
+                } else {
+                    self.execute_in_scope(alternative, locals)
+                };
+            }
+            Ast::While(condition, body) => {
+                while self.evaluate(condition, locals)?.number() != 0 {
+                    if let Flow::Return(value) = self.execute_in_scope(body, locals)? {
+                        return Ok(Flow::Return(value));
+                    }
+                }
+            }
+            Ast::Var(name, value) => {
+                let value = self.evaluate(value, locals)?;
+                let scope = locals.last_mut().expect("a function has a scope");
+                scope.insert(name.clone(), value);
+            }
+            Ast::Assignment(name, value) => {
+                let value = self.evaluate(value, locals)?;
+                match locals
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(name))
+                {
+                    Some(local) => *local = value,
+                    None => {
+                        self.globals.insert(name.clone(), value);
+                    }
+                }
+            }
+            // checked by the typechecker and folded into their uses
+            Ast::Const(_, _) | Ast::StaticAssert(_, _) => {}
+            Ast::Doc(_) => {}
+            expression => {
+                self.evaluate(expression, locals)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    // Branches and loop bodies get their own scope like in the compiled code,
+    // even when they are not blocks.
+    fn execute_in_scope(&mut self, statement: &Ast, locals: &mut Scopes) -> Result<Flow, String> {
+        locals.push(HashMap::new());
+        let flow = self.execute(statement, locals);
+        locals.pop();
+        flow
+    }
+
+    // With a stack of its own, as a chain of operators is as deep as it is
+    // long: the operands are evaluated first, in order.
+    fn evaluate(&mut self, expression: &Ast, locals: &mut Scopes) -> Result<Value, String> {
+        enum Step<'a> {
+            Enter(&'a Ast),
+            Exit(&'a Ast),
+        }
+        let mut steps = vec![Step::Enter(expression)];
+        let mut values = Vec::new();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Enter(ast) => {
+                    steps.push(Step::Exit(ast));
+                    steps.extend(ast.children().into_iter().rev().map(Step::Enter));
+                }
+                Step::Exit(ast) => {
+                    let operands = values.split_off(values.len() - ast.children().len());
+                    let value = self.operation(ast, operands, locals)?;
+                    values.push(value);
+                }
+            }
+        }
+        Ok(values.pop().expect("an expression has a value"))
+    }
+
+    // The value of the expression, given the values of its operands.
+    fn operation(
+        &mut self,
+        expression: &Ast,
+        operands: Vec<Value>,
+        locals: &Scopes,
+    ) -> Result<Value, String> {
+        let number = |i: usize| operands[i].number();
+        Ok(match expression {
+            Ast::Null | Ast::Undefined => Value::Number(0),
+            Ast::Number(value) => Value::Number(*value),
+            Ast::Float(value) => Value::Float(*value),
+            Ast::Bool(value) => Value::Number(*value as i32),
+            Ast::String(text) => string(text),
+            Ast::ArrayLiteral(_) => Value::array(operands),
+            Ast::ArrayLookup(_, _) => {
+                let elements = operands[0].elements()?;
+                let index = number(1);
+                let element = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| elements.borrow().get(index).cloned());
+                match (element, self.options.bounds_check) {
+                    (Some(element), _) => element,
+                    (None, BoundsCheck::Trap) => {
+                        return Err(format!("Index {} is out of bounds", index))
+                    }
+                    (None, _) => Value::Number(0),
+                }
+            }
+            Ast::ArrayLength(_) => {
+                let elements = operands[0].elements()?;
+                let length = elements.borrow().len();
+                Value::Number(length as i32)
+            }
+            Ast::Identifier(name) => locals
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(name))
+                .or_else(|| self.globals.get(name))
+                .cloned()
+                .ok_or_else(|| format!("Use of undefined name {}", name))?,
+            Ast::Not(_) => Value::Number((number(0) == 0) as i32),
+            Ast::Equal(_, _) | Ast::NotEqual(_, _) => {
+                let same = operands[0].same(&operands[1]);
+                Value::Number((same == matches!(expression, Ast::Equal(_, _))) as i32)
+            }
+            Ast::Addition(_, _) => Value::Number(number(0).wrapping_add(number(1))),
+            Ast::Subtraction(_, _) => Value::Number(number(0).wrapping_sub(number(1))),
+            Ast::Multiplication(_, _) => Value::Number(number(0).wrapping_mul(number(1))),
+            Ast::Division(_, _) => {
+                Value::Number(divide(number(0), number(1), self.options.arithmetic))
+            }
+            Ast::Call(name, _) => self.call(name, operands)?,
+            // the arrays are freed with the interpreter
+            Ast::Delete(_) => Value::Number(0),
+            other => unreachable!("{} is not an expression", other.kind()),
+        })
+    }
+}
+
+// Division as `sdiv` and `udiv` do it, dividing by zero gives zero.
+fn divide(lhs: i32, rhs: i32, arithmetic: Arithmetic) -> i32 {
+    if rhs == 0 {
+        return 0;
+    }
+    match arithmetic {
+        Arithmetic::Signed => lhs.wrapping_div(rhs),
+        Arithmetic::Unsigned => (lhs as u32 / rhs as u32) as i32,
+    }
+}
+
+fn string(text: &str) -> Value {
+    Value::array(
+        text.bytes()
+            .map(|byte| Value::Number(i32::from(byte)))
+            .collect(),
+    )
+}
+
+// The argument read like `argNumber` does, a decimal number with an
+// optional minus sign up to the first other character.
+fn parse_number(argument: &str) -> i32 {
+    let (sign, digits) = match argument.strip_prefix('-') {
+        Some(digits) => (-1, digits),
+        None => (1, argument),
+    };
+    digits
+        .bytes()
+        .take_while(u8::is_ascii_digit)
+        .fold(0i32, |value, digit| {
+            value.wrapping_mul(10).wrapping_add(i32::from(digit - b'0'))
+        })
+        .wrapping_mul(sign)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::phases::typecheck;
+
+    fn interpret(source: &str, options: CodegenOptions) -> Result<(String, i32), String> {
+        let mut ast = parser::parse_program(source).unwrap();
+        typecheck::lower_typed_operations(&mut ast, typecheck::builtins()).unwrap();
+        let mut output = Vec::new();
+        let status = Interpreter::new(ast)
+            .with_options(options)
+            .with_arguments(vec![String::from("main"), String::from("-12x")])
+            .run(&mut output)?;
+        Ok((String::from_utf8(output).unwrap(), status))
+    }
+
+    fn status(source: &str) -> i32 {
+        interpret(source, CodegenOptions::default()).unwrap().1
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(status("function main() { return 2 + 3 * (4 - 1); }"), 11);
+        assert_eq!(status("function main() { return (0 - 7) / 2; }"), -3);
+        assert_eq!(status("function main() { return 7 / 0; }"), 0);
+        let unsigned = CodegenOptions {
+            arithmetic: Arithmetic::Unsigned,
+            ..CodegenOptions::default()
+        };
+        assert_eq!(
+            interpret("function main() { return (0 - 2) / 2; }", unsigned).unwrap(),
+            (String::new(), i32::MAX)
+        );
+        assert_eq!(
+            status("function main() { var x = 1.5; if (x * 2.0 == 3.0) { return 1; } else { return 0; } }"),
+            1
+        );
+    }
+
+    #[test]
+    fn calls() {
+        let source = "
+            var calls = 0;
+            function factorial(n: number) {
+                calls = calls + 1;
+                if (n == 0) { return 1; } else { return n * factorial(n - 1); }
+            }
+            function main() { putchar(48 + factorial(3)); return calls; }";
+        assert_eq!(
+            interpret(source, CodegenOptions::default()).unwrap(),
+            (String::from("6"), 4)
+        );
+        assert_eq!(
+            status("function main() { return argCount() * 100 + argNumber(1); }"),
+            188
+        );
+        let endless = "function f(n: number) { return f(n); } function main() { return f(0); }";
+        // the calls nest as deep as in the driver, which runs on a large stack
+        let error = std::thread::Builder::new()
+            .stack_size(64 << 20)
+            .spawn(move || interpret(endless, CodegenOptions::default()))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(error.unwrap_err(), "Calls nested deeper than 1000");
+    }
+
+    #[test]
+    fn arrays() {
+        let source = "
+            function main() {
+                var xs = [1, 2, 3];
+                push(xs, 4);
+                var ys = concat(xs, slice(xs, 2, 10));
+                pop(ys);
+                return length(ys) * 10 + ys[4] + xs[7];
+            }";
+        assert_eq!(status(source), 53);
+        let trap = CodegenOptions {
+            bounds_check: BoundsCheck::Trap,
+            ..CodegenOptions::default()
+        };
+        assert_eq!(
+            interpret("function main() { var xs = [1]; return xs[1]; }", trap).unwrap_err(),
+            "Index 1 is out of bounds"
+        );
+    }
+
+    #[test]
+    fn equality() {
+        let source = "
+            function bit(condition: boolean, value: number) {
+                if (condition) { return value; } else { return 0; }
+            }
+            function main() {
+                var a = \"ab\";
+                var b = [1];
+                var c = b;
+                return bit(a == \"ab\", 1) + bit(a != \"ba\", 2) + bit(b == c, 4) + bit(b == [1], 8);
+            }";
+        assert_eq!(status(source), 7);
+    }
+}
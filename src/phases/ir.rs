@@ -0,0 +1,167 @@
+//! The textual form of `--emit=ir` and `--emit=ir-opt`: the ARM32
+//! instructions of each function of the program, split into basic blocks.
+//!
+//! The instruction list is the only form the code takes between the AST and
+//! the assembly, the passes of `phases::passes` rewriting it in place, so it
+//! is the one dumped. The registers are the ones of the machine, the code
+//! generator allocates them as it goes. A block starts at a label or after a
+//! branch or a return and ends with its terminator, followed by the blocks
+//! it continues to:
+//!
+//! ```text
+//! function f
+//!   bb0:
+//!     cmp r0, #0
+//!     beq .L0
+//!     -> bb2, bb1
+//!   bb1:
+//!     mov r0, #1
+//!     -> bb2
+//!   bb2 (.L0):
+//!     pop {fp, pc}
+//!     -> return
+//! ```
+//!
+//! The runtime routines are left out, only the functions of the program are
+//! dumped.
+use crate::phases::instruction::{Condition, Instruction, Register};
+
+// How a block ends, unless it continues to the next one.
+enum Terminator<'a> {
+    Jump(&'a str),
+    Branch(&'a str),
+    Return,
+}
+
+fn terminator(instruction: &Instruction) -> Option<Terminator<'_>> {
+    match instruction {
+        Instruction::B(Condition::Always, label) => Some(Terminator::Jump(label)),
+        Instruction::B(_, label) => Some(Terminator::Branch(label)),
+        Instruction::Pop(registers) if registers.contains(&Register::Pc) => {
+            Some(Terminator::Return)
+        }
+        Instruction::Bx(_) | Instruction::Mov(Condition::Always, Register::Pc, _) => {
+            Some(Terminator::Return)
+        }
+        _ => None,
+    }
+}
+
+/// Dumps the functions of the ARM32 code.
+pub fn to_text(code: &[Instruction]) -> String {
+    let mut output = String::new();
+    let mut i = 0;
+    while i < code.len() {
+        let name = match &code[i] {
+            Instruction::Global(name) | Instruction::Local(name) => name,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        // the function runs until the next symbol or section
+        let end = (i + 1..code.len())
+            .find(|&j| match &code[j] {
+                Instruction::Global(_) | Instruction::Local(_) | Instruction::Section(_) => true,
+                Instruction::Label(label) => label != name && !label.starts_with(".L"),
+                _ => false,
+            })
+            .unwrap_or(code.len());
+        output.push_str(&format!("function {}\n", name));
+        function_text(&code[i + 1..end], name, &mut output);
+        i = end;
+    }
+    output
+}
+
+fn function_text(code: &[Instruction], name: &str, output: &mut String) {
+    // the blocks, as their label and their instructions
+    let mut blocks: Vec<(Option<&str>, Vec<&Instruction>)> = vec![(None, Vec::new())];
+    for instruction in code {
+        match instruction {
+            Instruction::Label(label) if label == name => {}
+            Instruction::Label(label) => blocks.push((Some(label), Vec::new())),
+            instruction if instruction.is_executable() => {
+                let last = blocks.len() - 1;
+                if blocks[last]
+                    .1
+                    .last()
+                    .is_some_and(|last| terminator(last).is_some())
+                {
+                    blocks.push((None, Vec::new()));
+                }
+                let last = blocks.len() - 1;
+                blocks[last].1.push(instruction);
+            }
+            _ => {}
+        }
+    }
+    if blocks.len() > 1 && blocks[0].1.is_empty() {
+        blocks.remove(0);
+    }
+    let block_of = |label: &str| {
+        blocks
+            .iter()
+            .position(|(name, _)| *name == Some(label))
+            .map_or_else(|| String::from(label), |block| format!("bb{}", block))
+    };
+    for (block, (label, instructions)) in blocks.iter().enumerate() {
+        match label {
+            Some(label) => output.push_str(&format!("  bb{} ({}):\n", block, label)),
+            None => output.push_str(&format!("  bb{}:\n", block)),
+        }
+        for instruction in instructions {
+            output.push_str(&format!("    {}\n", instruction));
+        }
+        let next = || {
+            if block + 1 < blocks.len() {
+                format!("bb{}", block + 1)
+            } else {
+                String::from("end")
+            }
+        };
+        let successors = match instructions.last().and_then(|last| terminator(last)) {
+            Some(Terminator::Jump(label)) => block_of(label),
+            Some(Terminator::Branch(label)) => format!("{}, {}", block_of(label), next()),
+            Some(Terminator::Return) => String::from("return"),
+            None => next(),
+        };
+        output.push_str(&format!("    -> {}\n", successors));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phases::instruction::Operand;
+
+    #[test]
+    fn blocks() {
+        let code = vec![
+            Instruction::Local(String::from("f")),
+            Instruction::Label(String::from("f")),
+            Instruction::Cmp(Register::R0, Operand::Immediate(0)),
+            Instruction::B(Condition::Eq, String::from(".L0")),
+            Instruction::Mov(Condition::Always, Register::R0, Operand::Immediate(1)),
+            Instruction::Label(String::from(".L0")),
+            Instruction::Pop(vec![Register::Fp, Register::Pc]),
+            Instruction::Label(String::from("__rtsc_routine")),
+            Instruction::Bx(Register::Lr),
+        ];
+        assert_eq!(
+            to_text(&code),
+            "function f
+  bb0:
+    cmp r0, #0
+    beq .L0
+    -> bb2, bb1
+  bb1:
+    mov r0, #1
+    -> bb2
+  bb2 (.L0):
+    pop {fp, pc}
+    -> return
+"
+        );
+    }
+}
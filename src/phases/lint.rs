@@ -0,0 +1,223 @@
+//! Warnings about code which is valid but most likely not what was meant.
+use std::collections::BTreeMap;
+
+use crate::ast::Ast;
+use crate::diagnostic::{self, Diagnostic};
+use crate::lexer::{Lexer, Token, TokenKind};
+use crate::lines;
+use crate::phases::cfg;
+use crate::phases::liveness::{self, Liveness};
+use crate::span::Span;
+use crate::types::Type;
+
+/// The warnings about the program parsed from `source`, whose statements
+/// span `spans`, in source order.
+pub fn lint(source: &str, program: &Ast, spans: &[Span]) -> Vec<Diagnostic> {
+    let spans: BTreeMap<*const Ast, Span> = lines::statements(program)
+        .into_iter()
+        .map(|statement| statement as *const Ast)
+        .zip(spans.iter().copied())
+        .collect();
+    let span = |statement: &Ast| spans.get(&(statement as *const Ast)).copied();
+    let mut unreachable_statements = Vec::new();
+    unreachable(program, &mut unreachable_statements);
+    let mut warnings: Vec<Diagnostic> = unreachable_statements
+        .into_iter()
+        .map(|statement| {
+            Diagnostic::warning(
+                diagnostic::UNREACHABLE_CODE,
+                String::from("unreachable statement"),
+                span(statement),
+            )
+        })
+        .collect();
+    for (_, cfg) in cfg::functions(program) {
+        for statement in liveness::dead_stores(&cfg, &Liveness::new(&cfg)) {
+            if let Some(name) = liveness::defines(statement) {
+                warnings.push(Diagnostic::warning(
+                    diagnostic::UNUSED_ASSIGNMENT,
+                    format!("the value assigned to `{}` is never read", name),
+                    span(statement),
+                ));
+            }
+        }
+    }
+    // the parser fills in the types, only the tokens tell what was written
+    let tokens = Lexer::new().tokenize(source).unwrap_or_default();
+    for statement in lines::statements(program) {
+        if let (Ast::Function(name, signature, _) | Ast::Extern(name, signature), Some(span)) =
+            (statement, span(statement))
+        {
+            let start = tokens
+                .iter()
+                .position(|token| token.span.start >= span.start)
+                .unwrap_or(tokens.len());
+            warnings.extend(missing_annotations(name, signature, &tokens[start..]));
+        }
+    }
+    warnings.sort_by_key(|warning| warning.span.map(|span| span.start));
+    warnings
+}
+
+// The parameters and the return type of the function declared by the
+// tokens which have no type annotation, the parser gave them the implicit
+// type.
+fn missing_annotations(function: &str, signature: &Type, tokens: &[Token]) -> Vec<Diagnostic> {
+    let (parameter_types, return_type) = match signature {
+        Type::Function {
+            parameter_types,
+            return_type,
+        } => (parameter_types, return_type),
+        _ => return Vec::new(),
+    };
+    let warning = |token: &Token, message| {
+        Diagnostic::warning(diagnostic::MISSING_ANNOTATION, message, Some(token.span))
+    };
+    let mut warnings = Vec::new();
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        let next = tokens.get(i + 1).map(|token| token.kind);
+        match token.kind {
+            TokenKind::LeftParen | TokenKind::LeftBracket => depth += 1,
+            TokenKind::RightParen if depth == 1 => {
+                if next != Some(TokenKind::Colon) {
+                    let name = tokens.iter().find(|token| token.text == function);
+                    warnings.push(warning(
+                        name.unwrap_or(token),
+                        format!(
+                            "`{}` has no return type annotation, `{}` is assumed",
+                            function, return_type
+                        ),
+                    ));
+                }
+                break;
+            }
+            TokenKind::RightParen | TokenKind::RightBracket => depth -= 1,
+            TokenKind::Identifier
+                if depth == 1
+                    && matches!(tokens[i - 1].kind, TokenKind::LeftParen | TokenKind::Comma)
+                    && next != Some(TokenKind::Colon) =>
+            {
+                warnings.push(warning(
+                    token,
+                    format!(
+                        "parameter `{}` has no type annotation, `{}` is assumed",
+                        token.text, parameter_types[&token.text]
+                    ),
+                ));
+            }
+            _ => {}
+        }
+    }
+    warnings
+}
+
+// Whether running the statement always ends in a `return`.
+fn returns(statement: &Ast) -> bool {
+    match statement {
+        Ast::Return(_) => true,
+        Ast::Block(statements) => statements.iter().any(returns),
+        Ast::If(_, consequence, alternative) => returns(consequence) && returns(alternative),
+        _ => false,
+    }
+}
+
+// The first statement following one which always returns in every block,
+// the statements after it being just as unreachable.
+fn unreachable<'a>(ast: &'a Ast, statements: &mut Vec<&'a Ast>) {
+    match ast {
+        Ast::Namespace(_, members) => {
+            for member in members {
+                unreachable(member, statements);
+            }
+        }
+        Ast::Block(block) => {
+            for (i, statement) in block.iter().enumerate() {
+                unreachable(statement, statements);
+                if returns(statement) {
+                    // doc comments are not run
+                    statements.extend(
+                        block[i + 1..]
+                            .iter()
+                            .find(|statement| !matches!(statement, Ast::Doc(_))),
+                    );
+                    break;
+                }
+            }
+        }
+        Ast::If(_, consequence, alternative) => {
+            unreachable(consequence, statements);
+            unreachable(alternative, statements);
+        }
+        Ast::While(_, body) | Ast::Function(_, _, body) | Ast::Export(body) => {
+            unreachable(body, statements)
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, ParseOptions};
+    use crate::span::Location;
+
+    #[test]
+    fn warnings() {
+        let source = "function f(x) {
+    var y = 1;
+    y = x;
+    return y;
+    putchar(y);
+    if (x == 1) { putchar(x); } else {}
+}
+function g(x: number): number {
+    if (x == 1) return 1; else return 2;
+    return 3;
+    x = 4;
+}";
+        let (program, spans) =
+            parser::parse_program_with_spans(source, &ParseOptions::default()).unwrap();
+        let warnings: Vec<String> = lint(source, &program, &spans)
+            .iter()
+            .map(|warning| {
+                let location = Location::from_offset(source, warning.span.unwrap().start);
+                format!("{}: {}", location, warning)
+            })
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "1:10: warning[W0003]: `f` has no return type annotation, `number` is assumed",
+                "1:12: warning[W0003]: parameter `x` has no type annotation, `number` is assumed",
+                "2:5: warning[W0002]: the value assigned to `y` is never read",
+                "5:5: warning[W0001]: unreachable statement",
+                "10:5: warning[W0001]: unreachable statement",
+            ]
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        let source = "extern function abs(x): number;
+export function f(xs: array<number>, n, s: string): boolean { return true; }
+function g() { return abs(1); }";
+        let (program, spans) =
+            parser::parse_program_with_spans(source, &ParseOptions::default()).unwrap();
+        let warnings: Vec<String> = lint(source, &program, &spans)
+            .iter()
+            .map(|warning| {
+                let location = Location::from_offset(source, warning.span.unwrap().start);
+                format!("{}: {}", location, warning.message)
+            })
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "1:21: parameter `x` has no type annotation, `number` is assumed",
+                "2:38: parameter `n` has no type annotation, `number` is assumed",
+                "3:10: `g` has no return type annotation, `number` is assumed",
+            ]
+        );
+    }
+}
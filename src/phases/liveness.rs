@@ -0,0 +1,185 @@
+//! Liveness of the variables of a function, computed over its control-flow
+//! graph.
+//!
+//! A variable is live at a point when its current value may still be read
+//! later on. Variables are told apart by name only, so a `var` shadowing
+//! another one in a nested block counts as the same variable.
+use std::collections::BTreeSet;
+
+use crate::ast::Ast;
+use crate::phases::cfg::{BlockId, Cfg, Terminator};
+
+pub type Variables = BTreeSet<String>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Liveness {
+    /// Variables live at the start of each block.
+    pub live_in: Vec<Variables>,
+    /// Variables live at the end of each block.
+    pub live_out: Vec<Variables>,
+}
+
+impl Liveness {
+    pub fn new(cfg: &Cfg<'_>) -> Liveness {
+        let mut liveness = Liveness {
+            live_in: vec![Variables::new(); cfg.blocks.len()],
+            live_out: vec![Variables::new(); cfg.blocks.len()],
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in (0..cfg.blocks.len()).rev() {
+                let live_out: Variables = cfg
+                    .successors(block)
+                    .into_iter()
+                    .flat_map(|successor| liveness.live_in[successor].iter().cloned())
+                    .collect();
+                let live_in = live_before(cfg, block, 0, live_out.clone());
+                if live_in != liveness.live_in[block] || live_out != liveness.live_out[block] {
+                    liveness.live_in[block] = live_in;
+                    liveness.live_out[block] = live_out;
+                    changed = true;
+                }
+            }
+        }
+        liveness
+    }
+
+    /// Variables live right before the statement at `index` of the block.
+    pub fn live_at(&self, cfg: &Cfg<'_>, block: BlockId, index: usize) -> Variables {
+        live_before(cfg, block, index, self.live_out[block].clone())
+    }
+}
+
+// Goes backwards from the end of the block to the statement at `index`.
+fn live_before(cfg: &Cfg<'_>, block: BlockId, index: usize, mut live: Variables) -> Variables {
+    let block = &cfg.blocks[block];
+    match block.terminator {
+        Terminator::Branch(condition, _, _) => live.extend(uses(condition)),
+        Terminator::Return(expr) => live.extend(uses(expr)),
+        Terminator::Jump(_) | Terminator::Exit => {}
+    }
+    for statement in block.statements[index..].iter().rev() {
+        if let Some(name) = defines(statement) {
+            live.remove(name);
+        }
+        live.extend(uses(statement));
+    }
+    live
+}
+
+/// The variable a statement writes to.
+pub fn defines(statement: &Ast) -> Option<&str> {
+    match statement {
+        Ast::Var(name, _) | Ast::Assignment(name, _) => Some(name),
+        _ => None,
+    }
+}
+
+/// The variables read by a statement or an expression.
+pub fn uses(ast: &Ast) -> Variables {
+    let mut variables = Variables::new();
+    collect_uses(ast, &mut variables);
+    variables
+}
+
+// With a stack of its own, as a chain of operators is as deep as it is long.
+fn collect_uses(ast: &Ast, variables: &mut Variables) {
+    let mut nodes = vec![ast];
+    while let Some(node) = nodes.pop() {
+        match node {
+            Ast::Identifier(name) => {
+                variables.insert(name.clone());
+            }
+            Ast::Function(_, _, _)
+            | Ast::Namespace(_, _)
+            | Ast::Export(_)
+            | Ast::Const(_, _)
+            | Ast::StaticAssert(_, _) => {}
+            node => nodes.extend(node.children()),
+        }
+    }
+}
+
+/// Assignments to variables declared in the function whose value is never
+/// read afterwards. Stores in unreachable blocks are not reported.
+pub fn dead_stores<'a>(cfg: &Cfg<'a>, liveness: &Liveness) -> Vec<&'a Ast> {
+    let locals: BTreeSet<&str> = cfg
+        .blocks
+        .iter()
+        .flat_map(|block| block.statements.iter())
+        .filter_map(|statement| match statement {
+            Ast::Var(name, _) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let reachable = cfg.reachable();
+    let mut stores = Vec::new();
+    for (id, block) in cfg.blocks.iter().enumerate() {
+        if !reachable[id] {
+            continue;
+        }
+        for (index, statement) in block.statements.iter().enumerate() {
+            if let Some(name) = defines(statement) {
+                if locals.contains(name) && !liveness.live_at(cfg, id, index + 1).contains(name) {
+                    stores.push(*statement);
+                }
+            }
+        }
+    }
+    stores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::phases::cfg;
+
+    fn names(variables: &[&str]) -> Variables {
+        variables.iter().map(|x| String::from(*x)).collect()
+    }
+
+    #[test]
+    fn loop_liveness() {
+        let program = parser::parse_program(
+            "function f(n) {
+                var sum = 0;
+                while (n != 0) { sum = sum + n; n = n - 1; }
+                return sum;
+            }",
+        )
+        .unwrap();
+        let (_, cfg) = &cfg::functions(&program)[0];
+        let liveness = Liveness::new(cfg);
+        assert_eq!(liveness.live_in[cfg::ENTRY], names(&["n"]));
+        // the loop header keeps both alive around the back edge
+        assert_eq!(liveness.live_in[1], names(&["n", "sum"]));
+        assert_eq!(liveness.live_out[2], names(&["n", "sum"]));
+        assert_eq!(liveness.live_in[3], names(&["sum"]));
+        assert_eq!(liveness.live_at(cfg, 2, 1), names(&["n", "sum"]));
+        assert!(dead_stores(cfg, &liveness).is_empty());
+    }
+
+    #[test]
+    fn unused_assignments() {
+        let program = parser::parse_program(
+            "function f(c: boolean) {
+                var x = 1;
+                var y = 2;
+                if (c) { x = 3; } else { y = x; }
+                return y;
+            }",
+        )
+        .unwrap();
+        let (_, cfg) = &cfg::functions(&program)[0];
+        let liveness = Liveness::new(cfg);
+        assert_eq!(
+            dead_stores(cfg, &liveness),
+            vec![&Ast::Assignment(
+                String::from("x"),
+                Box::new(Ast::Number(3))
+            )]
+        );
+    }
+}
@@ -0,0 +1,941 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use crate::ast::Ast;
+use crate::mangle;
+use crate::phases::codegen::{
+    self, Arithmetic, BoundsCheck, CodeGenerator, CodegenOptions, Environment,
+};
+use crate::phases::runtime;
+use crate::types::Type;
+
+/// Generates textual LLVM IR that can be compiled with `clang` or `llc` for
+/// any architecture. Arrays are `ptr`s to heap memory allocated with
+/// `malloc`, holding the length followed by the elements, floats are
+/// `float`s and every other value is an `i32`. The `size_t` argument of `malloc` is assumed to be 64 bits.
+#[derive(Debug)]
+pub struct LlvmGenerator {
+    ast: Ast,
+    options: CodegenOptions,
+    constant_arrays_allowed: bool,
+    exported: BTreeSet<String>,
+}
+
+impl CodeGenerator for LlvmGenerator {
+    fn emit(&self, output: &mut dyn io::Write, _env: &mut Environment) -> io::Result<()> {
+        output.write_all(self.generate().as_bytes())
+    }
+}
+
+fn llvm_type(t: &Type) -> &'static str {
+    match t {
+        Type::Array { .. } | Type::String => "ptr",
+        Type::Float => "float",
+        _ => "i32",
+    }
+}
+
+fn zero(t: &Type) -> &'static str {
+    match t {
+        Type::Array { .. } | Type::String => "null",
+        Type::Float => "0.0",
+        _ => "0",
+    }
+}
+
+fn array_layout(element_type: &Type, length: usize) -> String {
+    format!("{{ i32, [{} x {}] }}", length, llvm_type(element_type))
+}
+
+// Prints the error to the standard error and aborts, used by the `trap`
+// bounds check.
+const BOUNDS_ERROR_ROUTINE: &str = "
+@__rtsc_bounds_error_message = private constant [34 x i8] c\"error: array index out of bounds\\0A\\00\"
+
+declare i64 @write(i32, ptr, i64)
+
+declare void @abort()
+
+define internal void @__rtsc_bounds_error() noreturn {
+entry:
+  %written = call i64 @write(i32 2, ptr @__rtsc_bounds_error_message, i64 33)
+  call void @abort()
+  unreachable
+}
+";
+
+fn functions(ast: &Ast) -> impl Iterator<Item = (&String, &Type, &Ast)> {
+    ast.declarations()
+        .filter(|statement| {
+            !matches!(
+                statement,
+                Ast::Import(_, _)
+                    | Ast::Var(_, _)
+                    | Ast::Const(_, _)
+                    | Ast::Extern(_, _)
+                    | Ast::StaticAssert(_, _)
+                    | Ast::Doc(_)
+            )
+        })
+        .map(|statement| match statement {
+            Ast::Function(name, function_type, body) => (name, function_type, body.as_ref()),
+            _ => panic!("Only functions are supported at the top level of an LLVM module"),
+        })
+}
+
+// Declaration of a function defined elsewhere with the given signature.
+fn declaration(name: &str, function_type: &Type) -> String {
+    match function_type {
+        Type::Function {
+            parameter_types,
+            return_type,
+        } => {
+            let parameters: Vec<&str> = parameter_types.values().map(llvm_type).collect();
+            format!(
+                "declare {} @{}({})",
+                llvm_type(return_type),
+                name,
+                parameters.join(", ")
+            )
+        }
+        _ => unreachable!(),
+    }
+}
+
+// Instructions of a single function. Every local lives in a stack slot
+// allocated in the entry block, `opt` promotes them to registers.
+#[derive(Debug)]
+struct FunctionBody {
+    allocas: Vec<String>,
+    lines: Vec<String>,
+    // name, slot and type of the locals, later declarations shadow the
+    // earlier ones
+    locals: Vec<(String, String, Type)>,
+    next_temporary: usize,
+    next_label: usize,
+    block: String,
+}
+
+impl FunctionBody {
+    fn new() -> FunctionBody {
+        FunctionBody {
+            allocas: Vec::new(),
+            lines: Vec::new(),
+            locals: Vec::new(),
+            next_temporary: 0,
+            next_label: 0,
+            block: String::from("entry"),
+        }
+    }
+
+    fn push(&mut self, instruction: String) {
+        self.lines.push(format!("  {}", instruction));
+    }
+
+    fn temporary(&mut self) -> String {
+        self.next_temporary += 1;
+        format!("%t{}", self.next_temporary - 1)
+    }
+
+    fn label(&mut self) -> String {
+        self.next_label += 1;
+        format!("L{}", self.next_label - 1)
+    }
+
+    fn start_block(&mut self, label: String) {
+        self.lines.push(format!("{}:", label));
+        self.block = label;
+    }
+
+    fn declare_local(&mut self, name: &str, t: Type) -> String {
+        let slot = format!("%{}.{}", name, self.allocas.len());
+        self.allocas
+            .push(format!("  {} = alloca {}", slot, llvm_type(&t)));
+        self.locals.push((String::from(name), slot.clone(), t));
+        slot
+    }
+
+    fn enter_scope(&self) -> usize {
+        self.locals.len()
+    }
+
+    fn leave_scope(&mut self, scope: usize) {
+        self.locals.truncate(scope);
+    }
+
+    fn local(&self, name: &str) -> &(String, String, Type) {
+        self.locals
+            .iter()
+            .rfind(|(local, _, _)| local == name)
+            .unwrap_or_else(|| panic!("Tried to use an undefined name {}", name))
+    }
+}
+
+// Signatures of the functions defined in the program and the functions it
+// calls without defining them, which get declared as external.
+#[derive(Debug, Default)]
+struct Module {
+    signatures: BTreeMap<String, Type>,
+    declarations: BTreeMap<String, String>,
+    uses_bounds_error: bool,
+    uses_free: bool,
+    // name and initializer of the constant arrays
+    constant_arrays: Vec<(String, String)>,
+}
+
+impl Module {
+    // Global holding the array, identical arrays share one.
+    fn constant_array(&mut self, layout: &str, values: &[i32]) -> String {
+        let elements: Vec<String> = values
+            .iter()
+            .map(|value| format!("i32 {}", value))
+            .collect();
+        let initializer = format!(
+            "{} {{ i32 {}, [{} x i32] [{}] }}",
+            layout,
+            values.len(),
+            values.len(),
+            elements.join(", ")
+        );
+        if let Some((name, _)) = self
+            .constant_arrays
+            .iter()
+            .find(|(_, other)| *other == initializer)
+        {
+            return name.clone();
+        }
+        let name = format!("@.array.{}", self.constant_arrays.len());
+        self.constant_arrays.push((name.clone(), initializer));
+        name
+    }
+}
+
+impl LlvmGenerator {
+    pub fn new(ast: Ast) -> LlvmGenerator {
+        LlvmGenerator {
+            constant_arrays_allowed: !codegen::deletes_arrays(&ast),
+            exported: codegen::exported_functions(&ast),
+            ast,
+            options: CodegenOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: CodegenOptions) -> LlvmGenerator {
+        self.options = options;
+        self
+    }
+
+    /// Generates the whole module.
+    pub fn generate(&self) -> String {
+        let mut module = Module::default();
+        for (name, function_type, _) in functions(&self.ast) {
+            module
+                .signatures
+                .insert(name.clone(), function_type.clone());
+        }
+        for statement in self.ast.top_level_statements() {
+            if let Ast::Extern(name, function_type) = statement {
+                module
+                    .signatures
+                    .insert(name.clone(), function_type.clone());
+                module
+                    .declarations
+                    .insert(name.clone(), declaration(name, function_type));
+            }
+        }
+
+        let mut definitions = String::new();
+        for (name, function_type, body) in functions(&self.ast) {
+            definitions.push_str(&self.generate_function(name, function_type, body, &mut module));
+        }
+
+        let mut output = String::from("declare ptr @malloc(i64)\n");
+        if module.uses_free {
+            output.push_str("declare void @free(ptr)\n");
+        }
+        for declaration in module.declarations.values() {
+            output.push_str(declaration);
+            output.push('\n');
+        }
+        for (name, value) in codegen::global_variables(&self.ast) {
+            output.push_str(&format!(
+                "@{} = internal global i32 {}\n",
+                mangle::global(name),
+                value
+            ));
+        }
+        for (name, initializer) in &module.constant_arrays {
+            output.push_str(&format!(
+                "{} = private unnamed_addr constant {}\n",
+                name, initializer
+            ));
+        }
+        output.push_str(&definitions);
+        if module.uses_bounds_error {
+            output.push_str(BOUNDS_ERROR_ROUTINE);
+        }
+        output
+    }
+
+    fn generate_function(
+        &self,
+        name: &str,
+        function_type: &Type,
+        body: &Ast,
+        module: &mut Module,
+    ) -> String {
+        let (parameters, return_type) = match function_type {
+            Type::Function {
+                parameter_types,
+                return_type,
+            } => (parameter_types, return_type.as_ref()),
+            _ => unreachable!(),
+        };
+
+        let mut function = FunctionBody::new();
+        // globals are constants initialized `i32`s, shadowed by the locals
+        for (global, _) in codegen::global_variables(&self.ast) {
+            function.locals.push((
+                String::from(global),
+                format!("@{}", mangle::global(global)),
+                Type::Number,
+            ));
+        }
+        let mut arguments = Vec::new();
+        for (parameter, t) in parameters {
+            let slot = function.declare_local(parameter, t.clone());
+            function.push(format!(
+                "store {} %{}, ptr {}",
+                llvm_type(t),
+                parameter,
+                slot
+            ));
+            arguments.push(format!("{} %{}", llvm_type(t), parameter));
+        }
+        self.emit_statement(body, return_type, &mut function, module);
+        // Functions falling off the end return 0, like on ARM.
+        function.push(format!(
+            "ret {} {}",
+            llvm_type(return_type),
+            zero(return_type)
+        ));
+
+        let mut output = format!(
+            "\ndefine {}{} @{}({}) {{\nentry:\n",
+            if self.exported.contains(name) {
+                ""
+            } else {
+                "internal "
+            },
+            llvm_type(return_type),
+            name,
+            arguments.join(", ")
+        );
+        for line in function.allocas.iter().chain(&function.lines) {
+            output.push_str(line);
+            output.push('\n');
+        }
+        output.push_str("}\n");
+        output
+    }
+
+    fn emit_in_scope(
+        &self,
+        ast: &Ast,
+        return_type: &Type,
+        function: &mut FunctionBody,
+        module: &mut Module,
+    ) {
+        let scope = function.enter_scope();
+        self.emit_statement(ast, return_type, function, module);
+        function.leave_scope(scope);
+    }
+
+    fn emit_statement(
+        &self,
+        ast: &Ast,
+        return_type: &Type,
+        function: &mut FunctionBody,
+        module: &mut Module,
+    ) {
+        match ast {
+            Ast::Block(statements) => {
+                let scope = function.enter_scope();
+                for statement in statements {
+                    self.emit_statement(statement, return_type, function, module);
+                }
+                function.leave_scope(scope);
+            }
+            Ast::Return(expr) => {
+                let (value, _) = self.emit_expression(expr, function, module);
+                function.push(format!("ret {} {}", llvm_type(return_type), value));
+                // anything after the return is unreachable but still needs
+                // a block to live in
+                let label = function.label();
+                function.start_block(label);
+            }
+            Ast::Delete(array) => {
+                let (value, _) = self.emit_expression(array, function, module);
+                function.push(format!("call void @free(ptr {})", value));
+                module.uses_free = true;
+            }
+            Ast::If(condition, consequence, alternative) => {
+                let condition = self.emit_condition(condition, function, module);
+                let (then_label, else_label, end_label) =
+                    (function.label(), function.label(), function.label());
+                function.push(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    condition, then_label, else_label
+                ));
+                function.start_block(then_label);
+                self.emit_in_scope(consequence, return_type, function, module);
+                function.push(format!("br label %{}", end_label));
+                function.start_block(else_label);
+                self.emit_in_scope(alternative, return_type, function, module);
+                function.push(format!("br label %{}", end_label));
+                function.start_block(end_label);
+            }
+            Ast::While(condition, block) => {
+                let (condition_label, body_label, end_label) =
+                    (function.label(), function.label(), function.label());
+                function.push(format!("br label %{}", condition_label));
+                function.start_block(condition_label.clone());
+                let condition = self.emit_condition(condition, function, module);
+                function.push(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    condition, body_label, end_label
+                ));
+                function.start_block(body_label);
+                self.emit_in_scope(block, return_type, function, module);
+                function.push(format!("br label %{}", condition_label));
+                function.start_block(end_label);
+            }
+            Ast::Var(name, expr) => {
+                let (value, t) = self.emit_expression(expr, function, module);
+                let slot = function.declare_local(name, t.clone());
+                function.push(format!("store {} {}, ptr {}", llvm_type(&t), value, slot));
+            }
+            Ast::Assignment(name, expr) => {
+                let (value, t) = self.emit_expression(expr, function, module);
+                let (_, slot, _) = function.local(name);
+                let instruction = format!("store {} {}, ptr {}", llvm_type(&t), value, slot);
+                function.push(instruction);
+            }
+            Ast::Const(_, _) | Ast::StaticAssert(_, _) | Ast::Doc(_) => {}
+            Ast::Function(_, _, _) | Ast::Import(_, _) | Ast::Export(_) | Ast::Extern(_, _) => {
+                panic!("Functions can only be defined at the top level")
+            }
+            expr => {
+                self.emit_expression(expr, function, module);
+            }
+        }
+    }
+
+    fn emit_condition(
+        &self,
+        ast: &Ast,
+        function: &mut FunctionBody,
+        module: &mut Module,
+    ) -> String {
+        let (value, _) = self.emit_expression(ast, function, module);
+        let condition = function.temporary();
+        function.push(format!("{} = icmp ne i32 {}, 0", condition, value));
+        condition
+    }
+
+    // Returns the operand holding the value of the expression and its type.
+    // With a stack of its own, as a chain of operators is as deep as it is
+    // long: the operands are emitted first, in order.
+    fn emit_expression(
+        &self,
+        expression: &Ast,
+        function: &mut FunctionBody,
+        module: &mut Module,
+    ) -> (String, Type) {
+        enum Step<'a> {
+            Enter(&'a Ast),
+            Exit(&'a Ast),
+        }
+        let mut steps = vec![Step::Enter(expression)];
+        let mut values = Vec::new();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Enter(ast) => {
+                    steps.push(Step::Exit(ast));
+                    steps.extend(ast.children().into_iter().rev().map(Step::Enter));
+                }
+                Step::Exit(ast) => {
+                    let operands = values.split_off(values.len() - ast.children().len());
+                    values.push(self.emit_operation(ast, operands, function, module));
+                }
+            }
+        }
+        values.pop().expect("an expression has a value")
+    }
+
+    // The operand holding the value of the expression and its type, given
+    // the ones of its operands.
+    fn emit_operation(
+        &self,
+        ast: &Ast,
+        operands: Vec<(String, Type)>,
+        function: &mut FunctionBody,
+        module: &mut Module,
+    ) -> (String, Type) {
+        let mut operands = operands.into_iter();
+        let mut operand = || operands.next().expect("the operation has the operand");
+        match ast {
+            Ast::Null => (String::from("0"), Type::Void),
+            Ast::Undefined => (String::from("0"), Type::Undefined),
+            Ast::Number(value) => (value.to_string(), Type::Number),
+            // float constants are written as the bits of the same double
+            Ast::Float(value) => (
+                format!("0x{:016X}", f64::from(*value).to_bits()),
+                Type::Float,
+            ),
+            Ast::Bool(value) => ((*value as i32).to_string(), Type::Boolean),
+            // strings cannot be deleted, so they are always constant
+            Ast::String(text) => {
+                let values = codegen::character_codes(text);
+                let layout = array_layout(&Type::Number, values.len());
+                (module.constant_array(&layout, &values), Type::String)
+            }
+            Ast::Identifier(name) => {
+                let (_, slot, t) = function.local(name).clone();
+                let value = function.temporary();
+                function.push(format!("{} = load {}, ptr {}", value, llvm_type(&t), slot));
+                (value, t)
+            }
+            Ast::ArrayLiteral(elements) => {
+                let values: Vec<(String, Type)> = elements.iter().map(|_| operand()).collect();
+                let element_type = values
+                    .last()
+                    .map(|(_, t)| t.clone())
+                    .unwrap_or(Type::Number);
+                let layout = array_layout(&element_type, values.len());
+                let constant =
+                    codegen::constant_elements(elements).filter(|_| self.constant_arrays_allowed);
+                if let Some(constants) = constant {
+                    let array = module.constant_array(&layout, &constants);
+                    let t = Type::Array {
+                        element_type: Box::new(element_type),
+                    };
+                    return (array, t);
+                }
+                let array = function.temporary();
+                function.push(format!(
+                    "{} = call ptr @malloc(i64 ptrtoint (ptr getelementptr ({}, ptr null, i32 1) to i64))",
+                    array, layout
+                ));
+                function.push(format!("store i32 {}, ptr {}", values.len(), array));
+                for (i, (value, t)) in values.iter().enumerate() {
+                    let element = function.temporary();
+                    function.push(format!(
+                        "{} = getelementptr {}, ptr {}, i32 0, i32 1, i32 {}",
+                        element, layout, array, i
+                    ));
+                    function.push(format!("store {} {}, ptr {}", llvm_type(t), value, element));
+                }
+                (
+                    array,
+                    Type::Array {
+                        element_type: Box::new(element_type),
+                    },
+                )
+            }
+            Ast::ArrayLookup(_, _) => {
+                let (array, array_type) = operand();
+                let (index, _) = operand();
+                let element_type = match array_type {
+                    Type::Array { element_type } => *element_type,
+                    Type::String => Type::Number,
+                    other => panic!("Expected an array, but got {}", other),
+                };
+                let layout = array_layout(&element_type, 0);
+                let t = llvm_type(&element_type);
+                let load = |function: &mut FunctionBody| {
+                    let element = function.temporary();
+                    function.push(format!(
+                        "{} = getelementptr {}, ptr {}, i32 0, i32 1, i32 {}",
+                        element, layout, array, index
+                    ));
+                    let loaded = function.temporary();
+                    function.push(format!("{} = load {}, ptr {}", loaded, t, element));
+                    loaded
+                };
+                if self.options.bounds_check == BoundsCheck::Off {
+                    return (load(function), element_type);
+                }
+
+                let length = function.temporary();
+                function.push(format!("{} = load i32, ptr {}", length, array));
+                let in_bounds = function.temporary();
+                function.push(format!(
+                    "{} = icmp ult i32 {}, {}",
+                    in_bounds, index, length
+                ));
+                let (load_label, end_label) = (function.label(), function.label());
+                let check_block = function.block.clone();
+                function.push(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    in_bounds, load_label, end_label
+                ));
+                if self.options.bounds_check == BoundsCheck::Trap {
+                    module.uses_bounds_error = true;
+                    function.start_block(end_label);
+                    function.push(String::from("call void @__rtsc_bounds_error()"));
+                    function.push(String::from("unreachable"));
+                    function.start_block(load_label);
+                    return (load(function), element_type);
+                }
+
+                function.start_block(load_label.clone());
+                let loaded = load(function);
+                function.push(format!("br label %{}", end_label));
+                function.start_block(end_label);
+                // out of bounds lookups give zero
+                let value = function.temporary();
+                function.push(format!(
+                    "{} = phi {} [ {}, %{} ], [ {}, %{} ]",
+                    value,
+                    t,
+                    loaded,
+                    load_label,
+                    zero(&element_type),
+                    check_block
+                ));
+                (value, element_type)
+            }
+            Ast::ArrayLength(_) => {
+                let (array, _) = operand();
+                let length = function.temporary();
+                function.push(format!("{} = load i32, ptr {}", length, array));
+                (length, Type::Number)
+            }
+            Ast::Not(_) => {
+                let (value, _) = operand();
+                let condition = function.temporary();
+                function.push(format!("{} = icmp eq i32 {}, 0", condition, value));
+                let result = function.temporary();
+                function.push(format!("{} = zext i1 {} to i32", result, condition));
+                (result, Type::Boolean)
+            }
+            Ast::Equal(_, _) | Ast::NotEqual(_, _) => {
+                let (lhs, t) = operand();
+                let (rhs, _) = operand();
+                let predicate = if let Ast::Equal(_, _) = ast {
+                    "eq"
+                } else {
+                    "ne"
+                };
+                let condition = function.temporary();
+                function.push(format!(
+                    "{} = icmp {} {} {}, {}",
+                    condition,
+                    predicate,
+                    llvm_type(&t),
+                    lhs,
+                    rhs
+                ));
+                let result = function.temporary();
+                function.push(format!("{} = zext i1 {} to i32", result, condition));
+                (result, Type::Boolean)
+            }
+            Ast::Addition(_, _)
+            | Ast::Subtraction(_, _)
+            | Ast::Multiplication(_, _)
+            | Ast::Division(_, _) => {
+                let (lhs, _) = operand();
+                let (rhs, _) = operand();
+                let operation = match ast {
+                    Ast::Addition(_, _) => "add",
+                    Ast::Subtraction(_, _) => "sub",
+                    Ast::Multiplication(_, _) => "mul",
+                    _ => match self.options.arithmetic {
+                        Arithmetic::Signed => "sdiv",
+                        Arithmetic::Unsigned => "udiv",
+                    },
+                };
+                let result = function.temporary();
+                function.push(format!("{} = {} i32 {}, {}", result, operation, lhs, rhs));
+                (result, Type::Number)
+            }
+            Ast::Call(name, _) if codegen::FLOAT_OPERATIONS.contains(&name.as_str()) => {
+                let (lhs, _) = operand();
+                let (rhs, _) = operand();
+                if name == codegen::FLOAT_EQUAL {
+                    // ordered, NaN equals nothing
+                    let condition = function.temporary();
+                    let result = function.temporary();
+                    function.push(format!("{} = fcmp oeq float {}, {}", condition, lhs, rhs));
+                    function.push(format!("{} = zext i1 {} to i32", result, condition));
+                    return (result, Type::Boolean);
+                }
+                let operation = match name.as_str() {
+                    codegen::FLOAT_ADD => "fadd",
+                    codegen::FLOAT_SUBTRACT => "fsub",
+                    codegen::FLOAT_MULTIPLY => "fmul",
+                    _ => "fdiv",
+                };
+                let result = function.temporary();
+                function.push(format!("{} = {} float {}, {}", result, operation, lhs, rhs));
+                (result, Type::Float)
+            }
+            Ast::Call(name, _) if name == codegen::ABS_INTRINSIC => {
+                let (value, _) = operand();
+                let negated = function.temporary();
+                let condition = function.temporary();
+                let result = function.temporary();
+                function.push(format!("{} = sub i32 0, {}", negated, value));
+                function.push(format!("{} = icmp slt i32 {}, 0", condition, value));
+                function.push(format!(
+                    "{} = select i1 {}, i32 {}, i32 {}",
+                    result, condition, negated, value
+                ));
+                (result, Type::Number)
+            }
+            // rotating is a funnel shift of the number with itself
+            Ast::Call(name, _)
+                if name == codegen::CLZ_INTRINSIC
+                    || name == runtime::POPCOUNT_ROUTINE
+                    || name == codegen::ROTATE_INTRINSIC =>
+            {
+                let (value, _) = operand();
+                let (intrinsic, parameters, arguments) = match name.as_str() {
+                    codegen::CLZ_INTRINSIC => (
+                        "llvm.ctlz.i32",
+                        "i32, i1",
+                        format!("i32 {}, i1 false", value),
+                    ),
+                    runtime::POPCOUNT_ROUTINE => {
+                        ("llvm.ctpop.i32", "i32", format!("i32 {}", value))
+                    }
+                    _ => {
+                        let (amount, _) = operand();
+                        (
+                            "llvm.fshr.i32",
+                            "i32, i32, i32",
+                            format!("i32 {}, i32 {}, i32 {}", value, value, amount),
+                        )
+                    }
+                };
+                module.declarations.insert(
+                    String::from(intrinsic),
+                    format!("declare i32 @{}({})", intrinsic, parameters),
+                );
+                let result = function.temporary();
+                function.push(format!(
+                    "{} = call i32 @{}({})",
+                    result, intrinsic, arguments
+                ));
+                (result, Type::Number)
+            }
+            Ast::Call(name, _) if codegen::INTRINSICS.contains(&name.as_str()) => {
+                let (lhs, _) = operand();
+                let (rhs, _) = operand();
+                let comparison = if name == codegen::MIN_INTRINSIC {
+                    "slt"
+                } else {
+                    "sgt"
+                };
+                let condition = function.temporary();
+                let result = function.temporary();
+                function.push(format!(
+                    "{} = icmp {} i32 {}, {}",
+                    condition, comparison, lhs, rhs
+                ));
+                function.push(format!(
+                    "{} = select i1 {}, i32 {}, i32 {}",
+                    result, condition, lhs, rhs
+                ));
+                (result, Type::Number)
+            }
+            Ast::Call(name, args) => {
+                let arguments: Vec<String> = args
+                    .iter()
+                    .map(|_| {
+                        let (value, t) = operand();
+                        format!("{} {}", llvm_type(&t), value)
+                    })
+                    .collect();
+                let return_type = match module.signatures.get(name) {
+                    Some(Type::Function { return_type, .. }) => *return_type.clone(),
+                    _ => {
+                        let parameters: Vec<&str> = arguments
+                            .iter()
+                            .map(|argument| argument.split(' ').next().unwrap())
+                            .collect();
+                        module.declarations.insert(
+                            name.clone(),
+                            format!("declare i32 @{}({})", name, parameters.join(", ")),
+                        );
+                        Type::Number
+                    }
+                };
+                let result = function.temporary();
+                function.push(format!(
+                    "{} = call {} @{}({})",
+                    result,
+                    llvm_type(&return_type),
+                    name,
+                    arguments.join(", ")
+                ));
+                (result, return_type)
+            }
+            statement => unreachable!("{:?} is not an expression", statement),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::phases::typecheck;
+    use linked_hash_map::LinkedHashMap;
+
+    fn generate(source: &str) -> String {
+        LlvmGenerator::new(parser::parse_program(source).unwrap()).generate()
+    }
+
+    #[test]
+    fn function() {
+        assert_eq!(
+            generate("export function add(x: number, y: number) { return x + y; }"),
+            "declare ptr @malloc(i64)
+
+define i32 @add(i32 %x, i32 %y) {
+entry:
+  %x.0 = alloca i32
+  %y.1 = alloca i32
+  store i32 %x, ptr %x.0
+  store i32 %y, ptr %y.1
+  %t0 = load i32, ptr %x.0
+  %t1 = load i32, ptr %y.1
+  %t2 = add i32 %t0, %t1
+  ret i32 %t2
+L0:
+  ret i32 0
+}
+"
+        );
+    }
+
+    #[test]
+    fn floats() {
+        let mut ast =
+            parser::parse_program("function f(x: float): boolean { return x * 0.5 == x; }")
+                .unwrap();
+        typecheck::lower_typed_operations(&mut ast, LinkedHashMap::new()).unwrap();
+        let module = LlvmGenerator::new(ast).generate();
+        assert!(module.contains("define internal i32 @f(float %x) {"));
+        assert!(module.contains("  %t1 = fmul float %t0, 0x3FE0000000000000\n"));
+        assert!(module.contains("  %t3 = fcmp oeq float %t1, %t2\n"));
+    }
+
+    #[test]
+    fn extern_functions_are_declared() {
+        let module = generate(
+            "extern function sum(xs: array<number>, n: number): number;
+            function f() { return sum([1, 2], 2); }",
+        );
+        assert!(module.contains("declare i32 @sum(ptr, i32)\n"));
+        assert!(!module.contains("define i32 @sum"));
+    }
+
+    #[test]
+    fn undefined_functions_are_declared() {
+        let module = generate("function f() { putchar(46); }");
+        assert!(module.contains("declare i32 @putchar(i32)\n"));
+        assert!(module.contains("  %t0 = call i32 @putchar(i32 46)\n"));
+    }
+
+    #[test]
+    fn loops() {
+        let module = generate("function f() { var i = 0; while (i != 3) { i = i + 1; } }");
+        assert!(module.contains(
+            "  br label %L0
+L0:
+  %t0 = load i32, ptr %i.0
+  %t1 = icmp ne i32 %t0, 3
+  %t2 = zext i1 %t1 to i32
+  %t3 = icmp ne i32 %t2, 0
+  br i1 %t3, label %L1, label %L2
+L1:
+"
+        ));
+    }
+
+    #[test]
+    fn arrays() {
+        let module = generate("function f(x) { var a = [x]; return a[0]; }");
+        assert!(module.contains("  %a.1 = alloca ptr\n"));
+        assert!(module.contains(
+            "  %t1 = call ptr @malloc(i64 ptrtoint (ptr getelementptr ({ i32, [1 x i32] }, ptr null, i32 1) to i64))\n"
+        ));
+        assert!(module.contains("  %t8 = phi i32 [ %t7, %L0 ], [ 0, %entry ]\n"));
+    }
+
+    #[test]
+    fn constant_arrays() {
+        let module = generate("function f() { var a = [1, 2]; var b = [1, 2]; }");
+        assert!(module.contains(
+            "@.array.0 = private unnamed_addr constant { i32, [2 x i32] } { i32 2, [2 x i32] [i32 1, i32 2] }\n"
+        ));
+        assert!(
+            module.contains("  store ptr @.array.0, ptr %a.0\n  store ptr @.array.0, ptr %b.1\n")
+        );
+    }
+
+    #[test]
+    fn delete() {
+        let module = generate("function f() { var a = [7]; delete a; }");
+        assert!(module.starts_with("declare ptr @malloc(i64)\ndeclare void @free(ptr)\n"));
+        assert!(module.contains("  %t2 = load ptr, ptr %a.0\n  call void @free(ptr %t2)\n"));
+    }
+
+    #[test]
+    fn globals() {
+        let module = generate("var count = 3; function f() { count = count + 1; }");
+        assert!(module.contains("declare ptr @malloc(i64)\n@_T.5count = internal global i32 3\n"));
+        assert!(module.contains(
+            "  %t0 = load i32, ptr @_T.5count
+  %t1 = add i32 %t0, 1
+  store i32 %t1, ptr @_T.5count
+"
+        ));
+    }
+
+    #[test]
+    fn division() {
+        let source = "function f(x: number) { return x / 2; }";
+        assert!(generate(source).contains("  %t1 = sdiv i32 %t0, 2\n"));
+        let ast = parser::parse_program(source).unwrap();
+        let module = LlvmGenerator::new(ast)
+            .with_options(CodegenOptions {
+                arithmetic: Arithmetic::Unsigned,
+                ..CodegenOptions::default()
+            })
+            .generate();
+        assert!(module.contains("  %t1 = udiv i32 %t0, 2\n"));
+    }
+
+    #[test]
+    fn trapping_bounds_check() {
+        let ast = parser::parse_program("function f() { var xs = [1]; return xs[2]; }").unwrap();
+        let module = LlvmGenerator::new(ast)
+            .with_options(CodegenOptions {
+                bounds_check: BoundsCheck::Trap,
+                ..CodegenOptions::default()
+            })
+            .generate();
+        assert!(module.contains(
+            "L1:
+  call void @__rtsc_bounds_error()
+  unreachable
+L0:
+"
+        ));
+        assert!(module.contains("define internal void @__rtsc_bounds_error() noreturn {"));
+    }
+}
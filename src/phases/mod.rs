@@ -1,2 +1,28 @@
+pub mod branches;
+pub mod callgraph;
+pub mod cfg;
 pub mod codegen;
+pub mod const_eval;
+pub mod copy_propagation;
+pub mod coverage;
+pub mod division;
+pub mod dominators;
+pub mod elf;
+pub mod gc;
+pub mod instruction;
+pub mod interpreter;
+pub mod ir;
+pub mod lint;
+pub mod liveness;
+pub mod llvm;
+pub mod namespaces;
+pub mod nested;
+pub mod passes;
+pub mod profile;
+pub mod runtime;
+pub mod schedule;
+pub mod target;
+pub mod thumb;
 pub mod typecheck;
+pub mod unroll;
+pub mod wasm;
@@ -0,0 +1,129 @@
+//! Functions grouped under a name with `namespace Foo { ... }`.
+//!
+//! Inside of the namespace its functions call each other by their own
+//! names, outside of it the module calls the exported ones as `Foo.bar()`.
+//! The namespaces only exist in the module declaring them: the typechecker
+//! resolves the qualified names, and before the modules are linked `flatten`
+//! moves the functions to the top level under mangled symbols, see
+//! `crate::mangle`, which the linker does not see outside of the object.
+use std::collections::BTreeMap;
+
+use crate::ast::Ast;
+use crate::lines;
+use crate::mangle;
+
+/// Replaces the namespaces of the module with their functions, renamed to
+/// their mangled symbols, and the calls to them with calls to the symbols.
+/// Gives for every statement of the module afterwards, in the order of
+/// `lines::statements`, the index of the statement it was before.
+pub fn flatten(program: &mut Ast, module: &str) -> Vec<usize> {
+    let statements = match program {
+        Ast::Block(statements) => statements,
+        program => return (0..lines::statements(program).len()).collect(),
+    };
+    let mut origins = Vec::new();
+    let mut next = 0;
+    let mut qualified = BTreeMap::new();
+    for statement in std::mem::take(statements) {
+        let (namespace, members) = match statement {
+            Ast::Namespace(namespace, members) => (namespace, members),
+            statement => {
+                let count = count(&statement);
+                origins.extend(next..next + count);
+                next += count;
+                statements.push(statement);
+                continue;
+            }
+        };
+        // the namespaces and the `export`s of their functions disappear
+        next += 1;
+        let mut names = BTreeMap::new();
+        for member in &members {
+            let (function, exported) = match member {
+                Ast::Export(function) => (function.as_ref(), true),
+                member => (member, false),
+            };
+            if let Ast::Function(name, _, _) = function {
+                let symbol = mangle::mangle(&[module, &namespace, name], 1);
+                if exported {
+                    qualified.insert(format!("{}.{}", namespace, name), symbol.clone());
+                }
+                names.insert(name.clone(), symbol);
+            }
+        }
+        for member in members {
+            let mut member = match member {
+                Ast::Export(function) => {
+                    next += 1;
+                    *function
+                }
+                member => member,
+            };
+            let count = count(&member);
+            origins.extend(next..next + count);
+            next += count;
+            if let Ast::Function(name, _, body) = &mut member {
+                *name = names[name.as_str()].clone();
+                rename_calls(body, &names);
+            }
+            statements.push(member);
+        }
+    }
+    rename_calls(program, &qualified);
+    origins
+}
+
+// The number of statements `lines::statements` visits for the statement.
+fn count(statement: &Ast) -> usize {
+    let statements = lines::statements(statement).len();
+    match statement {
+        // taken for the program
+        Ast::Block(_) => statements + 1,
+        _ => statements,
+    }
+}
+
+fn rename_calls(ast: &mut Ast, symbols: &BTreeMap<String, String>) {
+    // a chain of operators is as deep as it is long
+    let mut nodes = vec![ast];
+    while let Some(node) = nodes.pop() {
+        if let Ast::Call(name, _) = node {
+            if let Some(symbol) = symbols.get(name.as_str()) {
+                *name = symbol.clone();
+            }
+        }
+        nodes.extend(node.children_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn flattened() {
+        let source = "namespace geometry {
+    function square(x: number): number { return x * x; }
+    export function area(x: number): number { return square(x); }
+}
+function main(): number { return geometry.area(2); }";
+        let mut program = parser::parse_program(source).unwrap();
+        let origins = flatten(&mut program, "m");
+        let functions: Vec<String> = program
+            .declarations()
+            .map(|function| function.to_string())
+            .collect();
+        assert_eq!(
+            functions,
+            [
+                "function _T.1m8geometry6square(x: number): number {\n    return x * x;\n}",
+                "function _T.1m8geometry4area(x: number): number {\n    return _T.1m8geometry6square(x);\n}",
+                "function main(): number {\n    return _T.1m8geometry4area(2);\n}",
+            ]
+        );
+        // without the namespace at 0 and the `export` at 4
+        assert_eq!(origins, [1, 2, 3, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(origins.len(), lines::statements(&program).len());
+    }
+}
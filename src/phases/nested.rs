@@ -0,0 +1,379 @@
+//! Functions declared inside of other functions.
+//!
+//! A nested function is visible from its declaration to the end of the
+//! block declaring it, and like every function it only sees the globals, its
+//! parameters and the functions in scope, never the locals of the functions
+//! around it. The generators only know top level functions, so `mangle`
+//! first gives every nested function a symbol of its own, named after the
+//! module and the functions around it, see `crate::mangle`. Once the program
+//! is typechecked `hoist` moves them to the top level, where they become
+//! symbols the linker does not see outside of the object, as they are not
+//! exported.
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast::Ast;
+use crate::lines;
+use crate::mangle;
+
+/// Renames the functions of the module other modules cannot call, the
+/// nested ones and the top level ones which are not exported except for
+/// `main`, and the calls to them to their mangled symbols. No function of
+/// the program then takes the symbol of another module's function or of
+/// one the generated code calls, such as `malloc`.
+pub fn mangle(program: &mut Ast, module: &str) {
+    let mut names: BTreeSet<String> = program
+        .declarations()
+        .filter_map(|declaration| match declaration {
+            Ast::Function(name, _, _) | Ast::Extern(name, _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let top_level: BTreeMap<String, String> = program
+        .top_level_statements()
+        .iter()
+        .filter_map(|statement| match statement {
+            Ast::Function(name, _, _) if name != "main" => {
+                Some((name.clone(), mangle::mangle(&[module, name], 1)))
+            }
+            _ => None,
+        })
+        .collect();
+    names.extend(top_level.values().cloned());
+    let statements = match program {
+        Ast::Block(statements) => statements.iter_mut().collect(),
+        statement => vec![statement],
+    };
+    for statement in statements {
+        match statement {
+            Ast::Namespace(namespace, members) => {
+                // the functions of the namespace call each other by their
+                // names, which `namespaces::flatten` resolves
+                let own: BTreeMap<String, String> = members
+                    .iter()
+                    .map(|member| match member {
+                        Ast::Export(function) => function.as_ref(),
+                        member => member,
+                    })
+                    .filter_map(|function| match function {
+                        Ast::Function(name, _, _) => Some((name.clone(), name.clone())),
+                        _ => None,
+                    })
+                    .collect();
+                for member in members {
+                    let mut enclosing = vec![module.to_owned(), namespace.clone()];
+                    let mut scopes = vec![top_level.clone(), own.clone()];
+                    rename_body(member, &mut enclosing, &mut scopes, &mut names);
+                }
+            }
+            statement => {
+                let mut scopes = vec![top_level.clone()];
+                rename_body(
+                    statement,
+                    &mut vec![module.to_owned()],
+                    &mut scopes,
+                    &mut names,
+                );
+                if let Ast::Function(name, _, _) = statement {
+                    if let Some(mangled) = top_level.get(name) {
+                        *name = mangled.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Renames the functions nested in the top level function, which may be
+// exported, declared in the last of `enclosing`.
+fn rename_body(
+    statement: &mut Ast,
+    enclosing: &mut Vec<String>,
+    scopes: &mut Vec<BTreeMap<String, String>>,
+    names: &mut BTreeSet<String>,
+) {
+    let function = match statement {
+        Ast::Export(function) => function.as_mut(),
+        statement => statement,
+    };
+    if let Ast::Function(name, _, body) = function {
+        enclosing.push(name.clone());
+        rename(body, enclosing, scopes, names);
+        enclosing.pop();
+    }
+}
+
+// Renames the functions declared in the statement of the last of the
+// `enclosing` functions, which follow the module, and the calls to the
+// functions in `scopes`, the innermost last. With a stack of its own, as a
+// chain of operators is as deep as it is long, which leaves the scopes and
+// the functions to the steps after the statements inside of them.
+fn rename(
+    ast: &mut Ast,
+    enclosing: &mut Vec<String>,
+    scopes: &mut Vec<BTreeMap<String, String>>,
+    names: &mut BTreeSet<String>,
+) {
+    enum Step<'a> {
+        Visit(&'a mut Ast),
+        LeaveScope,
+        LeaveFunction,
+    }
+    let mut steps = vec![Step::Visit(ast)];
+    while let Some(step) = steps.pop() {
+        let ast = match step {
+            Step::Visit(ast) => ast,
+            Step::LeaveScope => {
+                scopes.pop();
+                continue;
+            }
+            Step::LeaveFunction => {
+                enclosing.pop();
+                continue;
+            }
+        };
+        match ast {
+            Ast::Block(statements) => {
+                scopes.push(BTreeMap::new());
+                steps.push(Step::LeaveScope);
+                steps.extend(statements.iter_mut().rev().map(Step::Visit));
+            }
+            Ast::Function(name, _, body) => {
+                enclosing.push(name.clone());
+                let components: Vec<&str> = enclosing.iter().map(String::as_str).collect();
+                let mut number = 1;
+                let mut mangled = mangle::mangle(&components, number);
+                while names.contains(&mangled) {
+                    number += 1;
+                    mangled = mangle::mangle(&components, number);
+                }
+                names.insert(mangled.clone());
+                // visible in its own body, for recursion
+                if let Some(scope) = scopes.last_mut() {
+                    scope.insert(name.clone(), mangled.clone());
+                }
+                *name = mangled;
+                scopes.push(BTreeMap::new());
+                steps.push(Step::LeaveFunction);
+                steps.push(Step::LeaveScope);
+                steps.push(Step::Visit(body));
+            }
+            Ast::Call(name, arguments) => {
+                if let Some(mangled) = scopes.iter().rev().find_map(|scope| scope.get(name)) {
+                    *name = mangled.clone();
+                }
+                steps.extend(arguments.iter_mut().rev().map(Step::Visit));
+            }
+            ast => steps.extend(ast.children_mut().into_iter().rev().map(Step::Visit)),
+        }
+    }
+}
+
+/// Moves the nested functions to the end of the program, the outer ones
+/// first. Gives for every statement of the program afterwards, in the
+/// order of `lines::statements`, the index of the statement it was before,
+/// so that the lines of the statements found in the source still apply.
+/// A function which was the branch of an `if` or the body of a `while`
+/// leaves an empty block behind, with the line of the function.
+pub fn hoist(program: &mut Ast) -> Vec<usize> {
+    let statements = match program {
+        Ast::Block(statements) => statements,
+        program => return (0..lines::statements(program).len()).collect(),
+    };
+    let mut hoist = Hoist {
+        next: 0,
+        functions: Vec::new(),
+    };
+    let mut origins = Vec::new();
+    for statement in statements.iter_mut() {
+        hoist.top_level(statement, &mut origins);
+    }
+    for (function, function_origins) in hoist.functions {
+        statements.push(function);
+        origins.extend(function_origins);
+    }
+    origins
+}
+
+struct Hoist {
+    // the index of the next statement in the order of `lines::statements`
+    next: usize,
+    // the nested functions in the order of their declarations, with the
+    // indices of their statements
+    functions: Vec<(Ast, Vec<usize>)>,
+}
+
+impl Hoist {
+    fn top_level(&mut self, statement: &mut Ast, origins: &mut Vec<usize>) {
+        origins.push(self.next);
+        self.next += 1;
+        match statement {
+            Ast::Export(function) => self.top_level(function, origins),
+            Ast::Function(_, _, body) => self.branch(body, origins),
+            statement => self.statement(statement, origins),
+        }
+    }
+
+    // Visits the statements inside of the statement, whose own index is
+    // already taken.
+    fn statement(&mut self, ast: &mut Ast, origins: &mut Vec<usize>) {
+        match ast {
+            Ast::Block(statements) => {
+                for mut statement in std::mem::take(statements) {
+                    if let Ast::Function(_, _, _) = statement {
+                        self.function(statement);
+                        continue;
+                    }
+                    origins.push(self.next);
+                    self.next += 1;
+                    self.statement(&mut statement, origins);
+                    statements.push(statement);
+                }
+            }
+            Ast::If(_, consequence, alternative) => {
+                self.branch(consequence, origins);
+                self.branch(alternative, origins);
+            }
+            Ast::While(_, body) => self.branch(body, origins),
+            _ => {}
+        }
+    }
+
+    fn branch(&mut self, branch: &mut Ast, origins: &mut Vec<usize>) {
+        origins.push(self.next);
+        if let Ast::Function(_, _, _) = branch {
+            let function = std::mem::replace(branch, Ast::Block(Vec::new()));
+            self.function(function);
+        } else {
+            self.next += 1;
+            self.statement(branch, origins);
+        }
+    }
+
+    fn function(&mut self, mut function: Ast) {
+        // the functions nested in it follow it
+        let index = self.functions.len();
+        self.functions.push((Ast::Null, Vec::new()));
+        let mut origins = vec![self.next];
+        self.next += 1;
+        if let Ast::Function(_, _, body) = &mut function {
+            self.branch(body, &mut origins);
+        }
+        self.functions[index] = (function, origins);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    const SOURCE: &str = "export function f(x: number): number {
+    var y = 1;
+    function g(n: number): number {
+        function h(): number { return 2; }
+        return n + h();
+    }
+    if (x == 1) {
+        function g(n: number): number { return g(n); }
+        y = g(x);
+    } else {}
+    return g(y);
+}
+function h(): number { return f(1); }";
+
+    #[test]
+    fn mangled_names() {
+        let mut program = parser::parse_program(SOURCE).unwrap();
+        mangle(&mut program, "m");
+        let expected = parser::parse_program(SOURCE).unwrap();
+        let functions: Vec<String> = lines::statements(&program)
+            .into_iter()
+            .filter_map(|statement| match statement {
+                Ast::Function(name, _, _) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            functions,
+            ["f", "_T.1m1f1g", "_T.1m1f1g1h", "_T.1m1f1g_2", "_T.1m1h"]
+        );
+        let calls = |program: &Ast| -> Vec<String> {
+            fn calls(ast: &Ast, names: &mut Vec<String>) {
+                if let Ast::Call(name, _) = ast {
+                    names.push(name.clone());
+                }
+                for child in ast.children() {
+                    calls(child, names);
+                }
+            }
+            let mut names = Vec::new();
+            calls(program, &mut names);
+            names
+        };
+        assert_eq!(calls(&expected), ["h", "g", "g", "g", "f"]);
+        // the inner `g` calls itself, the top level `h` is not shadowed and
+        // keeps its symbol, the exported `f` its name
+        assert_eq!(
+            calls(&program),
+            [
+                "_T.1m1f1g1h",
+                "_T.1m1f1g_2",
+                "_T.1m1f1g_2",
+                "_T.1m1f1g",
+                "f"
+            ]
+        );
+    }
+
+    #[test]
+    fn hoisted() {
+        let mut program = parser::parse_program(SOURCE).unwrap();
+        mangle(&mut program, "m");
+        let before: Vec<String> = lines::statements(&program)
+            .into_iter()
+            .map(|statement| statement.to_string())
+            .collect();
+        let origins = hoist(&mut program);
+        let top_level: Vec<&str> = program
+            .declarations()
+            .filter_map(|declaration| match declaration {
+                Ast::Function(name, _, _) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            top_level,
+            ["f", "_T.1m1h", "_T.1m1f1g", "_T.1m1f1g1h", "_T.1m1f1g_2"]
+        );
+        let after = lines::statements(&program);
+        assert_eq!(origins.len(), after.len());
+        for (statement, origin) in after.into_iter().zip(origins) {
+            // the statements containing the functions lost them
+            if !matches!(
+                statement,
+                Ast::Block(_) | Ast::Export(_) | Ast::Function(_, _, _) | Ast::If(_, _, _)
+            ) {
+                assert_eq!(statement.to_string(), before[origin]);
+            }
+        }
+    }
+
+    #[test]
+    fn hoisted_branch() {
+        let source = "function f(c: boolean) { if (c) function g() {} else {} }";
+        let mut program = parser::parse_program(source).unwrap();
+        mangle(&mut program, "m");
+        assert_eq!(hoist(&mut program), [0, 1, 2, 3, 5, 3, 4]);
+        let functions: Vec<String> = program
+            .declarations()
+            .map(|function| function.to_string())
+            .collect();
+        assert_eq!(
+            functions,
+            [
+                "function _T.1m1f(c: boolean): number {\n    if (c) {} else {}\n}",
+                "function _T.1m1f1g(): number {}"
+            ]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! The optional passes transforming the program, picked by the `-O` level.
+//!
+//! Every pass runs from its level up, in the order of `Pass::ALL`, unless it
+//! is turned off with `--disable-pass`, which helps telling which pass
+//! broke a program. The transformations the code generators need, such as
+//! replacing `const` bindings with their values, are no passes and always
+//! run.
+
+/// The level used without `-O`, which keeps the passes run before there
+/// were levels.
+pub const DEFAULT_LEVEL: u8 = 1;
+/// The highest level, `-O` levels above it are rejected.
+pub const MAX_LEVEL: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// Replaces the constant expressions of the functions with their values,
+    /// see `const_eval::fold`.
+    ConstFold,
+    /// Replaces the loops running a few constant times with copies of their
+    /// bodies, see `unroll`.
+    UnrollLoops,
+    /// Folds the branches on constants and removes the jumps to jumps, the
+    /// code never reached and the labels nothing jumps to, see `branches`.
+    SimplifyBranches,
+    /// Reuses the registers holding the locals instead of loading them
+    /// again, see `copy_propagation`.
+    CopyPropagation,
+    /// Reorders the instructions of the basic blocks to hide the latency of
+    /// the loads, see `schedule`.
+    Schedule,
+}
+
+impl Pass {
+    /// The passes in the order they run.
+    pub const ALL: [Pass; 5] = [
+        Pass::ConstFold,
+        Pass::UnrollLoops,
+        Pass::SimplifyBranches,
+        Pass::CopyPropagation,
+        Pass::Schedule,
+    ];
+
+    /// The name of the pass on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::ConstFold => "const-fold",
+            Pass::UnrollLoops => "unroll-loops",
+            Pass::SimplifyBranches => "simplify-branches",
+            Pass::CopyPropagation => "copy-propagation",
+            Pass::Schedule => "schedule",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Pass> {
+        Pass::ALL.iter().copied().find(|pass| pass.name() == name)
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Pass::ConstFold => {
+                "Replace the constant expressions of the functions with their values"
+            }
+            Pass::UnrollLoops => "Repeat the bodies of the loops running a few constant times",
+            Pass::SimplifyBranches => "Fold the constant branches and remove the jumps to jumps",
+            Pass::CopyPropagation => {
+                "Reuse the registers holding the locals instead of loading them again"
+            }
+            Pass::Schedule => "Reorder the instructions to use the loaded values later",
+        }
+    }
+
+    /// The lowest `-O` level running the pass.
+    pub fn level(self) -> u8 {
+        match self {
+            Pass::ConstFold | Pass::SimplifyBranches | Pass::CopyPropagation => 1,
+            Pass::UnrollLoops | Pass::Schedule => 2,
+        }
+    }
+}
+
+/// The passes which run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Passes(u32);
+
+impl Passes {
+    /// The passes of the level.
+    pub fn at_level(level: u8) -> Passes {
+        Pass::ALL
+            .iter()
+            .copied()
+            .filter(|pass| pass.level() <= level)
+            .fold(Passes(0), |passes, pass| Passes(passes.0 | bit(pass)))
+    }
+
+    pub fn without(self, pass: Pass) -> Passes {
+        Passes(self.0 & !bit(pass))
+    }
+
+    pub fn contains(self, pass: Pass) -> bool {
+        self.0 & bit(pass) != 0
+    }
+
+    /// The passes in the order they run.
+    pub fn iter(self) -> impl Iterator<Item = Pass> {
+        Pass::ALL
+            .iter()
+            .copied()
+            .filter(move |pass| self.contains(*pass))
+    }
+
+    /// Lists the passes with what they do, a line each.
+    pub fn render(self) -> String {
+        let width = self.iter().map(|pass| pass.name().len()).max().unwrap_or(0);
+        self.iter()
+            .map(|pass| {
+                format!(
+                    "{:<width$}  {}\n",
+                    pass.name(),
+                    pass.description(),
+                    width = width
+                )
+            })
+            .collect()
+    }
+}
+
+impl Default for Passes {
+    fn default() -> Passes {
+        Passes::at_level(DEFAULT_LEVEL)
+    }
+}
+
+fn bit(pass: Pass) -> u32 {
+    1 << pass as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels() {
+        assert_eq!(Passes::at_level(0).iter().count(), 0);
+        assert!(Passes::at_level(1).contains(Pass::ConstFold));
+        assert!(!Passes::at_level(1).contains(Pass::UnrollLoops));
+        assert!(Passes::at_level(2).contains(Pass::UnrollLoops));
+        assert_eq!(Passes::default(), Passes::at_level(DEFAULT_LEVEL));
+        let passes = Passes::at_level(MAX_LEVEL).without(Pass::ConstFold);
+        assert!(!passes.contains(Pass::ConstFold));
+        assert_eq!(Pass::from_name("const-fold"), Some(Pass::ConstFold));
+        assert_eq!(Pass::from_name("inline"), None);
+    }
+
+    #[test]
+    fn listing() {
+        assert_eq!(
+            Passes::at_level(1).render(),
+            "const-fold         Replace the constant expressions of the functions with their values
+simplify-branches  Fold the constant branches and remove the jumps to jumps
+copy-propagation   Reuse the registers holding the locals instead of loading them again
+"
+        );
+        assert_eq!(Passes::at_level(0).render(), "");
+    }
+}
@@ -0,0 +1,392 @@
+//! Function profiling added to the ARM32 output by `--instrument-profile`.
+//!
+//! Every function counts its calls and the clock ticks spent until it
+//! returns in an entry of `__rtsc_profile_table`, the functions in the order
+//! of their declarations. An entry is four little endian words: the calls,
+//! the low and high words of the ticks and padding. The time of a call
+//! includes the time of the functions it calls, so that recursive functions
+//! count the nested calls again. Once `main` returns, the `--entry` wrappers
+//! call `__rtsc_profile_dump`, which writes the table to `rtsc.prof` for
+//! `rtsc prof report`.
+//!
+//! The clock is the CPU time of the process in microseconds on Linux and
+//! the cycle counter of the Cortex-M data watchpoint and trace unit on bare
+//! metal, started by `_start`.
+use std::cmp::Reverse;
+use std::fmt::Write;
+
+use crate::ast::Ast;
+use crate::phases::codegen::{Console, Platform};
+use crate::phases::instruction::{Address, Condition, Instruction, Operand, Register};
+use crate::phases::runtime;
+
+pub const TABLE: &str = "__rtsc_profile_table";
+pub const ENTER_ROUTINE: &str = "__rtsc_profile_enter";
+pub const EXIT_ROUTINE: &str = "__rtsc_profile_exit";
+pub const DUMP_ROUTINE: &str = "__rtsc_profile_dump";
+const CLOCK_ROUTINE: &str = "__rtsc_profile_clock";
+/// Where the table is written to, relative to the working directory.
+pub const FILE_NAME: &str = "rtsc.prof";
+/// Bytes of the entry of a function in the table.
+const ENTRY_SIZE: i32 = 16;
+
+// `clock_gettime` of the CPU time of the process.
+const SYS_CLOCK_GETTIME: i32 = 263;
+const CLOCK_PROCESS_CPUTIME_ID: i32 = 2;
+// Cortex-M registers enabling and reading the cycle counter.
+const DEMCR: u32 = 0xe000_edfc;
+const DEMCR_TRCENA: i32 = 0x0100_0000;
+const DWT_CTRL: u32 = 0xe000_1000;
+const DWT_CYCCNT: u32 = 0xe000_1004;
+
+/// The names of the profiled functions, in the order of their entries.
+pub fn functions(program: &Ast) -> Vec<&str> {
+    program
+        .declarations()
+        .filter_map(|declaration| match declaration {
+            Ast::Function(name, _, _) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn address(base: Register, offset: i32) -> Address {
+    if offset == 0 {
+        Address::Register(base)
+    } else {
+        Address::Offset(base, Operand::Immediate(offset))
+    }
+}
+
+fn load(rd: Register, base: Register, offset: i32) -> Instruction {
+    Instruction::Ldr(Condition::Always, rd, address(base, offset))
+}
+
+fn store(rd: Register, base: Register, offset: i32) -> Instruction {
+    Instruction::Str(Condition::Always, rd, address(base, offset))
+}
+
+/// Counts the call of the function with the entry, once its frame is built,
+/// and keeps the time of the call in the frame at `slot` from `fp`.
+pub fn emit_enter(entry: usize, slot: i32, code: &mut Vec<Instruction>) {
+    code.push(Instruction::LoadConstant(Register::R0, entry as i32));
+    code.push(Instruction::Bl(String::from(ENTER_ROUTINE)));
+    code.push(store(Register::R0, Register::Fp, slot));
+}
+
+/// Adds the time since the call kept at `slot` to the entry, keeping the
+/// result of the function in `r0`.
+pub fn emit_exit(entry: usize, slot: i32, code: &mut Vec<Instruction>) {
+    code.push(Instruction::LoadConstant(Register::R1, entry as i32));
+    code.push(load(Register::R2, Register::Fp, slot));
+    code.push(Instruction::Bl(String::from(EXIT_ROUTINE)));
+}
+
+/// Starts the cycle counter on bare metal, which is off after a reset. The
+/// other bits of `DEMCR` are left to the debugger.
+pub fn emit_start_clock(platform: Platform, code: &mut Vec<Instruction>) {
+    if platform != Platform::BareMetal {
+        return;
+    }
+    code.push(Instruction::LoadConstant(Register::R0, DEMCR as i32));
+    code.push(load(Register::R1, Register::R0, 0));
+    code.push(Instruction::LoadConstant(Register::R2, DEMCR_TRCENA));
+    // sets the bit by taking it out when already set and adding it back
+    code.push(Instruction::Lsl(
+        Condition::Always,
+        Register::R3,
+        Register::R1,
+        Operand::Immediate(7),
+    ));
+    code.push(Instruction::Cmp(Register::R3, Operand::Immediate(0)));
+    code.push(Instruction::Sub(
+        Condition::Lt,
+        Register::R1,
+        Register::R1,
+        Operand::Register(Register::R2),
+    ));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R1,
+        Register::R1,
+        Operand::Register(Register::R2),
+    ));
+    code.push(store(Register::R1, Register::R0, 0));
+    code.push(Instruction::LoadConstant(Register::R0, DWT_CTRL as i32));
+    code.push(mov(Register::R1, 0));
+    code.push(store(Register::R1, Register::R0, 4));
+    code.push(mov(Register::R1, 1));
+    code.push(store(Register::R1, Register::R0, 0));
+}
+
+fn mov(rd: Register, value: i32) -> Instruction {
+    Instruction::Mov(Condition::Always, rd, Operand::Immediate(value))
+}
+
+// Points `rd` at the entry whose index is in `index`, clobbering `index`.
+fn emit_entry_address(rd: Register, index: Register, code: &mut Vec<Instruction>) {
+    code.push(Instruction::LoadAddress(rd, String::from(TABLE)));
+    code.push(Instruction::Lsl(
+        Condition::Always,
+        index,
+        index,
+        Operand::Immediate(ENTRY_SIZE.trailing_zeros() as i32),
+    ));
+    code.push(Instruction::Add(
+        Condition::Always,
+        rd,
+        rd,
+        Operand::Register(index),
+    ));
+}
+
+// Gives the ticks of the clock in `r0`, clobbering `r0` to `r3`.
+fn emit_clock(platform: Platform, console: Console, code: &mut Vec<Instruction>) {
+    code.push(Instruction::Label(String::from(CLOCK_ROUTINE)));
+    match (platform, console) {
+        (Platform::Linux, Console::Syscall) => {
+            // a `timespec` of seconds and nanoseconds on the stack
+            code.push(Instruction::Push(vec![Register::R7, Register::Lr]));
+            code.push(Instruction::Sub(
+                Condition::Always,
+                Register::Sp,
+                Register::Sp,
+                Operand::Immediate(8),
+            ));
+            code.push(mov(Register::R0, CLOCK_PROCESS_CPUTIME_ID));
+            code.push(Instruction::Mov(
+                Condition::Always,
+                Register::R1,
+                Operand::Register(Register::Sp),
+            ));
+            code.push(Instruction::LoadConstant(Register::R7, SYS_CLOCK_GETTIME));
+            code.push(Instruction::Svc(0));
+            code.push(load(Register::R0, Register::Sp, 0));
+            code.push(load(Register::R1, Register::Sp, 4));
+            code.push(Instruction::LoadConstant(Register::R2, 1000));
+            code.push(Instruction::Udiv(Register::R1, Register::R1, Register::R2));
+            code.push(Instruction::LoadConstant(Register::R2, 1_000_000));
+            code.push(Instruction::Mul(Register::R0, Register::R0, Register::R2));
+            code.push(Instruction::Add(
+                Condition::Always,
+                Register::R0,
+                Register::R0,
+                Operand::Register(Register::R1),
+            ));
+            code.push(Instruction::Add(
+                Condition::Always,
+                Register::Sp,
+                Register::Sp,
+                Operand::Immediate(8),
+            ));
+            code.push(Instruction::Pop(vec![Register::R7, Register::Pc]));
+        }
+        // `CLOCKS_PER_SEC` is a million
+        (Platform::Linux, _) => {
+            code.push(Instruction::Push(vec![Register::R4, Register::Lr]));
+            code.push(Instruction::Bl(String::from("clock")));
+            code.push(Instruction::Pop(vec![Register::R4, Register::Pc]));
+        }
+        (Platform::BareMetal, _) => {
+            code.push(Instruction::LoadConstant(Register::R0, DWT_CYCCNT as i32));
+            code.push(load(Register::R0, Register::R0, 0));
+            code.push(Instruction::Mov(
+                Condition::Always,
+                Register::Pc,
+                Operand::Register(Register::Lr),
+            ));
+        }
+    }
+}
+
+/// Emits the table of the `functions` and the routines filling and writing
+/// it to `rtsc.prof`.
+pub fn emit_runtime(
+    platform: Platform,
+    console: Console,
+    functions: usize,
+    code: &mut Vec<Instruction>,
+) {
+    let size = ENTRY_SIZE * functions as i32;
+    // takes the entry in `r0` and gives the time of the call
+    code.push(Instruction::Label(String::from(ENTER_ROUTINE)));
+    code.push(Instruction::Push(vec![Register::R4, Register::Lr]));
+    emit_entry_address(Register::R1, Register::R0, code);
+    code.push(load(Register::R0, Register::R1, 0));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R0,
+        Register::R0,
+        Operand::Immediate(1),
+    ));
+    code.push(store(Register::R0, Register::R1, 0));
+    code.push(Instruction::Bl(String::from(CLOCK_ROUTINE)));
+    code.push(Instruction::Pop(vec![Register::R4, Register::Pc]));
+
+    // takes the entry in `r1` and the time of the call in `r2`, the ticks
+    // are a 64 bit sum carried into the high word when the low one wraps
+    code.push(Instruction::Label(String::from(EXIT_ROUTINE)));
+    code.push(Instruction::Push(vec![
+        Register::R0,
+        Register::R1,
+        Register::R2,
+        Register::Lr,
+    ]));
+    code.push(Instruction::Bl(String::from(CLOCK_ROUTINE)));
+    code.push(load(Register::R1, Register::Sp, 4));
+    code.push(load(Register::R2, Register::Sp, 8));
+    code.push(Instruction::Sub(
+        Condition::Always,
+        Register::R0,
+        Register::R0,
+        Operand::Register(Register::R2),
+    ));
+    emit_entry_address(Register::R3, Register::R1, code);
+    code.push(load(Register::R1, Register::R3, 4));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R1,
+        Register::R1,
+        Operand::Register(Register::R0),
+    ));
+    code.push(store(Register::R1, Register::R3, 4));
+    code.push(Instruction::Cmp(
+        Register::R1,
+        Operand::Register(Register::R0),
+    ));
+    code.push(load(Register::R2, Register::R3, 8));
+    code.push(Instruction::Add(
+        Condition::Lo,
+        Register::R2,
+        Register::R2,
+        Operand::Immediate(1),
+    ));
+    code.push(store(Register::R2, Register::R3, 8));
+    code.push(Instruction::Pop(vec![
+        Register::R0,
+        Register::R1,
+        Register::R2,
+        Register::Pc,
+    ]));
+
+    emit_clock(platform, console, code);
+    runtime::emit_write_file(
+        platform,
+        console,
+        DUMP_ROUTINE,
+        FILE_NAME,
+        TABLE,
+        size,
+        code,
+    );
+    code.push(Instruction::Section(String::from(".bss")));
+    code.push(Instruction::Balign(4));
+    code.push(Instruction::Label(String::from(TABLE)));
+    code.push(Instruction::Space(size as u32));
+    code.push(Instruction::Section(String::from(".text")));
+}
+
+/// The entry of a function read from `rtsc.prof`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub calls: u32,
+    pub ticks: u64,
+}
+
+/// Reads the table written by the dump routine.
+pub fn read_table(bytes: &[u8]) -> Result<Vec<FunctionProfile>, String> {
+    let entries = bytes.chunks_exact(ENTRY_SIZE as usize);
+    if !entries.remainder().is_empty() {
+        return Err(format!(
+            "{} bytes are not a whole number of entries",
+            bytes.len()
+        ));
+    }
+    let word = |entry: &[u8], i: usize| {
+        u32::from_le_bytes([entry[i], entry[i + 1], entry[i + 2], entry[i + 3]])
+    };
+    Ok(entries
+        .map(|entry| FunctionProfile {
+            calls: word(entry, 0),
+            ticks: u64::from(word(entry, 8)) << 32 | u64::from(word(entry, 4)),
+        })
+        .collect())
+}
+
+/// Lists the functions with their calls and ticks, the ones taking the most
+/// time first, in the manner of the flat profile of `gprof`.
+pub fn report(functions: &[&str], profiles: &[FunctionProfile]) -> String {
+    let mut rows: Vec<_> = functions.iter().zip(profiles).collect();
+    rows.sort_by_key(|(_, profile)| Reverse(profile.ticks));
+    let mut output = String::from("     calls         ticks    ticks/call  function\n");
+    for (name, profile) in rows {
+        let per_call = match profile.calls {
+            0 => String::from("-"),
+            calls => (profile.ticks / u64::from(calls)).to_string(),
+        };
+        writeln!(
+            output,
+            "{:>10}  {:>12}  {:>12}  {}",
+            profile.calls, profile.ticks, per_call, name
+        )
+        .unwrap();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn profiled_functions() {
+        let program = parser::parse_program(
+            "extern function abs(x: number): number;
+            function main() { return f(); }
+            export function f(): number { return 1; }",
+        )
+        .unwrap();
+        assert_eq!(functions(&program), ["main", "f"]);
+    }
+
+    #[test]
+    fn table() {
+        let mut bytes = vec![2, 0, 0, 0, 0x10, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        bytes.extend([0; 16]);
+        assert_eq!(
+            read_table(&bytes),
+            Ok(vec![
+                FunctionProfile {
+                    calls: 2,
+                    ticks: 0x1_0000_0010,
+                },
+                FunctionProfile { calls: 0, ticks: 0 },
+            ])
+        );
+        assert!(read_table(&bytes[..12]).is_err());
+    }
+
+    #[test]
+    fn hot_functions_first() {
+        let profiles = [
+            FunctionProfile {
+                calls: 1,
+                ticks: 1200,
+            },
+            FunctionProfile { calls: 0, ticks: 0 },
+            FunctionProfile {
+                calls: 3,
+                ticks: 4500,
+            },
+        ];
+        assert_eq!(
+            report(&["main", "unused", "fib"], &profiles),
+            "     calls         ticks    ticks/call  function
+         3          4500          1500  fib
+         1          1200          1200  main
+         0             0             -  unused
+"
+        );
+    }
+}
@@ -0,0 +1,1044 @@
+//! Routines linked into the ARM32 output in place of the C library, for
+//! bare metal targets and programs writing to the console on their own.
+//!
+//! Arrays come from a fixed heap in `.bss` handed out by a bump allocator,
+//! which never reuses memory, so `delete` does nothing there. Characters
+//! go either to the debugger through semihosting or to a memory mapped
+//! transmit register, or on Linux straight to the `write` system call.
+use crate::phases::codegen::{self, Console, Platform};
+use crate::phases::instruction::{Address, Condition, Instruction, Operand, Register};
+
+/// Starts the names of the routines and the data of the runtime.
+pub const PREFIX: &str = "__rtsc_";
+/// The functions of the C library the generated code may call and the
+/// entry point it may define.
+pub const LIBRARY_SYMBOLS: [&str; 10] = [
+    "malloc", "free", "putchar", "write", "abort", "exit", "open", "close", "clock", "_start",
+];
+
+/// Whether the generated code calls or defines a function of the name,
+/// which the program must then not define.
+pub fn is_reserved(name: &str) -> bool {
+    name.starts_with(PREFIX) || LIBRARY_SYMBOLS.contains(&name)
+}
+
+pub const ALLOC_ROUTINE: &str = "__rtsc_bump_alloc";
+pub const PUTCHAR_ROUTINE: &str = "__rtsc_putchar";
+/// Stops the program after an unrecoverable error.
+pub const ABORT_ROUTINE: &str = "__rtsc_abort";
+const HEAP: &str = "__rtsc_heap";
+const HEAP_USED: &str = "__rtsc_heap_used";
+
+pub const ARG_COUNT_ROUTINE: &str = "__rtsc_arg_count";
+pub const ARG_NUMBER_ROUTINE: &str = "__rtsc_arg_number";
+pub const NUMBER_TO_STRING_ROUTINE: &str = "__rtsc_number_to_string";
+/// Compares two strings by their characters, called in place of `==` on
+/// strings.
+pub const STRING_EQUAL_ROUTINE: &str = "__rtsc_string_equal";
+/// Counts the set bits of a number, called in place of `popcount`.
+pub const POPCOUNT_ROUTINE: &str = "__rtsc_popcount";
+/// Allocates an array of the length in `r0` with room for the number of
+/// elements in `r1`.
+pub const NEW_ARRAY_ROUTINE: &str = "__rtsc_new_array";
+pub const PUSH_ROUTINE: &str = "__rtsc_push";
+pub const POP_ROUTINE: &str = "__rtsc_pop";
+pub const CONCAT_ROUTINE: &str = "__rtsc_concat";
+pub const SLICE_ROUTINE: &str = "__rtsc_slice";
+/// Bytes of the capacity kept in front of the length of every array.
+pub const CAPACITY_SIZE: i32 = 4;
+const ARGC: &str = "__rtsc_argc";
+const ARGV: &str = "__rtsc_argv";
+
+/// Bytes available to the arrays of a bare metal program.
+pub const HEAP_SIZE: u32 = 16 * 1024;
+
+// Semihosting operations, requested with `svc` in the ARM state and `bkpt`
+// in the Thumb state.
+pub const SEMIHOSTING_SVC: u32 = 0x12_3456;
+pub const SEMIHOSTING_BKPT: u32 = 0xab;
+const SYS_WRITEC: i32 = 0x03;
+const SYS_WRITE0: i32 = 0x04;
+const SYS_EXIT: i32 = 0x18;
+const SYS_OPEN: i32 = 0x01;
+const SYS_CLOSE: i32 = 0x02;
+const SYS_WRITE: i32 = 0x05;
+// `SYS_OPEN` mode of `fopen`'s "wb"
+const MODE_WB: i32 = 5;
+/// Reasons reported to the debugger by `SYS_EXIT`.
+pub const ADP_STOPPED_APPLICATION_EXIT: i32 = 0x2_0026;
+const ADP_STOPPED_RUN_TIME_ERROR: i32 = 0x2_0023;
+
+fn label(name: &str) -> Instruction {
+    Instruction::Label(String::from(name))
+}
+
+fn mov(rd: Register, value: i32) -> Instruction {
+    Instruction::Mov(Condition::Always, rd, Operand::Immediate(value))
+}
+
+fn ret() -> Instruction {
+    Instruction::Mov(
+        Condition::Always,
+        Register::Pc,
+        Operand::Register(Register::Lr),
+    )
+}
+
+/// Ends the program, reporting `reason` to the debugger with semihosting or
+/// spinning forever otherwise.
+pub fn emit_exit(console: Console, reason: i32, code: &mut Vec<Instruction>) {
+    if console == Console::Semihosting {
+        code.push(mov(Register::R0, SYS_EXIT));
+        code.push(Instruction::LoadConstant(Register::R1, reason));
+        code.push(Instruction::Svc(SEMIHOSTING_SVC));
+    }
+    code.push(Instruction::B(Condition::Always, String::from(".")));
+}
+
+/// Prints the zero terminated string at the label, when the console can
+/// print strings without the C library.
+pub fn emit_print(console: Console, message: &str, code: &mut Vec<Instruction>) {
+    if console == Console::Semihosting {
+        code.push(mov(Register::R0, SYS_WRITE0));
+        code.push(Instruction::LoadAddress(
+            Register::R1,
+            String::from(message),
+        ));
+        code.push(Instruction::Svc(SEMIHOSTING_SVC));
+    }
+}
+
+// Linux system calls and flags of `open` creating the file or emptying it.
+const LINUX_SYS_OPEN: i32 = 5;
+const LINUX_SYS_CLOSE: i32 = 6;
+const O_WRONLY_CREAT_TRUNC: i32 = 0o1 | 0o100 | 0o1000;
+const FILE_MODE: i32 = 0o644;
+
+/// Emits the `routine` writing the `size` bytes at the label `data` to the
+/// file, in the working directory of the program on Linux and of the
+/// debugger with semihosting. Nothing is written when the file cannot be
+/// opened.
+pub fn emit_write_file(
+    platform: Platform,
+    console: Console,
+    routine: &str,
+    file: &str,
+    data: &str,
+    size: i32,
+    code: &mut Vec<Instruction>,
+) {
+    let name = routine.trim_start_matches("__rtsc_");
+    let file_label = format!(".L{}_file", name);
+    let done = format!(".L{}_done", name);
+    // keeps the handle returned in `r0` in `r4`, giving up when negative
+    let check_handle = |code: &mut Vec<Instruction>| {
+        code.push(mov_register(Register::R4, Register::R0));
+        code.push(Instruction::Cmp(Register::R0, Operand::Immediate(0)));
+        code.push(Instruction::B(Condition::Lt, done.clone()));
+    };
+    code.push(label(routine));
+    match (platform, console) {
+        (Platform::Linux, _) => {
+            let syscall = console == Console::Syscall;
+            // `r7` is only saved for the system calls
+            let saved = if syscall { Register::R7 } else { Register::Lr };
+            code.push(Instruction::Push(vec![Register::R4, saved]));
+            code.push(Instruction::LoadAddress(Register::R0, file_label.clone()));
+            code.push(Instruction::LoadConstant(
+                Register::R1,
+                O_WRONLY_CREAT_TRUNC,
+            ));
+            code.push(Instruction::LoadConstant(Register::R2, FILE_MODE));
+            let call = |code: &mut Vec<Instruction>, number: i32, function: &str| {
+                if syscall {
+                    code.push(mov(Register::R7, number));
+                    code.push(Instruction::Svc(0));
+                } else {
+                    code.push(Instruction::Bl(String::from(function)));
+                }
+            };
+            call(code, LINUX_SYS_OPEN, "open");
+            check_handle(code);
+            code.push(Instruction::LoadAddress(Register::R1, String::from(data)));
+            code.push(Instruction::LoadConstant(Register::R2, size));
+            call(code, codegen::SYS_WRITE, "write");
+            code.push(mov_register(Register::R0, Register::R4));
+            call(code, LINUX_SYS_CLOSE, "close");
+            code.push(Instruction::Label(done.clone()));
+            if syscall {
+                code.push(Instruction::Pop(vec![Register::R4, Register::R7]));
+                code.push(ret());
+            } else {
+                code.push(Instruction::Pop(vec![Register::R4, Register::Pc]));
+            }
+        }
+        // the arguments are passed in a block of words pointed to by `r1`
+        (Platform::BareMetal, _) => {
+            let store = |rd, offset| {
+                Instruction::Str(
+                    Condition::Always,
+                    rd,
+                    Address::Offset(Register::Sp, Operand::Immediate(offset)),
+                )
+            };
+            let call = |code: &mut Vec<Instruction>, operation| {
+                code.push(mov(Register::R0, operation));
+                code.push(mov_register(Register::R1, Register::Sp));
+                code.push(Instruction::Svc(SEMIHOSTING_SVC));
+            };
+            code.push(Instruction::Push(vec![Register::R4, Register::Lr]));
+            code.push(sub(Register::Sp, Register::Sp, 16));
+            code.push(Instruction::LoadAddress(Register::R2, file_label.clone()));
+            code.push(store(Register::R2, 0));
+            code.push(mov(Register::R2, MODE_WB));
+            code.push(store(Register::R2, 4));
+            code.push(mov(Register::R2, file.len() as i32));
+            code.push(store(Register::R2, 8));
+            call(code, SYS_OPEN);
+            check_handle(code);
+            code.push(store(Register::R4, 0));
+            code.push(Instruction::LoadAddress(Register::R2, String::from(data)));
+            code.push(store(Register::R2, 4));
+            code.push(Instruction::LoadConstant(Register::R2, size));
+            code.push(store(Register::R2, 8));
+            call(code, SYS_WRITE);
+            code.push(store(Register::R4, 0));
+            call(code, SYS_CLOSE);
+            code.push(Instruction::Label(done.clone()));
+            code.push(add(Register::Sp, Register::Sp, 16));
+            code.push(Instruction::Pop(vec![Register::R4, Register::Pc]));
+        }
+    }
+    code.push(Instruction::Section(String::from(".rodata")));
+    code.push(Instruction::Label(file_label));
+    code.push(Instruction::Asciz(String::from(file)));
+    code.push(Instruction::Section(String::from(".text")));
+}
+
+// Takes the size of the array in `r0` and returns the pointer to it, or
+// aborts once the heap is exhausted.
+fn emit_alloc(code: &mut Vec<Instruction>) {
+    code.push(label(ALLOC_ROUTINE));
+    code.push(Instruction::LoadAddress(
+        Register::R1,
+        String::from(HEAP_USED),
+    ));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R2,
+        Address::Register(Register::R1),
+    ));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R3,
+        Register::R2,
+        Operand::Register(Register::R0),
+    ));
+    code.push(Instruction::LoadConstant(Register::Ip, HEAP_SIZE as i32));
+    code.push(Instruction::Cmp(
+        Register::Ip,
+        Operand::Register(Register::R3),
+    ));
+    code.push(Instruction::B(Condition::Lo, String::from(ABORT_ROUTINE)));
+    code.push(Instruction::Str(
+        Condition::Always,
+        Register::R3,
+        Address::Register(Register::R1),
+    ));
+    code.push(Instruction::LoadAddress(Register::R0, String::from(HEAP)));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R0,
+        Register::R0,
+        Operand::Register(Register::R2),
+    ));
+    code.push(ret());
+}
+
+/// Writes the character in `r0`.
+fn emit_putchar(console: Console, code: &mut Vec<Instruction>) {
+    code.push(label(PUTCHAR_ROUTINE));
+    match console {
+        Console::Libc => unreachable!("`putchar` comes from the C library"),
+        // the character is passed by its address
+        Console::Semihosting => {
+            code.push(Instruction::Push(vec![Register::R0, Register::Lr]));
+            code.push(Instruction::Mov(
+                Condition::Always,
+                Register::R1,
+                Operand::Register(Register::Sp),
+            ));
+            code.push(mov(Register::R0, SYS_WRITEC));
+            code.push(Instruction::Svc(SEMIHOSTING_SVC));
+            code.push(Instruction::Pop(vec![Register::R0, Register::Pc]));
+        }
+        Console::Mmio(address) => {
+            code.push(Instruction::LoadConstant(Register::R1, address as i32));
+            code.push(Instruction::Str(
+                Condition::Always,
+                Register::R0,
+                Address::Register(Register::R1),
+            ));
+            code.push(ret());
+        }
+        // `r7` is saved along with the character written from the stack
+        Console::Syscall => {
+            code.push(Instruction::Push(vec![Register::R0, Register::R7]));
+            code.push(mov(Register::R0, 1));
+            code.push(Instruction::Mov(
+                Condition::Always,
+                Register::R1,
+                Operand::Register(Register::Sp),
+            ));
+            code.push(mov(Register::R2, 1));
+            code.push(mov(Register::R7, codegen::SYS_WRITE));
+            code.push(Instruction::Svc(0));
+            code.push(Instruction::Pop(vec![Register::R0, Register::R7]));
+            code.push(ret());
+        }
+    }
+}
+
+/// Keeps `argc` and `argv` for the argument builtins, emitted by the entry
+/// wrappers before calling the program's `main`.
+pub fn emit_save_arguments(
+    argc: Register,
+    argv: Register,
+    scratch: Register,
+    code: &mut Vec<Instruction>,
+) {
+    for (register, global) in [(argc, ARGC), (argv, ARGV)] {
+        code.push(Instruction::LoadAddress(scratch, String::from(global)));
+        code.push(Instruction::Str(
+            Condition::Always,
+            register,
+            Address::Register(scratch),
+        ));
+    }
+}
+
+// `argCount()` gives the number of arguments including the program name,
+// `argNumber(i)` the `i`th one read as a decimal number with an optional
+// minus sign, stopping at the first other character. An index out of range
+// gives zero.
+fn emit_arguments(code: &mut Vec<Instruction>) {
+    let load_global = |rd: Register, global: &str, code: &mut Vec<Instruction>| {
+        code.push(Instruction::LoadAddress(rd, String::from(global)));
+        code.push(Instruction::Ldr(
+            Condition::Always,
+            rd,
+            Address::Register(rd),
+        ));
+    };
+    code.push(label(ARG_COUNT_ROUTINE));
+    load_global(Register::R0, ARGC, code);
+    code.push(ret());
+
+    let digits = ".Larg_number_digits";
+    let done = ".Larg_number_done";
+    code.push(label(ARG_NUMBER_ROUTINE));
+    load_global(Register::R1, ARGC, code);
+    // negative indices compare as large unsigned ones
+    code.push(Instruction::Cmp(
+        Register::R0,
+        Operand::Register(Register::R1),
+    ));
+    code.push(Instruction::Mov(
+        Condition::Hs,
+        Register::R0,
+        Operand::Immediate(0),
+    ));
+    code.push(Instruction::Mov(
+        Condition::Hs,
+        Register::Pc,
+        Operand::Register(Register::Lr),
+    ));
+    load_global(Register::R1, ARGV, code);
+    code.push(Instruction::Lsl(
+        Condition::Always,
+        Register::R0,
+        Register::R0,
+        Operand::Immediate(2),
+    ));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R1,
+        Address::Offset(Register::R1, Operand::Register(Register::R0)),
+    ));
+    // the first character in `r3` tells whether the number is negative
+    code.push(mov(Register::R0, 0));
+    code.push(Instruction::Ldrb(
+        Condition::Always,
+        Register::R3,
+        Address::Register(Register::R1),
+    ));
+    code.push(Instruction::Cmp(
+        Register::R3,
+        Operand::Immediate(i32::from(b'-')),
+    ));
+    code.push(Instruction::Add(
+        Condition::Eq,
+        Register::R1,
+        Register::R1,
+        Operand::Immediate(1),
+    ));
+    code.push(mov(Register::Ip, 10));
+    code.push(label(digits));
+    code.push(Instruction::Ldrb(
+        Condition::Always,
+        Register::R2,
+        Address::Register(Register::R1),
+    ));
+    code.push(Instruction::Sub(
+        Condition::Always,
+        Register::R2,
+        Register::R2,
+        Operand::Immediate(i32::from(b'0')),
+    ));
+    code.push(Instruction::Cmp(Register::R2, Operand::Immediate(10)));
+    code.push(Instruction::B(Condition::Hs, String::from(done)));
+    code.push(Instruction::Mul(Register::R0, Register::R0, Register::Ip));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R0,
+        Register::R0,
+        Operand::Register(Register::R2),
+    ));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R1,
+        Register::R1,
+        Operand::Immediate(1),
+    ));
+    code.push(Instruction::B(Condition::Always, String::from(digits)));
+    code.push(label(done));
+    code.push(Instruction::Cmp(
+        Register::R3,
+        Operand::Immediate(i32::from(b'-')),
+    ));
+    code.push(Instruction::Rsb(
+        Condition::Eq,
+        Register::R0,
+        Register::R0,
+        Operand::Immediate(0),
+    ));
+    code.push(ret());
+
+    code.push(Instruction::Section(String::from(".bss")));
+    code.push(Instruction::Balign(4));
+    for global in [ARGC, ARGV] {
+        code.push(label(global));
+        code.push(Instruction::Space(4));
+    }
+    code.push(Instruction::Section(String::from(".text")));
+}
+
+fn mov_register(rd: Register, rm: Register) -> Instruction {
+    Instruction::Mov(Condition::Always, rd, Operand::Register(rm))
+}
+
+fn add(rd: Register, rn: Register, value: i32) -> Instruction {
+    Instruction::Add(Condition::Always, rd, rn, Operand::Immediate(value))
+}
+
+fn sub(rd: Register, rn: Register, value: i32) -> Instruction {
+    Instruction::Sub(Condition::Always, rd, rn, Operand::Immediate(value))
+}
+
+/// Emits `numberToString(n)`, giving the decimal digits of the number as a
+/// new array of character codes. The characters are first written backwards
+/// into a buffer on the stack, a word each, then copied after the length of
+/// the array.
+pub fn emit_number_to_string(code: &mut Vec<Instruction>) {
+    // eleven characters for `-2147483648`, rounded to keep `sp` aligned
+    const BUFFER: i32 = 48;
+    let digits = ".Lnumber_to_string_digits";
+    let copy = ".Lnumber_to_string_copy";
+    let done = ".Lnumber_to_string_done";
+    code.push(label(NUMBER_TO_STRING_ROUTINE));
+    code.push(Instruction::Push(vec![
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::Lr,
+    ]));
+    code.push(sub(Register::Sp, Register::Sp, BUFFER));
+    code.push(Instruction::Mov(
+        Condition::Always,
+        Register::R6,
+        Operand::Register(Register::R0),
+    ));
+    // the magnitude is divided as unsigned, which also covers the lowest
+    // number whose negation overflows
+    code.push(Instruction::Cmp(Register::R0, Operand::Immediate(0)));
+    code.push(Instruction::Rsb(
+        Condition::Lt,
+        Register::R0,
+        Register::R0,
+        Operand::Immediate(0),
+    ));
+    code.push(add(Register::R5, Register::Sp, BUFFER));
+    code.push(mov(Register::R1, 10));
+    code.push(label(digits));
+    code.push(Instruction::Udiv(Register::R2, Register::R0, Register::R1));
+    code.push(Instruction::Mul(Register::R3, Register::R2, Register::R1));
+    code.push(Instruction::Sub(
+        Condition::Always,
+        Register::R3,
+        Register::R0,
+        Operand::Register(Register::R3),
+    ));
+    code.push(add(Register::R3, Register::R3, i32::from(b'0')));
+    code.push(sub(Register::R5, Register::R5, 4));
+    code.push(Instruction::Str(
+        Condition::Always,
+        Register::R3,
+        Address::Register(Register::R5),
+    ));
+    code.push(Instruction::Mov(
+        Condition::Always,
+        Register::R0,
+        Operand::Register(Register::R2),
+    ));
+    code.push(Instruction::Cmp(Register::R0, Operand::Immediate(0)));
+    code.push(Instruction::B(Condition::Ne, String::from(digits)));
+    code.push(Instruction::Cmp(Register::R6, Operand::Immediate(0)));
+    code.push(Instruction::Mov(
+        Condition::Lt,
+        Register::R3,
+        Operand::Immediate(i32::from(b'-')),
+    ));
+    code.push(Instruction::Sub(
+        Condition::Lt,
+        Register::R5,
+        Register::R5,
+        Operand::Immediate(4),
+    ));
+    code.push(Instruction::Str(
+        Condition::Lt,
+        Register::R3,
+        Address::Register(Register::R5),
+    ));
+    // the length in `r4`
+    code.push(add(Register::R4, Register::Sp, BUFFER));
+    code.push(Instruction::Sub(
+        Condition::Always,
+        Register::R4,
+        Register::R4,
+        Operand::Register(Register::R5),
+    ));
+    code.push(Instruction::Lsr(
+        Condition::Always,
+        Register::R4,
+        Register::R4,
+        Operand::Immediate(2),
+    ));
+    code.push(mov_register(Register::R0, Register::R4));
+    code.push(mov_register(Register::R1, Register::R4));
+    code.push(Instruction::Bl(String::from(NEW_ARRAY_ROUTINE)));
+    code.push(Instruction::Mov(
+        Condition::Always,
+        Register::R1,
+        Operand::Register(Register::R0),
+    ));
+    code.push(label(copy));
+    code.push(Instruction::Cmp(Register::R4, Operand::Immediate(0)));
+    code.push(Instruction::B(Condition::Eq, String::from(done)));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R2,
+        Address::Register(Register::R5),
+    ));
+    code.push(add(Register::R5, Register::R5, 4));
+    code.push(add(Register::R1, Register::R1, 4));
+    code.push(Instruction::Str(
+        Condition::Always,
+        Register::R2,
+        Address::Register(Register::R1),
+    ));
+    code.push(sub(Register::R4, Register::R4, 1));
+    code.push(Instruction::B(Condition::Always, String::from(copy)));
+    code.push(label(done));
+    code.push(add(Register::Sp, Register::Sp, BUFFER));
+    code.push(Instruction::Pop(vec![
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::Pc,
+    ]));
+}
+
+/// Emits the allocation of arrays through `alloc`. The capacity is stored in
+/// the word before the length, so that the array pointer still points at the
+/// length followed by the elements.
+pub fn emit_new_array(alloc: &str, code: &mut Vec<Instruction>) {
+    code.push(label(NEW_ARRAY_ROUTINE));
+    code.push(Instruction::Push(vec![
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::Lr,
+    ]));
+    code.push(mov_register(Register::R4, Register::R0));
+    code.push(mov_register(Register::R5, Register::R1));
+    // a word for the capacity and one for the length
+    code.push(add(Register::R0, Register::R1, 2));
+    code.push(Instruction::Lsl(
+        Condition::Always,
+        Register::R0,
+        Register::R0,
+        Operand::Immediate(2),
+    ));
+    code.push(Instruction::Bl(String::from(alloc)));
+    code.push(Instruction::Str(
+        Condition::Always,
+        Register::R5,
+        Address::Register(Register::R0),
+    ));
+    code.push(add(Register::R0, Register::R0, CAPACITY_SIZE));
+    code.push(Instruction::Str(
+        Condition::Always,
+        Register::R4,
+        Address::Register(Register::R0),
+    ));
+    code.push(Instruction::Pop(vec![
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::Pc,
+    ]));
+}
+
+// Copies `r3` elements from the array at `r1` after the element `r2` points
+// to, leaving `r2` at the last one copied.
+fn emit_copy(copy: &str, copied: &str, code: &mut Vec<Instruction>) {
+    code.push(label(copy));
+    code.push(Instruction::Cmp(Register::R3, Operand::Immediate(0)));
+    code.push(Instruction::B(Condition::Eq, String::from(copied)));
+    code.push(add(Register::R1, Register::R1, 4));
+    code.push(add(Register::R2, Register::R2, 4));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::Ip,
+        Address::Register(Register::R1),
+    ));
+    code.push(Instruction::Str(
+        Condition::Always,
+        Register::Ip,
+        Address::Register(Register::R2),
+    ));
+    code.push(sub(Register::R3, Register::R3, 1));
+    code.push(Instruction::B(Condition::Always, String::from(copy)));
+    code.push(label(copied));
+}
+
+/// Emits `push`, `pop`, `concat` and `slice`. `push` appends the element in
+/// `r1` to the array in `r0` when it has room for it, otherwise to a copy
+/// with twice the capacity, and returns the array it ended up in. `pop`
+/// removes the last element and returns it, or zero when the array is empty.
+/// `slice` copies the elements from the index in `r1` up to the one in `r2`
+/// into a new array, both indices clamped to the array.
+///
+/// The routines allocating keep their arguments on the stack, where the
+/// collector finds them.
+pub fn emit_array_builtins(code: &mut Vec<Instruction>) {
+    let arguments = vec![
+        Register::R0,
+        Register::R1,
+        Register::R4,
+        Register::R5,
+        Register::R6,
+        Register::Lr,
+    ];
+    let load_argument = |rd: Register, i: i32, code: &mut Vec<Instruction>| {
+        code.push(Instruction::Ldr(
+            Condition::Always,
+            rd,
+            Address::Offset(Register::Sp, Operand::Immediate(4 * i)),
+        ));
+    };
+    let epilogue = |code: &mut Vec<Instruction>| {
+        code.push(add(Register::Sp, Register::Sp, 8));
+        code.push(Instruction::Pop(vec![
+            Register::R4,
+            Register::R5,
+            Register::R6,
+            Register::Pc,
+        ]));
+    };
+
+    let store = ".Lpush_store";
+    code.push(label(PUSH_ROUTINE));
+    code.push(Instruction::Push(arguments.clone()));
+    code.push(mov_register(Register::R4, Register::R0));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R5,
+        Address::Register(Register::R0),
+    ));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R1,
+        Address::Offset(Register::R0, Operand::Immediate(-CAPACITY_SIZE)),
+    ));
+    code.push(Instruction::Cmp(
+        Register::R5,
+        Operand::Register(Register::R1),
+    ));
+    code.push(Instruction::B(Condition::Lo, String::from(store)));
+    code.push(mov_register(Register::R0, Register::R5));
+    code.push(Instruction::Lsl(
+        Condition::Always,
+        Register::R1,
+        Register::R5,
+        Operand::Immediate(1),
+    ));
+    code.push(Instruction::Cmp(Register::R1, Operand::Immediate(4)));
+    code.push(Instruction::Mov(
+        Condition::Lo,
+        Register::R1,
+        Operand::Immediate(4),
+    ));
+    code.push(Instruction::Bl(String::from(NEW_ARRAY_ROUTINE)));
+    code.push(mov_register(Register::R1, Register::R4));
+    code.push(mov_register(Register::R2, Register::R0));
+    code.push(mov_register(Register::R3, Register::R5));
+    code.push(mov_register(Register::R4, Register::R0));
+    emit_copy(".Lpush_copy", ".Lpush_copied", code);
+    code.push(label(store));
+    code.push(add(Register::R5, Register::R5, 1));
+    code.push(Instruction::Str(
+        Condition::Always,
+        Register::R5,
+        Address::Register(Register::R4),
+    ));
+    code.push(Instruction::Lsl(
+        Condition::Always,
+        Register::R5,
+        Register::R5,
+        Operand::Immediate(2),
+    ));
+    load_argument(Register::R1, 1, code);
+    code.push(Instruction::Str(
+        Condition::Always,
+        Register::R1,
+        Address::Offset(Register::R4, Operand::Register(Register::R5)),
+    ));
+    code.push(mov_register(Register::R0, Register::R4));
+    epilogue(code);
+
+    code.push(label(POP_ROUTINE));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R1,
+        Address::Register(Register::R0),
+    ));
+    code.push(Instruction::Cmp(Register::R1, Operand::Immediate(0)));
+    code.push(Instruction::Mov(
+        Condition::Eq,
+        Register::R0,
+        Operand::Immediate(0),
+    ));
+    code.push(Instruction::Mov(
+        Condition::Eq,
+        Register::Pc,
+        Operand::Register(Register::Lr),
+    ));
+    code.push(sub(Register::R2, Register::R1, 1));
+    code.push(Instruction::Str(
+        Condition::Always,
+        Register::R2,
+        Address::Register(Register::R0),
+    ));
+    code.push(Instruction::Lsl(
+        Condition::Always,
+        Register::R1,
+        Register::R1,
+        Operand::Immediate(2),
+    ));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R0,
+        Address::Offset(Register::R0, Operand::Register(Register::R1)),
+    ));
+    code.push(ret());
+
+    code.push(label(CONCAT_ROUTINE));
+    code.push(Instruction::Push(arguments.clone()));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R4,
+        Address::Register(Register::R0),
+    ));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R5,
+        Address::Register(Register::R1),
+    ));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R0,
+        Register::R4,
+        Operand::Register(Register::R5),
+    ));
+    code.push(mov_register(Register::R1, Register::R0));
+    code.push(Instruction::Bl(String::from(NEW_ARRAY_ROUTINE)));
+    load_argument(Register::R1, 0, code);
+    code.push(mov_register(Register::R2, Register::R0));
+    code.push(mov_register(Register::R3, Register::R4));
+    emit_copy(".Lconcat_copy_first", ".Lconcat_first_copied", code);
+    load_argument(Register::R1, 1, code);
+    code.push(mov_register(Register::R3, Register::R5));
+    emit_copy(".Lconcat_copy_second", ".Lconcat_second_copied", code);
+    epilogue(code);
+
+    code.push(label(SLICE_ROUTINE));
+    code.push(Instruction::Push(arguments));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R3,
+        Address::Register(Register::R0),
+    ));
+    code.push(Instruction::Cmp(
+        Register::R2,
+        Operand::Register(Register::R3),
+    ));
+    code.push(Instruction::Mov(
+        Condition::Gt,
+        Register::R2,
+        Operand::Register(Register::R3),
+    ));
+    code.push(Instruction::Cmp(Register::R1, Operand::Immediate(0)));
+    code.push(Instruction::Mov(
+        Condition::Lt,
+        Register::R1,
+        Operand::Immediate(0),
+    ));
+    // a start past the end gives an empty array
+    code.push(Instruction::Cmp(
+        Register::R1,
+        Operand::Register(Register::R2),
+    ));
+    code.push(Instruction::Mov(
+        Condition::Gt,
+        Register::R1,
+        Operand::Register(Register::R2),
+    ));
+    code.push(Instruction::Lsl(
+        Condition::Always,
+        Register::R4,
+        Register::R1,
+        Operand::Immediate(2),
+    ));
+    code.push(Instruction::Sub(
+        Condition::Always,
+        Register::R5,
+        Register::R2,
+        Operand::Register(Register::R1),
+    ));
+    code.push(mov_register(Register::R0, Register::R5));
+    code.push(mov_register(Register::R1, Register::R5));
+    code.push(Instruction::Bl(String::from(NEW_ARRAY_ROUTINE)));
+    load_argument(Register::R1, 0, code);
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R1,
+        Register::R1,
+        Operand::Register(Register::R4),
+    ));
+    code.push(mov_register(Register::R2, Register::R0));
+    code.push(mov_register(Register::R3, Register::R5));
+    emit_copy(".Lslice_copy", ".Lslice_copied", code);
+    epilogue(code);
+}
+
+/// Emits the string comparison, taking the strings in `r0` and `r1` and
+/// returning one when they have the same length and characters, zero
+/// otherwise.
+pub fn emit_string_equal(code: &mut Vec<Instruction>) {
+    let characters = ".Lstring_equal_characters";
+    let different = |code: &mut Vec<Instruction>| {
+        code.push(Instruction::Mov(
+            Condition::Ne,
+            Register::R0,
+            Operand::Immediate(0),
+        ));
+        code.push(Instruction::Mov(
+            Condition::Ne,
+            Register::Pc,
+            Operand::Register(Register::Lr),
+        ));
+    };
+    code.push(label(STRING_EQUAL_ROUTINE));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R2,
+        Address::Register(Register::R0),
+    ));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R3,
+        Address::Register(Register::R1),
+    ));
+    code.push(Instruction::Cmp(
+        Register::R2,
+        Operand::Register(Register::R3),
+    ));
+    different(code);
+    // `r2` counts the characters left to compare
+    code.push(label(characters));
+    code.push(Instruction::Cmp(Register::R2, Operand::Immediate(0)));
+    code.push(Instruction::Mov(
+        Condition::Eq,
+        Register::R0,
+        Operand::Immediate(1),
+    ));
+    code.push(Instruction::Mov(
+        Condition::Eq,
+        Register::Pc,
+        Operand::Register(Register::Lr),
+    ));
+    code.push(add(Register::R0, Register::R0, 4));
+    code.push(add(Register::R1, Register::R1, 4));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::R3,
+        Address::Register(Register::R0),
+    ));
+    code.push(Instruction::Ldr(
+        Condition::Always,
+        Register::Ip,
+        Address::Register(Register::R1),
+    ));
+    code.push(Instruction::Cmp(
+        Register::R3,
+        Operand::Register(Register::Ip),
+    ));
+    different(code);
+    code.push(sub(Register::R2, Register::R2, 1));
+    code.push(Instruction::B(Condition::Always, String::from(characters)));
+}
+
+/// Emits the bit count, taking the number in `r0` and returning the number of
+/// ones in it. The bits are shifted out of the top one by one until none is
+/// left, adding each to the count in `r1`.
+pub fn emit_popcount(code: &mut Vec<Instruction>) {
+    let bits = ".Lpopcount_bits";
+    code.push(label(POPCOUNT_ROUTINE));
+    code.push(mov(Register::R1, 0));
+    code.push(label(bits));
+    code.push(Instruction::Cmp(Register::R0, Operand::Immediate(0)));
+    code.push(Instruction::Mov(
+        Condition::Eq,
+        Register::R0,
+        Operand::Register(Register::R1),
+    ));
+    code.push(Instruction::Mov(
+        Condition::Eq,
+        Register::Pc,
+        Operand::Register(Register::Lr),
+    ));
+    code.push(Instruction::Lsr(
+        Condition::Always,
+        Register::R2,
+        Register::R0,
+        Operand::Immediate(31),
+    ));
+    code.push(Instruction::Add(
+        Condition::Always,
+        Register::R1,
+        Register::R1,
+        Operand::Register(Register::R2),
+    ));
+    code.push(Instruction::Lsl(
+        Condition::Always,
+        Register::R0,
+        Register::R0,
+        Operand::Immediate(1),
+    ));
+    code.push(Instruction::B(Condition::Always, String::from(bits)));
+}
+
+/// Emits the routines standing in for the C library and the heap, nothing
+/// when the program is linked with it, then the argument builtins when the
+/// program calls them.
+pub fn emit_runtime(
+    platform: Platform,
+    console: Console,
+    uses_arguments: bool,
+    code: &mut Vec<Instruction>,
+) {
+    if uses_arguments {
+        emit_arguments(code);
+    }
+    if console != Console::Libc {
+        emit_putchar(console, code);
+    }
+    if platform == Platform::Linux {
+        return;
+    }
+    emit_alloc(code);
+    code.push(label(ABORT_ROUTINE));
+    emit_exit(console, ADP_STOPPED_RUN_TIME_ERROR, code);
+    code.push(Instruction::Section(String::from(".bss")));
+    code.push(Instruction::Balign(8));
+    code.push(label(HEAP_USED));
+    code.push(Instruction::Space(4));
+    code.push(Instruction::Balign(8));
+    code.push(label(HEAP));
+    code.push(Instruction::Space(HEAP_SIZE));
+    code.push(Instruction::Section(String::from(".text")));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phases::instruction;
+
+    #[test]
+    fn mmio_console() {
+        let mut code = Vec::new();
+        emit_runtime(
+            Platform::BareMetal,
+            Console::Mmio(0x4000_c000),
+            false,
+            &mut code,
+        );
+        assert_eq!(
+            instruction::render(&code[..4]),
+            "__rtsc_putchar:\n    ldr r1, =1073790976\n    str r0, [r1]\n    mov pc, lr\n"
+        );
+        let abort = code
+            .iter()
+            .position(|x| *x == label(ABORT_ROUTINE))
+            .unwrap();
+        assert_eq!(
+            code[abort + 1],
+            Instruction::B(Condition::Always, String::from("."))
+        );
+    }
+
+    #[test]
+    fn syscall_console() {
+        let mut code = Vec::new();
+        emit_runtime(Platform::Linux, Console::Syscall, false, &mut code);
+        assert_eq!(
+            instruction::render(&code),
+            "__rtsc_putchar:\n    push {r0, r7}\n    mov r0, #1\n    mov r1, sp\n    mov r2, #1\n    mov r7, #4\n    svc #0\n    pop {r0, r7}\n    mov pc, lr\n"
+        );
+    }
+
+    #[test]
+    fn semihosting_exit() {
+        let mut code = Vec::new();
+        emit_exit(
+            Console::Semihosting,
+            ADP_STOPPED_APPLICATION_EXIT,
+            &mut code,
+        );
+        assert_eq!(
+            instruction::render(&code),
+            "    mov r0, #24\n    ldr r1, =131110\n    svc #1193046\n    b .\n"
+        );
+    }
+}
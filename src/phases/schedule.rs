@@ -0,0 +1,394 @@
+//! Instruction scheduling within the basic blocks of the ARM32 code.
+//!
+//! In-order cores such as the Cortex-A7 stall when an instruction uses the
+//! result of a load issued right before it. The pass reorders the
+//! instructions between two barriers so that independent work fills the
+//! cycles after a load: every cycle it issues, of the instructions whose
+//! operands are ready, the one with the longest chain of latencies after
+//! it, in the original order on ties. Instructions only move past the ones
+//! they share nothing with: no register, no flags and no memory one of them
+//! writes. A local's slot, `[fp, #offset]`, only overlaps the same slot and
+//! memory reached through other bases, which includes the stack the pushes
+//! and pops use. Labels, branches, calls, anything touching `pc`, comments
+//! and the debug line markers are barriers and stay where they are.
+use crate::phases::instruction::{
+    Address, Condition, FloatRegister, Instruction, Operand, Register,
+};
+
+/// Cycles until the result of a load can be used.
+pub const LOAD_LATENCY: usize = 3;
+/// Cycles until the result of a multiplication can be used.
+pub const MULTIPLY_LATENCY: usize = 3;
+/// Cycles until the result of a division can be used.
+pub const DIVIDE_LATENCY: usize = 8;
+
+pub fn schedule(code: Vec<Instruction>) -> Vec<Instruction> {
+    schedule_mapped(code).0
+}
+
+/// The scheduled code along with the index of the instruction each
+/// instruction comes from.
+pub fn schedule_mapped(code: Vec<Instruction>) -> (Vec<Instruction>, Vec<usize>) {
+    let effects: Vec<Option<Effects>> = code.iter().map(effects).collect();
+    let mut order = Vec::with_capacity(code.len());
+    let mut start = 0;
+    for (i, instruction_effects) in effects.iter().enumerate() {
+        if instruction_effects.is_none() {
+            order.extend(
+                schedule_block(&effects[start..i])
+                    .into_iter()
+                    .map(|j| start + j),
+            );
+            order.push(i);
+            start = i + 1;
+        }
+    }
+    order.extend(
+        schedule_block(&effects[start..])
+            .into_iter()
+            .map(|j| start + j),
+    );
+    let mut code: Vec<Option<Instruction>> = code.into_iter().map(Some).collect();
+    let scheduled = order.iter().map(|&i| code[i].take().unwrap()).collect();
+    (scheduled, order)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resource {
+    Register(Register),
+    Float(FloatRegister),
+    /// The condition flags.
+    Flags,
+    /// The flags of the VFP comparisons, copied by `vmrs`.
+    FloatFlags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Memory {
+    /// The slot of a local at the offset from `fp`.
+    Slot(i32),
+    Other,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Effects {
+    reads: Vec<Resource>,
+    writes: Vec<Resource>,
+    loads: Option<Memory>,
+    stores: Option<Memory>,
+    latency: usize,
+}
+
+impl Effects {
+    // Whether the instruction has to stay after `earlier`, and whether it
+    // reads a result of it.
+    fn depends_on(&self, earlier: &Effects) -> (bool, bool) {
+        let reads_result = earlier
+            .writes
+            .iter()
+            .any(|resource| self.reads.contains(resource));
+        let registers = reads_result
+            || earlier
+                .writes
+                .iter()
+                .any(|resource| self.writes.contains(resource))
+            || earlier
+                .reads
+                .iter()
+                .any(|resource| self.writes.contains(resource));
+        let memory = overlap(earlier.stores, self.loads)
+            || overlap(earlier.stores, self.stores)
+            || overlap(earlier.loads, self.stores);
+        (registers || memory, reads_result)
+    }
+}
+
+fn overlap(lhs: Option<Memory>, rhs: Option<Memory>) -> bool {
+    match (lhs, rhs) {
+        (Some(Memory::Slot(lhs)), Some(Memory::Slot(rhs))) => lhs == rhs,
+        (Some(_), Some(_)) => true,
+        _ => false,
+    }
+}
+
+// What the instruction reads and writes, `None` for the barriers.
+fn effects(instruction: &Instruction) -> Option<Effects> {
+    let mut effects = Effects {
+        latency: 1,
+        ..Effects::default()
+    };
+    let register = Resource::Register;
+    let float = Resource::Float;
+    let operand = |operand: &Operand| match operand {
+        Operand::Register(register) => vec![Resource::Register(*register)],
+        Operand::Immediate(_) => Vec::new(),
+    };
+    let condition = match instruction {
+        Instruction::Mov(condition, _, _)
+        | Instruction::Ldr(condition, _, _)
+        | Instruction::Ldrb(condition, _, _)
+        | Instruction::Str(condition, _, _)
+        | Instruction::Add(condition, _, _, _)
+        | Instruction::Sub(condition, _, _, _)
+        | Instruction::Rsb(condition, _, _, _)
+        | Instruction::Lsl(condition, _, _, _)
+        | Instruction::Lsr(condition, _, _, _)
+        | Instruction::Ror(condition, _, _, _) => *condition,
+        _ => Condition::Always,
+    };
+    match instruction {
+        Instruction::Mov(_, target, source) => {
+            effects.reads = operand(source);
+            effects.writes = vec![register(*target)];
+        }
+        Instruction::LoadConstant(target, _) | Instruction::LoadAddress(target, _) => {
+            // from the literal pool, which never changes
+            effects.writes = vec![register(*target)];
+            effects.latency = LOAD_LATENCY;
+        }
+        Instruction::Ldr(_, target, address) | Instruction::Ldrb(_, target, address) => {
+            let (reads, memory) = access(address);
+            effects.reads = reads;
+            effects.writes = vec![register(*target)];
+            effects.loads = Some(memory);
+            effects.latency = LOAD_LATENCY;
+        }
+        Instruction::Str(_, source, address) => {
+            let (mut reads, memory) = access(address);
+            reads.push(register(*source));
+            effects.reads = reads;
+            effects.stores = Some(memory);
+        }
+        Instruction::Add(_, target, source, value)
+        | Instruction::Sub(_, target, source, value)
+        | Instruction::Rsb(_, target, source, value)
+        | Instruction::Lsl(_, target, source, value)
+        | Instruction::Lsr(_, target, source, value)
+        | Instruction::Ror(_, target, source, value) => {
+            effects.reads = operand(value);
+            effects.reads.push(register(*source));
+            effects.writes = vec![register(*target)];
+        }
+        Instruction::Mul(target, lhs, rhs) => {
+            effects.reads = vec![register(*lhs), register(*rhs)];
+            effects.writes = vec![register(*target)];
+            effects.latency = MULTIPLY_LATENCY;
+        }
+        Instruction::Sdiv(target, lhs, rhs) | Instruction::Udiv(target, lhs, rhs) => {
+            effects.reads = vec![register(*lhs), register(*rhs)];
+            effects.writes = vec![register(*target)];
+            effects.latency = DIVIDE_LATENCY;
+        }
+        Instruction::Clz(target, source) => {
+            effects.reads = vec![register(*source)];
+            effects.writes = vec![register(*target)];
+        }
+        Instruction::Cmp(lhs, rhs) => {
+            effects.reads = operand(rhs);
+            effects.reads.push(register(*lhs));
+            effects.writes = vec![Resource::Flags];
+        }
+        Instruction::Push(registers) => {
+            effects.reads = registers.iter().copied().map(register).collect();
+            effects.reads.push(register(Register::Sp));
+            effects.writes = vec![register(Register::Sp)];
+            effects.stores = Some(Memory::Other);
+        }
+        Instruction::Pop(registers) => {
+            effects.reads = vec![register(Register::Sp)];
+            effects.writes = registers.iter().copied().map(register).collect();
+            effects.writes.push(register(Register::Sp));
+            effects.loads = Some(Memory::Other);
+            effects.latency = LOAD_LATENCY;
+        }
+        Instruction::VmovToFloat(target, source) => {
+            effects.reads = vec![register(*source)];
+            effects.writes = vec![float(*target)];
+        }
+        Instruction::VmovFromFloat(target, source) => {
+            effects.reads = vec![float(*source)];
+            effects.writes = vec![register(*target)];
+        }
+        Instruction::Vadd(target, lhs, rhs)
+        | Instruction::Vsub(target, lhs, rhs)
+        | Instruction::Vmul(target, lhs, rhs)
+        | Instruction::Vdiv(target, lhs, rhs) => {
+            effects.reads = vec![float(*lhs), float(*rhs)];
+            effects.writes = vec![float(*target)];
+        }
+        Instruction::Vcmp(lhs, rhs) => {
+            effects.reads = vec![float(*lhs), float(*rhs)];
+            effects.writes = vec![Resource::FloatFlags];
+        }
+        Instruction::Vmrs => {
+            effects.reads = vec![Resource::FloatFlags];
+            effects.writes = vec![Resource::Flags];
+        }
+        _ => return None,
+    }
+    if condition != Condition::Always {
+        // when the condition fails the old value stays
+        effects.reads.push(Resource::Flags);
+        effects.reads.extend(effects.writes.clone());
+    }
+    let pc = Resource::Register(Register::Pc);
+    if effects.reads.contains(&pc) || effects.writes.contains(&pc) {
+        return None;
+    }
+    Some(effects)
+}
+
+// The registers addressing the memory and what it is. The slots are only
+// addressed while `sp` is below them, moving `sp` keeps them in place.
+fn access(address: &Address) -> (Vec<Resource>, Memory) {
+    match address {
+        Address::Register(Register::Fp) => (
+            vec![
+                Resource::Register(Register::Fp),
+                Resource::Register(Register::Sp),
+            ],
+            Memory::Slot(0),
+        ),
+        Address::Offset(Register::Fp, Operand::Immediate(offset)) => (
+            vec![
+                Resource::Register(Register::Fp),
+                Resource::Register(Register::Sp),
+            ],
+            Memory::Slot(*offset),
+        ),
+        Address::Register(base) => (vec![Resource::Register(*base)], Memory::Other),
+        Address::Offset(base, Operand::Register(index)) => (
+            vec![Resource::Register(*base), Resource::Register(*index)],
+            Memory::Other,
+        ),
+        Address::Offset(base, Operand::Immediate(_)) => {
+            (vec![Resource::Register(*base)], Memory::Other)
+        }
+    }
+}
+
+// The order of the instructions of a block without barriers, as indices
+// into it.
+fn schedule_block(block: &[Option<Effects>]) -> Vec<usize> {
+    let block: Vec<&Effects> = block.iter().flatten().collect();
+    // the instructions depending on each one, and whether on its result
+    let mut successors: Vec<Vec<(usize, bool)>> = vec![Vec::new(); block.len()];
+    let mut predecessors = vec![0; block.len()];
+    for later in 0..block.len() {
+        for earlier in 0..later {
+            let (depends, reads_result) = block[later].depends_on(block[earlier]);
+            if depends {
+                successors[earlier].push((later, reads_result));
+                predecessors[later] += 1;
+            }
+        }
+    }
+    // the cycles from issuing each one to the end of the block
+    let mut heights = vec![0; block.len()];
+    for i in (0..block.len()).rev() {
+        heights[i] = block[i].latency
+            + successors[i]
+                .iter()
+                .map(|&(successor, _)| heights[successor])
+                .max()
+                .unwrap_or(0);
+    }
+    let mut ready_at = vec![0; block.len()];
+    let mut scheduled = vec![false; block.len()];
+    let mut order = Vec::with_capacity(block.len());
+    let mut cycle = 0;
+    while order.len() < block.len() {
+        let candidates = (0..block.len()).filter(|&i| !scheduled[i] && predecessors[i] == 0);
+        let next = candidates
+            .min_by_key(|&i| (ready_at[i].max(cycle), std::cmp::Reverse(heights[i]), i))
+            .unwrap();
+        cycle = cycle.max(ready_at[next]);
+        scheduled[next] = true;
+        order.push(next);
+        for &(successor, reads_result) in &successors[next] {
+            predecessors[successor] -= 1;
+            if reads_result {
+                ready_at[successor] = ready_at[successor].max(cycle + block[next].latency);
+            }
+        }
+        cycle += 1;
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phases::instruction;
+
+    fn ldr(register: Register, address: Address) -> Instruction {
+        Instruction::Ldr(Condition::Always, register, address)
+    }
+
+    fn slot(offset: i32) -> Address {
+        Address::Offset(Register::Fp, Operand::Immediate(offset))
+    }
+
+    fn add(register: Register, value: i32) -> Instruction {
+        Instruction::Add(
+            Condition::Always,
+            register,
+            register,
+            Operand::Immediate(value),
+        )
+    }
+
+    #[test]
+    fn hidden_load_latency() {
+        let code = vec![
+            ldr(Register::R0, slot(-16)),
+            add(Register::R0, 1),
+            ldr(Register::R1, slot(-20)),
+            add(Register::R1, 2),
+            Instruction::B(Condition::Always, String::from(".L0")),
+            ldr(Register::R0, slot(-16)),
+            add(Register::R0, 1),
+        ];
+        let (scheduled, origins) = schedule_mapped(code);
+        assert_eq!(
+            instruction::render(&scheduled),
+            "    ldr r0, [fp, #-16]
+    ldr r1, [fp, #-20]
+    add r0, r0, #1
+    add r1, r1, #2
+    b .L0
+    ldr r0, [fp, #-16]
+    add r0, r0, #1
+"
+        );
+        assert_eq!(origins, [0, 2, 1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn dependencies() {
+        let code = vec![
+            // the same slot
+            Instruction::Str(Condition::Always, Register::R0, slot(-16)),
+            add(Register::R2, 1),
+            ldr(Register::R1, slot(-16)),
+            add(Register::R1, 1),
+            // memory through another base
+            Instruction::Str(
+                Condition::Always,
+                Register::R1,
+                Address::Register(Register::R3),
+            ),
+            ldr(Register::R0, slot(-20)),
+            add(Register::R0, 1),
+            // the flags
+            Instruction::Cmp(Register::R0, Operand::Immediate(0)),
+            Instruction::Mov(Condition::Eq, Register::R1, Operand::Immediate(1)),
+            Instruction::Mov(Condition::Always, Register::R1, Operand::Immediate(2)),
+        ];
+        let scheduled = schedule(code.clone());
+        // only the load of the slot moves, ahead of the independent `add`
+        let mut expected = code;
+        expected.swap(1, 2);
+        assert_eq!(scheduled, expected);
+    }
+}
@@ -0,0 +1,287 @@
+//! Target specifications, the details of an ARM32 target which the platform
+//! leaves open, such as the architecture version and the floating point
+//! calling convention, so that close variants of a target only take a file
+//! instead of a code generator of their own.
+//!
+//! A specification is written in a subset of TOML, a key and a string,
+//! number or boolean per line, with `#` starting a comment:
+//!
+//! ```text
+//! # Raspberry Pi 1
+//! arch = "armv6"
+//! float-abi = "hard"
+//! ```
+//!
+//! The keys are
+//!
+//! - `arch`: `armv7` (default), the A profile or for bare metal Thumb code
+//!   the M profile, or `armv6`, which has neither Thumb-2 nor division,
+//! - `float-abi`: `hard`, passing floats in the VFP registers, or `soft`,
+//!   passing them in the core registers, by default the one of the
+//!   platform,
+//! - `hardware-division`: whether `sdiv` and `udiv` are available, by
+//!   default on ARMv7 only, see `phases::division`,
+//! - `expression-registers`: how many of `r0`-`r3` evaluate expressions
+//!   before the generator spills to the stack, 4 by default,
+//! - `word-size` and `argument-registers`: 32 and 4, the only ones the
+//!   generator supports, given to document the calling convention.
+use std::collections::BTreeMap;
+
+/// The version of the ARM architecture the ARM32 code is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arch {
+    /// ARMv7-A, or ARMv7-M for bare metal Thumb code.
+    #[default]
+    Armv7,
+    /// ARMv6, which has neither Thumb-2 nor division instructions.
+    Armv6,
+}
+
+/// How floats are passed to functions and returned from them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatAbi {
+    /// In the VFP registers, like arm-linux-gnueabihf.
+    Hard,
+    /// In the core registers, like arm-none-eabi, the VFP still does the
+    /// arithmetic.
+    Soft,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetSpec {
+    pub arch: Arch,
+    /// `None` for the convention of the platform, hard on Linux and soft on
+    /// bare metal.
+    pub float_abi: Option<FloatAbi>,
+    /// Divides with `sdiv` and `udiv` instead of calling the routines of
+    /// `phases::division`.
+    pub hardware_division: bool,
+    /// How many registers evaluate expressions not calling any function,
+    /// from 1 to 4.
+    pub expression_registers: usize,
+}
+
+impl Default for TargetSpec {
+    fn default() -> TargetSpec {
+        TargetSpec {
+            arch: Arch::Armv7,
+            float_abi: None,
+            hardware_division: true,
+            expression_registers: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::String(text) => write!(f, "\"{}\"", text),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl TargetSpec {
+    /// Reads a specification, the keys it leaves out getting their defaults.
+    pub fn parse(text: &str) -> Result<TargetSpec, String> {
+        let mut values = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let (key, value) = match parse_line(line) {
+                Ok(Some(entry)) => entry,
+                Ok(None) => continue,
+                Err(message) => return Err(format!("line {}: {}", i + 1, message)),
+            };
+            if values.insert(key.clone(), (i + 1, value)).is_some() {
+                return Err(format!("line {}: `{}` is given twice", i + 1, key));
+            }
+        }
+        let mut spec = TargetSpec::default();
+        let mut hardware_division = None;
+        for (key, (line, value)) in values {
+            let invalid = || format!("line {}: invalid value {} for `{}`", line, value, key);
+            match (key.as_str(), &value) {
+                ("arch", Value::String(arch)) => {
+                    spec.arch = match arch.as_str() {
+                        "armv7" => Arch::Armv7,
+                        "armv6" => Arch::Armv6,
+                        _ => return Err(invalid()),
+                    }
+                }
+                ("float-abi", Value::String(abi)) => {
+                    spec.float_abi = Some(match abi.as_str() {
+                        "hard" => FloatAbi::Hard,
+                        "soft" => FloatAbi::Soft,
+                        _ => return Err(invalid()),
+                    })
+                }
+                ("hardware-division", Value::Boolean(available)) => {
+                    hardware_division = Some(*available)
+                }
+                ("expression-registers", Value::Integer(count @ 1..=4)) => {
+                    spec.expression_registers = *count as usize
+                }
+                ("word-size", Value::Integer(32)) | ("argument-registers", Value::Integer(4)) => {}
+                ("word-size", Value::Integer(_)) => {
+                    return Err(format!("line {}: only 32 bit words are supported", line))
+                }
+                ("argument-registers", Value::Integer(_)) => {
+                    return Err(format!(
+                        "line {}: only 4 argument registers are supported",
+                        line
+                    ))
+                }
+                (
+                    "arch"
+                    | "float-abi"
+                    | "hardware-division"
+                    | "expression-registers"
+                    | "word-size"
+                    | "argument-registers",
+                    _,
+                ) => return Err(invalid()),
+                _ => return Err(format!("line {}: unknown key `{}`", line, key)),
+            }
+        }
+        spec.hardware_division = match (spec.arch, hardware_division) {
+            (Arch::Armv6, Some(true)) => {
+                return Err(String::from("ARMv6 has no division instructions"))
+            }
+            (Arch::Armv6, _) => false,
+            (Arch::Armv7, available) => available.unwrap_or(true),
+        };
+        Ok(spec)
+    }
+}
+
+// The key and the value of the line, `None` for blank lines and comments.
+fn parse_line(line: &str) -> Result<Option<(String, Value)>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    if line.starts_with('[') {
+        return Err(String::from("tables are not supported"));
+    }
+    let (key, rest) = line
+        .split_once('=')
+        .ok_or_else(|| String::from("expected `key = value`"))?;
+    let key = key.trim();
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!("invalid key `{}`", key));
+    }
+    let rest = rest.trim_start();
+    let (value, rest) = match rest.strip_prefix('"') {
+        Some(string) => {
+            let end = string
+                .find('"')
+                .ok_or_else(|| String::from("unterminated string"))?;
+            (
+                Value::String(String::from(&string[..end])),
+                &string[end + 1..],
+            )
+        }
+        None => {
+            let end = rest.find('#').unwrap_or(rest.len());
+            let value = match rest[..end].trim() {
+                "true" => Value::Boolean(true),
+                "false" => Value::Boolean(false),
+                number => Value::Integer(
+                    number
+                        .replace('_', "")
+                        .parse()
+                        .map_err(|_| format!("invalid value `{}`", number))?,
+                ),
+            };
+            (value, &rest[end..])
+        }
+    };
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected `{}` after the value", rest));
+    }
+    Ok(Some((String::from(key), value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specifications() {
+        assert_eq!(TargetSpec::parse(""), Ok(TargetSpec::default()));
+        assert_eq!(
+            TargetSpec::parse(
+                "# Raspberry Pi 1\n\
+                 arch = \"armv6\"\n\
+                 float-abi = \"hard\" # like Raspbian\n\
+                 \n\
+                 word-size = 32\n\
+                 argument-registers = 4\n\
+                 expression-registers = 2\n"
+            ),
+            Ok(TargetSpec {
+                arch: Arch::Armv6,
+                float_abi: Some(FloatAbi::Hard),
+                hardware_division: false,
+                expression_registers: 2,
+            })
+        );
+        assert_eq!(
+            TargetSpec::parse("float-abi = \"soft\"\nhardware-division = false"),
+            Ok(TargetSpec {
+                float_abi: Some(FloatAbi::Soft),
+                hardware_division: false,
+                ..TargetSpec::default()
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_specifications() {
+        let error = |text| TargetSpec::parse(text).unwrap_err();
+        assert_eq!(
+            error("arch = \"armv8\""),
+            "line 1: invalid value \"armv8\" for `arch`"
+        );
+        assert_eq!(error("\nregisters = 16"), "line 2: unknown key `registers`");
+        assert_eq!(
+            error("word-size = 64"),
+            "line 1: only 32 bit words are supported"
+        );
+        assert_eq!(
+            error("expression-registers = 5"),
+            "line 1: invalid value 5 for `expression-registers`"
+        );
+        assert_eq!(
+            error("hardware-division = \"yes\""),
+            "line 1: invalid value \"yes\" for `hardware-division`"
+        );
+        assert_eq!(
+            error("arch = \"armv6\"\nhardware-division = true"),
+            "ARMv6 has no division instructions"
+        );
+        assert_eq!(
+            error("arch = \"armv7\"\narch = \"armv6\""),
+            "line 2: `arch` is given twice"
+        );
+        assert_eq!(error("[target]"), "line 1: tables are not supported");
+        assert_eq!(error("arch"), "line 1: expected `key = value`");
+        assert_eq!(error("arch = \"armv7"), "line 1: unterminated string");
+        assert_eq!(
+            error("arch = \"armv7\" armv6"),
+            "line 1: unexpected `armv6` after the value"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Turns the ARM32 code into Thumb-2 for `--thumb`. In unified syntax most
+//! instructions are written the same in both states, so only what Thumb-2
+//! cannot encode is rewritten: conditional instructions other than branches
+//! need an `it` in front, loads and stores only reach 255 bytes below their
+//! base, and returns have to stay in the Thumb state.
+use std::collections::BTreeSet;
+
+use crate::phases::instruction::{Address, Condition, Instruction, Operand, Register};
+use crate::phases::runtime;
+
+/// The lowest offset from the base a Thumb-2 load or store can encode.
+const MIN_OFFSET: i32 = -255;
+
+pub fn thumb(code: Vec<Instruction>) -> Vec<Instruction> {
+    thumb_mapped(code).0
+}
+
+/// The Thumb-2 code along with the index of the ARM instruction each
+/// instruction comes from.
+pub fn thumb_mapped(code: Vec<Instruction>) -> (Vec<Instruction>, Vec<usize>) {
+    // the linker only switches to the Thumb state when calling symbols
+    // marked as Thumb functions
+    let functions: BTreeSet<String> = code
+        .iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Global(name) | Instruction::Local(name) | Instruction::Bl(name) => {
+                Some(name.clone())
+            }
+            _ => None,
+        })
+        .collect();
+    let mut thumb = Vec::with_capacity(code.len());
+    let mut origins = Vec::with_capacity(code.len());
+    let mut in_text = true;
+    for (origin, instruction) in code.into_iter().enumerate() {
+        match &instruction {
+            Instruction::Section(name) => in_text = name == ".text",
+            Instruction::Label(name) if in_text && functions.contains(name) => {
+                thumb.push(Instruction::Directive(String::from(".thumb_func")));
+            }
+            _ => {}
+        }
+        for instruction in rewrite(instruction) {
+            match condition(&instruction) {
+                Some(Condition::Always) | None => {}
+                Some(condition) => thumb.push(Instruction::It(condition)),
+            }
+            thumb.push(instruction);
+        }
+        origins.resize(thumb.len(), origin);
+    }
+    (thumb, origins)
+}
+
+fn rewrite(instruction: Instruction) -> Vec<Instruction> {
+    match instruction {
+        // `mov pc, lr` does not return to ARM code, e.g. the C library
+        Instruction::Mov(Condition::Always, Register::Pc, Operand::Register(Register::Lr)) => {
+            vec![Instruction::Bx(Register::Lr)]
+        }
+        Instruction::Svc(runtime::SEMIHOSTING_SVC) => {
+            vec![Instruction::Bkpt(runtime::SEMIHOSTING_BKPT)]
+        }
+        // loads go through their own register, stores through `ip`, which the
+        // code generator never stores
+        Instruction::Ldr(cond, rd, Address::Offset(base, Operand::Immediate(offset)))
+            if offset < MIN_OFFSET =>
+        {
+            vec![
+                Instruction::Sub(cond, rd, base, Operand::Immediate(-offset)),
+                Instruction::Ldr(cond, rd, Address::Register(rd)),
+            ]
+        }
+        Instruction::Ldrb(cond, rd, Address::Offset(base, Operand::Immediate(offset)))
+            if offset < MIN_OFFSET =>
+        {
+            vec![
+                Instruction::Sub(cond, rd, base, Operand::Immediate(-offset)),
+                Instruction::Ldrb(cond, rd, Address::Register(rd)),
+            ]
+        }
+        Instruction::Str(cond, rd, Address::Offset(base, Operand::Immediate(offset)))
+            if offset < MIN_OFFSET =>
+        {
+            vec![
+                Instruction::Sub(cond, Register::Ip, base, Operand::Immediate(-offset)),
+                Instruction::Str(cond, rd, Address::Register(Register::Ip)),
+            ]
+        }
+        instruction => vec![instruction],
+    }
+}
+
+// Conditional branches have encodings of their own.
+fn condition(instruction: &Instruction) -> Option<Condition> {
+    match instruction {
+        Instruction::Mov(cond, _, _)
+        | Instruction::Ldr(cond, _, _)
+        | Instruction::Ldrb(cond, _, _)
+        | Instruction::Str(cond, _, _)
+        | Instruction::Add(cond, _, _, _)
+        | Instruction::Sub(cond, _, _, _)
+        | Instruction::Rsb(cond, _, _, _)
+        | Instruction::Lsl(cond, _, _, _)
+        | Instruction::Lsr(cond, _, _, _)
+        | Instruction::Ror(cond, _, _, _) => Some(*cond),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(name: &str) -> Instruction {
+        Instruction::Label(String::from(name))
+    }
+
+    #[test]
+    fn rewrites() {
+        let local = |offset| Address::Offset(Register::Fp, Operand::Immediate(offset));
+        let code = vec![
+            Instruction::Global(String::from("main")),
+            label("main"),
+            Instruction::Mov(Condition::Eq, Register::R0, Operand::Immediate(1)),
+            Instruction::B(Condition::Ne, String::from(".L1")),
+            label(".L1"),
+            Instruction::Ldr(Condition::Always, Register::R0, local(-8)),
+            Instruction::Ldr(Condition::Always, Register::R0, local(-300)),
+            Instruction::Str(Condition::Always, Register::R1, local(-300)),
+            Instruction::Svc(runtime::SEMIHOSTING_SVC),
+            Instruction::Mov(
+                Condition::Always,
+                Register::Pc,
+                Operand::Register(Register::Lr),
+            ),
+        ];
+        assert_eq!(
+            thumb(code),
+            vec![
+                Instruction::Global(String::from("main")),
+                Instruction::Directive(String::from(".thumb_func")),
+                label("main"),
+                Instruction::It(Condition::Eq),
+                Instruction::Mov(Condition::Eq, Register::R0, Operand::Immediate(1)),
+                Instruction::B(Condition::Ne, String::from(".L1")),
+                label(".L1"),
+                Instruction::Ldr(Condition::Always, Register::R0, local(-8)),
+                Instruction::Sub(
+                    Condition::Always,
+                    Register::R0,
+                    Register::Fp,
+                    Operand::Immediate(300)
+                ),
+                Instruction::Ldr(
+                    Condition::Always,
+                    Register::R0,
+                    Address::Register(Register::R0)
+                ),
+                Instruction::Sub(
+                    Condition::Always,
+                    Register::Ip,
+                    Register::Fp,
+                    Operand::Immediate(300)
+                ),
+                Instruction::Str(
+                    Condition::Always,
+                    Register::R1,
+                    Address::Register(Register::Ip)
+                ),
+                Instruction::Bkpt(runtime::SEMIHOSTING_BKPT),
+                Instruction::Bx(Register::Lr),
+            ]
+        );
+    }
+}
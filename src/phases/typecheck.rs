@@ -1,17 +1,129 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::rc::Rc;
+
 use crate::types::Type;
 use linked_hash_map::LinkedHashMap;
 
 use crate::ast::Ast;
+use crate::diagnostic;
+use crate::phases::codegen::{
+    ABS, ABS_INTRINSIC, ARG_COUNT, ARG_NUMBER, CLZ, CLZ_INTRINSIC, CONCAT, FLOAT_ADD, FLOAT_DIVIDE,
+    FLOAT_EQUAL, FLOAT_MULTIPLY, FLOAT_SUBTRACT, MAX, MAX_INTRINSIC, MIN, MIN_INTRINSIC,
+    NUMBER_TO_STRING, POP, POPCOUNT, PUSH, ROTATE, ROTATE_INTRINSIC, SLICE,
+};
+use crate::phases::const_eval::{self, Constant, Constants};
+use crate::phases::runtime;
+use crate::span::Span;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub code: &'static str,
+    pub message: String,
+    /// The innermost statement with the error, when the checker was given
+    /// the spans of the statements.
+    pub span: Option<Span>,
+}
+
+impl TypeError {
+    pub fn new(code: &'static str, message: String) -> TypeError {
+        TypeError {
+            code,
+            message,
+            span: None,
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The functions every program can call without declaring them.
+pub fn builtins() -> LinkedHashMap<String, Type> {
+    let mut functions = LinkedHashMap::new();
+    // I kind of use `putchar` function in test so I add it to the available funcions at start
+    let mut putchar_parameters = LinkedHashMap::new();
+    putchar_parameters.insert(String::from("x1"), Type::Number);
+    functions.insert(
+        String::from("putchar"),
+        Type::Function {
+            parameter_types: putchar_parameters,
+            return_type: Box::new(Type::Void),
+        },
+    );
+    functions.insert(
+        String::from(ARG_COUNT),
+        Type::Function {
+            parameter_types: LinkedHashMap::new(),
+            return_type: Box::new(Type::Number),
+        },
+    );
+    let mut number_to_string_parameters = LinkedHashMap::new();
+    number_to_string_parameters.insert(String::from("n"), Type::Number);
+    functions.insert(
+        String::from(NUMBER_TO_STRING),
+        Type::Function {
+            parameter_types: number_to_string_parameters,
+            return_type: Box::new(Type::Array {
+                element_type: Box::new(Type::Number),
+            }),
+        },
+    );
+    let mut arg_number_parameters = LinkedHashMap::new();
+    arg_number_parameters.insert(String::from("i"), Type::Number);
+    functions.insert(
+        String::from(ARG_NUMBER),
+        Type::Function {
+            parameter_types: arg_number_parameters,
+            return_type: Box::new(Type::Number),
+        },
+    );
+    for (name, parameters) in [
+        (MIN, &["x", "y"][..]),
+        (MAX, &["x", "y"]),
+        (ABS, &["x"]),
+        (CLZ, &["x"]),
+        (POPCOUNT, &["x"]),
+        (ROTATE, &["x", "n"]),
+    ] {
+        functions.insert(
+            String::from(name),
+            Type::Function {
+                parameter_types: parameters
+                    .iter()
+                    .map(|parameter| (String::from(*parameter), Type::Number))
+                    .collect(),
+                return_type: Box::new(Type::Number),
+            },
+        );
+    }
+    functions
+}
 
 pub trait TypeChecker {
-    fn check(&mut self, ast: &Ast) -> Type;
+    fn check(&mut self, ast: &Ast) -> Result<Type, TypeError>;
 }
 
 #[derive(Debug)]
 pub struct StaticTypeChecker {
     locals: LinkedHashMap<String, Type>,
+    globals: LinkedHashMap<String, Type>,
     functions: LinkedHashMap<String, Type>,
     current_return_type: Option<Type>,
+    // the values of the `const` bindings in scope, which are also in the
+    // locals or the globals with their types
+    constants: Constants,
+    // `==` and `!=` between two strings, by their address in the checked
+    // tree
+    string_comparisons: BTreeSet<*const Ast>,
+    // arithmetic and comparisons of floats, likewise
+    float_operations: BTreeSet<*const Ast>,
+    // the spans of the statements by their address, shared with the
+    // checkers of the functions
+    statement_spans: Rc<BTreeMap<*const Ast, Span>>,
 }
 
 impl StaticTypeChecker {
@@ -22,95 +134,383 @@ impl StaticTypeChecker {
     ) -> StaticTypeChecker {
         StaticTypeChecker {
             locals,
+            globals: LinkedHashMap::new(),
             functions,
             current_return_type,
+            constants: Constants::new(),
+            string_comparisons: BTreeSet::new(),
+            float_operations: BTreeSet::new(),
+            statement_spans: Rc::new(BTreeMap::new()),
         }
     }
 
-    fn assert_type(lhs: Type, rhs: Type) {
+    /// Gives the errors the span of the statement they are in, see
+    /// `parser::parse_program_with_spans` for the spans of a program's
+    /// statements.
+    pub fn with_statement_spans(
+        mut self,
+        statement_spans: BTreeMap<*const Ast, Span>,
+    ) -> StaticTypeChecker {
+        self.statement_spans = Rc::new(statement_spans);
+        self
+    }
+
+    // Points the error at the statement unless a statement nested in it
+    // was found to have it.
+    fn locate(&self, mut error: TypeError, statement: &Ast) -> TypeError {
+        if error.span.is_none() {
+            error.span = self
+                .statement_spans
+                .get(&(statement as *const Ast))
+                .copied();
+        }
+        error
+    }
+
+    fn assert_type(lhs: Type, rhs: Type) -> Result<(), TypeError> {
         if lhs == Type::Undefined || rhs == Type::Undefined {
-            return;
+            return Ok(());
         }
         if lhs != rhs {
-            panic!("Type mismatch {:?} != {:?}", lhs, rhs);
+            return Err(TypeError::new(
+                diagnostic::TYPE_MISMATCH,
+                format!("Type mismatch {} != {}", lhs, rhs),
+            ));
         }
+        Ok(())
     }
-}
 
-impl TypeChecker for StaticTypeChecker {
-    fn check(&mut self, ast: &Ast) -> Type {
-        match ast {
-            Ast::Number(_) => Type::Number,
-            Ast::Bool(_) => Type::Boolean,
-            Ast::Undefined => Type::Undefined,
-            Ast::Null => Type::Void,
-            Ast::Not(expr) => {
-                StaticTypeChecker::assert_type(Type::Boolean, self.check(expr));
-                Type::Boolean
-            }
-            Ast::Addition(lhs, rhs)
-            | Ast::Subtraction(lhs, rhs)
-            | Ast::Multiplication(lhs, rhs)
-            | Ast::Division(lhs, rhs) => {
-                StaticTypeChecker::assert_type(Type::Number, self.check(lhs));
-                StaticTypeChecker::assert_type(Type::Number, self.check(rhs));
-                Type::Number
-            }
-            Ast::Equal(lhs, rhs) | Ast::NotEqual(lhs, rhs) => {
-                StaticTypeChecker::assert_type(self.check(lhs), self.check(rhs));
-                Type::Boolean
+    // Locals declared inside blocks, branches and loop bodies are not
+    // visible after them, the code generator reuses their stack slots.
+    // Neither are the functions declared in them inside of functions, see
+    // `phases::nested`.
+    fn check_in_scope(&mut self, ast: &Ast) -> Result<Type, TypeError> {
+        let (locals, constants) = (self.locals.clone(), self.constants.clone());
+        let functions = self
+            .current_return_type
+            .as_ref()
+            .map(|_| self.functions.clone());
+        let result = self.check(ast);
+        self.locals = locals;
+        self.constants = constants;
+        if let Some(functions) = functions {
+            self.functions = functions;
+        }
+        result
+    }
+
+    // The array builtins take arrays of any element type, which the
+    // signatures of functions cannot express.
+    fn array_builtin_arity(name: &str, arguments: &[Ast]) -> Result<(), TypeError> {
+        let expected = match name {
+            POP => 1,
+            SLICE => 3,
+            _ => 2,
+        };
+        if arguments.len() != expected {
+            return Err(TypeError::new(
+                diagnostic::ARGUMENT_COUNT,
+                format!(
+                    "`{}` takes {} arguments, but got {}",
+                    name,
+                    expected,
+                    arguments.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn array_builtin_type(
+        name: &str,
+        arguments: &[Ast],
+        mut types: Vec<Type>,
+    ) -> Result<Type, TypeError> {
+        let element_type = match types.remove(0) {
+            Type::Array { element_type } => *element_type,
+            other => {
+                return Err(TypeError::new(
+                    diagnostic::NOT_AN_ARRAY,
+                    format!("Expected an array, but got {}", other),
+                ))
             }
-            Ast::Var(name, expr) => {
-                let t = self.check(expr);
-                self.locals.insert(name.clone(), t);
-                Type::Void
+        };
+        match name {
+            PUSH => {
+                if !matches!(arguments[0], Ast::Identifier(_)) {
+                    return Err(TypeError::new(
+                        diagnostic::PUSH_TARGET,
+                        String::from("The array passed to `push` must be a variable"),
+                    ));
+                }
+                StaticTypeChecker::assert_type(element_type, types.remove(0))?;
+                Ok(Type::Void)
             }
-            Ast::Identifier(name) => {
-                if let Some(t) = self.locals.get(name) {
-                    t.clone()
-                } else {
-                    panic!("Undefined variable {}", name);
+            POP => Ok(element_type),
+            SLICE => {
+                for index in types {
+                    StaticTypeChecker::assert_type(Type::Number, index)?;
                 }
+                Ok(Type::Array {
+                    element_type: Box::new(element_type),
+                })
             }
-            Ast::Assignment(name, expr) => {
-                if let Some(t) = self.locals.get(name) {
-                    StaticTypeChecker::assert_type(t.clone(), self.check(expr));
+            _ => {
+                let array = Type::Array {
+                    element_type: Box::new(element_type),
+                };
+                StaticTypeChecker::assert_type(array.clone(), types.remove(0))?;
+                Ok(array)
+            }
+        }
+    }
+
+    // Expressions are checked with a stack of their own instead of
+    // recursively, as a chain of operators like `x + x + ...` is as deep as
+    // it is long. An operation is checked after its operands, which are
+    // checked in order.
+    fn check_expression(&mut self, expression: &Ast) -> Result<Type, TypeError> {
+        enum Step<'a> {
+            Enter(&'a Ast),
+            Exit(&'a Ast, usize),
+        }
+        let mut steps = vec![Step::Enter(expression)];
+        let mut types = Vec::new();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Enter(ast) => {
+                    let operands = self.operands(ast)?;
+                    steps.push(Step::Exit(ast, operands.len()));
+                    steps.extend(operands.into_iter().rev().map(Step::Enter));
+                }
+                Step::Exit(ast, count) => {
+                    let operands = types.split_off(types.len() - count);
+                    types.push(self.operation_type(ast, operands)?);
+                }
+            }
+        }
+        Ok(types.pop().expect("an expression has a type"))
+    }
+
+    // The operands of the expression in the order they are checked, after
+    // what can be checked before them.
+    fn operands<'a>(&self, ast: &'a Ast) -> Result<Vec<&'a Ast>, TypeError> {
+        match ast {
+            Ast::ArrayLiteral(elements) if elements.is_empty() => Err(TypeError::new(
+                diagnostic::EMPTY_ARRAY,
+                String::from("Cannot infer type from an empty array"),
+            )),
+            Ast::ArrayLookup(array, index) => Ok(vec![index, array]),
+            Ast::Call(name, arguments) if [PUSH, POP, CONCAT, SLICE].contains(&name.as_str()) => {
+                StaticTypeChecker::array_builtin_arity(name, arguments)?;
+                Ok(arguments.iter().collect())
+            }
+            Ast::Call(name, arguments) => match self.functions.get(name) {
+                Some(Type::Function {
+                    parameter_types: ps,
+                    ..
+                }) if arguments.len() != ps.len() => Err(TypeError::new(
+                    diagnostic::ARGUMENT_COUNT,
+                    format!(
+                        "`{}` takes {} arguments, but got {}",
+                        name,
+                        ps.len(),
+                        arguments.len()
+                    ),
+                )),
+                Some(_) => Ok(arguments.iter().collect()),
+                None => Err(TypeError::new(
+                    diagnostic::UNDEFINED_FUNCTION,
+                    format!("Use of undefined function {}", name),
+                )),
+            },
+            ast => Ok(ast.children()),
+        }
+    }
+
+    // The type of the expression, given the types of its operands.
+    fn operation_type(&mut self, ast: &Ast, operands: Vec<Type>) -> Result<Type, TypeError> {
+        match ast {
+            Ast::Number(_) => Ok(Type::Number),
+            Ast::Float(_) => Ok(Type::Float),
+            Ast::Bool(_) => Ok(Type::Boolean),
+            Ast::String(_) => Ok(Type::String),
+            Ast::Undefined => Ok(Type::Undefined),
+            Ast::Null => Ok(Type::Void),
+            Ast::Identifier(name) => self.variable(name).cloned(),
+            Ast::Not(_) => {
+                StaticTypeChecker::assert_type(Type::Boolean, operands[0].clone())?;
+                Ok(Type::Boolean)
+            }
+            Ast::Addition(_, _)
+            | Ast::Subtraction(_, _)
+            | Ast::Multiplication(_, _)
+            | Ast::Division(_, _) => {
+                let (lhs, rhs) = (operands[0].clone(), operands[1].clone());
+                // numbers and floats are never mixed, there are no implicit
+                // conversions
+                let operand_type = if lhs == Type::Float || rhs == Type::Float {
+                    self.float_operations.insert(ast as *const Ast);
+                    Type::Float
                 } else {
-                    panic!("Undefined variable {}", name);
+                    Type::Number
+                };
+                StaticTypeChecker::assert_type(operand_type.clone(), lhs)?;
+                StaticTypeChecker::assert_type(operand_type.clone(), rhs)?;
+                Ok(operand_type)
+            }
+            Ast::Equal(_, _) | Ast::NotEqual(_, _) => {
+                let (lhs, rhs) = (operands[0].clone(), operands[1].clone());
+                if lhs == Type::String && rhs == Type::String {
+                    self.string_comparisons.insert(ast as *const Ast);
+                }
+                if lhs == Type::Float && rhs == Type::Float {
+                    self.float_operations.insert(ast as *const Ast);
                 }
-                Type::Void
+                StaticTypeChecker::assert_type(lhs, rhs)?;
+                Ok(Type::Boolean)
             }
-            Ast::ArrayLiteral(elements) => {
-                if elements.is_empty() {
-                    panic!("Cannot infer type from an empty array");
+            Ast::ArrayLiteral(_) => {
+                for pair in operands.windows(2) {
+                    StaticTypeChecker::assert_type(pair[0].clone(), pair[1].clone())?;
                 }
-                let types: Vec<Type> = elements.iter().map(|x| self.check(x)).collect();
-                let array_type = types
-                    .iter()
-                    .reduce(|lhs, rhs| {
-                        StaticTypeChecker::assert_type(lhs.clone(), rhs.clone());
-                        &rhs
-                    })
-                    .unwrap();
-                Type::Array {
-                    element_type: Box::new(array_type.clone()),
+                Ok(Type::Array {
+                    element_type: Box::new(operands[operands.len() - 1].clone()),
+                })
+            }
+            Ast::ArrayLength(_) => match &operands[0] {
+                Type::Array { element_type: _ } | Type::String => Ok(Type::Number),
+                other => Err(TypeError::new(
+                    diagnostic::NOT_AN_ARRAY,
+                    format!("Expected an array, but got {}", other),
+                )),
+            },
+            Ast::ArrayLookup(_, _) => {
+                StaticTypeChecker::assert_type(Type::Number, operands[0].clone())?;
+                match &operands[1] {
+                    // the characters are looked up by their code
+                    Type::Array { element_type: _ } | Type::String => Ok(Type::Number),
+                    other => Err(TypeError::new(
+                        diagnostic::NOT_AN_ARRAY,
+                        format!("Expected an array, but got {}", other),
+                    )),
                 }
             }
-            Ast::ArrayLength(expr) => {
-                if let Type::Array { element_type: _ } = self.check(expr) {
-                    Type::Number
-                } else {
-                    panic!("Expected an array, but got {:?}", self.check(expr));
+            Ast::Call(name, arguments) if [PUSH, POP, CONCAT, SLICE].contains(&name.as_str()) => {
+                StaticTypeChecker::array_builtin_type(name, arguments, operands)
+            }
+            Ast::Call(name, _) => match &self.functions[name] {
+                Type::Function {
+                    parameter_types: ps,
+                    return_type: rt,
+                } => {
+                    for (arg, (_, param)) in operands.into_iter().zip(ps.iter()) {
+                        StaticTypeChecker::assert_type(arg, param.clone())?;
+                    }
+                    Ok(*rt.clone())
+                }
+                _ => unreachable!(),
+            },
+            statement => unreachable!("{:?} is not an expression", statement),
+        }
+    }
+
+    // Locals shadow the globals.
+    fn variable(&self, name: &str) -> Result<&Type, TypeError> {
+        self.locals
+            .get(name)
+            .or_else(|| self.globals.get(name))
+            .ok_or_else(|| {
+                TypeError::new(
+                    diagnostic::UNDEFINED_VARIABLE,
+                    format!("Undefined variable {}", name),
+                )
+            })
+    }
+}
+
+impl TypeChecker for StaticTypeChecker {
+    fn check(&mut self, ast: &Ast) -> Result<Type, TypeError> {
+        match ast {
+            Ast::Number(_)
+            | Ast::Float(_)
+            | Ast::Bool(_)
+            | Ast::String(_)
+            | Ast::Undefined
+            | Ast::Null
+            | Ast::Identifier(_)
+            | Ast::Not(_)
+            | Ast::Addition(_, _)
+            | Ast::Subtraction(_, _)
+            | Ast::Multiplication(_, _)
+            | Ast::Division(_, _)
+            | Ast::Equal(_, _)
+            | Ast::NotEqual(_, _)
+            | Ast::ArrayLiteral(_)
+            | Ast::ArrayLength(_)
+            | Ast::ArrayLookup(_, _)
+            | Ast::Call(_, _) => self.check_expression(ast),
+            // Variables declared outside of any function are globals, their
+            // initial value is placed in the data section.
+            Ast::Var(name, expr) if self.current_return_type.is_none() => {
+                let constant = const_eval::evaluate(expr, &self.constants);
+                if !matches!(expr.as_ref(), Ast::Null | Ast::Undefined)
+                    && !matches!(
+                        constant,
+                        Some(Constant::Number(_) | Constant::Float(_) | Constant::Bool(_))
+                    )
+                {
+                    return Err(TypeError::new(
+                        diagnostic::GLOBAL_INITIALIZER,
+                        format!(
+                            "Global variable {} must be initialized with a constant",
+                            name
+                        ),
+                    ));
                 }
+                let t = self.check(expr)?;
+                self.globals.insert(name.clone(), t);
+                self.constants.remove(name);
+                Ok(Type::Void)
             }
-            Ast::ArrayLookup(array, index) => {
-                StaticTypeChecker::assert_type(Type::Number, self.check(index));
-                if let Type::Array { element_type: _ } = self.check(array) {
-                    Type::Number
+            Ast::Var(name, expr) => {
+                let t = self.check(expr)?;
+                self.locals.insert(name.clone(), t);
+                self.constants.remove(name);
+                Ok(Type::Void)
+            }
+            Ast::Const(name, expr) => {
+                let t = self.check(expr)?;
+                let value = const_eval::evaluate(expr, &self.constants).ok_or_else(|| {
+                    TypeError::new(
+                        diagnostic::NOT_CONSTANT,
+                        format!("The value of constant {} must be a constant", name),
+                    )
+                })?;
+                if self.current_return_type.is_none() {
+                    self.globals.insert(name.clone(), t);
                 } else {
-                    panic!("Expected an array, but got {:?}", self.check(array));
+                    self.locals.insert(name.clone(), t);
                 }
+                self.constants.insert(name.clone(), value);
+                Ok(Type::Void)
             }
+            Ast::Assignment(name, _) if self.constants.contains_key(name) => Err(TypeError::new(
+                diagnostic::ASSIGNMENT_TO_CONSTANT,
+                format!("Cannot assign to constant {}", name),
+            )),
+            Ast::Assignment(name, expr) => {
+                let t = self.variable(name)?.clone();
+                StaticTypeChecker::assert_type(t, self.check(expr)?)?;
+                Ok(Type::Void)
+            }
+            Ast::Function(name, _, _) if runtime::is_reserved(name) => Err(TypeError::new(
+                diagnostic::RESERVED_NAME,
+                format!(
+                    "Function `{}` takes the name of a symbol the generated code uses",
+                    name
+                ),
+            )),
             Ast::Function(name, function_type, block) => {
                 self.functions.insert(name.clone(), function_type.clone());
                 let (parameters, rt) = match function_type {
@@ -125,57 +525,596 @@ impl TypeChecker for StaticTypeChecker {
                     self.functions.clone(),
                     Some(*rt.clone()),
                 );
-                env.check(block);
-                Type::Void
-            }
-            Ast::Call(name, arguments) => {
-                let called_f_signature = self
-                    .functions
-                    .clone()
-                    .get(name)
-                    .unwrap_or_else(|| panic!("Use of undefined function {}", name))
-                    .clone();
-                if let Type::Function {
-                    parameter_types: ps,
-                    return_type: rt,
-                } = called_f_signature
-                {
-                    let arg_types: Vec<Type> = arguments.iter().map(|x| self.check(x)).collect();
-                    let param_types: Vec<Type> = ps.iter().map(|(_, t)| t.clone()).collect();
-                    for (arg, param) in arg_types.iter().zip(param_types) {
-                        StaticTypeChecker::assert_type(arg.clone(), param);
-                    }
-                    *rt
-                } else {
-                    unreachable!()
+                env.globals = self.globals.clone();
+                env.constants = self.constants.clone();
+                env.statement_spans = Rc::clone(&self.statement_spans);
+                for parameter in parameters.keys() {
+                    env.constants.remove(parameter);
                 }
+                env.check(block)?;
+                self.string_comparisons.extend(env.string_comparisons);
+                self.float_operations.extend(env.float_operations);
+                Ok(Type::Void)
             }
             Ast::Return(expr) => {
-                let t = self.check(expr);
+                let t = self.check(expr)?;
                 if let Some(rt) = self.current_return_type.clone() {
-                    StaticTypeChecker::assert_type(rt, t);
-                    Type::Void
+                    StaticTypeChecker::assert_type(rt, t)?;
+                    Ok(Type::Void)
                 } else {
-                    panic!("Return statement used outside of any function.");
+                    Err(TypeError::new(
+                        diagnostic::RETURN_OUTSIDE_FUNCTION,
+                        String::from("Return statement used outside of any function."),
+                    ))
                 }
             }
+            Ast::Delete(expr) => match self.check(expr)? {
+                Type::Array { element_type: _ } => Ok(Type::Void),
+                other => Err(TypeError::new(
+                    diagnostic::NOT_AN_ARRAY,
+                    format!("Expected an array, but got {}", other),
+                )),
+            },
             Ast::If(condition, consequence, alternative) => {
-                self.check(condition);
-                self.check(consequence);
-                self.check(alternative);
-                Type::Void
+                self.check(condition)?;
+                self.check_in_scope(consequence)?;
+                self.check_in_scope(alternative)?;
+                Ok(Type::Void)
             }
             Ast::While(condition, body) => {
-                self.check(condition);
-                self.check(body);
-                Type::Void
+                self.check(condition)?;
+                self.check_in_scope(body)?;
+                Ok(Type::Void)
+            }
+            Ast::Import(_, _) | Ast::Export(_) if self.current_return_type.is_some() => {
+                Err(TypeError::new(
+                    diagnostic::NOT_AT_TOP_LEVEL,
+                    String::from("Imports and exports are only allowed at the top level"),
+                ))
+            }
+            // Imported signatures are provided upfront by the module loader.
+            Ast::Import(_, _) => Ok(Type::Void),
+            Ast::Extern(_, _) if self.current_return_type.is_some() => Err(TypeError::new(
+                diagnostic::NOT_AT_TOP_LEVEL,
+                String::from("Extern declarations are only allowed at the top level"),
+            )),
+            Ast::Extern(name, function_type) => {
+                self.functions.insert(name.clone(), function_type.clone());
+                Ok(Type::Void)
+            }
+            Ast::Export(function) => self.check(function),
+            Ast::Namespace(_, _) if self.current_return_type.is_some() => Err(TypeError::new(
+                diagnostic::NOT_AT_TOP_LEVEL,
+                String::from("Namespaces are only allowed at the top level"),
+            )),
+            // The members see each other by their names, the rest of the
+            // program only sees the exported ones by their qualified names.
+            Ast::Namespace(namespace, statements) => {
+                let functions = self.functions.clone();
+                let mut exported = Vec::new();
+                for statement in statements {
+                    match statement {
+                        Ast::Export(function) => {
+                            if let Ast::Function(name, function_type, _) = function.as_ref() {
+                                exported.push((
+                                    format!("{}.{}", namespace, name),
+                                    function_type.clone(),
+                                ));
+                            }
+                        }
+                        Ast::Function(_, _, _) | Ast::Doc(_) => {}
+                        _ => {
+                            return Err(TypeError::new(
+                                diagnostic::NOT_AT_TOP_LEVEL,
+                                format!("Namespace {} can only contain functions", namespace),
+                            ))
+                        }
+                    }
+                    self.check(statement)?;
+                }
+                self.functions = functions;
+                self.functions.extend(exported);
+                Ok(Type::Void)
+            }
+            Ast::Doc(_) => Ok(Type::Void),
+            Ast::StaticAssert(condition, message) => {
+                StaticTypeChecker::assert_type(Type::Boolean, self.check(condition)?)?;
+                match const_eval::evaluate(condition, &self.constants) {
+                    Some(Constant::Bool(true)) => Ok(Type::Void),
+                    Some(_) => Err(TypeError::new(
+                        diagnostic::STATIC_ASSERTION,
+                        format!("Static assertion failed: {}", message),
+                    )),
+                    None => Err(TypeError::new(
+                        diagnostic::NOT_CONSTANT,
+                        String::from("The condition of `static_assert` must be a constant"),
+                    )),
+                }
             }
             Ast::Block(statements) => {
+                let (locals, constants) = (self.locals.clone(), self.constants.clone());
+                let functions = self
+                    .current_return_type
+                    .as_ref()
+                    .map(|_| self.functions.clone());
                 for statement in statements {
-                    self.check(statement);
+                    self.check(statement)
+                        .map_err(|error| self.locate(error, statement))?;
+                }
+                self.locals = locals;
+                self.constants = constants;
+                if let Some(functions) = functions {
+                    self.functions = functions;
                 }
-                Type::Void
+                Ok(Type::Void)
+            }
+        }
+    }
+}
+
+/// Typechecks the program and replaces the operations whose code depends on
+/// the types of their operands with calls, as the generators do not know
+/// the types. Comparisons of strings call the runtime routine comparing
+/// their characters, `==` on any other value compares the words themselves.
+/// Arithmetic and comparisons of floats call the `codegen::FLOAT_*`
+/// builtins, which the generators expand in place, and so do the numeric
+/// and bit manipulation builtins such as `min` unless the program defines
+/// its own.
+pub fn lower_typed_operations(
+    program: &mut Ast,
+    functions: LinkedHashMap<String, Type>,
+) -> Result<(), TypeError> {
+    // the operands are lowered first, replacing the operation moves them
+    fn lower(
+        ast: &mut Ast,
+        address: *const Ast,
+        checker: &StaticTypeChecker,
+        defined: &BTreeSet<String>,
+    ) {
+        if let Ast::Call(name, _) = ast {
+            let intrinsic = match name.as_str() {
+                MIN => MIN_INTRINSIC,
+                MAX => MAX_INTRINSIC,
+                ABS => ABS_INTRINSIC,
+                CLZ => CLZ_INTRINSIC,
+                POPCOUNT => runtime::POPCOUNT_ROUTINE,
+                ROTATE => ROTATE_INTRINSIC,
+                _ => return,
+            };
+            if !defined.contains(name) {
+                *name = String::from(intrinsic);
             }
+            return;
         }
+        let equal_routine = if checker.string_comparisons.contains(&address) {
+            runtime::STRING_EQUAL_ROUTINE
+        } else if checker.float_operations.contains(&address) {
+            FLOAT_EQUAL
+        } else {
+            return;
+        };
+        let (name, lhs, rhs, negated) = match std::mem::replace(ast, Ast::Null) {
+            Ast::Equal(lhs, rhs) => (equal_routine, lhs, rhs, false),
+            Ast::NotEqual(lhs, rhs) => (equal_routine, lhs, rhs, true),
+            Ast::Addition(lhs, rhs) => (FLOAT_ADD, lhs, rhs, false),
+            Ast::Subtraction(lhs, rhs) => (FLOAT_SUBTRACT, lhs, rhs, false),
+            Ast::Multiplication(lhs, rhs) => (FLOAT_MULTIPLY, lhs, rhs, false),
+            Ast::Division(lhs, rhs) => (FLOAT_DIVIDE, lhs, rhs, false),
+            other => unreachable!("{:?} is not a typed operation", other),
+        };
+        let call = Ast::Call(String::from(name), vec![*lhs, *rhs]);
+        *ast = if negated {
+            Ast::Not(Box::new(call))
+        } else {
+            call
+        };
+    }
+    let mut checker = StaticTypeChecker::new(LinkedHashMap::new(), functions, None);
+    checker.check(program)?;
+    let defined = program
+        .declarations()
+        .filter_map(|declaration| match declaration {
+            Ast::Function(name, _, _) | Ast::Extern(name, _) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    program.rewrite_bottom_up(|ast, address| lower(ast, address, &checker, &defined));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format;
+    use crate::parser;
+    use crate::parser::combinators::Parser;
+
+    fn check(source: &str) -> Result<Type, TypeError> {
+        let (_, ast) = parser::make_full_parser(&parser::ParseOptions::default())
+            .parse(source)
+            .unwrap();
+        StaticTypeChecker::new(LinkedHashMap::new(), LinkedHashMap::new(), None).check(&ast)
+    }
+
+    #[test]
+    fn well_typed_program() {
+        let source = "function f(x: number, y: boolean): number {
+                var xs = [x, 2];
+                if (!y) { return xs[0]; } else { return length(xs); }
+            }
+            function g() { return f(1, true) + 1; }";
+        assert_eq!(check(source), Ok(Type::Void));
+    }
+
+    #[test]
+    fn type_mismatch() {
+        let source = "function f(x: boolean) { return x; }";
+        assert_eq!(
+            check(source),
+            Err(TypeError::new(
+                diagnostic::TYPE_MISMATCH,
+                String::from("Type mismatch number != boolean")
+            ))
+        );
+    }
+
+    #[test]
+    fn undefined_function() {
+        assert_eq!(
+            check("function f() { return g(); }"),
+            Err(TypeError::new(
+                diagnostic::UNDEFINED_FUNCTION,
+                String::from("Use of undefined function g")
+            ))
+        );
+    }
+
+    #[test]
+    fn argument_count() {
+        assert_eq!(
+            check("function g(x: number) { return x; } function f() { return g(1, 2); }"),
+            Err(TypeError::new(
+                diagnostic::ARGUMENT_COUNT,
+                String::from("`g` takes 1 arguments, but got 2")
+            ))
+        );
+        assert_eq!(
+            check("extern function abs(x: number): number; function f() { return abs(); }"),
+            Err(TypeError::new(
+                diagnostic::ARGUMENT_COUNT,
+                String::from("`abs` takes 1 arguments, but got 0")
+            ))
+        );
+    }
+
+    #[test]
+    fn extern_functions() {
+        let source = "extern function abs(x: number): number;
+            function f(): number { return abs(0 - 2); }";
+        assert_eq!(check(source), Ok(Type::Void));
+        assert_eq!(
+            check("extern function abs(x: number): number; function f() { return abs(true); }"),
+            Err(TypeError::new(
+                diagnostic::TYPE_MISMATCH,
+                String::from("Type mismatch boolean != number")
+            ))
+        );
+        assert_eq!(
+            check("function f() { extern function abs(x: number): number; }"),
+            Err(TypeError::new(
+                diagnostic::NOT_AT_TOP_LEVEL,
+                String::from("Extern declarations are only allowed at the top level")
+            ))
+        );
+    }
+
+    #[test]
+    fn static_assertions() {
+        let source = "static_assert(2 * 3 == 6, \"arithmetic\");
+            function f(x: number) { static_assert(!(1.5 == 2.5), \"floats\"); return x; }";
+        assert_eq!(check(source), Ok(Type::Void));
+        assert_eq!(
+            check("static_assert(length(\"abc\") == 4, \"three characters\");"),
+            Err(TypeError::new(
+                diagnostic::STATIC_ASSERTION,
+                String::from("Static assertion failed: three characters")
+            ))
+        );
+        assert_eq!(
+            check("function f(x: number) { static_assert(x == 1, \"one\"); return x; }"),
+            Err(TypeError::new(
+                diagnostic::NOT_CONSTANT,
+                String::from("The condition of `static_assert` must be a constant")
+            ))
+        );
+        assert_eq!(
+            check("static_assert(1, \"number\");"),
+            Err(TypeError::new(
+                diagnostic::TYPE_MISMATCH,
+                String::from("Type mismatch boolean != number")
+            ))
+        );
+    }
+
+    #[test]
+    fn constants() {
+        let source = "const N = 2 * 3;
+            var area = N * N;
+            static_assert(N * N == 36, \"area\");
+            function f(N: boolean): boolean { return !N; }
+            function g(x: number) { const M = N + 1; static_assert(M == 7, \"seven\"); return x * M; }";
+        assert_eq!(check(source), Ok(Type::Void));
+        assert_eq!(
+            check("function f(x: number) { const N = x; return N; }"),
+            Err(TypeError::new(
+                diagnostic::NOT_CONSTANT,
+                String::from("The value of constant N must be a constant")
+            ))
+        );
+        assert_eq!(
+            check("const N = 1; function f() { N = 2; return N; }"),
+            Err(TypeError::new(
+                diagnostic::ASSIGNMENT_TO_CONSTANT,
+                String::from("Cannot assign to constant N")
+            ))
+        );
+        assert_eq!(
+            check("const N = 1; function f() { var N = 2; N = 3; return N; }"),
+            Ok(Type::Void)
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let source = "function f(s: string): number {
+                if (s == \"abc\") { return length(s); } else { return s[0]; }
+            }";
+        assert_eq!(check(source), Ok(Type::Void));
+        assert_eq!(
+            check("function f() { delete \"abc\"; }"),
+            Err(TypeError::new(
+                diagnostic::NOT_AN_ARRAY,
+                String::from("Expected an array, but got string")
+            ))
+        );
+        assert_eq!(
+            check("function f() { return \"1\" == 1; }"),
+            Err(TypeError::new(
+                diagnostic::TYPE_MISMATCH,
+                String::from("Type mismatch string != number")
+            ))
+        );
+    }
+
+    #[test]
+    fn lowered_string_comparisons() {
+        let (_, mut ast) = parser::make_full_parser(&parser::ParseOptions::default())
+            .parse("function f(s: string): boolean { return s != \"a\" == (1 == 1); }")
+            .unwrap();
+        lower_typed_operations(&mut ast, LinkedHashMap::new()).unwrap();
+        assert_eq!(
+            format::format_program(&ast),
+            "function f(s: string): boolean {\n    return !__rtsc_string_equal(s, \"a\") == (1 == 1);\n}\n"
+        );
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(
+            check("function f(x: float): float { return x * 2.5 - x; }"),
+            Ok(Type::Void)
+        );
+        assert_eq!(
+            check("function f(x: float): float { return x + 1; }"),
+            Err(TypeError::new(
+                diagnostic::TYPE_MISMATCH,
+                String::from("Type mismatch float != number")
+            ))
+        );
+        let (_, mut ast) = parser::make_full_parser(&parser::ParseOptions::default())
+            .parse("function f(x: float): boolean { return x / 2.0 != x; }")
+            .unwrap();
+        lower_typed_operations(&mut ast, LinkedHashMap::new()).unwrap();
+        assert_eq!(
+            format::format_program(&ast),
+            "function f(x: float): boolean {\n    return !__rtsc_feq(__rtsc_fdiv(x, 2.0), x);\n}\n"
+        );
+    }
+
+    #[test]
+    fn intrinsics() {
+        let lowered = |source| {
+            let (_, mut ast) = parser::make_full_parser(&parser::ParseOptions::default())
+                .parse(source)
+                .unwrap();
+            lower_typed_operations(&mut ast, builtins()).map(|_| format::format_program(&ast))
+        };
+        assert_eq!(
+            lowered("function f(x: number) { return max(abs(x), min(x, 1)); }"),
+            Ok(String::from(
+                "function f(x: number): number {\n    return __rtsc_max(__rtsc_abs(x), __rtsc_min(x, 1));\n}\n"
+            ))
+        );
+        assert_eq!(
+            lowered("function f(x: number) { return rotate(x, clz(x)) + popcount(x); }"),
+            Ok(String::from(
+                "function f(x: number): number {\n    return __rtsc_rotate(x, __rtsc_clz(x)) + __rtsc_popcount(x);\n}\n"
+            ))
+        );
+        // a function of the program is called instead
+        assert_eq!(
+            lowered("function abs(x: number) { return x; } function f() { return abs(1); }"),
+            Ok(String::from(
+                "function abs(x: number): number {\n    return x;\n}\nfunction f(): number {\n    return abs(1);\n}\n"
+            ))
+        );
+        assert_eq!(
+            lowered("function f() { return min(1, true); }"),
+            Err(TypeError::new(
+                diagnostic::TYPE_MISMATCH,
+                String::from("Type mismatch boolean != number")
+            ))
+        );
+    }
+
+    #[test]
+    fn array_builtins() {
+        let source = "function f(xs: array<boolean>): array<boolean> {
+                push(xs, true);
+                if (pop(xs)) { return concat(xs, [false]); } else { return slice(xs, 1, 2); }
+            }";
+        assert_eq!(check(source), Ok(Type::Void));
+        assert_eq!(
+            check("function f(xs: array<number>) { push(xs, true); }"),
+            Err(TypeError::new(
+                diagnostic::TYPE_MISMATCH,
+                String::from("Type mismatch number != boolean")
+            ))
+        );
+        assert_eq!(
+            check("function f() { push([1], 2); }"),
+            Err(TypeError::new(
+                diagnostic::PUSH_TARGET,
+                String::from("The array passed to `push` must be a variable")
+            ))
+        );
+        assert_eq!(
+            check("function f() { return pop(1); }"),
+            Err(TypeError::new(
+                diagnostic::NOT_AN_ARRAY,
+                String::from("Expected an array, but got number")
+            ))
+        );
+        assert_eq!(
+            check("function f(xs: array<number>) { return concat(xs); }"),
+            Err(TypeError::new(
+                diagnostic::ARGUMENT_COUNT,
+                String::from("`concat` takes 2 arguments, but got 1")
+            ))
+        );
+        assert_eq!(
+            check("function f(xs: array<number>) { return slice(xs, 0, true); }"),
+            Err(TypeError::new(
+                diagnostic::TYPE_MISMATCH,
+                String::from("Type mismatch number != boolean")
+            ))
+        );
+    }
+
+    #[test]
+    fn delete_non_array() {
+        assert_eq!(
+            check("function f() { var xs = [1]; delete xs; }"),
+            Ok(Type::Void)
+        );
+        assert_eq!(
+            check("function f(x) { delete x; }"),
+            Err(TypeError::new(
+                diagnostic::NOT_AN_ARRAY,
+                String::from("Expected an array, but got number")
+            ))
+        );
+    }
+
+    #[test]
+    fn globals() {
+        let source = "var count = 0;
+            function increment() { count = count + 1; return count; }";
+        assert_eq!(check(source), Ok(Type::Void));
+        assert_eq!(
+            check("function f() { return g; } var g = 1;"),
+            Err(TypeError::new(
+                diagnostic::UNDEFINED_VARIABLE,
+                String::from("Undefined variable g")
+            ))
+        );
+        assert_eq!(
+            check("var xs = [1];"),
+            Err(TypeError::new(
+                diagnostic::GLOBAL_INITIALIZER,
+                String::from("Global variable xs must be initialized with a constant")
+            ))
+        );
+    }
+
+    #[test]
+    fn return_outside_function() {
+        assert!(check("return 1;").is_err());
+    }
+
+    #[test]
+    fn block_scoped_locals() {
+        assert_eq!(
+            check("function f(c: boolean) { if (c) { var y = 1; } else {} return y; }"),
+            Err(TypeError::new(
+                diagnostic::UNDEFINED_VARIABLE,
+                String::from("Undefined variable y")
+            ))
+        );
+        assert_eq!(
+            check("function f(): boolean { var x = true; { var x = 1; } return !x; }"),
+            Ok(Type::Void)
+        );
+    }
+
+    #[test]
+    fn nested_functions() {
+        assert_eq!(
+            check(
+                "function f(x: number): number {
+                    function g(n: number): number { return n + 1; }
+                    return g(x);
+                }"
+            ),
+            Ok(Type::Void)
+        );
+        assert_eq!(
+            check("function f(): number { { function g(): number { return 1; } } return g(); }"),
+            Err(TypeError::new(
+                diagnostic::UNDEFINED_FUNCTION,
+                String::from("Use of undefined function g")
+            ))
+        );
+        // the locals of the enclosing function are not captured
+        assert_eq!(
+            check(
+                "function f(x: number): number { function g(): number { return x; } return g(); }"
+            ),
+            Err(TypeError::new(
+                diagnostic::UNDEFINED_VARIABLE,
+                String::from("Undefined variable x")
+            ))
+        );
+    }
+
+    #[test]
+    fn namespaces() {
+        let source = "namespace geometry {
+                function square(x: number): number { return x * x; }
+                export function area(w: number): number { return square(w); }
+            }
+            function f(): number { return geometry.area(2); }";
+        assert_eq!(check(source), Ok(Type::Void));
+        assert_eq!(
+            check(&source.replace("geometry.area(2)", "geometry.square(2)")),
+            Err(TypeError::new(
+                diagnostic::UNDEFINED_FUNCTION,
+                String::from("Use of undefined function geometry.square")
+            ))
+        );
+        assert_eq!(
+            check(&source.replace("geometry.area(2)", "area(2)")),
+            Err(TypeError::new(
+                diagnostic::UNDEFINED_FUNCTION,
+                String::from("Use of undefined function area")
+            ))
+        );
+        assert_eq!(
+            check("namespace n { var x = 1; }"),
+            Err(TypeError::new(
+                diagnostic::NOT_AT_TOP_LEVEL,
+                String::from("Namespace n can only contain functions")
+            ))
+        );
     }
 }
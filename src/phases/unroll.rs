@@ -0,0 +1,209 @@
+//! Unrolling of the loops running a small constant number of times.
+//!
+//! A loop counting a local from a constant to another one by a constant
+//! step, as in
+//!
+//! ```text
+//! var i = 0;
+//! while (i != 4) { sum = sum + xs[i]; i = i + 1; }
+//! ```
+//!
+//! is replaced with as many copies of its body as it runs, each in a block
+//! of its own, which saves the condition and the jump back on every
+//! iteration. The constant evaluator gives the start, the end and the step,
+//! so the loop qualifies once `const_eval::fold` replaced the bindings. The
+//! body has to change the counter exactly once, at its top level, and the
+//! counter has to reach the end within `MAX_TRIPS` steps, wrapping around
+//! like the generated code does. The copies keep the statements of the
+//! counter, which ends up with the value it has after the loop.
+use crate::ast::Ast;
+use crate::lines;
+use crate::phases::const_eval::{self, Constant, Constants};
+
+/// The most iterations of a loop which is unrolled.
+pub const MAX_TRIPS: usize = 8;
+/// The most statements the copies of the body may have together.
+pub const MAX_STATEMENTS: usize = 64;
+
+/// Unrolls the loops of the functions of the program. Gives for every
+/// statement of the program afterwards, in the order of
+/// `lines::statements`, the index of the statement it was before, the
+/// copies of a body all getting the indices of the body.
+pub fn unroll(program: &mut Ast) -> Vec<usize> {
+    let mut unroll = Unroll {
+        next: 0,
+        origins: Vec::new(),
+    };
+    match program {
+        Ast::Block(statements) => {
+            for statement in statements {
+                unroll.statement(statement);
+            }
+        }
+        program => unroll.statement(program),
+    }
+    unroll.origins
+}
+
+struct Unroll {
+    // the index of the next statement in the order of `lines::statements`
+    next: usize,
+    origins: Vec<usize>,
+}
+
+impl Unroll {
+    fn statement(&mut self, ast: &mut Ast) {
+        self.origins.push(self.next);
+        self.next += 1;
+        match ast {
+            Ast::Block(statements) => {
+                for i in 0..statements.len() {
+                    let (before, rest) = statements.split_at_mut(i);
+                    match (before.last(), &mut rest[0]) {
+                        (Some(Ast::Var(counter, start)), Ast::While(condition, body)) => {
+                            let index = self.origins.len();
+                            self.origins.push(self.next);
+                            self.next += 1;
+                            // the loops nested in the body first
+                            self.statement(body);
+                            if let Some(trips) = trips(counter, start, condition, body) {
+                                let body_origins = self.origins.split_off(index + 1);
+                                for _ in 0..trips {
+                                    self.origins.extend(&body_origins);
+                                }
+                                rest[0] = Ast::Block(vec![body.as_ref().clone(); trips]);
+                            }
+                        }
+                        (_, statement) => self.statement(statement),
+                    }
+                }
+            }
+            Ast::If(_, consequence, alternative) => {
+                self.statement(consequence);
+                self.statement(alternative);
+            }
+            Ast::While(_, body) | Ast::Function(_, _, body) | Ast::Export(body) => {
+                self.statement(body)
+            }
+            _ => {}
+        }
+    }
+}
+
+// How many times the loop counting `counter` from `start` runs, when it is
+// unrolled.
+fn trips(counter: &str, start: &Ast, condition: &Ast, body: &Ast) -> Option<usize> {
+    let number = |expression| match const_eval::evaluate(expression, &Constants::new()) {
+        Some(Constant::Number(value)) => Some(value),
+        _ => None,
+    };
+    let is_counter =
+        |expression: &Ast| matches!(expression, Ast::Identifier(name) if name == counter);
+    let start = number(start)?;
+    let end = match condition {
+        Ast::NotEqual(lhs, rhs) if is_counter(lhs) => number(rhs)?,
+        Ast::NotEqual(lhs, rhs) if is_counter(rhs) => number(lhs)?,
+        _ => return None,
+    };
+    let statements = match body {
+        Ast::Block(statements) => statements.as_slice(),
+        body => std::slice::from_ref(body),
+    };
+    let step = statements.iter().find_map(|statement| match statement {
+        Ast::Assignment(name, value) if name == counter => match value.as_ref() {
+            Ast::Addition(lhs, rhs) if is_counter(lhs) => number(rhs),
+            Ast::Addition(lhs, rhs) if is_counter(rhs) => number(lhs),
+            Ast::Subtraction(lhs, rhs) if is_counter(lhs) => number(rhs).map(i32::wrapping_neg),
+            _ => None,
+        },
+        _ => None,
+    })?;
+    if changes(body, counter) != 1 {
+        return None;
+    }
+    let trips = (0..=MAX_TRIPS)
+        .find(|&trips| start.wrapping_add(step.wrapping_mul(trips as i32)) == end)?;
+    if trips * lines::statements(body).len() > MAX_STATEMENTS {
+        return None;
+    }
+    Some(trips)
+}
+
+// How many statements of the body assign the counter, or declare something
+// making the assignments hard to tell apart.
+fn changes(ast: &Ast, counter: &str) -> usize {
+    let own = match ast {
+        Ast::Assignment(name, _) | Ast::Var(name, _) if name == counter => 1,
+        // a nested function would be declared again by every copy
+        Ast::Function(_, _, _) => 2,
+        _ => 0,
+    };
+    own + ast
+        .children()
+        .into_iter()
+        .map(|child| changes(child, counter))
+        .sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn constant_trip_counts() {
+        let mut program = parser::parse_program(
+            "function f(xs: array<number>) {
+                var sum = 0;
+                var i = 0;
+                while (i != 3) { sum = sum + xs[i]; i = i + 1; }
+                var j = 10;
+                while (j != 0) { j = j - 5; }
+                return sum;
+            }",
+        )
+        .unwrap();
+        let before = lines::statements(&program).len();
+        let origins = unroll(&mut program);
+        let expected = parser::parse_program(
+            "function f(xs: array<number>) {
+                var sum = 0;
+                var i = 0;
+                {
+                    { sum = sum + xs[i]; i = i + 1; }
+                    { sum = sum + xs[i]; i = i + 1; }
+                    { sum = sum + xs[i]; i = i + 1; }
+                }
+                var j = 10;
+                { { j = j - 5; } { j = j - 5; } }
+                return sum;
+            }",
+        )
+        .unwrap();
+        assert_eq!(program, expected);
+        assert_eq!(before, 13);
+        assert_eq!(
+            origins,
+            [0, 1, 2, 3, 4, 5, 6, 7, 5, 6, 7, 5, 6, 7, 8, 9, 10, 11, 10, 11, 12]
+        );
+    }
+
+    #[test]
+    fn kept_loops() {
+        let source = "function f(n: number, c: boolean) {
+            var i = 0;
+            while (i != n) { i = i + 1; }
+            var j = 0;
+            while (j != 3) { if (c) { j = j + 1; } else { j = j + 2; } }
+            var k = 0;
+            while (k != 100) { k = k + 1; }
+            var l = 1;
+            while (l != 0) { l = l + 2; }
+        }";
+        let mut program = parser::parse_program(source).unwrap();
+        let origins = unroll(&mut program);
+        assert_eq!(program, parser::parse_program(source).unwrap());
+        assert_eq!(origins, (0..origins.len()).collect::<Vec<_>>());
+        assert_eq!(origins.len(), lines::statements(&program).len());
+    }
+}
@@ -0,0 +1,769 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use crate::ast::Ast;
+use crate::phases::codegen::{
+    self, Arithmetic, BoundsCheck, CodeGenerator, CodegenOptions, Environment,
+};
+use crate::phases::runtime;
+use crate::types::Type;
+
+// Arrays live in the linear memory and are allocated with a bump allocator
+// that never frees, the first word of each array holds its length. The
+// constant arrays come first, past address 0 so that `null` never points to
+// a valid array, followed by the heap.
+const STATIC_START: usize = 8;
+const PAGE_SIZE: usize = 64 * 1024;
+
+const RUNTIME: &str = "  (func $__rtsc_alloc (param $size i32) (result i32)
+    (local $address i32)
+    global.get $__rtsc_heap
+    local.set $address
+    global.get $__rtsc_heap
+    local.get $size
+    i32.add
+    global.set $__rtsc_heap
+    block
+      loop
+        global.get $__rtsc_heap
+        memory.size
+        i32.const 16
+        i32.shl
+        i32.le_u
+        br_if 1
+        i32.const 1
+        memory.grow
+        i32.const -1
+        i32.eq
+        if
+          unreachable
+        end
+        br 0
+      end
+    end
+    local.get $address)
+";
+
+// Looks up an element, what happens for an index outside of the array
+// depends on the bounds check option.
+fn array_get_runtime(bounds_check: BoundsCheck) -> &'static str {
+    match bounds_check {
+        BoundsCheck::Zero => {
+            "  (func $__rtsc_array_get (param $array i32) (param $index i32) (result i32)
+    local.get $index
+    local.get $array
+    i32.load
+    i32.lt_u
+    if (result i32)
+      local.get $array
+      local.get $index
+      i32.const 2
+      i32.shl
+      i32.add
+      i32.load offset=4
+    else
+      i32.const 0
+    end)
+"
+        }
+        // WebAssembly has no standard error output, the host reports the
+        // trap instead.
+        BoundsCheck::Trap => {
+            "  (func $__rtsc_array_get (param $array i32) (param $index i32) (result i32)
+    local.get $index
+    local.get $array
+    i32.load
+    i32.ge_u
+    if
+      unreachable
+    end
+    local.get $array
+    local.get $index
+    i32.const 2
+    i32.shl
+    i32.add
+    i32.load offset=4)
+"
+        }
+        BoundsCheck::Off => {
+            "  (func $__rtsc_array_get (param $array i32) (param $index i32) (result i32)
+    local.get $array
+    local.get $index
+    i32.const 2
+    i32.shl
+    i32.add
+    i32.load offset=4)
+"
+        }
+    }
+}
+
+/// Generates a WebAssembly module in the text format. Every function is
+/// exported and every called function that the program does not define,
+/// like `putchar`, is imported from the `env` module of the host.
+#[derive(Debug)]
+pub struct WasmGenerator {
+    ast: Ast,
+    options: CodegenOptions,
+    // values of the arrays placed in data segments, in address order
+    constant_arrays: RefCell<Vec<Vec<i32>>>,
+    exported: BTreeSet<String>,
+}
+
+impl CodeGenerator for WasmGenerator {
+    fn emit(&self, output: &mut dyn io::Write, _env: &mut Environment) -> io::Result<()> {
+        output.write_all(self.generate().as_bytes())
+    }
+}
+
+// Instructions of a single function body, indented by their nesting.
+#[derive(Debug, Default)]
+struct FunctionBody {
+    lines: Vec<String>,
+    depth: usize,
+    locals: Vec<String>,
+    // visible variables and the locals holding them, later declarations
+    // shadow the earlier ones
+    variables: Vec<(String, String)>,
+    globals: Vec<String>,
+}
+
+impl FunctionBody {
+    fn push(&mut self, instruction: impl Into<String>) {
+        let instruction = instruction.into();
+        if instruction == "end" || instruction == "else" {
+            self.depth -= 1;
+        }
+        self.lines
+            .push(format!("{}{}", "  ".repeat(self.depth + 2), instruction));
+        if instruction.starts_with("block")
+            || instruction.starts_with("loop")
+            || instruction.starts_with("if")
+            || instruction == "else"
+        {
+            self.depth += 1;
+        }
+    }
+
+    fn declare_local(&mut self, name: &str) {
+        if !self.locals.iter().any(|local| local == name) {
+            self.locals.push(String::from(name));
+        }
+    }
+
+    // Shadowing a visible variable needs a fresh local, otherwise the local
+    // named after the variable is reused.
+    fn declare_variable(&mut self, name: &str) -> String {
+        let local = if self.variables.iter().any(|(variable, _)| variable == name) {
+            format!("{}.{}", name, self.locals.len())
+        } else {
+            String::from(name)
+        };
+        self.declare_local(&local);
+        self.variables.push((String::from(name), local.clone()));
+        local
+    }
+
+    // Reads or writes the variable with `local.get`/`local.set`, falling back
+    // to the global of the same name when no local shadows it.
+    fn access(&self, operation: &str, name: &str) -> String {
+        match self
+            .variables
+            .iter()
+            .rfind(|(variable, _)| variable == name)
+        {
+            Some((_, local)) => format!("local.{} ${}", operation, local),
+            None if self.globals.iter().any(|global| global == name) => {
+                format!("global.{} ${}", operation, name)
+            }
+            None => panic!("Tried to use an undefined name {}", name),
+        }
+    }
+
+    fn enter_scope(&self) -> usize {
+        self.variables.len()
+    }
+
+    fn leave_scope(&mut self, scope: usize) {
+        self.variables.truncate(scope);
+    }
+}
+
+fn functions(ast: &Ast) -> impl Iterator<Item = (&String, &Type, &Ast)> {
+    ast.declarations()
+        .filter(|statement| {
+            !matches!(
+                statement,
+                Ast::Import(_, _)
+                    | Ast::Var(_, _)
+                    | Ast::Const(_, _)
+                    | Ast::Extern(_, _)
+                    | Ast::StaticAssert(_, _)
+                    | Ast::Doc(_)
+            )
+        })
+        .map(|statement| match statement {
+            Ast::Function(name, function_type, body) => (name, function_type, body.as_ref()),
+            _ => panic!("Only functions are supported at the top level of a WebAssembly module"),
+        })
+}
+
+// Collects the arity of every called function.
+fn collect_calls(ast: &Ast, calls: &mut BTreeMap<String, usize>) {
+    // a chain of operators is as deep as it is long
+    let mut nodes = vec![ast];
+    while let Some(node) = nodes.pop() {
+        match node {
+            Ast::Call(name, args) => {
+                calls.insert(name.clone(), args.len());
+            }
+            // checked by the typechecker and folded into their uses
+            Ast::Const(_, _) | Ast::StaticAssert(_, _) => continue,
+            _ => {}
+        }
+        nodes.extend(node.children().into_iter().rev());
+    }
+}
+
+impl WasmGenerator {
+    pub fn new(ast: Ast) -> WasmGenerator {
+        WasmGenerator {
+            exported: codegen::exported_functions(&ast),
+            ast,
+            options: CodegenOptions::default(),
+            constant_arrays: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn with_options(mut self, options: CodegenOptions) -> WasmGenerator {
+        self.options = options;
+        self
+    }
+
+    /// Generates the whole module.
+    pub fn generate(&self) -> String {
+        let mut output = String::from("(module\n");
+
+        let mut calls = BTreeMap::new();
+        collect_calls(&self.ast, &mut calls);
+        for (name, _, _) in functions(&self.ast) {
+            calls.remove(name);
+        }
+        for name in codegen::FLOAT_OPERATIONS.iter().chain(&codegen::INTRINSICS) {
+            calls.remove(*name);
+        }
+        calls.remove(runtime::POPCOUNT_ROUTINE);
+        for (name, arity) in calls {
+            output.push_str(&format!(
+                "  (import \"env\" \"{}\" (func ${}{} (result i32)))\n",
+                name,
+                name,
+                " (param i32)".repeat(arity)
+            ));
+        }
+
+        self.constant_arrays.borrow_mut().clear();
+        let mut definitions = String::new();
+        for (name, function_type, body) in functions(&self.ast) {
+            definitions.push_str(&self.generate_function(name, function_type, body));
+        }
+
+        let mut address = STATIC_START;
+        let mut segments = String::new();
+        for values in self.constant_arrays.borrow().iter() {
+            let bytes: String = std::iter::once(values.len() as i32)
+                .chain(values.iter().copied())
+                .flat_map(i32::to_le_bytes)
+                .map(|byte| format!("\\{:02x}", byte))
+                .collect();
+            segments.push_str(&format!("  (data (i32.const {}) \"{}\")\n", address, bytes));
+            address += 4 * (values.len() + 1);
+        }
+        let pages = address.div_ceil(PAGE_SIZE);
+        output.push_str(&format!("  (memory (export \"memory\") {})\n", pages));
+        output.push_str(&segments);
+        output.push_str(&format!(
+            "  (global $__rtsc_heap (mut i32) (i32.const {}))\n",
+            address
+        ));
+        output.push_str(RUNTIME);
+        output.push_str(array_get_runtime(self.options.bounds_check));
+        for (name, value) in codegen::global_variables(&self.ast) {
+            output.push_str(&format!(
+                "  (global ${} (mut i32) (i32.const {}))\n",
+                name, value
+            ));
+        }
+        output.push_str(&definitions);
+        output.push_str(")\n");
+        output
+    }
+
+    // Address of the array holding the values in a data segment, identical
+    // arrays share one.
+    fn constant_array(&self, values: Vec<i32>) -> usize {
+        let mut constant_arrays = self.constant_arrays.borrow_mut();
+        let mut address = STATIC_START;
+        for other in constant_arrays.iter() {
+            if *other == values {
+                return address;
+            }
+            address += 4 * (other.len() + 1);
+        }
+        constant_arrays.push(values);
+        address
+    }
+
+    fn generate_function(&self, name: &str, function_type: &Type, body: &Ast) -> String {
+        let parameters = match function_type {
+            Type::Function {
+                parameter_types, ..
+            } => parameter_types,
+            _ => unreachable!(),
+        };
+
+        let mut function = FunctionBody {
+            globals: codegen::global_variables(&self.ast)
+                .into_iter()
+                .map(|(name, _)| String::from(name))
+                .collect(),
+            ..FunctionBody::default()
+        };
+        for parameter in parameters.keys() {
+            function
+                .variables
+                .push((parameter.clone(), parameter.clone()));
+        }
+        self.emit_statement(body, &mut function);
+        // Functions falling off the end return 0, like on ARM.
+        function.push("i32.const 0");
+
+        let mut output = format!("  (func ${}", name);
+        if self.exported.contains(name) {
+            output.push_str(&format!(" (export \"{}\")", name));
+        }
+        for parameter in parameters.keys() {
+            output.push_str(&format!(" (param ${} i32)", parameter));
+        }
+        output.push_str(" (result i32)\n");
+        for local in &function.locals {
+            if !parameters.contains_key(local) {
+                output.push_str(&format!("    (local ${} i32)\n", local));
+            }
+        }
+        output.push_str(&function.lines.join("\n"));
+        output.push_str(")\n");
+        output
+    }
+
+    fn emit_in_scope(&self, ast: &Ast, function: &mut FunctionBody) {
+        let scope = function.enter_scope();
+        self.emit_statement(ast, function);
+        function.leave_scope(scope);
+    }
+
+    fn emit_statement(&self, ast: &Ast, function: &mut FunctionBody) {
+        match ast {
+            Ast::Block(statements) => {
+                let scope = function.enter_scope();
+                for statement in statements {
+                    self.emit_statement(statement, function);
+                }
+                function.leave_scope(scope);
+            }
+            Ast::Return(expr) => {
+                self.emit_expression(expr, function);
+                function.push("return");
+            }
+            // The bump allocator cannot reuse memory, only the side effects
+            // of the expression are kept.
+            Ast::Delete(array) => {
+                self.emit_expression(array, function);
+                function.push("drop");
+            }
+            Ast::If(condition, consequence, alternative) => {
+                self.emit_expression(condition, function);
+                function.push("if");
+                self.emit_in_scope(consequence, function);
+                function.push("else");
+                self.emit_in_scope(alternative, function);
+                function.push("end");
+            }
+            Ast::While(condition, block) => {
+                function.push("block");
+                function.push("loop");
+                self.emit_expression(condition, function);
+                function.push("i32.eqz");
+                function.push("br_if 1");
+                self.emit_in_scope(block, function);
+                function.push("br 0");
+                function.push("end");
+                function.push("end");
+            }
+            Ast::Var(name, expr) => {
+                self.emit_expression(expr, function);
+                let local = function.declare_variable(name);
+                function.push(format!("local.set ${}", local));
+            }
+            Ast::Assignment(name, expr) => {
+                self.emit_expression(expr, function);
+                let instruction = function.access("set", name);
+                function.push(instruction);
+            }
+            Ast::Const(_, _) | Ast::StaticAssert(_, _) | Ast::Doc(_) => {}
+            Ast::Function(_, _, _) | Ast::Import(_, _) | Ast::Export(_) | Ast::Extern(_, _) => {
+                panic!("Functions can only be defined at the top level")
+            }
+            expr => {
+                self.emit_expression(expr, function);
+                function.push("drop");
+            }
+        }
+    }
+
+    // With a stack of its own, as a chain of operators is as deep as it is
+    // long: the operands are emitted in order, with the instructions between
+    // and after them left to the steps following theirs.
+    fn emit_expression(&self, expression: &Ast, function: &mut FunctionBody) {
+        enum Step<'a> {
+            Emit(&'a Ast),
+            Push(String),
+        }
+        let emit = Step::Emit;
+        let push = |instruction: &str| Step::Push(String::from(instruction));
+        let mut steps = vec![Step::Emit(expression)];
+        while let Some(step) = steps.pop() {
+            let ast = match step {
+                Step::Emit(ast) => ast,
+                Step::Push(instruction) => {
+                    function.push(instruction);
+                    continue;
+                }
+            };
+            let sequence = match ast {
+                Ast::Null | Ast::Undefined => vec![push("i32.const 0")],
+                Ast::Number(value) => vec![Step::Push(format!("i32.const {}", value))],
+                Ast::Float(value) => {
+                    vec![Step::Push(format!("i32.const {}", value.to_bits() as i32))]
+                }
+                Ast::Bool(value) => vec![Step::Push(format!("i32.const {}", *value as i32))],
+                Ast::Identifier(name) => vec![Step::Push(function.access("get", name))],
+                Ast::String(text) => {
+                    let values = codegen::character_codes(text);
+                    vec![Step::Push(format!(
+                        "i32.const {}",
+                        self.constant_array(values)
+                    ))]
+                }
+                // `delete` does nothing here, so constant arrays are always safe
+                Ast::ArrayLiteral(elements) if codegen::constant_elements(elements).is_some() => {
+                    let values = codegen::constant_elements(elements).unwrap();
+                    vec![Step::Push(format!(
+                        "i32.const {}",
+                        self.constant_array(values)
+                    ))]
+                }
+                Ast::ArrayLiteral(elements) => {
+                    let array = format!("array.{}", function.locals.len());
+                    function.declare_local(&array);
+                    let mut sequence = vec![
+                        Step::Push(format!("i32.const {}", 4 * (elements.len() + 1))),
+                        push("call $__rtsc_alloc"),
+                        Step::Push(format!("local.set ${}", array)),
+                        Step::Push(format!("local.get ${}", array)),
+                        Step::Push(format!("i32.const {}", elements.len())),
+                        push("i32.store"),
+                    ];
+                    for (i, element) in elements.iter().enumerate() {
+                        sequence.push(Step::Push(format!("local.get ${}", array)));
+                        sequence.push(emit(element));
+                        sequence.push(Step::Push(format!("i32.store offset={}", 4 * (i + 1))));
+                    }
+                    sequence.push(Step::Push(format!("local.get ${}", array)));
+                    sequence
+                }
+                Ast::ArrayLookup(array, index) => {
+                    vec![emit(array), emit(index), push("call $__rtsc_array_get")]
+                }
+                Ast::ArrayLength(array) => vec![emit(array), push("i32.load")],
+                Ast::Not(expr) => vec![emit(expr), push("i32.eqz")],
+                Ast::Equal(lhs, rhs)
+                | Ast::NotEqual(lhs, rhs)
+                | Ast::Addition(lhs, rhs)
+                | Ast::Subtraction(lhs, rhs)
+                | Ast::Multiplication(lhs, rhs)
+                | Ast::Division(lhs, rhs) => vec![
+                    emit(lhs),
+                    emit(rhs),
+                    push(match ast {
+                        Ast::Equal(_, _) => "i32.eq",
+                        Ast::NotEqual(_, _) => "i32.ne",
+                        Ast::Addition(_, _) => "i32.add",
+                        Ast::Subtraction(_, _) => "i32.sub",
+                        Ast::Multiplication(_, _) => "i32.mul",
+                        _ => match self.options.arithmetic {
+                            Arithmetic::Signed => "i32.div_s",
+                            Arithmetic::Unsigned => "i32.div_u",
+                        },
+                    }),
+                ],
+                // floats are kept in `i32`s like every other value
+                Ast::Call(name, args) if codegen::FLOAT_OPERATIONS.contains(&name.as_str()) => {
+                    let mut sequence = Vec::new();
+                    for arg in args {
+                        sequence.push(emit(arg));
+                        sequence.push(push("f32.reinterpret_i32"));
+                    }
+                    sequence.push(push(match name.as_str() {
+                        codegen::FLOAT_ADD => "f32.add",
+                        codegen::FLOAT_SUBTRACT => "f32.sub",
+                        codegen::FLOAT_MULTIPLY => "f32.mul",
+                        codegen::FLOAT_DIVIDE => "f32.div",
+                        _ => "f32.eq",
+                    }));
+                    if name != codegen::FLOAT_EQUAL {
+                        sequence.push(push("i32.reinterpret_f32"));
+                    }
+                    sequence
+                }
+                // `select` keeps the first value when the condition holds
+                Ast::Call(name, args) if name == codegen::ABS_INTRINSIC => {
+                    function.declare_local("intrinsic.lhs");
+                    vec![
+                        emit(&args[0]),
+                        push("local.set $intrinsic.lhs"),
+                        push("i32.const 0"),
+                        push("local.get $intrinsic.lhs"),
+                        push("i32.sub"),
+                        push("local.get $intrinsic.lhs"),
+                        push("local.get $intrinsic.lhs"),
+                        push("i32.const 0"),
+                        push("i32.lt_s"),
+                        push("select"),
+                    ]
+                }
+                Ast::Call(name, args)
+                    if name == codegen::CLZ_INTRINSIC || name == runtime::POPCOUNT_ROUTINE =>
+                {
+                    vec![
+                        emit(&args[0]),
+                        push(if name == codegen::CLZ_INTRINSIC {
+                            "i32.clz"
+                        } else {
+                            "i32.popcnt"
+                        }),
+                    ]
+                }
+                Ast::Call(name, args) if name == codegen::ROTATE_INTRINSIC => {
+                    vec![emit(&args[0]), emit(&args[1]), push("i32.rotr")]
+                }
+                Ast::Call(name, args) if codegen::INTRINSICS.contains(&name.as_str()) => {
+                    function.declare_local("intrinsic.lhs");
+                    function.declare_local("intrinsic.rhs");
+                    vec![
+                        emit(&args[0]),
+                        emit(&args[1]),
+                        push("local.set $intrinsic.rhs"),
+                        push("local.tee $intrinsic.lhs"),
+                        push("local.get $intrinsic.rhs"),
+                        push("local.get $intrinsic.lhs"),
+                        push("local.get $intrinsic.rhs"),
+                        push(if name == codegen::MIN_INTRINSIC {
+                            "i32.lt_s"
+                        } else {
+                            "i32.gt_s"
+                        }),
+                        push("select"),
+                    ]
+                }
+                Ast::Call(name, args) => {
+                    let mut sequence: Vec<_> = args.iter().map(emit).collect();
+                    sequence.push(Step::Push(format!("call ${}", name)));
+                    sequence
+                }
+                statement => unreachable!("{:?} is not an expression", statement),
+            };
+            steps.extend(sequence.into_iter().rev());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::phases::typecheck;
+    use linked_hash_map::LinkedHashMap;
+
+    fn generate(source: &str) -> String {
+        WasmGenerator::new(parser::parse_program(source).unwrap()).generate()
+    }
+
+    #[test]
+    fn function() {
+        let module = generate("export function add(x: number, y: number) { return x + y; }");
+        assert!(module.contains(
+            "  (func $add (export \"add\") (param $x i32) (param $y i32) (result i32)
+    local.get $x
+    local.get $y
+    i32.add
+    return
+    i32.const 0)
+"
+        ));
+        let module = generate("function add(x: number, y: number) { return x + y; }");
+        assert!(module.contains("  (func $add (param $x i32)"));
+    }
+
+    #[test]
+    fn floats() {
+        let mut ast =
+            parser::parse_program("function f(x: float): float { return x / 2.0; }").unwrap();
+        typecheck::lower_typed_operations(&mut ast, LinkedHashMap::new()).unwrap();
+        let module = WasmGenerator::new(ast).generate();
+        assert!(module.contains(
+            "    local.get $x
+    f32.reinterpret_i32
+    i32.const 1073741824
+    f32.reinterpret_i32
+    f32.div
+    i32.reinterpret_f32
+"
+        ));
+        assert!(!module.contains("import"));
+    }
+
+    #[test]
+    fn undefined_functions_are_imported() {
+        let module = generate("function f() { putchar(46); }");
+        assert!(module
+            .contains("  (import \"env\" \"putchar\" (func $putchar (param i32) (result i32)))\n"));
+        assert!(module.contains(
+            "    i32.const 46
+    call $putchar
+    drop
+"
+        ));
+    }
+
+    #[test]
+    fn locals_and_loops() {
+        let module = generate("function f() { var i = 0; while (i != 3) { i = i + 1; } }");
+        assert!(module.contains(
+            "    (local $i i32)
+    i32.const 0
+    local.set $i
+    block
+      loop
+        local.get $i
+        i32.const 3
+        i32.ne
+        i32.eqz
+        br_if 1
+        local.get $i
+        i32.const 1
+        i32.add
+        local.set $i
+        br 0
+      end
+    end
+"
+        ));
+    }
+
+    #[test]
+    fn array_literal() {
+        let module = generate("function f(x) { return [x]; }");
+        assert!(module.contains(
+            "    (local $array.0 i32)
+    i32.const 8
+    call $__rtsc_alloc
+    local.set $array.0
+    local.get $array.0
+    i32.const 1
+    i32.store
+    local.get $array.0
+    local.get $x
+    i32.store offset=4
+    local.get $array.0
+    return
+"
+        ));
+    }
+
+    #[test]
+    fn constant_arrays() {
+        let module = generate("function f() { var a = [7]; var b = [1, 2]; var c = [7]; }");
+        assert!(module.contains(
+            "  (memory (export \"memory\") 1)
+  (data (i32.const 8) \"\\01\\00\\00\\00\\07\\00\\00\\00\")
+  (data (i32.const 16) \"\\02\\00\\00\\00\\01\\00\\00\\00\\02\\00\\00\\00\")
+  (global $__rtsc_heap (mut i32) (i32.const 28))
+"
+        ));
+        assert!(module.contains(
+            "    i32.const 8
+    local.set $a
+    i32.const 16
+    local.set $b
+    i32.const 8
+    local.set $c
+"
+        ));
+    }
+
+    #[test]
+    fn division() {
+        let source = "function f(x: number) { return x / 2; }";
+        assert!(generate(source).contains("i32.div_s"));
+        let ast = parser::parse_program(source).unwrap();
+        let module = WasmGenerator::new(ast)
+            .with_options(CodegenOptions {
+                arithmetic: Arithmetic::Unsigned,
+                ..CodegenOptions::default()
+            })
+            .generate();
+        assert!(module.contains("i32.div_u"));
+    }
+
+    #[test]
+    fn shadowing() {
+        let module = generate("function f(x: number) { { var x = 2; x = 3; } return x; }");
+        assert!(module.contains(
+            "    (local $x.0 i32)
+    i32.const 2
+    local.set $x.0
+    i32.const 3
+    local.set $x.0
+    local.get $x
+    return
+"
+        ));
+    }
+
+    #[test]
+    fn globals() {
+        let module = generate("var count = 3; function f() { count = count + 1; return count; }");
+        assert!(module.contains("  (global $count (mut i32) (i32.const 3))\n"));
+        assert!(module.contains(
+            "    global.get $count
+    i32.const 1
+    i32.add
+    global.set $count
+"
+        ));
+    }
+
+    #[test]
+    fn trapping_bounds_check() {
+        let ast = parser::parse_program("function f() { var xs = [1]; return xs[2]; }").unwrap();
+        let module = WasmGenerator::new(ast)
+            .with_options(CodegenOptions {
+                bounds_check: BoundsCheck::Trap,
+                ..CodegenOptions::default()
+            })
+            .generate();
+        assert!(module.contains("    i32.ge_u\n    if\n      unreachable\n    end\n"));
+    }
+}
@@ -0,0 +1,118 @@
+//! The source map written by `--source-map`, relating the lines of the
+//! ARM32 assembly to the statements they were generated from, for tools
+//! showing the source and the assembly side by side.
+//!
+//! ```json
+//! {
+//!   "files": ["main.ts"],
+//!   "mappings": [
+//!     {"assembly": {"first": 3, "last": 8}, "file": 0, "span": {"start": 0, "end": 42},
+//!      "line": 1, "column": 1}
+//!   ]
+//! }
+//! ```
+//!
+//! Every mapping covers consecutive lines of the assembly, counted from 1,
+//! coming from the same statement. The span is the byte range of the
+//! statement in the source file, starting at the line and column given.
+//! The code of a statement nested in another one is mapped to the inner
+//! statement, and the lines of the runtime and the data are not mapped.
+use crate::diagnostic::json_string;
+use crate::lines::SourceLine;
+use crate::phases::instruction::Instruction;
+
+/// Renders the source map of the code, given the `sources` of its
+/// instructions found by `Arm32Generator::instruction_sources`, which index
+/// `lines`.
+pub fn to_json(
+    files: &[String],
+    lines: &[SourceLine],
+    code: &[Instruction],
+    sources: &[Option<usize>],
+) -> String {
+    // (first line, last line, statement line)
+    let mut ranges: Vec<(usize, usize, usize)> = Vec::new();
+    let mut line = 0;
+    for (instruction, source) in code.iter().zip(sources) {
+        // a global is written after an empty line, see `instruction::write`
+        line += if matches!(instruction, Instruction::Global(_) | Instruction::Local(_)) {
+            2
+        } else {
+            1
+        };
+        let source = match source {
+            Some(source) => *source,
+            None => continue,
+        };
+        match ranges.last_mut() {
+            Some((_, last, previous)) if *last + 1 == line && *previous == source => *last = line,
+            _ => ranges.push((line, line, source)),
+        }
+    }
+    let mappings: Vec<String> = ranges
+        .into_iter()
+        .map(|(first, last, source)| {
+            let source = &lines[source];
+            format!(
+                "{{\"assembly\": {{\"first\": {}, \"last\": {}}}, \"file\": {}, \"span\": \
+                 {{\"start\": {}, \"end\": {}}}, \"line\": {}, \"column\": {}}}",
+                first,
+                last,
+                source.file,
+                source.span.start,
+                source.span.end,
+                source.location.line,
+                source.location.column
+            )
+        })
+        .collect();
+    let mappings = if mappings.is_empty() {
+        String::new()
+    } else {
+        format!("\n    {}\n  ", mappings.join(",\n    "))
+    };
+    let files: Vec<String> = files.iter().map(|file| json_string(file)).collect();
+    format!(
+        "{{\n  \"files\": [{}],\n  \"mappings\": [{}]\n}}\n",
+        files.join(", "),
+        mappings
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines;
+    use crate::parser::{self, ParseOptions};
+    use crate::phases::codegen::{Arm32Generator, CodegenOptions, Environment};
+    use crate::phases::instruction;
+
+    #[test]
+    fn statements_mapped() {
+        let source = "function f(x: number): number {\n    var y = x;\n    return y;\n}";
+        let (program, spans) =
+            parser::parse_program_with_spans(source, &ParseOptions::default()).unwrap();
+        let source_lines = lines::statement_lines(0, source, &spans);
+        let generator = Arm32Generator::new(program)
+            .with_options(CodegenOptions {
+                source_map: true,
+                ..CodegenOptions::default()
+            })
+            .with_source(vec![String::from("f.ts")], source_lines.clone());
+        let code = generator.generate(&mut Environment::default());
+        let sources = generator.instruction_sources();
+        let map = to_json(&[String::from("f.ts")], &source_lines, &code, &sources);
+        let assembly = instruction::render(&code);
+        let assembly: Vec<&str> = assembly.lines().collect();
+        // `var y = x;` loads `x` and stores it into `y`
+        assert_eq!(
+            assembly[7..9],
+            ["    ldr r0, [fp, #-16]", "    str r0, [fp, #-20]"]
+        );
+        assert!(map.starts_with("{\n  \"files\": [\"f.ts\"],\n  \"mappings\": [\n"));
+        assert!(map.contains(
+            "{\"assembly\": {\"first\": 8, \"last\": 9}, \"file\": 0, \"span\": {\"start\": 36, \
+             \"end\": 46}, \"line\": 2, \"column\": 5}"
+        ));
+    }
+}
@@ -0,0 +1,184 @@
+//! The symbols of a program as JSON, written by `--emit=symbols` for tools
+//! and debugger scripts: the functions with their signatures and the stack
+//! offsets of their parameters and locals, and the global variables.
+//!
+//! ```json
+//! {
+//!   "functions": [
+//!     {"name": "main::f", "symbol": "_T.4main1f", "signature": "(number) => number", "exported": false,
+//!      "extern": false, "frame_size": 32, "parameters": [{"name": "x", "type": "number",
+//!      "offset": -16}], "locals": [{"name": "a", "offset": -20}]}
+//!   ],
+//!   "globals": [{"name": "count", "symbol": "_T.5count", "value": 0}]
+//! }
+//! ```
+//!
+//! The names of the mangled functions are the demangled ones, see
+//! `crate::mangle`. The offsets are from the `fp` of the ARM32 frame. Extern functions have
+//! no frame, their size and the offsets are `null`.
+use std::collections::BTreeMap;
+
+use crate::ast::Ast;
+use crate::diagnostic::json_string;
+use crate::mangle;
+use crate::phases::codegen::{self, FrameLayout};
+use crate::types::Type;
+
+/// Renders the symbols of the program given the frames of its functions and
+/// their sizes by label, see `codegen::frame_sizes`.
+pub fn to_json(
+    program: &Ast,
+    layouts: &[FrameLayout],
+    frame_sizes: &BTreeMap<String, u32>,
+) -> String {
+    let exported: Vec<&str> = program
+        .top_level_statements()
+        .iter()
+        .filter_map(|statement| match statement {
+            Ast::Export(function) => match function.as_ref() {
+                Ast::Function(name, _, _) => Some(name.as_str()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    let mut functions = Vec::new();
+    for declaration in program.declarations() {
+        let (name, signature, external) = match declaration {
+            Ast::Function(name, signature, _) => (name, signature, false),
+            Ast::Extern(name, signature) => (name, signature, true),
+            _ => continue,
+        };
+        let layout = layouts
+            .iter()
+            .find(|layout| !external && layout.function == *name);
+        functions.push(function_json(
+            name,
+            signature,
+            exported.contains(&name.as_str()),
+            layout,
+            frame_sizes,
+        ));
+    }
+    let globals: Vec<String> = codegen::global_variables(program)
+        .into_iter()
+        .map(|(name, value)| {
+            format!(
+                "{{\"name\": {}, \"symbol\": {}, \"value\": {}}}",
+                json_string(name),
+                json_string(&mangle::global(name)),
+                value
+            )
+        })
+        .collect();
+    format!(
+        "{{\n  \"functions\": [{}],\n  \"globals\": [{}]\n}}\n",
+        list(&functions),
+        list(&globals)
+    )
+}
+
+// The items on their own lines.
+fn list(items: &[String]) -> String {
+    if items.is_empty() {
+        return String::new();
+    }
+    format!("\n    {}\n  ", items.join(",\n    "))
+}
+
+fn function_json(
+    name: &str,
+    signature: &Type,
+    exported: bool,
+    layout: Option<&FrameLayout>,
+    frame_sizes: &BTreeMap<String, u32>,
+) -> String {
+    let offset = |variable: &str, variables: &[(String, isize)]| {
+        variables
+            .iter()
+            .find(|(name, _)| name == variable)
+            .map_or(String::from("null"), |(_, offset)| offset.to_string())
+    };
+    let parameters: Vec<String> = match signature {
+        Type::Function {
+            parameter_types, ..
+        } => parameter_types
+            .iter()
+            .map(|(parameter, parameter_type)| {
+                format!(
+                    "{{\"name\": {}, \"type\": {}, \"offset\": {}}}",
+                    json_string(parameter),
+                    json_string(&parameter_type.to_string()),
+                    layout.map_or(String::from("null"), |layout| {
+                        offset(parameter, &layout.parameters)
+                    })
+                )
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let locals: Vec<String> = layout
+        .map(|layout| layout.locals.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|(local, offset)| {
+            format!(
+                "{{\"name\": {}, \"offset\": {}}}",
+                json_string(local),
+                offset
+            )
+        })
+        .collect();
+    let symbol = layout.map_or(name, |layout| layout.label.as_str());
+    let frame_size = layout
+        .and_then(|layout| frame_sizes.get(&layout.label))
+        .map_or(String::from("null"), u32::to_string);
+    format!(
+        "{{\"name\": {}, \"symbol\": {}, \"signature\": {}, \"exported\": {}, \"extern\": {}, \
+         \"frame_size\": {}, \"parameters\": [{}], \"locals\": [{}]}}",
+        json_string(&mangle::demangle(name).unwrap_or_else(|| String::from(name))),
+        json_string(symbol),
+        json_string(&signature.to_string()),
+        exported,
+        layout.is_none(),
+        frame_size,
+        parameters.join(", "),
+        locals.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::phases::codegen::{Arm32Generator, Environment};
+
+    #[test]
+    fn symbols() {
+        let program = parser::parse_program(
+            "var count = 2;
+            extern function abs(x: number): number;
+            export function f(x: number): number { var a = x; return a; }",
+        )
+        .unwrap();
+        let generator = Arm32Generator::new(program.clone());
+        let code = generator.generate(&mut Environment::default());
+        assert_eq!(
+            to_json(
+                &program,
+                &generator.frame_layouts(),
+                &codegen::frame_sizes(&code)
+            ),
+            r#"{
+  "functions": [
+    {"name": "abs", "symbol": "abs", "signature": "(number) => number", "exported": false, "extern": true, "frame_size": null, "parameters": [{"name": "x", "type": "number", "offset": null}], "locals": []},
+    {"name": "f", "symbol": "f", "signature": "(number) => number", "exported": true, "extern": false, "frame_size": 32, "parameters": [{"name": "x", "type": "number", "offset": -16}], "locals": [{"name": "a", "offset": -20}]}
+  ],
+  "globals": [
+    {"name": "count", "symbol": "_T.5count", "value": 2}
+  ]
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! Wall time and sizes of the phases of a compilation, printed with
+//! `--time-passes` to spot where the time goes.
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct Pass {
+    name: &'static str,
+    duration: Duration,
+    /// How much the phase produced, e.g. `(132, "tokens")`.
+    size: Option<(usize, &'static str)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Timings {
+    passes: Vec<Pass>,
+}
+
+impl Timings {
+    /// Runs the phase, measuring how long it takes.
+    pub fn time<T>(&mut self, name: &'static str, phase: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = phase();
+        self.passes.push(Pass {
+            name,
+            duration: start.elapsed(),
+            size: None,
+        });
+        result
+    }
+
+    /// Records the size of the output of the phase timed last.
+    pub fn size(&mut self, count: usize, unit: &'static str) {
+        if let Some(pass) = self.passes.last_mut() {
+            pass.size = Some((count, unit));
+        }
+    }
+
+    /// A line per phase with its time in milliseconds, followed by the total.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        let line = |report: &mut String, name: &str, duration: Duration, size: String| {
+            let line = format!(
+                "time: {:>9.3}ms  {:<10}{}",
+                duration.as_secs_f64() * 1000.0,
+                name,
+                size
+            );
+            writeln!(report, "{}", line.trim_end()).unwrap();
+        };
+        for pass in &self.passes {
+            let size = match pass.size {
+                Some((count, unit)) => format!("{} {}", count, unit),
+                None => String::new(),
+            };
+            line(&mut report, pass.name, pass.duration, size);
+        }
+        let total = self.passes.iter().map(|pass| pass.duration).sum();
+        line(&mut report, "total", total, String::new());
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let mut timings = Timings::default();
+        assert_eq!(timings.time("parse", || 1 + 1), 2);
+        timings.size(3, "nodes");
+        timings.time("link", || {});
+        let report = timings.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("time: "));
+        assert!(lines[0].ends_with("ms  parse     3 nodes"));
+        assert!(lines[1].ends_with("ms  link"));
+        assert!(lines[2].ends_with("ms  total"));
+    }
+}
@@ -1,9 +1,19 @@
+use std::fmt;
+
 use linked_hash_map::LinkedHashMap;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Type {
     Boolean,
     Number,
+    /// Single precision, kept in the same 32 bit word as a number.
+    Float,
+    /// Characters, stored like an array of their codes.
+    String,
     Void,
     Undefined,
     Array {
@@ -20,6 +30,8 @@ impl PartialEq for Type {
         match (self, rhs) {
             (Type::Boolean, Type::Boolean) => true,
             (Type::Number, Type::Number) => true,
+            (Type::Float, Type::Float) => true,
+            (Type::String, Type::String) => true,
             (Type::Undefined, Type::Undefined) => true,
             (Type::Void, Type::Void) => true,
             (Type::Array { element_type: a }, Type::Array { element_type: b }) => a == b,
@@ -32,10 +44,74 @@ impl PartialEq for Type {
                     parameter_types: pb,
                     return_type: rb,
                 },
-            ) => ra == rb && pa.values().into_iter().eq(pb.values().into_iter()),
+            ) => ra == rb && pa.values().eq(pb.values()),
             _ => false,
         }
     }
 }
 
 impl Eq for Type {}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Boolean => write!(f, "boolean"),
+            Type::Number => write!(f, "number"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Void => write!(f, "void"),
+            Type::Undefined => write!(f, "undefined"),
+            Type::Array { element_type } => write!(f, "Array<{}>", element_type),
+            Type::Function {
+                parameter_types,
+                return_type,
+            } => {
+                write!(f, "(")?;
+                for (i, parameter_type) in parameter_types.values().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", parameter_type)?;
+                }
+                write!(f, ") => {}", return_type)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_scalar_types() {
+        assert_eq!(Type::Boolean.to_string(), "boolean");
+        assert_eq!(Type::Number.to_string(), "number");
+        assert_eq!(Type::Float.to_string(), "float");
+        assert_eq!(Type::String.to_string(), "string");
+        assert_eq!(Type::Void.to_string(), "void");
+        assert_eq!(Type::Undefined.to_string(), "undefined");
+    }
+
+    #[test]
+    fn display_compound_types() {
+        let array = Type::Array {
+            element_type: Box::new(Type::Array {
+                element_type: Box::new(Type::Number),
+            }),
+        };
+        assert_eq!(array.to_string(), "Array<Array<number>>");
+
+        let function = Type::Function {
+            parameter_types: [
+                (String::from("x"), Type::Number),
+                (String::from("y"), Type::Boolean),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            return_type: Box::new(Type::Void),
+        };
+        assert_eq!(function.to_string(), "(number, boolean) => void");
+    }
+}
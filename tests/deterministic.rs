@@ -0,0 +1,80 @@
+//! Compiling the same program with the same options has to give the same
+//! bytes every time, for reproducible builds. Every run is a new process,
+//! so nothing may depend on the order of hash maps, addresses or the time.
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+const PROGRAMS: [&str; 3] = [
+    "main.ts",
+    "tests/golden/strings.ts",
+    "tests/golden/globals.ts",
+];
+
+// Programs calling builtins which only the ARM32 runtime has, along with the
+// configurations generating code for other targets.
+const ARM32_ONLY: [&str; 1] = ["tests/golden/strings.ts"];
+const OTHER_TARGETS: [&str; 2] = ["--target=wasm32", "--emit=llvm-ir"];
+
+const CONFIGURATIONS: [&[&str]; 10] = [
+    &[],
+    &["--gc", "--entry=main", "--source-comments"],
+    &["-g", "--bounds-check=trap", "--null-check"],
+    &["--thumb", "--asm-header=unified"],
+    &["--target=wasm32"],
+    &["--emit=llvm-ir"],
+    &["--emit=call-graph"],
+    &["--emit=stack-usage"],
+    &["--emit=symbols"],
+    &["--emit=cfg"],
+];
+
+// Everything the compiler writes and how it exits, with the object file
+// written by `--emit=obj` when there is one.
+fn compile(program: &str, arguments: &[&str]) -> (Option<i32>, Vec<u8>, Vec<u8>, Vec<u8>) {
+    let object = env::temp_dir().join(format!("rtsc-deterministic-{}.o", process::id()));
+    let output = Command::new(env!("CARGO_BIN_EXE_rtsc"))
+        .args(arguments)
+        .arg(program)
+        .output()
+        .unwrap();
+    let object_bytes = fs::read(&object).unwrap_or_default();
+    let _ = fs::remove_file(&object);
+    (
+        output.status.code(),
+        output.stdout,
+        output.stderr,
+        object_bytes,
+    )
+}
+
+#[test]
+fn same_output_twice() {
+    let object = env::temp_dir().join(format!("rtsc-deterministic-{}.o", process::id()));
+    let object = object.to_str().unwrap();
+    let object_configuration = ["--emit=obj", "-o", object];
+    for program in PROGRAMS {
+        for arguments in CONFIGURATIONS
+            .iter()
+            .chain([&object_configuration[..]].iter())
+        {
+            let other_target = arguments
+                .iter()
+                .any(|argument| OTHER_TARGETS.contains(argument));
+            let status = if ARM32_ONLY.contains(&program) && other_target {
+                1
+            } else {
+                0
+            };
+            let first = compile(program, arguments);
+            assert_eq!(first.0, Some(status), "{} {:?}", program, arguments);
+            assert_eq!(
+                compile(program, arguments),
+                first,
+                "{} {:?}",
+                program,
+                arguments
+            );
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Compiles every program in `tests/golden` to ARM32 assembly and compares it
+//! with the `.s` file next to it, so that changes to the code generator show
+//! up as diffs of the snapshots. Run with `RTSC_BLESS=1` to write the
+//! snapshots from the current output instead.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rtsc::compiler::Output;
+use rtsc::Compiler;
+
+fn programs(directory: &Path) -> Vec<PathBuf> {
+    let mut programs: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ts"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+fn compile(program: &Path) -> String {
+    let compiled = Compiler::new()
+        .compile_file(program)
+        .unwrap_or_else(|errors| panic!("{}: {:?}", program.display(), errors));
+    match compiled.output {
+        Output::Text(assembly) => assembly,
+        output => panic!("{}: {:?} is not assembly", program.display(), output),
+    }
+}
+
+// The first line which differs, enough to tell what changed.
+fn difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(expected), Some(actual)) if expected == actual => continue,
+            (None, None) => break,
+            (expected, actual) => {
+                return format!(
+                    "line {}: expected {:?}, got {:?}",
+                    line,
+                    expected.unwrap_or("<end>"),
+                    actual.unwrap_or("<end>")
+                )
+            }
+        }
+    }
+    String::from("the snapshots differ")
+}
+
+#[test]
+fn golden() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let bless = env::var_os("RTSC_BLESS").is_some();
+    let mut failures = Vec::new();
+    for program in programs(&directory) {
+        let assembly = compile(&program);
+        let snapshot = program.with_extension("s");
+        if bless {
+            fs::write(&snapshot, &assembly).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&snapshot) {
+            Ok(expected) if expected == assembly => {}
+            Ok(expected) => failures.push(format!(
+                "{}: {}",
+                snapshot.display(),
+                difference(&expected, &assembly)
+            )),
+            Err(error) => failures.push(format!("{}: {}", snapshot.display(), error)),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "the assembly differs from the snapshots, rerun with RTSC_BLESS=1 to \
+         update them if the changes are intended:\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,229 @@
+
+.local _T.6arrays3sum
+_T.6arrays3sum:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    sub sp, sp, #8
+    ldr r0, =0
+    str r0, [fp, #-20]
+    ldr r0, =0
+    str r0, [fp, #-24]
+.L0:
+    ldr r0, [fp, #-24]
+    ldr r1, [fp, #-16]
+    ldr r1, [r1, #0]
+    cmp r0, r1
+    moveq r0, #0
+    movne r0, #1
+    cmp r0, #0
+    beq .L1
+    ldr r1, [fp, #-16]
+    ldr r0, [fp, #-24]
+    ldr r2, [r1]
+    cmp r0, r2
+    movhs r1, #0
+    addlo r1, r1, #4
+    lsllo r0, r0, #2
+    ldrlo r1, [r1, r0]
+    ldr r0, [fp, #-20]
+    add r0, r0, r1
+    str r0, [fp, #-20]
+    ldr r0, [fp, #-24]
+    ldr r1, =1
+    add r0, r0, r1
+    str r0, [fp, #-24]
+    b .L0
+.L1:
+    ldr r0, [fp, #-20]
+    mov sp, fp
+    pop {fp, pc}
+
+.global main
+main:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    sub sp, sp, #24
+    ldr r0, =.L2
+    str r0, [fp, #-20]
+    sub sp, sp, #16
+    ldr r0, [fp, #-20]
+    str r0, [sp, #0]
+    ldr r0, =4
+    str r0, [sp, #4]
+    pop {r0, r1, r2, r3}
+    bl __rtsc_push
+    str r0, [fp, #-20]
+    sub sp, sp, #16
+    ldr r0, [fp, #-20]
+    str r0, [sp, #0]
+    sub sp, sp, #16
+    ldr r0, [fp, #-20]
+    str r0, [sp, #0]
+    ldr r0, =1
+    str r0, [sp, #4]
+    ldr r0, =3
+    str r0, [sp, #8]
+    pop {r0, r1, r2, r3}
+    bl __rtsc_slice
+    str r0, [sp, #4]
+    pop {r0, r1, r2, r3}
+    bl __rtsc_concat
+    str r0, [fp, #-24]
+    str r0, [fp, #-28]
+    ldr r1, =0
+    ldr r2, [r0]
+    cmp r1, r2
+    movhs r0, #0
+    addlo r0, r0, #4
+    lsllo r1, r1, #2
+    ldrlo r0, [r0, r1]
+    str r0, [fp, #-32]
+    ldr r0, [fp, #-28]
+    ldr r1, =1
+    ldr r2, [r0]
+    cmp r1, r2
+    movhs r0, #0
+    addlo r0, r0, #4
+    lsllo r1, r1, #2
+    ldrlo r0, [r0, r1]
+    str r0, [fp, #-36]
+    ldr r0, =48
+    ldr r1, [fp, #-32]
+    add r0, r0, r1
+    ldr r1, [fp, #-36]
+    add r0, r0, r1
+    bl putchar
+    ldr r0, [fp, #-24]
+    bl _T.6arrays3sum
+    mov sp, fp
+    pop {fp, pc}
+.section .rodata
+    .balign 4
+    .word 3
+.L2:
+    .word 3
+    .word 1
+    .word 2
+    .word 3
+.section .text
+__rtsc_push:
+    push {r0, r1, r4, r5, r6, lr}
+    mov r4, r0
+    ldr r5, [r0]
+    ldr r1, [r0, #-4]
+    cmp r5, r1
+    blo .Lpush_copied
+    mov r0, r5
+    lsl r1, r5, #1
+    cmp r1, #4
+    movlo r1, #4
+    bl __rtsc_new_array
+    mov r1, r4
+    mov r2, r0
+    mov r3, r5
+    mov r4, r0
+.Lpush_copy:
+    cmp r3, #0
+    beq .Lpush_copied
+    add r1, r1, #4
+    add r2, r2, #4
+    ldr ip, [r1]
+    str ip, [r2]
+    sub r3, r3, #1
+    b .Lpush_copy
+.Lpush_copied:
+    add r5, r5, #1
+    str r5, [r4]
+    lsl r5, r5, #2
+    ldr r1, [sp, #4]
+    str r1, [r4, r5]
+    mov r0, r4
+    add sp, sp, #8
+    pop {r4, r5, r6, pc}
+__rtsc_pop:
+    ldr r1, [r0]
+    cmp r1, #0
+    moveq r0, #0
+    moveq pc, lr
+    sub r2, r1, #1
+    str r2, [r0]
+    lsl r1, r1, #2
+    ldr r0, [r0, r1]
+    mov pc, lr
+__rtsc_concat:
+    push {r0, r1, r4, r5, r6, lr}
+    ldr r4, [r0]
+    ldr r5, [r1]
+    add r0, r4, r5
+    mov r1, r0
+    bl __rtsc_new_array
+    ldr r1, [sp, #0]
+    mov r2, r0
+    mov r3, r4
+.Lconcat_copy_first:
+    cmp r3, #0
+    beq .Lconcat_first_copied
+    add r1, r1, #4
+    add r2, r2, #4
+    ldr ip, [r1]
+    str ip, [r2]
+    sub r3, r3, #1
+    b .Lconcat_copy_first
+.Lconcat_first_copied:
+    ldr r1, [sp, #4]
+    mov r3, r5
+.Lconcat_copy_second:
+    cmp r3, #0
+    beq .Lconcat_second_copied
+    add r1, r1, #4
+    add r2, r2, #4
+    ldr ip, [r1]
+    str ip, [r2]
+    sub r3, r3, #1
+    b .Lconcat_copy_second
+.Lconcat_second_copied:
+    add sp, sp, #8
+    pop {r4, r5, r6, pc}
+__rtsc_slice:
+    push {r0, r1, r4, r5, r6, lr}
+    ldr r3, [r0]
+    cmp r2, r3
+    movgt r2, r3
+    cmp r1, #0
+    movlt r1, #0
+    cmp r1, r2
+    movgt r1, r2
+    lsl r4, r1, #2
+    sub r5, r2, r1
+    mov r0, r5
+    mov r1, r5
+    bl __rtsc_new_array
+    ldr r1, [sp, #0]
+    add r1, r1, r4
+    mov r2, r0
+    mov r3, r5
+.Lslice_copy:
+    cmp r3, #0
+    beq .Lslice_copied
+    add r1, r1, #4
+    add r2, r2, #4
+    ldr ip, [r1]
+    str ip, [r2]
+    sub r3, r3, #1
+    b .Lslice_copy
+.Lslice_copied:
+    add sp, sp, #8
+    pop {r4, r5, r6, pc}
+__rtsc_new_array:
+    push {r4, r5, r6, lr}
+    mov r4, r0
+    mov r5, r1
+    add r0, r1, #2
+    lsl r0, r0, #2
+    bl malloc
+    str r5, [r0]
+    add r0, r0, #4
+    str r4, [r0]
+    pop {r4, r5, r6, pc}
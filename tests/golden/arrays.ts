@@ -0,0 +1,18 @@
+function sum(xs: array<number>) {
+    var total = 0;
+    var i = 0;
+    while (i != length(xs)) {
+        total = total + xs[i];
+        i = i + 1;
+    }
+    return total;
+}
+
+function main() {
+    var xs = [1, 2, 3];
+    push(xs, 4);
+    var ys = concat(xs, slice(xs, 1, 3));
+    var [first, second] = ys;
+    putchar(48 + first + second);
+    return sum(ys);
+}
@@ -0,0 +1,325 @@
+
+.local _T.6basics6assert
+_T.6basics6assert:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    ldr r0, [fp, #-16]
+    cmp r0, #0
+    beq .L0
+    ldr r0, =46
+    bl putchar
+    b .L1
+.L0:
+    ldr r0, =70
+    bl putchar
+.L1:
+    mov sp, fp
+    mov r0, #0
+    pop {fp, pc}
+
+.local _T.6basics27assert_four_parameters_work
+_T.6basics27assert_four_parameters_work:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    ldr r0, [fp, #-16]
+    ldr r1, =1
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, [fp, #-12]
+    ldr r1, =2
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, [fp, #-8]
+    ldr r1, =3
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, [fp, #-4]
+    ldr r1, =4
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    mov sp, fp
+    mov r0, #0
+    pop {fp, pc}
+
+.local _T.6basics7add_one
+_T.6basics7add_one:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    ldr r0, [fp, #-16]
+    ldr r1, =1
+    add r0, r0, r1
+    mov sp, fp
+    pop {fp, pc}
+
+.local _T.6basics9factorial
+_T.6basics9factorial:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    ldr r0, [fp, #-16]
+    ldr r1, =0
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    cmp r0, #0
+    beq .L2
+    ldr r0, =1
+    mov sp, fp
+    pop {fp, pc}
+.L2:
+    ldr r0, [fp, #-16]
+    push {r0, ip}
+    ldr r0, [fp, #-16]
+    ldr r1, =1
+    sub r0, r0, r1
+    bl _T.6basics9factorial
+    pop {r1, ip}
+    mul r0, r1, r0
+    mov sp, fp
+    pop {fp, pc}
+
+.local _T.6basics9fibonacci
+_T.6basics9fibonacci:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    ldr r0, [fp, #-16]
+    ldr r1, =0
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    cmp r0, #0
+    beq .L4
+    ldr r0, =0
+    mov sp, fp
+    pop {fp, pc}
+.L4:
+    ldr r0, [fp, #-16]
+    ldr r1, =1
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    cmp r0, #0
+    beq .L6
+    ldr r0, =1
+    mov sp, fp
+    pop {fp, pc}
+.L6:
+    ldr r0, [fp, #-16]
+    ldr r1, =1
+    sub r0, r0, r1
+    bl _T.6basics9fibonacci
+    push {r0, ip}
+    ldr r0, [fp, #-16]
+    ldr r1, =2
+    sub r0, r0, r1
+    bl _T.6basics9fibonacci
+    pop {r1, ip}
+    add r0, r1, r0
+    mov sp, fp
+    pop {fp, pc}
+
+.global main
+main:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    sub sp, sp, #16
+    mov r0, #1
+    bl _T.6basics6assert
+    mov r0, #1
+    bl _T.6basics6assert
+    mov r0, #1
+    cmp r0, #0
+    mov r0, #1
+    bl _T.6basics6assert
+    sub sp, sp, #16
+    ldr r0, =1
+    str r0, [sp, #0]
+    ldr r0, =2
+    str r0, [sp, #4]
+    ldr r0, =3
+    str r0, [sp, #8]
+    ldr r0, =4
+    str r0, [sp, #12]
+    pop {r0, r1, r2, r3}
+    bl _T.6basics27assert_four_parameters_work
+    ldr r0, =1
+    bl _T.6basics7add_one
+    push {r0, ip}
+    ldr r0, =2
+    pop {r1, ip}
+    cmp r1, r0
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, =5
+    bl _T.6basics9factorial
+    push {r0, ip}
+    ldr r0, =120
+    pop {r1, ip}
+    cmp r1, r0
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, =3
+    bl _T.6basics9fibonacci
+    push {r0, ip}
+    ldr r0, =2
+    pop {r1, ip}
+    cmp r1, r0
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, =1
+    str r0, [fp, #-20]
+    ldr r1, =1
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, =0
+    str r0, [fp, #-20]
+    ldr r1, =0
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, =4
+    str r0, [fp, #-24]
+.L10:
+    ldr r0, [fp, #-24]
+    ldr r1, =4
+    cmp r0, r1
+    moveq r0, #0
+    movne r0, #1
+    cmp r0, #0
+    beq .L11
+    ldr r0, [fp, #-24]
+    ldr r1, =1
+    add r0, r0, r1
+    str r0, [fp, #-24]
+    b .L10
+.L11:
+    ldr r0, [fp, #-24]
+    ldr r1, =4
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    mov r0, #1
+    bl _T.6basics6assert
+    mov r0, #1
+    bl _T.6basics6assert
+    ldr r0, =-12
+    str r0, [fp, #-28]
+    cmp r0, #0
+    rsblt r0, r0, #0
+    lsr r0, r0, #2
+    rsblt r0, r0, #0
+    ldr r1, =-3
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, [fp, #-28]
+    ldr r1, =-4
+    sdiv r0, r0, r1
+    ldr r1, =3
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    mov r0, #1
+    bl _T.6basics6assert
+    ldr r0, [fp, #-28]
+    ldr r1, =-2
+    mul r0, r0, r1
+    ldr r1, =24
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, =.L12
+    str r0, [fp, #-32]
+    ldr r1, =0
+    ldr r2, [r0]
+    cmp r1, r2
+    movhs r0, #0
+    addlo r0, r0, #4
+    lsllo r1, r1, #2
+    ldrlo r0, [r0, r1]
+    ldr r1, =10
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, [fp, #-32]
+    ldr r1, =1
+    ldr r2, [r0]
+    cmp r1, r2
+    movhs r0, #0
+    addlo r0, r0, #4
+    lsllo r1, r1, #2
+    ldrlo r0, [r0, r1]
+    ldr r1, =20
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, [fp, #-32]
+    ldr r1, =2
+    ldr r2, [r0]
+    cmp r1, r2
+    movhs r0, #0
+    addlo r0, r0, #4
+    lsllo r1, r1, #2
+    ldrlo r0, [r0, r1]
+    ldr r1, =30
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, [fp, #-32]
+    ldr r1, =3
+    ldr r2, [r0]
+    cmp r1, r2
+    movhs r0, #0
+    addlo r0, r0, #4
+    lsllo r1, r1, #2
+    ldrlo r0, [r0, r1]
+    mov r1, #0
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    ldr r0, [fp, #-32]
+    ldr r0, [r0, #0]
+    ldr r1, =3
+    cmp r0, r1
+    moveq r0, #1
+    movne r0, #0
+    bl _T.6basics6assert
+    mov sp, fp
+    mov r0, #0
+    pop {fp, pc}
+.section .rodata
+    .balign 4
+    .word 3
+.L12:
+    .word 3
+    .word 10
+    .word 20
+    .word 30
+.section .text
@@ -0,0 +1,81 @@
+
+.local _T.7globals4tick
+_T.7globals4tick:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    ldr r0, =_T.7counter
+    ldr r0, [r0]
+    ldr r1, =1
+    add r0, r0, r1
+    ldr r1, =_T.7counter
+    str r0, [r1]
+    ldr r0, =_T.7counter
+    ldr r0, [r0]
+    mov sp, fp
+    pop {fp, pc}
+
+.local _T.7globals4area
+_T.7globals4area:
+    vmov r0, s0
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    ldr r0, =_T.5scale
+    ldr r0, [r0]
+    ldr r1, [fp, #-16]
+    vmov s0, r0
+    vmov s1, r1
+    vmul.f32 s0, s0, s1
+    vmov r0, s0
+    vmov s0, r0
+    vmov s1, r1
+    vmul.f32 s0, s0, s1
+    vmov r0, s0
+    vmov s0, r0
+    mov sp, fp
+    pop {fp, pc}
+
+.global main
+main:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    sub sp, sp, #8
+    bl _T.7globals4tick
+    bl _T.7globals4tick
+    ldr r0, =1073741824
+    vmov s0, r0
+    bl _T.7globals4area
+    vmov r0, s0
+    vmov s0, r0
+    bl sqrtf
+    vmov r0, s0
+    str r0, [fp, #-20]
+    ldr r1, =0
+    vmov s0, r0
+    vmov s1, r1
+    vcmp.f32 s0, s1
+    vmrs APSR_nzcv, fpscr
+    moveq r0, #1
+    movne r0, #0
+    cmp r0, #0
+    beq .L0
+    ldr r0, =1
+    mov sp, fp
+    pop {fp, pc}
+.L0:
+    ldr r0, =_T.7counter
+    ldr r0, [r0]
+    mov sp, fp
+    pop {fp, pc}
+.section .data
+    .balign 4
+_T.5scale:
+    .word 1075838976
+.section .text
+.section .bss
+    .balign 4
+_T.7counter:
+    .space 4
+.section .text
@@ -0,0 +1,24 @@
+var counter = 0;
+var scale = 2.5;
+
+extern function sqrtf(x: float): float;
+
+function tick() {
+    counter = counter + 1;
+    return counter;
+}
+
+function area(r: float): float {
+    return scale * r * r;
+}
+
+function main() {
+    tick();
+    tick();
+    var side = sqrtf(area(2.0));
+    if (side == 0.0) {
+        return 1;
+    } else {
+        return counter;
+    }
+}
@@ -0,0 +1,155 @@
+
+.local _T.7strings5print
+_T.7strings5print:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    sub sp, sp, #8
+    ldr r0, =0
+    str r0, [fp, #-20]
+.L0:
+    ldr r0, [fp, #-20]
+    ldr r1, [fp, #-16]
+    ldr r1, [r1, #0]
+    cmp r0, r1
+    moveq r0, #0
+    movne r0, #1
+    cmp r0, #0
+    beq .L1
+    ldr r0, [fp, #-16]
+    ldr r1, [fp, #-20]
+    ldr r2, [r0]
+    cmp r1, r2
+    movhs r0, #0
+    addlo r0, r0, #4
+    lsllo r1, r1, #2
+    ldrlo r0, [r0, r1]
+    bl putchar
+    ldr r0, [fp, #-20]
+    ldr r1, =1
+    add r0, r0, r1
+    str r0, [fp, #-20]
+    b .L0
+.L1:
+    mov sp, fp
+    mov r0, #0
+    pop {fp, pc}
+
+.global main
+main:
+    push {fp, lr}
+    mov fp, sp
+    push {r0, r1, r2, r3}
+    sub sp, sp, #8
+    ldr r0, =.L2
+    str r0, [fp, #-20]
+    sub sp, sp, #16
+    ldr r0, [fp, #-20]
+    str r0, [sp, #0]
+    ldr r0, =.L2
+    str r0, [sp, #4]
+    pop {r0, r1, r2, r3}
+    bl __rtsc_string_equal
+    cmp r0, #0
+    beq .L3
+    ldr r0, [fp, #-20]
+    bl _T.7strings5print
+    b .L4
+.L3:
+    ldr r0, =.L5
+    bl _T.7strings5print
+.L4:
+    ldr r0, =-42
+    bl __rtsc_number_to_string
+    str r0, [fp, #-24]
+    ldr r0, [r0, #0]
+    mov sp, fp
+    pop {fp, pc}
+.section .rodata
+    .balign 4
+    .word 5
+.L2:
+    .word 5
+    .word 104
+    .word 101
+    .word 108
+    .word 108
+    .word 111
+    .word 3
+.L5:
+    .word 3
+    .word 98
+    .word 121
+    .word 101
+.section .text
+__rtsc_number_to_string:
+    push {r4, r5, r6, lr}
+    sub sp, sp, #48
+    mov r6, r0
+    cmp r0, #0
+    rsblt r0, r0, #0
+    add r5, sp, #48
+    mov r1, #10
+.Lnumber_to_string_digits:
+    udiv r2, r0, r1
+    mul r3, r2, r1
+    sub r3, r0, r3
+    add r3, r3, #48
+    sub r5, r5, #4
+    str r3, [r5]
+    mov r0, r2
+    cmp r0, #0
+    bne .Lnumber_to_string_digits
+    cmp r6, #0
+    movlt r3, #45
+    sublt r5, r5, #4
+    strlt r3, [r5]
+    add r4, sp, #48
+    sub r4, r4, r5
+    lsr r4, r4, #2
+    mov r0, r4
+    mov r1, r4
+    bl __rtsc_new_array
+    mov r1, r0
+.Lnumber_to_string_copy:
+    cmp r4, #0
+    beq .Lnumber_to_string_done
+    ldr r2, [r5]
+    add r5, r5, #4
+    add r1, r1, #4
+    str r2, [r1]
+    sub r4, r4, #1
+    b .Lnumber_to_string_copy
+.Lnumber_to_string_done:
+    add sp, sp, #48
+    pop {r4, r5, r6, pc}
+__rtsc_new_array:
+    push {r4, r5, r6, lr}
+    mov r4, r0
+    mov r5, r1
+    add r0, r1, #2
+    lsl r0, r0, #2
+    bl malloc
+    str r5, [r0]
+    add r0, r0, #4
+    str r4, [r0]
+    pop {r4, r5, r6, pc}
+__rtsc_string_equal:
+    ldr r2, [r0]
+    ldr r3, [r1]
+    cmp r2, r3
+    movne r0, #0
+    movne pc, lr
+.Lstring_equal_characters:
+    cmp r2, #0
+    moveq r0, #1
+    moveq pc, lr
+    add r0, r0, #4
+    add r1, r1, #4
+    ldr r3, [r0]
+    ldr ip, [r1]
+    cmp r3, ip
+    movne r0, #0
+    movne pc, lr
+    sub r2, r2, #1
+    b .Lstring_equal_characters
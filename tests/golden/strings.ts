@@ -0,0 +1,18 @@
+function print(s: string) {
+    var i = 0;
+    while (i != length(s)) {
+        putchar(s[i]);
+        i = i + 1;
+    }
+}
+
+function main() {
+    var greeting = "hello";
+    if (greeting == "hello") {
+        print(greeting);
+    } else {
+        print("bye");
+    }
+    var digits = numberToString(0 - 42);
+    return length(digits);
+}
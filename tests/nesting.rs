@@ -0,0 +1,54 @@
+//! Runs the compiler on programs nested right up to `parser::MAX_DEPTH`,
+//! which have to compile without running any phase out of stack, as the
+//! ones nested deeper have to be rejected with an error.
+use std::env;
+use std::fs;
+use std::process::{self, Command};
+
+use rtsc::parser::MAX_DEPTH;
+
+// `main` holding `depth` nested `if` statements around an assignment, as
+// many levels deep with the function, the assignment and its expression
+fn nested_ifs(depth: usize) -> String {
+    format!(
+        "function main(): number {{\nvar x = 0;\n{}x = x + 1;\n{}return x;\n}}\n",
+        "if (x == 0) {\n".repeat(depth - 3),
+        "} else {}\n".repeat(depth - 3)
+    )
+}
+
+// How the compiler exits on the program, with what it prints to the
+// standard error.
+fn compile(source: &str, arguments: &[&str]) -> (Option<i32>, String) {
+    let directory = env::temp_dir();
+    let input = directory.join(format!("rtsc-nesting-{}.ts", process::id()));
+    let output = directory.join(format!("rtsc-nesting-{}.s", process::id()));
+    fs::write(&input, source).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_rtsc"))
+        .args(arguments)
+        .arg("-o")
+        .arg(&output)
+        .arg(&input)
+        .output()
+        .unwrap();
+    let _ = fs::remove_file(&input);
+    let _ = fs::remove_file(&output);
+    (
+        result.status.code(),
+        String::from_utf8(result.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn nested_up_to_the_limit() {
+    let (status, stderr) = compile(&nested_ifs(MAX_DEPTH), &[]);
+    assert_eq!(status, Some(0), "{}", stderr);
+
+    let (status, stderr) = compile(&nested_ifs(MAX_DEPTH + 1), &[]);
+    assert_eq!(status, Some(1), "{}", stderr);
+    assert!(stderr.contains("Nesting too deep"), "{}", stderr);
+
+    let max_depth = format!("--max-depth={}", MAX_DEPTH + 1);
+    let (status, stderr) = compile(&nested_ifs(MAX_DEPTH + 1), &[&max_depth]);
+    assert_eq!(status, Some(0), "{}", stderr);
+}
@@ -0,0 +1,77 @@
+//! Compiles every program in `tests/programs` and compares the diagnostics it
+//! gets, rendered as the command line prints them, with the `.stderr` file
+//! next to it. A program compiling without warnings has an empty one. Run
+//! with `RTSC_BLESS=1` to write the `.stderr` files from the current
+//! diagnostics instead. The modules the programs import are kept in
+//! `tests/programs/modules`.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rtsc::{Compiler, Diagnostic};
+
+const DIRECTORY: &str = "tests/programs";
+
+fn programs() -> Vec<PathBuf> {
+    // relative to the package, which the tests run in, so that the rendered
+    // paths are the same wherever it is checked out
+    let mut programs: Vec<PathBuf> = fs::read_dir(DIRECTORY)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ts"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+fn render(diagnostic: &Diagnostic) -> String {
+    let source = diagnostic
+        .file
+        .as_ref()
+        .and_then(|file| fs::read_to_string(file).ok());
+    match (&diagnostic.file, source) {
+        (Some(file), Some(source)) => diagnostic.render_snippet(file, &source, false),
+        _ => diagnostic.to_string(),
+    }
+}
+
+fn diagnostics(program: &Path) -> String {
+    let diagnostics = match Compiler::new().compile_file(program) {
+        Ok(compiled) => compiled.warnings,
+        Err(diagnostics) => diagnostics,
+    };
+    diagnostics
+        .iter()
+        .map(|diagnostic| render(diagnostic) + "\n")
+        .collect()
+}
+
+#[test]
+fn programs_diagnostics() {
+    let bless = env::var_os("RTSC_BLESS").is_some();
+    let mut failures = Vec::new();
+    for program in programs() {
+        let actual = diagnostics(&program);
+        let expected_path = program.with_extension("stderr");
+        if bless {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{}:\nexpected:\n{}\ngot:\n{}",
+                program.display(),
+                expected,
+                actual
+            )),
+            Err(error) => failures.push(format!("{}: {}", expected_path.display(), error)),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "the diagnostics differ, rerun with RTSC_BLESS=1 to update them if the \
+         changes are intended:\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,5 @@
+function main() {
+    var xs = [1, 2];
+    var ys = concat(xs);
+    return length(ys);
+}
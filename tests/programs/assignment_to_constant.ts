@@ -0,0 +1,6 @@
+const limit = 10;
+
+function main() {
+    limit = 20;
+    return limit;
+}
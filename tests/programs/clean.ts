@@ -0,0 +1,4 @@
+function main(): number {
+    var x = 1;
+    return x + 1;
+}
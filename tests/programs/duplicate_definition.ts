@@ -0,0 +1,9 @@
+import { shown } from "./modules/library";
+
+function shown(): number {
+    return 2;
+}
+
+function main(): number {
+    return shown();
+}
@@ -0,0 +1,4 @@
+function main() {
+    var xs = [];
+    return 0;
+}
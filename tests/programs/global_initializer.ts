@@ -0,0 +1,9 @@
+function one() {
+    return 1;
+}
+
+var x = one();
+
+function main() {
+    return x;
+}
@@ -0,0 +1,5 @@
+import { b } from "./modules/cycle_b";
+
+export function a() {
+    return b();
+}
@@ -0,0 +1,5 @@
+import { hidden } from "./modules/library";
+
+function main() {
+    return hidden();
+}
@@ -0,0 +1,5 @@
+import { f } from "./modules/nowhere";
+
+function main() {
+    return f();
+}
@@ -0,0 +1,5 @@
+import { a } from "../import_cycle";
+
+export function b() {
+    return a();
+}
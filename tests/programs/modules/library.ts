@@ -0,0 +1,7 @@
+export function shown(): number {
+    return 1;
+}
+
+function hidden(): number {
+    return 2;
+}
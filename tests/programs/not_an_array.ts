@@ -0,0 +1,4 @@
+function main() {
+    var x = 1;
+    return x[0];
+}
@@ -0,0 +1,4 @@
+function main() {
+    extern function abs(x: number): number;
+    return abs(0 - 1);
+}
@@ -0,0 +1,5 @@
+function main() {
+    var size = 4;
+    static_assert(size == 4, "size is four");
+    return size;
+}
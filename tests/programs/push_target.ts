@@ -0,0 +1,4 @@
+function main() {
+    push([1, 2], 3);
+    return 0;
+}
@@ -0,0 +1,5 @@
+static_assert(length("abc") == 4, "three characters");
+
+function main() {
+    return 0;
+}
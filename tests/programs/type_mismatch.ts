@@ -0,0 +1,5 @@
+function main() {
+    var x = 1;
+    x = true;
+    return x;
+}
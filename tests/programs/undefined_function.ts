@@ -0,0 +1,3 @@
+function main() {
+    return f(1);
+}
@@ -0,0 +1,3 @@
+function main() {
+    return y + 1;
+}
@@ -0,0 +1,7 @@
+function main() {
+    var x = 1;
+    x = 2;
+    x = 3;
+    return x;
+    putchar(x);
+}
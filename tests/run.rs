@@ -0,0 +1,260 @@
+//! Runs every program in `tests/run` and checks what it prints against the
+//! `.stdout` file next to it and its exit status against the `// exit: N`
+//! comment on its first line (zero without one).
+//!
+//! The programs always run in `phases::interpreter`. They are also built into
+//! ARM32 Linux executables, in several configurations, and run under QEMU to
+//! check that they behave as the interpreter says, catching miscompilations of
+//! calls and arrays which the golden tests cannot. That needs a cross
+//! toolchain and user mode QEMU, so it only runs when `RTSC_QEMU` is set: to
+//! `1` for `qemu-arm`, otherwise to the emulator command. `RTSC_LINKER`
+//! replaces `arm-linux-gnueabihf-gcc -static`.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use rtsc::compiler::{EmitKind, Output, Target};
+use rtsc::phases::codegen::CodegenOptions;
+use rtsc::phases::interpreter::Interpreter;
+use rtsc::phases::passes::{self, Passes};
+use rtsc::Compiler;
+
+const LINKER: &str = "arm-linux-gnueabihf-gcc -static";
+const EMULATOR: &str = "qemu-arm";
+
+fn programs(directory: &Path) -> Vec<PathBuf> {
+    let mut programs: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ts"))
+        .collect();
+    programs.sort();
+    programs
+}
+
+fn expected_status(source: &str) -> i32 {
+    source
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("// exit:"))
+        .map_or(0, |status| status.trim().parse().unwrap())
+}
+
+// Runs `command` split into words with `arguments` appended.
+fn run(command: &str, arguments: &[&Path]) -> Result<process::Output, String> {
+    let mut words = command.split_whitespace();
+    let program = words.next().expect("the command is empty");
+    Command::new(program)
+        .args(words)
+        .args(arguments)
+        .output()
+        .map_err(|error| format!("cannot run `{}`: {}", program, error))
+}
+
+// What running a program shows, the exit status only keeps its lowest byte.
+#[derive(Debug, PartialEq)]
+struct Observed {
+    stdout: String,
+    status: i32,
+}
+
+fn expected(program: &Path) -> Observed {
+    let source = fs::read_to_string(program).unwrap();
+    Observed {
+        stdout: fs::read_to_string(program.with_extension("stdout")).unwrap(),
+        status: expected_status(&source),
+    }
+}
+
+fn interpret(program: &Path) -> Result<Observed, String> {
+    let ast = Compiler::new()
+        .link_file(program)
+        .map_err(|errors| format!("{:?}", errors))?;
+    let mut stdout = Vec::new();
+    let status = Interpreter::new(ast).run(&mut stdout)?;
+    Ok(Observed {
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        status: status & 0xff,
+    })
+}
+
+fn execute(
+    program: &Path,
+    options: CodegenOptions,
+    linker: &str,
+    emulator: &str,
+) -> Result<Observed, String> {
+    let compiled = Compiler::new()
+        .with_codegen_options(options)
+        .compile_file(program)
+        .map_err(|errors| format!("{:?}", errors))?;
+    let assembly = match compiled.output {
+        Output::Text(assembly) => assembly,
+        output => return Err(format!("{:?} is not assembly", output)),
+    };
+
+    let name = program.file_stem().unwrap().to_string_lossy();
+    let base = env::temp_dir().join(format!("rtsc-qemu-{}-{}", process::id(), name));
+    let assembly_path = base.with_extension("s");
+    fs::write(&assembly_path, assembly).map_err(|error| error.to_string())?;
+    let linked = run(linker, &[&assembly_path, Path::new("-o"), &base]);
+    let _ = fs::remove_file(&assembly_path);
+    let linked = linked?;
+    if !linked.status.success() {
+        return Err(format!(
+            "`{}` failed:\n{}",
+            linker,
+            String::from_utf8_lossy(&linked.stderr)
+        ));
+    }
+    let ran = run(emulator, &[&base]);
+    let _ = fs::remove_file(&base);
+    let ran = ran?;
+    Ok(Observed {
+        stdout: String::from_utf8_lossy(&ran.stdout).into_owned(),
+        status: ran
+            .status
+            .code()
+            .ok_or_else(|| format!("killed by {}", ran.status))?,
+    })
+}
+
+#[test]
+fn interpreter() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/run");
+    for program in programs(&directory) {
+        assert_eq!(
+            interpret(&program),
+            Ok(expected(&program)),
+            "{}",
+            program.display()
+        );
+    }
+}
+
+#[test]
+fn qemu() {
+    let emulator = match env::var("RTSC_QEMU") {
+        Ok(emulator) if emulator == "1" => String::from(EMULATOR),
+        Ok(emulator) => emulator,
+        Err(_) => {
+            eprintln!("skipping the QEMU tests, set RTSC_QEMU to run them");
+            return;
+        }
+    };
+    let linker = env::var("RTSC_LINKER").unwrap_or_else(|_| String::from(LINKER));
+    let configurations = [
+        ("default", CodegenOptions::default()),
+        (
+            "gc",
+            CodegenOptions {
+                gc: true,
+                ..CodegenOptions::default()
+            },
+        ),
+        (
+            "thumb",
+            CodegenOptions {
+                thumb: true,
+                ..CodegenOptions::default()
+            },
+        ),
+        // the optional passes change the code, not what it does
+        (
+            "O0",
+            CodegenOptions {
+                passes: Passes::at_level(0),
+                ..CodegenOptions::default()
+            },
+        ),
+        (
+            "O2",
+            CodegenOptions {
+                passes: Passes::at_level(passes::MAX_LEVEL),
+                ..CodegenOptions::default()
+            },
+        ),
+        // an `it` goes in front of every conditional instruction, wherever
+        // the scheduling moves it
+        (
+            "O2 thumb",
+            CodegenOptions {
+                thumb: true,
+                passes: Passes::at_level(passes::MAX_LEVEL),
+                ..CodegenOptions::default()
+            },
+        ),
+    ];
+
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/run");
+    let mut failures = Vec::new();
+    for program in programs(&directory) {
+        let interpreted = match interpret(&program) {
+            Ok(interpreted) => interpreted,
+            Err(error) => {
+                failures.push(format!("{}: {}", program.display(), error));
+                continue;
+            }
+        };
+        for (configuration, options) in &configurations {
+            let failure = match execute(&program, *options, &linker, &emulator) {
+                Ok(executed) if executed == interpreted => continue,
+                Ok(executed) => format!(
+                    "the interpreter gives {:?}, the executable {:?}",
+                    interpreted, executed
+                ),
+                Err(error) => error,
+            };
+            failures.push(format!(
+                "{} ({}): {}",
+                program.display(),
+                configuration,
+                failure
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+// Generated and minified programs chain thousands of operators, which nest
+// as deep in the tree as the chain is long.
+#[test]
+fn long_chains() {
+    let terms = 5000;
+    let program = env::temp_dir().join(format!("rtsc-chain-{}.ts", process::id()));
+    let source = format!(
+        "function main(): number {{\n    var x = 1;\n    return x{};\n}}\n",
+        " + x".repeat(terms)
+    );
+    fs::write(&program, source).unwrap();
+    let expected = Observed {
+        stdout: String::new(),
+        status: (terms as i32 + 1) & 0xff,
+    };
+    assert_eq!(interpret(&program), Ok(expected));
+    for compiler in [
+        Compiler::new(),
+        Compiler::new().with_target(Target::Wasm32),
+        Compiler::new().with_emit(EmitKind::LlvmIr),
+    ] {
+        assert!(compiler.compile_file(&program).is_ok());
+    }
+    if let Ok(emulator) = env::var("RTSC_QEMU") {
+        let emulator = if emulator == "1" {
+            String::from(EMULATOR)
+        } else {
+            emulator
+        };
+        let linker = env::var("RTSC_LINKER").unwrap_or_else(|_| String::from(LINKER));
+        let executed = execute(&program, CodegenOptions::default(), &linker, &emulator);
+        assert_eq!(executed, interpret(&program));
+    }
+    let _ = fs::remove_file(&program);
+}
+
+#[test]
+fn exit_comments() {
+    assert_eq!(expected_status("// exit: 13\nfunction main() {}"), 13);
+    assert_eq!(expected_status("function main() {}"), 0);
+}
@@ -0,0 +1,34 @@
+// exit: 15
+function assert(x: boolean) {
+    if (x) {
+        putchar(46);
+    } else {
+        putchar(70);
+    }
+}
+
+function sum(xs: array<number>) {
+    var total = 0;
+    var i = 0;
+    while (i != length(xs)) {
+        total = total + xs[i];
+        i = i + 1;
+    }
+    return total;
+}
+
+function main() {
+    var xs = [1, 2, 3];
+    push(xs, 4);
+    assert(length(xs) == 4);
+    assert(xs[3] == 4);
+    var ys = concat(xs, slice(xs, 1, 3));
+    assert(length(ys) == 6);
+    assert(ys[5] == 3);
+    assert(xs[10] == undefined);
+    var [first, second] = ys;
+    assert(first + second == 3);
+    assert(pop(ys) == 3);
+    putchar(10);
+    return sum(ys) + 3;
+}
@@ -0,0 +1,22 @@
+// exit: 35
+function assert(x: boolean) {
+    if (x) {
+        putchar(46);
+    } else {
+        putchar(70);
+    }
+}
+
+function main() {
+    var x = 255;
+    assert(clz(x) == 24);
+    assert(clz(0) == 32);
+    assert(clz(0 - 1) == 0);
+    assert(popcount(x) == 8);
+    assert(popcount(0 - 1) == 32);
+    assert(rotate(x, 4) == 0 - 268435441);
+    assert(rotate(2, 33) == 1);
+    assert(rotate(x, 0) == x);
+    putchar(10);
+    return popcount(x) + clz(x) + popcount(rotate(7, 3));
+}
@@ -0,0 +1,42 @@
+// exit: 13
+function assert(x: boolean) {
+    if (x) {
+        putchar(46);
+    } else {
+        putchar(70);
+    }
+}
+
+function digits(x: number, y: number, z: number, w: number) {
+    return x * 1000 + y * 100 + z * 10 + w;
+}
+
+function factorial(n: number) {
+    if (n == 0) {
+        return 1;
+    } else {
+        return n * factorial(n - 1);
+    }
+}
+
+function fibonacci(n: number) {
+    if (n == 0) {
+        return 0;
+    } else {
+        if (n == 1) {
+            return 1;
+        } else {
+            return fibonacci(n - 1) + fibonacci(n - 2);
+        }
+    }
+}
+
+function main() {
+    assert(digits(1, 2, 3, 4) == 1234);
+    // The outer arguments have to survive the nested calls.
+    assert(digits(digits(0, 0, 0, 1), 2, digits(0, 0, 0, 3), 4) == 1234);
+    assert(factorial(5) == 120);
+    assert(fibonacci(10) == 55);
+    putchar(10);
+    return fibonacci(7);
+}
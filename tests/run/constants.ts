@@ -0,0 +1,27 @@
+// exit: 44
+const ROWS = 3;
+const COLUMNS = ROWS + 1;
+const MARK = ".";
+var cells = ROWS * COLUMNS;
+
+static_assert(ROWS * COLUMNS == 12, "cells");
+
+function row(ROWS: number) {
+    var i = 0;
+    while (i != ROWS) {
+        putchar(MARK[0]);
+        i = i + 1;
+    }
+    putchar(10);
+    return 0;
+}
+
+function main() {
+    const NEGATIVE = 0 - 7;
+    var y = 0;
+    while (y != ROWS) {
+        row(COLUMNS);
+        y = y + 1;
+    }
+    return cells * ROWS + NEGATIVE / 2 + 11;
+}
@@ -0,0 +1,21 @@
+// exit: 12
+function assert(x: boolean) {
+    if (x) {
+        putchar(46);
+    } else {
+        putchar(70);
+    }
+}
+
+function main() {
+    var a = 0 - 7;
+    var b = 5;
+    assert(min(a, b) == a);
+    assert(max(a, b) == b);
+    assert(abs(a) == 7);
+    assert(abs(b) == 5);
+    assert(min(b, b) == b);
+    assert(max(min(a, 0), 0 - 9) == a);
+    putchar(10);
+    return abs(a) + max(b, 0);
+}
@@ -0,0 +1,45 @@
+// exit: 42
+function assert(x: boolean) {
+    if (x) {
+        putchar(46);
+    } else {
+        putchar(70);
+    }
+}
+
+const ROWS = 3;
+
+function main() {
+    var sum = 0;
+    var row = 0;
+    while (row != ROWS) {
+        var column = 0;
+        while (column != 4) {
+            sum = sum + row * 4 + column;
+            column = column + 1;
+        }
+        row = row + 1;
+    }
+    assert(sum == 66);
+    assert(row == 3);
+    var countdown = 9;
+    while (countdown != 0) {
+        countdown = countdown - 3;
+        putchar(48 + countdown);
+    }
+    var never = 5;
+    while (never != 5) {
+        never = never + 1;
+        putchar(70);
+    }
+    var xs = [4, 8, 15, 16];
+    var total = 0;
+    var i = 0;
+    while (i != length(xs)) {
+        total = total + xs[i];
+        i = i + 1;
+    }
+    assert(total == 43);
+    putchar(10);
+    return total - 1;
+}
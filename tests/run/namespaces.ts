@@ -0,0 +1,27 @@
+// exit: 7
+namespace stars {
+    function star() {
+        putchar(42);
+    }
+    export function line(n: number) {
+        if (n == 0) {
+            putchar(10);
+        } else {
+            star();
+            line(n - 1);
+        }
+    }
+}
+
+namespace text {
+    export function line(n: number) {
+        putchar(n);
+        putchar(10);
+    }
+}
+
+function main() {
+    stars.line(3);
+    text.line(65);
+    return 7;
+}
@@ -0,0 +1,33 @@
+// exit: 10
+function assert(x: boolean) {
+    if (x) {
+        putchar(46);
+    } else {
+        putchar(70);
+    }
+}
+
+function main() {
+    function min(a: number, b: number): number {
+        return a + b;
+    }
+    function factorial(n: number): number {
+        if (n == 0) {
+            return 1;
+        } else {}
+        return n * factorial(n - 1);
+    }
+    assert(min(1, 2) == 3);
+    assert(factorial(4) == 24);
+    var x = 2;
+    if (x == 2) {
+        function factorial(n: number): number {
+            return n;
+        }
+        x = factorial(5);
+    } else {}
+    assert(x == 5);
+    assert(factorial(3) == 6);
+    putchar(10);
+    return x + factorial(1) + 4;
+}
@@ -0,0 +1,23 @@
+// exit: 8
+function main() {
+    var x = 4;
+    var total = 0;
+    var i = 0;
+    while (i != 5) {
+        {
+            var x = 100;
+            total = total + x;
+        }
+        total = total + x;
+        i = i + 1;
+    }
+    if (x == 4) {
+        var x = 7;
+        total = total + x - 7;
+    } else {
+        total = 0;
+    }
+    putchar(48 + x);
+    putchar(10);
+    return total;
+}
@@ -0,0 +1,26 @@
+// exit: 5
+function print(s: string) {
+    var i = 0;
+    while (i != length(s)) {
+        putchar(s[i]);
+        i = i + 1;
+    }
+    putchar(10);
+}
+
+function main() {
+    print("hello");
+    if ("same" == "same") {
+        print("equal");
+    } else {
+        print("different");
+    }
+    var digits = numberToString(0 - 42);
+    var i = 0;
+    while (i != length(digits)) {
+        putchar(digits[i]);
+        i = i + 1;
+    }
+    putchar(10);
+    return length(numberToString(12345));
+}